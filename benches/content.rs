@@ -0,0 +1,50 @@
+#![feature(test)]
+extern crate test;
+use test::Bencher;
+
+use lopdf::content::{Content, Operation};
+use lopdf::Object;
+
+fn synthetic_content() -> Content {
+    let mut operations = Vec::new();
+    for i in 0..1000 {
+        operations.push(Operation::new(
+            "cm",
+            vec![
+                Object::Real(0.5 + i as f32 * 0.001),
+                0.into(),
+                0.into(),
+                Object::Real(1.25),
+                (i as i64).into(),
+                Object::Real(-3.75),
+            ],
+        ));
+        operations.push(Operation::new("Tf", vec![Object::Name(b"F1".to_vec()), 12.into()]));
+        operations.push(Operation::new("Tj", vec![Object::string_literal("Hello World (text)")]));
+        operations.push(Operation::new(
+            "TJ",
+            vec![Object::Array(vec![
+                Object::string_literal("A"),
+                Object::Real(-120.5),
+                Object::string_literal("B"),
+            ])],
+        ));
+    }
+    Content { operations }
+}
+
+#[bench]
+fn bench_content_encode(b: &mut Bencher) {
+    let content = synthetic_content();
+    b.iter(|| content.encode().unwrap());
+}
+
+#[bench]
+fn bench_content_encode_into(b: &mut Bencher) {
+    let content = synthetic_content();
+    let mut buffer = Vec::new();
+    b.iter(|| {
+        buffer.clear();
+        content.encode_into(&mut buffer).unwrap();
+    });
+}
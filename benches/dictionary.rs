@@ -0,0 +1,47 @@
+#![feature(test)]
+use std::fs;
+
+extern crate test;
+use test::Bencher;
+
+use lopdf::{Document, Object};
+
+fn asset_buffers() -> Vec<Vec<u8>> {
+    ["example.pdf", "AnnotationDemo.pdf", "Incremental.pdf", "unicode.pdf"]
+        .iter()
+        .map(|name| fs::read(format!("assets/{name}")).unwrap())
+        .collect()
+}
+
+#[bench]
+fn bench_load_and_extract_assets(b: &mut Bencher) {
+    let buffers = asset_buffers();
+    b.iter(|| {
+        for buffer in &buffers {
+            let doc = Document::load_mem(buffer).unwrap();
+            let pages: Vec<u32> = doc.get_pages().keys().copied().collect();
+            let _ = doc.extract_text(&pages);
+        }
+    })
+}
+
+#[bench]
+fn bench_dictionary_lookup(b: &mut Bencher) {
+    let docs: Vec<Document> = asset_buffers()
+        .iter()
+        .map(|buffer| Document::load_mem(buffer).unwrap())
+        .collect();
+    let keys: [&[u8]; 6] = [b"Type", b"Subtype", b"Filter", b"Length", b"Font", b"Missing"];
+    b.iter(|| {
+        let mut found = 0;
+        for object in docs.iter().flat_map(|doc| doc.objects.values()) {
+            let dict = match object {
+                Object::Dictionary(dict) => dict,
+                Object::Stream(stream) => &stream.dict,
+                _ => continue,
+            };
+            found += keys.iter().filter(|key| dict.has(key)).count();
+        }
+        found
+    })
+}
@@ -126,7 +126,7 @@ fn main() {
         documents_objects.extend(doc.objects);
 
         // Lets shadow our pointer back if nothing then set to (0,0) tto point to the next page
-        let object = first_object.unwrap_or((0, 0));
+        let object = first_object.unwrap_or(ObjectId(0, 0));
 
         // This will use the layering to implement children under Parents in the bookmarks
         // Example as we are generating it here.
@@ -248,8 +248,7 @@ fn main() {
         // Set new "Kids" list (collected from documents pages) for "Pages"
         dictionary.set(
             "Kids",
-            documents_pages.into_keys().map(|object_id| Object::Reference(object_id))
-                .collect::<Vec<_>>(),
+            documents_pages.into_keys().map(Object::Reference).collect::<Vec<_>>(),
         );
 
         document.objects.insert(page_id, Object::Dictionary(dictionary));
@@ -283,6 +282,13 @@ fn main() {
         }
     }
 
+    // Documents produced by the same tool often embed the same font subset, keep a single copy.
+    // Can be disabled to speed up the process.
+    let merged_fonts = document.merge_duplicate_fonts();
+    if merged_fonts > 0 {
+        println!("Merged {merged_fonts} duplicate fonts.");
+    }
+
     // Most of the time this does nothing unless there are a lot of streams
     // Can be disabled to speed up the process.
     // document.compress();
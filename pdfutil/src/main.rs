@@ -1,5 +1,5 @@
 use log::info;
-use lopdf::{Bookmark, Document, Object, ObjectId};
+use lopdf::{Bookmark, Document, FontInfo, Object, ObjectId};
 use std::collections::BTreeMap;
 
 #[macro_use]
@@ -51,6 +51,7 @@ fn main() {
         )
         .subcommand(SubCommand::with_name("compress").about("Compress PDF document"))
         .subcommand(SubCommand::with_name("decompress").about("Decompress PDF document"))
+        .subcommand(SubCommand::with_name("linearize").about("Linearize PDF document for fast web view"))
         .subcommand(
             SubCommand::with_name("delete_pages").about("Delete pages").arg(
                 Arg::with_name("pages")
@@ -101,7 +102,29 @@ fn main() {
                         .takes_value(true),
                 ),
         )
+        .subcommand(SubCommand::with_name("info").about("Print document statistics"))
+        .subcommand(
+            SubCommand::with_name("fonts")
+                .about("List the fonts used by the pages")
+                .arg(
+                    Arg::with_name("page")
+                        .long("page")
+                        .value_name("page number")
+                        .help("Only list the fonts used by this page")
+                        .takes_value(true),
+                )
+                .arg(Arg::with_name("json").long("json").help("Print the fonts as JSON")),
+        )
         .subcommand(SubCommand::with_name("print_streams").about("Print streams"))
+        .subcommand(
+            SubCommand::with_name("repair")
+                .about("Repair a damaged PDF document and report the problems found")
+                .arg(
+                    Arg::with_name("strict-report")
+                        .long("strict-report")
+                        .help("Exit with an error if any problem was found"),
+                ),
+        )
         .subcommand(SubCommand::with_name("renumber_objects").about("Renumber objects"))
         .subcommand(SubCommand::with_name("delete_zero_length_streams").about("Delete zero length stream objects"))
         .get_matches();
@@ -174,7 +197,7 @@ fn main() {
             documents_objects.extend(doc.objects);
 
             // Lets shadow our pointer back if nothing then set to (0,0) tto point to the next page
-            let object = first_object.unwrap_or((0, 0));
+            let object = first_object.unwrap_or(ObjectId(0, 0));
 
             // This will use the layering to implement children under Parents in the bookmarks
             // Example as we are generating it here.
@@ -351,7 +374,14 @@ fn main() {
     if let (cmd, Some(args)) = app.subcommand() {
         if let Some(input) = args.value_of("input") {
             info!("Open {}", input);
-            let mut doc = Document::load(input).unwrap();
+            let mut doc = match Document::load(input) {
+                Ok(doc) => doc,
+                Err(err) => {
+                    eprintln!("Failed to load {}: {}", input, err);
+                    std::process::exit(1);
+                }
+            };
+            let mut exit_code = 0;
             //info!("{:?}", doc.get_pages());
 
             info!("Do {}", cmd);
@@ -405,6 +435,35 @@ fn main() {
                         let _ = doc.replace_text(page, words[0], words[1]);
                     }
                 }
+                "info" => {
+                    println!("{:#?}", doc.statistics());
+                    print_largest_objects(&doc, 10);
+                }
+                "fonts" => {
+                    let page = args.value_of("page").map(|page| u32::from_str(page).unwrap());
+                    let fonts: Vec<FontInfo> = doc
+                        .font_report()
+                        .into_iter()
+                        .filter(|font| page.map_or(true, |page| font.pages.contains(&page)))
+                        .collect();
+                    if args.is_present("json") {
+                        println!("{}", fonts_json(&fonts));
+                    } else {
+                        print_fonts(&fonts);
+                    }
+                }
+                "repair" => match doc.repair() {
+                    Ok(summary) => {
+                        print!("{}", summary);
+                        if args.is_present("strict-report") && !summary.is_clean() {
+                            exit_code = 2;
+                        }
+                    }
+                    Err(err) => {
+                        eprintln!("Document is unrecoverable: {}", err);
+                        std::process::exit(1);
+                    }
+                },
                 "print_streams" => {
                     for (_, object) in doc.objects.iter() {
                         if let Object::Stream(stream) = object {
@@ -433,7 +492,15 @@ fn main() {
 
             if let Some(output) = args.value_of("output") {
                 info!("Save to {}", output);
-                doc.save(output).unwrap();
+                if cmd == "linearize" {
+                    let mut file = std::io::BufWriter::new(std::fs::File::create(output).unwrap());
+                    doc.save_linearized(&mut file).unwrap();
+                } else {
+                    doc.save(output).unwrap();
+                }
+            }
+            if exit_code != 0 {
+                std::process::exit(exit_code);
             }
         }
     }
@@ -457,6 +524,85 @@ fn apply_operation(doc: &mut Document, operation: &str) {
         }
     }
 
+    /// Print the `count` largest objects, streams or not, as they would be saved.
+    fn print_largest_objects(doc: &Document, count: usize) {
+        let mut sizes: Vec<(ObjectId, usize)> = doc.object_sizes().into_iter().collect();
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        println!("Largest objects:");
+        for (id, size) in sizes.into_iter().take(count) {
+            let object = &doc.objects[&id];
+            let kind = match object.type_name() {
+                Ok(name) => format!("{} /{}", object.enum_variant(), String::from_utf8_lossy(name)),
+                Err(_) => object.enum_variant().to_string(),
+            };
+            println!("  {} {} R\t{}\t{} bytes", id.0, id.1, kind, size);
+        }
+    }
+
+    fn print_fonts(fonts: &[FontInfo]) {
+        for font in fonts {
+            let names: Vec<_> = font.names.iter().map(|name| String::from_utf8_lossy(name)).collect();
+            let id = font.id.map_or("direct".to_string(), |id| format!("{} {} R", id.0, id.1));
+            let subtype = match (&font.subtype, &font.descendant_subtype) {
+                (Some(subtype), Some(descendant)) => format!("{}/{}", subtype, descendant),
+                (subtype, _) => subtype.clone().unwrap_or_else(|| "?".to_string()),
+            };
+            let pages: Vec<_> = font.pages.iter().map(|page| page.to_string()).collect();
+            println!(
+                "{} ({}): {} {}, encoding {}, {}, {}, pages {}",
+                names.join(","),
+                id,
+                font.base_font.as_deref().unwrap_or("?"),
+                subtype,
+                font.encoding,
+                font.embedded.as_ref().map_or("not embedded".to_string(), |kind| format!("embedded {}", kind)),
+                if font.to_unicode { "ToUnicode" } else { "no ToUnicode" },
+                pages.join(",")
+            );
+        }
+    }
+
+    fn fonts_json(fonts: &[FontInfo]) -> String {
+        fn string(text: &str) -> String {
+            let mut quoted = String::from("\"");
+            for c in text.chars() {
+                match c {
+                    '"' => quoted.push_str("\\\""),
+                    '\\' => quoted.push_str("\\\\"),
+                    c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => quoted.push(c),
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+        fn optional(text: &Option<String>) -> String {
+            text.as_deref().map_or("null".to_string(), string)
+        }
+
+        let items: Vec<String> = fonts
+            .iter()
+            .map(|font| {
+                let names: Vec<_> = font.names.iter().map(|name| string(&String::from_utf8_lossy(name))).collect();
+                let pages: Vec<_> = font.pages.iter().map(|page| page.to_string()).collect();
+                format!(
+                    "{{\"id\":{},\"names\":[{}],\"base_font\":{},\"subtype\":{},\"descendant_subtype\":{},\
+                     \"encoding\":{},\"embedded\":{},\"to_unicode\":{},\"pages\":[{}]}}",
+                    font.id.map_or("null".to_string(), |id| format!("[{},{}]", id.0, id.1)),
+                    names.join(","),
+                    optional(&font.base_font),
+                    optional(&font.subtype),
+                    optional(&font.descendant_subtype),
+                    string(&font.encoding.to_string()),
+                    optional(&font.embedded),
+                    font.to_unicode,
+                    pages.join(",")
+                )
+            })
+            .collect();
+        format!("[{}]", items.join(","))
+    }
+
     fn compute_page_numbers(pages: &str) -> Vec<u32> {
         let mut page_numbers = vec![];
         for page in pages.split(',') {
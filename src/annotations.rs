@@ -0,0 +1,197 @@
+use crate::{CertifiedChange, Dictionary, Document, Error, Object, ObjectId, Result};
+
+impl Document {
+    /// Get the annotations of a page like [`Document::get_page_annotations`], with their IDs.
+    ///
+    /// Annotations written directly in `/Annots` have no ID, [`Document::promote_direct_annotations`]
+    /// turns them into objects of their own. Entries which aren't dictionaries are skipped.
+    pub fn get_page_annotations_with_ids(
+        &self, page_id: impl Into<ObjectId>,
+    ) -> Result<Vec<(Option<ObjectId>, &Dictionary)>> {
+        let page_id = page_id.into();
+        let Ok(annots) = self.get_dictionary(page_id)?.get(b"Annots") else {
+            return Ok(vec![]);
+        };
+        let annotations = self
+            .dereference(annots)?
+            .1
+            .as_array()?
+            .iter()
+            .filter_map(|annotation| {
+                let (id, annotation) = self.dereference(annotation).ok()?;
+                Some((id, annotation.as_dict().ok()?))
+            })
+            .collect();
+        Ok(annotations)
+    }
+
+    /// Move the annotations written directly in the `/Annots` of a page into objects of their own,
+    /// which are referenced in their place, and return their IDs. The dictionaries are unchanged.
+    pub fn promote_direct_annotations(&mut self, page_id: impl Into<ObjectId>) -> Result<Vec<ObjectId>> {
+        let page_id = page_id.into();
+        let Some(annots) = self.page_annots_mut(page_id)? else {
+            return Ok(vec![]);
+        };
+        let direct: Vec<(usize, Object)> = annots
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, annotation)| matches!(annotation, Object::Dictionary(_)))
+            .map(|(index, annotation)| (index, std::mem::replace(annotation, Object::Null)))
+            .collect();
+        let promoted: Vec<(usize, ObjectId)> = direct
+            .into_iter()
+            .map(|(index, annotation)| (index, self.add_object(annotation)))
+            .collect();
+        if let Some(annots) = self.page_annots_mut(page_id)? {
+            for &(index, id) in &promoted {
+                annots[index] = id.into();
+            }
+        }
+        Ok(promoted.into_iter().map(|(_, id)| id).collect())
+    }
+
+    /// Remove the annotation `annot_id` from the `/Annots` of a page, along with its `/Popup`.
+    ///
+    /// Fails with [`Error::ObjectNotFound`] if the page doesn't list the annotation. The objects are
+    /// kept, as a form field may still reference a widget, [`Document::prune_objects`] removes
+    /// them once nothing does.
+    pub fn remove_annotation(&mut self, page_id: impl Into<ObjectId>, annot_id: impl Into<ObjectId>) -> Result<()> {
+        let page_id = page_id.into();
+        let annot_id = annot_id.into();
+        self.check_certification(CertifiedChange::Annotate)?;
+        let popup = self
+            .get_dictionary(annot_id)
+            .and_then(|annotation| annotation.get(b"Popup"))
+            .and_then(Object::as_reference)
+            .ok();
+        let annots = self.page_annots_mut(page_id)?.ok_or(Error::ObjectNotFound(annot_id))?;
+        if !annots
+            .iter()
+            .any(|annotation| annotation.as_reference().ok() == Some(annot_id))
+        {
+            return Err(Error::ObjectNotFound(annot_id));
+        }
+        annots.retain(|annotation| match annotation.as_reference() {
+            Ok(id) => id != annot_id && Some(id) != popup,
+            Err(_) => true,
+        });
+        Ok(())
+    }
+
+    /// Change the annotation dictionary `annot_id` with `f`, returning what it returns.
+    ///
+    /// Changing a widget annotation counts as filling in a form, see [`Document::set_respect_certification`].
+    pub fn update_annotation<F, T>(&mut self, annot_id: impl Into<ObjectId>, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Dictionary) -> T,
+    {
+        let annot_id = annot_id.into();
+        let widget = self
+            .get_dictionary(annot_id)?
+            .get(b"Subtype")
+            .and_then(Object::as_name)
+            .ok()
+            == Some(b"Widget");
+        self.check_certification(if widget {
+            CertifiedChange::FillForms
+        } else {
+            CertifiedChange::Annotate
+        })?;
+        let annotation = self.get_dictionary_mut(annot_id)?;
+        if !annotation.has(b"Subtype") {
+            return Err(Error::DictKey("Subtype".to_string()));
+        }
+        Ok(f(annotation))
+    }
+
+    /// The `/Annots` array of a page, which may be an object of its own, `None` if there is none.
+    fn page_annots_mut(&mut self, page_id: ObjectId) -> Result<Option<&mut Vec<Object>>> {
+        let annots_id = match self.get_dictionary(page_id)?.get(b"Annots") {
+            Ok(Object::Reference(id)) => *id,
+            Ok(_) => page_id,
+            Err(_) => return Ok(None),
+        };
+        let annots = match self.get_object_mut(annots_id)? {
+            Object::Dictionary(page) => page.get_mut(b"Annots")?,
+            annots => annots,
+        };
+        annots.as_array_mut().map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+
+    fn annotation(contents: &str) -> Dictionary {
+        dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Rect" => vec![0.into(), 0.into(), 20.into(), 20.into()],
+            "Contents" => Object::string_literal(contents),
+        }
+    }
+
+    #[test]
+    fn remove_and_update_annotations() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let ids: Vec<ObjectId> = ["one", "two", "three"]
+            .into_iter()
+            .map(|contents| doc.add_object(annotation(contents)))
+            .collect();
+        let popup_id = doc.add_object(dictionary! { "Type" => "Annot", "Subtype" => "Popup", "Parent" => ids[1] });
+        doc.get_dictionary_mut(ids[1]).unwrap().set("Popup", popup_id);
+        let mut annots: Vec<Object> = ids.iter().map(|&id| id.into()).collect();
+        annots.push(popup_id.into());
+        // The array of annotations is an object of its own.
+        let annots_id = doc.add_object(annots);
+        doc.get_dictionary_mut(page_id).unwrap().set("Annots", annots_id);
+
+        let found = doc.get_page_annotations_with_ids(page_id).unwrap();
+        let found: Vec<Option<ObjectId>> = found.into_iter().map(|(id, _)| id).collect();
+        assert_eq!(found, [Some(ids[0]), Some(ids[1]), Some(ids[2]), Some(popup_id)]);
+
+        doc.remove_annotation(page_id, ids[1]).unwrap();
+        let found = doc.get_page_annotations_with_ids(page_id).unwrap();
+        let found: Vec<Option<ObjectId>> = found.into_iter().map(|(id, _)| id).collect();
+        assert_eq!(found, [Some(ids[0]), Some(ids[2])]);
+        assert_eq!(doc.get_page_annotations(page_id).unwrap().len(), 2);
+        assert!(matches!(
+            doc.remove_annotation(page_id, ids[1]),
+            Err(Error::ObjectNotFound(id)) if id == ids[1]
+        ));
+
+        doc.clear_dirty();
+        doc.update_annotation(ids[2], |annotation| {
+            annotation.set("Contents", Object::string_literal("3"))
+        })
+        .unwrap();
+        assert!(doc.dirty_objects().contains(&ids[2]));
+        let contents = doc.get_dictionary(ids[2]).unwrap().get(b"Contents").unwrap();
+        assert_eq!(contents.as_str().unwrap(), b"3");
+        assert!(doc.update_annotation(annots_id, |_| ()).is_err());
+    }
+
+    #[test]
+    fn promote_direct_annotation() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let indirect_id = doc.add_object(annotation("indirect"));
+        let direct = annotation("direct");
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Annots", vec![direct.clone().into(), indirect_id.into()]);
+        let found = doc.get_page_annotations_with_ids(page_id).unwrap();
+        assert_eq!(found[0], (None, &direct));
+
+        let promoted = doc.promote_direct_annotations(page_id).unwrap();
+        assert_eq!(promoted.len(), 1);
+        assert_eq!(doc.get_dictionary(promoted[0]).unwrap(), &direct);
+        let found = doc.get_page_annotations_with_ids(page_id).unwrap();
+        assert_eq!(found[0], (Some(promoted[0]), &direct));
+        assert_eq!(found[1].0, Some(indirect_id));
+        assert!(doc.promote_direct_annotations(page_id).unwrap().is_empty());
+    }
+}
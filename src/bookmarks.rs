@@ -1,27 +1,160 @@
 use super::{Dictionary, Document, Object, ObjectId};
-use std::collections::HashMap;
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::ops::{BitOr, BitOrAssign};
+
+/// Bookmarks nested deeper than this are skipped, see [`BookmarkIssue::TooDeep`].
+const MAX_BOOKMARK_DEPTH: usize = 256;
 
 #[derive(Debug, Clone)]
 pub struct Bookmark {
     /// Children, Must be a Collection that allows for insertion of the same page ID.
     pub children: Vec<u32>,
     pub title: String,
-    /// 0, 1 for italic, 2 for bold, 3 for italic bold
-    pub format: u32,
-    /// R,G,B
-    pub color: [f32; 3],
-    pub page: ObjectId,
+    /// Italic and bold style of the title.
+    pub flags: OutlineFlags,
+    /// R,G,B color of the title, `None` leaves it to the viewer.
+    pub color: Option<[f32; 3]>,
+    /// Whether the children are shown initially.
+    pub open: bool,
+    pub target: BookmarkTarget,
     pub id: u32,
 }
 
+/// Style flags of an outline item, written as its `/F` entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct OutlineFlags(u32);
+
+impl OutlineFlags {
+    pub const ITALIC: OutlineFlags = OutlineFlags(1);
+    pub const BOLD: OutlineFlags = OutlineFlags(2);
+
+    pub const fn empty() -> OutlineFlags {
+        OutlineFlags(0)
+    }
+
+    /// Keep the known flags of `bits`, ignoring the others.
+    pub const fn from_bits_truncate(bits: u32) -> OutlineFlags {
+        OutlineFlags(bits & 3)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(&self, other: OutlineFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for OutlineFlags {
+    type Output = OutlineFlags;
+
+    fn bitor(self, rhs: OutlineFlags) -> OutlineFlags {
+        OutlineFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for OutlineFlags {
+    fn bitor_assign(&mut self, rhs: OutlineFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// What happens when an outline item is activated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookmarkTarget {
+    /// Go to a page, shown as given by the fit. A page of `ObjectId(0, 0)` is replaced by the page
+    /// of the first child, see [`Document::adjust_zero_pages`].
+    Page(ObjectId, DestinationFit),
+    /// Open a URI.
+    Uri(String),
+    /// Go to a named destination.
+    Named(Vec<u8>),
+}
+
+/// How a page is shown when going to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DestinationFit {
+    /// Fit the whole page in the window.
+    #[default]
+    Fit,
+    /// Fit the page width, with the given top coordinate at the top of the window.
+    FitH(Option<f32>),
+    /// Fit the page height, with the given left coordinate at the left of the window.
+    FitV(Option<f32>),
+    /// Put the given left and top coordinates at the top left corner of the window, with a zoom factor.
+    XYZ {
+        left: Option<f32>,
+        top: Option<f32>,
+        zoom: Option<f32>,
+    },
+}
+
+impl DestinationFit {
+    fn to_objects(self) -> Vec<Object> {
+        let value = |value: Option<f32>| value.map_or(Object::Null, Object::Real);
+        match self {
+            DestinationFit::Fit => vec![Object::Name(b"Fit".to_vec())],
+            DestinationFit::FitH(top) => vec![Object::Name(b"FitH".to_vec()), value(top)],
+            DestinationFit::FitV(left) => vec![Object::Name(b"FitV".to_vec()), value(left)],
+            DestinationFit::XYZ { left, top, zoom } => {
+                vec![Object::Name(b"XYZ".to_vec()), value(left), value(top), value(zoom)]
+            }
+        }
+    }
+}
+
+/// A problem of the bookmark tree, whose bookmarks are skipped rather than followed when fixing
+/// pages with [`Document::adjust_zero_pages`] and building the outline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookmarkIssue {
+    /// A bookmark is listed again, as a child of `parent` or at the top for `None`, after it was
+    /// visited, as when its children lead back to it.
+    Revisited { parent: Option<u32>, child: u32 },
+    /// A bookmark listed as a child of `parent`, or at the top for `None`, isn't in the bookmark table.
+    Missing { parent: Option<u32>, child: u32 },
+    /// The children of the bookmark are nested too deep, they are skipped.
+    TooDeep(u32),
+}
+
+/// The bookmarks reachable from the top ones, each visited once.
+#[derive(Debug, Default)]
+pub(crate) struct BookmarkTree {
+    /// The top bookmarks, which are in the bookmark table.
+    pub roots: Vec<u32>,
+    /// The children of the bookmarks which are in the bookmark table and visited here.
+    pub children: HashMap<u32, Vec<u32>>,
+    /// The bookmarks in depth first order, parents before their children.
+    pub order: Vec<u32>,
+    pub issues: Vec<BookmarkIssue>,
+}
+
+impl BookmarkTarget {
+    /// The page to go to, if the target is a page.
+    pub fn page(&self) -> Option<ObjectId> {
+        match self {
+            BookmarkTarget::Page(page, _) => Some(*page),
+            _ => None,
+        }
+    }
+}
+
 impl Bookmark {
-    pub fn new(title: String, color: [f32; 3], format: u32, page: ObjectId) -> Bookmark {
+    /// A bookmark going to a page, fitting it in the window. `format` holds the [`OutlineFlags`] bits.
+    pub fn new(title: String, color: [f32; 3], format: u32, page: impl Into<ObjectId>) -> Bookmark {
+        let page = page.into();
         Bookmark {
             children: Vec::new(),
             title,
-            format,
-            color,
-            page,
+            flags: OutlineFlags::from_bits_truncate(format),
+            color: Some(color),
+            open: true,
+            target: BookmarkTarget::Page(page, DestinationFit::Fit),
             id: 0,
         }
     }
@@ -46,8 +179,50 @@ pub fn add_bookmark(&mut self, mut bookmark: Bookmark, parent: Option<u32>) -> u
         id
     }
 
+    /// Walk the bookmarks from the top ones, skipping those visited before, missing or nested
+    /// deeper than [`MAX_BOOKMARK_DEPTH`], which are reported as issues.
+    pub(crate) fn bookmark_tree(&self) -> BookmarkTree {
+        let mut tree = BookmarkTree::default();
+        let mut visited = HashSet::new();
+        let mut stack: Vec<(Option<u32>, usize, u32)> = self.bookmarks.iter().rev().map(|&id| (None, 1, id)).collect();
+        while let Some((parent, depth, id)) = stack.pop() {
+            let Some(bookmark) = self.bookmark_table.get(&id) else {
+                tree.issues.push(BookmarkIssue::Missing { parent, child: id });
+                continue;
+            };
+            if !visited.insert(id) {
+                tree.issues.push(BookmarkIssue::Revisited { parent, child: id });
+                continue;
+            }
+            match parent {
+                Some(parent) => tree.children.entry(parent).or_default().push(id),
+                None => tree.roots.push(id),
+            }
+            tree.order.push(id);
+            if bookmark.children.is_empty() {
+                continue;
+            }
+            if depth >= MAX_BOOKMARK_DEPTH {
+                tree.issues.push(BookmarkIssue::TooDeep(id));
+                continue;
+            }
+            stack.extend(
+                bookmark
+                    .children
+                    .iter()
+                    .rev()
+                    .map(|&child| (Some(id), depth + 1, child)),
+            );
+        }
+        for issue in &tree.issues {
+            warn!("Skipping bookmark: {:?}", issue);
+        }
+        tree
+    }
+
     fn outline_child(
-        &self, maxid: &mut u32, parent: (ObjectId, &[u32]), processed: &mut HashMap<ObjectId, Dictionary>,
+        &self, tree: &BookmarkTree, maxid: &mut u32, parent: (ObjectId, &[u32]),
+        processed: &mut HashMap<ObjectId, Dictionary>,
     ) -> (Option<ObjectId>, Option<ObjectId>, i64) {
         let mut first: Option<ObjectId> = None;
         let mut last: Option<ObjectId> = None;
@@ -55,40 +230,41 @@ fn outline_child(
         for i in parent.1 {
             let mut child = Dictionary::new();
             *maxid += 1;
-            let id: ObjectId = (*maxid, 0);
-            *maxid += 1;
-            let info_id: ObjectId = (*maxid, 0);
-            let bookmark = self.bookmark_table.get(i).unwrap();
-
-            let info = dictionary! {
-                "D" =>  vec![bookmark.page.into(), Object::Name("Fit".into())],
-                "S" => "GoTo",
-            };
-
-            let title_bytes = if bookmark.title.is_ascii() {
-                bookmark.title.as_bytes().to_vec()
-            } else {
-                // If the title contains non-ASCII characters:
-                // Create a new vector with the UTF-16 Byte Order Mark (BOM) for UTF-16BE.
-                let mut bom = vec![0xFE, 0xFF];
-                let utf16_title = bookmark.title.encode_utf16();
-                // Append the UTF-16BE encoded bytes of the title to the BOM.
-                bom.extend(utf16_title.flat_map(u16::to_be_bytes));
-                bom
-            };
+            let id: ObjectId = ObjectId(*maxid, 0);
+            let bookmark = &self.bookmark_table[i];
 
             child.set("Parent", parent.0);
-            child.set("Title", Object::string_literal(title_bytes));
-            child.set("A", info_id);
-            child.set("F", Object::Integer(bookmark.format.into()));
-            child.set(
-                "C",
-                vec![
-                    bookmark.color[0].into(),
-                    bookmark.color[1].into(),
-                    bookmark.color[2].into(),
-                ],
-            );
+            child.set("Title", self.encode_text_string(&bookmark.title));
+            match &bookmark.target {
+                BookmarkTarget::Page(page, fit) => {
+                    let mut destination = vec![(*page).into()];
+                    destination.extend(fit.to_objects());
+                    let info = dictionary! {
+                        "D" => destination,
+                        "S" => "GoTo",
+                    };
+                    *maxid += 1;
+                    let info_id = ObjectId(*maxid, 0);
+                    processed.insert(info_id, info);
+                    child.set("A", info_id);
+                }
+                BookmarkTarget::Uri(uri) => {
+                    child.set(
+                        "A",
+                        dictionary! {
+                            "S" => "URI",
+                            "URI" => Object::string_literal(uri.as_str()),
+                        },
+                    );
+                }
+                BookmarkTarget::Named(name) => child.set("Dest", Object::string_literal(name.clone())),
+            }
+            if !bookmark.flags.is_empty() {
+                child.set("F", Object::Integer(bookmark.flags.bits().into()));
+            }
+            if let Some(color) = bookmark.color {
+                child.set("C", vec![color[0].into(), color[1].into(), color[2].into()]);
+            }
 
             if first.is_none() {
                 first = Some(id);
@@ -100,8 +276,8 @@ fn outline_child(
 
             last = Some(id);
 
-            if !bookmark.children.is_empty() {
-                let (c_first, c_last, c_count) = self.outline_child(maxid, (id, &bookmark.children[..]), processed);
+            if let Some(children) = tree.children.get(i) {
+                let (c_first, c_last, c_count) = self.outline_child(tree, maxid, (id, &children[..]), processed);
 
                 if let Some(n) = c_first {
                     child.set("First", n);
@@ -111,11 +287,10 @@ fn outline_child(
                     child.set("Last", n);
                 }
 
-                child.set("Count", c_count);
+                child.set("Count", if bookmark.open { c_count } else { -c_count });
             }
 
             processed.insert(id, child);
-            processed.insert(info_id, info);
         }
 
         (first, last, count as i64)
@@ -124,13 +299,14 @@ fn outline_child(
     pub fn build_outline(&mut self) -> Option<ObjectId> {
         let mut processed: HashMap<ObjectId, Dictionary> = HashMap::new();
 
-        if !self.bookmarks.is_empty() {
+        let tree = self.bookmark_tree();
+        if !tree.roots.is_empty() {
             let mut outline = Dictionary::new();
             let mut maxid = self.max_id;
             maxid += 1;
-            let id: ObjectId = (maxid, 0);
+            let id: ObjectId = ObjectId(maxid, 0);
 
-            let (first, last, count) = self.outline_child(&mut maxid, (id, &self.bookmarks[..]), &mut processed);
+            let (first, last, count) = self.outline_child(&tree, &mut maxid, (id, &tree.roots[..]), &mut processed);
 
             if let Some(n) = first {
                 outline.set("First", n);
@@ -144,9 +320,11 @@ pub fn build_outline(&mut self) -> Option<ObjectId> {
 
             for (obj_id, obj) in processed.drain() {
                 self.objects.insert(obj_id, obj.into());
+                self.dirty.insert(obj_id);
             }
 
             self.objects.insert(id, outline.into());
+            self.dirty.insert(id);
             self.max_id = maxid;
             return Some(id);
         }
@@ -154,3 +332,150 @@ pub fn build_outline(&mut self) -> Option<ObjectId> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+
+    #[test]
+    fn styled_closed_bookmark_round_trip() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let mut bookmark = Bookmark::new("Links".to_string(), [0.0; 3], 0, ObjectId(0, 0));
+        bookmark.flags = OutlineFlags::BOLD | OutlineFlags::ITALIC;
+        bookmark.color = Some([1.0, 0.0, 0.5]);
+        bookmark.open = false;
+        bookmark.target = BookmarkTarget::Uri("https://example.com/".to_string());
+        let parent = doc.add_bookmark(bookmark, None);
+        let mut child = Bookmark::new("Page".to_string(), [0.0; 3], 0, page_id);
+        child.color = None;
+        child.target = BookmarkTarget::Page(page_id, DestinationFit::FitH(Some(700.0)));
+        doc.add_bookmark(child, Some(parent));
+        doc.add_bookmark(Bookmark::new("Named".to_string(), [0.0; 3], 0, page_id), Some(parent));
+        doc.bookmark_table.get_mut(&3).unwrap().target = BookmarkTarget::Named(b"chapter1".to_vec());
+        let outline_id = doc.build_outline().unwrap();
+        doc.catalog_mut().unwrap().set("Outlines", outline_id);
+
+        let mut buffer = Vec::new();
+        doc.save_to(&mut buffer).unwrap();
+        let doc = Document::load_mem(&buffer).unwrap();
+        let outline_id = doc.catalog().unwrap().get(b"Outlines").unwrap().as_reference().unwrap();
+        let outline = doc.get_dictionary(outline_id).unwrap();
+        let item = doc
+            .get_dictionary(outline.get(b"First").unwrap().as_reference().unwrap())
+            .unwrap();
+        assert_eq!(item.get(b"F").unwrap().as_i64().unwrap(), 3);
+        let color: Vec<_> = item
+            .get(b"C")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c.as_float().unwrap())
+            .collect();
+        assert_eq!(color, [1.0, 0.0, 0.5]);
+        assert_eq!(item.get(b"Count").unwrap().as_i64().unwrap(), -2);
+        let action = item.get(b"A").unwrap().as_dict().unwrap();
+        assert_eq!(action.get(b"S").unwrap().as_name().unwrap(), b"URI");
+        assert_eq!(action.get(b"URI").unwrap().as_str().unwrap(), b"https://example.com/");
+
+        let first = doc
+            .get_dictionary(item.get(b"First").unwrap().as_reference().unwrap())
+            .unwrap();
+        assert!(first.get(b"F").is_err());
+        assert!(first.get(b"C").is_err());
+        let action = doc
+            .get_dictionary(first.get(b"A").unwrap().as_reference().unwrap())
+            .unwrap();
+        let destination = action.get(b"D").unwrap().as_array().unwrap();
+        assert_eq!(destination[0].as_reference().unwrap(), page_id);
+        assert_eq!(destination[1].as_name().unwrap(), b"FitH");
+        assert_eq!(destination[2].as_float().unwrap(), 700.0);
+
+        let last = doc
+            .get_dictionary(item.get(b"Last").unwrap().as_reference().unwrap())
+            .unwrap();
+        assert_eq!(last.get(b"Dest").unwrap().as_str().unwrap(), b"chapter1");
+        assert!(last.get(b"A").is_err());
+    }
+
+    #[test]
+    fn zero_pages_take_first_descendant_page() {
+        let mut doc = create_document();
+        let page = doc.page_iter().next().unwrap();
+        let other = ObjectId(page.0 + 100, 0);
+        let zero = ObjectId(0, 0);
+        let mut add = |title: &str, target: ObjectId, parent: Option<u32>| {
+            doc.add_bookmark(Bookmark::new(title.to_string(), [0.0; 3], 0, target), parent)
+        };
+        // A URI child is passed over, and a child without a page of its own takes its children's.
+        let chapter = add("Chapter", zero, None);
+        let link = add("Link", zero, Some(chapter));
+        let section = add("Section", zero, Some(chapter));
+        let empty = add("Empty", zero, Some(section));
+        add("Other", other, Some(section));
+        add("Page", page, Some(chapter));
+        // A bookmark with a page keeps it, its children are fixed all the same.
+        let part = add("Part", page, None);
+        let nested = add("Nested", zero, Some(part));
+        add("Other", other, Some(nested));
+        doc.bookmark_table.get_mut(&link).unwrap().target = BookmarkTarget::Uri("https://example.com/".to_string());
+
+        assert!(doc.adjust_zero_pages().is_empty());
+        let target = |id: u32| doc.bookmark_table[&id].target.page();
+        assert_eq!(target(chapter), Some(other));
+        assert_eq!(target(link), None);
+        assert_eq!(target(section), Some(other));
+        assert_eq!(target(empty), Some(zero));
+        assert_eq!(target(part), Some(page));
+        assert_eq!(target(nested), Some(other));
+    }
+
+    #[test]
+    fn cyclic_and_deep_bookmarks() {
+        let mut doc = create_document();
+        let page = doc.page_iter().next().unwrap();
+        let first = doc.add_bookmark(Bookmark::new("First".to_string(), [0.0; 3], 0, ObjectId(0, 0)), None);
+        let second = doc.add_bookmark(Bookmark::new("Second".to_string(), [0.0; 3], 0, page), Some(first));
+        doc.bookmark_table
+            .get_mut(&second)
+            .unwrap()
+            .children
+            .extend([first, u32::MAX]);
+
+        let issues = doc.adjust_zero_pages();
+        assert_eq!(
+            issues,
+            [
+                BookmarkIssue::Revisited {
+                    parent: Some(second),
+                    child: first
+                },
+                BookmarkIssue::Missing {
+                    parent: Some(second),
+                    child: u32::MAX
+                },
+            ]
+        );
+        assert_eq!(doc.bookmark_table[&first].target.page(), Some(page));
+
+        // A chain nested far deeper than the outline is built to.
+        let mut parent = second;
+        for _ in 0..10_000 {
+            parent = doc.add_bookmark(
+                Bookmark::new("Deep".to_string(), [0.0; 3], 0, ObjectId(0, 0)),
+                Some(parent),
+            );
+        }
+        let issues = doc.adjust_zero_pages();
+        assert_eq!(issues.len(), 3);
+        assert!(matches!(issues[2], BookmarkIssue::TooDeep(_)));
+        // The outline gets an item and a GoTo action for each bookmark down to the limit.
+        let objects = doc.objects.len();
+        let outline_id = doc.build_outline().unwrap();
+        let outline = doc.get_dictionary(outline_id).unwrap();
+        assert_eq!(outline.get(b"Count").unwrap().as_i64().unwrap(), 1);
+        assert_eq!(doc.objects.len() - objects, 1 + 2 * MAX_BOOKMARK_DEPTH);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{Error, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag to abort a long-running operation from another thread, such as loading a large document.
+///
+/// Clones share the flag: once [`CancelToken::cancel`] is called on any of them, the operations
+/// given one stop at their next check and fail with [`Error::Cancelled`].
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Request the operations using the token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fail with [`Error::Cancelled`] once the token is cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Tokens are equal when they share the same flag.
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &CancelToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelToken {}
+
+/// Check an optional token, an operation without one is never cancelled.
+pub(crate) fn check_cancel(cancel: Option<&CancelToken>) -> Result<()> {
+    cancel.map_or(Ok(()), CancelToken::check)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+    use crate::{Document, ExtractionOptions, Object, Stream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn cancel_loading_from_another_thread() {
+        let mut doc = create_document_with_texts(&["first"]);
+        for i in 0..5_000 {
+            doc.add_object(dictionary! { "Index" => i, "Name" => Object::string_literal("padding") });
+        }
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+
+        let start = Instant::now();
+        Document::load_mem(&buffer).unwrap();
+        let full_load = start.elapsed();
+
+        let cancel = CancelToken::new();
+        let start = Instant::now();
+        let loading = thread::spawn({
+            let cancel = cancel.clone();
+            move || Document::load_mem_with_cancel(&buffer, &cancel)
+        });
+        thread::sleep(Duration::from_millis(5));
+        cancel.cancel();
+        let result = loading.join().unwrap();
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(start.elapsed() < full_load);
+    }
+
+    #[test]
+    fn cancelled_operations() {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let stream_id = doc.add_object(Stream::new(dictionary! {}, vec![b'x'; 500]));
+        let cancel = CancelToken::new();
+        let options = ExtractionOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            doc.extract_text_with_options(&[1, 2], &options).unwrap(),
+            doc.extract_text(&[1, 2]).unwrap()
+        );
+
+        cancel.cancel();
+        assert!(cancel.clone().is_cancelled());
+        assert!(matches!(
+            doc.extract_text_with_options(&[1, 2], &options),
+            Err(Error::Cancelled)
+        ));
+        let chunks = doc.extract_text_chunks_with_options(&[1, 2], &options);
+        assert!(matches!(chunks[..], [Err(Error::Cancelled)]));
+
+        assert!(matches!(doc.prune_objects_with_cancel(&cancel), Err(Error::Cancelled)));
+        assert!(doc.has_object(stream_id));
+        assert!(matches!(doc.compress_with_cancel(&cancel), Err(Error::Cancelled)));
+        let stream = doc.get_object(stream_id).and_then(Object::as_stream).unwrap();
+        assert!(!stream.dict.has(b"Filter"));
+
+        // A new token doesn't stop anything.
+        let cancel = CancelToken::new();
+        doc.compress_with_cancel(&cancel).unwrap();
+        let stream = doc.get_object(stream_id).and_then(Object::as_stream).unwrap();
+        assert!(stream.dict.has(b"Filter"));
+        assert_eq!(doc.prune_objects_with_cancel(&cancel).unwrap(), [stream_id]);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{decode_text_string, Dictionary, Document, Error, Object, ObjectId, Result};
+
+/// The changes a certification signature permits, the `/P` entry of its DocMDP transform parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocMdpPermission {
+    /// Level 1: no changes; any change invalidates the signature.
+    NoChanges,
+    /// Level 2: filling in forms, instantiating page templates and signing.
+    FormFilling,
+    /// Level 3: form filling and signing, as well as creating, deleting and modifying annotations.
+    Annotations,
+}
+
+impl DocMdpPermission {
+    /// The permission of a `/P` value. `None`, for a missing entry, is level 2; values other than 1 to 3
+    /// are taken as level 1, so nothing is permitted that the signer may not have meant to permit.
+    pub fn from_level(level: Option<i64>) -> Self {
+        match level {
+            None | Some(2) => DocMdpPermission::FormFilling,
+            Some(3) => DocMdpPermission::Annotations,
+            Some(_) => DocMdpPermission::NoChanges,
+        }
+    }
+
+    /// The `/P` value of the permission.
+    pub fn level(self) -> i64 {
+        match self {
+            DocMdpPermission::NoChanges => 1,
+            DocMdpPermission::FormFilling => 2,
+            DocMdpPermission::Annotations => 3,
+        }
+    }
+
+    /// Whether a change of the kind `change` keeps the certification valid.
+    pub fn allows(self, change: CertifiedChange) -> bool {
+        match change {
+            CertifiedChange::FillForms => self != DocMdpPermission::NoChanges,
+            CertifiedChange::Annotate => self == DocMdpPermission::Annotations,
+            CertifiedChange::Other => false,
+        }
+    }
+}
+
+/// The kind of change a method makes to a document, checked against its certification when
+/// [`Document::set_respect_certification`] is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertifiedChange {
+    /// Filling in form fields.
+    FillForms,
+    /// Creating, deleting or modifying annotations other than form fields.
+    Annotate,
+    /// Any other change, like deleting pages or rewriting page content.
+    Other,
+}
+
+/// The certification signature of a document, see [`Document::certification`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificationInfo {
+    /// The signature dictionary, `None` if it is written directly in `/Perms`.
+    pub signature_id: Option<ObjectId>,
+    /// The changes the signature permits.
+    pub permission: DocMdpPermission,
+    /// The signature handler `/Filter`, e.g. `Adobe.PPKLite`.
+    pub filter: Option<Vec<u8>>,
+    /// The encoding of the signature `/SubFilter`, e.g. `adbe.pkcs7.detached`.
+    pub sub_filter: Option<Vec<u8>>,
+    /// The `/Name` of the signer.
+    pub name: Option<String>,
+    /// The `/Reason` for signing.
+    pub reason: Option<String>,
+    /// The `/Location` of signing.
+    pub location: Option<String>,
+    /// The `/ContactInfo` of the signer.
+    pub contact_info: Option<String>,
+    /// The time of signing `/M`, as written, e.g. `D:20240102030405+01'00'`.
+    pub signing_time: Option<String>,
+}
+
+impl Document {
+    /// Get the certification signature of the document, referenced by the `/DocMDP` entry of the
+    /// catalog `/Perms`, with the changes it permits. `None` if the document isn't certified.
+    ///
+    /// The permission is taken from the DocMDP signature reference of the signature; a signature
+    /// without one permits form filling, the default level. The signature itself isn't verified.
+    pub fn certification(&self) -> Option<CertificationInfo> {
+        let perms = self.catalog().ok()?.get_deref(b"Perms", self).ok()?.as_dict().ok()?;
+        let (signature_id, signature) = self.dereference(perms.get(b"DocMDP").ok()?).ok()?;
+        let signature = signature.as_dict().ok()?;
+        let name = |key: &[u8]| signature.get(key).and_then(Object::as_name).ok().map(<[u8]>::to_vec);
+        let text = |key: &[u8]| signature.get(key).ok().and_then(|value| decode_text_string(value).ok());
+        Some(CertificationInfo {
+            signature_id,
+            permission: DocMdpPermission::from_level(self.docmdp_level(signature)),
+            filter: name(b"Filter"),
+            sub_filter: name(b"SubFilter"),
+            name: text(b"Name"),
+            reason: text(b"Reason"),
+            location: text(b"Location"),
+            contact_info: text(b"ContactInfo"),
+            signing_time: text(b"M"),
+        })
+    }
+
+    /// Check changes against the certification of the document before making them, see
+    /// [`Document::check_certification`]. Off by default.
+    ///
+    /// Methods making a change of a kind the certification doesn't permit then fail with
+    /// [`Error::CertificationViolation`] and leave the document untouched: [`Document::try_delete_pages`],
+    /// [`Document::replace_text`], [`Document::replace_partial_text`], [`Document::remove_annotation`]
+    /// and [`Document::update_annotation`]. [`Document::delete_pages`] logs the error instead.
+    pub fn set_respect_certification(&mut self, respect: bool) {
+        self.respect_certification = respect;
+    }
+
+    /// Whether changes are checked against the certification, see [`Document::set_respect_certification`].
+    pub fn respects_certification(&self) -> bool {
+        self.respect_certification
+    }
+
+    /// Fail with [`Error::CertificationViolation`] if changes are checked against the certification of
+    /// the document and it doesn't permit a change of the kind `change`.
+    pub fn check_certification(&self, change: CertifiedChange) -> Result<()> {
+        if !self.respect_certification {
+            return Ok(());
+        }
+        match self.certification() {
+            Some(certification) if !certification.permission.allows(change) => Err(Error::CertificationViolation {
+                permission: certification.permission,
+                change,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The `/P` of the DocMDP signature reference of a signature dictionary.
+    fn docmdp_level(&self, signature: &Dictionary) -> Option<i64> {
+        let references = signature
+            .get_deref(b"Reference", self)
+            .and_then(Object::as_array)
+            .ok()?;
+        references
+            .iter()
+            .filter_map(|reference| self.dereference(reference).ok()?.1.as_dict().ok())
+            .find(|reference| {
+                reference
+                    .get(b"TransformMethod")
+                    .and_then(Object::as_name)
+                    .is_ok_and(|method| method == b"DocMDP")
+            })
+            .and_then(|reference| {
+                reference
+                    .get_deref(b"TransformParams", self)
+                    .and_then(Object::as_dict)
+                    .ok()
+            })
+            .and_then(|params| params.get(b"P").and_then(Object::as_i64).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+    use crate::StringFormat;
+
+    /// A document certified with DocMDP permission level 2, with a text field.
+    fn certified_document() -> (Document, ObjectId) {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let page_id = doc.page_iter().next().unwrap();
+        let signature_id = doc.add_object(dictionary! {
+            "Type" => "Sig",
+            "Filter" => "Adobe.PPKLite",
+            "SubFilter" => "adbe.pkcs7.detached",
+            "Name" => Object::string_literal("Certifier"),
+            "Reason" => Object::string_literal("I am the author"),
+            "M" => Object::string_literal("D:20240102030405Z"),
+            "ByteRange" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+            "Contents" => Object::String(vec![0; 16], StringFormat::Hexadecimal),
+            "Reference" => vec![dictionary! {
+                "Type" => "SigRef",
+                "TransformMethod" => "DocMDP",
+                "TransformParams" => dictionary! { "Type" => "TransformParams", "P" => 2, "V" => "1.2" },
+            }.into()],
+        });
+        let signature_field_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Sig",
+            "T" => Object::string_literal("Certification"),
+            "V" => signature_id,
+            "Rect" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+            "P" => page_id,
+        });
+        let text_field_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Tx",
+            "T" => Object::string_literal("Name"),
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 20.into()],
+            "P" => page_id,
+        });
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Annots", vec![signature_field_id.into(), text_field_id.into()]);
+        let catalog = doc.catalog_mut().unwrap();
+        catalog.set(
+            "AcroForm",
+            dictionary! {
+                "Fields" => vec![signature_field_id.into(), text_field_id.into()],
+                "SigFlags" => 3,
+            },
+        );
+        catalog.set("Perms", dictionary! { "DocMDP" => signature_id });
+        (doc, text_field_id)
+    }
+
+    #[test]
+    fn certification_limits_changes() {
+        let (mut doc, text_field_id) = certified_document();
+        let certification = doc.certification().unwrap();
+        assert_eq!(certification.permission, DocMdpPermission::FormFilling);
+        assert_eq!(certification.permission.level(), 2);
+        assert_eq!(certification.filter.as_deref(), Some(b"Adobe.PPKLite".as_slice()));
+        assert_eq!(certification.name.as_deref(), Some("Certifier"));
+        assert_eq!(certification.reason.as_deref(), Some("I am the author"));
+        assert_eq!(certification.signing_time.as_deref(), Some("D:20240102030405Z"));
+        assert_eq!(certification.location, None);
+
+        // The certification survives saving.
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let mut doc = Document::load_mem(&buffer).unwrap();
+        assert_eq!(doc.certification(), Some(certification));
+
+        // Nothing is checked unless asked for.
+        assert!(!doc.respects_certification());
+        doc.check_certification(CertifiedChange::Other).unwrap();
+        doc.set_respect_certification(true);
+
+        // Filling in the form is permitted.
+        doc.update_annotation(text_field_id, |field| field.set("V", Object::string_literal("Jane")))
+            .unwrap();
+        let page_id = doc.page_iter().next().unwrap();
+        assert!(matches!(
+            doc.remove_annotation(page_id, text_field_id),
+            Err(Error::CertificationViolation {
+                permission: DocMdpPermission::FormFilling,
+                change: CertifiedChange::Annotate,
+            })
+        ));
+        // Deleting pages isn't, and leaves the document untouched.
+        assert!(matches!(
+            doc.try_delete_pages(&[2]),
+            Err(Error::CertificationViolation {
+                change: CertifiedChange::Other,
+                ..
+            })
+        ));
+        doc.delete_pages(&[2]);
+        assert_eq!(doc.get_pages().len(), 2);
+        assert!(doc.replace_text(1, "first", "other").is_err());
+
+        doc.set_respect_certification(false);
+        doc.try_delete_pages(&[2]).unwrap();
+        assert_eq!(doc.get_pages().len(), 1);
+    }
+
+    #[test]
+    fn permission_levels() {
+        assert_eq!(DocMdpPermission::from_level(None), DocMdpPermission::FormFilling);
+        assert_eq!(DocMdpPermission::from_level(Some(1)), DocMdpPermission::NoChanges);
+        assert_eq!(DocMdpPermission::from_level(Some(3)), DocMdpPermission::Annotations);
+        assert_eq!(DocMdpPermission::from_level(Some(7)), DocMdpPermission::NoChanges);
+        assert!(!DocMdpPermission::NoChanges.allows(CertifiedChange::FillForms));
+        assert!(DocMdpPermission::Annotations.allows(CertifiedChange::Annotate));
+        assert!(!DocMdpPermission::Annotations.allows(CertifiedChange::Other));
+    }
+}
@@ -1,8 +1,22 @@
 use crate::{
     encodings::{self, bytes_to_string},
-    Error, Object, Result, StringFormat,
+    Document, Error, Object, Result, StringFormat,
 };
 
+/// How the text strings lopdf creates for a document are encoded, see
+/// [`Document::set_text_string_encoding`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextStringEncoding {
+    /// PDFDocEncoding for ASCII text and UTF-16BE otherwise, which all PDF versions read.
+    #[default]
+    Utf16,
+    /// UTF-8 for text beyond ASCII, which PDF 2.0 introduced. It can't be chosen for a document of
+    /// an earlier version.
+    Utf8,
+    /// UTF-8 like `Utf8`, raising the version of the document to 2.0 if it is earlier.
+    Utf8UpgradeVersion,
+}
+
 /// Creates a text string.
 /// If the input only contains ASCII characters, the string is encoded
 /// in PDFDocEncoding, otherwise in UTF-16BE.
@@ -13,6 +27,17 @@ pub fn text_string(text: &str) -> Object {
     Object::String(encodings::encode_utf16_be(text), StringFormat::Hexadecimal)
 }
 
+/// Creates a text string like [`text_string`], encoding text beyond ASCII in UTF-8 with its byte
+/// order mark instead of UTF-16BE if `prefer_utf8`. UTF-8 text strings need PDF 2.0.
+pub fn text_string_v2(text: &str, prefer_utf8: bool) -> Object {
+    if !prefer_utf8 || text.is_ascii() {
+        return text_string(text);
+    }
+    let mut bytes = b"\xEF\xBB\xBF".to_vec();
+    bytes.extend(text.as_bytes());
+    Object::String(bytes, StringFormat::Literal)
+}
+
 /// Decodes a text string.
 /// Depending on the BOM at the start of the string, a different encoding is chosen.
 /// All encodings specified in PDF2.0 are supported (PDFDocEncoding, UTF-16BE,
@@ -36,20 +61,121 @@ pub fn decode_text_string(obj: &Object) -> Result<String> {
         .map_err(|_| Error::TextStringDecode)
     } else if s.starts_with(b"\xEF\xBB\xBF") {
         // Detected UTF-8 BOM
-        String::from_utf8(s.to_vec()).map_err(|_| Error::TextStringDecode)
+        String::from_utf8(s[3..].to_vec()).map_err(|_| Error::TextStringDecode)
+    } else if let Some(text) = decode_bom_less_utf16(s) {
+        Ok(text)
     } else {
         // If neither BOM is detected, PDFDocEncoding is used
         Ok(bytes_to_string(&encodings::PDF_DOC_ENCODING, s))
     }
 }
 
+impl Document {
+    /// Choose how the text strings lopdf creates are encoded, e.g. the titles of bookmarks, the
+    /// `/Producer` stamped when saving and the `/Lang` of the catalog.
+    ///
+    /// [`TextStringEncoding::Utf8`] fails with [`Error::UnsupportedVersion`] for a document whose
+    /// version is below 2.0, [`TextStringEncoding::Utf8UpgradeVersion`] raises the version to 2.0.
+    /// Should the version be lowered afterwards, text strings are created in UTF-16BE again.
+    pub fn set_text_string_encoding(&mut self, encoding: TextStringEncoding) -> Result<()> {
+        if encoding != TextStringEncoding::Utf16 && !self.is_version_at_least((2, 0)) {
+            if encoding == TextStringEncoding::Utf8 {
+                return Err(Error::UnsupportedVersion {
+                    feature: "UTF-8 text strings",
+                    required: "2.0",
+                    version: self.version.clone(),
+                });
+            }
+            self.version = "2.0".to_string();
+        }
+        self.text_string_encoding = encoding;
+        Ok(())
+    }
+
+    /// Get how the text strings lopdf creates are encoded, see [`Document::set_text_string_encoding`].
+    pub fn text_string_encoding(&self) -> TextStringEncoding {
+        self.text_string_encoding
+    }
+
+    /// Create a text string with the encoding chosen for the document, see
+    /// [`Document::set_text_string_encoding`], and UTF-16BE while the version is below 2.0.
+    pub fn encode_text_string(&self, text: &str) -> Object {
+        text_string_v2(text, self.prefers_utf8_text_strings())
+    }
+}
+
+/// Whether a character is likely to appear in a text string: printable characters of the Latin,
+/// Greek, Cyrillic and CJK scripts, general punctuation and whitespace.
+fn is_plausible_text(ch: char) -> bool {
+    matches!(ch,
+        '\t' | '\n' | '\r'
+        | '\u{20}'..='\u{7E}'
+        | '\u{A0}'..='\u{24F}'
+        | '\u{370}'..='\u{4FF}'
+        | '\u{2000}'..='\u{206F}'
+        | '\u{3000}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7A3}'
+        | '\u{FF00}'..='\u{FFEF}')
+}
+
+/// Decode a text string written in UTF-16BE without the byte order mark, as some producers do.
+///
+/// The detection is conservative, `None` is returned unless the bytes can't be plausible
+/// PDFDocEncoding text and make plausible UTF-16BE text:
+/// * every other byte is 0, as in `\0T\0i\0t\0l\0e`, which is only used by control codes in
+///   PDFDocEncoding, or
+/// * some bytes are undefined in PDFDocEncoding, and the string decodes to text of the scripts
+///   [`is_plausible_text`] accepts, beyond Latin-1.
+pub(crate) fn decode_bom_less_utf16(bytes: &[u8]) -> Option<String> {
+    if bytes.is_empty() || bytes.len() % 2 != 0 {
+        return None;
+    }
+    let is_undefined = |&byte: &u8| encodings::PDF_DOC_ENCODING[byte as usize].is_none() && !b"\t\n\r".contains(&byte);
+    if !bytes.iter().any(is_undefined) {
+        return None;
+    }
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    let text = String::from_utf16(&units).ok()?;
+    if !text.chars().all(is_plausible_text) {
+        return None;
+    }
+    let latin_1 = bytes.chunks_exact(2).all(|pair| pair[0] == 0);
+    (latin_1 || text.chars().any(|ch| ch > '\u{FF}')).then_some(text)
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
-        common_data_structures::decode_text_string, encodings, parser::ParserInput, text_string, writer::Writer,
-        Object, StringFormat,
+        common_data_structures::decode_text_string, encodings, parser::ParserInput, text_string, text_string_v2,
+        writer::Writer, Error, Object, SaveOptions, StringFormat, TextStringEncoding,
     };
 
+    fn decode(bytes: &[u8]) -> String {
+        decode_text_string(&Object::String(bytes.to_vec(), StringFormat::Literal)).unwrap()
+    }
+
+    #[test]
+    fn bom_less_utf16_is_detected() {
+        assert_eq!(decode(b"Plain title"), "Plain title");
+        // Latin-1 bytes, "Café déjà vu" in PDFDocEncoding.
+        assert_eq!(decode(b"Caf\xE9 d\xE9j\xE0 vu"), "Café déjà vu");
+        assert_eq!(decode(b"\xC0\xE9\xC8\xE8"), "ÀéÈè");
+        assert_eq!(decode(b"\0T\0i\0t\0l\0e"), "Title");
+        assert_eq!(decode(b"\0C\0a\0f\0\xE9"), "Café");
+        assert_eq!(decode(&encodings::encode_utf16_be("中文标题")[2..]), "中文标题");
+        assert_eq!(decode(&encodings::encode_utf16_be("Глава 1")[2..]), "Глава 1");
+        // Binary garbage isn't taken for UTF-16, undefined bytes are dropped as before.
+        assert_eq!(decode(b"\x01\x02\xFF\xFE\x80\x00\x13\x37"), "ÿþ•7");
+        assert_eq!(decode(b"\x00\x01\x00\x02"), "");
+        // Odd length.
+        assert_eq!(decode(b"\0T\0i\0"), "Ti");
+    }
+
     #[test]
     fn spec_example1_encode() {
         let input = "text‰";
@@ -59,7 +185,7 @@ fn spec_example1_encode() {
             "Key" => Object::String(text_string, StringFormat::Literal),
         ));
         let mut actual = vec![];
-        Writer::write_object(&mut actual, &dict).unwrap();
+        Writer::write_object(&mut actual, &dict, &SaveOptions::default()).unwrap();
         // "\x8B" is equivalent to the escaped version "\\213" which is used
         // in the original example.
         let expected = b"<</Key(text\x8B)>>";
@@ -85,7 +211,7 @@ fn spec_example2_encode() {
             "Key" => text_string(input),
         ));
         let mut actual = vec![];
-        Writer::write_object(&mut actual, &dict).unwrap();
+        Writer::write_object(&mut actual, &dict, &SaveOptions::default()).unwrap();
         let expected = b"<</Key<FEFF0442043504410442>>>";
         assert_eq!(actual.as_slice(), expected);
     }
@@ -100,4 +226,67 @@ fn spec_example2_decode() {
         let expected = "тест";
         assert_eq!(&actual, expected);
     }
+
+    #[test]
+    fn utf8_text_strings() {
+        for text in ["Plain", "Café", "тест", "中文标题"] {
+            for prefer_utf8 in [false, true] {
+                let object = text_string_v2(text, prefer_utf8);
+                let bytes = object.as_str().unwrap();
+                assert_eq!(bytes.starts_with(b"\xEF\xBB\xBF"), prefer_utf8 && !text.is_ascii());
+                assert_eq!(decode_text_string(&object).unwrap(), text);
+            }
+        }
+    }
+
+    #[test]
+    fn text_string_encoding_of_document() {
+        let title = "Überblick";
+        let mut doc = crate::creator::tests::create_document();
+        doc.version = "1.7".to_string();
+        assert!(matches!(
+            doc.set_text_string_encoding(TextStringEncoding::Utf8),
+            Err(Error::UnsupportedVersion { .. })
+        ));
+        assert_eq!(doc.text_string_encoding(), TextStringEncoding::Utf16);
+        assert!(doc.encode_text_string(title).as_str().unwrap().starts_with(b"\xFE\xFF"));
+
+        doc.set_text_string_encoding(TextStringEncoding::Utf8UpgradeVersion)
+            .unwrap();
+        assert_eq!(doc.version, "2.0");
+        doc.set_text_string_encoding(TextStringEncoding::Utf8).unwrap();
+        assert!(doc
+            .encode_text_string(title)
+            .as_str()
+            .unwrap()
+            .starts_with(b"\xEF\xBB\xBF"));
+
+        let page_id = doc.page_iter().next().unwrap();
+        doc.add_bookmark(crate::Bookmark::new(title.to_string(), [0.0; 3], 0, page_id), None);
+        let outline_id = doc.build_outline().unwrap();
+        doc.catalog_mut().unwrap().set("Outlines", outline_id);
+        let options = SaveOptions {
+            update_producer: Some("Générateur".to_string()),
+            ..SaveOptions::default()
+        };
+        let mut buffer = vec![];
+        doc.save_with_options(&mut buffer, options).unwrap();
+        let doc = crate::Document::load_mem(&buffer).unwrap();
+        assert_eq!(doc.version, "2.0");
+        let info = doc.trailer.get(b"Info").and_then(Object::as_reference).unwrap();
+        let producer = doc.get_dictionary(info).unwrap().get(b"Producer").unwrap();
+        assert!(producer.as_str().unwrap().starts_with(b"\xEF\xBB\xBF"));
+        assert_eq!(decode_text_string(producer).unwrap(), "Générateur");
+        let outline = doc.get_dictionary(outline_id).unwrap();
+        let first = outline.get(b"First").and_then(Object::as_reference).unwrap();
+        let bookmark_title = doc.get_dictionary(first).unwrap().get(b"Title").unwrap();
+        assert!(bookmark_title.as_str().unwrap().starts_with(b"\xEF\xBB\xBF"));
+        assert_eq!(decode_text_string(bookmark_title).unwrap(), title);
+
+        // Lowering the version brings back UTF-16BE.
+        let mut doc = doc;
+        doc.set_text_string_encoding(TextStringEncoding::Utf8).unwrap();
+        doc.version = "1.7".to_string();
+        assert!(doc.encode_text_string(title).as_str().unwrap().starts_with(b"\xFE\xFF"));
+    }
 }
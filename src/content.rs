@@ -1,7 +1,10 @@
-use super::Object;
-use crate::writer::Writer;
-use crate::Result;
-use std::io::Write;
+use super::{Dictionary, Object, StringFormat};
+use crate::font_metrics::{simple_font_widths, FontWidths};
+use crate::object::array_from_objects;
+#[cfg(feature = "nom_parser")]
+use crate::parser::{self, ParserInput};
+use crate::writer::{SaveOptions, Writer};
+use crate::{Error, Result};
 
 #[derive(Debug, Clone)]
 pub struct Operation {
@@ -24,23 +27,629 @@ pub struct Content<Operations: AsRef<[Operation]> = Vec<Operation>> {
 }
 
 impl<Operations: AsRef<[Operation]>> Content<Operations> {
+    /// Estimate the number of bytes the encoded operations take, for preallocating buffers.
+    pub fn operations_size_hint(&self) -> usize {
+        fn object_size_hint(object: &Object) -> usize {
+            match object {
+                Object::Null => 4,
+                Object::Boolean(_) => 5,
+                Object::Integer(_) => 4,
+                Object::Real(_) => 6,
+                Object::Name(name) => name.len() + 1,
+                Object::String(text, _) => text.len() + 2,
+                Object::Array(array) => array.iter().map(|item| object_size_hint(item) + 1).sum::<usize>() + 2,
+                Object::Dictionary(dict) => dict
+                    .iter()
+                    .map(|(key, value)| key.len() + object_size_hint(value) + 2)
+                    .sum::<usize>()
+                    .saturating_add(4),
+                Object::Stream(stream) => stream.content.len() + 16,
+                Object::Reference(_) => 8,
+                Object::RawToken(token) => token.len(),
+            }
+        }
+
+        self.operations
+            .as_ref()
+            .iter()
+            .map(|operation| {
+                let operands: usize = operation
+                    .operands
+                    .iter()
+                    .map(|operand| object_size_hint(operand) + 1)
+                    .sum();
+                operands + operation.operator.len() + 1
+            })
+            .sum()
+    }
+
     /// Encode content operations.
     pub fn encode(&self) -> Result<Vec<u8>> {
+        self.encode_with_options(&SaveOptions::default())
+    }
+
+    /// Encode content operations, formatting operands according to `options`.
+    pub fn encode_with_options(&self, options: &SaveOptions) -> Result<Vec<u8>> {
         let mut buffer = Vec::new();
+        self.encode_into_with_options(&mut buffer, options)?;
+        Ok(buffer)
+    }
+
+    /// Encode content operations, appending them to `buffer`.
+    ///
+    /// Reusing one buffer avoids reallocating when encoding many content streams.
+    pub fn encode_into(&self, buffer: &mut Vec<u8>) -> Result<()> {
+        self.encode_into_with_options(buffer, &SaveOptions::default())
+    }
+
+    /// Encode content operations into `buffer`, formatting operands according to `options`.
+    pub fn encode_into_with_options(&self, buffer: &mut Vec<u8>, options: &SaveOptions) -> Result<()> {
+        buffer.reserve(self.operations_size_hint());
         let mut first_operation = true;
         for operation in self.operations.as_ref() {
             // Add new line after each operation except the last one.
             if first_operation {
                 first_operation = false;
             } else {
-                buffer.write_all(b"\n")?;
+                buffer.push(b'\n');
             }
             for operand in &operation.operands {
-                Writer::write_object(&mut buffer, operand)?;
-                buffer.write_all(b" ")?;
+                Writer::write_object(buffer, operand, options)?;
+                buffer.push(b' ');
             }
-            buffer.write_all(operation.operator.as_bytes())?;
+            buffer.extend_from_slice(operation.operator.as_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// The type of an operand of a content stream operator, see [`operator_operands`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    /// An integer or real number.
+    Number,
+    Name,
+    String,
+    Array,
+    /// A property list, given inline or as the name of an entry in the `/Properties` resources.
+    NameOrDictionary,
+    /// Any number of numbers, e.g. the color components of `sc`.
+    Numbers,
+    /// An optional name after the other operands, e.g. the pattern of `scn`.
+    OptionalName,
+}
+
+impl OperandKind {
+    fn describe(self) -> &'static str {
+        match self {
+            OperandKind::Number => "a number",
+            OperandKind::Name | OperandKind::OptionalName => "a name",
+            OperandKind::String => "a string",
+            OperandKind::Array => "an array",
+            OperandKind::NameOrDictionary => "a name or dictionary",
+            OperandKind::Numbers => "numbers",
+        }
+    }
+
+    fn matches(self, operand: &Object) -> bool {
+        match self {
+            OperandKind::Number | OperandKind::Numbers => matches!(operand, Object::Integer(_) | Object::Real(_)),
+            OperandKind::Name | OperandKind::OptionalName => matches!(operand, Object::Name(_)),
+            OperandKind::String => matches!(operand, Object::String(..)),
+            OperandKind::Array => matches!(operand, Object::Array(_)),
+            OperandKind::NameOrDictionary => matches!(operand, Object::Name(_) | Object::Dictionary(_)),
+        }
+    }
+}
+
+/// Operands of the operators of the PDF specification, except the inline image operator `BI`
+/// whose operands are parsed with it.
+const OPERATORS: &[(&str, &[OperandKind])] = {
+    use OperandKind::*;
+    const NONE: &[OperandKind] = &[];
+    const POINT: &[OperandKind] = &[Number, Number];
+    const MATRIX: &[OperandKind] = &[Number, Number, Number, Number, Number, Number];
+    const FOUR: &[OperandKind] = &[Number, Number, Number, Number];
+    &[
+        // General graphics state
+        ("w", &[Number]),
+        ("J", &[Number]),
+        ("j", &[Number]),
+        ("M", &[Number]),
+        ("d", &[Array, Number]),
+        ("ri", &[Name]),
+        ("i", &[Number]),
+        ("gs", &[Name]),
+        // Special graphics state
+        ("q", NONE),
+        ("Q", NONE),
+        ("cm", MATRIX),
+        // Path construction and painting
+        ("m", POINT),
+        ("l", POINT),
+        ("c", MATRIX),
+        ("v", FOUR),
+        ("y", FOUR),
+        ("h", NONE),
+        ("re", FOUR),
+        ("S", NONE),
+        ("s", NONE),
+        ("f", NONE),
+        ("F", NONE),
+        ("f*", NONE),
+        ("B", NONE),
+        ("B*", NONE),
+        ("b", NONE),
+        ("b*", NONE),
+        ("n", NONE),
+        ("W", NONE),
+        ("W*", NONE),
+        // Text objects, state, positioning and showing
+        ("BT", NONE),
+        ("ET", NONE),
+        ("Tc", &[Number]),
+        ("Tw", &[Number]),
+        ("Tz", &[Number]),
+        ("TL", &[Number]),
+        ("Tf", &[Name, Number]),
+        ("Tr", &[Number]),
+        ("Ts", &[Number]),
+        ("Td", POINT),
+        ("TD", POINT),
+        ("Tm", MATRIX),
+        ("T*", NONE),
+        ("Tj", &[String]),
+        ("TJ", &[Array]),
+        ("'", &[String]),
+        ("\"", &[Number, Number, String]),
+        // Type 3 fonts
+        ("d0", POINT),
+        ("d1", MATRIX),
+        // Color
+        ("CS", &[Name]),
+        ("cs", &[Name]),
+        ("SC", &[Numbers]),
+        ("sc", &[Numbers]),
+        ("SCN", &[Numbers, OptionalName]),
+        ("scn", &[Numbers, OptionalName]),
+        ("G", &[Number]),
+        ("g", &[Number]),
+        ("RG", &[Number, Number, Number]),
+        ("rg", &[Number, Number, Number]),
+        ("K", FOUR),
+        ("k", FOUR),
+        // Shading, XObjects and marked content
+        ("sh", &[Name]),
+        ("Do", &[Name]),
+        ("MP", &[Name]),
+        ("DP", &[Name, NameOrDictionary]),
+        ("BMC", &[Name]),
+        ("BDC", &[Name, NameOrDictionary]),
+        ("EMC", NONE),
+        // Compatibility
+        ("BX", NONE),
+        ("EX", NONE),
+    ]
+};
+
+/// Get the operands expected by an operator of the PDF specification, `None` for other operators.
+pub fn operator_operands(operator: &str) -> Option<&'static [OperandKind]> {
+    OPERATORS
+        .iter()
+        .find(|(name, _)| *name == operator)
+        .map(|(_, operands)| *operands)
+}
+
+impl Operation {
+    /// Check the operands against [`operator_operands`], describing the mismatch if they don't fit.
+    ///
+    /// Operators which aren't in the table, including `BI`, aren't checked.
+    pub(crate) fn operand_mismatch(&self) -> Option<String> {
+        let expected = operator_operands(&self.operator)?;
+        let mut operands = self.operands.iter().peekable();
+        for kind in expected {
+            match kind {
+                OperandKind::Numbers => while operands.next_if(|operand| kind.matches(operand)).is_some() {},
+                OperandKind::OptionalName => {
+                    operands.next_if(|operand| kind.matches(operand));
+                }
+                _ => match operands.next() {
+                    Some(operand) if kind.matches(operand) => {}
+                    Some(operand) => {
+                        return Some(format!(
+                            "`{}` expects {} but found {}",
+                            self.operator,
+                            kind.describe(),
+                            operand.enum_variant()
+                        ))
+                    }
+                    None => {
+                        let described: Vec<_> = expected.iter().map(|kind| kind.describe()).collect();
+                        return Some(format!(
+                            "`{}` expects {} operands ({}), found {}",
+                            self.operator,
+                            expected.len(),
+                            described.join(", "),
+                            self.operands.len()
+                        ));
+                    }
+                },
+            }
+        }
+        operands.next().map(|extra| {
+            format!(
+                "`{}` has an unexpected extra operand {}",
+                self.operator,
+                extra.enum_variant()
+            )
+        })
+    }
+}
+
+/// The error for invalid content at `offset` of `bytes`, quoting the token there.
+pub(crate) fn invalid_content(bytes: &[u8], offset: usize, reason: String) -> Error {
+    let rest = &bytes[offset.min(bytes.len())..];
+    let end = rest
+        .iter()
+        .skip(1)
+        .position(|c| c.is_ascii_whitespace())
+        .map_or(rest.len(), |end| end + 1);
+    Error::ContentFragment {
+        offset,
+        token: String::from_utf8_lossy(&rest[..end]).into_owned(),
+        reason,
+    }
+}
+
+/// An operand of a content operation, borrowing its bytes from the content stream.
+///
+/// Nothing is decoded until needed, see [`RawOperand::to_object`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawOperand<'a> {
+    Null,
+    Boolean(bool),
+    /// The digits of an integer or real number.
+    Number(&'a [u8]),
+    /// A name, with its leading `/` and its `#` escapes.
+    Name(&'a [u8]),
+    /// A string, with its delimiters and its escapes or white-space.
+    String(&'a [u8], StringFormat),
+    /// An array, including its brackets.
+    Array(&'a [u8]),
+    /// A dictionary, including its delimiters.
+    Dictionary(&'a [u8]),
+    /// The dictionary, data and `EI` operator of an inline image, the operand of `BI`.
+    InlineImage(&'a [u8]),
+    /// A token which isn't a PDF object, see [`Object::RawToken`].
+    Token(&'a [u8]),
+}
+
+#[cfg(feature = "nom_parser")]
+impl RawOperand<'_> {
+    /// The value of a number operand.
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            RawOperand::Number(number) => std::str::from_utf8(number).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value of an integer operand.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            RawOperand::Number(number) => std::str::from_utf8(number).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Decode the operand into the object [`Content::decode`] gives for it.
+    pub fn to_object(&self) -> Result<Object> {
+        parser::raw_operand_object(self).ok_or(Error::Parse(crate::error::ParseError::InvalidContentStream))
+    }
+}
+
+/// A content operation borrowing its operator and operands from the content stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawOperation<'a> {
+    pub operator: &'a str,
+    pub operands: Vec<RawOperand<'a>>,
+}
+
+#[cfg(feature = "nom_parser")]
+impl RawOperation<'_> {
+    /// Decode the operands into an owned [`Operation`].
+    pub fn to_operation(&self) -> Result<Operation> {
+        Ok(Operation {
+            operator: self.operator.to_string(),
+            operands: self.operands.iter().map(RawOperand::to_object).collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// Iterator lexing the operations of a content stream one at a time, see [`Content::iter_operations`].
+///
+/// It ends after an error, at the first bytes which aren't an operation, where [`Content::decode`]
+/// stops too.
+#[cfg(feature = "nom_parser")]
+#[derive(Debug, Clone)]
+pub struct OperationIter<'a> {
+    data: &'a [u8],
+    input: Option<ParserInput<'a>>,
+}
+
+#[cfg(feature = "nom_parser")]
+impl<'a> OperationIter<'a> {
+    /// Decode each operation into an owned [`Operation`], ending with a warning at the first error.
+    pub fn cloned_operations(self) -> impl Iterator<Item = Operation> + 'a {
+        self.map_while(
+            |operation| match operation.and_then(|operation| operation.to_operation()) {
+                Ok(operation) => Some(operation),
+                Err(err) => {
+                    log::warn!("Stopped decoding content operations: {err}");
+                    None
+                }
+            },
+        )
+    }
+}
+
+#[cfg(feature = "nom_parser")]
+impl<'a> Iterator for OperationIter<'a> {
+    type Item = Result<RawOperation<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let input = self.input.take().filter(|input| !input.is_empty())?;
+        match parser::raw_operation(input) {
+            Some((rest, operation)) => {
+                self.input = Some(rest);
+                Some(Ok(operation))
+            }
+            None => Some(Err(invalid_content(
+                self.data,
+                input.location_offset(),
+                "not a content operation".to_string(),
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "nom_parser")]
+impl std::iter::FusedIterator for OperationIter<'_> {}
+
+#[cfg(feature = "nom_parser")]
+impl Content<Vec<Operation>> {
+    /// Lex content operations one at a time, without decoding the whole content like [`Content::decode`].
+    ///
+    /// Operands borrow from `data`, so long content streams can be scanned in little memory.
+    pub fn iter_operations(data: &[u8]) -> OperationIter<'_> {
+        OperationIter {
+            data,
+            input: Some(parser::skip_content_space(ParserInput::new_extra(
+                data,
+                "content operations",
+            ))),
+        }
+    }
+}
+
+pub(crate) const IDENTITY_MATRIX: [f32; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+pub(crate) fn matrix_from_operands(operands: &[Object]) -> Option<[f32; 6]> {
+    array_from_objects(operands).ok()
+}
+
+/// Multiply two transformation matrices, `m` is applied before `n`.
+pub(crate) fn multiply_matrix(m: &[f32; 6], n: &[f32; 6]) -> [f32; 6] {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+/// Text state parameters and matrices in effect while showing text, see section 9.3 of the PDF specification.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextState {
+    /// Character spacing `Tc`, in unscaled text space units.
+    pub char_spacing: f32,
+    /// Word spacing `Tw`, in unscaled text space units, added to the single-byte code 32.
+    pub word_spacing: f32,
+    /// Horizontal scaling `Tz`, in percent.
+    pub horizontal_scaling: f32,
+    /// Leading `TL`, the distance between lines moved by `T*`, in unscaled text space units.
+    pub leading: f32,
+    /// Font size given to `Tf`.
+    pub font_size: f32,
+    /// Text rise `Ts`, in unscaled text space units.
+    pub rise: f32,
+    /// Text rendering mode `Tr`, see [`TextState::is_clipping`].
+    pub render_mode: i64,
+    /// Text matrix `Tm`, moved by every shown glyph.
+    pub text_matrix: [f32; 6],
+    /// Text line matrix, the text matrix at the start of the current line.
+    pub line_matrix: [f32; 6],
+}
+
+impl Default for TextState {
+    fn default() -> Self {
+        TextState {
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scaling: 100.0,
+            leading: 0.0,
+            font_size: 0.0,
+            rise: 0.0,
+            render_mode: 0,
+            text_matrix: IDENTITY_MATRIX,
+            line_matrix: IDENTITY_MATRIX,
+        }
+    }
+}
+
+impl TextState {
+    /// Update the state for a text object, text state or text positioning operator.
+    ///
+    /// The text showing operators `'` and `"` move to the next line and set the spacing before showing
+    /// their string, which is left to the caller. Returns whether the operation starts a new line:
+    /// `T*`, `'` and `"` always do, even with a leading of 0, other operators when they move to another
+    /// vertical position.
+    pub fn apply(&mut self, operation: &Operation) -> bool {
+        let operands = &operation.operands;
+        let number = |index: usize| operands.get(index).and_then(|it| it.as_float().ok());
+        let line_position = self.line_matrix[5];
+        match operation.operator.as_str() {
+            "BT" => {
+                self.text_matrix = IDENTITY_MATRIX;
+                self.line_matrix = IDENTITY_MATRIX;
+                return false;
+            }
+            "Tf" => self.font_size = number(1).unwrap_or(self.font_size),
+            "Tc" => self.char_spacing = number(0).unwrap_or(self.char_spacing),
+            "Tw" => self.word_spacing = number(0).unwrap_or(self.word_spacing),
+            "Tz" => self.horizontal_scaling = number(0).unwrap_or(self.horizontal_scaling),
+            "TL" => self.leading = number(0).unwrap_or(self.leading),
+            "Ts" => self.rise = number(0).unwrap_or(self.rise),
+            "Tr" => {
+                self.render_mode = operands
+                    .first()
+                    .and_then(|it| it.as_i64().ok())
+                    .unwrap_or(self.render_mode)
+            }
+            "Td" | "TD" => {
+                let (tx, ty) = (number(0).unwrap_or(0.0), number(1).unwrap_or(0.0));
+                if operation.operator == "TD" {
+                    self.leading = -ty;
+                }
+                self.move_line(tx, ty);
+            }
+            "Tm" => {
+                if let Some(matrix) = matrix_from_operands(operands) {
+                    self.line_matrix = matrix;
+                    self.text_matrix = matrix;
+                }
+            }
+            "T*" | "'" => self.move_line(0.0, -self.leading),
+            "\"" => {
+                self.word_spacing = number(0).unwrap_or(self.word_spacing);
+                self.char_spacing = number(1).unwrap_or(self.char_spacing);
+                self.move_line(0.0, -self.leading);
+            }
+            _ => {}
+        }
+        matches!(operation.operator.as_str(), "T*" | "'" | "\"") || self.line_matrix[5] != line_position
+    }
+
+    /// Whether glyphs are added to the clipping path, by rendering modes 4 to 7. Besides modes 4 to 6,
+    /// which also paint them, the glyphs of mode 7 are invisible and only clip what is painted after.
+    pub fn is_clipping(&self) -> bool {
+        (4..=7).contains(&self.render_mode)
+    }
+
+    fn move_line(&mut self, tx: f32, ty: f32) {
+        self.line_matrix = multiply_matrix(&[1.0, 0.0, 0.0, 1.0, tx, ty], &self.line_matrix);
+        self.text_matrix = self.line_matrix;
+    }
+
+    /// Horizontal displacement of a glyph of `width` thousandths of an em, in unscaled text space units.
+    ///
+    /// That is `(w / 1000 * Tfs + Tc + Tw) * Th`, where the word spacing only applies to `is_space` glyphs.
+    pub fn glyph_advance(&self, width: f32, is_space: bool) -> f32 {
+        let word_spacing = if is_space { self.word_spacing } else { 0.0 };
+        (width / 1000.0 * self.font_size + self.char_spacing + word_spacing) * self.horizontal_scaling / 100.0
+    }
+
+    /// Horizontal displacement of a number in a `TJ` array, in unscaled text space units.
+    pub fn adjustment_advance(&self, adjustment: f32) -> f32 {
+        -adjustment / 1000.0 * self.font_size * self.horizontal_scaling / 100.0
+    }
+
+    /// Move the text matrix by `tx` unscaled text space units, after showing a glyph.
+    pub fn advance(&mut self, tx: f32) {
+        self.text_matrix = multiply_matrix(&[1.0, 0.0, 0.0, 1.0, tx, 0.0], &self.text_matrix);
+    }
+
+    /// Rectangle `[llx lly urx ury]` in user space covered by glyphs shown from `start` to `end`
+    /// unscaled text space units after the current position, with the CTM `ctm`.
+    ///
+    /// Glyphs are assumed to extend from a quarter of the font size below the baseline to the font size above it.
+    pub fn bounds(&self, start: f32, end: f32, ctm: &[f32; 6]) -> [f32; 4] {
+        let matrix = multiply_matrix(&self.text_matrix, ctm);
+        let (bottom, top) = (self.rise - self.font_size / 4.0, self.rise + self.font_size);
+        [(start, bottom), (end, bottom), (start, top), (end, top)]
+            .map(|(x, y)| {
+                (
+                    matrix[0] * x + matrix[2] * y + matrix[4],
+                    matrix[1] * x + matrix[3] * y + matrix[5],
+                )
+            })
+            .iter()
+            .fold(
+                [f32::MAX, f32::MAX, f32::MIN, f32::MIN],
+                |[llx, lly, urx, ury], &(x, y)| [llx.min(x), lly.min(y), urx.max(x), ury.max(y)],
+            )
+    }
+}
+
+/// Glyph widths of a font, in thousandths of an em.
+#[derive(Clone)]
+pub(crate) enum GlyphWidths {
+    /// `/Widths` of a simple font, starting at `/FirstChar`.
+    Simple(FontWidths),
+    /// `/W` array of the descendant font of a composite font, as code ranges.
+    Composite { ranges: Vec<(i64, i64, f32)>, default: f32 },
+}
+
+impl GlyphWidths {
+    /// Read the widths of `font`, dereferencing indirect objects with `resolve`.
+    ///
+    /// Widths which aren't given default to one em, or to the `/MissingWidth` or `/DW` of the font.
+    /// Simple fonts are read as with [`FontMetrics::widths`](crate::FontMetrics::widths).
+    pub(crate) fn from_font<'a>(font: &'a Dictionary, resolve: impl Fn(&'a Object) -> &'a Object) -> Self {
+        let get = |dict: &'a Dictionary, key: &[u8]| dict.get(key).ok().map(&resolve);
+        if let Some(Object::Array(descendants)) = get(font, b"DescendantFonts") {
+            let descendant = descendants.first().map(&resolve).and_then(|it| it.as_dict().ok());
+            let default = descendant
+                .and_then(|it| get(it, b"DW")?.as_float().ok())
+                .unwrap_or(1000.0);
+            let mut ranges = vec![];
+            if let Some(Object::Array(items)) = descendant.and_then(|it| get(it, b"W")) {
+                let mut items = items.iter().map(&resolve).peekable();
+                while let Some(first) = items.next().and_then(|it| it.as_i64().ok()) {
+                    match items.next() {
+                        Some(Object::Array(widths)) => {
+                            for (code, width) in (first..).zip(widths) {
+                                ranges.push((code, code, resolve(width).as_float().unwrap_or(default)));
+                            }
+                        }
+                        Some(last) => {
+                            let (Ok(last), Some(Ok(width))) = (last.as_i64(), items.next().map(Object::as_float))
+                            else {
+                                break;
+                            };
+                            ranges.push((first, last, width));
+                        }
+                        None => break,
+                    }
+                }
+            }
+            return GlyphWidths::Composite { ranges, default };
+        }
+
+        GlyphWidths::Simple(simple_font_widths(font, resolve, 1000.0).0)
+    }
+
+    /// Width of the glyph of a character code given as big-endian bytes.
+    pub(crate) fn width(&self, code: &[u8]) -> f32 {
+        let code = code.iter().fold(0, |value, &byte| value * 256 + i64::from(byte));
+        match self {
+            GlyphWidths::Simple(widths) => u8::try_from(code).map_or(widths.missing_width, |code| widths.width(code)),
+            GlyphWidths::Composite { ranges, default } => ranges
+                .iter()
+                .find(|(first, last, _)| (*first..=*last).contains(&code))
+                .map_or(*default, |(_, _, width)| *width),
         }
-        Ok(buffer)
     }
 }
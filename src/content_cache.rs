@@ -0,0 +1,171 @@
+use crate::{Document, ObjectId};
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::Mutex;
+
+/// Decompressed page content kept between reads, see [`Document::enable_content_cache`].
+#[derive(Debug, Default)]
+pub(crate) struct ContentCache {
+    state: Mutex<Option<CacheState>>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheState {
+    max_bytes: usize,
+    used_bytes: usize,
+    /// Incremented on every lookup, to find the least recently used entry.
+    clock: u64,
+    entries: HashMap<ObjectId, CacheEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    /// The page and its content streams, with the generation they were last modified at.
+    sources: Vec<(ObjectId, u64)>,
+    content: Vec<u8>,
+    last_used: u64,
+}
+
+impl Clone for ContentCache {
+    fn clone(&self) -> Self {
+        ContentCache {
+            state: Mutex::new(self.state.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl ContentCache {
+    pub(crate) fn get(&self, page_id: ObjectId, sources: &[(ObjectId, u64)]) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let state = state.as_mut()?;
+        state.clock += 1;
+        let clock = state.clock;
+        let entry = state.entries.get_mut(&page_id)?;
+        if entry.sources != sources {
+            return None;
+        }
+        entry.last_used = clock;
+        Some(entry.content.clone())
+    }
+
+    pub(crate) fn insert(&self, page_id: ObjectId, sources: Vec<(ObjectId, u64)>, content: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        let Some(state) = state.as_mut() else {
+            return;
+        };
+        if let Some(stale) = state.entries.remove(&page_id) {
+            state.used_bytes -= stale.content.len();
+        }
+        if content.len() > state.max_bytes {
+            return;
+        }
+        while state.used_bytes + content.len() > state.max_bytes {
+            let Some(&oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| id)
+            else {
+                break;
+            };
+            if let Some(evicted) = state.entries.remove(&oldest) {
+                state.used_bytes -= evicted.content.len();
+            }
+        }
+        state.used_bytes += content.len();
+        let last_used = state.clock;
+        state.entries.insert(
+            page_id,
+            CacheEntry {
+                sources,
+                content: content.to_vec(),
+                last_used,
+            },
+        );
+    }
+
+    /// Approximate heap memory held by the cache, in bytes.
+    pub(crate) fn memory_usage(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.as_ref().map_or(0, |state| {
+            let entry_size = size_of::<(ObjectId, CacheEntry)>() + 1;
+            state.entries.capacity() * entry_size
+                + state
+                    .entries
+                    .values()
+                    .map(|entry| entry.content.capacity() + entry.sources.capacity() * size_of::<(ObjectId, u64)>())
+                    .sum::<usize>()
+        })
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.state.lock().unwrap().is_some()
+    }
+}
+
+impl Document {
+    /// Keep the decompressed content of pages read with [`Document::get_page_content`], up to
+    /// `max_bytes` in total, evicting the least recently used pages first.
+    ///
+    /// The content of a page is read again once the page or one of its content streams is modified
+    /// through the methods of `Document`. Edits made directly through the `objects` field aren't
+    /// noticed, so the cache should be disabled before making them.
+    pub fn enable_content_cache(&mut self, max_bytes: usize) {
+        *self.content_cache.state.lock().unwrap() = Some(CacheState {
+            max_bytes,
+            used_bytes: 0,
+            clock: 0,
+            entries: HashMap::new(),
+        });
+    }
+
+    /// Stop caching page content and drop the cached content.
+    pub fn disable_content_cache(&mut self) {
+        *self.content_cache.state.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::creator::tests::create_document_with_texts;
+    use crate::object::tests::DECOMPRESSIONS;
+    use std::cell::Cell;
+
+    #[test]
+    fn cached_content_is_decompressed_once() {
+        let mut doc = create_document_with_texts(&["Hello World", "Second page"]);
+        doc.compress();
+        let pages = doc.get_pages();
+        let count = || DECOMPRESSIONS.with(Cell::get);
+
+        doc.enable_content_cache(1 << 20);
+        let start = count();
+        let content = doc.get_page_content(pages[&1]).unwrap();
+        doc.extract_text(&[1]).unwrap();
+        assert_eq!(doc.get_page_content(pages[&1]).unwrap(), content);
+        assert_eq!(count() - start, 1);
+
+        // Changing the content stream invalidates the cached content.
+        doc.replace_text(1, "Hello World", "Goodbye").unwrap();
+        let start = count();
+        assert!(doc.extract_text(&[1]).unwrap().contains("Goodbye"));
+        doc.get_page_content(pages[&1]).unwrap();
+        assert_eq!(count() - start, 1);
+
+        // Only one page fits.
+        doc.enable_content_cache(content.len() + 1);
+        doc.get_page_content(pages[&1]).unwrap();
+        doc.get_page_content(pages[&2]).unwrap();
+        let start = count();
+        doc.get_page_content(pages[&2]).unwrap();
+        assert_eq!(count() - start, 0);
+        doc.get_page_content(pages[&1]).unwrap();
+        assert_eq!(count() - start, 1);
+
+        doc.disable_content_cache();
+        let start = count();
+        doc.get_page_content(pages[&2]).unwrap();
+        doc.get_page_content(pages[&2]).unwrap();
+        assert_eq!(count() - start, 2);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::Result;
-use crate::{Dictionary, Document, Object, ObjectId};
+use crate::{Dictionary, Document, Object, ObjectId, StringFormat};
 
 impl Document {
     /// Create new PDF document with version.
@@ -9,33 +9,66 @@ pub fn with_version<S: Into<String>>(version: S) -> Document {
         document
     }
 
+    /// Create a document with an empty page tree and the file identifier `file_id`, but without
+    /// document information dictionary, so it has nothing that changes from one run to the next.
+    ///
+    /// It can be saved as is with [`SaveOptions::canonical`](crate::SaveOptions::canonical).
+    pub fn minimal<S: Into<String>>(version: S, file_id: &[u8]) -> Document {
+        let mut document = Self::with_version(version);
+        let pages_id = document.add_object(dictionary! {
+            "Type" => "Pages",
+            "Kids" => Vec::<Object>::new(),
+            "Count" => 0,
+        });
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        let file_id = Object::String(file_id.to_vec(), StringFormat::Hexadecimal);
+        document.trailer.set("ID", vec![file_id.clone(), file_id]);
+        document
+    }
+
     /// Create an object ID.
     pub fn new_object_id(&mut self) -> ObjectId {
         self.max_id += 1;
-        (self.max_id, 0)
+        ObjectId(self.max_id, 0)
     }
 
     /// Add PDF object into document's object list.
     pub fn add_object<T: Into<Object>>(&mut self, object: T) -> ObjectId {
         self.max_id += 1;
-        let id = (self.max_id, 0);
+        let id = ObjectId(self.max_id, 0);
         self.objects.insert(id, object.into());
+        self.dirty.insert(id);
         id
     }
 
-    pub fn set_object<T: Into<Object>>(&mut self, id: ObjectId, object: T) {
+    pub fn set_object<T: Into<Object>>(&mut self, id: impl Into<ObjectId>, object: T) {
+        let id = id.into();
         self.objects.insert(id, object.into());
+        self.dirty.insert(id);
     }
 
     /// Remove PDF object from document's object list.
-    pub fn remove_object(&mut self, object_id: &ObjectId) -> Result<()> {
+    pub fn remove_object(&mut self, object_id: impl Into<ObjectId>) -> Result<()> {
+        let object_id = object_id.into();
         for (_, page_id) in self.get_pages() {
-            let page = self.get_object_mut(page_id)?.as_dict_mut()?;
-            let annots = page.get_mut(b"Annots")?.as_array_mut()?;
+            // The annotations array of the page may be an indirect object.
+            let annots_id = match self.get_dictionary(page_id)?.get_maybe_array(b"Annots") {
+                Ok(annots) => annots.id().unwrap_or(page_id),
+                Err(_) => continue,
+            };
+            let annots = match self.get_object_mut(annots_id)? {
+                Object::Array(annots) => annots,
+                Object::Dictionary(page) => page.get_mut(b"Annots")?.as_array_mut()?,
+                _ => continue,
+            };
 
             annots.retain(|object| {
                 if let Ok(id) = object.as_reference() {
-                    return id != *object_id;
+                    return id != object_id;
                 }
 
                 true
@@ -48,7 +81,8 @@ pub fn remove_object(&mut self, object_id: &ObjectId) -> Result<()> {
     /// Get the page's resource dictionary.
     ///
     /// Get Object that has the key "Resources".
-    pub fn get_or_create_resources(&mut self, page_id: ObjectId) -> Result<&mut Object> {
+    pub fn get_or_create_resources(&mut self, page_id: impl Into<ObjectId>) -> Result<&mut Object> {
+        let page_id = page_id.into();
         let resources_id = {
             let page = self.get_object(page_id).and_then(Object::as_dict)?;
             if page.has(b"Resources") {
@@ -71,8 +105,10 @@ pub fn get_or_create_resources(&mut self, page_id: ObjectId) -> Result<&mut Obje
     ///
     /// Get Object that has the key `Resources -> XObject`.
     pub fn add_xobject<N: Into<Vec<u8>>>(
-        &mut self, page_id: ObjectId, xobject_name: N, xobject_id: ObjectId,
+        &mut self, page_id: impl Into<ObjectId>, xobject_name: N, xobject_id: impl Into<ObjectId>,
     ) -> Result<()> {
+        let page_id = page_id.into();
+        let xobject_id = xobject_id.into();
         if let Ok(resources) = self.get_or_create_resources(page_id).and_then(Object::as_dict_mut) {
             if !resources.has(b"XObject") {
                 resources.set("XObject", Dictionary::new());
@@ -95,8 +131,10 @@ pub fn add_xobject<N: Into<Vec<u8>>>(
     ///
     /// Get Object that has the key `Resources -> ExtGState`.
     pub fn add_graphics_state<N: Into<Vec<u8>>>(
-        &mut self, page_id: ObjectId, gs_name: N, gs_id: ObjectId,
+        &mut self, page_id: impl Into<ObjectId>, gs_name: N, gs_id: impl Into<ObjectId>,
     ) -> Result<()> {
+        let page_id = page_id.into();
+        let gs_id = gs_id.into();
         if let Ok(resources) = self.get_or_create_resources(page_id).and_then(Object::as_dict_mut) {
             if !resources.has(b"ExtGState") {
                 resources.set("ExtGState", Dictionary::new());
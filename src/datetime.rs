@@ -34,6 +34,7 @@ fn from(date: DateTime<Utc>) -> Self {
 }
 
 impl From<Time> for Object {
+    #[allow(deprecated)]
     fn from(date: Time) -> Self {
         // can only fail if the TIME_FMT_ENCODE_STR would be invalid
         Object::string_literal(
@@ -47,6 +48,7 @@ fn from(date: Time) -> Self {
 }
 
 impl From<OffsetDateTime> for Object {
+    #[allow(deprecated)]
     fn from(date: OffsetDateTime) -> Self {
         Object::string_literal({
             // D:%Y%m%d%H%M%S:%z'
@@ -97,6 +99,8 @@ pub fn as_datetime(&self) -> Option<DateTime<Local>> {
     /// however, be calculated manually
     #[cfg(not(feature = "chrono_time"))]
     pub fn as_datetime(&self) -> Option<OffsetDateTime> {
+        // Its replacement `parse_borrowed` needs a version of time newer than the minimum Rust version allows.
+        #[allow(deprecated)]
         let format = time::format_description::parse(
             "[year][month][day][hour][minute][second][offset_hour sign:mandatory][offset_minute]",
         )
@@ -1,4 +1,4 @@
-use super::{Dictionary, Document, Object, Result};
+use super::{Dictionary, Document, NameTree, Object, Result, TreeKey};
 use indexmap::IndexMap;
 #[derive(Debug, Clone)]
 pub struct Destination(Dictionary);
@@ -30,43 +30,23 @@ pub fn page(&self) -> Result<&Object> {
 }
 
 impl Document {
+    /// Add the destinations of the name tree `tree`, e.g. the `/Dests` of the `/Names` dictionary,
+    /// to `named_destinations`. Values which aren't destinations are skipped.
     pub fn get_named_destinations(
         &self, tree: &Dictionary, named_destinations: &mut IndexMap<Vec<u8>, Destination>,
     ) -> Result<()> {
-        if let Ok(kids) = tree.get(b"Kids") {
-            for kid in kids.as_array()? {
-                if let Ok(kid) = kid.as_reference().and_then(move |id| self.get_dictionary(id)) {
-                    self.get_named_destinations(kid, named_destinations)?;
-                }
-            }
-        }
-        if let Ok(names) = tree.get(b"Names") {
-            let mut names = names.as_array()?.iter();
-            loop {
-                let key = names.next();
-                if key.is_none() {
-                    break;
-                }
-                let val = names.next();
-                if val.is_none() {
-                    break;
-                }
-                if let Ok(obj_ref) = val.unwrap().as_reference() {
-                    if let Ok(dict) = self.get_dictionary(obj_ref) {
-                        let val = dict.get(b"D").as_ref().unwrap().as_array()?;
-                        let dest = Destination::new(key.unwrap().clone(), val[0].clone(), val[1].clone());
-                        named_destinations.insert(key.unwrap().as_str().unwrap().to_vec(), dest);
-                    } else if let Ok(Object::Array(val)) = self.get_object(obj_ref) {
-                        let dest = Destination::new(key.unwrap().clone(), val[0].clone(), val[1].clone());
-                        named_destinations.insert(key.unwrap().as_str().unwrap().to_vec(), dest);
-                    }
-                } else if let Ok(dict) = val.unwrap().as_dict() {
-                    let val = dict.get(b"D").as_ref().unwrap().as_array()?;
-                    let dest = Destination::new(key.unwrap().clone(), val[0].clone(), val[1].clone());
-                    named_destinations.insert(key.unwrap().as_str().unwrap().to_vec(), dest);
-                } else {
-                    // TODO: Log error: Unpexpected node type
-                }
+        for (key, value) in NameTree::from_dict(self, tree).iter() {
+            let TreeKey::Name(name) = key else {
+                continue;
+            };
+            let value = self.dereference(value)?.1;
+            let destination = match value {
+                Object::Dictionary(dict) => dict.get_deref(b"D", self).and_then(Object::as_array),
+                value => value.as_array(),
+            };
+            if let Ok([page, typ, ..]) = destination.map(Vec::as_slice) {
+                let dest = Destination::new(Object::string_literal(name.clone()), page.clone(), typ.clone());
+                named_destinations.insert(name, dest);
             }
         }
         Ok(())
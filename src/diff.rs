@@ -0,0 +1,372 @@
+#![cfg(feature = "nom_parser")]
+//! Compare the text of two documents, ignoring how their objects are laid out.
+use std::collections::HashMap;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Document, PageExtractionOptions, Result, TextRun};
+
+/// How [`text_diff`] pairs the pages of the two documents.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PageAlignment {
+    /// Compare the pages with the same page number.
+    #[default]
+    Index,
+    /// When the page counts differ, pair the pages sharing the most words, keeping their order, so
+    /// an inserted or removed page doesn't show as a change of all the following ones. Pages with
+    /// the same count are compared by index.
+    BestMatch,
+}
+
+/// Options of [`text_diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextDiffOptions {
+    pub alignment: PageAlignment,
+    /// Locate the changed words on their page, from the positioned text runs of the page.
+    pub positions: bool,
+}
+
+/// Whether a word is only in the first or only in the second document.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Inserted,
+    Deleted,
+}
+
+/// A word added or removed, see [`text_diff`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordChange {
+    pub kind: ChangeKind,
+    pub word: String,
+    /// Index of the word among the words of its page, in the second document for an inserted word
+    /// and in the first one for a deleted word.
+    pub word_index: usize,
+    /// Rectangle `[llx lly urx ury]` in user space of the page showing the word, when
+    /// [`TextDiffOptions::positions`] is set and a text run shows the word.
+    pub position: Option<[f32; 4]>,
+}
+
+/// The changed words of a pair of pages. A page without counterpart has all its words changed.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageDiff {
+    /// Page number in the first document.
+    pub old_page: Option<u32>,
+    /// Page number in the second document.
+    pub new_page: Option<u32>,
+    /// The changes in reading order.
+    pub changes: Vec<WordChange>,
+}
+
+/// The text differences between two documents, as given by [`text_diff`].
+///
+/// Its `Display` summarizes it, e.g. `2 words added, 1 removed across 1 page`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextDiff {
+    /// The pages with changes.
+    pub pages: Vec<PageDiff>,
+}
+
+impl TextDiff {
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Number of words only in the second document.
+    pub fn added(&self) -> usize {
+        self.count(ChangeKind::Inserted)
+    }
+
+    /// Number of words only in the first document.
+    pub fn removed(&self) -> usize {
+        self.count(ChangeKind::Deleted)
+    }
+
+    fn count(&self, kind: ChangeKind) -> usize {
+        self.pages
+            .iter()
+            .flat_map(|page| &page.changes)
+            .filter(|change| change.kind == kind)
+            .count()
+    }
+}
+
+impl fmt::Display for TextDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (added, pages) = (self.added(), self.pages.len());
+        write!(
+            f,
+            "{added} word{} added, {} removed across {pages} page{}",
+            if added == 1 { "" } else { "s" },
+            self.removed(),
+            if pages == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Compare the text extracted from the pages of two documents word by word.
+///
+/// The pages are paired as [`TextDiffOptions::alignment`] says, and the words of each pair, split at
+/// white space, are compared with a longest common subsequence. Only the pages with changes are
+/// reported, so documents showing the same words give an empty diff whatever their structure.
+pub fn text_diff(a: &Document, b: &Document, options: &TextDiffOptions) -> Result<TextDiff> {
+    let old_pages = page_words(a, options.positions)?;
+    let new_pages = page_words(b, options.positions)?;
+    let pairs = if options.alignment == PageAlignment::BestMatch && old_pages.len() != new_pages.len() {
+        best_match_pages(&old_pages, &new_pages)
+    } else {
+        (0..old_pages.len().max(new_pages.len()))
+            .map(|index| {
+                (
+                    Some(index).filter(|&it| it < old_pages.len()),
+                    Some(index).filter(|&it| it < new_pages.len()),
+                )
+            })
+            .collect()
+    };
+
+    let mut diff = TextDiff::default();
+    for (old_index, new_index) in pairs {
+        let old = old_index.map(|index| &old_pages[index]);
+        let new = new_index.map(|index| &new_pages[index]);
+        let changes = diff_words(old.map_or(&[], |page| &page.words), new.map_or(&[], |page| &page.words));
+        if changes.is_empty() {
+            continue;
+        }
+        let changes = changes
+            .into_iter()
+            .map(|(kind, word_index)| {
+                let page = match kind {
+                    ChangeKind::Inserted => new,
+                    ChangeKind::Deleted => old,
+                }
+                .expect("a change has a page");
+                WordChange {
+                    kind,
+                    word: page.words[word_index].clone(),
+                    word_index,
+                    position: page.positions.get(word_index).copied().flatten(),
+                }
+            })
+            .collect();
+        diff.pages.push(PageDiff {
+            old_page: old.map(|page| page.number),
+            new_page: new.map(|page| page.number),
+            changes,
+        });
+    }
+    Ok(diff)
+}
+
+struct PageWords {
+    number: u32,
+    words: Vec<String>,
+    /// Position of each word, empty unless positions were requested.
+    positions: Vec<Option<[f32; 4]>>,
+}
+
+fn page_words(doc: &Document, positions: bool) -> Result<Vec<PageWords>> {
+    let options = PageExtractionOptions {
+        text_runs: positions,
+        ..Default::default()
+    };
+    doc.get_pages()
+        .into_keys()
+        .map(|number| {
+            let page = doc.extract_page(number, &options)?;
+            let words: Vec<String> = page.text.split_whitespace().map(str::to_string).collect();
+            let positions = if positions {
+                locate_words(&words, &page.text_runs)
+            } else {
+                vec![]
+            };
+            Ok(PageWords {
+                number,
+                words,
+                positions,
+            })
+        })
+        .collect()
+}
+
+/// Find the words of the text in the text runs, in order. A word which doesn't match one of the next
+/// few words of the runs, e.g. because it spans several runs, has no position.
+fn locate_words(words: &[String], runs: &[TextRun]) -> Vec<Option<[f32; 4]>> {
+    const LOOKAHEAD: usize = 8;
+    let mut run_words = vec![];
+    for run in runs {
+        let chars: Vec<char> = run.text.chars().collect();
+        let mut start = None;
+        for index in 0..=chars.len() {
+            match (start, chars.get(index).is_some_and(|ch| !ch.is_whitespace())) {
+                (None, true) => start = Some(index),
+                (Some(first), false) => {
+                    let word: String = chars[first..index].iter().collect();
+                    run_words.push((word, run.bounds(first..index)));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut next = 0;
+    words
+        .iter()
+        .map(|word| {
+            let found = run_words[next.min(run_words.len())..]
+                .iter()
+                .take(LOOKAHEAD)
+                .position(|(run_word, _)| run_word == word)?;
+            next += found + 1;
+            Some(run_words[next - 1].1)
+        })
+        .collect()
+}
+
+/// The words only in `old` or only in `new`, by index, outside a longest common subsequence.
+fn diff_words(old: &[String], new: &[String]) -> Vec<(ChangeKind, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // lengths[i][j] is the length of the longest common subsequence of old_middle[i..] and new_middle[j..].
+    let width = new_middle.len() + 1;
+    let mut lengths = vec![0u32; (old_middle.len() + 1) * width];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lengths[i * width + j] = if old_middle[i] == new_middle[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            i += 1;
+            j += 1;
+        } else if i < old_middle.len()
+            && (j == new_middle.len() || lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+        {
+            changes.push((ChangeKind::Deleted, prefix + i));
+            i += 1;
+        } else {
+            changes.push((ChangeKind::Inserted, prefix + j));
+            j += 1;
+        }
+    }
+    changes
+}
+
+/// Pair the pages of two documents in order, maximizing the number of words shared by the pairs.
+fn best_match_pages(old: &[PageWords], new: &[PageWords]) -> Vec<(Option<usize>, Option<usize>)> {
+    fn counts(page: &PageWords) -> HashMap<&str, usize> {
+        let mut counts = HashMap::new();
+        for word in &page.words {
+            *counts.entry(word.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
+    let old_counts: Vec<_> = old.iter().map(counts).collect();
+    let new_counts: Vec<_> = new.iter().map(counts).collect();
+    let shared = |i: usize, j: usize| {
+        old_counts[i]
+            .iter()
+            .map(|(word, count)| new_counts[j].get(word).map_or(0, |other| *count.min(other)))
+            .sum::<usize>()
+    };
+
+    // scores[i][j] is the best total of old[i..] and new[j..].
+    let width = new.len() + 1;
+    let mut scores = vec![0usize; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            let paired = shared(i, j) + scores[(i + 1) * width + j + 1];
+            scores[i * width + j] = paired.max(scores[(i + 1) * width + j]).max(scores[i * width + j + 1]);
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i == old.len() {
+            pairs.push((None, Some(j)));
+            j += 1;
+        } else if j == new.len() || scores[i * width + j] == scores[(i + 1) * width + j] {
+            pairs.push((Some(i), None));
+            i += 1;
+        } else if scores[i * width + j] == scores[i * width + j + 1] {
+            pairs.push((None, Some(j)));
+            j += 1;
+        } else {
+            pairs.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+
+    #[test]
+    fn diff_of_one_changed_word() {
+        let old = create_document_with_texts(&["first page", "the quick fox", "last page"]);
+        let new = create_document_with_texts(&["first page", "the slow fox", "last page"]);
+        let options = TextDiffOptions {
+            positions: true,
+            ..Default::default()
+        };
+        assert!(text_diff(&old, &old, &options).unwrap().is_empty());
+
+        let diff = text_diff(&old, &new, &options).unwrap();
+        assert_eq!(diff.pages.len(), 1);
+        let page = &diff.pages[0];
+        assert_eq!((page.old_page, page.new_page), (Some(2), Some(2)));
+        let changes: Vec<_> = page
+            .changes
+            .iter()
+            .map(|it| (it.kind, it.word.as_str(), it.word_index))
+            .collect();
+        assert_eq!(
+            changes,
+            [(ChangeKind::Deleted, "quick", 1), (ChangeKind::Inserted, "slow", 1)]
+        );
+        // The words are shown at (100, 600) in 48 point Courier, 28.8 units per glyph.
+        let [llx, _, urx, _] = page.changes[0].position.unwrap();
+        assert!((llx - (100.0 + 4.0 * 28.8)).abs() < 0.1 && (urx - llx - 5.0 * 28.8).abs() < 0.1);
+        assert_eq!(diff.to_string(), "1 word added, 1 removed across 1 page");
+
+        // A removed page shifts the following ones, unless pages are aligned by content.
+        let shorter = create_document_with_texts(&["first page", "last page"]);
+        let diff = text_diff(&old, &shorter, &options).unwrap();
+        assert_eq!(diff.to_string(), "2 words added, 5 removed across 2 pages");
+        let options = TextDiffOptions {
+            alignment: PageAlignment::BestMatch,
+            ..options
+        };
+        let diff = text_diff(&old, &shorter, &options).unwrap();
+        assert_eq!(diff.pages.len(), 1);
+        assert_eq!((diff.pages[0].old_page, diff.pages[0].new_page), (Some(2), None));
+        assert_eq!(diff.to_string(), "0 words added, 3 removed across 1 page");
+    }
+}
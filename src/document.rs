@@ -1,13 +1,21 @@
-use super::encodings::Encoding;
-use super::{Bookmark, Dictionary, Object, ObjectId};
+use super::encodings::{CodedCharacterSet, Encoding};
+use super::{Bookmark, BookmarkIssue, BookmarkTarget, Dictionary, Object, ObjectId};
+use crate::cancel::check_cancel;
+use crate::content_cache::ContentCache;
+#[cfg(feature = "encryption")]
 use crate::encryption;
+#[cfg(feature = "encryption")]
+use crate::logging::span;
+use crate::object_stream::CompressedObjects;
 use crate::xobject::PdfImage;
 use crate::xref::{Xref, XrefType};
-use crate::{Error, Result, Stream};
-use log::debug;
+use crate::{CancelToken, Error, MaybeRef, Result, Stream, TextStringEncoding};
+use log::{debug, warn};
 use std::cmp::max;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Write;
+use std::mem::size_of;
+use std::ops::Range;
 use std::str;
 
 /// A PDF document.
@@ -46,6 +54,122 @@ pub struct Document {
     /// It is used to support incremental updates in PDFs.
     /// Default value is `0`.
     pub xref_start: usize,
+
+    /// Damage that was worked around while reading the document.
+    /// This value is only set during reading.
+    pub load_report: LoadReport,
+
+    /// Objects modified since the document was loaded.
+    pub(crate) dirty: DirtyObjects,
+
+    /// Single-byte font encodings registered by name, see [`Document::register_encoding`].
+    pub(crate) custom_encodings: HashMap<Vec<u8>, CodedCharacterSet>,
+
+    /// Objects resolved from object streams on demand, see [`Document::get_object`].
+    pub(crate) compressed_objects: CompressedObjects,
+
+    /// Decompressed page content, see [`Document::enable_content_cache`].
+    pub(crate) content_cache: ContentCache,
+
+    /// How the text strings lopdf creates are encoded, see [`Document::set_text_string_encoding`].
+    pub(crate) text_string_encoding: TextStringEncoding,
+
+    /// Whether changes are checked against the certification, see [`Document::set_respect_certification`].
+    pub(crate) respect_certification: bool,
+
+    /// Where the structures of the file were, see [`Document::file_spans`].
+    pub(crate) file_spans: FileSpans,
+}
+
+/// Objects handed out mutably, added or removed, see [`Document::dirty_objects`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DirtyObjects {
+    generation: u64,
+    ids: HashSet<ObjectId>,
+    /// The generation each object was last recorded at, which is kept by [`Document::clear_dirty`].
+    modified: HashMap<ObjectId, u64>,
+}
+
+impl DirtyObjects {
+    pub(crate) fn insert(&mut self, id: ObjectId) {
+        self.generation += 1;
+        self.ids.insert(id);
+        self.modified.insert(id, self.generation);
+    }
+
+    /// The generation an object was last recorded at, 0 if it never was.
+    pub(crate) fn modified_at(&self, id: ObjectId) -> u64 {
+        self.modified.get(&id).copied().unwrap_or(0)
+    }
+
+    /// Approximate heap memory held by the sets, in bytes.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.ids.capacity() * (size_of::<ObjectId>() + 1)
+            + self.modified.capacity() * (size_of::<(ObjectId, u64)>() + 1)
+    }
+}
+
+/// The byte ranges of the structures of the file a document was loaded from, recorded with
+/// [`ParseOptions::record_spans`](crate::ParseOptions::record_spans).
+///
+/// The ranges are offsets in the buffer given to the reader, including any bytes before the `%PDF-` header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileSpans {
+    /// The indirect objects, from their `N G obj` header through their `endobj`, including object
+    /// streams and cross-reference streams. An object stored in several revisions has the range of
+    /// the revision it was read from.
+    pub objects: BTreeMap<ObjectId, Range<usize>>,
+    /// The objects stored in object streams, with the object stream and their index in it as given by
+    /// their cross-reference entry.
+    pub compressed: BTreeMap<ObjectId, (ObjectId, u16)>,
+    /// The cross-reference sections, newest first: tables from `xref` through their last entry,
+    /// and cross-reference streams, also found in `objects`.
+    pub xref_sections: Vec<Range<usize>>,
+    /// The `trailer` dictionaries following the cross-reference tables, newest first, from the
+    /// `trailer` keyword through the end of the dictionary.
+    pub trailers: Vec<Range<usize>>,
+}
+
+/// Recovery performed while loading a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Number of incremental updates at the end of the file that were discarded
+    /// because they were cut off, lacking their cross-reference section or `%%EOF` marker.
+    pub discarded_revisions: usize,
+    /// Number of bytes at the end of the file that were ignored with the discarded updates.
+    pub ignored_bytes: usize,
+    /// Number of cross-reference sections read, following the `/Prev` chain from the last one.
+    pub xref_sections: usize,
+    /// Pages listed more than once in the page tree, which are only numbered at their first occurrence.
+    pub duplicate_pages: Vec<ObjectId>,
+    /// The cross-reference sections couldn't be read, the objects were located by scanning the file.
+    pub reconstructed_xref: bool,
+    /// Byte ranges of the revisions of a file whose cross-reference table was reconstructed, oldest
+    /// first, found at its `%%EOF` markers. Objects of later revisions replace those of earlier ones.
+    pub recovered_revisions: Vec<Range<usize>>,
+    /// Objects listed in the cross-reference sections which couldn't be read.
+    pub unreadable_objects: Vec<ObjectId>,
+    /// Keys given more than once in a dictionary, with the object holding it. Which value was kept
+    /// depends on [`ParseOptions::duplicate_keys`](crate::ParseOptions::duplicate_keys).
+    pub duplicate_keys: Vec<(ObjectId, Vec<u8>)>,
+    /// Byte ranges of garbage between the end of an object and its `endobj`, which were skipped.
+    pub skipped_bytes: Vec<(ObjectId, Range<usize>)>,
+    /// Page tree nodes whose `/Rotate` wasn't a multiple of 90, with the rotation it was rounded to,
+    /// see [`Rotation::from_degrees`](crate::Rotation::from_degrees).
+    pub invalid_rotations: Vec<(ObjectId, crate::Rotation)>,
+    /// Streams whose data extends after the end of the file or lies within the data of another
+    /// stream, as when a cross-reference entry points into the middle of an object.
+    pub invalid_stream_extents: Vec<ObjectId>,
+    /// Streams whose `/Length` was a Real or a string and was coerced to an integer, see
+    /// [`ParseOptions::lenient`](crate::ParseOptions::lenient).
+    pub coerced_lengths: Vec<ObjectId>,
+    /// The object stream holding the catalog, which couldn't be decrypted or decoded, and why.
+    /// Loading fails with [`Error::MissingCatalog`](crate::Error::MissingCatalog) instead,
+    /// unless [`ParseOptions::lenient`](crate::ParseOptions::lenient) is set.
+    pub unreadable_catalog: Option<(ObjectId, String)>,
+    /// Object streams whose `/Extends` chain is cyclic or too long to follow. Their objects were
+    /// still read, but an object given more than once may not be taken from the nearest stream.
+    pub invalid_object_stream_chains: Vec<ObjectId>,
 }
 
 impl Document {
@@ -61,6 +185,14 @@ pub fn new() -> Self {
             bookmarks: Vec::new(),
             bookmark_table: HashMap::new(),
             xref_start: 0,
+            load_report: LoadReport::default(),
+            dirty: DirtyObjects::default(),
+            custom_encodings: HashMap::new(),
+            text_string_encoding: TextStringEncoding::default(),
+            compressed_objects: CompressedObjects::default(),
+            content_cache: ContentCache::default(),
+            respect_certification: false,
+            file_spans: FileSpans::default(),
         }
     }
 
@@ -78,47 +210,95 @@ pub fn new_from_prev(prev: &Document) -> Self {
             bookmarks: Vec::new(),
             bookmark_table: HashMap::new(),
             xref_start: 0,
+            load_report: LoadReport::default(),
+            dirty: DirtyObjects::default(),
+            custom_encodings: prev.custom_encodings.clone(),
+            text_string_encoding: prev.text_string_encoding,
+            compressed_objects: CompressedObjects::default(),
+            content_cache: ContentCache::default(),
+            respect_certification: prev.respect_certification,
+            file_spans: FileSpans::default(),
         }
     }
 
-    const DEREF_LIMIT: usize = 128;
+    /// IDs of the objects that may have been modified since the document was loaded
+    /// or [`Document::clear_dirty`] was called.
+    ///
+    /// Objects are recorded when they are obtained mutably, added or removed through the methods of
+    /// `Document`, even if they end up unchanged. Edits made directly through the `objects` field are
+    /// not recorded.
+    pub fn dirty_objects(&self) -> &HashSet<ObjectId> {
+        &self.dirty.ids
+    }
 
-    fn recursive_fix_pages(&mut self, bookmarks: &[u32], first: bool) -> ObjectId {
-        if !bookmarks.is_empty() {
-            for id in bookmarks {
-                let (children, mut page) = match self.bookmark_table.get(id) {
-                    Some(n) => (n.children.clone(), n.page),
-                    None => return (0, 0),
-                };
+    /// The byte ranges of the objects in the file the document was loaded from, see [`FileSpans::objects`].
+    /// Empty unless the document was loaded with [`ParseOptions::record_spans`](crate::ParseOptions::record_spans).
+    pub fn object_spans(&self) -> &BTreeMap<ObjectId, Range<usize>> {
+        &self.file_spans.objects
+    }
 
-                if 0 == page.0 && !children.is_empty() {
-                    let objectid = self.recursive_fix_pages(&children[..], false);
+    /// The byte ranges of the structures of the file the document was loaded from, which are only
+    /// recorded with [`ParseOptions::record_spans`](crate::ParseOptions::record_spans).
+    pub fn file_spans(&self) -> &FileSpans {
+        &self.file_spans
+    }
 
-                    let bookmark = self.bookmark_table.get_mut(id).unwrap();
-                    bookmark.page = objectid;
-                    page = objectid;
-                }
+    /// Counter that is incremented whenever an object is recorded as modified.
+    pub fn generation(&self) -> u64 {
+        self.dirty.generation
+    }
 
-                if !first && 0 != page.0 {
-                    return page;
-                }
+    /// Forget the recorded modifications, e.g. after saving the document.
+    pub fn clear_dirty(&mut self) {
+        self.dirty.ids.clear();
+    }
 
-                if first && !children.is_empty() {
-                    self.recursive_fix_pages(&children[..], first);
-                }
-            }
-        }
+    /// Register a single-byte encoding for fonts whose `/Encoding` is the given name.
+    ///
+    /// The table is used both to decode extracted text and to encode replacement text.
+    /// A registered name takes precedence over the standard encodings, and variants of those
+    /// can be built by patching a few slots of [`WIN_ANSI_ENCODING`](crate::WIN_ANSI_ENCODING) and the like.
+    pub fn register_encoding<N: Into<Vec<u8>>>(&mut self, name: N, table: CodedCharacterSet) {
+        self.custom_encodings.insert(name.into(), table);
+    }
 
-        (0, 0)
+    /// Get the encoding registered under the given name.
+    pub fn registered_encoding(&self, name: &[u8]) -> Option<&CodedCharacterSet> {
+        self.custom_encodings.get(name)
     }
 
-    /// Adjusts the Parents that have a ObjectId of (0,_) to that
-    /// of their first child. will recurse through all entries
-    /// till all parents of children are set. This should be
-    /// ran before building the final bookmark objects but after
-    /// renumbering of objects.
-    pub fn adjust_zero_pages(&mut self) {
-        self.recursive_fix_pages(&self.bookmarks.clone(), true);
+    pub(crate) const DEREF_LIMIT: usize = 128;
+
+    /// Replace the pages of `ObjectId(0, 0)` of bookmarks, see [`BookmarkTarget::Page`], with the
+    /// page of their first descendant which goes to a page: the first child whose page is set or
+    /// could be replaced, in the order of the children. Bookmarks whose descendants go to no page,
+    /// or only to URIs and named destinations, keep the zero page. This should be run before
+    /// building the outline with [`Document::build_outline`], but after renumbering objects.
+    ///
+    /// Bookmarks listed more than once, as in a cycle, missing from the bookmark table or nested
+    /// too deep are skipped, and returned as the issues of the bookmark tree.
+    pub fn adjust_zero_pages(&mut self) -> Vec<BookmarkIssue> {
+        let tree = self.bookmark_tree();
+        // Children come after their parents, so they are fixed first going backwards.
+        let mut pages: HashMap<u32, ObjectId> = HashMap::new();
+        for &id in tree.order.iter().rev() {
+            let Some(bookmark) = self.bookmark_table.get_mut(&id) else {
+                continue;
+            };
+            let BookmarkTarget::Page(page, _) = &mut bookmark.target else {
+                continue;
+            };
+            if page.0 == 0 {
+                let children = tree.children.get(&id).into_iter().flatten();
+                if let Some(first) = children.filter_map(|child| pages.get(child)).next() {
+                    *page = *first;
+                }
+            }
+            if page.0 != 0 {
+                pages.insert(id, *page);
+            }
+        }
+        tree.issues
     }
 
     /// Follow references if the supplied object is a reference.
@@ -133,7 +313,7 @@ pub fn dereference<'a>(&'a self, mut object: &'a Object) -> Result<(Option<Objec
 
         while let Ok(ref_id) = object.as_reference() {
             id = Some(ref_id);
-            object = self.objects.get(&ref_id).ok_or(Error::ObjectNotFound(ref_id))?;
+            object = self.stored_object(ref_id)?;
 
             nb_deref += 1;
             if nb_deref > Self::DEREF_LIMIT {
@@ -145,31 +325,67 @@ pub fn dereference<'a>(&'a self, mut object: &'a Object) -> Result<(Option<Objec
     }
 
     /// Get object by object id, will iteratively dereference a referenced object.
-    pub fn get_object(&self, id: ObjectId) -> Result<&Object> {
-        let object = self.objects.get(&id).ok_or(Error::ObjectNotFound(id))?;
+    ///
+    /// An object that isn't loaded but has a compressed cross-reference entry, e.g. after it was dropped
+    /// by the filter of [`Document::load_filtered`], is parsed out of its object stream on the first lookup,
+    /// without the other objects of the stream. Edits of the object stream afterwards aren't seen.
+    pub fn get_object(&self, id: impl Into<ObjectId>) -> Result<&Object> {
+        let id = id.into();
+        let object = self.stored_object(id)?;
         self.dereference(object).map(|(_, object)| object)
     }
 
+    /// The object `id` itself, from `objects` or resolved from its object stream.
+    fn stored_object(&self, id: ObjectId) -> Result<&Object> {
+        match self.objects.get(&id) {
+            Some(object) => Ok(object),
+            None => self.compressed_objects.get(self, id),
+        }
+    }
+
+    /// Get an object that is only known from a compressed cross-reference entry, like
+    /// [`Document::get_object`] does, and keep it in `objects`.
+    pub fn load_compressed_object(&mut self, id: impl Into<ObjectId>) -> Result<&Object> {
+        let id = id.into();
+        if !self.objects.contains_key(&id) {
+            self.compressed_objects.get(self, id)?;
+            let object = self.compressed_objects.take(id).ok_or(Error::ObjectNotFound(id))?;
+            self.objects.insert(id, object);
+        }
+        self.get_object(id)
+    }
+
     /// Determines if an object exists in the current document (or incremental update.)
     /// with the given `ObjectId`.
     /// `true` if the object exists, `false` if it does not exist.
-    pub fn has_object(&self, id: ObjectId) -> bool {
+    pub fn has_object(&self, id: impl Into<ObjectId>) -> bool {
+        let id = id.into();
         self.objects.contains_key(&id)
     }
 
     /// Get mutable reference to object by object ID, will iteratively dereference a referenced object.
-    pub fn get_object_mut(&mut self, id: ObjectId) -> Result<&mut Object> {
-        let object = self.objects.get(&id).ok_or(Error::ObjectNotFound(id))?;
+    pub fn get_object_mut(&mut self, id: impl Into<ObjectId>) -> Result<&mut Object> {
+        let id = id.into();
+        let object = self.stored_object(id)?;
         let (ref_id, _obj) = self.dereference(object)?;
 
-        Ok(self.objects.get_mut(&ref_id.unwrap_or(id)).unwrap())
+        let id = ref_id.unwrap_or(id);
+        // An object resolved from its object stream is kept from now on.
+        if !self.objects.contains_key(&id) {
+            self.load_compressed_object(id)?;
+        }
+        self.dirty.insert(id);
+        Ok(self.objects.get_mut(&id).unwrap())
     }
 
     /// Get the object ID of the page that contains `id`.
-    pub fn get_object_page(&self, id: ObjectId) -> Result<ObjectId> {
+    pub fn get_object_page(&self, id: impl Into<ObjectId>) -> Result<ObjectId> {
+        let id = id.into();
         for (_, object_id) in self.get_pages() {
             let page = self.get_object(object_id)?.as_dict()?;
-            let annots = page.get(b"Annots")?.as_array()?;
+            let Ok(annots) = page.get_maybe_array(b"Annots").and_then(|it| it.resolve(self)) else {
+                continue;
+            };
             let mut objects_ids = annots.iter().map(Object::as_reference);
 
             let contains = objects_ids.any(|object_id| Some(id) == object_id.ok());
@@ -182,29 +398,32 @@ pub fn get_object_page(&self, id: ObjectId) -> Result<ObjectId> {
     }
 
     /// Get dictionary object by id.
-    pub fn get_dictionary(&self, id: ObjectId) -> Result<&Dictionary> {
+    pub fn get_dictionary(&self, id: impl Into<ObjectId>) -> Result<&Dictionary> {
+        let id = id.into();
         self.get_object(id).and_then(Object::as_dict)
     }
 
     /// Get a mutable dictionary object by id.
-    pub fn get_dictionary_mut(&mut self, id: ObjectId) -> Result<&mut Dictionary> {
+    pub fn get_dictionary_mut(&mut self, id: impl Into<ObjectId>) -> Result<&mut Dictionary> {
+        let id = id.into();
         self.get_object_mut(id).and_then(Object::as_dict_mut)
     }
 
     /// Get dictionary in dictionary by key.
     pub fn get_dict_in_dict<'a>(&'a self, node: &'a Dictionary, key: &[u8]) -> Result<&'a Dictionary> {
-        match node.get(key)? {
-            Object::Reference(object_id) => self.get_dictionary(*object_id),
-            Object::Dictionary(dic) => Ok(dic),
-            obj => Err(Error::ObjectType {
-                expected: "Dictionary",
-                found: obj.enum_variant(),
-            }),
-        }
+        node.get_maybe_dict(key)?.resolve(self)
     }
 
     /// Traverse objects from trailer recursively, return all referenced object IDs.
     pub fn traverse_objects<A: Fn(&mut Object)>(&mut self, action: A) -> Vec<ObjectId> {
+        // Traversing can only fail when cancelled.
+        self.traverse_objects_with_cancel(action, None).unwrap_or_default()
+    }
+
+    /// Traverse objects like [`Document::traverse_objects`], checking `cancel` before each object.
+    pub(crate) fn traverse_objects_with_cancel<A: Fn(&mut Object)>(
+        &mut self, action: A, cancel: Option<&CancelToken>,
+    ) -> Result<Vec<ObjectId>> {
         fn traverse_array<A: Fn(&mut Object)>(array: &mut [Object], action: &A, refs: &mut Vec<ObjectId>) {
             for item in array.iter_mut() {
                 traverse_object(item, action, refs);
@@ -221,10 +440,8 @@ fn traverse_object<A: Fn(&mut Object)>(object: &mut Object, action: &A, refs: &m
                 Object::Array(array) => traverse_array(array, action, refs),
                 Object::Dictionary(dict) => traverse_dictionary(dict, action, refs),
                 Object::Stream(stream) => traverse_dictionary(&mut stream.dict, action, refs),
-                Object::Reference(id) => {
-                    if !refs.contains(id) {
-                        refs.push(*id);
-                    }
+                Object::Reference(id) if !refs.contains(id) => {
+                    refs.push(*id);
                 }
                 _ => {}
             }
@@ -233,20 +450,19 @@ fn traverse_object<A: Fn(&mut Object)>(object: &mut Object, action: &A, refs: &m
         traverse_dictionary(&mut self.trailer, &action, &mut refs);
         let mut index = 0;
         while index < refs.len() {
+            check_cancel(cancel)?;
             if let Some(object) = self.objects.get_mut(&refs[index]) {
+                self.dirty.insert(refs[index]);
                 traverse_object(object, &action, &mut refs);
             }
             index += 1;
         }
-        refs
+        Ok(refs)
     }
 
     /// Return dictionary with encryption information
     pub fn get_encrypted(&self) -> Result<&Dictionary> {
-        self.trailer
-            .get(b"Encrypt")
-            .and_then(Object::as_reference)
-            .and_then(|id| self.get_dictionary(id))
+        self.trailer.get_maybe_dict(b"Encrypt")?.resolve(self)
     }
 
     /// Return true is PDF document is encrypted
@@ -255,7 +471,9 @@ pub fn is_encrypted(&self) -> bool {
     }
 
     /// Replaces all encrypted Strings and Streams with their decrypted contents
+    #[cfg(feature = "encryption")]
     pub fn decrypt<P: AsRef<[u8]>>(&mut self, password: P) -> Result<()> {
+        let _span = span!(DEBUG, "decrypt", objects = self.objects.len());
         // Find the ID of the encryption dict; we'll want to skip it when decrypting
         let encryption_obj_id = self.trailer.get(b"Encrypt").and_then(Object::as_reference)?;
 
@@ -278,17 +496,42 @@ pub fn decrypt<P: AsRef<[u8]>>(&mut self, password: P) -> Result<()> {
             .as_name()
             .unwrap_or_default();
         let is_aes = cfm == b"AESV2";
+        // Streams may name the crypt filter they were encrypted with in their filters.
+        let crypt_filters: HashMap<Vec<u8>, bool> = self
+            .get_encrypted()?
+            .get(b"CF")
+            .and_then(Object::as_dict)
+            .into_iter()
+            .flat_map(|filters| filters.iter())
+            .map(|(name, filter)| {
+                let cfm = filter
+                    .as_dict()
+                    .and_then(|filter| filter.get(b"CFM"))
+                    .and_then(Object::as_name);
+                (name.clone(), matches!(cfm, Ok(b"AESV2")))
+            })
+            .collect();
         for (&id, obj) in self.objects.iter_mut() {
             // The encryption dictionary is not encrypted, leave it alone
             if id == encryption_obj_id {
                 continue;
             }
+            self.dirty.insert(id);
 
             // If a Metadata stream but metadata isn't encrypted, leave it alone
             if obj.type_name().ok() == Some(b"Metadata") && !metadata_is_encrypted {
                 continue;
             }
 
+            let mut is_aes = is_aes;
+            if let Object::Stream(stream) = obj {
+                match stream.take_crypt_filter() {
+                    Some(name) if name == b"Identity" => continue,
+                    Some(name) => is_aes = crypt_filters.get(&name).copied().unwrap_or(is_aes),
+                    None => {}
+                }
+            }
+
             let decrypted = match encryption::decrypt_object(&key, id, &*obj, is_aes) {
                 Ok(content) => content,
                 Err(encryption::DecryptionError::NotDecryptable) => {
@@ -344,12 +587,23 @@ pub fn get_pages(&self) -> BTreeMap<u32, ObjectId> {
         self.page_iter().enumerate().map(|(i, p)| ((i + 1) as u32, p)).collect()
     }
 
+    /// Iterate over the page IDs in page order.
+    ///
+    /// A page listed more than once in the page tree is only yielded at its first occurrence.
     pub fn page_iter(&self) -> impl Iterator<Item = ObjectId> + '_ {
         PageTreeIter::new(self)
     }
 
+    /// Get the pages listed more than once in the page tree.
+    pub(crate) fn duplicate_pages(&self) -> Vec<ObjectId> {
+        let mut pages = PageTreeIter::new(self);
+        pages.by_ref().for_each(drop);
+        pages.duplicates
+    }
+
     /// Get content stream object ids of a page.
-    pub fn get_page_contents(&self, page_id: ObjectId) -> Vec<ObjectId> {
+    pub fn get_page_contents(&self, page_id: impl Into<ObjectId>) -> Vec<ObjectId> {
+        let page_id = page_id.into();
         let mut streams = vec![];
         if let Ok(page) = self.get_dictionary(page_id) {
             let mut nb_deref = 0;
@@ -387,7 +641,8 @@ pub fn get_page_contents(&self, page_id: ObjectId) -> Vec<ObjectId> {
     }
 
     /// Add content to a page. All existing content will be unchanged.
-    pub fn add_page_contents(&mut self, page_id: ObjectId, content: Vec<u8>) -> Result<()> {
+    pub fn add_page_contents(&mut self, page_id: impl Into<ObjectId>, content: Vec<u8>) -> Result<()> {
+        let page_id = page_id.into();
         let page = self.get_dictionary(page_id)?;
         let mut current_content_list: Vec<Object> = match page.get(b"Contents") {
             Ok(Object::Reference(id)) => {
@@ -401,118 +656,181 @@ pub fn add_page_contents(&mut self, page_id: ObjectId, content: Vec<u8>) -> Resu
 
         let page_mut = self.get_object_mut(page_id).and_then(Object::as_dict_mut)?;
         page_mut.set("Contents", current_content_list);
+        self.touch_piece_info(page_id);
         Ok(())
     }
 
     /// Get content of a page.
-    pub fn get_page_content(&self, page_id: ObjectId) -> Result<Vec<u8>> {
-        let mut content = Vec::new();
+    ///
+    /// A page may divide its content into several streams, anywhere between lexical tokens.
+    /// They are joined into one buffer, separated by whitespace where needed, which should be
+    /// decoded as a whole rather than stream by stream.
+    pub fn get_page_content(&self, page_id: impl Into<ObjectId>) -> Result<Vec<u8>> {
+        let page_id = page_id.into();
+        let mut content: Vec<u8> = Vec::new();
         let content_streams = self.get_page_contents(page_id);
+        let cache_sources = self.content_cache.is_enabled().then(|| {
+            std::iter::once(page_id)
+                .chain(content_streams.iter().copied())
+                .map(|id| (id, self.dirty.modified_at(id)))
+                .collect::<Vec<_>>()
+        });
+        if let Some(cached) = cache_sources
+            .as_deref()
+            .and_then(|sources| self.content_cache.get(page_id, sources))
+        {
+            return Ok(cached);
+        }
         for object_id in content_streams {
             if let Ok(content_stream) = self.get_object(object_id).and_then(Object::as_stream) {
+                if content
+                    .last()
+                    .is_some_and(|&byte| !byte.is_ascii_whitespace() && byte != 0)
+                {
+                    content.push(b'\n');
+                }
                 match content_stream.decompressed_content() {
                     Ok(data) => content.write_all(&data)?,
                     Err(_) => content.write_all(&content_stream.content)?,
                 };
             }
         }
+        if let Some(sources) = cache_sources {
+            self.content_cache.insert(page_id, sources, &content);
+        }
         Ok(content)
     }
 
     /// Get resources used by a page.
-    pub fn get_page_resources(&self, page_id: ObjectId) -> Result<(Option<&Dictionary>, Vec<ObjectId>)> {
-        fn collect_resources(
-            page_node: &Dictionary, resource_ids: &mut Vec<ObjectId>, doc: &Document,
-            already_seen: &mut HashSet<ObjectId>,
-        ) -> Result<()> {
-            if let Ok(resource_id) = page_node.get(b"Resources").and_then(Object::as_reference) {
-                resource_ids.push(resource_id);
-            }
-            if let Ok(parent_id) = page_node.get(b"Parent").and_then(Object::as_reference) {
-                if already_seen.contains(&parent_id) {
-                    return Err(Error::ReferenceCycle(parent_id));
-                }
-                already_seen.insert(parent_id);
-                let parent_dict = doc.get_dictionary(parent_id)?;
-                collect_resources(parent_dict, resource_ids, doc, already_seen)?;
-            }
-            Ok(())
-        }
-
+    ///
+    /// Returns the nearest resource dictionary held directly by the page or one of its ancestors,
+    /// and the ids of the resource dictionaries they refer to, nearest first.
+    pub fn get_page_resources(&self, page_id: impl Into<ObjectId>) -> Result<(Option<&Dictionary>, Vec<ObjectId>)> {
+        let page_id = page_id.into();
         let mut resource_dict = None;
         let mut resource_ids = Vec::new();
-        if let Ok(page) = self.get_dictionary(page_id) {
-            resource_dict = page.get(b"Resources").and_then(Object::as_dict).ok();
-            collect_resources(page, &mut resource_ids, self, &mut HashSet::new())?;
+        if let Ok(page) = self.page(page_id) {
+            resource_dict = page
+                .ancestry()
+                .find_map(|(_, node)| node.get(b"Resources").and_then(Object::as_dict).ok());
+            resource_ids = page
+                .ancestry()
+                .filter_map(|(_, node)| node.get(b"Resources").and_then(Object::as_reference).ok())
+                .collect();
         }
         Ok((resource_dict, resource_ids))
     }
 
     /// Get fonts used by a page.
-    pub fn get_page_fonts(&self, page_id: ObjectId) -> Result<BTreeMap<Vec<u8>, &Dictionary>> {
-        fn collect_fonts_from_resources<'a>(
-            resources: &'a Dictionary, fonts: &mut BTreeMap<Vec<u8>, &'a Dictionary>, doc: &'a Document,
-        ) {
-            if let Ok(font) = resources.get(b"Font") {
-                let font_dict = match font {
-                    Object::Reference(id) => doc.get_object(*id).and_then(Object::as_dict).ok(),
-                    Object::Dictionary(dict) => Some(dict),
-                    _ => None,
-                };
-                if let Some(font_dict) = font_dict {
-                    for (name, value) in font_dict.iter() {
-                        let font = match value {
-                            Object::Reference(id) => doc.get_dictionary(*id).ok(),
-                            Object::Dictionary(dict) => Some(dict),
-                            _ => None,
-                        };
-                        if !fonts.contains_key(name) {
-                            font.map(|font| fonts.insert(name.clone(), font));
-                        }
-                    }
-                }
-            }
-        }
-
+    ///
+    /// These are the fonts of the page resources, which its content can use. The appearance streams
+    /// of its annotations have their own, see [`Document::get_page_annotation_fonts`].
+    pub fn get_page_fonts(&self, page_id: impl Into<ObjectId>) -> Result<BTreeMap<Vec<u8>, &Dictionary>> {
+        let page_id = page_id.into();
         let mut fonts = BTreeMap::new();
         let (resource_dict, resource_ids) = self.get_page_resources(page_id)?;
         if let Some(resources) = resource_dict {
-            collect_fonts_from_resources(resources, &mut fonts, self);
+            self.collect_fonts_from_resources(resources, &mut fonts);
         }
         for resource_id in resource_ids {
             if let Ok(resources) = self.get_dictionary(resource_id) {
-                collect_fonts_from_resources(resources, &mut fonts, self);
+                self.collect_fonts_from_resources(resources, &mut fonts);
             }
         }
         Ok(fonts)
     }
 
+    /// Get the fonts of the appearance stream of each annotation of a page, in the order of
+    /// [`Document::get_page_annotations`], see [`Document::get_appearance_fonts`].
+    ///
+    /// The same name can stand for different fonts in the page content and in each appearance
+    /// stream, so each annotation has its own map, which is empty if it has no appearance stream.
+    pub fn get_page_annotation_fonts(
+        &self, page_id: impl Into<ObjectId>,
+    ) -> Result<Vec<BTreeMap<Vec<u8>, &Dictionary>>> {
+        let page_id = page_id.into();
+        Ok(self
+            .get_page_annotations(page_id)?
+            .into_iter()
+            .map(|annotation| match self.get_annotation_appearance(annotation) {
+                Ok(appearance) => self.get_appearance_fonts(appearance),
+                Err(_) => BTreeMap::new(),
+            })
+            .collect())
+    }
+
+    /// Get the default resources of the interactive form, the `/DR` entry of the `/AcroForm` dictionary.
+    ///
+    /// Fonts named by the `/DA` default appearance of form fields are defined here, and appearance
+    /// streams often use them without listing them in their own resources.
+    pub fn get_acroform_resources(&self) -> Result<&Dictionary> {
+        let acroform = self.get_dict_in_dict(self.catalog()?, b"AcroForm")?;
+        self.get_dict_in_dict(acroform, b"DR")
+    }
+
+    /// Get the normal appearance stream of an annotation.
+    ///
+    /// If the annotation has an appearance for each state, the one of its `/AS` state is returned.
+    pub fn get_annotation_appearance<'a>(&'a self, annotation: &'a Dictionary) -> Result<&'a Stream> {
+        let appearances = self.get_dict_in_dict(annotation, b"AP")?;
+        let (_, normal) = self.dereference(appearances.get(b"N")?)?;
+        match normal {
+            Object::Dictionary(states) => {
+                let state = annotation.get(b"AS")?.as_name()?;
+                self.dereference(states.get(state)?)?.1.as_stream()
+            }
+            _ => normal.as_stream(),
+        }
+    }
+
+    /// Get the fonts an appearance stream can use: those of its own resources, followed by those
+    /// of [`Document::get_acroform_resources`] with other names.
+    pub fn get_appearance_fonts<'a>(&'a self, appearance: &'a Stream) -> BTreeMap<Vec<u8>, &'a Dictionary> {
+        let mut fonts = BTreeMap::new();
+        if let Ok(resources) = self.get_dict_in_dict(&appearance.dict, b"Resources") {
+            self.collect_fonts_from_resources(resources, &mut fonts);
+        }
+        if let Ok(resources) = self.get_acroform_resources() {
+            self.collect_fonts_from_resources(resources, &mut fonts);
+        }
+        fonts
+    }
+
+    /// Add the fonts of a resource dictionary which aren't in `fonts` yet.
+    pub(crate) fn collect_fonts_from_resources<'a>(
+        &'a self, resources: &'a Dictionary, fonts: &mut BTreeMap<Vec<u8>, &'a Dictionary>,
+    ) {
+        if let Ok(font_dict) = resources.get_maybe_dict(b"Font").and_then(|it| it.resolve(self)) {
+            for (name, value) in font_dict.iter() {
+                let font = MaybeRef::<Dictionary>::new(value).and_then(|it| it.resolve(self));
+                if let (Ok(font), false) = (font, fonts.contains_key(name)) {
+                    fonts.insert(name.clone(), font);
+                }
+            }
+        }
+    }
+
     /// Get the PDF annotations of a page. The /Subtype of each annotation dictionary defines the
     /// annotation type (Text, Link, Highlight, Underline, Ink, Popup, Widget, etc.). The /Rect of
     /// an annotation dictionary defines its location on the page.
-    pub fn get_page_annotations(&self, page_id: ObjectId) -> Result<Vec<&Dictionary>> {
+    pub fn get_page_annotations(&self, page_id: impl Into<ObjectId>) -> Result<Vec<&Dictionary>> {
+        let page_id = page_id.into();
         let mut annotations = vec![];
         if let Ok(page) = self.get_dictionary(page_id) {
-            match page.get(b"Annots") {
-                Ok(Object::Reference(id)) => self
-                    .get_object(*id)
-                    .and_then(Object::as_array)?
-                    .iter()
-                    .flat_map(Object::as_reference)
-                    .flat_map(|id| self.get_dictionary(id))
-                    .for_each(|a| annotations.push(a)),
-                Ok(Object::Array(a)) => a
+            if let Ok(annots) = page.get_maybe_array(b"Annots") {
+                annots
+                    .resolve(self)?
                     .iter()
-                    .flat_map(Object::as_reference)
-                    .flat_map(|id| self.get_dictionary(id))
-                    .for_each(|a| annotations.push(a)),
-                _ => {}
+                    .flat_map(MaybeRef::<Dictionary>::new)
+                    .flat_map(|annotation| annotation.resolve(self))
+                    .for_each(|a| annotations.push(a));
             }
         }
         Ok(annotations)
     }
 
-    pub fn get_page_images(&self, page_id: ObjectId) -> Result<Vec<PdfImage>> {
+    pub fn get_page_images(&self, page_id: impl Into<ObjectId>) -> Result<Vec<PdfImage<'_>>> {
+        let page_id = page_id.into();
         let mut images = vec![];
         if let Ok(page) = self.get_dictionary(page_id) {
             let resources = self.get_dict_in_dict(page, b"Resources")?;
@@ -525,46 +843,7 @@ pub fn get_page_images(&self, page_id: ObjectId) -> Result<Vec<PdfImage>> {
                 if dict.get(b"Subtype")?.as_name()? != b"Image" {
                     continue;
                 }
-                let width = dict.get(b"Width")?.as_i64()?;
-                let height = dict.get(b"Height")?.as_i64()?;
-                let color_space = match dict.get(b"ColorSpace") {
-                    Ok(cs) => match cs {
-                        Object::Array(array) => Some(String::from_utf8_lossy(array[0].as_name()?).to_string()),
-                        Object::Name(name) => Some(String::from_utf8_lossy(name).to_string()),
-                        _ => None,
-                    },
-                    Err(_) => None,
-                };
-                let bits_per_component = match dict.get(b"BitsPerComponent") {
-                    Ok(bpc) => Some(bpc.as_i64()?),
-                    Err(_) => None,
-                };
-                let mut filters = vec![];
-                if let Ok(filter) = dict.get(b"Filter") {
-                    match filter {
-                        Object::Array(array) => {
-                            for obj in array.iter() {
-                                let name = obj.as_name()?;
-                                filters.push(String::from_utf8_lossy(name).to_string());
-                            }
-                        }
-                        Object::Name(name) => {
-                            filters.push(String::from_utf8_lossy(name).to_string());
-                        }
-                        _ => {}
-                    }
-                };
-
-                images.push(PdfImage {
-                    id,
-                    width,
-                    height,
-                    color_space,
-                    bits_per_component,
-                    filters: Some(filters),
-                    content: &xvalue.content,
-                    origin_dict: &xvalue.dict,
-                });
+                images.push(PdfImage::from_parts(id, dict, &xvalue.content)?);
             }
         }
         Ok(images)
@@ -591,6 +870,8 @@ struct PageTreeIter<'a> {
     stack: Vec<&'a [Object]>,
     kids: Option<&'a [Object]>,
     iter_limit: usize,
+    seen: HashSet<ObjectId>,
+    duplicates: Vec<ObjectId>,
 }
 
 impl<'a> PageTreeIter<'a> {
@@ -607,6 +888,8 @@ fn new(doc: &'a Document) -> Self {
                 kids: Self::kids(doc, page_tree_id),
                 stack: Vec::with_capacity(32),
                 iter_limit: doc.objects.len(),
+                seen: HashSet::new(),
+                duplicates: Vec::new(),
             }
         } else {
             Self {
@@ -614,6 +897,8 @@ fn new(doc: &'a Document) -> Self {
                 kids: None,
                 stack: Vec::new(),
                 iter_limit: doc.objects.len(),
+                seen: HashSet::new(),
+                duplicates: Vec::new(),
             }
         }
     }
@@ -643,17 +928,19 @@ fn next(&mut self) -> Option<Self::Item> {
                 if let Ok(kid_id) = kid.as_reference() {
                     if let Ok(type_name) = self.doc.get_dictionary(kid_id).and_then(Dictionary::get_type) {
                         match type_name {
-                            b"Page" => {
+                            b"Page" if self.seen.insert(kid_id) => {
                                 return Some(kid_id);
                             }
-                            b"Pages" => {
-                                if self.stack.len() < Self::PAGE_TREE_DEPTH_LIMIT {
-                                    let kids = self.kids.unwrap();
-                                    if !kids.is_empty() {
-                                        self.stack.push(kids);
-                                    }
-                                    self.kids = Self::kids(self.doc, kid_id);
+                            b"Page" => {
+                                warn!("Page {kid_id:?} is listed more than once in the page tree");
+                                self.duplicates.push(kid_id);
+                            }
+                            b"Pages" if self.stack.len() < Self::PAGE_TREE_DEPTH_LIMIT => {
+                                let kids = self.kids.unwrap();
+                                if !kids.is_empty() {
+                                    self.stack.push(kids);
                                 }
+                                self.kids = Self::kids(self.doc, kid_id);
                             }
                             _ => {}
                         }
@@ -691,7 +978,8 @@ fn size_hint(&self) -> (usize, Option<usize>) {
             })
             .sum();
 
-        (nb_pages, Some(nb_pages))
+        // Pages listed more than once are only yielded at their first occurrence.
+        (0, Some(nb_pages))
     }
 }
 
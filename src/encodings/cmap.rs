@@ -2,7 +2,7 @@
 use crate::parser::cmap_parser::parse;
 use crate::parser::ParserInput;
 
-use log::error;
+use log::{error, warn};
 use rangemap::RangeInclusiveMap;
 use thiserror::Error;
 
@@ -11,9 +11,13 @@
 /// Codes vary from 1 byte to 4 bytes so they are always in limits of u32.
 /// However to map a code to a unicode value an additional knowledge about the number of bytes is needed,
 /// as 2 byte code <0000> shouldn't be matched with a single byte <00> even though they have the same integer value.
+///
+/// Malformed entries of the CMap are skipped when parsing, rather than rejecting the whole CMap,
+/// see [`ToUnicodeCMap::skipped_entries`].
 #[derive(Debug, Default)]
 pub struct ToUnicodeCMap {
     bf_ranges: [RangeInclusiveMap<SourceCode, BfRangeTarget>; 4],
+    skipped_entries: usize,
 }
 
 #[derive(Debug, Error)]
@@ -36,15 +40,24 @@ impl ToUnicodeCMap {
     pub fn new() -> ToUnicodeCMap {
         ToUnicodeCMap {
             bf_ranges: [(); 4].map(|_| RangeInclusiveMap::new()),
+            skipped_entries: 0,
         }
     }
 
+    /// Number of bfchar and bfrange entries which were skipped when parsing because they were malformed.
+    pub fn skipped_entries(&self) -> usize {
+        self.skipped_entries
+    }
+
     pub(crate) fn parse(stream_content: Vec<u8>) -> Result<ToUnicodeCMap, UnicodeCMapError> {
-        let cmap_sections = parse(ParserInput::new_extra(&stream_content[..], "cmap"))?;
-        Self::from_sections(cmap_sections)
+        let (cmap_sections, skipped_entries) = parse(ParserInput::new_extra(&stream_content[..], "cmap"))?;
+        let mut cmap = Self::from_sections(cmap_sections);
+        cmap.skipped_entries += skipped_entries;
+        Ok(cmap)
     }
 
-    fn from_sections(cmap_sections: Vec<CMapSection>) -> Result<ToUnicodeCMap, UnicodeCMapError> {
+    /// Build the map from the parsed sections, skipping the invalid ranges and clamping the oversized ones.
+    fn from_sections(cmap_sections: Vec<CMapSection>) -> ToUnicodeCMap {
         let mut cmap = Self::new();
         for section in cmap_sections {
             match section {
@@ -55,9 +68,21 @@ fn from_sections(cmap_sections: Vec<CMapSection>) -> Result<ToUnicodeCMap, Unico
                     }
                 }
                 CMapSection::BfRange(range_mappings) => {
-                    for ((start, end, code_len), dst_vec) in range_mappings {
-                        if end < start {
-                            return Err(UnicodeCMapError::InvalidCodeRange);
+                    for ((start, mut end, code_len), dst_vec) in range_mappings {
+                        if end < start || dst_vec.is_empty() {
+                            warn!("Skipping invalid bfrange <{start:x}> <{end:x}>");
+                            cmap.skipped_entries += 1;
+                            continue;
+                        }
+                        // The range can't extend past the last string of an array, or past U+FFFF
+                        // when incrementing the last UTF-16 value of a string.
+                        let last_code = match dst_vec.as_slice() {
+                            [dst] => start.saturating_add(0xffff - *dst.last().unwrap() as u32),
+                            dst_vec => start.saturating_add((dst_vec.len() - 1) as u32),
+                        };
+                        if end > last_code {
+                            warn!("Clamping oversized bfrange <{start:x}> <{end:x}> to <{last_code:x}>");
+                            end = last_code;
                         }
                         match dst_vec.len() {
                             1 if dst_vec[0].len() == 1 => cmap.put(
@@ -69,14 +94,13 @@ fn from_sections(cmap_sections: Vec<CMapSection>) -> Result<ToUnicodeCMap, Unico
                                 },
                             ),
                             1 => cmap.put(start, end, code_len, BfRangeTarget::HexString(dst_vec[0].clone())),
-                            0 => return Err(UnicodeCMapError::InvalidCodeRange),
                             _ => cmap.put(start, end, code_len, BfRangeTarget::ArrayOfHexStrings(dst_vec.clone())),
                         }
                     }
                 }
             }
         }
-        Ok(cmap)
+        cmap
     }
 
     pub fn get(&self, code: SourceCode, code_len: CodeLen) -> Option<Vec<u16>> {
@@ -99,6 +123,35 @@ pub fn get(&self, code: SourceCode, code_len: CodeLen) -> Option<Vec<u16>> {
         })
     }
 
+    /// Find the source code of the given length mapped to the UTF-16 value, the reverse of [`ToUnicodeCMap::get`].
+    pub fn get_code(&self, value: &[u16], code_len: CodeLen) -> Option<SourceCode> {
+        if code_len > 4 || code_len == 0 || value.is_empty() {
+            return None;
+        }
+        use BfRangeTarget::*;
+
+        self.bf_ranges[(code_len - 1) as usize]
+            .iter()
+            .find_map(|(range, target)| match target {
+                UTF16CodePoint { offset } if value.len() == 1 => {
+                    let code = u32::wrapping_sub(value[0] as u32, *offset);
+                    range.contains(&code).then_some(code)
+                }
+                HexString(vec) if vec.len() == value.len() && vec[..vec.len() - 1] == value[..value.len() - 1] => {
+                    let last = *value.last().unwrap();
+                    let first = *vec.last().unwrap();
+                    let code = range.start().checked_add(last.checked_sub(first)? as u32)?;
+                    range.contains(&code).then_some(code)
+                }
+                ArrayOfHexStrings(vec_of_strings) => vec_of_strings
+                    .iter()
+                    .position(|string| string == value)
+                    .map(|index| range.start() + index as u32)
+                    .filter(|code| range.contains(code)),
+                _ => None,
+            })
+    }
+
     pub fn get_or_replacement_char(&self, code: SourceCode, code_len: CodeLen) -> Vec<u16> {
         self.get(code, code_len)
             .unwrap_or(vec![ToUnicodeCMap::REPLACEMENT_CHAR])
@@ -172,4 +225,68 @@ fn wrong_code_len_does_not_panic() {
         cmap.put_char(char_code, 5, char_value.clone());
         cmap.put_char(char_code, 0, char_value.clone());
     }
+
+    #[test]
+    fn code_can_be_found_from_value() {
+        let mut cmap = ToUnicodeCMap::new();
+        cmap.put(0x0001, 0x001A, 2, BfRangeTarget::UTF16CodePoint { offset: 0x0060 });
+        cmap.put(0x0100, 0x0101, 2, BfRangeTarget::HexString(vec![0x0066, 0x0066]));
+        cmap.put(
+            0x0200,
+            0x0201,
+            2,
+            BfRangeTarget::ArrayOfHexStrings(vec![vec![0x0041], vec![0x0042, 0x0043]]),
+        );
+
+        assert_eq!(cmap.get_code(&[0x0061], 2), Some(0x0001));
+        assert_eq!(cmap.get_code(&[0x007A], 2), Some(0x001A));
+        assert_eq!(cmap.get_code(&[0x0066, 0x0067], 2), Some(0x0101));
+        assert_eq!(cmap.get_code(&[0x0042, 0x0043], 2), Some(0x0201));
+        assert_eq!(cmap.get_code(&[0x0061], 1), None);
+        assert_eq!(cmap.get_code(&[0x0030], 2), None);
+    }
+
+    #[test]
+    fn malformed_entries_are_skipped() {
+        let content = b"/CIDInit /ProcSet findresource begin
+12 dict begin
+begincmap
+/CMapName /Adobe-Identity-UCS def
+/CMapType 2 def
+1 begincodespacerange
+<00> <FF>
+endcodespacerange
+3 beginbfchar
+<01> <0041>
+<02> <00zz>
+<3> <0043>
+endbfchar
+5 beginbfrange
+<10> <12> <0061>
+<20> <1f> <0062>
+<40> <4f> [<0070> <0071>]
+<50> <5x> <0063>
+<60> <ff> <FFFE>
+endbfrange
+endcmap
+CMapName currentdict /CMap defineresource pop
+end
+end
+";
+        let cmap = ToUnicodeCMap::parse(content.to_vec()).unwrap();
+        assert_eq!(cmap.skipped_entries(), 3);
+
+        assert_eq!(cmap.get(0x01, 1), Some(vec![0x0041]));
+        assert_eq!(cmap.get(0x02, 1), None);
+        // The missing final digit is assumed to be 0.
+        assert_eq!(cmap.get(0x30, 1), Some(vec![0x0043]));
+        assert_eq!(cmap.get(0x12, 1), Some(vec![0x0063]));
+        assert_eq!(cmap.get(0x20, 1), None);
+        // Oversized ranges are clamped.
+        assert_eq!(cmap.get(0x41, 1), Some(vec![0x0071]));
+        assert_eq!(cmap.get(0x42, 1), None);
+        assert_eq!(cmap.get(0x55, 1), None);
+        assert_eq!(cmap.get(0x61, 1), Some(vec![0xffff]));
+        assert_eq!(cmap.get(0x62, 1), None);
+    }
 }
@@ -4499,3 +4499,4561 @@ impl Glyph {
     pub const zuhiragana: u16 = 0x305a;
     pub const zukatakana: u16 = 0x30ba;
 }
+
+/// The glyph names of the Adobe Glyph List with their Unicode values, sorted by name.
+pub(crate) static GLYPH_NAMES: [(&str, u16); 4495] = [
+    ("A", Glyph::A),
+    ("AE", Glyph::AE),
+    ("AEacute", Glyph::AEacute),
+    ("AEmacron", Glyph::AEmacron),
+    ("AEsmall", Glyph::AEsmall),
+    ("Aacute", Glyph::Aacute),
+    ("Aacutesmall", Glyph::Aacutesmall),
+    ("Abreve", Glyph::Abreve),
+    ("Abreveacute", Glyph::Abreveacute),
+    ("Abrevecyrillic", Glyph::Abrevecyrillic),
+    ("Abrevedotbelow", Glyph::Abrevedotbelow),
+    ("Abrevegrave", Glyph::Abrevegrave),
+    ("Abrevehookabove", Glyph::Abrevehookabove),
+    ("Abrevetilde", Glyph::Abrevetilde),
+    ("Acaron", Glyph::Acaron),
+    ("Acircle", Glyph::Acircle),
+    ("Acircumflex", Glyph::Acircumflex),
+    ("Acircumflexacute", Glyph::Acircumflexacute),
+    ("Acircumflexdotbelow", Glyph::Acircumflexdotbelow),
+    ("Acircumflexgrave", Glyph::Acircumflexgrave),
+    ("Acircumflexhookabove", Glyph::Acircumflexhookabove),
+    ("Acircumflexsmall", Glyph::Acircumflexsmall),
+    ("Acircumflextilde", Glyph::Acircumflextilde),
+    ("Acute", Glyph::Acute),
+    ("Acutesmall", Glyph::Acutesmall),
+    ("Acyrillic", Glyph::Acyrillic),
+    ("Adblgrave", Glyph::Adblgrave),
+    ("Adieresis", Glyph::Adieresis),
+    ("Adieresiscyrillic", Glyph::Adieresiscyrillic),
+    ("Adieresismacron", Glyph::Adieresismacron),
+    ("Adieresissmall", Glyph::Adieresissmall),
+    ("Adotbelow", Glyph::Adotbelow),
+    ("Adotmacron", Glyph::Adotmacron),
+    ("Agrave", Glyph::Agrave),
+    ("Agravesmall", Glyph::Agravesmall),
+    ("Ahookabove", Glyph::Ahookabove),
+    ("Aiecyrillic", Glyph::Aiecyrillic),
+    ("Ainvertedbreve", Glyph::Ainvertedbreve),
+    ("Alpha", Glyph::Alpha),
+    ("Alphatonos", Glyph::Alphatonos),
+    ("Amacron", Glyph::Amacron),
+    ("Amonospace", Glyph::Amonospace),
+    ("Aogonek", Glyph::Aogonek),
+    ("Aring", Glyph::Aring),
+    ("Aringacute", Glyph::Aringacute),
+    ("Aringbelow", Glyph::Aringbelow),
+    ("Aringsmall", Glyph::Aringsmall),
+    ("Asmall", Glyph::Asmall),
+    ("Atilde", Glyph::Atilde),
+    ("Atildesmall", Glyph::Atildesmall),
+    ("Aybarmenian", Glyph::Aybarmenian),
+    ("B", Glyph::B),
+    ("Bcircle", Glyph::Bcircle),
+    ("Bdotaccent", Glyph::Bdotaccent),
+    ("Bdotbelow", Glyph::Bdotbelow),
+    ("Becyrillic", Glyph::Becyrillic),
+    ("Benarmenian", Glyph::Benarmenian),
+    ("Beta", Glyph::Beta),
+    ("Bhook", Glyph::Bhook),
+    ("Blinebelow", Glyph::Blinebelow),
+    ("Bmonospace", Glyph::Bmonospace),
+    ("Brevesmall", Glyph::Brevesmall),
+    ("Bsmall", Glyph::Bsmall),
+    ("Btopbar", Glyph::Btopbar),
+    ("C", Glyph::C),
+    ("Caarmenian", Glyph::Caarmenian),
+    ("Cacute", Glyph::Cacute),
+    ("Caron", Glyph::Caron),
+    ("Caronsmall", Glyph::Caronsmall),
+    ("Ccaron", Glyph::Ccaron),
+    ("Ccedilla", Glyph::Ccedilla),
+    ("Ccedillaacute", Glyph::Ccedillaacute),
+    ("Ccedillasmall", Glyph::Ccedillasmall),
+    ("Ccircle", Glyph::Ccircle),
+    ("Ccircumflex", Glyph::Ccircumflex),
+    ("Cdot", Glyph::Cdot),
+    ("Cdotaccent", Glyph::Cdotaccent),
+    ("Cedillasmall", Glyph::Cedillasmall),
+    ("Chaarmenian", Glyph::Chaarmenian),
+    ("Cheabkhasiancyrillic", Glyph::Cheabkhasiancyrillic),
+    ("Checyrillic", Glyph::Checyrillic),
+    ("Chedescenderabkhasiancyrillic", Glyph::Chedescenderabkhasiancyrillic),
+    ("Chedescendercyrillic", Glyph::Chedescendercyrillic),
+    ("Chedieresiscyrillic", Glyph::Chedieresiscyrillic),
+    ("Cheharmenian", Glyph::Cheharmenian),
+    ("Chekhakassiancyrillic", Glyph::Chekhakassiancyrillic),
+    ("Cheverticalstrokecyrillic", Glyph::Cheverticalstrokecyrillic),
+    ("Chi", Glyph::Chi),
+    ("Chook", Glyph::Chook),
+    ("Circumflexsmall", Glyph::Circumflexsmall),
+    ("Cmonospace", Glyph::Cmonospace),
+    ("Coarmenian", Glyph::Coarmenian),
+    ("Csmall", Glyph::Csmall),
+    ("D", Glyph::D),
+    ("DZ", Glyph::DZ),
+    ("DZcaron", Glyph::DZcaron),
+    ("Daarmenian", Glyph::Daarmenian),
+    ("Dafrican", Glyph::Dafrican),
+    ("Dbar", Glyph::Dbar),
+    ("Dcaron", Glyph::Dcaron),
+    ("Dcedilla", Glyph::Dcedilla),
+    ("Dcircle", Glyph::Dcircle),
+    ("Dcircumflexbelow", Glyph::Dcircumflexbelow),
+    ("Dcroat", Glyph::Dcroat),
+    ("Ddotaccent", Glyph::Ddotaccent),
+    ("Ddotbelow", Glyph::Ddotbelow),
+    ("Decyrillic", Glyph::Decyrillic),
+    ("Deicoptic", Glyph::Deicoptic),
+    ("Delta", Glyph::Delta),
+    ("Deltagreek", Glyph::Deltagreek),
+    ("Dhook", Glyph::Dhook),
+    ("Dieresis", Glyph::Dieresis),
+    ("DieresisAcute", Glyph::DieresisAcute),
+    ("DieresisGrave", Glyph::DieresisGrave),
+    ("Dieresissmall", Glyph::Dieresissmall),
+    ("Digammagreek", Glyph::Digammagreek),
+    ("Djecyrillic", Glyph::Djecyrillic),
+    ("Dlinebelow", Glyph::Dlinebelow),
+    ("Dmonospace", Glyph::Dmonospace),
+    ("Dotaccentsmall", Glyph::Dotaccentsmall),
+    ("Dslash", Glyph::Dslash),
+    ("Dsmall", Glyph::Dsmall),
+    ("Dtopbar", Glyph::Dtopbar),
+    ("Dz", Glyph::Dz),
+    ("Dzcaron", Glyph::Dzcaron),
+    ("Dzeabkhasiancyrillic", Glyph::Dzeabkhasiancyrillic),
+    ("Dzecyrillic", Glyph::Dzecyrillic),
+    ("Dzhecyrillic", Glyph::Dzhecyrillic),
+    ("E", Glyph::E),
+    ("Eacute", Glyph::Eacute),
+    ("Eacutesmall", Glyph::Eacutesmall),
+    ("Ebreve", Glyph::Ebreve),
+    ("Ecaron", Glyph::Ecaron),
+    ("Ecedillabreve", Glyph::Ecedillabreve),
+    ("Echarmenian", Glyph::Echarmenian),
+    ("Ecircle", Glyph::Ecircle),
+    ("Ecircumflex", Glyph::Ecircumflex),
+    ("Ecircumflexacute", Glyph::Ecircumflexacute),
+    ("Ecircumflexbelow", Glyph::Ecircumflexbelow),
+    ("Ecircumflexdotbelow", Glyph::Ecircumflexdotbelow),
+    ("Ecircumflexgrave", Glyph::Ecircumflexgrave),
+    ("Ecircumflexhookabove", Glyph::Ecircumflexhookabove),
+    ("Ecircumflexsmall", Glyph::Ecircumflexsmall),
+    ("Ecircumflextilde", Glyph::Ecircumflextilde),
+    ("Ecyrillic", Glyph::Ecyrillic),
+    ("Edblgrave", Glyph::Edblgrave),
+    ("Edieresis", Glyph::Edieresis),
+    ("Edieresissmall", Glyph::Edieresissmall),
+    ("Edot", Glyph::Edot),
+    ("Edotaccent", Glyph::Edotaccent),
+    ("Edotbelow", Glyph::Edotbelow),
+    ("Efcyrillic", Glyph::Efcyrillic),
+    ("Egrave", Glyph::Egrave),
+    ("Egravesmall", Glyph::Egravesmall),
+    ("Eharmenian", Glyph::Eharmenian),
+    ("Ehookabove", Glyph::Ehookabove),
+    ("Eightroman", Glyph::Eightroman),
+    ("Einvertedbreve", Glyph::Einvertedbreve),
+    ("Eiotifiedcyrillic", Glyph::Eiotifiedcyrillic),
+    ("Elcyrillic", Glyph::Elcyrillic),
+    ("Elevenroman", Glyph::Elevenroman),
+    ("Emacron", Glyph::Emacron),
+    ("Emacronacute", Glyph::Emacronacute),
+    ("Emacrongrave", Glyph::Emacrongrave),
+    ("Emcyrillic", Glyph::Emcyrillic),
+    ("Emonospace", Glyph::Emonospace),
+    ("Encyrillic", Glyph::Encyrillic),
+    ("Endescendercyrillic", Glyph::Endescendercyrillic),
+    ("Eng", Glyph::Eng),
+    ("Enghecyrillic", Glyph::Enghecyrillic),
+    ("Enhookcyrillic", Glyph::Enhookcyrillic),
+    ("Eogonek", Glyph::Eogonek),
+    ("Eopen", Glyph::Eopen),
+    ("Epsilon", Glyph::Epsilon),
+    ("Epsilontonos", Glyph::Epsilontonos),
+    ("Ercyrillic", Glyph::Ercyrillic),
+    ("Ereversed", Glyph::Ereversed),
+    ("Ereversedcyrillic", Glyph::Ereversedcyrillic),
+    ("Escyrillic", Glyph::Escyrillic),
+    ("Esdescendercyrillic", Glyph::Esdescendercyrillic),
+    ("Esh", Glyph::Esh),
+    ("Esmall", Glyph::Esmall),
+    ("Eta", Glyph::Eta),
+    ("Etarmenian", Glyph::Etarmenian),
+    ("Etatonos", Glyph::Etatonos),
+    ("Eth", Glyph::Eth),
+    ("Ethsmall", Glyph::Ethsmall),
+    ("Etilde", Glyph::Etilde),
+    ("Etildebelow", Glyph::Etildebelow),
+    ("Euro", Glyph::Euro),
+    ("Ezh", Glyph::Ezh),
+    ("Ezhcaron", Glyph::Ezhcaron),
+    ("Ezhreversed", Glyph::Ezhreversed),
+    ("F", Glyph::F),
+    ("FFIsmall", Glyph::FFIsmall),
+    ("FFLsmall", Glyph::FFLsmall),
+    ("FFsmall", Glyph::FFsmall),
+    ("FIsmall", Glyph::FIsmall),
+    ("FLsmall", Glyph::FLsmall),
+    ("Fcircle", Glyph::Fcircle),
+    ("Fdotaccent", Glyph::Fdotaccent),
+    ("Feharmenian", Glyph::Feharmenian),
+    ("Feicoptic", Glyph::Feicoptic),
+    ("Fhook", Glyph::Fhook),
+    ("Fitacyrillic", Glyph::Fitacyrillic),
+    ("Fiveroman", Glyph::Fiveroman),
+    ("Fmonospace", Glyph::Fmonospace),
+    ("Fourroman", Glyph::Fourroman),
+    ("Fsmall", Glyph::Fsmall),
+    ("G", Glyph::G),
+    ("GBsquare", Glyph::GBsquare),
+    ("Gacute", Glyph::Gacute),
+    ("Gamma", Glyph::Gamma),
+    ("Gammaafrican", Glyph::Gammaafrican),
+    ("Gangiacoptic", Glyph::Gangiacoptic),
+    ("Gbreve", Glyph::Gbreve),
+    ("Gcaron", Glyph::Gcaron),
+    ("Gcedilla", Glyph::Gcedilla),
+    ("Gcircle", Glyph::Gcircle),
+    ("Gcircumflex", Glyph::Gcircumflex),
+    ("Gcommaaccent", Glyph::Gcommaaccent),
+    ("Gdot", Glyph::Gdot),
+    ("Gdotaccent", Glyph::Gdotaccent),
+    ("Gecyrillic", Glyph::Gecyrillic),
+    ("Germandbls", Glyph::Germandbls),
+    ("Germandbls2", Glyph::Germandbls2),
+    ("Germandblssmall", Glyph::Germandblssmall),
+    ("Ghadarmenian", Glyph::Ghadarmenian),
+    ("Ghemiddlehookcyrillic", Glyph::Ghemiddlehookcyrillic),
+    ("Ghestrokecyrillic", Glyph::Ghestrokecyrillic),
+    ("Gheupturncyrillic", Glyph::Gheupturncyrillic),
+    ("Ghook", Glyph::Ghook),
+    ("Gimarmenian", Glyph::Gimarmenian),
+    ("Gjecyrillic", Glyph::Gjecyrillic),
+    ("Gmacron", Glyph::Gmacron),
+    ("Gmonospace", Glyph::Gmonospace),
+    ("Grave", Glyph::Grave),
+    ("Gravesmall", Glyph::Gravesmall),
+    ("Gsmall", Glyph::Gsmall),
+    ("Gsmallhook", Glyph::Gsmallhook),
+    ("Gstroke", Glyph::Gstroke),
+    ("H", Glyph::H),
+    ("H18533", Glyph::H18533),
+    ("H18543", Glyph::H18543),
+    ("H18551", Glyph::H18551),
+    ("H22073", Glyph::H22073),
+    ("HPsquare", Glyph::HPsquare),
+    ("Haabkhasiancyrillic", Glyph::Haabkhasiancyrillic),
+    ("Hadescendercyrillic", Glyph::Hadescendercyrillic),
+    ("Hardsigncyrillic", Glyph::Hardsigncyrillic),
+    ("Hbar", Glyph::Hbar),
+    ("Hbrevebelow", Glyph::Hbrevebelow),
+    ("Hcedilla", Glyph::Hcedilla),
+    ("Hcircle", Glyph::Hcircle),
+    ("Hcircumflex", Glyph::Hcircumflex),
+    ("Hdieresis", Glyph::Hdieresis),
+    ("Hdotaccent", Glyph::Hdotaccent),
+    ("Hdotbelow", Glyph::Hdotbelow),
+    ("Hmonospace", Glyph::Hmonospace),
+    ("Hoarmenian", Glyph::Hoarmenian),
+    ("Horicoptic", Glyph::Horicoptic),
+    ("Hsmall", Glyph::Hsmall),
+    ("Hungarumlaut", Glyph::Hungarumlaut),
+    ("Hungarumlautsmall", Glyph::Hungarumlautsmall),
+    ("Hzsquare", Glyph::Hzsquare),
+    ("I", Glyph::I),
+    ("IAcyrillic", Glyph::IAcyrillic),
+    ("IJ", Glyph::IJ),
+    ("IUcyrillic", Glyph::IUcyrillic),
+    ("Iacute", Glyph::Iacute),
+    ("Iacutesmall", Glyph::Iacutesmall),
+    ("Ibreve", Glyph::Ibreve),
+    ("Icaron", Glyph::Icaron),
+    ("Icircle", Glyph::Icircle),
+    ("Icircumflex", Glyph::Icircumflex),
+    ("Icircumflexsmall", Glyph::Icircumflexsmall),
+    ("Icyrillic", Glyph::Icyrillic),
+    ("Idblgrave", Glyph::Idblgrave),
+    ("Idieresis", Glyph::Idieresis),
+    ("Idieresisacute", Glyph::Idieresisacute),
+    ("Idieresiscyrillic", Glyph::Idieresiscyrillic),
+    ("Idieresissmall", Glyph::Idieresissmall),
+    ("Idot", Glyph::Idot),
+    ("Idotaccent", Glyph::Idotaccent),
+    ("Idotbelow", Glyph::Idotbelow),
+    ("Iebrevecyrillic", Glyph::Iebrevecyrillic),
+    ("Iecyrillic", Glyph::Iecyrillic),
+    ("Ifractur", Glyph::Ifractur),
+    ("Ifraktur", Glyph::Ifraktur),
+    ("Igrave", Glyph::Igrave),
+    ("Igravesmall", Glyph::Igravesmall),
+    ("Ihookabove", Glyph::Ihookabove),
+    ("Iicyrillic", Glyph::Iicyrillic),
+    ("Iinvertedbreve", Glyph::Iinvertedbreve),
+    ("Iishortcyrillic", Glyph::Iishortcyrillic),
+    ("Imacron", Glyph::Imacron),
+    ("Imacroncyrillic", Glyph::Imacroncyrillic),
+    ("Imonospace", Glyph::Imonospace),
+    ("Iniarmenian", Glyph::Iniarmenian),
+    ("Iocyrillic", Glyph::Iocyrillic),
+    ("Iogonek", Glyph::Iogonek),
+    ("Iota", Glyph::Iota),
+    ("Iotaafrican", Glyph::Iotaafrican),
+    ("Iotadieresis", Glyph::Iotadieresis),
+    ("Iotatonos", Glyph::Iotatonos),
+    ("Ismall", Glyph::Ismall),
+    ("Istroke", Glyph::Istroke),
+    ("Itilde", Glyph::Itilde),
+    ("Itildebelow", Glyph::Itildebelow),
+    ("Izhitsacyrillic", Glyph::Izhitsacyrillic),
+    ("Izhitsadblgravecyrillic", Glyph::Izhitsadblgravecyrillic),
+    ("J", Glyph::J),
+    ("Jaarmenian", Glyph::Jaarmenian),
+    ("Jcircle", Glyph::Jcircle),
+    ("Jcircumflex", Glyph::Jcircumflex),
+    ("Jecyrillic", Glyph::Jecyrillic),
+    ("Jheharmenian", Glyph::Jheharmenian),
+    ("Jmonospace", Glyph::Jmonospace),
+    ("Jsmall", Glyph::Jsmall),
+    ("K", Glyph::K),
+    ("KBsquare", Glyph::KBsquare),
+    ("KKsquare", Glyph::KKsquare),
+    ("Kabashkircyrillic", Glyph::Kabashkircyrillic),
+    ("Kacute", Glyph::Kacute),
+    ("Kacyrillic", Glyph::Kacyrillic),
+    ("Kadescendercyrillic", Glyph::Kadescendercyrillic),
+    ("Kahookcyrillic", Glyph::Kahookcyrillic),
+    ("Kappa", Glyph::Kappa),
+    ("Kastrokecyrillic", Glyph::Kastrokecyrillic),
+    ("Kaverticalstrokecyrillic", Glyph::Kaverticalstrokecyrillic),
+    ("Kcaron", Glyph::Kcaron),
+    ("Kcedilla", Glyph::Kcedilla),
+    ("Kcircle", Glyph::Kcircle),
+    ("Kcommaaccent", Glyph::Kcommaaccent),
+    ("Kdotbelow", Glyph::Kdotbelow),
+    ("Keharmenian", Glyph::Keharmenian),
+    ("Kenarmenian", Glyph::Kenarmenian),
+    ("Khacyrillic", Glyph::Khacyrillic),
+    ("Kheicoptic", Glyph::Kheicoptic),
+    ("Khook", Glyph::Khook),
+    ("Kjecyrillic", Glyph::Kjecyrillic),
+    ("Klinebelow", Glyph::Klinebelow),
+    ("Kmonospace", Glyph::Kmonospace),
+    ("Koppacyrillic", Glyph::Koppacyrillic),
+    ("Koppagreek", Glyph::Koppagreek),
+    ("Ksicyrillic", Glyph::Ksicyrillic),
+    ("Ksmall", Glyph::Ksmall),
+    ("L", Glyph::L),
+    ("LJ", Glyph::LJ),
+    ("LL", Glyph::LL),
+    ("Lacute", Glyph::Lacute),
+    ("Lambda", Glyph::Lambda),
+    ("Lcaron", Glyph::Lcaron),
+    ("Lcedilla", Glyph::Lcedilla),
+    ("Lcircle", Glyph::Lcircle),
+    ("Lcircumflexbelow", Glyph::Lcircumflexbelow),
+    ("Lcommaaccent", Glyph::Lcommaaccent),
+    ("Ldot", Glyph::Ldot),
+    ("Ldotaccent", Glyph::Ldotaccent),
+    ("Ldotbelow", Glyph::Ldotbelow),
+    ("Ldotbelowmacron", Glyph::Ldotbelowmacron),
+    ("Liwnarmenian", Glyph::Liwnarmenian),
+    ("Lj", Glyph::Lj),
+    ("Ljecyrillic", Glyph::Ljecyrillic),
+    ("Llinebelow", Glyph::Llinebelow),
+    ("Lmonospace", Glyph::Lmonospace),
+    ("Lslash", Glyph::Lslash),
+    ("Lslashsmall", Glyph::Lslashsmall),
+    ("Lsmall", Glyph::Lsmall),
+    ("M", Glyph::M),
+    ("MBsquare", Glyph::MBsquare),
+    ("Macron", Glyph::Macron),
+    ("Macronsmall", Glyph::Macronsmall),
+    ("Macute", Glyph::Macute),
+    ("Mcircle", Glyph::Mcircle),
+    ("Mdotaccent", Glyph::Mdotaccent),
+    ("Mdotbelow", Glyph::Mdotbelow),
+    ("Menarmenian", Glyph::Menarmenian),
+    ("Mmonospace", Glyph::Mmonospace),
+    ("Msmall", Glyph::Msmall),
+    ("Mturned", Glyph::Mturned),
+    ("Mu", Glyph::Mu),
+    ("N", Glyph::N),
+    ("NJ", Glyph::NJ),
+    ("Nacute", Glyph::Nacute),
+    ("Ncaron", Glyph::Ncaron),
+    ("Ncedilla", Glyph::Ncedilla),
+    ("Ncircle", Glyph::Ncircle),
+    ("Ncircumflexbelow", Glyph::Ncircumflexbelow),
+    ("Ncommaaccent", Glyph::Ncommaaccent),
+    ("Ndotaccent", Glyph::Ndotaccent),
+    ("Ndotbelow", Glyph::Ndotbelow),
+    ("Ng", Glyph::Ng),
+    ("Nhookleft", Glyph::Nhookleft),
+    ("Nineroman", Glyph::Nineroman),
+    ("Nj", Glyph::Nj),
+    ("Njecyrillic", Glyph::Njecyrillic),
+    ("Nlinebelow", Glyph::Nlinebelow),
+    ("Nmonospace", Glyph::Nmonospace),
+    ("Nowarmenian", Glyph::Nowarmenian),
+    ("Nsmall", Glyph::Nsmall),
+    ("Ntilde", Glyph::Ntilde),
+    ("Ntildesmall", Glyph::Ntildesmall),
+    ("Nu", Glyph::Nu),
+    ("O", Glyph::O),
+    ("OE", Glyph::OE),
+    ("OEsmall", Glyph::OEsmall),
+    ("Oacute", Glyph::Oacute),
+    ("Oacutesmall", Glyph::Oacutesmall),
+    ("Obarredcyrillic", Glyph::Obarredcyrillic),
+    ("Obarreddieresiscyrillic", Glyph::Obarreddieresiscyrillic),
+    ("Obreve", Glyph::Obreve),
+    ("Ocaron", Glyph::Ocaron),
+    ("Ocenteredtilde", Glyph::Ocenteredtilde),
+    ("Ocircle", Glyph::Ocircle),
+    ("Ocircumflex", Glyph::Ocircumflex),
+    ("Ocircumflexacute", Glyph::Ocircumflexacute),
+    ("Ocircumflexdotbelow", Glyph::Ocircumflexdotbelow),
+    ("Ocircumflexgrave", Glyph::Ocircumflexgrave),
+    ("Ocircumflexhookabove", Glyph::Ocircumflexhookabove),
+    ("Ocircumflexsmall", Glyph::Ocircumflexsmall),
+    ("Ocircumflextilde", Glyph::Ocircumflextilde),
+    ("Ocyrillic", Glyph::Ocyrillic),
+    ("Odblacute", Glyph::Odblacute),
+    ("Odblgrave", Glyph::Odblgrave),
+    ("Odieresis", Glyph::Odieresis),
+    ("Odieresiscyrillic", Glyph::Odieresiscyrillic),
+    ("Odieresissmall", Glyph::Odieresissmall),
+    ("Odotbelow", Glyph::Odotbelow),
+    ("Ogoneksmall", Glyph::Ogoneksmall),
+    ("Ograve", Glyph::Ograve),
+    ("Ogravesmall", Glyph::Ogravesmall),
+    ("Oharmenian", Glyph::Oharmenian),
+    ("Ohm", Glyph::Ohm),
+    ("Ohookabove", Glyph::Ohookabove),
+    ("Ohorn", Glyph::Ohorn),
+    ("Ohornacute", Glyph::Ohornacute),
+    ("Ohorndotbelow", Glyph::Ohorndotbelow),
+    ("Ohorngrave", Glyph::Ohorngrave),
+    ("Ohornhookabove", Glyph::Ohornhookabove),
+    ("Ohorntilde", Glyph::Ohorntilde),
+    ("Ohungarumlaut", Glyph::Ohungarumlaut),
+    ("Oi", Glyph::Oi),
+    ("Oinvertedbreve", Glyph::Oinvertedbreve),
+    ("Omacron", Glyph::Omacron),
+    ("Omacronacute", Glyph::Omacronacute),
+    ("Omacrongrave", Glyph::Omacrongrave),
+    ("Omega", Glyph::Omega),
+    ("Omegacyrillic", Glyph::Omegacyrillic),
+    ("Omegagreek", Glyph::Omegagreek),
+    ("Omegaroundcyrillic", Glyph::Omegaroundcyrillic),
+    ("Omegatitlocyrillic", Glyph::Omegatitlocyrillic),
+    ("Omegatonos", Glyph::Omegatonos),
+    ("Omicron", Glyph::Omicron),
+    ("Omicrontonos", Glyph::Omicrontonos),
+    ("Omonospace", Glyph::Omonospace),
+    ("Oneroman", Glyph::Oneroman),
+    ("Oogonek", Glyph::Oogonek),
+    ("Oogonekmacron", Glyph::Oogonekmacron),
+    ("Oopen", Glyph::Oopen),
+    ("Oslash", Glyph::Oslash),
+    ("Oslashacute", Glyph::Oslashacute),
+    ("Oslashsmall", Glyph::Oslashsmall),
+    ("Osmall", Glyph::Osmall),
+    ("Ostrokeacute", Glyph::Ostrokeacute),
+    ("Otcyrillic", Glyph::Otcyrillic),
+    ("Otilde", Glyph::Otilde),
+    ("Otildeacute", Glyph::Otildeacute),
+    ("Otildedieresis", Glyph::Otildedieresis),
+    ("Otildesmall", Glyph::Otildesmall),
+    ("P", Glyph::P),
+    ("Pacute", Glyph::Pacute),
+    ("Pcircle", Glyph::Pcircle),
+    ("Pdotaccent", Glyph::Pdotaccent),
+    ("Pecyrillic", Glyph::Pecyrillic),
+    ("Peharmenian", Glyph::Peharmenian),
+    ("Pemiddlehookcyrillic", Glyph::Pemiddlehookcyrillic),
+    ("Phi", Glyph::Phi),
+    ("Phook", Glyph::Phook),
+    ("Pi", Glyph::Pi),
+    ("Piwrarmenian", Glyph::Piwrarmenian),
+    ("Pmonospace", Glyph::Pmonospace),
+    ("Psi", Glyph::Psi),
+    ("Psicyrillic", Glyph::Psicyrillic),
+    ("Psmall", Glyph::Psmall),
+    ("Q", Glyph::Q),
+    ("Qcircle", Glyph::Qcircle),
+    ("Qmonospace", Glyph::Qmonospace),
+    ("Qsmall", Glyph::Qsmall),
+    ("R", Glyph::R),
+    ("Raarmenian", Glyph::Raarmenian),
+    ("Racute", Glyph::Racute),
+    ("Rcaron", Glyph::Rcaron),
+    ("Rcedilla", Glyph::Rcedilla),
+    ("Rcircle", Glyph::Rcircle),
+    ("Rcommaaccent", Glyph::Rcommaaccent),
+    ("Rdblgrave", Glyph::Rdblgrave),
+    ("Rdotaccent", Glyph::Rdotaccent),
+    ("Rdotbelow", Glyph::Rdotbelow),
+    ("Rdotbelowmacron", Glyph::Rdotbelowmacron),
+    ("Reharmenian", Glyph::Reharmenian),
+    ("Rfractur", Glyph::Rfractur),
+    ("Rfraktur", Glyph::Rfraktur),
+    ("Rho", Glyph::Rho),
+    ("Ringsmall", Glyph::Ringsmall),
+    ("Rinvertedbreve", Glyph::Rinvertedbreve),
+    ("Rlinebelow", Glyph::Rlinebelow),
+    ("Rmonospace", Glyph::Rmonospace),
+    ("Rsmall", Glyph::Rsmall),
+    ("Rsmallinverted", Glyph::Rsmallinverted),
+    ("Rsmallinvertedsuperior", Glyph::Rsmallinvertedsuperior),
+    ("S", Glyph::S),
+    ("SF010000", Glyph::SF010000),
+    ("SF020000", Glyph::SF020000),
+    ("SF030000", Glyph::SF030000),
+    ("SF040000", Glyph::SF040000),
+    ("SF050000", Glyph::SF050000),
+    ("SF060000", Glyph::SF060000),
+    ("SF070000", Glyph::SF070000),
+    ("SF080000", Glyph::SF080000),
+    ("SF090000", Glyph::SF090000),
+    ("SF100000", Glyph::SF100000),
+    ("SF110000", Glyph::SF110000),
+    ("SF190000", Glyph::SF190000),
+    ("SF200000", Glyph::SF200000),
+    ("SF210000", Glyph::SF210000),
+    ("SF220000", Glyph::SF220000),
+    ("SF230000", Glyph::SF230000),
+    ("SF240000", Glyph::SF240000),
+    ("SF250000", Glyph::SF250000),
+    ("SF260000", Glyph::SF260000),
+    ("SF270000", Glyph::SF270000),
+    ("SF280000", Glyph::SF280000),
+    ("SF360000", Glyph::SF360000),
+    ("SF370000", Glyph::SF370000),
+    ("SF380000", Glyph::SF380000),
+    ("SF390000", Glyph::SF390000),
+    ("SF400000", Glyph::SF400000),
+    ("SF410000", Glyph::SF410000),
+    ("SF420000", Glyph::SF420000),
+    ("SF430000", Glyph::SF430000),
+    ("SF440000", Glyph::SF440000),
+    ("SF450000", Glyph::SF450000),
+    ("SF460000", Glyph::SF460000),
+    ("SF470000", Glyph::SF470000),
+    ("SF480000", Glyph::SF480000),
+    ("SF490000", Glyph::SF490000),
+    ("SF500000", Glyph::SF500000),
+    ("SF510000", Glyph::SF510000),
+    ("SF520000", Glyph::SF520000),
+    ("SF530000", Glyph::SF530000),
+    ("SF540000", Glyph::SF540000),
+    ("SS", Glyph::SS),
+    ("SSsmall", Glyph::SSsmall),
+    ("Sacute", Glyph::Sacute),
+    ("Sacutedotaccent", Glyph::Sacutedotaccent),
+    ("Sampigreek", Glyph::Sampigreek),
+    ("Scaron", Glyph::Scaron),
+    ("Scarondotaccent", Glyph::Scarondotaccent),
+    ("Scaronsmall", Glyph::Scaronsmall),
+    ("Scedilla", Glyph::Scedilla),
+    ("Schwa", Glyph::Schwa),
+    ("Schwacyrillic", Glyph::Schwacyrillic),
+    ("Schwadieresiscyrillic", Glyph::Schwadieresiscyrillic),
+    ("Scircle", Glyph::Scircle),
+    ("Scircumflex", Glyph::Scircumflex),
+    ("Scommaaccent", Glyph::Scommaaccent),
+    ("Sdotaccent", Glyph::Sdotaccent),
+    ("Sdotbelow", Glyph::Sdotbelow),
+    ("Sdotbelowdotaccent", Glyph::Sdotbelowdotaccent),
+    ("Seharmenian", Glyph::Seharmenian),
+    ("Sevenroman", Glyph::Sevenroman),
+    ("Shaarmenian", Glyph::Shaarmenian),
+    ("Shacyrillic", Glyph::Shacyrillic),
+    ("Shchacyrillic", Glyph::Shchacyrillic),
+    ("Sheicoptic", Glyph::Sheicoptic),
+    ("Shhacyrillic", Glyph::Shhacyrillic),
+    ("Shimacoptic", Glyph::Shimacoptic),
+    ("Sigma", Glyph::Sigma),
+    ("Sixroman", Glyph::Sixroman),
+    ("Smonospace", Glyph::Smonospace),
+    ("Softsigncyrillic", Glyph::Softsigncyrillic),
+    ("Ssmall", Glyph::Ssmall),
+    ("Stigmagreek", Glyph::Stigmagreek),
+    ("T", Glyph::T),
+    ("Tau", Glyph::Tau),
+    ("Tbar", Glyph::Tbar),
+    ("Tcaron", Glyph::Tcaron),
+    ("Tcedilla", Glyph::Tcedilla),
+    ("Tcircle", Glyph::Tcircle),
+    ("Tcircumflexbelow", Glyph::Tcircumflexbelow),
+    ("Tcommaaccent", Glyph::Tcommaaccent),
+    ("Tdotaccent", Glyph::Tdotaccent),
+    ("Tdotbelow", Glyph::Tdotbelow),
+    ("Tecyrillic", Glyph::Tecyrillic),
+    ("Tedescendercyrillic", Glyph::Tedescendercyrillic),
+    ("Tenroman", Glyph::Tenroman),
+    ("Tetsecyrillic", Glyph::Tetsecyrillic),
+    ("Theta", Glyph::Theta),
+    ("Thook", Glyph::Thook),
+    ("Thorn", Glyph::Thorn),
+    ("Thornsmall", Glyph::Thornsmall),
+    ("Threeroman", Glyph::Threeroman),
+    ("Tildesmall", Glyph::Tildesmall),
+    ("Tiwnarmenian", Glyph::Tiwnarmenian),
+    ("Tlinebelow", Glyph::Tlinebelow),
+    ("Tmonospace", Glyph::Tmonospace),
+    ("Toarmenian", Glyph::Toarmenian),
+    ("Tonefive", Glyph::Tonefive),
+    ("Tonesix", Glyph::Tonesix),
+    ("Tonetwo", Glyph::Tonetwo),
+    ("Tretroflexhook", Glyph::Tretroflexhook),
+    ("Tsecyrillic", Glyph::Tsecyrillic),
+    ("Tshecyrillic", Glyph::Tshecyrillic),
+    ("Tsmall", Glyph::Tsmall),
+    ("Twelveroman", Glyph::Twelveroman),
+    ("Tworoman", Glyph::Tworoman),
+    ("U", Glyph::U),
+    ("Uacute", Glyph::Uacute),
+    ("Uacutesmall", Glyph::Uacutesmall),
+    ("Ubreve", Glyph::Ubreve),
+    ("Ucaron", Glyph::Ucaron),
+    ("Ucircle", Glyph::Ucircle),
+    ("Ucircumflex", Glyph::Ucircumflex),
+    ("Ucircumflexbelow", Glyph::Ucircumflexbelow),
+    ("Ucircumflexsmall", Glyph::Ucircumflexsmall),
+    ("Ucyrillic", Glyph::Ucyrillic),
+    ("Udblacute", Glyph::Udblacute),
+    ("Udblgrave", Glyph::Udblgrave),
+    ("Udieresis", Glyph::Udieresis),
+    ("Udieresisacute", Glyph::Udieresisacute),
+    ("Udieresisbelow", Glyph::Udieresisbelow),
+    ("Udieresiscaron", Glyph::Udieresiscaron),
+    ("Udieresiscyrillic", Glyph::Udieresiscyrillic),
+    ("Udieresisgrave", Glyph::Udieresisgrave),
+    ("Udieresismacron", Glyph::Udieresismacron),
+    ("Udieresissmall", Glyph::Udieresissmall),
+    ("Udotbelow", Glyph::Udotbelow),
+    ("Ugrave", Glyph::Ugrave),
+    ("Ugravesmall", Glyph::Ugravesmall),
+    ("Uhookabove", Glyph::Uhookabove),
+    ("Uhorn", Glyph::Uhorn),
+    ("Uhornacute", Glyph::Uhornacute),
+    ("Uhorndotbelow", Glyph::Uhorndotbelow),
+    ("Uhorngrave", Glyph::Uhorngrave),
+    ("Uhornhookabove", Glyph::Uhornhookabove),
+    ("Uhorntilde", Glyph::Uhorntilde),
+    ("Uhungarumlaut", Glyph::Uhungarumlaut),
+    ("Uhungarumlautcyrillic", Glyph::Uhungarumlautcyrillic),
+    ("Uinvertedbreve", Glyph::Uinvertedbreve),
+    ("Ukcyrillic", Glyph::Ukcyrillic),
+    ("Umacron", Glyph::Umacron),
+    ("Umacroncyrillic", Glyph::Umacroncyrillic),
+    ("Umacrondieresis", Glyph::Umacrondieresis),
+    ("Umonospace", Glyph::Umonospace),
+    ("Uogonek", Glyph::Uogonek),
+    ("Upsilon", Glyph::Upsilon),
+    ("Upsilon1", Glyph::Upsilon1),
+    ("Upsilonacutehooksymbolgreek", Glyph::Upsilonacutehooksymbolgreek),
+    ("Upsilonafrican", Glyph::Upsilonafrican),
+    ("Upsilondieresis", Glyph::Upsilondieresis),
+    ("Upsilondieresishooksymbolgreek", Glyph::Upsilondieresishooksymbolgreek),
+    ("Upsilonhooksymbol", Glyph::Upsilonhooksymbol),
+    ("Upsilontonos", Glyph::Upsilontonos),
+    ("Uring", Glyph::Uring),
+    ("Ushortcyrillic", Glyph::Ushortcyrillic),
+    ("Usmall", Glyph::Usmall),
+    ("Ustraightcyrillic", Glyph::Ustraightcyrillic),
+    ("Ustraightstrokecyrillic", Glyph::Ustraightstrokecyrillic),
+    ("Utilde", Glyph::Utilde),
+    ("Utildeacute", Glyph::Utildeacute),
+    ("Utildebelow", Glyph::Utildebelow),
+    ("V", Glyph::V),
+    ("Vcircle", Glyph::Vcircle),
+    ("Vdotbelow", Glyph::Vdotbelow),
+    ("Vecyrillic", Glyph::Vecyrillic),
+    ("Vewarmenian", Glyph::Vewarmenian),
+    ("Vhook", Glyph::Vhook),
+    ("Vmonospace", Glyph::Vmonospace),
+    ("Voarmenian", Glyph::Voarmenian),
+    ("Vsmall", Glyph::Vsmall),
+    ("Vtilde", Glyph::Vtilde),
+    ("W", Glyph::W),
+    ("Wacute", Glyph::Wacute),
+    ("Wcircle", Glyph::Wcircle),
+    ("Wcircumflex", Glyph::Wcircumflex),
+    ("Wdieresis", Glyph::Wdieresis),
+    ("Wdotaccent", Glyph::Wdotaccent),
+    ("Wdotbelow", Glyph::Wdotbelow),
+    ("Wgrave", Glyph::Wgrave),
+    ("Wmonospace", Glyph::Wmonospace),
+    ("Wsmall", Glyph::Wsmall),
+    ("X", Glyph::X),
+    ("Xcircle", Glyph::Xcircle),
+    ("Xdieresis", Glyph::Xdieresis),
+    ("Xdotaccent", Glyph::Xdotaccent),
+    ("Xeharmenian", Glyph::Xeharmenian),
+    ("Xi", Glyph::Xi),
+    ("Xmonospace", Glyph::Xmonospace),
+    ("Xsmall", Glyph::Xsmall),
+    ("Y", Glyph::Y),
+    ("Yacute", Glyph::Yacute),
+    ("Yacutesmall", Glyph::Yacutesmall),
+    ("Yatcyrillic", Glyph::Yatcyrillic),
+    ("Ycircle", Glyph::Ycircle),
+    ("Ycircumflex", Glyph::Ycircumflex),
+    ("Ydieresis", Glyph::Ydieresis),
+    ("Ydieresissmall", Glyph::Ydieresissmall),
+    ("Ydotaccent", Glyph::Ydotaccent),
+    ("Ydotbelow", Glyph::Ydotbelow),
+    ("Yericyrillic", Glyph::Yericyrillic),
+    ("Yerudieresiscyrillic", Glyph::Yerudieresiscyrillic),
+    ("Ygrave", Glyph::Ygrave),
+    ("Yhook", Glyph::Yhook),
+    ("Yhookabove", Glyph::Yhookabove),
+    ("Yiarmenian", Glyph::Yiarmenian),
+    ("Yicyrillic", Glyph::Yicyrillic),
+    ("Yiwnarmenian", Glyph::Yiwnarmenian),
+    ("Ymonospace", Glyph::Ymonospace),
+    ("Ysmall", Glyph::Ysmall),
+    ("Ytilde", Glyph::Ytilde),
+    ("Yusbigcyrillic", Glyph::Yusbigcyrillic),
+    ("Yusbigiotifiedcyrillic", Glyph::Yusbigiotifiedcyrillic),
+    ("Yuslittlecyrillic", Glyph::Yuslittlecyrillic),
+    ("Yuslittleiotifiedcyrillic", Glyph::Yuslittleiotifiedcyrillic),
+    ("Z", Glyph::Z),
+    ("Zaarmenian", Glyph::Zaarmenian),
+    ("Zacute", Glyph::Zacute),
+    ("Zcaron", Glyph::Zcaron),
+    ("Zcaronsmall", Glyph::Zcaronsmall),
+    ("Zcircle", Glyph::Zcircle),
+    ("Zcircumflex", Glyph::Zcircumflex),
+    ("Zdot", Glyph::Zdot),
+    ("Zdotaccent", Glyph::Zdotaccent),
+    ("Zdotbelow", Glyph::Zdotbelow),
+    ("Zecyrillic", Glyph::Zecyrillic),
+    ("Zedescendercyrillic", Glyph::Zedescendercyrillic),
+    ("Zedieresiscyrillic", Glyph::Zedieresiscyrillic),
+    ("Zeta", Glyph::Zeta),
+    ("Zhearmenian", Glyph::Zhearmenian),
+    ("Zhebrevecyrillic", Glyph::Zhebrevecyrillic),
+    ("Zhecyrillic", Glyph::Zhecyrillic),
+    ("Zhedescendercyrillic", Glyph::Zhedescendercyrillic),
+    ("Zhedieresiscyrillic", Glyph::Zhedieresiscyrillic),
+    ("Zlinebelow", Glyph::Zlinebelow),
+    ("Zmonospace", Glyph::Zmonospace),
+    ("Zsmall", Glyph::Zsmall),
+    ("Zstroke", Glyph::Zstroke),
+    ("a", Glyph::a),
+    ("aabengali", Glyph::aabengali),
+    ("aacute", Glyph::aacute),
+    ("aadeva", Glyph::aadeva),
+    ("aagujarati", Glyph::aagujarati),
+    ("aagurmukhi", Glyph::aagurmukhi),
+    ("aamatragurmukhi", Glyph::aamatragurmukhi),
+    ("aarusquare", Glyph::aarusquare),
+    ("aavowelsignbengali", Glyph::aavowelsignbengali),
+    ("aavowelsigndeva", Glyph::aavowelsigndeva),
+    ("aavowelsigngujarati", Glyph::aavowelsigngujarati),
+    ("abbreviationmarkarmenian", Glyph::abbreviationmarkarmenian),
+    ("abbreviationsigndeva", Glyph::abbreviationsigndeva),
+    ("abengali", Glyph::abengali),
+    ("abopomofo", Glyph::abopomofo),
+    ("abreve", Glyph::abreve),
+    ("abreveacute", Glyph::abreveacute),
+    ("abrevecyrillic", Glyph::abrevecyrillic),
+    ("abrevedotbelow", Glyph::abrevedotbelow),
+    ("abrevegrave", Glyph::abrevegrave),
+    ("abrevehookabove", Glyph::abrevehookabove),
+    ("abrevetilde", Glyph::abrevetilde),
+    ("acaron", Glyph::acaron),
+    ("acircle", Glyph::acircle),
+    ("acircumflex", Glyph::acircumflex),
+    ("acircumflexacute", Glyph::acircumflexacute),
+    ("acircumflexdotbelow", Glyph::acircumflexdotbelow),
+    ("acircumflexgrave", Glyph::acircumflexgrave),
+    ("acircumflexhookabove", Glyph::acircumflexhookabove),
+    ("acircumflextilde", Glyph::acircumflextilde),
+    ("acute", Glyph::acute),
+    ("acutebelowcmb", Glyph::acutebelowcmb),
+    ("acutecmb", Glyph::acutecmb),
+    ("acutecomb", Glyph::acutecomb),
+    ("acutedeva", Glyph::acutedeva),
+    ("acutelowmod", Glyph::acutelowmod),
+    ("acutetonecmb", Glyph::acutetonecmb),
+    ("acyrillic", Glyph::acyrillic),
+    ("adblgrave", Glyph::adblgrave),
+    ("addakgurmukhi", Glyph::addakgurmukhi),
+    ("adeva", Glyph::adeva),
+    ("adieresis", Glyph::adieresis),
+    ("adieresiscyrillic", Glyph::adieresiscyrillic),
+    ("adieresismacron", Glyph::adieresismacron),
+    ("adotbelow", Glyph::adotbelow),
+    ("adotmacron", Glyph::adotmacron),
+    ("ae", Glyph::ae),
+    ("aeacute", Glyph::aeacute),
+    ("aekorean", Glyph::aekorean),
+    ("aemacron", Glyph::aemacron),
+    ("afii00208", Glyph::afii00208),
+    ("afii08941", Glyph::afii08941),
+    ("afii10017", Glyph::afii10017),
+    ("afii10018", Glyph::afii10018),
+    ("afii10019", Glyph::afii10019),
+    ("afii10020", Glyph::afii10020),
+    ("afii10021", Glyph::afii10021),
+    ("afii10022", Glyph::afii10022),
+    ("afii10023", Glyph::afii10023),
+    ("afii10024", Glyph::afii10024),
+    ("afii10025", Glyph::afii10025),
+    ("afii10026", Glyph::afii10026),
+    ("afii10027", Glyph::afii10027),
+    ("afii10028", Glyph::afii10028),
+    ("afii10029", Glyph::afii10029),
+    ("afii10030", Glyph::afii10030),
+    ("afii10031", Glyph::afii10031),
+    ("afii10032", Glyph::afii10032),
+    ("afii10033", Glyph::afii10033),
+    ("afii10034", Glyph::afii10034),
+    ("afii10035", Glyph::afii10035),
+    ("afii10036", Glyph::afii10036),
+    ("afii10037", Glyph::afii10037),
+    ("afii10038", Glyph::afii10038),
+    ("afii10039", Glyph::afii10039),
+    ("afii10040", Glyph::afii10040),
+    ("afii10041", Glyph::afii10041),
+    ("afii10042", Glyph::afii10042),
+    ("afii10043", Glyph::afii10043),
+    ("afii10044", Glyph::afii10044),
+    ("afii10045", Glyph::afii10045),
+    ("afii10046", Glyph::afii10046),
+    ("afii10047", Glyph::afii10047),
+    ("afii10048", Glyph::afii10048),
+    ("afii10049", Glyph::afii10049),
+    ("afii10050", Glyph::afii10050),
+    ("afii10051", Glyph::afii10051),
+    ("afii10052", Glyph::afii10052),
+    ("afii10053", Glyph::afii10053),
+    ("afii10054", Glyph::afii10054),
+    ("afii10055", Glyph::afii10055),
+    ("afii10056", Glyph::afii10056),
+    ("afii10057", Glyph::afii10057),
+    ("afii10058", Glyph::afii10058),
+    ("afii10059", Glyph::afii10059),
+    ("afii10060", Glyph::afii10060),
+    ("afii10061", Glyph::afii10061),
+    ("afii10062", Glyph::afii10062),
+    ("afii10063", Glyph::afii10063),
+    ("afii10064", Glyph::afii10064),
+    ("afii10065", Glyph::afii10065),
+    ("afii10066", Glyph::afii10066),
+    ("afii10067", Glyph::afii10067),
+    ("afii10068", Glyph::afii10068),
+    ("afii10069", Glyph::afii10069),
+    ("afii10070", Glyph::afii10070),
+    ("afii10071", Glyph::afii10071),
+    ("afii10072", Glyph::afii10072),
+    ("afii10073", Glyph::afii10073),
+    ("afii10074", Glyph::afii10074),
+    ("afii10075", Glyph::afii10075),
+    ("afii10076", Glyph::afii10076),
+    ("afii10077", Glyph::afii10077),
+    ("afii10078", Glyph::afii10078),
+    ("afii10079", Glyph::afii10079),
+    ("afii10080", Glyph::afii10080),
+    ("afii10081", Glyph::afii10081),
+    ("afii10082", Glyph::afii10082),
+    ("afii10083", Glyph::afii10083),
+    ("afii10084", Glyph::afii10084),
+    ("afii10085", Glyph::afii10085),
+    ("afii10086", Glyph::afii10086),
+    ("afii10087", Glyph::afii10087),
+    ("afii10088", Glyph::afii10088),
+    ("afii10089", Glyph::afii10089),
+    ("afii10090", Glyph::afii10090),
+    ("afii10091", Glyph::afii10091),
+    ("afii10092", Glyph::afii10092),
+    ("afii10093", Glyph::afii10093),
+    ("afii10094", Glyph::afii10094),
+    ("afii10095", Glyph::afii10095),
+    ("afii10096", Glyph::afii10096),
+    ("afii10097", Glyph::afii10097),
+    ("afii10098", Glyph::afii10098),
+    ("afii10099", Glyph::afii10099),
+    ("afii10100", Glyph::afii10100),
+    ("afii10101", Glyph::afii10101),
+    ("afii10102", Glyph::afii10102),
+    ("afii10103", Glyph::afii10103),
+    ("afii10104", Glyph::afii10104),
+    ("afii10105", Glyph::afii10105),
+    ("afii10106", Glyph::afii10106),
+    ("afii10107", Glyph::afii10107),
+    ("afii10108", Glyph::afii10108),
+    ("afii10109", Glyph::afii10109),
+    ("afii10110", Glyph::afii10110),
+    ("afii10145", Glyph::afii10145),
+    ("afii10146", Glyph::afii10146),
+    ("afii10147", Glyph::afii10147),
+    ("afii10148", Glyph::afii10148),
+    ("afii10192", Glyph::afii10192),
+    ("afii10193", Glyph::afii10193),
+    ("afii10194", Glyph::afii10194),
+    ("afii10195", Glyph::afii10195),
+    ("afii10196", Glyph::afii10196),
+    ("afii10831", Glyph::afii10831),
+    ("afii10832", Glyph::afii10832),
+    ("afii10846", Glyph::afii10846),
+    ("afii299", Glyph::afii299),
+    ("afii300", Glyph::afii300),
+    ("afii301", Glyph::afii301),
+    ("afii57381", Glyph::afii57381),
+    ("afii57388", Glyph::afii57388),
+    ("afii57392", Glyph::afii57392),
+    ("afii57393", Glyph::afii57393),
+    ("afii57394", Glyph::afii57394),
+    ("afii57395", Glyph::afii57395),
+    ("afii57396", Glyph::afii57396),
+    ("afii57397", Glyph::afii57397),
+    ("afii57398", Glyph::afii57398),
+    ("afii57399", Glyph::afii57399),
+    ("afii57400", Glyph::afii57400),
+    ("afii57401", Glyph::afii57401),
+    ("afii57403", Glyph::afii57403),
+    ("afii57407", Glyph::afii57407),
+    ("afii57409", Glyph::afii57409),
+    ("afii57410", Glyph::afii57410),
+    ("afii57411", Glyph::afii57411),
+    ("afii57412", Glyph::afii57412),
+    ("afii57413", Glyph::afii57413),
+    ("afii57414", Glyph::afii57414),
+    ("afii57415", Glyph::afii57415),
+    ("afii57416", Glyph::afii57416),
+    ("afii57417", Glyph::afii57417),
+    ("afii57418", Glyph::afii57418),
+    ("afii57419", Glyph::afii57419),
+    ("afii57420", Glyph::afii57420),
+    ("afii57421", Glyph::afii57421),
+    ("afii57422", Glyph::afii57422),
+    ("afii57423", Glyph::afii57423),
+    ("afii57424", Glyph::afii57424),
+    ("afii57425", Glyph::afii57425),
+    ("afii57426", Glyph::afii57426),
+    ("afii57427", Glyph::afii57427),
+    ("afii57428", Glyph::afii57428),
+    ("afii57429", Glyph::afii57429),
+    ("afii57430", Glyph::afii57430),
+    ("afii57431", Glyph::afii57431),
+    ("afii57432", Glyph::afii57432),
+    ("afii57433", Glyph::afii57433),
+    ("afii57434", Glyph::afii57434),
+    ("afii57440", Glyph::afii57440),
+    ("afii57441", Glyph::afii57441),
+    ("afii57442", Glyph::afii57442),
+    ("afii57443", Glyph::afii57443),
+    ("afii57444", Glyph::afii57444),
+    ("afii57445", Glyph::afii57445),
+    ("afii57446", Glyph::afii57446),
+    ("afii57448", Glyph::afii57448),
+    ("afii57449", Glyph::afii57449),
+    ("afii57450", Glyph::afii57450),
+    ("afii57451", Glyph::afii57451),
+    ("afii57452", Glyph::afii57452),
+    ("afii57453", Glyph::afii57453),
+    ("afii57454", Glyph::afii57454),
+    ("afii57455", Glyph::afii57455),
+    ("afii57456", Glyph::afii57456),
+    ("afii57457", Glyph::afii57457),
+    ("afii57458", Glyph::afii57458),
+    ("afii57470", Glyph::afii57470),
+    ("afii57505", Glyph::afii57505),
+    ("afii57506", Glyph::afii57506),
+    ("afii57507", Glyph::afii57507),
+    ("afii57508", Glyph::afii57508),
+    ("afii57509", Glyph::afii57509),
+    ("afii57511", Glyph::afii57511),
+    ("afii57512", Glyph::afii57512),
+    ("afii57513", Glyph::afii57513),
+    ("afii57514", Glyph::afii57514),
+    ("afii57519", Glyph::afii57519),
+    ("afii57534", Glyph::afii57534),
+    ("afii57636", Glyph::afii57636),
+    ("afii57645", Glyph::afii57645),
+    ("afii57658", Glyph::afii57658),
+    ("afii57664", Glyph::afii57664),
+    ("afii57665", Glyph::afii57665),
+    ("afii57666", Glyph::afii57666),
+    ("afii57667", Glyph::afii57667),
+    ("afii57668", Glyph::afii57668),
+    ("afii57669", Glyph::afii57669),
+    ("afii57670", Glyph::afii57670),
+    ("afii57671", Glyph::afii57671),
+    ("afii57672", Glyph::afii57672),
+    ("afii57673", Glyph::afii57673),
+    ("afii57674", Glyph::afii57674),
+    ("afii57675", Glyph::afii57675),
+    ("afii57676", Glyph::afii57676),
+    ("afii57677", Glyph::afii57677),
+    ("afii57678", Glyph::afii57678),
+    ("afii57679", Glyph::afii57679),
+    ("afii57680", Glyph::afii57680),
+    ("afii57681", Glyph::afii57681),
+    ("afii57682", Glyph::afii57682),
+    ("afii57683", Glyph::afii57683),
+    ("afii57684", Glyph::afii57684),
+    ("afii57685", Glyph::afii57685),
+    ("afii57686", Glyph::afii57686),
+    ("afii57687", Glyph::afii57687),
+    ("afii57688", Glyph::afii57688),
+    ("afii57689", Glyph::afii57689),
+    ("afii57690", Glyph::afii57690),
+    ("afii57694", Glyph::afii57694),
+    ("afii57695", Glyph::afii57695),
+    ("afii57700", Glyph::afii57700),
+    ("afii57705", Glyph::afii57705),
+    ("afii57716", Glyph::afii57716),
+    ("afii57717", Glyph::afii57717),
+    ("afii57718", Glyph::afii57718),
+    ("afii57723", Glyph::afii57723),
+    ("afii57793", Glyph::afii57793),
+    ("afii57794", Glyph::afii57794),
+    ("afii57795", Glyph::afii57795),
+    ("afii57796", Glyph::afii57796),
+    ("afii57797", Glyph::afii57797),
+    ("afii57798", Glyph::afii57798),
+    ("afii57799", Glyph::afii57799),
+    ("afii57800", Glyph::afii57800),
+    ("afii57801", Glyph::afii57801),
+    ("afii57802", Glyph::afii57802),
+    ("afii57803", Glyph::afii57803),
+    ("afii57804", Glyph::afii57804),
+    ("afii57806", Glyph::afii57806),
+    ("afii57807", Glyph::afii57807),
+    ("afii57839", Glyph::afii57839),
+    ("afii57841", Glyph::afii57841),
+    ("afii57842", Glyph::afii57842),
+    ("afii57929", Glyph::afii57929),
+    ("afii61248", Glyph::afii61248),
+    ("afii61289", Glyph::afii61289),
+    ("afii61352", Glyph::afii61352),
+    ("afii61573", Glyph::afii61573),
+    ("afii61574", Glyph::afii61574),
+    ("afii61575", Glyph::afii61575),
+    ("afii61664", Glyph::afii61664),
+    ("afii63167", Glyph::afii63167),
+    ("afii64937", Glyph::afii64937),
+    ("agrave", Glyph::agrave),
+    ("agujarati", Glyph::agujarati),
+    ("agurmukhi", Glyph::agurmukhi),
+    ("ahiragana", Glyph::ahiragana),
+    ("ahookabove", Glyph::ahookabove),
+    ("aibengali", Glyph::aibengali),
+    ("aibopomofo", Glyph::aibopomofo),
+    ("aideva", Glyph::aideva),
+    ("aiecyrillic", Glyph::aiecyrillic),
+    ("aigujarati", Glyph::aigujarati),
+    ("aigurmukhi", Glyph::aigurmukhi),
+    ("aimatragurmukhi", Glyph::aimatragurmukhi),
+    ("ainarabic", Glyph::ainarabic),
+    ("ainfinalarabic", Glyph::ainfinalarabic),
+    ("aininitialarabic", Glyph::aininitialarabic),
+    ("ainmedialarabic", Glyph::ainmedialarabic),
+    ("ainvertedbreve", Glyph::ainvertedbreve),
+    ("aivowelsignbengali", Glyph::aivowelsignbengali),
+    ("aivowelsigndeva", Glyph::aivowelsigndeva),
+    ("aivowelsigngujarati", Glyph::aivowelsigngujarati),
+    ("akatakana", Glyph::akatakana),
+    ("akatakanahalfwidth", Glyph::akatakanahalfwidth),
+    ("akorean", Glyph::akorean),
+    ("alef", Glyph::alef),
+    ("alefarabic", Glyph::alefarabic),
+    ("alefdageshhebrew", Glyph::alefdageshhebrew),
+    ("aleffinalarabic", Glyph::aleffinalarabic),
+    ("alefhamzaabovearabic", Glyph::alefhamzaabovearabic),
+    ("alefhamzaabovefinalarabic", Glyph::alefhamzaabovefinalarabic),
+    ("alefhamzabelowarabic", Glyph::alefhamzabelowarabic),
+    ("alefhamzabelowfinalarabic", Glyph::alefhamzabelowfinalarabic),
+    ("alefhebrew", Glyph::alefhebrew),
+    ("aleflamedhebrew", Glyph::aleflamedhebrew),
+    ("alefmaddaabovearabic", Glyph::alefmaddaabovearabic),
+    ("alefmaddaabovefinalarabic", Glyph::alefmaddaabovefinalarabic),
+    ("alefmaksuraarabic", Glyph::alefmaksuraarabic),
+    ("alefmaksurafinalarabic", Glyph::alefmaksurafinalarabic),
+    ("alefmaksurainitialarabic", Glyph::alefmaksurainitialarabic),
+    ("alefmaksuramedialarabic", Glyph::alefmaksuramedialarabic),
+    ("alefpatahhebrew", Glyph::alefpatahhebrew),
+    ("alefqamatshebrew", Glyph::alefqamatshebrew),
+    ("aleph", Glyph::aleph),
+    ("allequal", Glyph::allequal),
+    ("alpha", Glyph::alpha),
+    ("alphatonos", Glyph::alphatonos),
+    ("altselector", Glyph::altselector),
+    ("amacron", Glyph::amacron),
+    ("amonospace", Glyph::amonospace),
+    ("ampersand", Glyph::ampersand),
+    ("ampersandmonospace", Glyph::ampersandmonospace),
+    ("ampersandsmall", Glyph::ampersandsmall),
+    ("amsquare", Glyph::amsquare),
+    ("anbopomofo", Glyph::anbopomofo),
+    ("angbopomofo", Glyph::angbopomofo),
+    ("angbracketleft", Glyph::angbracketleft),
+    ("angbracketright", Glyph::angbracketright),
+    ("angkhankhuthai", Glyph::angkhankhuthai),
+    ("angle", Glyph::angle),
+    ("anglebracketleft", Glyph::anglebracketleft),
+    ("anglebracketleftvertical", Glyph::anglebracketleftvertical),
+    ("anglebracketright", Glyph::anglebracketright),
+    ("anglebracketrightvertical", Glyph::anglebracketrightvertical),
+    ("angleleft", Glyph::angleleft),
+    ("angleleftBig", Glyph::angleleftBig),
+    ("angleleftBigg", Glyph::angleleftBigg),
+    ("angleleftbig", Glyph::angleleftbig),
+    ("angleleftbigg", Glyph::angleleftbigg),
+    ("angleright", Glyph::angleright),
+    ("anglerightBig", Glyph::anglerightBig),
+    ("anglerightBigg", Glyph::anglerightBigg),
+    ("anglerightbig", Glyph::anglerightbig),
+    ("anglerightbigg", Glyph::anglerightbigg),
+    ("angstrom", Glyph::angstrom),
+    ("anoteleia", Glyph::anoteleia),
+    ("anudattadeva", Glyph::anudattadeva),
+    ("anusvarabengali", Glyph::anusvarabengali),
+    ("anusvaradeva", Glyph::anusvaradeva),
+    ("anusvaragujarati", Glyph::anusvaragujarati),
+    ("aogonek", Glyph::aogonek),
+    ("apaatosquare", Glyph::apaatosquare),
+    ("aparen", Glyph::aparen),
+    ("apostrophearmenian", Glyph::apostrophearmenian),
+    ("apostrophemod", Glyph::apostrophemod),
+    ("apple", Glyph::apple),
+    ("approaches", Glyph::approaches),
+    ("approxequal", Glyph::approxequal),
+    ("approxequalorimage", Glyph::approxequalorimage),
+    ("approximatelyequal", Glyph::approximatelyequal),
+    ("araeaekorean", Glyph::araeaekorean),
+    ("araeakorean", Glyph::araeakorean),
+    ("arc", Glyph::arc),
+    ("arighthalfring", Glyph::arighthalfring),
+    ("aring", Glyph::aring),
+    ("aringacute", Glyph::aringacute),
+    ("aringbelow", Glyph::aringbelow),
+    ("arrowboth", Glyph::arrowboth),
+    ("arrowbothv", Glyph::arrowbothv),
+    ("arrowbt", Glyph::arrowbt),
+    ("arrowdashdown", Glyph::arrowdashdown),
+    ("arrowdashleft", Glyph::arrowdashleft),
+    ("arrowdashright", Glyph::arrowdashright),
+    ("arrowdashup", Glyph::arrowdashup),
+    ("arrowdblboth", Glyph::arrowdblboth),
+    ("arrowdblbothv", Glyph::arrowdblbothv),
+    ("arrowdbldown", Glyph::arrowdbldown),
+    ("arrowdblleft", Glyph::arrowdblleft),
+    ("arrowdblright", Glyph::arrowdblright),
+    ("arrowdbltp", Glyph::arrowdbltp),
+    ("arrowdblup", Glyph::arrowdblup),
+    ("arrowdblvertex", Glyph::arrowdblvertex),
+    ("arrowdown", Glyph::arrowdown),
+    ("arrowdownleft", Glyph::arrowdownleft),
+    ("arrowdownright", Glyph::arrowdownright),
+    ("arrowdownwhite", Glyph::arrowdownwhite),
+    ("arrowheaddownmod", Glyph::arrowheaddownmod),
+    ("arrowheadleftmod", Glyph::arrowheadleftmod),
+    ("arrowheadrightmod", Glyph::arrowheadrightmod),
+    ("arrowheadupmod", Glyph::arrowheadupmod),
+    ("arrowhorizex", Glyph::arrowhorizex),
+    ("arrowleft", Glyph::arrowleft),
+    ("arrowleftbothalf", Glyph::arrowleftbothalf),
+    ("arrowleftdbl", Glyph::arrowleftdbl),
+    ("arrowleftdblstroke", Glyph::arrowleftdblstroke),
+    ("arrowleftoverright", Glyph::arrowleftoverright),
+    ("arrowlefttophalf", Glyph::arrowlefttophalf),
+    ("arrowleftwhite", Glyph::arrowleftwhite),
+    ("arrownortheast", Glyph::arrownortheast),
+    ("arrownorthwest", Glyph::arrownorthwest),
+    ("arrowright", Glyph::arrowright),
+    ("arrowrightbothalf", Glyph::arrowrightbothalf),
+    ("arrowrightdblstroke", Glyph::arrowrightdblstroke),
+    ("arrowrightheavy", Glyph::arrowrightheavy),
+    ("arrowrightoverleft", Glyph::arrowrightoverleft),
+    ("arrowrighttophalf", Glyph::arrowrighttophalf),
+    ("arrowrightwhite", Glyph::arrowrightwhite),
+    ("arrowsoutheast", Glyph::arrowsoutheast),
+    ("arrowsouthwest", Glyph::arrowsouthwest),
+    ("arrowtableft", Glyph::arrowtableft),
+    ("arrowtabright", Glyph::arrowtabright),
+    ("arrowtp", Glyph::arrowtp),
+    ("arrowup", Glyph::arrowup),
+    ("arrowupdn", Glyph::arrowupdn),
+    ("arrowupdnbse", Glyph::arrowupdnbse),
+    ("arrowupdownbase", Glyph::arrowupdownbase),
+    ("arrowupleft", Glyph::arrowupleft),
+    ("arrowupleftofdown", Glyph::arrowupleftofdown),
+    ("arrowupright", Glyph::arrowupright),
+    ("arrowupwhite", Glyph::arrowupwhite),
+    ("arrowvertex", Glyph::arrowvertex),
+    ("arrowvertex2", Glyph::arrowvertex2),
+    ("ascendercompwordmark", Glyph::ascendercompwordmark),
+    ("asciicircum", Glyph::asciicircum),
+    ("asciicircummonospace", Glyph::asciicircummonospace),
+    ("asciitilde", Glyph::asciitilde),
+    ("asciitildemonospace", Glyph::asciitildemonospace),
+    ("ascript", Glyph::ascript),
+    ("ascriptturned", Glyph::ascriptturned),
+    ("asmallhiragana", Glyph::asmallhiragana),
+    ("asmallkatakana", Glyph::asmallkatakana),
+    ("asmallkatakanahalfwidth", Glyph::asmallkatakanahalfwidth),
+    ("asterisk", Glyph::asterisk),
+    ("asteriskaltonearabic", Glyph::asteriskaltonearabic),
+    ("asteriskarabic", Glyph::asteriskarabic),
+    ("asteriskcentered", Glyph::asteriskcentered),
+    ("asteriskmath", Glyph::asteriskmath),
+    ("asteriskmonospace", Glyph::asteriskmonospace),
+    ("asterisksmall", Glyph::asterisksmall),
+    ("asterism", Glyph::asterism),
+    ("asuperior", Glyph::asuperior),
+    ("asymptoticallyequal", Glyph::asymptoticallyequal),
+    ("at", Glyph::at),
+    ("atilde", Glyph::atilde),
+    ("atmonospace", Glyph::atmonospace),
+    ("atsmall", Glyph::atsmall),
+    ("aturned", Glyph::aturned),
+    ("aubengali", Glyph::aubengali),
+    ("aubopomofo", Glyph::aubopomofo),
+    ("audeva", Glyph::audeva),
+    ("augujarati", Glyph::augujarati),
+    ("augurmukhi", Glyph::augurmukhi),
+    ("aulengthmarkbengali", Glyph::aulengthmarkbengali),
+    ("aumatragurmukhi", Glyph::aumatragurmukhi),
+    ("auvowelsignbengali", Glyph::auvowelsignbengali),
+    ("auvowelsigndeva", Glyph::auvowelsigndeva),
+    ("auvowelsigngujarati", Glyph::auvowelsigngujarati),
+    ("avagrahadeva", Glyph::avagrahadeva),
+    ("aybarmenian", Glyph::aybarmenian),
+    ("ayin", Glyph::ayin),
+    ("ayinaltonehebrew", Glyph::ayinaltonehebrew),
+    ("ayinhebrew", Glyph::ayinhebrew),
+    ("b", Glyph::b),
+    ("babengali", Glyph::babengali),
+    ("backslash", Glyph::backslash),
+    ("backslashBig", Glyph::backslashBig),
+    ("backslashBigg", Glyph::backslashBigg),
+    ("backslashbig", Glyph::backslashbig),
+    ("backslashbigg", Glyph::backslashbigg),
+    ("backslashmonospace", Glyph::backslashmonospace),
+    ("badeva", Glyph::badeva),
+    ("bagujarati", Glyph::bagujarati),
+    ("bagurmukhi", Glyph::bagurmukhi),
+    ("bahiragana", Glyph::bahiragana),
+    ("bahtthai", Glyph::bahtthai),
+    ("bakatakana", Glyph::bakatakana),
+    ("bar", Glyph::bar),
+    ("bardbl", Glyph::bardbl),
+    ("bardblex", Glyph::bardblex),
+    ("barex", Glyph::barex),
+    ("barmonospace", Glyph::barmonospace),
+    ("bbopomofo", Glyph::bbopomofo),
+    ("bcircle", Glyph::bcircle),
+    ("bdotaccent", Glyph::bdotaccent),
+    ("bdotbelow", Glyph::bdotbelow),
+    ("beamedsixteenthnotes", Glyph::beamedsixteenthnotes),
+    ("because", Glyph::because),
+    ("becyrillic", Glyph::becyrillic),
+    ("beharabic", Glyph::beharabic),
+    ("behfinalarabic", Glyph::behfinalarabic),
+    ("behinitialarabic", Glyph::behinitialarabic),
+    ("behiragana", Glyph::behiragana),
+    ("behmedialarabic", Glyph::behmedialarabic),
+    ("behmeeminitialarabic", Glyph::behmeeminitialarabic),
+    ("behmeemisolatedarabic", Glyph::behmeemisolatedarabic),
+    ("behnoonfinalarabic", Glyph::behnoonfinalarabic),
+    ("bekatakana", Glyph::bekatakana),
+    ("benarmenian", Glyph::benarmenian),
+    ("bet", Glyph::bet),
+    ("beta", Glyph::beta),
+    ("betasymbolgreek", Glyph::betasymbolgreek),
+    ("betdagesh", Glyph::betdagesh),
+    ("betdageshhebrew", Glyph::betdageshhebrew),
+    ("bethebrew", Glyph::bethebrew),
+    ("betrafehebrew", Glyph::betrafehebrew),
+    ("bhabengali", Glyph::bhabengali),
+    ("bhadeva", Glyph::bhadeva),
+    ("bhagujarati", Glyph::bhagujarati),
+    ("bhagurmukhi", Glyph::bhagurmukhi),
+    ("bhook", Glyph::bhook),
+    ("bihiragana", Glyph::bihiragana),
+    ("bikatakana", Glyph::bikatakana),
+    ("bilabialclick", Glyph::bilabialclick),
+    ("bindigurmukhi", Glyph::bindigurmukhi),
+    ("birusquare", Glyph::birusquare),
+    ("blackcircle", Glyph::blackcircle),
+    ("blackdiamond", Glyph::blackdiamond),
+    ("blackdownpointingtriangle", Glyph::blackdownpointingtriangle),
+    ("blackleftpointingpointer", Glyph::blackleftpointingpointer),
+    ("blackleftpointingtriangle", Glyph::blackleftpointingtriangle),
+    ("blacklenticularbracketleft", Glyph::blacklenticularbracketleft),
+    (
+        "blacklenticularbracketleftvertical",
+        Glyph::blacklenticularbracketleftvertical,
+    ),
+    ("blacklenticularbracketright", Glyph::blacklenticularbracketright),
+    (
+        "blacklenticularbracketrightvertical",
+        Glyph::blacklenticularbracketrightvertical,
+    ),
+    ("blacklowerlefttriangle", Glyph::blacklowerlefttriangle),
+    ("blacklowerrighttriangle", Glyph::blacklowerrighttriangle),
+    ("blackrectangle", Glyph::blackrectangle),
+    ("blackrightpointingpointer", Glyph::blackrightpointingpointer),
+    ("blackrightpointingtriangle", Glyph::blackrightpointingtriangle),
+    ("blacksmallsquare", Glyph::blacksmallsquare),
+    ("blacksmilingface", Glyph::blacksmilingface),
+    ("blacksquare", Glyph::blacksquare),
+    ("blackstar", Glyph::blackstar),
+    ("blackupperlefttriangle", Glyph::blackupperlefttriangle),
+    ("blackupperrighttriangle", Glyph::blackupperrighttriangle),
+    ("blackuppointingsmalltriangle", Glyph::blackuppointingsmalltriangle),
+    ("blackuppointingtriangle", Glyph::blackuppointingtriangle),
+    ("blank", Glyph::blank),
+    ("blinebelow", Glyph::blinebelow),
+    ("block", Glyph::block),
+    ("bmonospace", Glyph::bmonospace),
+    ("bobaimaithai", Glyph::bobaimaithai),
+    ("bohiragana", Glyph::bohiragana),
+    ("bokatakana", Glyph::bokatakana),
+    ("bparen", Glyph::bparen),
+    ("bqsquare", Glyph::bqsquare),
+    ("braceex", Glyph::braceex),
+    ("braceex2", Glyph::braceex2),
+    ("braceleft", Glyph::braceleft),
+    ("braceleftBig", Glyph::braceleftBig),
+    ("braceleftBigg", Glyph::braceleftBigg),
+    ("braceleftbig", Glyph::braceleftbig),
+    ("braceleftbigg", Glyph::braceleftbigg),
+    ("braceleftbt", Glyph::braceleftbt),
+    ("braceleftmid", Glyph::braceleftmid),
+    ("braceleftmid2", Glyph::braceleftmid2),
+    ("braceleftmonospace", Glyph::braceleftmonospace),
+    ("braceleftsmall", Glyph::braceleftsmall),
+    ("bracelefttp", Glyph::bracelefttp),
+    ("braceleftvertical", Glyph::braceleftvertical),
+    ("braceright", Glyph::braceright),
+    ("bracerightBig", Glyph::bracerightBig),
+    ("bracerightBigg", Glyph::bracerightBigg),
+    ("bracerightbig", Glyph::bracerightbig),
+    ("bracerightbigg", Glyph::bracerightbigg),
+    ("bracerightbt", Glyph::bracerightbt),
+    ("bracerightmid", Glyph::bracerightmid),
+    ("bracerightmid2", Glyph::bracerightmid2),
+    ("bracerightmonospace", Glyph::bracerightmonospace),
+    ("bracerightsmall", Glyph::bracerightsmall),
+    ("bracerighttp", Glyph::bracerighttp),
+    ("bracerightvertical", Glyph::bracerightvertical),
+    ("bracketleft", Glyph::bracketleft),
+    ("bracketleftBig", Glyph::bracketleftBig),
+    ("bracketleftBigg", Glyph::bracketleftBigg),
+    ("bracketleftbig", Glyph::bracketleftbig),
+    ("bracketleftbigg", Glyph::bracketleftbigg),
+    ("bracketleftbt", Glyph::bracketleftbt),
+    ("bracketleftex", Glyph::bracketleftex),
+    ("bracketleftmonospace", Glyph::bracketleftmonospace),
+    ("bracketlefttp", Glyph::bracketlefttp),
+    ("bracketright", Glyph::bracketright),
+    ("bracketrightBig", Glyph::bracketrightBig),
+    ("bracketrightBigg", Glyph::bracketrightBigg),
+    ("bracketrightbig", Glyph::bracketrightbig),
+    ("bracketrightbigg", Glyph::bracketrightbigg),
+    ("bracketrightbt", Glyph::bracketrightbt),
+    ("bracketrightex", Glyph::bracketrightex),
+    ("bracketrightmonospace", Glyph::bracketrightmonospace),
+    ("bracketrighttp", Glyph::bracketrighttp),
+    ("breve", Glyph::breve),
+    ("brevebelowcmb", Glyph::brevebelowcmb),
+    ("brevecmb", Glyph::brevecmb),
+    ("breveinvertedbelowcmb", Glyph::breveinvertedbelowcmb),
+    ("breveinvertedcmb", Glyph::breveinvertedcmb),
+    ("breveinverteddoublecmb", Glyph::breveinverteddoublecmb),
+    ("bridgebelowcmb", Glyph::bridgebelowcmb),
+    ("bridgeinvertedbelowcmb", Glyph::bridgeinvertedbelowcmb),
+    ("brokenbar", Glyph::brokenbar),
+    ("bstroke", Glyph::bstroke),
+    ("bsuperior", Glyph::bsuperior),
+    ("btopbar", Glyph::btopbar),
+    ("buhiragana", Glyph::buhiragana),
+    ("bukatakana", Glyph::bukatakana),
+    ("bullet", Glyph::bullet),
+    ("bulletinverse", Glyph::bulletinverse),
+    ("bulletoperator", Glyph::bulletoperator),
+    ("bullseye", Glyph::bullseye),
+    ("c", Glyph::c),
+    ("caarmenian", Glyph::caarmenian),
+    ("cabengali", Glyph::cabengali),
+    ("cacute", Glyph::cacute),
+    ("cadeva", Glyph::cadeva),
+    ("cagujarati", Glyph::cagujarati),
+    ("cagurmukhi", Glyph::cagurmukhi),
+    ("calsquare", Glyph::calsquare),
+    ("candrabindubengali", Glyph::candrabindubengali),
+    ("candrabinducmb", Glyph::candrabinducmb),
+    ("candrabindudeva", Glyph::candrabindudeva),
+    ("candrabindugujarati", Glyph::candrabindugujarati),
+    ("capitalcompwordmark", Glyph::capitalcompwordmark),
+    ("capslock", Glyph::capslock),
+    ("careof", Glyph::careof),
+    ("caron", Glyph::caron),
+    ("caronbelowcmb", Glyph::caronbelowcmb),
+    ("caroncmb", Glyph::caroncmb),
+    ("carriagereturn", Glyph::carriagereturn),
+    ("cbopomofo", Glyph::cbopomofo),
+    ("ccaron", Glyph::ccaron),
+    ("ccedilla", Glyph::ccedilla),
+    ("ccedillaacute", Glyph::ccedillaacute),
+    ("ccircle", Glyph::ccircle),
+    ("ccircumflex", Glyph::ccircumflex),
+    ("ccurl", Glyph::ccurl),
+    ("cdot", Glyph::cdot),
+    ("cdotaccent", Glyph::cdotaccent),
+    ("cdsquare", Glyph::cdsquare),
+    ("cedilla", Glyph::cedilla),
+    ("cedillacmb", Glyph::cedillacmb),
+    ("ceilingleft", Glyph::ceilingleft),
+    ("ceilingleftBig", Glyph::ceilingleftBig),
+    ("ceilingleftBigg", Glyph::ceilingleftBigg),
+    ("ceilingleftbig", Glyph::ceilingleftbig),
+    ("ceilingleftbigg", Glyph::ceilingleftbigg),
+    ("ceilingright", Glyph::ceilingright),
+    ("ceilingrightBig", Glyph::ceilingrightBig),
+    ("ceilingrightBigg", Glyph::ceilingrightBigg),
+    ("ceilingrightbig", Glyph::ceilingrightbig),
+    ("ceilingrightbigg", Glyph::ceilingrightbigg),
+    ("cent", Glyph::cent),
+    ("centigrade", Glyph::centigrade),
+    ("centinferior", Glyph::centinferior),
+    ("centmonospace", Glyph::centmonospace),
+    ("centoldstyle", Glyph::centoldstyle),
+    ("centsuperior", Glyph::centsuperior),
+    ("chaarmenian", Glyph::chaarmenian),
+    ("chabengali", Glyph::chabengali),
+    ("chadeva", Glyph::chadeva),
+    ("chagujarati", Glyph::chagujarati),
+    ("chagurmukhi", Glyph::chagurmukhi),
+    ("chbopomofo", Glyph::chbopomofo),
+    ("cheabkhasiancyrillic", Glyph::cheabkhasiancyrillic),
+    ("checkmark", Glyph::checkmark),
+    ("checyrillic", Glyph::checyrillic),
+    ("chedescenderabkhasiancyrillic", Glyph::chedescenderabkhasiancyrillic),
+    ("chedescendercyrillic", Glyph::chedescendercyrillic),
+    ("chedieresiscyrillic", Glyph::chedieresiscyrillic),
+    ("cheharmenian", Glyph::cheharmenian),
+    ("chekhakassiancyrillic", Glyph::chekhakassiancyrillic),
+    ("cheverticalstrokecyrillic", Glyph::cheverticalstrokecyrillic),
+    ("chi", Glyph::chi),
+    ("chieuchacirclekorean", Glyph::chieuchacirclekorean),
+    ("chieuchaparenkorean", Glyph::chieuchaparenkorean),
+    ("chieuchcirclekorean", Glyph::chieuchcirclekorean),
+    ("chieuchkorean", Glyph::chieuchkorean),
+    ("chieuchparenkorean", Glyph::chieuchparenkorean),
+    ("chochangthai", Glyph::chochangthai),
+    ("chochanthai", Glyph::chochanthai),
+    ("chochingthai", Glyph::chochingthai),
+    ("chochoethai", Glyph::chochoethai),
+    ("chook", Glyph::chook),
+    ("cieucacirclekorean", Glyph::cieucacirclekorean),
+    ("cieucaparenkorean", Glyph::cieucaparenkorean),
+    ("cieuccirclekorean", Glyph::cieuccirclekorean),
+    ("cieuckorean", Glyph::cieuckorean),
+    ("cieucparenkorean", Glyph::cieucparenkorean),
+    ("cieucuparenkorean", Glyph::cieucuparenkorean),
+    ("circle", Glyph::circle),
+    ("circlecopyrt", Glyph::circlecopyrt),
+    ("circledivide", Glyph::circledivide),
+    ("circledot", Glyph::circledot),
+    ("circledotdisplay", Glyph::circledotdisplay),
+    ("circledottext", Glyph::circledottext),
+    ("circleminus", Glyph::circleminus),
+    ("circlemultiply", Glyph::circlemultiply),
+    ("circlemultiplydisplay", Glyph::circlemultiplydisplay),
+    ("circlemultiplytext", Glyph::circlemultiplytext),
+    ("circleot", Glyph::circleot),
+    ("circleplus", Glyph::circleplus),
+    ("circleplusdisplay", Glyph::circleplusdisplay),
+    ("circleplustext", Glyph::circleplustext),
+    ("circlepostalmark", Glyph::circlepostalmark),
+    ("circlewithlefthalfblack", Glyph::circlewithlefthalfblack),
+    ("circlewithrighthalfblack", Glyph::circlewithrighthalfblack),
+    ("circumflex", Glyph::circumflex),
+    ("circumflexbelowcmb", Glyph::circumflexbelowcmb),
+    ("circumflexcmb", Glyph::circumflexcmb),
+    ("clear", Glyph::clear),
+    ("clickalveolar", Glyph::clickalveolar),
+    ("clickdental", Glyph::clickdental),
+    ("clicklateral", Glyph::clicklateral),
+    ("clickretroflex", Glyph::clickretroflex),
+    ("club", Glyph::club),
+    ("clubsuitblack", Glyph::clubsuitblack),
+    ("clubsuitwhite", Glyph::clubsuitwhite),
+    ("cmcubedsquare", Glyph::cmcubedsquare),
+    ("cmonospace", Glyph::cmonospace),
+    ("cmsquaredsquare", Glyph::cmsquaredsquare),
+    ("coarmenian", Glyph::coarmenian),
+    ("colon", Glyph::colon),
+    ("colonmonetary", Glyph::colonmonetary),
+    ("colonmonospace", Glyph::colonmonospace),
+    ("colonsign", Glyph::colonsign),
+    ("colonsmall", Glyph::colonsmall),
+    ("colontriangularhalfmod", Glyph::colontriangularhalfmod),
+    ("colontriangularmod", Glyph::colontriangularmod),
+    ("comma", Glyph::comma),
+    ("commaabovecmb", Glyph::commaabovecmb),
+    ("commaaboverightcmb", Glyph::commaaboverightcmb),
+    ("commaaccent", Glyph::commaaccent),
+    ("commaarabic", Glyph::commaarabic),
+    ("commaarmenian", Glyph::commaarmenian),
+    ("commainferior", Glyph::commainferior),
+    ("commamonospace", Glyph::commamonospace),
+    ("commareversedabovecmb", Glyph::commareversedabovecmb),
+    ("commareversedmod", Glyph::commareversedmod),
+    ("commasmall", Glyph::commasmall),
+    ("commasuperior", Glyph::commasuperior),
+    ("commaturnedabovecmb", Glyph::commaturnedabovecmb),
+    ("commaturnedmod", Glyph::commaturnedmod),
+    ("compass", Glyph::compass),
+    ("compwordmark", Glyph::compwordmark),
+    ("congruent", Glyph::congruent),
+    ("contintegraldisplay", Glyph::contintegraldisplay),
+    ("contintegraltext", Glyph::contintegraltext),
+    ("contourintegral", Glyph::contourintegral),
+    ("control", Glyph::control),
+    ("controlACK", Glyph::controlACK),
+    ("controlBEL", Glyph::controlBEL),
+    ("controlBS", Glyph::controlBS),
+    ("controlCAN", Glyph::controlCAN),
+    ("controlCR", Glyph::controlCR),
+    ("controlDC1", Glyph::controlDC1),
+    ("controlDC2", Glyph::controlDC2),
+    ("controlDC3", Glyph::controlDC3),
+    ("controlDC4", Glyph::controlDC4),
+    ("controlDEL", Glyph::controlDEL),
+    ("controlDLE", Glyph::controlDLE),
+    ("controlEM", Glyph::controlEM),
+    ("controlENQ", Glyph::controlENQ),
+    ("controlEOT", Glyph::controlEOT),
+    ("controlESC", Glyph::controlESC),
+    ("controlETB", Glyph::controlETB),
+    ("controlETX", Glyph::controlETX),
+    ("controlFF", Glyph::controlFF),
+    ("controlFS", Glyph::controlFS),
+    ("controlGS", Glyph::controlGS),
+    ("controlHT", Glyph::controlHT),
+    ("controlLF", Glyph::controlLF),
+    ("controlNAK", Glyph::controlNAK),
+    ("controlRS", Glyph::controlRS),
+    ("controlSI", Glyph::controlSI),
+    ("controlSO", Glyph::controlSO),
+    ("controlSOT", Glyph::controlSOT),
+    ("controlSTX", Glyph::controlSTX),
+    ("controlSUB", Glyph::controlSUB),
+    ("controlSYN", Glyph::controlSYN),
+    ("controlUS", Glyph::controlUS),
+    ("controlVT", Glyph::controlVT),
+    ("coproduct", Glyph::coproduct),
+    ("coproductdisplay", Glyph::coproductdisplay),
+    ("coproducttext", Glyph::coproducttext),
+    ("copyright", Glyph::copyright),
+    ("copyrightsans", Glyph::copyrightsans),
+    ("copyrightserif", Glyph::copyrightserif),
+    ("cornerbracketleft", Glyph::cornerbracketleft),
+    ("cornerbracketlefthalfwidth", Glyph::cornerbracketlefthalfwidth),
+    ("cornerbracketleftvertical", Glyph::cornerbracketleftvertical),
+    ("cornerbracketright", Glyph::cornerbracketright),
+    ("cornerbracketrighthalfwidth", Glyph::cornerbracketrighthalfwidth),
+    ("cornerbracketrightvertical", Glyph::cornerbracketrightvertical),
+    ("corporationsquare", Glyph::corporationsquare),
+    ("cosquare", Glyph::cosquare),
+    ("coverkgsquare", Glyph::coverkgsquare),
+    ("cparen", Glyph::cparen),
+    ("cruzeiro", Glyph::cruzeiro),
+    ("cstretched", Glyph::cstretched),
+    ("curlyand", Glyph::curlyand),
+    ("curlyor", Glyph::curlyor),
+    ("currency", Glyph::currency),
+    ("cwm", Glyph::cwm),
+    ("cyrBreve", Glyph::cyrBreve),
+    ("cyrFlex", Glyph::cyrFlex),
+    ("cyrbreve", Glyph::cyrbreve),
+    ("cyrflex", Glyph::cyrflex),
+    ("d", Glyph::d),
+    ("daarmenian", Glyph::daarmenian),
+    ("dabengali", Glyph::dabengali),
+    ("dadarabic", Glyph::dadarabic),
+    ("dadeva", Glyph::dadeva),
+    ("dadfinalarabic", Glyph::dadfinalarabic),
+    ("dadinitialarabic", Glyph::dadinitialarabic),
+    ("dadmedialarabic", Glyph::dadmedialarabic),
+    ("dagesh", Glyph::dagesh),
+    ("dageshhebrew", Glyph::dageshhebrew),
+    ("dagger", Glyph::dagger),
+    ("daggerdbl", Glyph::daggerdbl),
+    ("dagujarati", Glyph::dagujarati),
+    ("dagurmukhi", Glyph::dagurmukhi),
+    ("dahiragana", Glyph::dahiragana),
+    ("dakatakana", Glyph::dakatakana),
+    ("dalarabic", Glyph::dalarabic),
+    ("dalet", Glyph::dalet),
+    ("daletdagesh", Glyph::daletdagesh),
+    ("daletdageshhebrew", Glyph::daletdageshhebrew),
+    ("dalethatafpatah", Glyph::dalethatafpatah),
+    ("dalethatafpatahhebrew", Glyph::dalethatafpatahhebrew),
+    ("dalethatafsegol", Glyph::dalethatafsegol),
+    ("dalethatafsegolhebrew", Glyph::dalethatafsegolhebrew),
+    ("dalethebrew", Glyph::dalethebrew),
+    ("dalethiriq", Glyph::dalethiriq),
+    ("dalethiriqhebrew", Glyph::dalethiriqhebrew),
+    ("daletholam", Glyph::daletholam),
+    ("daletholamhebrew", Glyph::daletholamhebrew),
+    ("daletpatah", Glyph::daletpatah),
+    ("daletpatahhebrew", Glyph::daletpatahhebrew),
+    ("daletqamats", Glyph::daletqamats),
+    ("daletqamatshebrew", Glyph::daletqamatshebrew),
+    ("daletqubuts", Glyph::daletqubuts),
+    ("daletqubutshebrew", Glyph::daletqubutshebrew),
+    ("daletsegol", Glyph::daletsegol),
+    ("daletsegolhebrew", Glyph::daletsegolhebrew),
+    ("daletsheva", Glyph::daletsheva),
+    ("daletshevahebrew", Glyph::daletshevahebrew),
+    ("dalettsere", Glyph::dalettsere),
+    ("dalettserehebrew", Glyph::dalettserehebrew),
+    ("dalfinalarabic", Glyph::dalfinalarabic),
+    ("dammaarabic", Glyph::dammaarabic),
+    ("dammalowarabic", Glyph::dammalowarabic),
+    ("dammatanaltonearabic", Glyph::dammatanaltonearabic),
+    ("dammatanarabic", Glyph::dammatanarabic),
+    ("danda", Glyph::danda),
+    ("dargahebrew", Glyph::dargahebrew),
+    ("dargalefthebrew", Glyph::dargalefthebrew),
+    ("dasiapneumatacyrilliccmb", Glyph::dasiapneumatacyrilliccmb),
+    ("dbar", Glyph::dbar),
+    ("dblGrave", Glyph::dblGrave),
+    ("dblanglebracketleft", Glyph::dblanglebracketleft),
+    ("dblanglebracketleftvertical", Glyph::dblanglebracketleftvertical),
+    ("dblanglebracketright", Glyph::dblanglebracketright),
+    ("dblanglebracketrightvertical", Glyph::dblanglebracketrightvertical),
+    ("dblarchinvertedbelowcmb", Glyph::dblarchinvertedbelowcmb),
+    ("dblarrowleft", Glyph::dblarrowleft),
+    ("dblarrowright", Glyph::dblarrowright),
+    ("dblbracketleft", Glyph::dblbracketleft),
+    ("dblbracketright", Glyph::dblbracketright),
+    ("dbldanda", Glyph::dbldanda),
+    ("dblgrave", Glyph::dblgrave),
+    ("dblgravecmb", Glyph::dblgravecmb),
+    ("dblintegral", Glyph::dblintegral),
+    ("dbllowline", Glyph::dbllowline),
+    ("dbllowlinecmb", Glyph::dbllowlinecmb),
+    ("dbloverlinecmb", Glyph::dbloverlinecmb),
+    ("dblprimemod", Glyph::dblprimemod),
+    ("dblverticalbar", Glyph::dblverticalbar),
+    ("dblverticallineabovecmb", Glyph::dblverticallineabovecmb),
+    ("dbopomofo", Glyph::dbopomofo),
+    ("dbsquare", Glyph::dbsquare),
+    ("dcaron", Glyph::dcaron),
+    ("dcedilla", Glyph::dcedilla),
+    ("dcircle", Glyph::dcircle),
+    ("dcircumflexbelow", Glyph::dcircumflexbelow),
+    ("dcroat", Glyph::dcroat),
+    ("ddabengali", Glyph::ddabengali),
+    ("ddadeva", Glyph::ddadeva),
+    ("ddagujarati", Glyph::ddagujarati),
+    ("ddagurmukhi", Glyph::ddagurmukhi),
+    ("ddalarabic", Glyph::ddalarabic),
+    ("ddalfinalarabic", Glyph::ddalfinalarabic),
+    ("dddhadeva", Glyph::dddhadeva),
+    ("ddhabengali", Glyph::ddhabengali),
+    ("ddhadeva", Glyph::ddhadeva),
+    ("ddhagujarati", Glyph::ddhagujarati),
+    ("ddhagurmukhi", Glyph::ddhagurmukhi),
+    ("ddotaccent", Glyph::ddotaccent),
+    ("ddotbelow", Glyph::ddotbelow),
+    ("decimalseparatorarabic", Glyph::decimalseparatorarabic),
+    ("decimalseparatorpersian", Glyph::decimalseparatorpersian),
+    ("decyrillic", Glyph::decyrillic),
+    ("degree", Glyph::degree),
+    ("dehihebrew", Glyph::dehihebrew),
+    ("dehiragana", Glyph::dehiragana),
+    ("deicoptic", Glyph::deicoptic),
+    ("dekatakana", Glyph::dekatakana),
+    ("deleteleft", Glyph::deleteleft),
+    ("deleteright", Glyph::deleteright),
+    ("delta", Glyph::delta),
+    ("deltaturned", Glyph::deltaturned),
+    (
+        "denominatorminusonenumeratorbengali",
+        Glyph::denominatorminusonenumeratorbengali,
+    ),
+    ("dezh", Glyph::dezh),
+    ("dhabengali", Glyph::dhabengali),
+    ("dhadeva", Glyph::dhadeva),
+    ("dhagujarati", Glyph::dhagujarati),
+    ("dhagurmukhi", Glyph::dhagurmukhi),
+    ("dhook", Glyph::dhook),
+    ("dialytikatonos", Glyph::dialytikatonos),
+    ("dialytikatonoscmb", Glyph::dialytikatonoscmb),
+    ("diamond", Glyph::diamond),
+    ("diamond2", Glyph::diamond2),
+    ("diamondmath", Glyph::diamondmath),
+    ("diamondsuitwhite", Glyph::diamondsuitwhite),
+    ("dieresis", Glyph::dieresis),
+    ("dieresisacute", Glyph::dieresisacute),
+    ("dieresisbelowcmb", Glyph::dieresisbelowcmb),
+    ("dieresiscmb", Glyph::dieresiscmb),
+    ("dieresisgrave", Glyph::dieresisgrave),
+    ("dieresistonos", Glyph::dieresistonos),
+    ("dihiragana", Glyph::dihiragana),
+    ("dikatakana", Glyph::dikatakana),
+    ("dittomark", Glyph::dittomark),
+    ("divide", Glyph::divide),
+    ("divides", Glyph::divides),
+    ("divisionslash", Glyph::divisionslash),
+    ("djecyrillic", Glyph::djecyrillic),
+    ("dkshade", Glyph::dkshade),
+    ("dlinebelow", Glyph::dlinebelow),
+    ("dlsquare", Glyph::dlsquare),
+    ("dmacron", Glyph::dmacron),
+    ("dmonospace", Glyph::dmonospace),
+    ("dnblock", Glyph::dnblock),
+    ("dochadathai", Glyph::dochadathai),
+    ("dodekthai", Glyph::dodekthai),
+    ("dohiragana", Glyph::dohiragana),
+    ("dokatakana", Glyph::dokatakana),
+    ("dollar", Glyph::dollar),
+    ("dollarinferior", Glyph::dollarinferior),
+    ("dollarmonospace", Glyph::dollarmonospace),
+    ("dollaroldstyle", Glyph::dollaroldstyle),
+    ("dollarsmall", Glyph::dollarsmall),
+    ("dollarsuperior", Glyph::dollarsuperior),
+    ("dong", Glyph::dong),
+    ("dorusquare", Glyph::dorusquare),
+    ("dotaccent", Glyph::dotaccent),
+    ("dotaccentcmb", Glyph::dotaccentcmb),
+    ("dotbelowcmb", Glyph::dotbelowcmb),
+    ("dotbelowcomb", Glyph::dotbelowcomb),
+    ("dotkatakana", Glyph::dotkatakana),
+    ("dotlessi", Glyph::dotlessi),
+    ("dotlessj", Glyph::dotlessj),
+    ("dotlessj2", Glyph::dotlessj2),
+    ("dotlessjstrokehook", Glyph::dotlessjstrokehook),
+    ("dotmath", Glyph::dotmath),
+    ("dottedcircle", Glyph::dottedcircle),
+    ("doubleyodpatah", Glyph::doubleyodpatah),
+    ("doubleyodpatahhebrew", Glyph::doubleyodpatahhebrew),
+    ("downtackbelowcmb", Glyph::downtackbelowcmb),
+    ("downtackmod", Glyph::downtackmod),
+    ("dparen", Glyph::dparen),
+    ("dsuperior", Glyph::dsuperior),
+    ("dtail", Glyph::dtail),
+    ("dtopbar", Glyph::dtopbar),
+    ("duhiragana", Glyph::duhiragana),
+    ("dukatakana", Glyph::dukatakana),
+    ("dz", Glyph::dz),
+    ("dzaltone", Glyph::dzaltone),
+    ("dzcaron", Glyph::dzcaron),
+    ("dzcurl", Glyph::dzcurl),
+    ("dzeabkhasiancyrillic", Glyph::dzeabkhasiancyrillic),
+    ("dzecyrillic", Glyph::dzecyrillic),
+    ("dzhecyrillic", Glyph::dzhecyrillic),
+    ("e", Glyph::e),
+    ("eacute", Glyph::eacute),
+    ("earth", Glyph::earth),
+    ("ebengali", Glyph::ebengali),
+    ("ebopomofo", Glyph::ebopomofo),
+    ("ebreve", Glyph::ebreve),
+    ("ecandradeva", Glyph::ecandradeva),
+    ("ecandragujarati", Glyph::ecandragujarati),
+    ("ecandravowelsigndeva", Glyph::ecandravowelsigndeva),
+    ("ecandravowelsigngujarati", Glyph::ecandravowelsigngujarati),
+    ("ecaron", Glyph::ecaron),
+    ("ecedillabreve", Glyph::ecedillabreve),
+    ("echarmenian", Glyph::echarmenian),
+    ("echyiwnarmenian", Glyph::echyiwnarmenian),
+    ("ecircle", Glyph::ecircle),
+    ("ecircumflex", Glyph::ecircumflex),
+    ("ecircumflexacute", Glyph::ecircumflexacute),
+    ("ecircumflexbelow", Glyph::ecircumflexbelow),
+    ("ecircumflexdotbelow", Glyph::ecircumflexdotbelow),
+    ("ecircumflexgrave", Glyph::ecircumflexgrave),
+    ("ecircumflexhookabove", Glyph::ecircumflexhookabove),
+    ("ecircumflextilde", Glyph::ecircumflextilde),
+    ("ecyrillic", Glyph::ecyrillic),
+    ("edblgrave", Glyph::edblgrave),
+    ("edeva", Glyph::edeva),
+    ("edieresis", Glyph::edieresis),
+    ("edot", Glyph::edot),
+    ("edotaccent", Glyph::edotaccent),
+    ("edotbelow", Glyph::edotbelow),
+    ("eegurmukhi", Glyph::eegurmukhi),
+    ("eematragurmukhi", Glyph::eematragurmukhi),
+    ("efcyrillic", Glyph::efcyrillic),
+    ("egrave", Glyph::egrave),
+    ("egujarati", Glyph::egujarati),
+    ("eharmenian", Glyph::eharmenian),
+    ("ehbopomofo", Glyph::ehbopomofo),
+    ("ehiragana", Glyph::ehiragana),
+    ("ehookabove", Glyph::ehookabove),
+    ("eibopomofo", Glyph::eibopomofo),
+    ("eight", Glyph::eight),
+    ("eightarabic", Glyph::eightarabic),
+    ("eightbengali", Glyph::eightbengali),
+    ("eightcircle", Glyph::eightcircle),
+    ("eightcircleinversesansserif", Glyph::eightcircleinversesansserif),
+    ("eightdeva", Glyph::eightdeva),
+    ("eighteencircle", Glyph::eighteencircle),
+    ("eighteenparen", Glyph::eighteenparen),
+    ("eighteenperiod", Glyph::eighteenperiod),
+    ("eightgujarati", Glyph::eightgujarati),
+    ("eightgurmukhi", Glyph::eightgurmukhi),
+    ("eighthackarabic", Glyph::eighthackarabic),
+    ("eighthangzhou", Glyph::eighthangzhou),
+    ("eighthnotebeamed", Glyph::eighthnotebeamed),
+    ("eightideographicparen", Glyph::eightideographicparen),
+    ("eightinferior", Glyph::eightinferior),
+    ("eightmonospace", Glyph::eightmonospace),
+    ("eightoldstyle", Glyph::eightoldstyle),
+    ("eightparen", Glyph::eightparen),
+    ("eightperiod", Glyph::eightperiod),
+    ("eightpersian", Glyph::eightpersian),
+    ("eightroman", Glyph::eightroman),
+    ("eightsuperior", Glyph::eightsuperior),
+    ("eightthai", Glyph::eightthai),
+    ("einvertedbreve", Glyph::einvertedbreve),
+    ("eiotifiedcyrillic", Glyph::eiotifiedcyrillic),
+    ("ekatakana", Glyph::ekatakana),
+    ("ekatakanahalfwidth", Glyph::ekatakanahalfwidth),
+    ("ekonkargurmukhi", Glyph::ekonkargurmukhi),
+    ("ekorean", Glyph::ekorean),
+    ("elcyrillic", Glyph::elcyrillic),
+    ("element", Glyph::element),
+    ("elevencircle", Glyph::elevencircle),
+    ("elevenparen", Glyph::elevenparen),
+    ("elevenperiod", Glyph::elevenperiod),
+    ("elevenroman", Glyph::elevenroman),
+    ("ellipsis", Glyph::ellipsis),
+    ("ellipsisvertical", Glyph::ellipsisvertical),
+    ("emacron", Glyph::emacron),
+    ("emacronacute", Glyph::emacronacute),
+    ("emacrongrave", Glyph::emacrongrave),
+    ("emcyrillic", Glyph::emcyrillic),
+    ("emdash", Glyph::emdash),
+    ("emdashvertical", Glyph::emdashvertical),
+    ("emonospace", Glyph::emonospace),
+    ("emphasismarkarmenian", Glyph::emphasismarkarmenian),
+    ("emptyset", Glyph::emptyset),
+    ("emptyslot", Glyph::emptyslot),
+    ("enbopomofo", Glyph::enbopomofo),
+    ("encyrillic", Glyph::encyrillic),
+    ("endash", Glyph::endash),
+    ("endashvertical", Glyph::endashvertical),
+    ("endescendercyrillic", Glyph::endescendercyrillic),
+    ("eng", Glyph::eng),
+    ("engbopomofo", Glyph::engbopomofo),
+    ("enghecyrillic", Glyph::enghecyrillic),
+    ("enhookcyrillic", Glyph::enhookcyrillic),
+    ("enspace", Glyph::enspace),
+    ("eogonek", Glyph::eogonek),
+    ("eokorean", Glyph::eokorean),
+    ("eopen", Glyph::eopen),
+    ("eopenclosed", Glyph::eopenclosed),
+    ("eopenreversed", Glyph::eopenreversed),
+    ("eopenreversedclosed", Glyph::eopenreversedclosed),
+    ("eopenreversedhook", Glyph::eopenreversedhook),
+    ("eparen", Glyph::eparen),
+    ("epsilon", Glyph::epsilon),
+    ("epsilon1", Glyph::epsilon1),
+    ("epsilontonos", Glyph::epsilontonos),
+    ("equal", Glyph::equal),
+    ("equalmonospace", Glyph::equalmonospace),
+    ("equalsmall", Glyph::equalsmall),
+    ("equalsuperior", Glyph::equalsuperior),
+    ("equivalence", Glyph::equivalence),
+    ("equivasymptotic", Glyph::equivasymptotic),
+    ("erbopomofo", Glyph::erbopomofo),
+    ("ercyrillic", Glyph::ercyrillic),
+    ("ereversed", Glyph::ereversed),
+    ("ereversedcyrillic", Glyph::ereversedcyrillic),
+    ("escyrillic", Glyph::escyrillic),
+    ("esdescendercyrillic", Glyph::esdescendercyrillic),
+    ("esh", Glyph::esh),
+    ("eshcurl", Glyph::eshcurl),
+    ("eshortdeva", Glyph::eshortdeva),
+    ("eshortvowelsigndeva", Glyph::eshortvowelsigndeva),
+    ("eshreversedloop", Glyph::eshreversedloop),
+    ("eshsquatreversed", Glyph::eshsquatreversed),
+    ("esmallhiragana", Glyph::esmallhiragana),
+    ("esmallkatakana", Glyph::esmallkatakana),
+    ("esmallkatakanahalfwidth", Glyph::esmallkatakanahalfwidth),
+    ("estimated", Glyph::estimated),
+    ("esuperior", Glyph::esuperior),
+    ("eta", Glyph::eta),
+    ("etarmenian", Glyph::etarmenian),
+    ("etatonos", Glyph::etatonos),
+    ("eth", Glyph::eth),
+    ("etilde", Glyph::etilde),
+    ("etildebelow", Glyph::etildebelow),
+    ("etnahtafoukhhebrew", Glyph::etnahtafoukhhebrew),
+    ("etnahtafoukhlefthebrew", Glyph::etnahtafoukhlefthebrew),
+    ("etnahtahebrew", Glyph::etnahtahebrew),
+    ("etnahtalefthebrew", Glyph::etnahtalefthebrew),
+    ("eturned", Glyph::eturned),
+    ("eukorean", Glyph::eukorean),
+    ("euro", Glyph::euro),
+    ("evowelsignbengali", Glyph::evowelsignbengali),
+    ("evowelsigndeva", Glyph::evowelsigndeva),
+    ("evowelsigngujarati", Glyph::evowelsigngujarati),
+    ("exclam", Glyph::exclam),
+    ("exclamarmenian", Glyph::exclamarmenian),
+    ("exclamdbl", Glyph::exclamdbl),
+    ("exclamdown", Glyph::exclamdown),
+    ("exclamdownsmall", Glyph::exclamdownsmall),
+    ("exclammonospace", Glyph::exclammonospace),
+    ("exclamsmall", Glyph::exclamsmall),
+    ("existential", Glyph::existential),
+    ("ezh", Glyph::ezh),
+    ("ezhcaron", Glyph::ezhcaron),
+    ("ezhcurl", Glyph::ezhcurl),
+    ("ezhreversed", Glyph::ezhreversed),
+    ("ezhtail", Glyph::ezhtail),
+    ("f", Glyph::f),
+    ("f_f", Glyph::f_f),
+    ("f_f_i", Glyph::f_f_i),
+    ("f_f_l", Glyph::f_f_l),
+    ("f_i", Glyph::f_i),
+    ("f_l", Glyph::f_l),
+    ("fadeva", Glyph::fadeva),
+    ("fagurmukhi", Glyph::fagurmukhi),
+    ("fahrenheit", Glyph::fahrenheit),
+    ("fathaarabic", Glyph::fathaarabic),
+    ("fathalowarabic", Glyph::fathalowarabic),
+    ("fathatanarabic", Glyph::fathatanarabic),
+    ("fbopomofo", Glyph::fbopomofo),
+    ("fcircle", Glyph::fcircle),
+    ("fdotaccent", Glyph::fdotaccent),
+    ("feharabic", Glyph::feharabic),
+    ("feharmenian", Glyph::feharmenian),
+    ("fehfinalarabic", Glyph::fehfinalarabic),
+    ("fehinitialarabic", Glyph::fehinitialarabic),
+    ("fehmedialarabic", Glyph::fehmedialarabic),
+    ("feicoptic", Glyph::feicoptic),
+    ("female", Glyph::female),
+    ("ff", Glyph::ff),
+    ("ffi", Glyph::ffi),
+    ("ffl", Glyph::ffl),
+    ("fi", Glyph::fi),
+    ("fifteencircle", Glyph::fifteencircle),
+    ("fifteenparen", Glyph::fifteenparen),
+    ("fifteenperiod", Glyph::fifteenperiod),
+    ("figuredash", Glyph::figuredash),
+    ("filledbox", Glyph::filledbox),
+    ("filledrect", Glyph::filledrect),
+    ("finalkaf", Glyph::finalkaf),
+    ("finalkafdagesh", Glyph::finalkafdagesh),
+    ("finalkafdageshhebrew", Glyph::finalkafdageshhebrew),
+    ("finalkafhebrew", Glyph::finalkafhebrew),
+    ("finalkafqamats", Glyph::finalkafqamats),
+    ("finalkafqamatshebrew", Glyph::finalkafqamatshebrew),
+    ("finalkafsheva", Glyph::finalkafsheva),
+    ("finalkafshevahebrew", Glyph::finalkafshevahebrew),
+    ("finalmem", Glyph::finalmem),
+    ("finalmemhebrew", Glyph::finalmemhebrew),
+    ("finalnun", Glyph::finalnun),
+    ("finalnunhebrew", Glyph::finalnunhebrew),
+    ("finalpe", Glyph::finalpe),
+    ("finalpehebrew", Glyph::finalpehebrew),
+    ("finaltsadi", Glyph::finaltsadi),
+    ("finaltsadihebrew", Glyph::finaltsadihebrew),
+    ("firsttonechinese", Glyph::firsttonechinese),
+    ("fisheye", Glyph::fisheye),
+    ("fitacyrillic", Glyph::fitacyrillic),
+    ("five", Glyph::five),
+    ("fivearabic", Glyph::fivearabic),
+    ("fivebengali", Glyph::fivebengali),
+    ("fivecircle", Glyph::fivecircle),
+    ("fivecircleinversesansserif", Glyph::fivecircleinversesansserif),
+    ("fivedeva", Glyph::fivedeva),
+    ("fiveeighths", Glyph::fiveeighths),
+    ("fivegujarati", Glyph::fivegujarati),
+    ("fivegurmukhi", Glyph::fivegurmukhi),
+    ("fivehackarabic", Glyph::fivehackarabic),
+    ("fivehangzhou", Glyph::fivehangzhou),
+    ("fiveideographicparen", Glyph::fiveideographicparen),
+    ("fiveinferior", Glyph::fiveinferior),
+    ("fivemonospace", Glyph::fivemonospace),
+    ("fiveoldstyle", Glyph::fiveoldstyle),
+    ("fiveparen", Glyph::fiveparen),
+    ("fiveperiod", Glyph::fiveperiod),
+    ("fivepersian", Glyph::fivepersian),
+    ("fiveroman", Glyph::fiveroman),
+    ("fivesuperior", Glyph::fivesuperior),
+    ("fivethai", Glyph::fivethai),
+    ("fl", Glyph::fl),
+    ("flat", Glyph::flat),
+    ("floorleft", Glyph::floorleft),
+    ("floorleftBig", Glyph::floorleftBig),
+    ("floorleftBigg", Glyph::floorleftBigg),
+    ("floorleftbig", Glyph::floorleftbig),
+    ("floorleftbigg", Glyph::floorleftbigg),
+    ("floorright", Glyph::floorright),
+    ("floorrightBig", Glyph::floorrightBig),
+    ("floorrightBigg", Glyph::floorrightBigg),
+    ("floorrightbig", Glyph::floorrightbig),
+    ("floorrightbigg", Glyph::floorrightbigg),
+    ("florin", Glyph::florin),
+    ("fmonospace", Glyph::fmonospace),
+    ("fmsquare", Glyph::fmsquare),
+    ("fofanthai", Glyph::fofanthai),
+    ("fofathai", Glyph::fofathai),
+    ("follows", Glyph::follows),
+    ("followsequal", Glyph::followsequal),
+    ("fongmanthai", Glyph::fongmanthai),
+    ("forall", Glyph::forall),
+    ("four", Glyph::four),
+    ("fourarabic", Glyph::fourarabic),
+    ("fourbengali", Glyph::fourbengali),
+    ("fourcircle", Glyph::fourcircle),
+    ("fourcircleinversesansserif", Glyph::fourcircleinversesansserif),
+    ("fourdeva", Glyph::fourdeva),
+    ("fourgujarati", Glyph::fourgujarati),
+    ("fourgurmukhi", Glyph::fourgurmukhi),
+    ("fourhackarabic", Glyph::fourhackarabic),
+    ("fourhangzhou", Glyph::fourhangzhou),
+    ("fourideographicparen", Glyph::fourideographicparen),
+    ("fourinferior", Glyph::fourinferior),
+    ("fourmonospace", Glyph::fourmonospace),
+    ("fournumeratorbengali", Glyph::fournumeratorbengali),
+    ("fouroldstyle", Glyph::fouroldstyle),
+    ("fourparen", Glyph::fourparen),
+    ("fourperiod", Glyph::fourperiod),
+    ("fourpersian", Glyph::fourpersian),
+    ("fourroman", Glyph::fourroman),
+    ("foursuperior", Glyph::foursuperior),
+    ("fourteencircle", Glyph::fourteencircle),
+    ("fourteenparen", Glyph::fourteenparen),
+    ("fourteenperiod", Glyph::fourteenperiod),
+    ("fourthai", Glyph::fourthai),
+    ("fourthtonechinese", Glyph::fourthtonechinese),
+    ("fparen", Glyph::fparen),
+    ("fraction", Glyph::fraction),
+    ("franc", Glyph::franc),
+    ("g", Glyph::g),
+    ("gabengali", Glyph::gabengali),
+    ("gacute", Glyph::gacute),
+    ("gadeva", Glyph::gadeva),
+    ("gafarabic", Glyph::gafarabic),
+    ("gaffinalarabic", Glyph::gaffinalarabic),
+    ("gafinitialarabic", Glyph::gafinitialarabic),
+    ("gafmedialarabic", Glyph::gafmedialarabic),
+    ("gagujarati", Glyph::gagujarati),
+    ("gagurmukhi", Glyph::gagurmukhi),
+    ("gahiragana", Glyph::gahiragana),
+    ("gakatakana", Glyph::gakatakana),
+    ("gamma", Glyph::gamma),
+    ("gammalatinsmall", Glyph::gammalatinsmall),
+    ("gammasuperior", Glyph::gammasuperior),
+    ("gangiacoptic", Glyph::gangiacoptic),
+    ("gbopomofo", Glyph::gbopomofo),
+    ("gbreve", Glyph::gbreve),
+    ("gcaron", Glyph::gcaron),
+    ("gcedilla", Glyph::gcedilla),
+    ("gcircle", Glyph::gcircle),
+    ("gcircumflex", Glyph::gcircumflex),
+    ("gcommaaccent", Glyph::gcommaaccent),
+    ("gdot", Glyph::gdot),
+    ("gdotaccent", Glyph::gdotaccent),
+    ("gecyrillic", Glyph::gecyrillic),
+    ("gehiragana", Glyph::gehiragana),
+    ("gekatakana", Glyph::gekatakana),
+    ("geometricallyequal", Glyph::geometricallyequal),
+    ("gereshaccenthebrew", Glyph::gereshaccenthebrew),
+    ("gereshhebrew", Glyph::gereshhebrew),
+    ("gereshmuqdamhebrew", Glyph::gereshmuqdamhebrew),
+    ("germandbls", Glyph::germandbls),
+    ("gershayimaccenthebrew", Glyph::gershayimaccenthebrew),
+    ("gershayimhebrew", Glyph::gershayimhebrew),
+    ("getamark", Glyph::getamark),
+    ("ghabengali", Glyph::ghabengali),
+    ("ghadarmenian", Glyph::ghadarmenian),
+    ("ghadeva", Glyph::ghadeva),
+    ("ghagujarati", Glyph::ghagujarati),
+    ("ghagurmukhi", Glyph::ghagurmukhi),
+    ("ghainarabic", Glyph::ghainarabic),
+    ("ghainfinalarabic", Glyph::ghainfinalarabic),
+    ("ghaininitialarabic", Glyph::ghaininitialarabic),
+    ("ghainmedialarabic", Glyph::ghainmedialarabic),
+    ("ghemiddlehookcyrillic", Glyph::ghemiddlehookcyrillic),
+    ("ghestrokecyrillic", Glyph::ghestrokecyrillic),
+    ("gheupturncyrillic", Glyph::gheupturncyrillic),
+    ("ghhadeva", Glyph::ghhadeva),
+    ("ghhagurmukhi", Glyph::ghhagurmukhi),
+    ("ghook", Glyph::ghook),
+    ("ghzsquare", Glyph::ghzsquare),
+    ("gihiragana", Glyph::gihiragana),
+    ("gikatakana", Glyph::gikatakana),
+    ("gimarmenian", Glyph::gimarmenian),
+    ("gimel", Glyph::gimel),
+    ("gimeldagesh", Glyph::gimeldagesh),
+    ("gimeldageshhebrew", Glyph::gimeldageshhebrew),
+    ("gimelhebrew", Glyph::gimelhebrew),
+    ("gjecyrillic", Glyph::gjecyrillic),
+    ("glottalinvertedstroke", Glyph::glottalinvertedstroke),
+    ("glottalstop", Glyph::glottalstop),
+    ("glottalstopinverted", Glyph::glottalstopinverted),
+    ("glottalstopmod", Glyph::glottalstopmod),
+    ("glottalstopreversed", Glyph::glottalstopreversed),
+    ("glottalstopreversedmod", Glyph::glottalstopreversedmod),
+    ("glottalstopreversedsuperior", Glyph::glottalstopreversedsuperior),
+    ("glottalstopstroke", Glyph::glottalstopstroke),
+    ("glottalstopstrokereversed", Glyph::glottalstopstrokereversed),
+    ("gmacron", Glyph::gmacron),
+    ("gmonospace", Glyph::gmonospace),
+    ("gohiragana", Glyph::gohiragana),
+    ("gokatakana", Glyph::gokatakana),
+    ("gparen", Glyph::gparen),
+    ("gpasquare", Glyph::gpasquare),
+    ("gradient", Glyph::gradient),
+    ("grave", Glyph::grave),
+    ("gravebelowcmb", Glyph::gravebelowcmb),
+    ("gravecmb", Glyph::gravecmb),
+    ("gravecomb", Glyph::gravecomb),
+    ("gravedeva", Glyph::gravedeva),
+    ("gravelowmod", Glyph::gravelowmod),
+    ("gravemonospace", Glyph::gravemonospace),
+    ("gravetonecmb", Glyph::gravetonecmb),
+    ("greater", Glyph::greater),
+    ("greaterequal", Glyph::greaterequal),
+    ("greaterequalorless", Glyph::greaterequalorless),
+    ("greatermonospace", Glyph::greatermonospace),
+    ("greatermuch", Glyph::greatermuch),
+    ("greaterorequivalent", Glyph::greaterorequivalent),
+    ("greaterorless", Glyph::greaterorless),
+    ("greateroverequal", Glyph::greateroverequal),
+    ("greatersmall", Glyph::greatersmall),
+    ("gscript", Glyph::gscript),
+    ("gstroke", Glyph::gstroke),
+    ("guhiragana", Glyph::guhiragana),
+    ("guillemotleft", Glyph::guillemotleft),
+    ("guillemotright", Glyph::guillemotright),
+    ("guilsinglleft", Glyph::guilsinglleft),
+    ("guilsinglright", Glyph::guilsinglright),
+    ("gukatakana", Glyph::gukatakana),
+    ("guramusquare", Glyph::guramusquare),
+    ("gysquare", Glyph::gysquare),
+    ("h", Glyph::h),
+    ("haabkhasiancyrillic", Glyph::haabkhasiancyrillic),
+    ("haaltonearabic", Glyph::haaltonearabic),
+    ("habengali", Glyph::habengali),
+    ("hadescendercyrillic", Glyph::hadescendercyrillic),
+    ("hadeva", Glyph::hadeva),
+    ("hagujarati", Glyph::hagujarati),
+    ("hagurmukhi", Glyph::hagurmukhi),
+    ("haharabic", Glyph::haharabic),
+    ("hahfinalarabic", Glyph::hahfinalarabic),
+    ("hahinitialarabic", Glyph::hahinitialarabic),
+    ("hahiragana", Glyph::hahiragana),
+    ("hahmedialarabic", Glyph::hahmedialarabic),
+    ("haitusquare", Glyph::haitusquare),
+    ("hakatakana", Glyph::hakatakana),
+    ("hakatakanahalfwidth", Glyph::hakatakanahalfwidth),
+    ("halantgurmukhi", Glyph::halantgurmukhi),
+    ("hamzaarabic", Glyph::hamzaarabic),
+    ("hamzadammaarabic", Glyph::hamzadammaarabic),
+    ("hamzadammatanarabic", Glyph::hamzadammatanarabic),
+    ("hamzafathaarabic", Glyph::hamzafathaarabic),
+    ("hamzafathatanarabic", Glyph::hamzafathatanarabic),
+    ("hamzalowarabic", Glyph::hamzalowarabic),
+    ("hamzalowkasraarabic", Glyph::hamzalowkasraarabic),
+    ("hamzalowkasratanarabic", Glyph::hamzalowkasratanarabic),
+    ("hamzasukunarabic", Glyph::hamzasukunarabic),
+    ("hangulfiller", Glyph::hangulfiller),
+    ("hardsigncyrillic", Glyph::hardsigncyrillic),
+    ("harpoonleftbarbup", Glyph::harpoonleftbarbup),
+    ("harpoonleftdown", Glyph::harpoonleftdown),
+    ("harpoonleftup", Glyph::harpoonleftup),
+    ("harpoonrightbarbup", Glyph::harpoonrightbarbup),
+    ("harpoonrightdown", Glyph::harpoonrightdown),
+    ("harpoonrightup", Glyph::harpoonrightup),
+    ("hasquare", Glyph::hasquare),
+    ("hatafpatah", Glyph::hatafpatah),
+    ("hatafpatah16", Glyph::hatafpatah16),
+    ("hatafpatah23", Glyph::hatafpatah23),
+    ("hatafpatah2f", Glyph::hatafpatah2f),
+    ("hatafpatahhebrew", Glyph::hatafpatahhebrew),
+    ("hatafpatahnarrowhebrew", Glyph::hatafpatahnarrowhebrew),
+    ("hatafpatahquarterhebrew", Glyph::hatafpatahquarterhebrew),
+    ("hatafpatahwidehebrew", Glyph::hatafpatahwidehebrew),
+    ("hatafqamats", Glyph::hatafqamats),
+    ("hatafqamats1b", Glyph::hatafqamats1b),
+    ("hatafqamats28", Glyph::hatafqamats28),
+    ("hatafqamats34", Glyph::hatafqamats34),
+    ("hatafqamatshebrew", Glyph::hatafqamatshebrew),
+    ("hatafqamatsnarrowhebrew", Glyph::hatafqamatsnarrowhebrew),
+    ("hatafqamatsquarterhebrew", Glyph::hatafqamatsquarterhebrew),
+    ("hatafqamatswidehebrew", Glyph::hatafqamatswidehebrew),
+    ("hatafsegol", Glyph::hatafsegol),
+    ("hatafsegol17", Glyph::hatafsegol17),
+    ("hatafsegol24", Glyph::hatafsegol24),
+    ("hatafsegol30", Glyph::hatafsegol30),
+    ("hatafsegolhebrew", Glyph::hatafsegolhebrew),
+    ("hatafsegolnarrowhebrew", Glyph::hatafsegolnarrowhebrew),
+    ("hatafsegolquarterhebrew", Glyph::hatafsegolquarterhebrew),
+    ("hatafsegolwidehebrew", Glyph::hatafsegolwidehebrew),
+    ("hatwide", Glyph::hatwide),
+    ("hatwider", Glyph::hatwider),
+    ("hatwiderr", Glyph::hatwiderr),
+    ("hbar", Glyph::hbar),
+    ("hbopomofo", Glyph::hbopomofo),
+    ("hbrevebelow", Glyph::hbrevebelow),
+    ("hcedilla", Glyph::hcedilla),
+    ("hcircle", Glyph::hcircle),
+    ("hcircumflex", Glyph::hcircumflex),
+    ("hdieresis", Glyph::hdieresis),
+    ("hdotaccent", Glyph::hdotaccent),
+    ("hdotbelow", Glyph::hdotbelow),
+    ("he", Glyph::he),
+    ("heart", Glyph::heart),
+    ("heart2", Glyph::heart2),
+    ("heartsuitblack", Glyph::heartsuitblack),
+    ("heartsuitwhite", Glyph::heartsuitwhite),
+    ("hedagesh", Glyph::hedagesh),
+    ("hedageshhebrew", Glyph::hedageshhebrew),
+    ("hehaltonearabic", Glyph::hehaltonearabic),
+    ("heharabic", Glyph::heharabic),
+    ("hehebrew", Glyph::hehebrew),
+    ("hehfinalaltonearabic", Glyph::hehfinalaltonearabic),
+    ("hehfinalalttwoarabic", Glyph::hehfinalalttwoarabic),
+    ("hehfinalarabic", Glyph::hehfinalarabic),
+    ("hehhamzaabovefinalarabic", Glyph::hehhamzaabovefinalarabic),
+    ("hehhamzaaboveisolatedarabic", Glyph::hehhamzaaboveisolatedarabic),
+    ("hehinitialaltonearabic", Glyph::hehinitialaltonearabic),
+    ("hehinitialarabic", Glyph::hehinitialarabic),
+    ("hehiragana", Glyph::hehiragana),
+    ("hehmedialaltonearabic", Glyph::hehmedialaltonearabic),
+    ("hehmedialarabic", Glyph::hehmedialarabic),
+    ("heiseierasquare", Glyph::heiseierasquare),
+    ("hekatakana", Glyph::hekatakana),
+    ("hekatakanahalfwidth", Glyph::hekatakanahalfwidth),
+    ("hekutaarusquare", Glyph::hekutaarusquare),
+    ("henghook", Glyph::henghook),
+    ("herutusquare", Glyph::herutusquare),
+    ("het", Glyph::het),
+    ("hethebrew", Glyph::hethebrew),
+    ("hhook", Glyph::hhook),
+    ("hhooksuperior", Glyph::hhooksuperior),
+    ("hieuhacirclekorean", Glyph::hieuhacirclekorean),
+    ("hieuhaparenkorean", Glyph::hieuhaparenkorean),
+    ("hieuhcirclekorean", Glyph::hieuhcirclekorean),
+    ("hieuhkorean", Glyph::hieuhkorean),
+    ("hieuhparenkorean", Glyph::hieuhparenkorean),
+    ("hihiragana", Glyph::hihiragana),
+    ("hikatakana", Glyph::hikatakana),
+    ("hikatakanahalfwidth", Glyph::hikatakanahalfwidth),
+    ("hiriq", Glyph::hiriq),
+    ("hiriq14", Glyph::hiriq14),
+    ("hiriq21", Glyph::hiriq21),
+    ("hiriq2d", Glyph::hiriq2d),
+    ("hiriqhebrew", Glyph::hiriqhebrew),
+    ("hiriqnarrowhebrew", Glyph::hiriqnarrowhebrew),
+    ("hiriqquarterhebrew", Glyph::hiriqquarterhebrew),
+    ("hiriqwidehebrew", Glyph::hiriqwidehebrew),
+    ("hlinebelow", Glyph::hlinebelow),
+    ("hmonospace", Glyph::hmonospace),
+    ("hoarmenian", Glyph::hoarmenian),
+    ("hohipthai", Glyph::hohipthai),
+    ("hohiragana", Glyph::hohiragana),
+    ("hokatakana", Glyph::hokatakana),
+    ("hokatakanahalfwidth", Glyph::hokatakanahalfwidth),
+    ("holam", Glyph::holam),
+    ("holam19", Glyph::holam19),
+    ("holam26", Glyph::holam26),
+    ("holam32", Glyph::holam32),
+    ("holamhebrew", Glyph::holamhebrew),
+    ("holamnarrowhebrew", Glyph::holamnarrowhebrew),
+    ("holamquarterhebrew", Glyph::holamquarterhebrew),
+    ("holamwidehebrew", Glyph::holamwidehebrew),
+    ("honokhukthai", Glyph::honokhukthai),
+    ("hookabovecomb", Glyph::hookabovecomb),
+    ("hookcmb", Glyph::hookcmb),
+    ("hookleftchar", Glyph::hookleftchar),
+    ("hookpalatalizedbelowcmb", Glyph::hookpalatalizedbelowcmb),
+    ("hookretroflexbelowcmb", Glyph::hookretroflexbelowcmb),
+    ("hookrightchar", Glyph::hookrightchar),
+    ("hoonsquare", Glyph::hoonsquare),
+    ("horicoptic", Glyph::horicoptic),
+    ("horizontalbar", Glyph::horizontalbar),
+    ("horncmb", Glyph::horncmb),
+    ("hotsprings", Glyph::hotsprings),
+    ("house", Glyph::house),
+    ("hparen", Glyph::hparen),
+    ("hsuperior", Glyph::hsuperior),
+    ("hturned", Glyph::hturned),
+    ("huhiragana", Glyph::huhiragana),
+    ("huiitosquare", Glyph::huiitosquare),
+    ("hukatakana", Glyph::hukatakana),
+    ("hukatakanahalfwidth", Glyph::hukatakanahalfwidth),
+    ("hungarumlaut", Glyph::hungarumlaut),
+    ("hungarumlautcmb", Glyph::hungarumlautcmb),
+    ("hv", Glyph::hv),
+    ("hyphen", Glyph::hyphen),
+    ("hyphen_alt", Glyph::hyphen_alt),
+    ("hyphenchar", Glyph::hyphenchar),
+    ("hypheninferior", Glyph::hypheninferior),
+    ("hyphenmonospace", Glyph::hyphenmonospace),
+    ("hyphensmall", Glyph::hyphensmall),
+    ("hyphensuperior", Glyph::hyphensuperior),
+    ("hyphentwo", Glyph::hyphentwo),
+    ("i", Glyph::i),
+    ("iacute", Glyph::iacute),
+    ("iacyrillic", Glyph::iacyrillic),
+    ("ibengali", Glyph::ibengali),
+    ("ibopomofo", Glyph::ibopomofo),
+    ("ibreve", Glyph::ibreve),
+    ("icaron", Glyph::icaron),
+    ("icircle", Glyph::icircle),
+    ("icircumflex", Glyph::icircumflex),
+    ("icyrillic", Glyph::icyrillic),
+    ("idblgrave", Glyph::idblgrave),
+    ("ideographearthcircle", Glyph::ideographearthcircle),
+    ("ideographfirecircle", Glyph::ideographfirecircle),
+    ("ideographicallianceparen", Glyph::ideographicallianceparen),
+    ("ideographiccallparen", Glyph::ideographiccallparen),
+    ("ideographiccentrecircle", Glyph::ideographiccentrecircle),
+    ("ideographicclose", Glyph::ideographicclose),
+    ("ideographiccomma", Glyph::ideographiccomma),
+    ("ideographiccommaleft", Glyph::ideographiccommaleft),
+    ("ideographiccongratulationparen", Glyph::ideographiccongratulationparen),
+    ("ideographiccorrectcircle", Glyph::ideographiccorrectcircle),
+    ("ideographicearthparen", Glyph::ideographicearthparen),
+    ("ideographicenterpriseparen", Glyph::ideographicenterpriseparen),
+    ("ideographicexcellentcircle", Glyph::ideographicexcellentcircle),
+    ("ideographicfestivalparen", Glyph::ideographicfestivalparen),
+    ("ideographicfinancialcircle", Glyph::ideographicfinancialcircle),
+    ("ideographicfinancialparen", Glyph::ideographicfinancialparen),
+    ("ideographicfireparen", Glyph::ideographicfireparen),
+    ("ideographichaveparen", Glyph::ideographichaveparen),
+    ("ideographichighcircle", Glyph::ideographichighcircle),
+    ("ideographiciterationmark", Glyph::ideographiciterationmark),
+    ("ideographiclaborcircle", Glyph::ideographiclaborcircle),
+    ("ideographiclaborparen", Glyph::ideographiclaborparen),
+    ("ideographicleftcircle", Glyph::ideographicleftcircle),
+    ("ideographiclowcircle", Glyph::ideographiclowcircle),
+    ("ideographicmedicinecircle", Glyph::ideographicmedicinecircle),
+    ("ideographicmetalparen", Glyph::ideographicmetalparen),
+    ("ideographicmoonparen", Glyph::ideographicmoonparen),
+    ("ideographicnameparen", Glyph::ideographicnameparen),
+    ("ideographicperiod", Glyph::ideographicperiod),
+    ("ideographicprintcircle", Glyph::ideographicprintcircle),
+    ("ideographicreachparen", Glyph::ideographicreachparen),
+    ("ideographicrepresentparen", Glyph::ideographicrepresentparen),
+    ("ideographicresourceparen", Glyph::ideographicresourceparen),
+    ("ideographicrightcircle", Glyph::ideographicrightcircle),
+    ("ideographicsecretcircle", Glyph::ideographicsecretcircle),
+    ("ideographicselfparen", Glyph::ideographicselfparen),
+    ("ideographicsocietyparen", Glyph::ideographicsocietyparen),
+    ("ideographicspace", Glyph::ideographicspace),
+    ("ideographicspecialparen", Glyph::ideographicspecialparen),
+    ("ideographicstockparen", Glyph::ideographicstockparen),
+    ("ideographicstudyparen", Glyph::ideographicstudyparen),
+    ("ideographicsunparen", Glyph::ideographicsunparen),
+    ("ideographicsuperviseparen", Glyph::ideographicsuperviseparen),
+    ("ideographicwaterparen", Glyph::ideographicwaterparen),
+    ("ideographicwoodparen", Glyph::ideographicwoodparen),
+    ("ideographiczero", Glyph::ideographiczero),
+    ("ideographmetalcircle", Glyph::ideographmetalcircle),
+    ("ideographmooncircle", Glyph::ideographmooncircle),
+    ("ideographnamecircle", Glyph::ideographnamecircle),
+    ("ideographsuncircle", Glyph::ideographsuncircle),
+    ("ideographwatercircle", Glyph::ideographwatercircle),
+    ("ideographwoodcircle", Glyph::ideographwoodcircle),
+    ("ideva", Glyph::ideva),
+    ("idieresis", Glyph::idieresis),
+    ("idieresisacute", Glyph::idieresisacute),
+    ("idieresiscyrillic", Glyph::idieresiscyrillic),
+    ("idotbelow", Glyph::idotbelow),
+    ("iebrevecyrillic", Glyph::iebrevecyrillic),
+    ("iecyrillic", Glyph::iecyrillic),
+    ("ieungacirclekorean", Glyph::ieungacirclekorean),
+    ("ieungaparenkorean", Glyph::ieungaparenkorean),
+    ("ieungcirclekorean", Glyph::ieungcirclekorean),
+    ("ieungkorean", Glyph::ieungkorean),
+    ("ieungparenkorean", Glyph::ieungparenkorean),
+    ("igrave", Glyph::igrave),
+    ("igujarati", Glyph::igujarati),
+    ("igurmukhi", Glyph::igurmukhi),
+    ("ihiragana", Glyph::ihiragana),
+    ("ihookabove", Glyph::ihookabove),
+    ("iibengali", Glyph::iibengali),
+    ("iicyrillic", Glyph::iicyrillic),
+    ("iideva", Glyph::iideva),
+    ("iigujarati", Glyph::iigujarati),
+    ("iigurmukhi", Glyph::iigurmukhi),
+    ("iimatragurmukhi", Glyph::iimatragurmukhi),
+    ("iinvertedbreve", Glyph::iinvertedbreve),
+    ("iishortcyrillic", Glyph::iishortcyrillic),
+    ("iivowelsignbengali", Glyph::iivowelsignbengali),
+    ("iivowelsigndeva", Glyph::iivowelsigndeva),
+    ("iivowelsigngujarati", Glyph::iivowelsigngujarati),
+    ("ij", Glyph::ij),
+    ("ikatakana", Glyph::ikatakana),
+    ("ikatakanahalfwidth", Glyph::ikatakanahalfwidth),
+    ("ikorean", Glyph::ikorean),
+    ("ilde", Glyph::ilde),
+    ("iluyhebrew", Glyph::iluyhebrew),
+    ("imacron", Glyph::imacron),
+    ("imacroncyrillic", Glyph::imacroncyrillic),
+    ("imageorapproximatelyequal", Glyph::imageorapproximatelyequal),
+    ("imatragurmukhi", Glyph::imatragurmukhi),
+    ("imonospace", Glyph::imonospace),
+    ("increment", Glyph::increment),
+    ("infinity", Glyph::infinity),
+    ("iniarmenian", Glyph::iniarmenian),
+    ("integral", Glyph::integral),
+    ("integralbottom", Glyph::integralbottom),
+    ("integralbt", Glyph::integralbt),
+    ("integraldisplay", Glyph::integraldisplay),
+    ("integralex", Glyph::integralex),
+    ("integraltext", Glyph::integraltext),
+    ("integraltop", Glyph::integraltop),
+    ("integraltp", Glyph::integraltp),
+    ("interrobang", Glyph::interrobang),
+    ("interrobangdown", Glyph::interrobangdown),
+    ("intersection", Glyph::intersection),
+    ("intersectiondisplay", Glyph::intersectiondisplay),
+    ("intersectionsq", Glyph::intersectionsq),
+    ("intersectiontext", Glyph::intersectiontext),
+    ("intisquare", Glyph::intisquare),
+    ("invbullet", Glyph::invbullet),
+    ("invcircle", Glyph::invcircle),
+    ("invsmileface", Glyph::invsmileface),
+    ("iocyrillic", Glyph::iocyrillic),
+    ("iogonek", Glyph::iogonek),
+    ("iota", Glyph::iota),
+    ("iotadieresis", Glyph::iotadieresis),
+    ("iotadieresistonos", Glyph::iotadieresistonos),
+    ("iotalatin", Glyph::iotalatin),
+    ("iotatonos", Glyph::iotatonos),
+    ("iparen", Glyph::iparen),
+    ("irigurmukhi", Glyph::irigurmukhi),
+    ("ismallhiragana", Glyph::ismallhiragana),
+    ("ismallkatakana", Glyph::ismallkatakana),
+    ("ismallkatakanahalfwidth", Glyph::ismallkatakanahalfwidth),
+    ("issharbengali", Glyph::issharbengali),
+    ("istroke", Glyph::istroke),
+    ("isuperior", Glyph::isuperior),
+    ("iterationhiragana", Glyph::iterationhiragana),
+    ("iterationkatakana", Glyph::iterationkatakana),
+    ("itilde", Glyph::itilde),
+    ("itildebelow", Glyph::itildebelow),
+    ("iubopomofo", Glyph::iubopomofo),
+    ("iucyrillic", Glyph::iucyrillic),
+    ("ivowelsignbengali", Glyph::ivowelsignbengali),
+    ("ivowelsigndeva", Glyph::ivowelsigndeva),
+    ("ivowelsigngujarati", Glyph::ivowelsigngujarati),
+    ("izhitsacyrillic", Glyph::izhitsacyrillic),
+    ("izhitsadblgravecyrillic", Glyph::izhitsadblgravecyrillic),
+    ("j", Glyph::j),
+    ("jaarmenian", Glyph::jaarmenian),
+    ("jabengali", Glyph::jabengali),
+    ("jadeva", Glyph::jadeva),
+    ("jagujarati", Glyph::jagujarati),
+    ("jagurmukhi", Glyph::jagurmukhi),
+    ("jbopomofo", Glyph::jbopomofo),
+    ("jcaron", Glyph::jcaron),
+    ("jcircle", Glyph::jcircle),
+    ("jcircumflex", Glyph::jcircumflex),
+    ("jcrossedtail", Glyph::jcrossedtail),
+    ("jdotlessstroke", Glyph::jdotlessstroke),
+    ("jecyrillic", Glyph::jecyrillic),
+    ("jeemarabic", Glyph::jeemarabic),
+    ("jeemfinalarabic", Glyph::jeemfinalarabic),
+    ("jeeminitialarabic", Glyph::jeeminitialarabic),
+    ("jeemmedialarabic", Glyph::jeemmedialarabic),
+    ("jeharabic", Glyph::jeharabic),
+    ("jehfinalarabic", Glyph::jehfinalarabic),
+    ("jhabengali", Glyph::jhabengali),
+    ("jhadeva", Glyph::jhadeva),
+    ("jhagujarati", Glyph::jhagujarati),
+    ("jhagurmukhi", Glyph::jhagurmukhi),
+    ("jheharmenian", Glyph::jheharmenian),
+    ("jis", Glyph::jis),
+    ("jmonospace", Glyph::jmonospace),
+    ("jparen", Glyph::jparen),
+    ("jsuperior", Glyph::jsuperior),
+    ("k", Glyph::k),
+    ("kabashkircyrillic", Glyph::kabashkircyrillic),
+    ("kabengali", Glyph::kabengali),
+    ("kacute", Glyph::kacute),
+    ("kacyrillic", Glyph::kacyrillic),
+    ("kadescendercyrillic", Glyph::kadescendercyrillic),
+    ("kadeva", Glyph::kadeva),
+    ("kaf", Glyph::kaf),
+    ("kafarabic", Glyph::kafarabic),
+    ("kafdagesh", Glyph::kafdagesh),
+    ("kafdageshhebrew", Glyph::kafdageshhebrew),
+    ("kaffinalarabic", Glyph::kaffinalarabic),
+    ("kafhebrew", Glyph::kafhebrew),
+    ("kafinitialarabic", Glyph::kafinitialarabic),
+    ("kafmedialarabic", Glyph::kafmedialarabic),
+    ("kafrafehebrew", Glyph::kafrafehebrew),
+    ("kagujarati", Glyph::kagujarati),
+    ("kagurmukhi", Glyph::kagurmukhi),
+    ("kahiragana", Glyph::kahiragana),
+    ("kahookcyrillic", Glyph::kahookcyrillic),
+    ("kakatakana", Glyph::kakatakana),
+    ("kakatakanahalfwidth", Glyph::kakatakanahalfwidth),
+    ("kappa", Glyph::kappa),
+    ("kappasymbolgreek", Glyph::kappasymbolgreek),
+    ("kapyeounmieumkorean", Glyph::kapyeounmieumkorean),
+    ("kapyeounphieuphkorean", Glyph::kapyeounphieuphkorean),
+    ("kapyeounpieupkorean", Glyph::kapyeounpieupkorean),
+    ("kapyeounssangpieupkorean", Glyph::kapyeounssangpieupkorean),
+    ("karoriisquare", Glyph::karoriisquare),
+    ("kashidaautoarabic", Glyph::kashidaautoarabic),
+    ("kashidaautonosidebearingarabic", Glyph::kashidaautonosidebearingarabic),
+    ("kasmallkatakana", Glyph::kasmallkatakana),
+    ("kasquare", Glyph::kasquare),
+    ("kasraarabic", Glyph::kasraarabic),
+    ("kasratanarabic", Glyph::kasratanarabic),
+    ("kastrokecyrillic", Glyph::kastrokecyrillic),
+    ("katahiraprolongmarkhalfwidth", Glyph::katahiraprolongmarkhalfwidth),
+    ("kaverticalstrokecyrillic", Glyph::kaverticalstrokecyrillic),
+    ("kbopomofo", Glyph::kbopomofo),
+    ("kcalsquare", Glyph::kcalsquare),
+    ("kcaron", Glyph::kcaron),
+    ("kcedilla", Glyph::kcedilla),
+    ("kcircle", Glyph::kcircle),
+    ("kcommaaccent", Glyph::kcommaaccent),
+    ("kdotbelow", Glyph::kdotbelow),
+    ("keharmenian", Glyph::keharmenian),
+    ("kehiragana", Glyph::kehiragana),
+    ("kekatakana", Glyph::kekatakana),
+    ("kekatakanahalfwidth", Glyph::kekatakanahalfwidth),
+    ("kenarmenian", Glyph::kenarmenian),
+    ("kesmallkatakana", Glyph::kesmallkatakana),
+    ("kgreenlandic", Glyph::kgreenlandic),
+    ("khabengali", Glyph::khabengali),
+    ("khacyrillic", Glyph::khacyrillic),
+    ("khadeva", Glyph::khadeva),
+    ("khagujarati", Glyph::khagujarati),
+    ("khagurmukhi", Glyph::khagurmukhi),
+    ("khaharabic", Glyph::khaharabic),
+    ("khahfinalarabic", Glyph::khahfinalarabic),
+    ("khahinitialarabic", Glyph::khahinitialarabic),
+    ("khahmedialarabic", Glyph::khahmedialarabic),
+    ("kheicoptic", Glyph::kheicoptic),
+    ("khhadeva", Glyph::khhadeva),
+    ("khhagurmukhi", Glyph::khhagurmukhi),
+    ("khieukhacirclekorean", Glyph::khieukhacirclekorean),
+    ("khieukhaparenkorean", Glyph::khieukhaparenkorean),
+    ("khieukhcirclekorean", Glyph::khieukhcirclekorean),
+    ("khieukhkorean", Glyph::khieukhkorean),
+    ("khieukhparenkorean", Glyph::khieukhparenkorean),
+    ("khokhaithai", Glyph::khokhaithai),
+    ("khokhonthai", Glyph::khokhonthai),
+    ("khokhuatthai", Glyph::khokhuatthai),
+    ("khokhwaithai", Glyph::khokhwaithai),
+    ("khomutthai", Glyph::khomutthai),
+    ("khook", Glyph::khook),
+    ("khorakhangthai", Glyph::khorakhangthai),
+    ("khzsquare", Glyph::khzsquare),
+    ("kihiragana", Glyph::kihiragana),
+    ("kikatakana", Glyph::kikatakana),
+    ("kikatakanahalfwidth", Glyph::kikatakanahalfwidth),
+    ("kiroguramusquare", Glyph::kiroguramusquare),
+    ("kiromeetorusquare", Glyph::kiromeetorusquare),
+    ("kirosquare", Glyph::kirosquare),
+    ("kiyeokacirclekorean", Glyph::kiyeokacirclekorean),
+    ("kiyeokaparenkorean", Glyph::kiyeokaparenkorean),
+    ("kiyeokcirclekorean", Glyph::kiyeokcirclekorean),
+    ("kiyeokkorean", Glyph::kiyeokkorean),
+    ("kiyeokparenkorean", Glyph::kiyeokparenkorean),
+    ("kiyeoksioskorean", Glyph::kiyeoksioskorean),
+    ("kjecyrillic", Glyph::kjecyrillic),
+    ("klinebelow", Glyph::klinebelow),
+    ("klsquare", Glyph::klsquare),
+    ("kmcubedsquare", Glyph::kmcubedsquare),
+    ("kmonospace", Glyph::kmonospace),
+    ("kmsquaredsquare", Glyph::kmsquaredsquare),
+    ("kohiragana", Glyph::kohiragana),
+    ("kohmsquare", Glyph::kohmsquare),
+    ("kokaithai", Glyph::kokaithai),
+    ("kokatakana", Glyph::kokatakana),
+    ("kokatakanahalfwidth", Glyph::kokatakanahalfwidth),
+    ("kooposquare", Glyph::kooposquare),
+    ("koppacyrillic", Glyph::koppacyrillic),
+    ("koreanstandardsymbol", Glyph::koreanstandardsymbol),
+    ("koroniscmb", Glyph::koroniscmb),
+    ("kparen", Glyph::kparen),
+    ("kpasquare", Glyph::kpasquare),
+    ("ksicyrillic", Glyph::ksicyrillic),
+    ("ktsquare", Glyph::ktsquare),
+    ("kturned", Glyph::kturned),
+    ("kuhiragana", Glyph::kuhiragana),
+    ("kukatakana", Glyph::kukatakana),
+    ("kukatakanahalfwidth", Glyph::kukatakanahalfwidth),
+    ("kvsquare", Glyph::kvsquare),
+    ("kwsquare", Glyph::kwsquare),
+    ("l", Glyph::l),
+    ("labengali", Glyph::labengali),
+    ("lacute", Glyph::lacute),
+    ("ladeva", Glyph::ladeva),
+    ("lagujarati", Glyph::lagujarati),
+    ("lagurmukhi", Glyph::lagurmukhi),
+    ("lakkhangyaothai", Glyph::lakkhangyaothai),
+    ("lamaleffinalarabic", Glyph::lamaleffinalarabic),
+    ("lamalefhamzaabovefinalarabic", Glyph::lamalefhamzaabovefinalarabic),
+    (
+        "lamalefhamzaaboveisolatedarabic",
+        Glyph::lamalefhamzaaboveisolatedarabic,
+    ),
+    ("lamalefhamzabelowfinalarabic", Glyph::lamalefhamzabelowfinalarabic),
+    (
+        "lamalefhamzabelowisolatedarabic",
+        Glyph::lamalefhamzabelowisolatedarabic,
+    ),
+    ("lamalefisolatedarabic", Glyph::lamalefisolatedarabic),
+    ("lamalefmaddaabovefinalarabic", Glyph::lamalefmaddaabovefinalarabic),
+    (
+        "lamalefmaddaaboveisolatedarabic",
+        Glyph::lamalefmaddaaboveisolatedarabic,
+    ),
+    ("lamarabic", Glyph::lamarabic),
+    ("lambda", Glyph::lambda),
+    ("lambdastroke", Glyph::lambdastroke),
+    ("lamed", Glyph::lamed),
+    ("lameddagesh", Glyph::lameddagesh),
+    ("lameddageshhebrew", Glyph::lameddageshhebrew),
+    ("lamedhebrew", Glyph::lamedhebrew),
+    ("lamedholam", Glyph::lamedholam),
+    ("lamedholamdagesh", Glyph::lamedholamdagesh),
+    ("lamedholamdageshhebrew", Glyph::lamedholamdageshhebrew),
+    ("lamedholamhebrew", Glyph::lamedholamhebrew),
+    ("lamfinalarabic", Glyph::lamfinalarabic),
+    ("lamhahinitialarabic", Glyph::lamhahinitialarabic),
+    ("laminitialarabic", Glyph::laminitialarabic),
+    ("lamjeeminitialarabic", Glyph::lamjeeminitialarabic),
+    ("lamkhahinitialarabic", Glyph::lamkhahinitialarabic),
+    ("lamlamhehisolatedarabic", Glyph::lamlamhehisolatedarabic),
+    ("lammedialarabic", Glyph::lammedialarabic),
+    ("lammeemhahinitialarabic", Glyph::lammeemhahinitialarabic),
+    ("lammeeminitialarabic", Glyph::lammeeminitialarabic),
+    ("lammeemjeeminitialarabic", Glyph::lammeemjeeminitialarabic),
+    ("lammeemkhahinitialarabic", Glyph::lammeemkhahinitialarabic),
+    ("largecircle", Glyph::largecircle),
+    ("latticetop", Glyph::latticetop),
+    ("lbar", Glyph::lbar),
+    ("lbelt", Glyph::lbelt),
+    ("lbopomofo", Glyph::lbopomofo),
+    ("lcaron", Glyph::lcaron),
+    ("lcedilla", Glyph::lcedilla),
+    ("lcircle", Glyph::lcircle),
+    ("lcircumflexbelow", Glyph::lcircumflexbelow),
+    ("lcommaaccent", Glyph::lcommaaccent),
+    ("ldot", Glyph::ldot),
+    ("ldotaccent", Glyph::ldotaccent),
+    ("ldotbelow", Glyph::ldotbelow),
+    ("ldotbelowmacron", Glyph::ldotbelowmacron),
+    ("leftangleabovecmb", Glyph::leftangleabovecmb),
+    ("lefttackbelowcmb", Glyph::lefttackbelowcmb),
+    ("less", Glyph::less),
+    ("lessequal", Glyph::lessequal),
+    ("lessequalorgreater", Glyph::lessequalorgreater),
+    ("lessmonospace", Glyph::lessmonospace),
+    ("lessmuch", Glyph::lessmuch),
+    ("lessorequivalent", Glyph::lessorequivalent),
+    ("lessorgreater", Glyph::lessorgreater),
+    ("lessoverequal", Glyph::lessoverequal),
+    ("lesssmall", Glyph::lesssmall),
+    ("lezh", Glyph::lezh),
+    ("lfblock", Glyph::lfblock),
+    ("lhookretroflex", Glyph::lhookretroflex),
+    ("lira", Glyph::lira),
+    ("liwnarmenian", Glyph::liwnarmenian),
+    ("lj", Glyph::lj),
+    ("ljecyrillic", Glyph::ljecyrillic),
+    ("ll", Glyph::ll),
+    ("lladeva", Glyph::lladeva),
+    ("llagujarati", Glyph::llagujarati),
+    ("llinebelow", Glyph::llinebelow),
+    ("llladeva", Glyph::llladeva),
+    ("llvocalicbengali", Glyph::llvocalicbengali),
+    ("llvocalicdeva", Glyph::llvocalicdeva),
+    ("llvocalicvowelsignbengali", Glyph::llvocalicvowelsignbengali),
+    ("llvocalicvowelsigndeva", Glyph::llvocalicvowelsigndeva),
+    ("lmiddletilde", Glyph::lmiddletilde),
+    ("lmonospace", Glyph::lmonospace),
+    ("lmsquare", Glyph::lmsquare),
+    ("lochulathai", Glyph::lochulathai),
+    ("logicaland", Glyph::logicaland),
+    ("logicalanddisplay", Glyph::logicalanddisplay),
+    ("logicalandtext", Glyph::logicalandtext),
+    ("logicalnot", Glyph::logicalnot),
+    ("logicalnotreversed", Glyph::logicalnotreversed),
+    ("logicalor", Glyph::logicalor),
+    ("logicalordisplay", Glyph::logicalordisplay),
+    ("logicalortext", Glyph::logicalortext),
+    ("lolingthai", Glyph::lolingthai),
+    ("longs", Glyph::longs),
+    ("lowlinecenterline", Glyph::lowlinecenterline),
+    ("lowlinecmb", Glyph::lowlinecmb),
+    ("lowlinedashed", Glyph::lowlinedashed),
+    ("lozenge", Glyph::lozenge),
+    ("lparen", Glyph::lparen),
+    ("lscript", Glyph::lscript),
+    ("lslash", Glyph::lslash),
+    ("lsquare", Glyph::lsquare),
+    ("lsuperior", Glyph::lsuperior),
+    ("ltshade", Glyph::ltshade),
+    ("luthai", Glyph::luthai),
+    ("lvocalicbengali", Glyph::lvocalicbengali),
+    ("lvocalicdeva", Glyph::lvocalicdeva),
+    ("lvocalicvowelsignbengali", Glyph::lvocalicvowelsignbengali),
+    ("lvocalicvowelsigndeva", Glyph::lvocalicvowelsigndeva),
+    ("lxsquare", Glyph::lxsquare),
+    ("m", Glyph::m),
+    ("mabengali", Glyph::mabengali),
+    ("macron", Glyph::macron),
+    ("macronbelowcmb", Glyph::macronbelowcmb),
+    ("macroncmb", Glyph::macroncmb),
+    ("macronlowmod", Glyph::macronlowmod),
+    ("macronmonospace", Glyph::macronmonospace),
+    ("macute", Glyph::macute),
+    ("madeva", Glyph::madeva),
+    ("magujarati", Glyph::magujarati),
+    ("magurmukhi", Glyph::magurmukhi),
+    ("mahapakhhebrew", Glyph::mahapakhhebrew),
+    ("mahapakhlefthebrew", Glyph::mahapakhlefthebrew),
+    ("mahiragana", Glyph::mahiragana),
+    ("maichattawalowleftthai", Glyph::maichattawalowleftthai),
+    ("maichattawalowrightthai", Glyph::maichattawalowrightthai),
+    ("maichattawathai", Glyph::maichattawathai),
+    ("maichattawaupperleftthai", Glyph::maichattawaupperleftthai),
+    ("maieklowleftthai", Glyph::maieklowleftthai),
+    ("maieklowrightthai", Glyph::maieklowrightthai),
+    ("maiekthai", Glyph::maiekthai),
+    ("maiekupperleftthai", Glyph::maiekupperleftthai),
+    ("maihanakatleftthai", Glyph::maihanakatleftthai),
+    ("maihanakatthai", Glyph::maihanakatthai),
+    ("maitaikhuleftthai", Glyph::maitaikhuleftthai),
+    ("maitaikhuthai", Glyph::maitaikhuthai),
+    ("maitholowleftthai", Glyph::maitholowleftthai),
+    ("maitholowrightthai", Glyph::maitholowrightthai),
+    ("maithothai", Glyph::maithothai),
+    ("maithoupperleftthai", Glyph::maithoupperleftthai),
+    ("maitrilowleftthai", Glyph::maitrilowleftthai),
+    ("maitrilowrightthai", Glyph::maitrilowrightthai),
+    ("maitrithai", Glyph::maitrithai),
+    ("maitriupperleftthai", Glyph::maitriupperleftthai),
+    ("maiyamokthai", Glyph::maiyamokthai),
+    ("makatakana", Glyph::makatakana),
+    ("makatakanahalfwidth", Glyph::makatakanahalfwidth),
+    ("male", Glyph::male),
+    ("mansyonsquare", Glyph::mansyonsquare),
+    ("maqafhebrew", Glyph::maqafhebrew),
+    ("mars", Glyph::mars),
+    ("masoracirclehebrew", Glyph::masoracirclehebrew),
+    ("masquare", Glyph::masquare),
+    ("mbopomofo", Glyph::mbopomofo),
+    ("mbsquare", Glyph::mbsquare),
+    ("mcircle", Glyph::mcircle),
+    ("mcubedsquare", Glyph::mcubedsquare),
+    ("mdotaccent", Glyph::mdotaccent),
+    ("mdotbelow", Glyph::mdotbelow),
+    ("meemarabic", Glyph::meemarabic),
+    ("meemfinalarabic", Glyph::meemfinalarabic),
+    ("meeminitialarabic", Glyph::meeminitialarabic),
+    ("meemmedialarabic", Glyph::meemmedialarabic),
+    ("meemmeeminitialarabic", Glyph::meemmeeminitialarabic),
+    ("meemmeemisolatedarabic", Glyph::meemmeemisolatedarabic),
+    ("meetorusquare", Glyph::meetorusquare),
+    ("mehiragana", Glyph::mehiragana),
+    ("meizierasquare", Glyph::meizierasquare),
+    ("mekatakana", Glyph::mekatakana),
+    ("mekatakanahalfwidth", Glyph::mekatakanahalfwidth),
+    ("mem", Glyph::mem),
+    ("memdagesh", Glyph::memdagesh),
+    ("memdageshhebrew", Glyph::memdageshhebrew),
+    ("memhebrew", Glyph::memhebrew),
+    ("menarmenian", Glyph::menarmenian),
+    ("merkhahebrew", Glyph::merkhahebrew),
+    ("merkhakefulahebrew", Glyph::merkhakefulahebrew),
+    ("merkhakefulalefthebrew", Glyph::merkhakefulalefthebrew),
+    ("merkhalefthebrew", Glyph::merkhalefthebrew),
+    ("mhook", Glyph::mhook),
+    ("mhzsquare", Glyph::mhzsquare),
+    ("middledotkatakanahalfwidth", Glyph::middledotkatakanahalfwidth),
+    ("middot", Glyph::middot),
+    ("mieumacirclekorean", Glyph::mieumacirclekorean),
+    ("mieumaparenkorean", Glyph::mieumaparenkorean),
+    ("mieumcirclekorean", Glyph::mieumcirclekorean),
+    ("mieumkorean", Glyph::mieumkorean),
+    ("mieumpansioskorean", Glyph::mieumpansioskorean),
+    ("mieumparenkorean", Glyph::mieumparenkorean),
+    ("mieumpieupkorean", Glyph::mieumpieupkorean),
+    ("mieumsioskorean", Glyph::mieumsioskorean),
+    ("mihiragana", Glyph::mihiragana),
+    ("mikatakana", Glyph::mikatakana),
+    ("mikatakanahalfwidth", Glyph::mikatakanahalfwidth),
+    ("minus", Glyph::minus),
+    ("minusbelowcmb", Glyph::minusbelowcmb),
+    ("minuscircle", Glyph::minuscircle),
+    ("minusmod", Glyph::minusmod),
+    ("minusplus", Glyph::minusplus),
+    ("minute", Glyph::minute),
+    ("miribaarusquare", Glyph::miribaarusquare),
+    ("mirisquare", Glyph::mirisquare),
+    ("mlonglegturned", Glyph::mlonglegturned),
+    ("mlsquare", Glyph::mlsquare),
+    ("mmcubedsquare", Glyph::mmcubedsquare),
+    ("mmonospace", Glyph::mmonospace),
+    ("mmsquaredsquare", Glyph::mmsquaredsquare),
+    ("mohiragana", Glyph::mohiragana),
+    ("mohmsquare", Glyph::mohmsquare),
+    ("mokatakana", Glyph::mokatakana),
+    ("mokatakanahalfwidth", Glyph::mokatakanahalfwidth),
+    ("molsquare", Glyph::molsquare),
+    ("momathai", Glyph::momathai),
+    ("moverssquare", Glyph::moverssquare),
+    ("moverssquaredsquare", Glyph::moverssquaredsquare),
+    ("mparen", Glyph::mparen),
+    ("mpasquare", Glyph::mpasquare),
+    ("mssquare", Glyph::mssquare),
+    ("msuperior", Glyph::msuperior),
+    ("mturned", Glyph::mturned),
+    ("mu", Glyph::mu),
+    ("mu1", Glyph::mu1),
+    ("muasquare", Glyph::muasquare),
+    ("muchgreater", Glyph::muchgreater),
+    ("muchless", Glyph::muchless),
+    ("mufsquare", Glyph::mufsquare),
+    ("mugreek", Glyph::mugreek),
+    ("mugsquare", Glyph::mugsquare),
+    ("muhiragana", Glyph::muhiragana),
+    ("mukatakana", Glyph::mukatakana),
+    ("mukatakanahalfwidth", Glyph::mukatakanahalfwidth),
+    ("mulsquare", Glyph::mulsquare),
+    ("multiply", Glyph::multiply),
+    ("mumsquare", Glyph::mumsquare),
+    ("munahhebrew", Glyph::munahhebrew),
+    ("munahlefthebrew", Glyph::munahlefthebrew),
+    ("musicalnote", Glyph::musicalnote),
+    ("musicalnotedbl", Glyph::musicalnotedbl),
+    ("musicflatsign", Glyph::musicflatsign),
+    ("musicsharpsign", Glyph::musicsharpsign),
+    ("mussquare", Glyph::mussquare),
+    ("muvsquare", Glyph::muvsquare),
+    ("muwsquare", Glyph::muwsquare),
+    ("mvmegasquare", Glyph::mvmegasquare),
+    ("mvsquare", Glyph::mvsquare),
+    ("mwmegasquare", Glyph::mwmegasquare),
+    ("mwsquare", Glyph::mwsquare),
+    ("n", Glyph::n),
+    ("nabengali", Glyph::nabengali),
+    ("nabla", Glyph::nabla),
+    ("nacute", Glyph::nacute),
+    ("nadeva", Glyph::nadeva),
+    ("nagujarati", Glyph::nagujarati),
+    ("nagurmukhi", Glyph::nagurmukhi),
+    ("nahiragana", Glyph::nahiragana),
+    ("nakatakana", Glyph::nakatakana),
+    ("nakatakanahalfwidth", Glyph::nakatakanahalfwidth),
+    ("napostrophe", Glyph::napostrophe),
+    ("nasquare", Glyph::nasquare),
+    ("natural", Glyph::natural),
+    ("nbopomofo", Glyph::nbopomofo),
+    ("nbspace", Glyph::nbspace),
+    ("ncaron", Glyph::ncaron),
+    ("ncedilla", Glyph::ncedilla),
+    ("ncircle", Glyph::ncircle),
+    ("ncircumflexbelow", Glyph::ncircumflexbelow),
+    ("ncommaaccent", Glyph::ncommaaccent),
+    ("ndotaccent", Glyph::ndotaccent),
+    ("ndotbelow", Glyph::ndotbelow),
+    ("negationslash", Glyph::negationslash),
+    ("nehiragana", Glyph::nehiragana),
+    ("nekatakana", Glyph::nekatakana),
+    ("nekatakanahalfwidth", Glyph::nekatakanahalfwidth),
+    ("newsheqelsign", Glyph::newsheqelsign),
+    ("nfsquare", Glyph::nfsquare),
+    ("ng", Glyph::ng),
+    ("ngabengali", Glyph::ngabengali),
+    ("ngadeva", Glyph::ngadeva),
+    ("ngagujarati", Glyph::ngagujarati),
+    ("ngagurmukhi", Glyph::ngagurmukhi),
+    ("ngonguthai", Glyph::ngonguthai),
+    ("nhiragana", Glyph::nhiragana),
+    ("nhookleft", Glyph::nhookleft),
+    ("nhookretroflex", Glyph::nhookretroflex),
+    ("nieunacirclekorean", Glyph::nieunacirclekorean),
+    ("nieunaparenkorean", Glyph::nieunaparenkorean),
+    ("nieuncieuckorean", Glyph::nieuncieuckorean),
+    ("nieuncirclekorean", Glyph::nieuncirclekorean),
+    ("nieunhieuhkorean", Glyph::nieunhieuhkorean),
+    ("nieunkorean", Glyph::nieunkorean),
+    ("nieunpansioskorean", Glyph::nieunpansioskorean),
+    ("nieunparenkorean", Glyph::nieunparenkorean),
+    ("nieunsioskorean", Glyph::nieunsioskorean),
+    ("nieuntikeutkorean", Glyph::nieuntikeutkorean),
+    ("nihiragana", Glyph::nihiragana),
+    ("nikatakana", Glyph::nikatakana),
+    ("nikatakanahalfwidth", Glyph::nikatakanahalfwidth),
+    ("nikhahitleftthai", Glyph::nikhahitleftthai),
+    ("nikhahitthai", Glyph::nikhahitthai),
+    ("nine", Glyph::nine),
+    ("ninearabic", Glyph::ninearabic),
+    ("ninebengali", Glyph::ninebengali),
+    ("ninecircle", Glyph::ninecircle),
+    ("ninecircleinversesansserif", Glyph::ninecircleinversesansserif),
+    ("ninedeva", Glyph::ninedeva),
+    ("ninegujarati", Glyph::ninegujarati),
+    ("ninegurmukhi", Glyph::ninegurmukhi),
+    ("ninehackarabic", Glyph::ninehackarabic),
+    ("ninehangzhou", Glyph::ninehangzhou),
+    ("nineideographicparen", Glyph::nineideographicparen),
+    ("nineinferior", Glyph::nineinferior),
+    ("ninemonospace", Glyph::ninemonospace),
+    ("nineoldstyle", Glyph::nineoldstyle),
+    ("nineparen", Glyph::nineparen),
+    ("nineperiod", Glyph::nineperiod),
+    ("ninepersian", Glyph::ninepersian),
+    ("nineroman", Glyph::nineroman),
+    ("ninesuperior", Glyph::ninesuperior),
+    ("nineteencircle", Glyph::nineteencircle),
+    ("nineteenparen", Glyph::nineteenparen),
+    ("nineteenperiod", Glyph::nineteenperiod),
+    ("ninethai", Glyph::ninethai),
+    ("nj", Glyph::nj),
+    ("njecyrillic", Glyph::njecyrillic),
+    ("nkatakana", Glyph::nkatakana),
+    ("nkatakanahalfwidth", Glyph::nkatakanahalfwidth),
+    ("nlegrightlong", Glyph::nlegrightlong),
+    ("nlinebelow", Glyph::nlinebelow),
+    ("nmonospace", Glyph::nmonospace),
+    ("nmsquare", Glyph::nmsquare),
+    ("nnabengali", Glyph::nnabengali),
+    ("nnadeva", Glyph::nnadeva),
+    ("nnagujarati", Glyph::nnagujarati),
+    ("nnagurmukhi", Glyph::nnagurmukhi),
+    ("nnnadeva", Glyph::nnnadeva),
+    ("nohiragana", Glyph::nohiragana),
+    ("nokatakana", Glyph::nokatakana),
+    ("nokatakanahalfwidth", Glyph::nokatakanahalfwidth),
+    ("nonbreakingspace", Glyph::nonbreakingspace),
+    ("nonenthai", Glyph::nonenthai),
+    ("nonuthai", Glyph::nonuthai),
+    ("noonarabic", Glyph::noonarabic),
+    ("noonfinalarabic", Glyph::noonfinalarabic),
+    ("noonghunnaarabic", Glyph::noonghunnaarabic),
+    ("noonghunnafinalarabic", Glyph::noonghunnafinalarabic),
+    ("noonhehinitialarabic", Glyph::noonhehinitialarabic),
+    ("nooninitialarabic", Glyph::nooninitialarabic),
+    ("noonjeeminitialarabic", Glyph::noonjeeminitialarabic),
+    ("noonjeemisolatedarabic", Glyph::noonjeemisolatedarabic),
+    ("noonmedialarabic", Glyph::noonmedialarabic),
+    ("noonmeeminitialarabic", Glyph::noonmeeminitialarabic),
+    ("noonmeemisolatedarabic", Glyph::noonmeemisolatedarabic),
+    ("noonnoonfinalarabic", Glyph::noonnoonfinalarabic),
+    ("notcontains", Glyph::notcontains),
+    ("notelement", Glyph::notelement),
+    ("notelementof", Glyph::notelementof),
+    ("notequal", Glyph::notequal),
+    ("notgreater", Glyph::notgreater),
+    ("notgreaternorequal", Glyph::notgreaternorequal),
+    ("notgreaternorless", Glyph::notgreaternorless),
+    ("notidentical", Glyph::notidentical),
+    ("notless", Glyph::notless),
+    ("notlessnorequal", Glyph::notlessnorequal),
+    ("notparallel", Glyph::notparallel),
+    ("notprecedes", Glyph::notprecedes),
+    ("notsubset", Glyph::notsubset),
+    ("notsucceeds", Glyph::notsucceeds),
+    ("notsuperset", Glyph::notsuperset),
+    ("nowarmenian", Glyph::nowarmenian),
+    ("nparen", Glyph::nparen),
+    ("nssquare", Glyph::nssquare),
+    ("nsuperior", Glyph::nsuperior),
+    ("ntilde", Glyph::ntilde),
+    ("nu", Glyph::nu),
+    ("nuhiragana", Glyph::nuhiragana),
+    ("nukatakana", Glyph::nukatakana),
+    ("nukatakanahalfwidth", Glyph::nukatakanahalfwidth),
+    ("nuktabengali", Glyph::nuktabengali),
+    ("nuktadeva", Glyph::nuktadeva),
+    ("nuktagujarati", Glyph::nuktagujarati),
+    ("nuktagurmukhi", Glyph::nuktagurmukhi),
+    ("numbersign", Glyph::numbersign),
+    ("numbersignmonospace", Glyph::numbersignmonospace),
+    ("numbersignsmall", Glyph::numbersignsmall),
+    ("numeralsigngreek", Glyph::numeralsigngreek),
+    ("numeralsignlowergreek", Glyph::numeralsignlowergreek),
+    ("numero", Glyph::numero),
+    ("nun", Glyph::nun),
+    ("nundagesh", Glyph::nundagesh),
+    ("nundageshhebrew", Glyph::nundageshhebrew),
+    ("nunhebrew", Glyph::nunhebrew),
+    ("nvsquare", Glyph::nvsquare),
+    ("nwsquare", Glyph::nwsquare),
+    ("nyabengali", Glyph::nyabengali),
+    ("nyadeva", Glyph::nyadeva),
+    ("nyagujarati", Glyph::nyagujarati),
+    ("nyagurmukhi", Glyph::nyagurmukhi),
+    ("o", Glyph::o),
+    ("oacute", Glyph::oacute),
+    ("oangthai", Glyph::oangthai),
+    ("obarred", Glyph::obarred),
+    ("obarredcyrillic", Glyph::obarredcyrillic),
+    ("obarreddieresiscyrillic", Glyph::obarreddieresiscyrillic),
+    ("obengali", Glyph::obengali),
+    ("obopomofo", Glyph::obopomofo),
+    ("obreve", Glyph::obreve),
+    ("ocandradeva", Glyph::ocandradeva),
+    ("ocandragujarati", Glyph::ocandragujarati),
+    ("ocandravowelsigndeva", Glyph::ocandravowelsigndeva),
+    ("ocandravowelsigngujarati", Glyph::ocandravowelsigngujarati),
+    ("ocaron", Glyph::ocaron),
+    ("ocircle", Glyph::ocircle),
+    ("ocircumflex", Glyph::ocircumflex),
+    ("ocircumflexacute", Glyph::ocircumflexacute),
+    ("ocircumflexdotbelow", Glyph::ocircumflexdotbelow),
+    ("ocircumflexgrave", Glyph::ocircumflexgrave),
+    ("ocircumflexhookabove", Glyph::ocircumflexhookabove),
+    ("ocircumflextilde", Glyph::ocircumflextilde),
+    ("ocyrillic", Glyph::ocyrillic),
+    ("odblacute", Glyph::odblacute),
+    ("odblgrave", Glyph::odblgrave),
+    ("odeva", Glyph::odeva),
+    ("odieresis", Glyph::odieresis),
+    ("odieresiscyrillic", Glyph::odieresiscyrillic),
+    ("odotbelow", Glyph::odotbelow),
+    ("oe", Glyph::oe),
+    ("oekorean", Glyph::oekorean),
+    ("ogonek", Glyph::ogonek),
+    ("ogonekcmb", Glyph::ogonekcmb),
+    ("ograve", Glyph::ograve),
+    ("ogujarati", Glyph::ogujarati),
+    ("oharmenian", Glyph::oharmenian),
+    ("ohiragana", Glyph::ohiragana),
+    ("ohookabove", Glyph::ohookabove),
+    ("ohorn", Glyph::ohorn),
+    ("ohornacute", Glyph::ohornacute),
+    ("ohorndotbelow", Glyph::ohorndotbelow),
+    ("ohorngrave", Glyph::ohorngrave),
+    ("ohornhookabove", Glyph::ohornhookabove),
+    ("ohorntilde", Glyph::ohorntilde),
+    ("ohungarumlaut", Glyph::ohungarumlaut),
+    ("oi", Glyph::oi),
+    ("oinvertedbreve", Glyph::oinvertedbreve),
+    ("okatakana", Glyph::okatakana),
+    ("okatakanahalfwidth", Glyph::okatakanahalfwidth),
+    ("okorean", Glyph::okorean),
+    ("olehebrew", Glyph::olehebrew),
+    ("omacron", Glyph::omacron),
+    ("omacronacute", Glyph::omacronacute),
+    ("omacrongrave", Glyph::omacrongrave),
+    ("omdeva", Glyph::omdeva),
+    ("omega", Glyph::omega),
+    ("omega1", Glyph::omega1),
+    ("omegacyrillic", Glyph::omegacyrillic),
+    ("omegalatinclosed", Glyph::omegalatinclosed),
+    ("omegaroundcyrillic", Glyph::omegaroundcyrillic),
+    ("omegatitlocyrillic", Glyph::omegatitlocyrillic),
+    ("omegatonos", Glyph::omegatonos),
+    ("omgujarati", Glyph::omgujarati),
+    ("omicron", Glyph::omicron),
+    ("omicrontonos", Glyph::omicrontonos),
+    ("omonospace", Glyph::omonospace),
+    ("one", Glyph::one),
+    ("onearabic", Glyph::onearabic),
+    ("onebengali", Glyph::onebengali),
+    ("onecircle", Glyph::onecircle),
+    ("onecircleinversesansserif", Glyph::onecircleinversesansserif),
+    ("onedeva", Glyph::onedeva),
+    ("onedotenleader", Glyph::onedotenleader),
+    ("oneeighth", Glyph::oneeighth),
+    ("onefitted", Glyph::onefitted),
+    ("onegujarati", Glyph::onegujarati),
+    ("onegurmukhi", Glyph::onegurmukhi),
+    ("onehackarabic", Glyph::onehackarabic),
+    ("onehalf", Glyph::onehalf),
+    ("onehangzhou", Glyph::onehangzhou),
+    ("oneideographicparen", Glyph::oneideographicparen),
+    ("oneinferior", Glyph::oneinferior),
+    ("onemonospace", Glyph::onemonospace),
+    ("onenumeratorbengali", Glyph::onenumeratorbengali),
+    ("oneoldstyle", Glyph::oneoldstyle),
+    ("oneparen", Glyph::oneparen),
+    ("oneperiod", Glyph::oneperiod),
+    ("onepersian", Glyph::onepersian),
+    ("onequarter", Glyph::onequarter),
+    ("oneroman", Glyph::oneroman),
+    ("onesuperior", Glyph::onesuperior),
+    ("onethai", Glyph::onethai),
+    ("onethird", Glyph::onethird),
+    ("oogonek", Glyph::oogonek),
+    ("oogonekmacron", Glyph::oogonekmacron),
+    ("oogurmukhi", Glyph::oogurmukhi),
+    ("oomatragurmukhi", Glyph::oomatragurmukhi),
+    ("oopen", Glyph::oopen),
+    ("oparen", Glyph::oparen),
+    ("openbullet", Glyph::openbullet),
+    ("option", Glyph::option),
+    ("ordfeminine", Glyph::ordfeminine),
+    ("ordmasculine", Glyph::ordmasculine),
+    ("orthogonal", Glyph::orthogonal),
+    ("oshortdeva", Glyph::oshortdeva),
+    ("oshortvowelsigndeva", Glyph::oshortvowelsigndeva),
+    ("oslash", Glyph::oslash),
+    ("oslashacute", Glyph::oslashacute),
+    ("osmallhiragana", Glyph::osmallhiragana),
+    ("osmallkatakana", Glyph::osmallkatakana),
+    ("osmallkatakanahalfwidth", Glyph::osmallkatakanahalfwidth),
+    ("ostrokeacute", Glyph::ostrokeacute),
+    ("osuperior", Glyph::osuperior),
+    ("otcyrillic", Glyph::otcyrillic),
+    ("otilde", Glyph::otilde),
+    ("otildeacute", Glyph::otildeacute),
+    ("otildedieresis", Glyph::otildedieresis),
+    ("oubopomofo", Glyph::oubopomofo),
+    ("overline", Glyph::overline),
+    ("overlinecenterline", Glyph::overlinecenterline),
+    ("overlinecmb", Glyph::overlinecmb),
+    ("overlinedashed", Glyph::overlinedashed),
+    ("overlinedblwavy", Glyph::overlinedblwavy),
+    ("overlinewavy", Glyph::overlinewavy),
+    ("overscore", Glyph::overscore),
+    ("ovowelsignbengali", Glyph::ovowelsignbengali),
+    ("ovowelsigndeva", Glyph::ovowelsigndeva),
+    ("ovowelsigngujarati", Glyph::ovowelsigngujarati),
+    ("owner", Glyph::owner),
+    ("p", Glyph::p),
+    ("paampssquare", Glyph::paampssquare),
+    ("paasentosquare", Glyph::paasentosquare),
+    ("pabengali", Glyph::pabengali),
+    ("pacute", Glyph::pacute),
+    ("padeva", Glyph::padeva),
+    ("pagedown", Glyph::pagedown),
+    ("pageup", Glyph::pageup),
+    ("pagujarati", Glyph::pagujarati),
+    ("pagurmukhi", Glyph::pagurmukhi),
+    ("pahiragana", Glyph::pahiragana),
+    ("paiyannoithai", Glyph::paiyannoithai),
+    ("pakatakana", Glyph::pakatakana),
+    ("palatalizationcyrilliccmb", Glyph::palatalizationcyrilliccmb),
+    ("palochkacyrillic", Glyph::palochkacyrillic),
+    ("pansioskorean", Glyph::pansioskorean),
+    ("paragraph", Glyph::paragraph),
+    ("parallel", Glyph::parallel),
+    ("parenleft", Glyph::parenleft),
+    ("parenleftBig", Glyph::parenleftBig),
+    ("parenleftBigg", Glyph::parenleftBigg),
+    ("parenleftaltonearabic", Glyph::parenleftaltonearabic),
+    ("parenleftbig", Glyph::parenleftbig),
+    ("parenleftbigg", Glyph::parenleftbigg),
+    ("parenleftbt", Glyph::parenleftbt),
+    ("parenleftex", Glyph::parenleftex),
+    ("parenleftex2", Glyph::parenleftex2),
+    ("parenleftinferior", Glyph::parenleftinferior),
+    ("parenleftmonospace", Glyph::parenleftmonospace),
+    ("parenleftsmall", Glyph::parenleftsmall),
+    ("parenleftsuperior", Glyph::parenleftsuperior),
+    ("parenlefttp", Glyph::parenlefttp),
+    ("parenleftvertical", Glyph::parenleftvertical),
+    ("parenright", Glyph::parenright),
+    ("parenrightBig", Glyph::parenrightBig),
+    ("parenrightBigg", Glyph::parenrightBigg),
+    ("parenrightaltonearabic", Glyph::parenrightaltonearabic),
+    ("parenrightbig", Glyph::parenrightbig),
+    ("parenrightbigg", Glyph::parenrightbigg),
+    ("parenrightbt", Glyph::parenrightbt),
+    ("parenrightex", Glyph::parenrightex),
+    ("parenrightex2", Glyph::parenrightex2),
+    ("parenrightinferior", Glyph::parenrightinferior),
+    ("parenrightmonospace", Glyph::parenrightmonospace),
+    ("parenrightsmall", Glyph::parenrightsmall),
+    ("parenrightsuperior", Glyph::parenrightsuperior),
+    ("parenrighttp", Glyph::parenrighttp),
+    ("parenrightvertical", Glyph::parenrightvertical),
+    ("partialdiff", Glyph::partialdiff),
+    ("paseqhebrew", Glyph::paseqhebrew),
+    ("pashtahebrew", Glyph::pashtahebrew),
+    ("pasquare", Glyph::pasquare),
+    ("patah", Glyph::patah),
+    ("patah11", Glyph::patah11),
+    ("patah1d", Glyph::patah1d),
+    ("patah2a", Glyph::patah2a),
+    ("patahhebrew", Glyph::patahhebrew),
+    ("patahnarrowhebrew", Glyph::patahnarrowhebrew),
+    ("patahquarterhebrew", Glyph::patahquarterhebrew),
+    ("patahwidehebrew", Glyph::patahwidehebrew),
+    ("pazerhebrew", Glyph::pazerhebrew),
+    ("pbopomofo", Glyph::pbopomofo),
+    ("pcircle", Glyph::pcircle),
+    ("pdotaccent", Glyph::pdotaccent),
+    ("pe", Glyph::pe),
+    ("pecyrillic", Glyph::pecyrillic),
+    ("pedagesh", Glyph::pedagesh),
+    ("pedageshhebrew", Glyph::pedageshhebrew),
+    ("peezisquare", Glyph::peezisquare),
+    ("pefinaldageshhebrew", Glyph::pefinaldageshhebrew),
+    ("peharabic", Glyph::peharabic),
+    ("peharmenian", Glyph::peharmenian),
+    ("pehebrew", Glyph::pehebrew),
+    ("pehfinalarabic", Glyph::pehfinalarabic),
+    ("pehinitialarabic", Glyph::pehinitialarabic),
+    ("pehiragana", Glyph::pehiragana),
+    ("pehmedialarabic", Glyph::pehmedialarabic),
+    ("pekatakana", Glyph::pekatakana),
+    ("pemiddlehookcyrillic", Glyph::pemiddlehookcyrillic),
+    ("perafehebrew", Glyph::perafehebrew),
+    ("percent", Glyph::percent),
+    ("percentarabic", Glyph::percentarabic),
+    ("percentmonospace", Glyph::percentmonospace),
+    ("percentsmall", Glyph::percentsmall),
+    ("period", Glyph::period),
+    ("periodarmenian", Glyph::periodarmenian),
+    ("periodcentered", Glyph::periodcentered),
+    ("periodhalfwidth", Glyph::periodhalfwidth),
+    ("periodinferior", Glyph::periodinferior),
+    ("periodmonospace", Glyph::periodmonospace),
+    ("periodsmall", Glyph::periodsmall),
+    ("periodsuperior", Glyph::periodsuperior),
+    ("perispomenigreekcmb", Glyph::perispomenigreekcmb),
+    ("perpendicular", Glyph::perpendicular),
+    ("pertenthousand", Glyph::pertenthousand),
+    ("perthousand", Glyph::perthousand),
+    ("peseta", Glyph::peseta),
+    ("pfsquare", Glyph::pfsquare),
+    ("phabengali", Glyph::phabengali),
+    ("phadeva", Glyph::phadeva),
+    ("phagujarati", Glyph::phagujarati),
+    ("phagurmukhi", Glyph::phagurmukhi),
+    ("phi", Glyph::phi),
+    ("phi2", Glyph::phi2),
+    ("phieuphacirclekorean", Glyph::phieuphacirclekorean),
+    ("phieuphaparenkorean", Glyph::phieuphaparenkorean),
+    ("phieuphcirclekorean", Glyph::phieuphcirclekorean),
+    ("phieuphkorean", Glyph::phieuphkorean),
+    ("phieuphparenkorean", Glyph::phieuphparenkorean),
+    ("philatin", Glyph::philatin),
+    ("phinthuthai", Glyph::phinthuthai),
+    ("phisymbolgreek", Glyph::phisymbolgreek),
+    ("phook", Glyph::phook),
+    ("phophanthai", Glyph::phophanthai),
+    ("phophungthai", Glyph::phophungthai),
+    ("phosamphaothai", Glyph::phosamphaothai),
+    ("pi", Glyph::pi),
+    ("pi1", Glyph::pi1),
+    ("pieupacirclekorean", Glyph::pieupacirclekorean),
+    ("pieupaparenkorean", Glyph::pieupaparenkorean),
+    ("pieupcieuckorean", Glyph::pieupcieuckorean),
+    ("pieupcirclekorean", Glyph::pieupcirclekorean),
+    ("pieupkiyeokkorean", Glyph::pieupkiyeokkorean),
+    ("pieupkorean", Glyph::pieupkorean),
+    ("pieupparenkorean", Glyph::pieupparenkorean),
+    ("pieupsioskiyeokkorean", Glyph::pieupsioskiyeokkorean),
+    ("pieupsioskorean", Glyph::pieupsioskorean),
+    ("pieupsiostikeutkorean", Glyph::pieupsiostikeutkorean),
+    ("pieupthieuthkorean", Glyph::pieupthieuthkorean),
+    ("pieuptikeutkorean", Glyph::pieuptikeutkorean),
+    ("pihiragana", Glyph::pihiragana),
+    ("pikatakana", Glyph::pikatakana),
+    ("pisymbolgreek", Glyph::pisymbolgreek),
+    ("piwrarmenian", Glyph::piwrarmenian),
+    ("plus", Glyph::plus),
+    ("plusbelowcmb", Glyph::plusbelowcmb),
+    ("pluscircle", Glyph::pluscircle),
+    ("plusminus", Glyph::plusminus),
+    ("plusmod", Glyph::plusmod),
+    ("plusmonospace", Glyph::plusmonospace),
+    ("plussmall", Glyph::plussmall),
+    ("plussuperior", Glyph::plussuperior),
+    ("pmonospace", Glyph::pmonospace),
+    ("pmsquare", Glyph::pmsquare),
+    ("pohiragana", Glyph::pohiragana),
+    ("pointingindexdownwhite", Glyph::pointingindexdownwhite),
+    ("pointingindexleftwhite", Glyph::pointingindexleftwhite),
+    ("pointingindexrightwhite", Glyph::pointingindexrightwhite),
+    ("pointingindexupwhite", Glyph::pointingindexupwhite),
+    ("pokatakana", Glyph::pokatakana),
+    ("poplathai", Glyph::poplathai),
+    ("postalmark", Glyph::postalmark),
+    ("postalmarkface", Glyph::postalmarkface),
+    ("pparen", Glyph::pparen),
+    ("precedes", Glyph::precedes),
+    ("precedesequal", Glyph::precedesequal),
+    ("prescription", Glyph::prescription),
+    ("prime", Glyph::prime),
+    ("primemod", Glyph::primemod),
+    ("primereversed", Glyph::primereversed),
+    ("product", Glyph::product),
+    ("productdisplay", Glyph::productdisplay),
+    ("producttext", Glyph::producttext),
+    ("projective", Glyph::projective),
+    ("prolongedkana", Glyph::prolongedkana),
+    ("propellor", Glyph::propellor),
+    ("propersubset", Glyph::propersubset),
+    ("propersuperset", Glyph::propersuperset),
+    ("proportion", Glyph::proportion),
+    ("proportional", Glyph::proportional),
+    ("psi", Glyph::psi),
+    ("psicyrillic", Glyph::psicyrillic),
+    ("psilipneumatacyrilliccmb", Glyph::psilipneumatacyrilliccmb),
+    ("pssquare", Glyph::pssquare),
+    ("puhiragana", Glyph::puhiragana),
+    ("pukatakana", Glyph::pukatakana),
+    ("punctdash", Glyph::punctdash),
+    ("pvsquare", Glyph::pvsquare),
+    ("pwsquare", Glyph::pwsquare),
+    ("q", Glyph::q),
+    ("qadeva", Glyph::qadeva),
+    ("qadmahebrew", Glyph::qadmahebrew),
+    ("qafarabic", Glyph::qafarabic),
+    ("qaffinalarabic", Glyph::qaffinalarabic),
+    ("qafinitialarabic", Glyph::qafinitialarabic),
+    ("qafmedialarabic", Glyph::qafmedialarabic),
+    ("qamats", Glyph::qamats),
+    ("qamats10", Glyph::qamats10),
+    ("qamats1a", Glyph::qamats1a),
+    ("qamats1c", Glyph::qamats1c),
+    ("qamats27", Glyph::qamats27),
+    ("qamats29", Glyph::qamats29),
+    ("qamats33", Glyph::qamats33),
+    ("qamatsde", Glyph::qamatsde),
+    ("qamatshebrew", Glyph::qamatshebrew),
+    ("qamatsnarrowhebrew", Glyph::qamatsnarrowhebrew),
+    ("qamatsqatanhebrew", Glyph::qamatsqatanhebrew),
+    ("qamatsqatannarrowhebrew", Glyph::qamatsqatannarrowhebrew),
+    ("qamatsqatanquarterhebrew", Glyph::qamatsqatanquarterhebrew),
+    ("qamatsqatanwidehebrew", Glyph::qamatsqatanwidehebrew),
+    ("qamatsquarterhebrew", Glyph::qamatsquarterhebrew),
+    ("qamatswidehebrew", Glyph::qamatswidehebrew),
+    ("qarneyparahebrew", Glyph::qarneyparahebrew),
+    ("qbopomofo", Glyph::qbopomofo),
+    ("qcircle", Glyph::qcircle),
+    ("qhook", Glyph::qhook),
+    ("qmonospace", Glyph::qmonospace),
+    ("qof", Glyph::qof),
+    ("qofdagesh", Glyph::qofdagesh),
+    ("qofdageshhebrew", Glyph::qofdageshhebrew),
+    ("qofhatafpatah", Glyph::qofhatafpatah),
+    ("qofhatafpatahhebrew", Glyph::qofhatafpatahhebrew),
+    ("qofhatafsegol", Glyph::qofhatafsegol),
+    ("qofhatafsegolhebrew", Glyph::qofhatafsegolhebrew),
+    ("qofhebrew", Glyph::qofhebrew),
+    ("qofhiriq", Glyph::qofhiriq),
+    ("qofhiriqhebrew", Glyph::qofhiriqhebrew),
+    ("qofholam", Glyph::qofholam),
+    ("qofholamhebrew", Glyph::qofholamhebrew),
+    ("qofpatah", Glyph::qofpatah),
+    ("qofpatahhebrew", Glyph::qofpatahhebrew),
+    ("qofqamats", Glyph::qofqamats),
+    ("qofqamatshebrew", Glyph::qofqamatshebrew),
+    ("qofqubuts", Glyph::qofqubuts),
+    ("qofqubutshebrew", Glyph::qofqubutshebrew),
+    ("qofsegol", Glyph::qofsegol),
+    ("qofsegolhebrew", Glyph::qofsegolhebrew),
+    ("qofsheva", Glyph::qofsheva),
+    ("qofshevahebrew", Glyph::qofshevahebrew),
+    ("qoftsere", Glyph::qoftsere),
+    ("qoftserehebrew", Glyph::qoftserehebrew),
+    ("qparen", Glyph::qparen),
+    ("quarternote", Glyph::quarternote),
+    ("qubuts", Glyph::qubuts),
+    ("qubuts18", Glyph::qubuts18),
+    ("qubuts25", Glyph::qubuts25),
+    ("qubuts31", Glyph::qubuts31),
+    ("qubutshebrew", Glyph::qubutshebrew),
+    ("qubutsnarrowhebrew", Glyph::qubutsnarrowhebrew),
+    ("qubutsquarterhebrew", Glyph::qubutsquarterhebrew),
+    ("qubutswidehebrew", Glyph::qubutswidehebrew),
+    ("question", Glyph::question),
+    ("questionarabic", Glyph::questionarabic),
+    ("questionarmenian", Glyph::questionarmenian),
+    ("questiondown", Glyph::questiondown),
+    ("questiondownsmall", Glyph::questiondownsmall),
+    ("questiongreek", Glyph::questiongreek),
+    ("questionmonospace", Glyph::questionmonospace),
+    ("questionsmall", Glyph::questionsmall),
+    ("quotedbl", Glyph::quotedbl),
+    ("quotedblbase", Glyph::quotedblbase),
+    ("quotedblleft", Glyph::quotedblleft),
+    ("quotedblmonospace", Glyph::quotedblmonospace),
+    ("quotedblprime", Glyph::quotedblprime),
+    ("quotedblprimereversed", Glyph::quotedblprimereversed),
+    ("quotedblright", Glyph::quotedblright),
+    ("quoteleft", Glyph::quoteleft),
+    ("quoteleftreversed", Glyph::quoteleftreversed),
+    ("quotereversed", Glyph::quotereversed),
+    ("quoteright", Glyph::quoteright),
+    ("quoterightn", Glyph::quoterightn),
+    ("quotesinglbase", Glyph::quotesinglbase),
+    ("quotesingle", Glyph::quotesingle),
+    ("quotesinglemonospace", Glyph::quotesinglemonospace),
+    ("r", Glyph::r),
+    ("raarmenian", Glyph::raarmenian),
+    ("rabengali", Glyph::rabengali),
+    ("racute", Glyph::racute),
+    ("radeva", Glyph::radeva),
+    ("radical", Glyph::radical),
+    ("radicalBig", Glyph::radicalBig),
+    ("radicalBigg", Glyph::radicalBigg),
+    ("radicalbig", Glyph::radicalbig),
+    ("radicalbigg", Glyph::radicalbigg),
+    ("radicalbt", Glyph::radicalbt),
+    ("radicalex", Glyph::radicalex),
+    ("radoverssquare", Glyph::radoverssquare),
+    ("radoverssquaredsquare", Glyph::radoverssquaredsquare),
+    ("radsquare", Glyph::radsquare),
+    ("rafe", Glyph::rafe),
+    ("rafehebrew", Glyph::rafehebrew),
+    ("ragujarati", Glyph::ragujarati),
+    ("ragurmukhi", Glyph::ragurmukhi),
+    ("rahiragana", Glyph::rahiragana),
+    ("rakatakana", Glyph::rakatakana),
+    ("rakatakanahalfwidth", Glyph::rakatakanahalfwidth),
+    ("ralowerdiagonalbengali", Glyph::ralowerdiagonalbengali),
+    ("ramiddlediagonalbengali", Glyph::ramiddlediagonalbengali),
+    ("ramshorn", Glyph::ramshorn),
+    ("rangedash", Glyph::rangedash),
+    ("ratio", Glyph::ratio),
+    ("rbopomofo", Glyph::rbopomofo),
+    ("rcaron", Glyph::rcaron),
+    ("rcedilla", Glyph::rcedilla),
+    ("rcircle", Glyph::rcircle),
+    ("rcommaaccent", Glyph::rcommaaccent),
+    ("rdblgrave", Glyph::rdblgrave),
+    ("rdotaccent", Glyph::rdotaccent),
+    ("rdotbelow", Glyph::rdotbelow),
+    ("rdotbelowmacron", Glyph::rdotbelowmacron),
+    ("referencemark", Glyph::referencemark),
+    ("reflexsubset", Glyph::reflexsubset),
+    ("reflexsuperset", Glyph::reflexsuperset),
+    ("registered", Glyph::registered),
+    ("registersans", Glyph::registersans),
+    ("registerserif", Glyph::registerserif),
+    ("reharabic", Glyph::reharabic),
+    ("reharmenian", Glyph::reharmenian),
+    ("rehfinalarabic", Glyph::rehfinalarabic),
+    ("rehiragana", Glyph::rehiragana),
+    ("rehyehaleflamarabic", Glyph::rehyehaleflamarabic),
+    ("rekatakana", Glyph::rekatakana),
+    ("rekatakanahalfwidth", Glyph::rekatakanahalfwidth),
+    ("resh", Glyph::resh),
+    ("reshdageshhebrew", Glyph::reshdageshhebrew),
+    ("reshhatafpatah", Glyph::reshhatafpatah),
+    ("reshhatafpatahhebrew", Glyph::reshhatafpatahhebrew),
+    ("reshhatafsegol", Glyph::reshhatafsegol),
+    ("reshhatafsegolhebrew", Glyph::reshhatafsegolhebrew),
+    ("reshhebrew", Glyph::reshhebrew),
+    ("reshhiriq", Glyph::reshhiriq),
+    ("reshhiriqhebrew", Glyph::reshhiriqhebrew),
+    ("reshholam", Glyph::reshholam),
+    ("reshholamhebrew", Glyph::reshholamhebrew),
+    ("reshpatah", Glyph::reshpatah),
+    ("reshpatahhebrew", Glyph::reshpatahhebrew),
+    ("reshqamats", Glyph::reshqamats),
+    ("reshqamatshebrew", Glyph::reshqamatshebrew),
+    ("reshqubuts", Glyph::reshqubuts),
+    ("reshqubutshebrew", Glyph::reshqubutshebrew),
+    ("reshsegol", Glyph::reshsegol),
+    ("reshsegolhebrew", Glyph::reshsegolhebrew),
+    ("reshsheva", Glyph::reshsheva),
+    ("reshshevahebrew", Glyph::reshshevahebrew),
+    ("reshtsere", Glyph::reshtsere),
+    ("reshtserehebrew", Glyph::reshtserehebrew),
+    ("reversedtilde", Glyph::reversedtilde),
+    ("reviahebrew", Glyph::reviahebrew),
+    ("reviamugrashhebrew", Glyph::reviamugrashhebrew),
+    ("revlogicalnot", Glyph::revlogicalnot),
+    ("rfishhook", Glyph::rfishhook),
+    ("rfishhookreversed", Glyph::rfishhookreversed),
+    ("rhabengali", Glyph::rhabengali),
+    ("rhadeva", Glyph::rhadeva),
+    ("rho", Glyph::rho),
+    ("rho1", Glyph::rho1),
+    ("rhook", Glyph::rhook),
+    ("rhookturned", Glyph::rhookturned),
+    ("rhookturnedsuperior", Glyph::rhookturnedsuperior),
+    ("rhosymbolgreek", Glyph::rhosymbolgreek),
+    ("rhotichookmod", Glyph::rhotichookmod),
+    ("rieulacirclekorean", Glyph::rieulacirclekorean),
+    ("rieulaparenkorean", Glyph::rieulaparenkorean),
+    ("rieulcirclekorean", Glyph::rieulcirclekorean),
+    ("rieulhieuhkorean", Glyph::rieulhieuhkorean),
+    ("rieulkiyeokkorean", Glyph::rieulkiyeokkorean),
+    ("rieulkiyeoksioskorean", Glyph::rieulkiyeoksioskorean),
+    ("rieulkorean", Glyph::rieulkorean),
+    ("rieulmieumkorean", Glyph::rieulmieumkorean),
+    ("rieulpansioskorean", Glyph::rieulpansioskorean),
+    ("rieulparenkorean", Glyph::rieulparenkorean),
+    ("rieulphieuphkorean", Glyph::rieulphieuphkorean),
+    ("rieulpieupkorean", Glyph::rieulpieupkorean),
+    ("rieulpieupsioskorean", Glyph::rieulpieupsioskorean),
+    ("rieulsioskorean", Glyph::rieulsioskorean),
+    ("rieulthieuthkorean", Glyph::rieulthieuthkorean),
+    ("rieultikeutkorean", Glyph::rieultikeutkorean),
+    ("rieulyeorinhieuhkorean", Glyph::rieulyeorinhieuhkorean),
+    ("rightangle", Glyph::rightangle),
+    ("righttackbelowcmb", Glyph::righttackbelowcmb),
+    ("righttriangle", Glyph::righttriangle),
+    ("rihiragana", Glyph::rihiragana),
+    ("rikatakana", Glyph::rikatakana),
+    ("rikatakanahalfwidth", Glyph::rikatakanahalfwidth),
+    ("ring", Glyph::ring),
+    ("ringbelowcmb", Glyph::ringbelowcmb),
+    ("ringcmb", Glyph::ringcmb),
+    ("ringhalfleft", Glyph::ringhalfleft),
+    ("ringhalfleftarmenian", Glyph::ringhalfleftarmenian),
+    ("ringhalfleftbelowcmb", Glyph::ringhalfleftbelowcmb),
+    ("ringhalfleftcentered", Glyph::ringhalfleftcentered),
+    ("ringhalfright", Glyph::ringhalfright),
+    ("ringhalfrightbelowcmb", Glyph::ringhalfrightbelowcmb),
+    ("ringhalfrightcentered", Glyph::ringhalfrightcentered),
+    ("rinvertedbreve", Glyph::rinvertedbreve),
+    ("rittorusquare", Glyph::rittorusquare),
+    ("rlinebelow", Glyph::rlinebelow),
+    ("rlongleg", Glyph::rlongleg),
+    ("rlonglegturned", Glyph::rlonglegturned),
+    ("rmonospace", Glyph::rmonospace),
+    ("rohiragana", Glyph::rohiragana),
+    ("rokatakana", Glyph::rokatakana),
+    ("rokatakanahalfwidth", Glyph::rokatakanahalfwidth),
+    ("roruathai", Glyph::roruathai),
+    ("rparen", Glyph::rparen),
+    ("rrabengali", Glyph::rrabengali),
+    ("rradeva", Glyph::rradeva),
+    ("rragurmukhi", Glyph::rragurmukhi),
+    ("rreharabic", Glyph::rreharabic),
+    ("rrehfinalarabic", Glyph::rrehfinalarabic),
+    ("rrvocalicbengali", Glyph::rrvocalicbengali),
+    ("rrvocalicdeva", Glyph::rrvocalicdeva),
+    ("rrvocalicgujarati", Glyph::rrvocalicgujarati),
+    ("rrvocalicvowelsignbengali", Glyph::rrvocalicvowelsignbengali),
+    ("rrvocalicvowelsigndeva", Glyph::rrvocalicvowelsigndeva),
+    ("rrvocalicvowelsigngujarati", Glyph::rrvocalicvowelsigngujarati),
+    ("rsuperior", Glyph::rsuperior),
+    ("rtblock", Glyph::rtblock),
+    ("rturned", Glyph::rturned),
+    ("rturnedsuperior", Glyph::rturnedsuperior),
+    ("ruhiragana", Glyph::ruhiragana),
+    ("rukatakana", Glyph::rukatakana),
+    ("rukatakanahalfwidth", Glyph::rukatakanahalfwidth),
+    ("rupeemarkbengali", Glyph::rupeemarkbengali),
+    ("rupeesignbengali", Glyph::rupeesignbengali),
+    ("rupiah", Glyph::rupiah),
+    ("ruthai", Glyph::ruthai),
+    ("rvocalicbengali", Glyph::rvocalicbengali),
+    ("rvocalicdeva", Glyph::rvocalicdeva),
+    ("rvocalicgujarati", Glyph::rvocalicgujarati),
+    ("rvocalicvowelsignbengali", Glyph::rvocalicvowelsignbengali),
+    ("rvocalicvowelsigndeva", Glyph::rvocalicvowelsigndeva),
+    ("rvocalicvowelsigngujarati", Glyph::rvocalicvowelsigngujarati),
+    ("s", Glyph::s),
+    ("sabengali", Glyph::sabengali),
+    ("sacute", Glyph::sacute),
+    ("sacutedotaccent", Glyph::sacutedotaccent),
+    ("sadarabic", Glyph::sadarabic),
+    ("sadeva", Glyph::sadeva),
+    ("sadfinalarabic", Glyph::sadfinalarabic),
+    ("sadinitialarabic", Glyph::sadinitialarabic),
+    ("sadmedialarabic", Glyph::sadmedialarabic),
+    ("sagujarati", Glyph::sagujarati),
+    ("sagurmukhi", Glyph::sagurmukhi),
+    ("sahiragana", Glyph::sahiragana),
+    ("sakatakana", Glyph::sakatakana),
+    ("sakatakanahalfwidth", Glyph::sakatakanahalfwidth),
+    (
+        "sallallahoualayhewasallamarabic",
+        Glyph::sallallahoualayhewasallamarabic,
+    ),
+    ("samekh", Glyph::samekh),
+    ("samekhdagesh", Glyph::samekhdagesh),
+    ("samekhdageshhebrew", Glyph::samekhdageshhebrew),
+    ("samekhhebrew", Glyph::samekhhebrew),
+    ("saraaathai", Glyph::saraaathai),
+    ("saraaethai", Glyph::saraaethai),
+    ("saraaimaimalaithai", Glyph::saraaimaimalaithai),
+    ("saraaimaimuanthai", Glyph::saraaimaimuanthai),
+    ("saraamthai", Glyph::saraamthai),
+    ("saraathai", Glyph::saraathai),
+    ("saraethai", Glyph::saraethai),
+    ("saraiileftthai", Glyph::saraiileftthai),
+    ("saraiithai", Glyph::saraiithai),
+    ("saraileftthai", Glyph::saraileftthai),
+    ("saraithai", Glyph::saraithai),
+    ("saraothai", Glyph::saraothai),
+    ("saraueeleftthai", Glyph::saraueeleftthai),
+    ("saraueethai", Glyph::saraueethai),
+    ("saraueleftthai", Glyph::saraueleftthai),
+    ("sarauethai", Glyph::sarauethai),
+    ("sarauthai", Glyph::sarauthai),
+    ("sarauuthai", Glyph::sarauuthai),
+    ("sbopomofo", Glyph::sbopomofo),
+    ("scaron", Glyph::scaron),
+    ("scarondotaccent", Glyph::scarondotaccent),
+    ("scedilla", Glyph::scedilla),
+    ("schwa", Glyph::schwa),
+    ("schwacyrillic", Glyph::schwacyrillic),
+    ("schwadieresiscyrillic", Glyph::schwadieresiscyrillic),
+    ("schwahook", Glyph::schwahook),
+    ("scircle", Glyph::scircle),
+    ("scircumflex", Glyph::scircumflex),
+    ("scommaaccent", Glyph::scommaaccent),
+    ("sdotaccent", Glyph::sdotaccent),
+    ("sdotbelow", Glyph::sdotbelow),
+    ("sdotbelowdotaccent", Glyph::sdotbelowdotaccent),
+    ("seagullbelowcmb", Glyph::seagullbelowcmb),
+    ("second", Glyph::second),
+    ("secondtonechinese", Glyph::secondtonechinese),
+    ("section", Glyph::section),
+    ("seenarabic", Glyph::seenarabic),
+    ("seenfinalarabic", Glyph::seenfinalarabic),
+    ("seeninitialarabic", Glyph::seeninitialarabic),
+    ("seenmedialarabic", Glyph::seenmedialarabic),
+    ("segol", Glyph::segol),
+    ("segol13", Glyph::segol13),
+    ("segol1f", Glyph::segol1f),
+    ("segol2c", Glyph::segol2c),
+    ("segolhebrew", Glyph::segolhebrew),
+    ("segolnarrowhebrew", Glyph::segolnarrowhebrew),
+    ("segolquarterhebrew", Glyph::segolquarterhebrew),
+    ("segoltahebrew", Glyph::segoltahebrew),
+    ("segolwidehebrew", Glyph::segolwidehebrew),
+    ("seharmenian", Glyph::seharmenian),
+    ("sehiragana", Glyph::sehiragana),
+    ("sekatakana", Glyph::sekatakana),
+    ("sekatakanahalfwidth", Glyph::sekatakanahalfwidth),
+    ("semicolon", Glyph::semicolon),
+    ("semicolonarabic", Glyph::semicolonarabic),
+    ("semicolonmonospace", Glyph::semicolonmonospace),
+    ("semicolonsmall", Glyph::semicolonsmall),
+    ("semivoicedmarkkana", Glyph::semivoicedmarkkana),
+    ("semivoicedmarkkanahalfwidth", Glyph::semivoicedmarkkanahalfwidth),
+    ("sentisquare", Glyph::sentisquare),
+    ("sentosquare", Glyph::sentosquare),
+    ("seven", Glyph::seven),
+    ("sevenarabic", Glyph::sevenarabic),
+    ("sevenbengali", Glyph::sevenbengali),
+    ("sevencircle", Glyph::sevencircle),
+    ("sevencircleinversesansserif", Glyph::sevencircleinversesansserif),
+    ("sevendeva", Glyph::sevendeva),
+    ("seveneighths", Glyph::seveneighths),
+    ("sevengujarati", Glyph::sevengujarati),
+    ("sevengurmukhi", Glyph::sevengurmukhi),
+    ("sevenhackarabic", Glyph::sevenhackarabic),
+    ("sevenhangzhou", Glyph::sevenhangzhou),
+    ("sevenideographicparen", Glyph::sevenideographicparen),
+    ("seveninferior", Glyph::seveninferior),
+    ("sevenmonospace", Glyph::sevenmonospace),
+    ("sevenoldstyle", Glyph::sevenoldstyle),
+    ("sevenparen", Glyph::sevenparen),
+    ("sevenperiod", Glyph::sevenperiod),
+    ("sevenpersian", Glyph::sevenpersian),
+    ("sevenroman", Glyph::sevenroman),
+    ("sevensuperior", Glyph::sevensuperior),
+    ("seventeencircle", Glyph::seventeencircle),
+    ("seventeenparen", Glyph::seventeenparen),
+    ("seventeenperiod", Glyph::seventeenperiod),
+    ("seventhai", Glyph::seventhai),
+    ("sfthyphen", Glyph::sfthyphen),
+    ("shaarmenian", Glyph::shaarmenian),
+    ("shabengali", Glyph::shabengali),
+    ("shacyrillic", Glyph::shacyrillic),
+    ("shaddaarabic", Glyph::shaddaarabic),
+    ("shaddadammaarabic", Glyph::shaddadammaarabic),
+    ("shaddadammatanarabic", Glyph::shaddadammatanarabic),
+    ("shaddafathaarabic", Glyph::shaddafathaarabic),
+    ("shaddafathatanarabic", Glyph::shaddafathatanarabic),
+    ("shaddakasraarabic", Glyph::shaddakasraarabic),
+    ("shaddakasratanarabic", Glyph::shaddakasratanarabic),
+    ("shade", Glyph::shade),
+    ("shadedark", Glyph::shadedark),
+    ("shadelight", Glyph::shadelight),
+    ("shademedium", Glyph::shademedium),
+    ("shadeva", Glyph::shadeva),
+    ("shagujarati", Glyph::shagujarati),
+    ("shagurmukhi", Glyph::shagurmukhi),
+    ("shalshelethebrew", Glyph::shalshelethebrew),
+    ("sharp", Glyph::sharp),
+    ("shbopomofo", Glyph::shbopomofo),
+    ("shchacyrillic", Glyph::shchacyrillic),
+    ("sheenarabic", Glyph::sheenarabic),
+    ("sheenfinalarabic", Glyph::sheenfinalarabic),
+    ("sheeninitialarabic", Glyph::sheeninitialarabic),
+    ("sheenmedialarabic", Glyph::sheenmedialarabic),
+    ("sheicoptic", Glyph::sheicoptic),
+    ("sheqel", Glyph::sheqel),
+    ("sheqelhebrew", Glyph::sheqelhebrew),
+    ("sheva", Glyph::sheva),
+    ("sheva115", Glyph::sheva115),
+    ("sheva15", Glyph::sheva15),
+    ("sheva22", Glyph::sheva22),
+    ("sheva2e", Glyph::sheva2e),
+    ("shevahebrew", Glyph::shevahebrew),
+    ("shevanarrowhebrew", Glyph::shevanarrowhebrew),
+    ("shevaquarterhebrew", Glyph::shevaquarterhebrew),
+    ("shevawidehebrew", Glyph::shevawidehebrew),
+    ("shhacyrillic", Glyph::shhacyrillic),
+    ("shimacoptic", Glyph::shimacoptic),
+    ("shin", Glyph::shin),
+    ("shindagesh", Glyph::shindagesh),
+    ("shindageshhebrew", Glyph::shindageshhebrew),
+    ("shindageshshindot", Glyph::shindageshshindot),
+    ("shindageshshindothebrew", Glyph::shindageshshindothebrew),
+    ("shindageshsindot", Glyph::shindageshsindot),
+    ("shindageshsindothebrew", Glyph::shindageshsindothebrew),
+    ("shindothebrew", Glyph::shindothebrew),
+    ("shinhebrew", Glyph::shinhebrew),
+    ("shinshindot", Glyph::shinshindot),
+    ("shinshindothebrew", Glyph::shinshindothebrew),
+    ("shinsindot", Glyph::shinsindot),
+    ("shinsindothebrew", Glyph::shinsindothebrew),
+    ("shook", Glyph::shook),
+    ("sigma", Glyph::sigma),
+    ("sigma1", Glyph::sigma1),
+    ("sigmafinal", Glyph::sigmafinal),
+    ("sigmalunatesymbolgreek", Glyph::sigmalunatesymbolgreek),
+    ("sihiragana", Glyph::sihiragana),
+    ("sikatakana", Glyph::sikatakana),
+    ("sikatakanahalfwidth", Glyph::sikatakanahalfwidth),
+    ("siluqhebrew", Glyph::siluqhebrew),
+    ("siluqlefthebrew", Glyph::siluqlefthebrew),
+    ("similar", Glyph::similar),
+    ("similarequal", Glyph::similarequal),
+    ("sindothebrew", Glyph::sindothebrew),
+    ("siosacirclekorean", Glyph::siosacirclekorean),
+    ("siosaparenkorean", Glyph::siosaparenkorean),
+    ("sioscieuckorean", Glyph::sioscieuckorean),
+    ("sioscirclekorean", Glyph::sioscirclekorean),
+    ("sioskiyeokkorean", Glyph::sioskiyeokkorean),
+    ("sioskorean", Glyph::sioskorean),
+    ("siosnieunkorean", Glyph::siosnieunkorean),
+    ("siosparenkorean", Glyph::siosparenkorean),
+    ("siospieupkorean", Glyph::siospieupkorean),
+    ("siostikeutkorean", Glyph::siostikeutkorean),
+    ("six", Glyph::six),
+    ("sixarabic", Glyph::sixarabic),
+    ("sixbengali", Glyph::sixbengali),
+    ("sixcircle", Glyph::sixcircle),
+    ("sixcircleinversesansserif", Glyph::sixcircleinversesansserif),
+    ("sixdeva", Glyph::sixdeva),
+    ("sixgujarati", Glyph::sixgujarati),
+    ("sixgurmukhi", Glyph::sixgurmukhi),
+    ("sixhackarabic", Glyph::sixhackarabic),
+    ("sixhangzhou", Glyph::sixhangzhou),
+    ("sixideographicparen", Glyph::sixideographicparen),
+    ("sixinferior", Glyph::sixinferior),
+    ("sixmonospace", Glyph::sixmonospace),
+    ("sixoldstyle", Glyph::sixoldstyle),
+    ("sixparen", Glyph::sixparen),
+    ("sixperiod", Glyph::sixperiod),
+    ("sixpersian", Glyph::sixpersian),
+    ("sixroman", Glyph::sixroman),
+    ("sixsuperior", Glyph::sixsuperior),
+    ("sixteencircle", Glyph::sixteencircle),
+    (
+        "sixteencurrencydenominatorbengali",
+        Glyph::sixteencurrencydenominatorbengali,
+    ),
+    ("sixteenparen", Glyph::sixteenparen),
+    ("sixteenperiod", Glyph::sixteenperiod),
+    ("sixthai", Glyph::sixthai),
+    ("slash", Glyph::slash),
+    ("slashBig", Glyph::slashBig),
+    ("slashBigg", Glyph::slashBigg),
+    ("slashbig", Glyph::slashbig),
+    ("slashbigg", Glyph::slashbigg),
+    ("slashmonospace", Glyph::slashmonospace),
+    ("slong", Glyph::slong),
+    ("slongdotaccent", Glyph::slongdotaccent),
+    ("slurabove", Glyph::slurabove),
+    ("slurbelow", Glyph::slurbelow),
+    ("smileface", Glyph::smileface),
+    ("smonospace", Glyph::smonospace),
+    ("sofpasuqhebrew", Glyph::sofpasuqhebrew),
+    ("softhyphen", Glyph::softhyphen),
+    ("softsigncyrillic", Glyph::softsigncyrillic),
+    ("sohiragana", Glyph::sohiragana),
+    ("sokatakana", Glyph::sokatakana),
+    ("sokatakanahalfwidth", Glyph::sokatakanahalfwidth),
+    ("soliduslongoverlaycmb", Glyph::soliduslongoverlaycmb),
+    ("solidusshortoverlaycmb", Glyph::solidusshortoverlaycmb),
+    ("sorusithai", Glyph::sorusithai),
+    ("sosalathai", Glyph::sosalathai),
+    ("sosothai", Glyph::sosothai),
+    ("sosuathai", Glyph::sosuathai),
+    ("space", Glyph::space),
+    ("spacehackarabic", Glyph::spacehackarabic),
+    ("spade", Glyph::spade),
+    ("spadesuitblack", Glyph::spadesuitblack),
+    ("spadesuitwhite", Glyph::spadesuitwhite),
+    ("sparen", Glyph::sparen),
+    ("squarebelowcmb", Glyph::squarebelowcmb),
+    ("squarecc", Glyph::squarecc),
+    ("squarecm", Glyph::squarecm),
+    ("squarediagonalcrosshatchfill", Glyph::squarediagonalcrosshatchfill),
+    ("squarehorizontalfill", Glyph::squarehorizontalfill),
+    ("squarekg", Glyph::squarekg),
+    ("squarekm", Glyph::squarekm),
+    ("squarekmcapital", Glyph::squarekmcapital),
+    ("squareln", Glyph::squareln),
+    ("squarelog", Glyph::squarelog),
+    ("squaremg", Glyph::squaremg),
+    ("squaremil", Glyph::squaremil),
+    ("squaremm", Glyph::squaremm),
+    ("squaremsquared", Glyph::squaremsquared),
+    ("squareorthogonalcrosshatchfill", Glyph::squareorthogonalcrosshatchfill),
+    (
+        "squareupperlefttolowerrightfill",
+        Glyph::squareupperlefttolowerrightfill,
+    ),
+    (
+        "squareupperrighttolowerleftfill",
+        Glyph::squareupperrighttolowerleftfill,
+    ),
+    ("squareverticalfill", Glyph::squareverticalfill),
+    ("squarewhitewithsmallblack", Glyph::squarewhitewithsmallblack),
+    ("srsquare", Glyph::srsquare),
+    ("ssabengali", Glyph::ssabengali),
+    ("ssadeva", Glyph::ssadeva),
+    ("ssagujarati", Glyph::ssagujarati),
+    ("ssangcieuckorean", Glyph::ssangcieuckorean),
+    ("ssanghieuhkorean", Glyph::ssanghieuhkorean),
+    ("ssangieungkorean", Glyph::ssangieungkorean),
+    ("ssangkiyeokkorean", Glyph::ssangkiyeokkorean),
+    ("ssangnieunkorean", Glyph::ssangnieunkorean),
+    ("ssangpieupkorean", Glyph::ssangpieupkorean),
+    ("ssangsioskorean", Glyph::ssangsioskorean),
+    ("ssangtikeutkorean", Glyph::ssangtikeutkorean),
+    ("ssuperior", Glyph::ssuperior),
+    ("star", Glyph::star),
+    ("sterling", Glyph::sterling),
+    ("sterlingmonospace", Glyph::sterlingmonospace),
+    ("strokelongoverlaycmb", Glyph::strokelongoverlaycmb),
+    ("strokeshortoverlaycmb", Glyph::strokeshortoverlaycmb),
+    ("subset", Glyph::subset),
+    ("subsetnotequal", Glyph::subsetnotequal),
+    ("subsetorequal", Glyph::subsetorequal),
+    ("subsetsqequal", Glyph::subsetsqequal),
+    ("succeeds", Glyph::succeeds),
+    ("suchthat", Glyph::suchthat),
+    ("suhiragana", Glyph::suhiragana),
+    ("sukatakana", Glyph::sukatakana),
+    ("sukatakanahalfwidth", Glyph::sukatakanahalfwidth),
+    ("sukunarabic", Glyph::sukunarabic),
+    ("summation", Glyph::summation),
+    ("summationdisplay", Glyph::summationdisplay),
+    ("summationtext", Glyph::summationtext),
+    ("sun", Glyph::sun),
+    ("superset", Glyph::superset),
+    ("supersetnotequal", Glyph::supersetnotequal),
+    ("supersetorequal", Glyph::supersetorequal),
+    ("supersetsqequal", Glyph::supersetsqequal),
+    ("svsquare", Glyph::svsquare),
+    ("syouwaerasquare", Glyph::syouwaerasquare),
+    ("t", Glyph::t),
+    ("tabengali", Glyph::tabengali),
+    ("tackdown", Glyph::tackdown),
+    ("tackleft", Glyph::tackleft),
+    ("tadeva", Glyph::tadeva),
+    ("tagujarati", Glyph::tagujarati),
+    ("tagurmukhi", Glyph::tagurmukhi),
+    ("taharabic", Glyph::taharabic),
+    ("tahfinalarabic", Glyph::tahfinalarabic),
+    ("tahinitialarabic", Glyph::tahinitialarabic),
+    ("tahiragana", Glyph::tahiragana),
+    ("tahmedialarabic", Glyph::tahmedialarabic),
+    ("taisyouerasquare", Glyph::taisyouerasquare),
+    ("takatakana", Glyph::takatakana),
+    ("takatakanahalfwidth", Glyph::takatakanahalfwidth),
+    ("tatweelarabic", Glyph::tatweelarabic),
+    ("tau", Glyph::tau),
+    ("tav", Glyph::tav),
+    ("tavdages", Glyph::tavdages),
+    ("tavdagesh", Glyph::tavdagesh),
+    ("tavdageshhebrew", Glyph::tavdageshhebrew),
+    ("tavhebrew", Glyph::tavhebrew),
+    ("tbar", Glyph::tbar),
+    ("tbopomofo", Glyph::tbopomofo),
+    ("tcaron", Glyph::tcaron),
+    ("tccurl", Glyph::tccurl),
+    ("tcedilla", Glyph::tcedilla),
+    ("tcheharabic", Glyph::tcheharabic),
+    ("tchehfinalarabic", Glyph::tchehfinalarabic),
+    ("tchehinitialarabic", Glyph::tchehinitialarabic),
+    ("tchehmedialarabic", Glyph::tchehmedialarabic),
+    ("tchehmeeminitialarabic", Glyph::tchehmeeminitialarabic),
+    ("tcircle", Glyph::tcircle),
+    ("tcircumflexbelow", Glyph::tcircumflexbelow),
+    ("tcommaaccent", Glyph::tcommaaccent),
+    ("tdieresis", Glyph::tdieresis),
+    ("tdotaccent", Glyph::tdotaccent),
+    ("tdotbelow", Glyph::tdotbelow),
+    ("tecyrillic", Glyph::tecyrillic),
+    ("tedescendercyrillic", Glyph::tedescendercyrillic),
+    ("teharabic", Glyph::teharabic),
+    ("tehfinalarabic", Glyph::tehfinalarabic),
+    ("tehhahinitialarabic", Glyph::tehhahinitialarabic),
+    ("tehhahisolatedarabic", Glyph::tehhahisolatedarabic),
+    ("tehinitialarabic", Glyph::tehinitialarabic),
+    ("tehiragana", Glyph::tehiragana),
+    ("tehjeeminitialarabic", Glyph::tehjeeminitialarabic),
+    ("tehjeemisolatedarabic", Glyph::tehjeemisolatedarabic),
+    ("tehmarbutaarabic", Glyph::tehmarbutaarabic),
+    ("tehmarbutafinalarabic", Glyph::tehmarbutafinalarabic),
+    ("tehmedialarabic", Glyph::tehmedialarabic),
+    ("tehmeeminitialarabic", Glyph::tehmeeminitialarabic),
+    ("tehmeemisolatedarabic", Glyph::tehmeemisolatedarabic),
+    ("tehnoonfinalarabic", Glyph::tehnoonfinalarabic),
+    ("tekatakana", Glyph::tekatakana),
+    ("tekatakanahalfwidth", Glyph::tekatakanahalfwidth),
+    ("telephone", Glyph::telephone),
+    ("telephoneblack", Glyph::telephoneblack),
+    ("telishagedolahebrew", Glyph::telishagedolahebrew),
+    ("telishaqetanahebrew", Glyph::telishaqetanahebrew),
+    ("tencircle", Glyph::tencircle),
+    ("tenideographicparen", Glyph::tenideographicparen),
+    ("tenparen", Glyph::tenparen),
+    ("tenperiod", Glyph::tenperiod),
+    ("tenroman", Glyph::tenroman),
+    ("tesh", Glyph::tesh),
+    ("tet", Glyph::tet),
+    ("tetdagesh", Glyph::tetdagesh),
+    ("tetdageshhebrew", Glyph::tetdageshhebrew),
+    ("tethebrew", Glyph::tethebrew),
+    ("tetsecyrillic", Glyph::tetsecyrillic),
+    ("tevirhebrew", Glyph::tevirhebrew),
+    ("tevirlefthebrew", Glyph::tevirlefthebrew),
+    ("thabengali", Glyph::thabengali),
+    ("thadeva", Glyph::thadeva),
+    ("thagujarati", Glyph::thagujarati),
+    ("thagurmukhi", Glyph::thagurmukhi),
+    ("thalarabic", Glyph::thalarabic),
+    ("thalfinalarabic", Glyph::thalfinalarabic),
+    ("thanthakhatlowleftthai", Glyph::thanthakhatlowleftthai),
+    ("thanthakhatlowrightthai", Glyph::thanthakhatlowrightthai),
+    ("thanthakhatthai", Glyph::thanthakhatthai),
+    ("thanthakhatupperleftthai", Glyph::thanthakhatupperleftthai),
+    ("theharabic", Glyph::theharabic),
+    ("thehfinalarabic", Glyph::thehfinalarabic),
+    ("thehinitialarabic", Glyph::thehinitialarabic),
+    ("thehmedialarabic", Glyph::thehmedialarabic),
+    ("thereexists", Glyph::thereexists),
+    ("therefore", Glyph::therefore),
+    ("theta", Glyph::theta),
+    ("theta1", Glyph::theta1),
+    ("thetasymbolgreek", Glyph::thetasymbolgreek),
+    ("thieuthacirclekorean", Glyph::thieuthacirclekorean),
+    ("thieuthaparenkorean", Glyph::thieuthaparenkorean),
+    ("thieuthcirclekorean", Glyph::thieuthcirclekorean),
+    ("thieuthkorean", Glyph::thieuthkorean),
+    ("thieuthparenkorean", Glyph::thieuthparenkorean),
+    ("thirteencircle", Glyph::thirteencircle),
+    ("thirteenparen", Glyph::thirteenparen),
+    ("thirteenperiod", Glyph::thirteenperiod),
+    ("thonangmonthothai", Glyph::thonangmonthothai),
+    ("thook", Glyph::thook),
+    ("thophuthaothai", Glyph::thophuthaothai),
+    ("thorn", Glyph::thorn),
+    ("thothahanthai", Glyph::thothahanthai),
+    ("thothanthai", Glyph::thothanthai),
+    ("thothongthai", Glyph::thothongthai),
+    ("thothungthai", Glyph::thothungthai),
+    ("thousandcyrillic", Glyph::thousandcyrillic),
+    ("thousandsseparatorarabic", Glyph::thousandsseparatorarabic),
+    ("thousandsseparatorpersian", Glyph::thousandsseparatorpersian),
+    ("three", Glyph::three),
+    ("threearabic", Glyph::threearabic),
+    ("threebengali", Glyph::threebengali),
+    ("threecircle", Glyph::threecircle),
+    ("threecircleinversesansserif", Glyph::threecircleinversesansserif),
+    ("threedeva", Glyph::threedeva),
+    ("threeeighths", Glyph::threeeighths),
+    ("threegujarati", Glyph::threegujarati),
+    ("threegurmukhi", Glyph::threegurmukhi),
+    ("threehackarabic", Glyph::threehackarabic),
+    ("threehangzhou", Glyph::threehangzhou),
+    ("threeideographicparen", Glyph::threeideographicparen),
+    ("threeinferior", Glyph::threeinferior),
+    ("threemonospace", Glyph::threemonospace),
+    ("threenumeratorbengali", Glyph::threenumeratorbengali),
+    ("threeoldstyle", Glyph::threeoldstyle),
+    ("threeparen", Glyph::threeparen),
+    ("threeperiod", Glyph::threeperiod),
+    ("threepersian", Glyph::threepersian),
+    ("threequarters", Glyph::threequarters),
+    ("threequartersemdash", Glyph::threequartersemdash),
+    ("threeroman", Glyph::threeroman),
+    ("threesuperior", Glyph::threesuperior),
+    ("threethai", Glyph::threethai),
+    ("thzsquare", Glyph::thzsquare),
+    ("tie", Glyph::tie),
+    ("tihiragana", Glyph::tihiragana),
+    ("tikatakana", Glyph::tikatakana),
+    ("tikatakanahalfwidth", Glyph::tikatakanahalfwidth),
+    ("tikeutacirclekorean", Glyph::tikeutacirclekorean),
+    ("tikeutaparenkorean", Glyph::tikeutaparenkorean),
+    ("tikeutcirclekorean", Glyph::tikeutcirclekorean),
+    ("tikeutkorean", Glyph::tikeutkorean),
+    ("tikeutparenkorean", Glyph::tikeutparenkorean),
+    ("tilde", Glyph::tilde),
+    ("tildebelowcmb", Glyph::tildebelowcmb),
+    ("tildecmb", Glyph::tildecmb),
+    ("tildecomb", Glyph::tildecomb),
+    ("tildedoublecmb", Glyph::tildedoublecmb),
+    ("tildeoperator", Glyph::tildeoperator),
+    ("tildeoverlaycmb", Glyph::tildeoverlaycmb),
+    ("tildeverticalcmb", Glyph::tildeverticalcmb),
+    ("tildewide", Glyph::tildewide),
+    ("tildewider", Glyph::tildewider),
+    ("tildewiderr", Glyph::tildewiderr),
+    ("timescircle", Glyph::timescircle),
+    ("tipehahebrew", Glyph::tipehahebrew),
+    ("tipehalefthebrew", Glyph::tipehalefthebrew),
+    ("tippigurmukhi", Glyph::tippigurmukhi),
+    ("titlocyrilliccmb", Glyph::titlocyrilliccmb),
+    ("tiwnarmenian", Glyph::tiwnarmenian),
+    ("tlinebelow", Glyph::tlinebelow),
+    ("tmonospace", Glyph::tmonospace),
+    ("toarmenian", Glyph::toarmenian),
+    ("tohiragana", Glyph::tohiragana),
+    ("tokatakana", Glyph::tokatakana),
+    ("tokatakanahalfwidth", Glyph::tokatakanahalfwidth),
+    ("tonebarextrahighmod", Glyph::tonebarextrahighmod),
+    ("tonebarextralowmod", Glyph::tonebarextralowmod),
+    ("tonebarhighmod", Glyph::tonebarhighmod),
+    ("tonebarlowmod", Glyph::tonebarlowmod),
+    ("tonebarmidmod", Glyph::tonebarmidmod),
+    ("tonefive", Glyph::tonefive),
+    ("tonesix", Glyph::tonesix),
+    ("tonetwo", Glyph::tonetwo),
+    ("tonos", Glyph::tonos),
+    ("tonsquare", Glyph::tonsquare),
+    ("topatakthai", Glyph::topatakthai),
+    ("tortoiseshellbracketleft", Glyph::tortoiseshellbracketleft),
+    ("tortoiseshellbracketleftsmall", Glyph::tortoiseshellbracketleftsmall),
+    (
+        "tortoiseshellbracketleftvertical",
+        Glyph::tortoiseshellbracketleftvertical,
+    ),
+    ("tortoiseshellbracketright", Glyph::tortoiseshellbracketright),
+    ("tortoiseshellbracketrightsmall", Glyph::tortoiseshellbracketrightsmall),
+    (
+        "tortoiseshellbracketrightvertical",
+        Glyph::tortoiseshellbracketrightvertical,
+    ),
+    ("totaothai", Glyph::totaothai),
+    ("tpalatalhook", Glyph::tpalatalhook),
+    ("tparen", Glyph::tparen),
+    ("trademark", Glyph::trademark),
+    ("trademarksans", Glyph::trademarksans),
+    ("trademarkserif", Glyph::trademarkserif),
+    ("tretroflexhook", Glyph::tretroflexhook),
+    ("triagdn", Glyph::triagdn),
+    ("triaglf", Glyph::triaglf),
+    ("triagrt", Glyph::triagrt),
+    ("triagup", Glyph::triagup),
+    ("triangle", Glyph::triangle),
+    ("triangleinv", Glyph::triangleinv),
+    ("triangleleft", Glyph::triangleleft),
+    ("triangleright", Glyph::triangleright),
+    ("ts", Glyph::ts),
+    ("tsadi", Glyph::tsadi),
+    ("tsadidagesh", Glyph::tsadidagesh),
+    ("tsadidageshhebrew", Glyph::tsadidageshhebrew),
+    ("tsadihebrew", Glyph::tsadihebrew),
+    ("tsecyrillic", Glyph::tsecyrillic),
+    ("tsere", Glyph::tsere),
+    ("tsere12", Glyph::tsere12),
+    ("tsere1e", Glyph::tsere1e),
+    ("tsere2b", Glyph::tsere2b),
+    ("tserehebrew", Glyph::tserehebrew),
+    ("tserenarrowhebrew", Glyph::tserenarrowhebrew),
+    ("tserequarterhebrew", Glyph::tserequarterhebrew),
+    ("tserewidehebrew", Glyph::tserewidehebrew),
+    ("tshecyrillic", Glyph::tshecyrillic),
+    ("tsuperior", Glyph::tsuperior),
+    ("ttabengali", Glyph::ttabengali),
+    ("ttadeva", Glyph::ttadeva),
+    ("ttagujarati", Glyph::ttagujarati),
+    ("ttagurmukhi", Glyph::ttagurmukhi),
+    ("tteharabic", Glyph::tteharabic),
+    ("ttehfinalarabic", Glyph::ttehfinalarabic),
+    ("ttehinitialarabic", Glyph::ttehinitialarabic),
+    ("ttehmedialarabic", Glyph::ttehmedialarabic),
+    ("tthabengali", Glyph::tthabengali),
+    ("tthadeva", Glyph::tthadeva),
+    ("tthagujarati", Glyph::tthagujarati),
+    ("tthagurmukhi", Glyph::tthagurmukhi),
+    ("tturned", Glyph::tturned),
+    ("tuhiragana", Glyph::tuhiragana),
+    ("tukatakana", Glyph::tukatakana),
+    ("tukatakanahalfwidth", Glyph::tukatakanahalfwidth),
+    ("turnstileleft", Glyph::turnstileleft),
+    ("turnstileright", Glyph::turnstileright),
+    ("tusmallhiragana", Glyph::tusmallhiragana),
+    ("tusmallkatakana", Glyph::tusmallkatakana),
+    ("tusmallkatakanahalfwidth", Glyph::tusmallkatakanahalfwidth),
+    ("twelvecircle", Glyph::twelvecircle),
+    ("twelveparen", Glyph::twelveparen),
+    ("twelveperiod", Glyph::twelveperiod),
+    ("twelveroman", Glyph::twelveroman),
+    ("twelveudash", Glyph::twelveudash),
+    ("twentycircle", Glyph::twentycircle),
+    ("twentyhangzhou", Glyph::twentyhangzhou),
+    ("twentyparen", Glyph::twentyparen),
+    ("twentyperiod", Glyph::twentyperiod),
+    ("two", Glyph::two),
+    ("twoarabic", Glyph::twoarabic),
+    ("twobengali", Glyph::twobengali),
+    ("twocircle", Glyph::twocircle),
+    ("twocircleinversesansserif", Glyph::twocircleinversesansserif),
+    ("twodeva", Glyph::twodeva),
+    ("twodotenleader", Glyph::twodotenleader),
+    ("twodotleader", Glyph::twodotleader),
+    ("twodotleadervertical", Glyph::twodotleadervertical),
+    ("twogujarati", Glyph::twogujarati),
+    ("twogurmukhi", Glyph::twogurmukhi),
+    ("twohackarabic", Glyph::twohackarabic),
+    ("twohangzhou", Glyph::twohangzhou),
+    ("twoideographicparen", Glyph::twoideographicparen),
+    ("twoinferior", Glyph::twoinferior),
+    ("twomonospace", Glyph::twomonospace),
+    ("twonumeratorbengali", Glyph::twonumeratorbengali),
+    ("twooldstyle", Glyph::twooldstyle),
+    ("twoparen", Glyph::twoparen),
+    ("twoperiod", Glyph::twoperiod),
+    ("twopersian", Glyph::twopersian),
+    ("tworoman", Glyph::tworoman),
+    ("twostroke", Glyph::twostroke),
+    ("twosuperior", Glyph::twosuperior),
+    ("twothai", Glyph::twothai),
+    ("twothirds", Glyph::twothirds),
+    ("u", Glyph::u),
+    ("uacute", Glyph::uacute),
+    ("ubar", Glyph::ubar),
+    ("ubengali", Glyph::ubengali),
+    ("ubopomofo", Glyph::ubopomofo),
+    ("ubreve", Glyph::ubreve),
+    ("ucaron", Glyph::ucaron),
+    ("ucircle", Glyph::ucircle),
+    ("ucircumflex", Glyph::ucircumflex),
+    ("ucircumflexbelow", Glyph::ucircumflexbelow),
+    ("ucyrillic", Glyph::ucyrillic),
+    ("udattadeva", Glyph::udattadeva),
+    ("udblacute", Glyph::udblacute),
+    ("udblgrave", Glyph::udblgrave),
+    ("udeva", Glyph::udeva),
+    ("udieresis", Glyph::udieresis),
+    ("udieresisacute", Glyph::udieresisacute),
+    ("udieresisbelow", Glyph::udieresisbelow),
+    ("udieresiscaron", Glyph::udieresiscaron),
+    ("udieresiscyrillic", Glyph::udieresiscyrillic),
+    ("udieresisgrave", Glyph::udieresisgrave),
+    ("udieresismacron", Glyph::udieresismacron),
+    ("udotbelow", Glyph::udotbelow),
+    ("ugrave", Glyph::ugrave),
+    ("ugujarati", Glyph::ugujarati),
+    ("ugurmukhi", Glyph::ugurmukhi),
+    ("uhiragana", Glyph::uhiragana),
+    ("uhookabove", Glyph::uhookabove),
+    ("uhorn", Glyph::uhorn),
+    ("uhornacute", Glyph::uhornacute),
+    ("uhorndotbelow", Glyph::uhorndotbelow),
+    ("uhorngrave", Glyph::uhorngrave),
+    ("uhornhookabove", Glyph::uhornhookabove),
+    ("uhorntilde", Glyph::uhorntilde),
+    ("uhungarumlaut", Glyph::uhungarumlaut),
+    ("uhungarumlautcyrillic", Glyph::uhungarumlautcyrillic),
+    ("uinvertedbreve", Glyph::uinvertedbreve),
+    ("ukatakana", Glyph::ukatakana),
+    ("ukatakanahalfwidth", Glyph::ukatakanahalfwidth),
+    ("ukcyrillic", Glyph::ukcyrillic),
+    ("ukorean", Glyph::ukorean),
+    ("umacron", Glyph::umacron),
+    ("umacroncyrillic", Glyph::umacroncyrillic),
+    ("umacrondieresis", Glyph::umacrondieresis),
+    ("umatragurmukhi", Glyph::umatragurmukhi),
+    ("umonospace", Glyph::umonospace),
+    ("underscore", Glyph::underscore),
+    ("underscoredbl", Glyph::underscoredbl),
+    ("underscoremonospace", Glyph::underscoremonospace),
+    ("underscorevertical", Glyph::underscorevertical),
+    ("underscorewavy", Glyph::underscorewavy),
+    ("union", Glyph::union),
+    ("uniondisplay", Glyph::uniondisplay),
+    ("unionmulti", Glyph::unionmulti),
+    ("unionmultidisplay", Glyph::unionmultidisplay),
+    ("unionmultitext", Glyph::unionmultitext),
+    ("unionsq", Glyph::unionsq),
+    ("unionsqdisplay", Glyph::unionsqdisplay),
+    ("unionsqtext", Glyph::unionsqtext),
+    ("uniontext", Glyph::uniontext),
+    ("universal", Glyph::universal),
+    ("uogonek", Glyph::uogonek),
+    ("uparen", Glyph::uparen),
+    ("upblock", Glyph::upblock),
+    ("upperdothebrew", Glyph::upperdothebrew),
+    ("upsilon", Glyph::upsilon),
+    ("upsilondieresis", Glyph::upsilondieresis),
+    ("upsilondieresistonos", Glyph::upsilondieresistonos),
+    ("upsilonlatin", Glyph::upsilonlatin),
+    ("upsilontonos", Glyph::upsilontonos),
+    ("uptackbelowcmb", Glyph::uptackbelowcmb),
+    ("uptackmod", Glyph::uptackmod),
+    ("uragurmukhi", Glyph::uragurmukhi),
+    ("uring", Glyph::uring),
+    ("ushortcyrillic", Glyph::ushortcyrillic),
+    ("usmallhiragana", Glyph::usmallhiragana),
+    ("usmallkatakana", Glyph::usmallkatakana),
+    ("usmallkatakanahalfwidth", Glyph::usmallkatakanahalfwidth),
+    ("ustraightcyrillic", Glyph::ustraightcyrillic),
+    ("ustraightstrokecyrillic", Glyph::ustraightstrokecyrillic),
+    ("utilde", Glyph::utilde),
+    ("utildeacute", Glyph::utildeacute),
+    ("utildebelow", Glyph::utildebelow),
+    ("uubengali", Glyph::uubengali),
+    ("uudeva", Glyph::uudeva),
+    ("uugujarati", Glyph::uugujarati),
+    ("uugurmukhi", Glyph::uugurmukhi),
+    ("uumatragurmukhi", Glyph::uumatragurmukhi),
+    ("uuvowelsignbengali", Glyph::uuvowelsignbengali),
+    ("uuvowelsigndeva", Glyph::uuvowelsigndeva),
+    ("uuvowelsigngujarati", Glyph::uuvowelsigngujarati),
+    ("uvowelsignbengali", Glyph::uvowelsignbengali),
+    ("uvowelsigndeva", Glyph::uvowelsigndeva),
+    ("uvowelsigngujarati", Glyph::uvowelsigngujarati),
+    ("v", Glyph::v),
+    ("vadeva", Glyph::vadeva),
+    ("vagujarati", Glyph::vagujarati),
+    ("vagurmukhi", Glyph::vagurmukhi),
+    ("vakatakana", Glyph::vakatakana),
+    ("vav", Glyph::vav),
+    ("vavdagesh", Glyph::vavdagesh),
+    ("vavdagesh65", Glyph::vavdagesh65),
+    ("vavdageshhebrew", Glyph::vavdageshhebrew),
+    ("vavhebrew", Glyph::vavhebrew),
+    ("vavholam", Glyph::vavholam),
+    ("vavholamhebrew", Glyph::vavholamhebrew),
+    ("vavvavhebrew", Glyph::vavvavhebrew),
+    ("vavyodhebrew", Glyph::vavyodhebrew),
+    ("vcircle", Glyph::vcircle),
+    ("vdotbelow", Glyph::vdotbelow),
+    ("vector", Glyph::vector),
+    ("vecyrillic", Glyph::vecyrillic),
+    ("veharabic", Glyph::veharabic),
+    ("vehfinalarabic", Glyph::vehfinalarabic),
+    ("vehinitialarabic", Glyph::vehinitialarabic),
+    ("vehmedialarabic", Glyph::vehmedialarabic),
+    ("vekatakana", Glyph::vekatakana),
+    ("venus", Glyph::venus),
+    ("verticalbar", Glyph::verticalbar),
+    ("verticallineabovecmb", Glyph::verticallineabovecmb),
+    ("verticallinebelowcmb", Glyph::verticallinebelowcmb),
+    ("verticallinelowmod", Glyph::verticallinelowmod),
+    ("verticallinemod", Glyph::verticallinemod),
+    ("vewarmenian", Glyph::vewarmenian),
+    ("vhook", Glyph::vhook),
+    ("vikatakana", Glyph::vikatakana),
+    ("viramabengali", Glyph::viramabengali),
+    ("viramadeva", Glyph::viramadeva),
+    ("viramagujarati", Glyph::viramagujarati),
+    ("visargabengali", Glyph::visargabengali),
+    ("visargadeva", Glyph::visargadeva),
+    ("visargagujarati", Glyph::visargagujarati),
+    ("visiblespace", Glyph::visiblespace),
+    ("visualspace", Glyph::visualspace),
+    ("vmonospace", Glyph::vmonospace),
+    ("voarmenian", Glyph::voarmenian),
+    ("voicediterationhiragana", Glyph::voicediterationhiragana),
+    ("voicediterationkatakana", Glyph::voicediterationkatakana),
+    ("voicedmarkkana", Glyph::voicedmarkkana),
+    ("voicedmarkkanahalfwidth", Glyph::voicedmarkkanahalfwidth),
+    ("vokatakana", Glyph::vokatakana),
+    ("vparen", Glyph::vparen),
+    ("vtilde", Glyph::vtilde),
+    ("vturned", Glyph::vturned),
+    ("vuhiragana", Glyph::vuhiragana),
+    ("vukatakana", Glyph::vukatakana),
+    ("w", Glyph::w),
+    ("wacute", Glyph::wacute),
+    ("waekorean", Glyph::waekorean),
+    ("wahiragana", Glyph::wahiragana),
+    ("wakatakana", Glyph::wakatakana),
+    ("wakatakanahalfwidth", Glyph::wakatakanahalfwidth),
+    ("wakorean", Glyph::wakorean),
+    ("wasmallhiragana", Glyph::wasmallhiragana),
+    ("wasmallkatakana", Glyph::wasmallkatakana),
+    ("wattosquare", Glyph::wattosquare),
+    ("wavedash", Glyph::wavedash),
+    ("wavyunderscorevertical", Glyph::wavyunderscorevertical),
+    ("wawarabic", Glyph::wawarabic),
+    ("wawfinalarabic", Glyph::wawfinalarabic),
+    ("wawhamzaabovearabic", Glyph::wawhamzaabovearabic),
+    ("wawhamzaabovefinalarabic", Glyph::wawhamzaabovefinalarabic),
+    ("wbsquare", Glyph::wbsquare),
+    ("wcircle", Glyph::wcircle),
+    ("wcircumflex", Glyph::wcircumflex),
+    ("wdieresis", Glyph::wdieresis),
+    ("wdotaccent", Glyph::wdotaccent),
+    ("wdotbelow", Glyph::wdotbelow),
+    ("wehiragana", Glyph::wehiragana),
+    ("weierstrass", Glyph::weierstrass),
+    ("wekatakana", Glyph::wekatakana),
+    ("wekorean", Glyph::wekorean),
+    ("weokorean", Glyph::weokorean),
+    ("wgrave", Glyph::wgrave),
+    ("whitebullet", Glyph::whitebullet),
+    ("whitecircle", Glyph::whitecircle),
+    ("whitecircleinverse", Glyph::whitecircleinverse),
+    ("whitecornerbracketleft", Glyph::whitecornerbracketleft),
+    ("whitecornerbracketleftvertical", Glyph::whitecornerbracketleftvertical),
+    ("whitecornerbracketright", Glyph::whitecornerbracketright),
+    (
+        "whitecornerbracketrightvertical",
+        Glyph::whitecornerbracketrightvertical,
+    ),
+    ("whitediamond", Glyph::whitediamond),
+    (
+        "whitediamondcontainingblacksmalldiamond",
+        Glyph::whitediamondcontainingblacksmalldiamond,
+    ),
+    ("whitedownpointingsmalltriangle", Glyph::whitedownpointingsmalltriangle),
+    ("whitedownpointingtriangle", Glyph::whitedownpointingtriangle),
+    ("whiteleftpointingsmalltriangle", Glyph::whiteleftpointingsmalltriangle),
+    ("whiteleftpointingtriangle", Glyph::whiteleftpointingtriangle),
+    ("whitelenticularbracketleft", Glyph::whitelenticularbracketleft),
+    ("whitelenticularbracketright", Glyph::whitelenticularbracketright),
+    (
+        "whiterightpointingsmalltriangle",
+        Glyph::whiterightpointingsmalltriangle,
+    ),
+    ("whiterightpointingtriangle", Glyph::whiterightpointingtriangle),
+    ("whitesmallsquare", Glyph::whitesmallsquare),
+    ("whitesmilingface", Glyph::whitesmilingface),
+    ("whitesquare", Glyph::whitesquare),
+    ("whitestar", Glyph::whitestar),
+    ("whitetelephone", Glyph::whitetelephone),
+    ("whitetortoiseshellbracketleft", Glyph::whitetortoiseshellbracketleft),
+    ("whitetortoiseshellbracketright", Glyph::whitetortoiseshellbracketright),
+    ("whiteuppointingsmalltriangle", Glyph::whiteuppointingsmalltriangle),
+    ("whiteuppointingtriangle", Glyph::whiteuppointingtriangle),
+    ("wihiragana", Glyph::wihiragana),
+    ("wikatakana", Glyph::wikatakana),
+    ("wikorean", Glyph::wikorean),
+    ("wmonospace", Glyph::wmonospace),
+    ("wohiragana", Glyph::wohiragana),
+    ("wokatakana", Glyph::wokatakana),
+    ("wokatakanahalfwidth", Glyph::wokatakanahalfwidth),
+    ("won", Glyph::won),
+    ("wonmonospace", Glyph::wonmonospace),
+    ("wowaenthai", Glyph::wowaenthai),
+    ("wparen", Glyph::wparen),
+    ("wreathproduct", Glyph::wreathproduct),
+    ("wring", Glyph::wring),
+    ("wsuperior", Glyph::wsuperior),
+    ("wturned", Glyph::wturned),
+    ("wynn", Glyph::wynn),
+    ("x", Glyph::x),
+    ("xabovecmb", Glyph::xabovecmb),
+    ("xbopomofo", Glyph::xbopomofo),
+    ("xcircle", Glyph::xcircle),
+    ("xdieresis", Glyph::xdieresis),
+    ("xdotaccent", Glyph::xdotaccent),
+    ("xeharmenian", Glyph::xeharmenian),
+    ("xi", Glyph::xi),
+    ("xmonospace", Glyph::xmonospace),
+    ("xparen", Glyph::xparen),
+    ("xsuperior", Glyph::xsuperior),
+    ("y", Glyph::y),
+    ("yaadosquare", Glyph::yaadosquare),
+    ("yabengali", Glyph::yabengali),
+    ("yacute", Glyph::yacute),
+    ("yadeva", Glyph::yadeva),
+    ("yaekorean", Glyph::yaekorean),
+    ("yagujarati", Glyph::yagujarati),
+    ("yagurmukhi", Glyph::yagurmukhi),
+    ("yahiragana", Glyph::yahiragana),
+    ("yakatakana", Glyph::yakatakana),
+    ("yakatakanahalfwidth", Glyph::yakatakanahalfwidth),
+    ("yakorean", Glyph::yakorean),
+    ("yamakkanthai", Glyph::yamakkanthai),
+    ("yasmallhiragana", Glyph::yasmallhiragana),
+    ("yasmallkatakana", Glyph::yasmallkatakana),
+    ("yasmallkatakanahalfwidth", Glyph::yasmallkatakanahalfwidth),
+    ("yatcyrillic", Glyph::yatcyrillic),
+    ("ycircle", Glyph::ycircle),
+    ("ycircumflex", Glyph::ycircumflex),
+    ("ydieresis", Glyph::ydieresis),
+    ("ydotaccent", Glyph::ydotaccent),
+    ("ydotbelow", Glyph::ydotbelow),
+    ("yeharabic", Glyph::yeharabic),
+    ("yehbarreearabic", Glyph::yehbarreearabic),
+    ("yehbarreefinalarabic", Glyph::yehbarreefinalarabic),
+    ("yehfinalarabic", Glyph::yehfinalarabic),
+    ("yehhamzaabovearabic", Glyph::yehhamzaabovearabic),
+    ("yehhamzaabovefinalarabic", Glyph::yehhamzaabovefinalarabic),
+    ("yehhamzaaboveinitialarabic", Glyph::yehhamzaaboveinitialarabic),
+    ("yehhamzaabovemedialarabic", Glyph::yehhamzaabovemedialarabic),
+    ("yehinitialarabic", Glyph::yehinitialarabic),
+    ("yehmedialarabic", Glyph::yehmedialarabic),
+    ("yehmeeminitialarabic", Glyph::yehmeeminitialarabic),
+    ("yehmeemisolatedarabic", Glyph::yehmeemisolatedarabic),
+    ("yehnoonfinalarabic", Glyph::yehnoonfinalarabic),
+    ("yehthreedotsbelowarabic", Glyph::yehthreedotsbelowarabic),
+    ("yekorean", Glyph::yekorean),
+    ("yen", Glyph::yen),
+    ("yenmonospace", Glyph::yenmonospace),
+    ("yeokorean", Glyph::yeokorean),
+    ("yeorinhieuhkorean", Glyph::yeorinhieuhkorean),
+    ("yerahbenyomohebrew", Glyph::yerahbenyomohebrew),
+    ("yerahbenyomolefthebrew", Glyph::yerahbenyomolefthebrew),
+    ("yericyrillic", Glyph::yericyrillic),
+    ("yerudieresiscyrillic", Glyph::yerudieresiscyrillic),
+    ("yesieungkorean", Glyph::yesieungkorean),
+    ("yesieungpansioskorean", Glyph::yesieungpansioskorean),
+    ("yesieungsioskorean", Glyph::yesieungsioskorean),
+    ("yetivhebrew", Glyph::yetivhebrew),
+    ("ygrave", Glyph::ygrave),
+    ("yhook", Glyph::yhook),
+    ("yhookabove", Glyph::yhookabove),
+    ("yiarmenian", Glyph::yiarmenian),
+    ("yicyrillic", Glyph::yicyrillic),
+    ("yikorean", Glyph::yikorean),
+    ("yinyang", Glyph::yinyang),
+    ("yiwnarmenian", Glyph::yiwnarmenian),
+    ("ymonospace", Glyph::ymonospace),
+    ("yod", Glyph::yod),
+    ("yoddagesh", Glyph::yoddagesh),
+    ("yoddageshhebrew", Glyph::yoddageshhebrew),
+    ("yodhebrew", Glyph::yodhebrew),
+    ("yodyodhebrew", Glyph::yodyodhebrew),
+    ("yodyodpatahhebrew", Glyph::yodyodpatahhebrew),
+    ("yohiragana", Glyph::yohiragana),
+    ("yoikorean", Glyph::yoikorean),
+    ("yokatakana", Glyph::yokatakana),
+    ("yokatakanahalfwidth", Glyph::yokatakanahalfwidth),
+    ("yokorean", Glyph::yokorean),
+    ("yosmallhiragana", Glyph::yosmallhiragana),
+    ("yosmallkatakana", Glyph::yosmallkatakana),
+    ("yosmallkatakanahalfwidth", Glyph::yosmallkatakanahalfwidth),
+    ("yotgreek", Glyph::yotgreek),
+    ("yoyaekorean", Glyph::yoyaekorean),
+    ("yoyakorean", Glyph::yoyakorean),
+    ("yoyakthai", Glyph::yoyakthai),
+    ("yoyingthai", Glyph::yoyingthai),
+    ("yparen", Glyph::yparen),
+    ("ypogegrammeni", Glyph::ypogegrammeni),
+    ("ypogegrammenigreekcmb", Glyph::ypogegrammenigreekcmb),
+    ("yr", Glyph::yr),
+    ("yring", Glyph::yring),
+    ("ysuperior", Glyph::ysuperior),
+    ("ytilde", Glyph::ytilde),
+    ("yturned", Glyph::yturned),
+    ("yuhiragana", Glyph::yuhiragana),
+    ("yuikorean", Glyph::yuikorean),
+    ("yukatakana", Glyph::yukatakana),
+    ("yukatakanahalfwidth", Glyph::yukatakanahalfwidth),
+    ("yukorean", Glyph::yukorean),
+    ("yusbigcyrillic", Glyph::yusbigcyrillic),
+    ("yusbigiotifiedcyrillic", Glyph::yusbigiotifiedcyrillic),
+    ("yuslittlecyrillic", Glyph::yuslittlecyrillic),
+    ("yuslittleiotifiedcyrillic", Glyph::yuslittleiotifiedcyrillic),
+    ("yusmallhiragana", Glyph::yusmallhiragana),
+    ("yusmallkatakana", Glyph::yusmallkatakana),
+    ("yusmallkatakanahalfwidth", Glyph::yusmallkatakanahalfwidth),
+    ("yuyekorean", Glyph::yuyekorean),
+    ("yuyeokorean", Glyph::yuyeokorean),
+    ("yyabengali", Glyph::yyabengali),
+    ("yyadeva", Glyph::yyadeva),
+    ("z", Glyph::z),
+    ("zaarmenian", Glyph::zaarmenian),
+    ("zacute", Glyph::zacute),
+    ("zadeva", Glyph::zadeva),
+    ("zagurmukhi", Glyph::zagurmukhi),
+    ("zaharabic", Glyph::zaharabic),
+    ("zahfinalarabic", Glyph::zahfinalarabic),
+    ("zahinitialarabic", Glyph::zahinitialarabic),
+    ("zahiragana", Glyph::zahiragana),
+    ("zahmedialarabic", Glyph::zahmedialarabic),
+    ("zainarabic", Glyph::zainarabic),
+    ("zainfinalarabic", Glyph::zainfinalarabic),
+    ("zakatakana", Glyph::zakatakana),
+    ("zaqefgadolhebrew", Glyph::zaqefgadolhebrew),
+    ("zaqefqatanhebrew", Glyph::zaqefqatanhebrew),
+    ("zarqahebrew", Glyph::zarqahebrew),
+    ("zayin", Glyph::zayin),
+    ("zayindagesh", Glyph::zayindagesh),
+    ("zayindageshhebrew", Glyph::zayindageshhebrew),
+    ("zayinhebrew", Glyph::zayinhebrew),
+    ("zbopomofo", Glyph::zbopomofo),
+    ("zcaron", Glyph::zcaron),
+    ("zcircle", Glyph::zcircle),
+    ("zcircumflex", Glyph::zcircumflex),
+    ("zcurl", Glyph::zcurl),
+    ("zdot", Glyph::zdot),
+    ("zdotaccent", Glyph::zdotaccent),
+    ("zdotbelow", Glyph::zdotbelow),
+    ("zecyrillic", Glyph::zecyrillic),
+    ("zedescendercyrillic", Glyph::zedescendercyrillic),
+    ("zedieresiscyrillic", Glyph::zedieresiscyrillic),
+    ("zehiragana", Glyph::zehiragana),
+    ("zekatakana", Glyph::zekatakana),
+    ("zero", Glyph::zero),
+    ("zeroarabic", Glyph::zeroarabic),
+    ("zerobengali", Glyph::zerobengali),
+    ("zerodeva", Glyph::zerodeva),
+    ("zerogujarati", Glyph::zerogujarati),
+    ("zerogurmukhi", Glyph::zerogurmukhi),
+    ("zerohackarabic", Glyph::zerohackarabic),
+    ("zeroinferior", Glyph::zeroinferior),
+    ("zeromonospace", Glyph::zeromonospace),
+    ("zerooldstyle", Glyph::zerooldstyle),
+    ("zeropersian", Glyph::zeropersian),
+    ("zerosuperior", Glyph::zerosuperior),
+    ("zerothai", Glyph::zerothai),
+    ("zerowidthjoiner", Glyph::zerowidthjoiner),
+    ("zerowidthnonjoiner", Glyph::zerowidthnonjoiner),
+    ("zerowidthspace", Glyph::zerowidthspace),
+    ("zeta", Glyph::zeta),
+    ("zhbopomofo", Glyph::zhbopomofo),
+    ("zhearmenian", Glyph::zhearmenian),
+    ("zhebrevecyrillic", Glyph::zhebrevecyrillic),
+    ("zhecyrillic", Glyph::zhecyrillic),
+    ("zhedescendercyrillic", Glyph::zhedescendercyrillic),
+    ("zhedieresiscyrillic", Glyph::zhedieresiscyrillic),
+    ("zihiragana", Glyph::zihiragana),
+    ("zikatakana", Glyph::zikatakana),
+    ("zinorhebrew", Glyph::zinorhebrew),
+    ("zlinebelow", Glyph::zlinebelow),
+    ("zmonospace", Glyph::zmonospace),
+    ("zohiragana", Glyph::zohiragana),
+    ("zokatakana", Glyph::zokatakana),
+    ("zparen", Glyph::zparen),
+    ("zretroflexhook", Glyph::zretroflexhook),
+    ("zstroke", Glyph::zstroke),
+    ("zuhiragana", Glyph::zuhiragana),
+    ("zukatakana", Glyph::zukatakana),
+];
+
+/// Get the Unicode value of a glyph name of a `/Differences` array, from the Adobe Glyph List or
+/// the `uniXXXX` and `uXXXX` forms.
+pub(crate) fn glyph_unicode(name: &[u8]) -> Option<u16> {
+    let name = std::str::from_utf8(name).ok()?;
+    if let Ok(index) = GLYPH_NAMES.binary_search_by(|(glyph, _)| glyph.as_bytes().cmp(name.as_bytes())) {
+        return Some(GLYPH_NAMES[index].1);
+    }
+    let hex = name
+        .strip_prefix("uni")
+        .filter(|hex| hex.len() == 4)
+        .or_else(|| name.strip_prefix('u').filter(|hex| (4..=6).contains(&hex.len())))?;
+    u16::from_str_radix(hex, 16).ok()
+}
@@ -8,6 +8,7 @@
 use encoding_rs::UTF_16BE;
 use log::debug;
 
+pub(crate) use self::glyphnames::glyph_unicode;
 pub use self::mappings::*;
 
 pub fn bytes_to_string(encoding: &CodedCharacterSet, bytes: &[u8]) -> String {
@@ -25,8 +26,14 @@ pub fn string_to_bytes(encoding: &CodedCharacterSet, text: &str) -> Vec<u8> {
         .collect()
 }
 
+/// The encoding of the strings shown with a font, see [`crate::Dictionary::get_font_encoding`].
+///
+/// More kinds of encodings may be supported, so matches on it need a wildcard arm.
+#[non_exhaustive]
 pub enum Encoding<'a> {
     OneByteEncoding(&'a CodedCharacterSet),
+    /// A base encoding changed by the `/Differences` of an encoding dictionary.
+    DifferencesEncoding(Box<CodedCharacterSet>),
     SimpleEncoding(&'a [u8]),
     UnicodeMapEncoding(ToUnicodeCMap),
 }
@@ -36,6 +43,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             // UnicodeCMap and Bytes encoding ommitted to not bloat debug log
             Self::OneByteEncoding(_arg0) => f.debug_tuple("OneByteEncoding").finish(),
+            Self::DifferencesEncoding(_arg0) => f.debug_tuple("DifferencesEncoding").finish(),
             Self::SimpleEncoding(arg0) => f.debug_tuple("SimpleEncoding").field(arg0).finish(),
             Self::UnicodeMapEncoding(_arg0) => f.debug_tuple("UnicodeMapEncoding").finish(),
         }
@@ -46,6 +54,7 @@ impl Encoding<'_> {
     pub fn bytes_to_string(&self, bytes: &[u8]) -> Result<String> {
         match self {
             Self::OneByteEncoding(map) => Ok(bytes_to_string(map, bytes)),
+            Self::DifferencesEncoding(map) => Ok(bytes_to_string(map, bytes)),
             Self::SimpleEncoding(b"UniGB-UCS2-H") | Self::SimpleEncoding(b"UniGB-UTF16-H") => {
                 Ok(UTF_16BE.decode(bytes).0.to_string())
             }
@@ -88,6 +97,7 @@ pub fn bytes_to_string(&self, bytes: &[u8]) -> Result<String> {
     pub fn string_to_bytes(&self, text: &str) -> Vec<u8> {
         match self {
             Self::OneByteEncoding(map) => string_to_bytes(map, text),
+            Self::DifferencesEncoding(map) => string_to_bytes(map, text),
             Self::SimpleEncoding(b"UniGB-UCS2-H") | Self::SimpleEncoding(b"UniGB-UTF16-H") => encode_utf16_be(text),
             Self::UnicodeMapEncoding(_unicode_map) => {
                 // maybe only possible if the unicode map is an identity?
@@ -99,6 +109,54 @@ pub fn string_to_bytes(&self, text: &str) -> Vec<u8> {
             }
         }
     }
+
+    /// The character set of a one byte encoding.
+    pub(crate) fn code_set(&self) -> Option<&CodedCharacterSet> {
+        match self {
+            Self::OneByteEncoding(map) => Some(map),
+            Self::DifferencesEncoding(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Decode a single character code of a string shown in a content stream.
+    pub(crate) fn code_to_string(&self, code: &[u8]) -> Result<String> {
+        match self {
+            Self::UnicodeMapEncoding(unicode_map) => {
+                let value = code.iter().fold(0, |value, &byte| value * 256 + u32::from(byte));
+                let utf16 = unicode_map.get_or_replacement_char(value, code.len() as u8);
+                Ok(String::from_utf16_lossy(&utf16))
+            }
+            _ => self.bytes_to_string(code),
+        }
+    }
+
+    /// Encode text as character codes of `code_width` bytes each.
+    ///
+    /// Returns `None` if the encoding has no code of that width for some character.
+    pub(crate) fn string_to_codes(&self, text: &str, code_width: usize) -> Option<Vec<u8>> {
+        match self {
+            Self::OneByteEncoding(_) | Self::DifferencesEncoding(_) if code_width == 1 => {
+                let map = self.code_set()?;
+                text.encode_utf16()
+                    .map(|ch| map.iter().position(|&code| code == Some(ch)).map(|byte| byte as u8))
+                    .collect()
+            }
+            Self::SimpleEncoding(b"UniGB-UCS2-H") if code_width == 2 => {
+                Some(text.encode_utf16().flat_map(u16::to_be_bytes).collect())
+            }
+            Self::UnicodeMapEncoding(unicode_map) if (1..=4).contains(&code_width) => {
+                let mut bytes = Vec::with_capacity(text.len() * code_width);
+                let mut units = [0; 2];
+                for ch in text.chars() {
+                    let code = unicode_map.get_code(ch.encode_utf16(&mut units), code_width as u8)?;
+                    bytes.extend_from_slice(&code.to_be_bytes()[4 - code_width..]);
+                }
+                Some(bytes)
+            }
+            _ => None,
+        }
+    }
 }
 
 /// Encodes the given `str` to UTF-16BE.
@@ -1,6 +1,7 @@
 use crate::rc4::Rc4;
 use crate::{Document, Object, ObjectId};
 use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use log::warn;
 use md5::{Digest as _, Md5};
 use thiserror::Error;
 
@@ -56,19 +57,6 @@ pub fn get_encryption_key<P>(doc: &Document, password: P, check_password: bool)
         .get_encrypted()
         .map_err(|_| DecryptionError::MissingEncryptDictionary)?;
 
-    // Very early versions of PDF assume a key length of 40 bits
-    let key_len = encryption_dict
-        .get(b"Length")
-        .unwrap_or(&DEFAULT_KEY_LEN)
-        .as_i64()
-        .map_err(|_| DecryptionError::InvalidType)? as usize
-        / 8; // Length is in bits, convert to bytes
-
-    // MD5 produces 128bit digests, so key_len must not be greater
-    if key_len > 128 / 8 {
-        return Err(DecryptionError::InvalidKeyLength);
-    }
-
     // Make sure we support the encryption algorithm
     let algorithm = encryption_dict
         .get(b"V")
@@ -82,6 +70,24 @@ pub fn get_encryption_key<P>(doc: &Document, password: P, check_password: bool)
         _ => return Err(DecryptionError::UnsupportedEncryption),
     }
 
+    // Very early versions of PDF assume a key length of 40 bits
+    let mut key_len = encryption_dict
+        .get(b"Length")
+        .unwrap_or(&DEFAULT_KEY_LEN)
+        .as_i64()
+        .map_err(|_| DecryptionError::InvalidType)? as usize
+        / 8; // Length is in bits, convert to bytes
+             // Keys of V 1 are always 40 bits, like Acrobat does whatever the Length
+    if algorithm == 1 && key_len != 40 / 8 {
+        warn!("Ignoring /Length of the V 1 encryption dictionary, which always uses 40 bit keys");
+        key_len = 40 / 8;
+    }
+
+    // MD5 produces 128bit digests, so key_len must not be greater
+    if key_len > 128 / 8 {
+        return Err(DecryptionError::InvalidKeyLength);
+    }
+
     // Revision number dictates hashing strategy
     let revision = encryption_dict
         .get(b"R")
@@ -208,10 +214,13 @@ fn compute_user_password<K, ID>(key: K, revision: i64, file_id_0: ID) -> Vec<u8>
 
 /// Decrypts `obj` and returns the content of the string or stream.
 /// If obj is not an decryptable type, returns the NotDecryptable error.
-pub fn decrypt_object<Key>(key: Key, obj_id: ObjectId, obj: &Object, aes: bool) -> Result<Vec<u8>, DecryptionError>
+pub fn decrypt_object<Key>(
+    key: Key, obj_id: impl Into<ObjectId>, obj: &Object, aes: bool,
+) -> Result<Vec<u8>, DecryptionError>
 where
     Key: AsRef<[u8]>,
 {
+    let obj_id = obj_id.into();
     let key = key.as_ref();
     let len = if aes { key.len() + 9 } else { key.len() + 5 };
     let mut builder = Vec::<u8>::with_capacity(len);
@@ -284,4 +293,124 @@ fn rc4_works() {
             assert_eq!(plain.as_bytes(), &decrypted[..]);
         }
     }
+
+    /// An RC4 encryption dictionary whose `/U` is made for the user password `password` with a key
+    /// derived as in Algorithm 3.2 with `key_len` bytes, independently of `get_encryption_key`.
+    fn rc4_encryption(doc: &mut Document, password: &[u8], encryption: crate::Dictionary, key_len: usize) -> Vec<u8> {
+        let owner = vec![0x42; 32];
+        let file_id = b"0123456789abcdef".to_vec();
+        let revision = encryption.get(b"R").unwrap().as_i64().unwrap();
+        let mut input = [password, &PAD_BYTES[..32 - password.len()]].concat();
+        input.extend(&owner);
+        input.extend((-4i32).to_le_bytes());
+        input.extend(&file_id);
+        let mut key = Md5::digest(&input)[..key_len].to_vec();
+        if revision >= 3 {
+            for _ in 0..50 {
+                key = Md5::digest(&key)[..key_len].to_vec();
+            }
+        }
+
+        let mut encryption = encryption;
+        encryption.set("Filter", "Standard");
+        encryption.set("O", Object::string_literal(owner));
+        encryption.set("P", -4);
+        encryption.set(
+            "U",
+            Object::string_literal(compute_user_password(&key, revision, &file_id)),
+        );
+        let encrypt_id = doc.add_object(encryption);
+        doc.trailer.set("Encrypt", encrypt_id);
+        let file_id = Object::string_literal(file_id);
+        doc.trailer.set("ID", vec![file_id.clone(), file_id]);
+        key
+    }
+
+    #[test]
+    fn forty_bit_keys() {
+        use crate::creator::tests::create_document;
+        use crate::dictionary;
+
+        // Without /Length, keys are 40 bits, also with the 50 extra hashes of revision 3.
+        for (version, revision) in [(1, 2), (2, 2), (2, 3)] {
+            let mut doc = create_document();
+            let key = rc4_encryption(&mut doc, b"secret", dictionary! { "V" => version, "R" => revision }, 5);
+            assert_eq!(get_encryption_key(&doc, "secret", true).unwrap(), key);
+            assert!(matches!(
+                get_encryption_key(&doc, "wrong", true),
+                Err(DecryptionError::IncorrectPassword)
+            ));
+        }
+
+        // V 1 always uses 40 bit keys, whatever /Length says.
+        for length in [128, 56] {
+            let mut doc = create_document();
+            let encryption = dictionary! { "V" => 1, "R" => 2, "Length" => length };
+            let key = rc4_encryption(&mut doc, b"secret", encryption, 5);
+            assert_eq!(get_encryption_key(&doc, "secret", true).unwrap(), key);
+        }
+
+        // V 2 uses the /Length it gives.
+        let mut doc = create_document();
+        let key = rc4_encryption(&mut doc, b"", dictionary! { "V" => 2, "R" => 3, "Length" => 128 }, 16);
+        assert_eq!(get_encryption_key(&doc, "", true).unwrap(), key);
+    }
+
+    #[test]
+    fn decrypt_streams_with_crypt_filter() {
+        use crate::creator::tests::create_document;
+        use crate::{dictionary, Stream};
+
+        let mut doc = create_document();
+        let encrypt_id = doc.add_object(dictionary! {
+            "Filter" => "Standard",
+            "V" => 4,
+            "R" => 4,
+            "Length" => 128,
+            "O" => Object::string_literal(vec![0x42; 32]),
+            "P" => -4,
+            "CF" => dictionary! { "StdCF" => dictionary! { "CFM" => "V2" } },
+            "StmF" => "StdCF",
+            "StrF" => "StdCF",
+        });
+        doc.trailer.set("Encrypt", encrypt_id);
+        let file_id = Object::string_literal(b"0123456789abcdef".to_vec());
+        doc.trailer.set("ID", vec![file_id.clone(), file_id]);
+        let key = get_encryption_key(&doc, "", false).unwrap();
+
+        let data = b"Some text which compresses well. ".repeat(10);
+        let mut stream = Stream::new(dictionary! {}, data.clone());
+        stream.compress().unwrap();
+        let filters = vec!["Crypt".into(), "FlateDecode".into()];
+        // Named crypt filter, the data is encrypted.
+        let mut encrypted = Stream::new(
+            dictionary! {
+                "Filter" => filters.clone(),
+                "DecodeParms" => vec![dictionary! { "Name" => "StdCF" }.into(), Object::Null],
+            },
+            stream.content.clone(),
+        );
+        let encrypted_id = doc.new_object_id();
+        encrypted.set_content(decrypt_object(&key, encrypted_id, &Object::Stream(encrypted.clone()), false).unwrap());
+        doc.objects.insert(encrypted_id, Object::Stream(encrypted));
+        // Identity crypt filter, the data isn't encrypted.
+        let identity_id = doc.add_object(Stream::new(
+            dictionary! {
+                "Filter" => filters,
+                "DecodeParms" => vec![dictionary! { "Type" => "CryptFilterDecodeParms" }.into(), Object::Null],
+            },
+            stream.content.clone(),
+        ));
+
+        doc.decrypt("").unwrap();
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let doc = Document::load_mem(&buffer).unwrap();
+        for id in [encrypted_id, identity_id] {
+            let stream = doc.get_object(id).unwrap().as_stream().unwrap();
+            assert_eq!(stream.filters().unwrap(), [b"FlateDecode"]);
+            assert!(!stream.dict.has(b"DecodeParms"));
+            assert_eq!(stream.decompressed_content().unwrap(), data);
+        }
+    }
 }
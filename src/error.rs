@@ -1,11 +1,33 @@
 use thiserror::Error;
 
 use crate::encodings::cmap::UnicodeCMapError;
-use crate::{encryption, ObjectId};
+#[cfg(feature = "encryption")]
+use crate::encryption;
+use crate::ObjectId;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Broad classification of an [`Error`](enum@Error), intended for programmatic handling such as retry logic.
+///
+/// The category of a variant is kept stable across releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Reading or writing the underlying data failed.
+    Io,
+    /// The input isn't valid PDF syntax or its encoded data can't be decoded.
+    Parse,
+    /// The document is syntactically valid but its object structure doesn't match expectations.
+    Structure,
+    /// The document couldn't be decrypted.
+    Encryption,
+    /// The document uses a feature lopdf doesn't support.
+    Unsupported,
+    /// A safety limit was reached, e.g. on nesting depth, or the operation was cancelled or refused.
+    Limit,
+}
+
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum Error {
     /// Lopdf does not (yet) implement a needed feature.
     #[error("missing feature of lopdf: {0}; please open an issue at https://github.com/J-F-Liu/lopdf/ to let the developers know of your usecase")]
@@ -17,6 +39,18 @@ pub enum Error {
         expected: &'static str,
         found: &'static str,
     },
+    /// An array has another number of elements than expected, e.g. a rectangle without 4 numbers.
+    #[error(
+        "expected array of {expected} {element}s{}, found {found} elements",
+        .key.as_ref().map_or(String::new(), |key| format!(" for /{key}"))
+    )]
+    ArrayLength {
+        expected: usize,
+        found: usize,
+        element: &'static str,
+        /// The dictionary key holding the array, if known.
+        key: Option<String>,
+    },
     #[error("dictionary has wrong type: ")]
     DictType { expected: &'static str, found: String },
     /// The encountered character encoding is invalid.
@@ -29,6 +63,7 @@ pub enum Error {
     #[error("couldn't parse input: {0}")]
     Parse(#[from] ParseError),
     /// Error when decrypting the contents of the file
+    #[cfg(feature = "encryption")]
     #[error("decryption error: {0}")]
     Decryption(#[from] encryption::DecryptionError),
     /// Dictionary key was not found.
@@ -37,18 +72,58 @@ pub enum Error {
     /// Invalid inline image.
     #[error("invalid inline image: {0}")]
     InvalidInlineImage(String),
+    /// A feature needs a later PDF version than the one of the document.
+    #[error("{feature}: PDF {required} is needed, the document is version {version}")]
+    UnsupportedVersion {
+        feature: &'static str,
+        required: &'static str,
+        version: String,
+    },
+    /// A change isn't permitted by the certification of the document, see
+    /// [`Document::set_respect_certification`](crate::Document::set_respect_certification).
+    #[error("the certification of the document doesn't permit the change ({change:?}), its DocMDP permission is {permission:?}")]
+    CertificationViolation {
+        permission: crate::DocMdpPermission,
+        change: crate::CertifiedChange,
+    },
+    /// A language tag that isn't a BCP 47 tag, see [`Document::set_language`](crate::Document::set_language).
+    #[error("invalid language tag {0:?}")]
+    InvalidLanguageTag(String),
+    /// Invalid developer extension.
+    #[error("invalid developer extension: {0}")]
+    InvalidExtension(String),
+    /// A page user unit below 1, see [`Document::set_user_unit`](crate::Document::set_user_unit).
+    #[error("invalid user unit {0}, it must be at least 1")]
+    InvalidUserUnit(f32),
+    /// Two resource dictionaries define a resource with the same name differently,
+    /// see [`Dictionary::merge_resources`](crate::Dictionary::merge_resources).
+    #[error("conflicting definitions of resource /{name} in /{category}")]
+    ResourceConflict { category: String, name: String },
     /// Invalid document outline.
     #[error("invalid document outline: {0}")]
     InvalidOutline(String),
+    /// An entry of a stream dictionary describing the data was changed with
+    /// [`Stream::with_dict_mut`](crate::Stream::with_dict_mut), which restored it.
+    #[error("/{0} of a stream dictionary can only be changed through the stream")]
+    ManagedStreamKey(String),
     /// Invalid stream.
     #[error("invalid stream: {0}")]
     InvalidStream(String),
+    /// The data of the stream is in an external file, see [`Stream::data`](crate::Stream::data).
+    #[error("stream data is in an external file")]
+    ExternalStream,
+    /// Image not allowed as a page thumbnail.
+    #[error("invalid thumbnail image: {0}")]
+    InvalidThumbnail(String),
     /// Invalid object stream.
     #[error("invalid object stream: {0}")]
     InvalidObjectStream(String),
     /// Byte offset in stream or file is invalid.
-    #[error("invalid byte offset")]
+    #[error("invalid byte offset {0}")]
     InvalidOffset(usize),
+    /// The data of a stream doesn't lie within the file.
+    #[error("stream data of {length} bytes at offset {start} extends after the end of the file")]
+    StreamExtent { start: usize, length: usize },
     /// IO error
     #[error("IO error: {0}")]
     IO(#[from] std::io::Error),
@@ -60,17 +135,26 @@ pub enum Error {
     #[error("missing xref entry")]
     MissingXrefEntry,
     /// The Object ID was not found.
-    #[error("object ID {} {} not found", .0.0, .0.1)]
+    #[error("object {0} not found")]
     ObjectNotFound(ObjectId),
     /// Dereferencing object failed due to a reference cycle.
-    #[error("reference cycle with object ID {} {}", .0.0, .0.1)]
+    #[error("reference cycle with object {0}")]
     ReferenceCycle(ObjectId),
     /// Page number was not found in document.
-    #[error("page number not found")]
+    #[error("page number {0} not found")]
     PageNumberNotFound(u32),
+    /// The object isn't a page dictionary.
+    #[error("object {0} is not a page")]
+    NotAPage(ObjectId),
+    /// The media box of a page has no area, so its content can't be scaled to fit another box.
+    #[error("page {0} has an empty media box")]
+    EmptyMediaBox(ObjectId),
     /// Numeric type cast failed.
     #[error("numberic type cast failed: {0}")]
     NumericCast(String),
+    /// The operation was stopped with a [`CancelToken`](crate::CancelToken).
+    #[error("operation cancelled")]
+    Cancelled,
     /// Dereferencing object reached the limit.
     /// This might indicate a reference loop.
     #[error("dereferencing object reached limit, may indicate a reference cycle")]
@@ -81,9 +165,22 @@ pub enum Error {
     /// Error while parsing cross reference table.
     #[error("failed parsing cross reference table: {0}")]
     Xref(XrefError),
+    /// A dictionary has a key twice, with [`DuplicateKeyPolicy::Error`](crate::DuplicateKeyPolicy::Error).
+    #[error("object {id} has key /{key} more than once")]
+    DuplicateKey { id: ObjectId, key: String },
+    /// The catalog is in an object stream which couldn't be decrypted or decoded, so the document has no root.
+    #[error("catalog {catalog} is in object stream {container}, which couldn't be read: {cause}")]
+    MissingCatalog {
+        catalog: ObjectId,
+        container: ObjectId,
+        cause: String,
+    },
     /// Invalid indirect object while parsing at offset.
     #[error("invalid indirect object at byte offset {offset}")]
     IndirectObject { offset: usize },
+    /// Text which isn't an object ID, see [`ObjectId`](crate::ObjectId).
+    #[error("invalid object ID: {0:?}")]
+    InvalidObjectId(String),
     /// Found object ID does not match expected object ID.
     #[error("found object ID does not match expected object ID")]
     ObjectIdMismatch,
@@ -94,15 +191,90 @@ pub enum Error {
     /// Syntax error while processing the content stream.
     #[error("syntax error in content stream: {0}")]
     Syntax(String),
+    /// Invalid content, see [`Content::parse_fragment`](crate::content::Content::parse_fragment)
+    /// and [`OperationIter`](crate::content::OperationIter).
+    #[error("invalid content at byte {offset} near {token:?}: {reason}")]
+    ContentFragment {
+        offset: usize,
+        token: String,
+        reason: String,
+    },
     /// Could not parse ToUnicodeCMap.
     #[error("failed parsing ToUnicode CMap: {0}")]
     ToUnicodeCMap(#[from] UnicodeCMapError),
+    /// Bytes that must be UTF-8 are not.
+    #[error("invalid UTF-8: {0}")]
+    Utf8(#[from] std::str::Utf8Error),
+}
+
+impl Error {
+    /// Classify the error, see [`ErrorCategory`].
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::IO(_) => ErrorCategory::Io,
+            Error::Decompress(_)
+            | Error::Parse(_)
+            | Error::InvalidInlineImage(_)
+            | Error::InvalidStream(_)
+            | Error::InvalidObjectStream(_)
+            | Error::InvalidOffset(_)
+            | Error::StreamExtent { .. }
+            | Error::Xref(_)
+            | Error::IndirectObject { .. }
+            | Error::DuplicateKey { .. }
+            | Error::MissingCatalog { .. }
+            | Error::Syntax(_)
+            | Error::ContentFragment { .. }
+            | Error::InvalidObjectId(_)
+            | Error::ToUnicodeCMap(_)
+            | Error::Utf8(_) => ErrorCategory::Parse,
+            Error::ObjectType { .. }
+            | Error::ArrayLength { .. }
+            | Error::DictType { .. }
+            | Error::CharacterEncoding
+            | Error::DictKey(_)
+            | Error::InvalidExtension(_)
+            | Error::InvalidLanguageTag(_)
+            | Error::ResourceConflict { .. }
+            | Error::InvalidOutline(_)
+            | Error::InvalidUserUnit(_)
+            | Error::InvalidThumbnail(_)
+            | Error::NoOutline
+            | Error::MissingXrefEntry
+            | Error::ObjectNotFound(_)
+            | Error::ReferenceCycle(_)
+            | Error::PageNumberNotFound(_)
+            | Error::NotAPage(_)
+            | Error::EmptyMediaBox(_)
+            | Error::ManagedStreamKey(_)
+            | Error::NumericCast(_)
+            | Error::TextStringDecode
+            | Error::ObjectIdMismatch => ErrorCategory::Structure,
+            #[cfg(feature = "encryption")]
+            Error::Decryption(encryption::DecryptionError::UnsupportedEncryption) => ErrorCategory::Unsupported,
+            #[cfg(feature = "encryption")]
+            Error::Decryption(_) => ErrorCategory::Encryption,
+            Error::Unimplemented(_) | Error::ExternalStream | Error::UnsupportedVersion { .. } => {
+                ErrorCategory::Unsupported
+            }
+            Error::ReferenceLimit | Error::Cancelled | Error::CertificationViolation { .. } => ErrorCategory::Limit,
+            #[cfg(feature = "embed_image")]
+            Error::Image(err) => match err {
+                image::ImageError::IoError(_) => ErrorCategory::Io,
+                image::ImageError::Unsupported(_) => ErrorCategory::Unsupported,
+                image::ImageError::Limits(_) => ErrorCategory::Limit,
+                _ => ErrorCategory::Parse,
+            },
+        }
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum DecompressError {
     #[error("decoding ASCII85 failed: {0}")]
     Ascii85(&'static str),
+    #[error("decoding ASCIIHex failed: {0}")]
+    AsciiHex(&'static str),
 }
 
 #[derive(Error, Debug)]
@@ -130,4 +302,62 @@ pub enum XrefError {
     /// The trailer's "XRefStm" field was invalid.
     #[error("invalid start value of XRefStm")]
     StreamStart,
+    /// The "W" array of a cross reference stream was invalid.
+    #[error("invalid W array of cross reference stream")]
+    StreamWidths,
+    /// The "Index" array of a cross reference stream was invalid.
+    #[error("invalid Index array of cross reference stream")]
+    StreamIndex,
+    /// A cross reference stream had fewer bytes than its entries need.
+    #[error("cross reference stream has {found} bytes of entries, expected {expected}")]
+    StreamLength { expected: usize, found: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn error_categories() {
+        let io = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "truncated");
+        assert_eq!(Error::from(io).category(), ErrorCategory::Io);
+        assert_eq!(Error::from(ParseError::InvalidXref).category(), ErrorCategory::Parse);
+        assert_eq!(
+            Error::ObjectNotFound(ObjectId(1, 0)).category(),
+            ErrorCategory::Structure
+        );
+        #[cfg(feature = "encryption")]
+        {
+            assert_eq!(
+                Error::from(encryption::DecryptionError::IncorrectPassword).category(),
+                ErrorCategory::Encryption
+            );
+            assert_eq!(
+                Error::from(encryption::DecryptionError::UnsupportedEncryption).category(),
+                ErrorCategory::Unsupported
+            );
+        }
+        assert_eq!(Error::Unimplemented("feature").category(), ErrorCategory::Unsupported);
+        assert_eq!(Error::ReferenceLimit.category(), ErrorCategory::Limit);
+    }
+
+    #[test]
+    fn source_chain_reaches_cause() {
+        let io = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let err = Error::from(io);
+        let source = err.source().unwrap();
+        let io = source.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(io.kind(), std::io::ErrorKind::PermissionDenied);
+
+        let bytes = vec![0xC3, 0x28];
+        let err = Error::from(std::str::from_utf8(&bytes).unwrap_err());
+        assert!(err.source().unwrap().is::<std::str::Utf8Error>());
+
+        #[cfg(feature = "encryption")]
+        {
+            let err = Error::from(encryption::DecryptionError::IncorrectPassword);
+            assert!(err.source().unwrap().is::<encryption::DecryptionError>());
+        }
+    }
 }
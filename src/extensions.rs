@@ -0,0 +1,179 @@
+use crate::{Dictionary, Document, Error, Object, Result};
+use log::warn;
+
+/// A developer extension declared in the `/Extensions` dictionary of the catalog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeveloperExtension {
+    /// The registered developer prefix, e.g. `ADBE` or `ISO_`.
+    pub prefix: Vec<u8>,
+    /// The PDF version the extension is based on, e.g. `1.7`.
+    pub base_version: String,
+    pub extension_level: i64,
+}
+
+/// Prefix of extensions defined by ISO, which may be declared more than once.
+const ISO_PREFIX: &[u8] = b"ISO_";
+
+/// Parse a PDF version like `1.7` into its major and minor numbers.
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.split_once('.')?;
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|c| c.is_ascii_digit());
+    if !is_number(major) || !is_number(minor) {
+        return None;
+    }
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+fn extension_dictionary(base_version: &str, extension_level: i64) -> Dictionary {
+    dictionary! {
+        "Type" => "DeveloperExtensions",
+        "BaseVersion" => Object::Name(base_version.as_bytes().to_vec()),
+        "ExtensionLevel" => extension_level,
+    }
+}
+
+impl Document {
+    /// Whether the header version of the document is at least `version`, given as major and minor
+    /// numbers. An invalid header version is taken for an old one.
+    pub(crate) fn is_version_at_least(&self, version: (u32, u32)) -> bool {
+        parse_version(&self.version).is_some_and(|own| own >= version)
+    }
+
+    /// Declare a developer extension in the `/Extensions` dictionary of the catalog.
+    ///
+    /// `base_version` must be a PDF version like `1.7`. An extension with another prefix than
+    /// `ISO_` replaces an earlier declaration with the same prefix; ISO extensions are collected in
+    /// an array. The header version of the document is raised to `base_version` if it is lower.
+    pub fn add_extension(&mut self, prefix: &[u8], base_version: &str, extension_level: i64) -> Result<()> {
+        if prefix.is_empty() {
+            return Err(Error::InvalidExtension("empty developer prefix".to_string()));
+        }
+        let Some(base) = parse_version(base_version) else {
+            return Err(Error::InvalidExtension(format!(
+                "invalid base version {base_version:?}"
+            )));
+        };
+
+        let extension = extension_dictionary(base_version, extension_level);
+        let mut extensions = self
+            .catalog()
+            .and_then(|catalog| self.get_dict_in_dict(catalog, b"Extensions"))
+            .cloned()
+            .unwrap_or_else(|_| dictionary! { "Type" => "Extensions" });
+        let value = match extensions.get(prefix).map(|value| self.dereference(value)) {
+            Ok(Ok((_, existing))) if prefix == ISO_PREFIX => {
+                let mut items = match existing {
+                    Object::Array(items) => items.clone(),
+                    Object::Dictionary(_) => vec![existing.clone()],
+                    _ => vec![],
+                };
+                let same = |item: &Object| {
+                    self.dereference(item).ok().and_then(|(_, item)| item.as_dict().ok()) == Some(&extension)
+                };
+                if !items.iter().any(same) {
+                    items.push(Object::Dictionary(extension));
+                }
+                Object::Array(items)
+            }
+            _ => Object::Dictionary(extension),
+        };
+        extensions.set(prefix, value);
+
+        let catalog = self.catalog_mut()?;
+        match catalog.get(b"Extensions") {
+            Ok(Object::Reference(id)) => {
+                let id = *id;
+                *self.get_dictionary_mut(id)? = extensions;
+            }
+            _ => catalog.set("Extensions", extensions),
+        }
+        if parse_version(&self.version).is_some_and(|version| version < base) {
+            self.version = base_version.to_string();
+        }
+        Ok(())
+    }
+
+    /// Get the developer extensions declared in the catalog, in the order of their prefixes.
+    ///
+    /// Declarations without a valid `/BaseVersion` name or integer `/ExtensionLevel` are skipped.
+    pub fn get_extensions(&self) -> Result<Vec<DeveloperExtension>> {
+        let catalog = self.catalog()?;
+        let Ok(extensions) = self.get_dict_in_dict(catalog, b"Extensions") else {
+            return Ok(vec![]);
+        };
+        let mut declared = vec![];
+        for (prefix, value) in extensions {
+            if prefix == b"Type" {
+                continue;
+            }
+            let items = match self.dereference(value)?.1 {
+                Object::Array(items) => items.iter().collect(),
+                item => vec![item],
+            };
+            for item in items {
+                let Ok(dict) = self.dereference(item).and_then(|(_, item)| item.as_dict()) else {
+                    warn!(
+                        "skipping developer extension {} which isn't a dictionary",
+                        String::from_utf8_lossy(prefix)
+                    );
+                    continue;
+                };
+                let base_version = dict
+                    .get(b"BaseVersion")
+                    .and_then(Object::as_name)
+                    .ok()
+                    .and_then(|name| std::str::from_utf8(name).ok())
+                    .filter(|name| parse_version(name).is_some());
+                let extension_level = dict.get(b"ExtensionLevel").and_then(Object::as_i64);
+                match (base_version, extension_level) {
+                    (Some(base_version), Ok(extension_level)) => declared.push(DeveloperExtension {
+                        prefix: prefix.clone(),
+                        base_version: base_version.to_string(),
+                        extension_level,
+                    }),
+                    _ => warn!(
+                        "skipping invalid developer extension {}",
+                        String::from_utf8_lossy(prefix)
+                    ),
+                }
+            }
+        }
+        Ok(declared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+
+    #[test]
+    fn extensions_round_trip() {
+        let mut doc = create_document();
+        assert!(doc.get_extensions().unwrap().is_empty());
+        doc.add_extension(b"ADBE", "1.7", 3).unwrap();
+        doc.add_extension(b"ADBE", "1.7", 8).unwrap();
+        doc.add_extension(b"ISO_", "2.0", 32001).unwrap();
+        doc.add_extension(b"ISO_", "2.0", 32001).unwrap();
+        assert_eq!(doc.version, "2.0");
+        assert!(doc.add_extension(b"ADBE", "1.7x", 1).is_err());
+        assert!(doc.add_extension(b"", "1.7", 1).is_err());
+
+        let mut buffer = Vec::new();
+        doc.save_to(&mut buffer).unwrap();
+        let doc = Document::load_mem(&buffer).unwrap();
+        assert!(buffer.starts_with(b"%PDF-2.0"));
+        let extension = |prefix: &[u8], base_version: &str, extension_level| DeveloperExtension {
+            prefix: prefix.to_vec(),
+            base_version: base_version.to_string(),
+            extension_level,
+        };
+        assert_eq!(
+            doc.get_extensions().unwrap(),
+            [extension(b"ADBE", "1.7", 8), extension(b"ISO_", "2.0", 32001)]
+        );
+        let catalog = doc.catalog().unwrap();
+        let extensions = doc.get_dict_in_dict(catalog, b"Extensions").unwrap();
+        assert!(extensions.get(b"ISO_").unwrap().as_array().is_ok());
+    }
+}
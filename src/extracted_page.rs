@@ -0,0 +1,416 @@
+#![cfg(feature = "nom_parser")]
+use log::warn;
+use std::collections::BTreeMap;
+
+use crate::content::TextState;
+use crate::optional_content::{LayerState, LayerVisibility};
+use crate::page::Rotation;
+use crate::parser_aux::{inline_images, unit_square_bounds, ContentEvent, TextRunCollector};
+use crate::xobject::{InlineImage, PdfImage};
+use crate::{BookmarkTarget, DestinationFit, Dictionary, Document, Error, NameTree, Object, ObjectId, Result, TextRun};
+
+/// Maximum number of named destinations followed to resolve the target of a link.
+const MAX_DESTINATION_DEPTH: usize = 8;
+
+/// Options of [`Document::extract_page`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageExtractionOptions {
+    /// Which layers contribute to the plain text, as for [`Document::extract_text_with_options`].
+    pub layer_visibility: LayerVisibility,
+    /// Also collect the strings shown with their position, as [`Document::get_page_text_runs`] does.
+    pub text_runs: bool,
+    /// Leave the text shown in a clipping rendering mode out of the plain text, as for
+    /// [`Document::extract_text_with_options`]. It is still counted and collected in the text runs.
+    pub skip_clipping_text: bool,
+}
+
+/// The effective boxes, rotation and unit of a page, with inherited attributes resolved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageGeometry {
+    pub media_box: [f32; 4],
+    pub crop_box: [f32; 4],
+    pub rotation: Rotation,
+    pub user_unit: f32,
+}
+
+/// A link annotation of a page, see [`Document::extract_page`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageLink {
+    /// The active area of the link, `/Rect`.
+    pub rect: Option<[f32; 4]>,
+    /// Where the link goes, from its `/Dest` or its `GoTo` or `URI` action. Named destinations are
+    /// resolved to pages when the document defines them.
+    pub target: Option<BookmarkTarget>,
+}
+
+/// What [`Document::extract_page`] gets from a page.
+#[derive(Debug, Clone)]
+pub struct ExtractedPage<'a> {
+    pub page_id: ObjectId,
+    pub geometry: PageGeometry,
+    /// The text of the page, as [`Document::extract_text`] gives it.
+    pub text: String,
+    /// The strings shown, if [`PageExtractionOptions::text_runs`] is set.
+    pub text_runs: Vec<TextRun>,
+    /// Number of strings shown in a clipping rendering mode, see [`TextState::is_clipping`].
+    pub clipping_strings: usize,
+    /// The image XObjects painted by the page, including those of the Form XObjects it paints, in
+    /// painting order. Each has the placement rectangle of the CTM it is painted with, so an image
+    /// painted twice is listed twice.
+    pub images: Vec<PdfImage<'a>>,
+    /// The inline images painted by the page, as [`Document::get_page_inline_images`] gives them.
+    pub inline_images: Vec<InlineImage>,
+    pub annotations: Vec<&'a Dictionary>,
+    pub links: Vec<PageLink>,
+}
+
+impl Document {
+    /// Get the text, images, annotations, links and geometry of a page at once.
+    ///
+    /// The content of the page is decoded and walked a single time, feeding the text extraction and
+    /// the collection of text runs and images together. Images which aren't valid are skipped.
+    pub fn extract_page(&self, page_number: u32, options: &PageExtractionOptions) -> Result<ExtractedPage<'_>> {
+        let page_id = *self
+            .get_pages()
+            .get(&page_number)
+            .ok_or(Error::PageNumberNotFound(page_number))?;
+        let page = self.page(page_id)?;
+        let geometry = PageGeometry {
+            media_box: page.media_box()?,
+            crop_box: page.crop_box()?,
+            rotation: page.rotate()?,
+            user_unit: page.user_unit(),
+        };
+
+        let mut runs = if options.text_runs {
+            Some(TextRunCollector::new(self, page_id)?)
+        } else {
+            None
+        };
+        let mut images = ImageCollector::new(self, page_id)?;
+        let mut clipping = ClippingTextCounter::default();
+        let layers = LayerState::new(self, &options.layer_visibility);
+        let chunks = self.extract_page_text_chunks(page_id, &layers, options.skip_clipping_text, &mut |event| {
+            if let Some(runs) = &mut runs {
+                runs.visit(event);
+            }
+            images.visit(event);
+            clipping.visit(event);
+        })?;
+        let text = chunks.into_iter().collect::<Result<String>>()?;
+
+        let annotations = self.get_page_annotations(page_id)?;
+        let links = annotations
+            .iter()
+            .filter(|annotation| annotation.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Link"))
+            .map(|annotation| PageLink {
+                rect: annotation.get_as::<[f32; 4]>(b"Rect").ok(),
+                target: self.link_target(annotation),
+            })
+            .collect();
+
+        Ok(ExtractedPage {
+            page_id,
+            geometry,
+            text,
+            text_runs: runs.map(|runs| runs.runs).unwrap_or_default(),
+            clipping_strings: clipping.strings,
+            images: images.images,
+            inline_images: images.inline_images,
+            annotations,
+            links,
+        })
+    }
+
+    /// Where a link annotation goes, from its `/Dest` or `/A` action.
+    fn link_target(&self, annotation: &Dictionary) -> Option<BookmarkTarget> {
+        if let Ok(destination) = annotation.get(b"Dest") {
+            return self.destination_target(destination, 0);
+        }
+        let action = annotation.get_deref(b"A", self).and_then(Object::as_dict).ok()?;
+        match action.get(b"S").and_then(Object::as_name).ok()? {
+            b"GoTo" => self.destination_target(action.get(b"D").ok()?, 0),
+            b"URI" => {
+                let uri = action.get_deref(b"URI", self).and_then(Object::as_str).ok()?;
+                Some(BookmarkTarget::Uri(String::from_utf8_lossy(uri).into_owned()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve an explicit or named destination to a page and a fit.
+    ///
+    /// The fits without an equivalent [`DestinationFit`] are approximated: `FitB`, `FitBH` and
+    /// `FitBV` become `Fit`, `FitH` and `FitV`, and `FitR` becomes `Fit`.
+    fn destination_target(&self, destination: &Object, depth: usize) -> Option<BookmarkTarget> {
+        let (_, destination) = self.dereference(destination).ok()?;
+        match destination {
+            Object::Array(items) => {
+                let page = items.first()?.as_reference().ok()?;
+                let value = |index: usize| items.get(index).and_then(|value| value.as_float().ok());
+                let fit = match items.get(1).and_then(|fit| fit.as_name().ok()) {
+                    Some(b"FitH" | b"FitBH") => DestinationFit::FitH(value(2)),
+                    Some(b"FitV" | b"FitBV") => DestinationFit::FitV(value(2)),
+                    Some(b"XYZ") => DestinationFit::XYZ {
+                        left: value(2),
+                        top: value(3),
+                        zoom: value(4),
+                    },
+                    _ => DestinationFit::Fit,
+                };
+                Some(BookmarkTarget::Page(page, fit))
+            }
+            Object::Dictionary(dict) => self.destination_target(dict.get(b"D").ok()?, depth),
+            Object::Name(name) | Object::String(name, _) => {
+                let resolved = Some(depth)
+                    .filter(|&depth| depth < MAX_DESTINATION_DEPTH)
+                    .and_then(|_| self.named_destination(destination));
+                match resolved {
+                    Some(resolved) => self.destination_target(resolved, depth + 1),
+                    None => Some(BookmarkTarget::Named(name.clone())),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Look up a named destination: names in the `/Dests` dictionary of the catalog, strings in the
+    /// `/Dests` name tree of the `/Names` dictionary.
+    fn named_destination(&self, name: &Object) -> Option<&Object> {
+        let catalog = self.catalog().ok()?;
+        match name {
+            Object::Name(name) => self.get_dict_in_dict(catalog, b"Dests").ok()?.get(name).ok(),
+            Object::String(name, _) => {
+                let names = self.get_dict_in_dict(catalog, b"Names").ok()?;
+                let tree = names.get(b"Dests").ok()?;
+                NameTree::load(self, tree).ok()?.get(name.as_slice())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Collects the image XObjects and inline images painted while walking the content of a page.
+struct ImageCollector<'a> {
+    doc: &'a Document,
+    page_id: ObjectId,
+    /// The XObjects of the page, followed by those of each Form XObject being painted.
+    xobject_scopes: Vec<BTreeMap<Vec<u8>, ObjectId>>,
+    images: Vec<PdfImage<'a>>,
+    inline_images: Vec<InlineImage>,
+}
+
+impl<'a> ImageCollector<'a> {
+    fn new(doc: &'a Document, page_id: ObjectId) -> Result<Self> {
+        let mut xobjects = BTreeMap::new();
+        let (resource_dict, resource_ids) = doc.get_page_resources(page_id)?;
+        let resources = resource_dict
+            .into_iter()
+            .chain(resource_ids.into_iter().filter_map(|id| doc.get_dictionary(id).ok()));
+        for resources in resources {
+            doc.collect_xobjects_from_resources(resources, &mut xobjects);
+        }
+        Ok(ImageCollector {
+            doc,
+            page_id,
+            xobject_scopes: vec![xobjects],
+            images: vec![],
+            inline_images: vec![],
+        })
+    }
+
+    fn visit(&mut self, event: ContentEvent<'_, 'a>) {
+        match event {
+            ContentEvent::Operation(operation, ctm) if operation.operator == "Do" => {
+                let name = operation.operands.first().and_then(|name| name.as_name().ok());
+                let scope = self.xobject_scopes.last();
+                let Some(&id) = name.zip(scope).and_then(|(name, scope)| scope.get(name)) else {
+                    return;
+                };
+                let Ok(stream) = self.doc.get_object(id).and_then(Object::as_stream) else {
+                    return;
+                };
+                if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Image") {
+                    return;
+                }
+                match PdfImage::from_parts(id, &stream.dict, &stream.content) {
+                    Ok(mut image) => {
+                        image.rect = Some(unit_square_bounds(ctm));
+                        self.images.push(image);
+                    }
+                    Err(err) => warn!("Skipping image {id:?}: {err}"),
+                }
+            }
+            ContentEvent::Operation(operation, ctm) => {
+                for image in inline_images(self.page_id, operation, ctm) {
+                    match image {
+                        Ok(image) => self.inline_images.push(image),
+                        Err(err) => warn!("Skipping inline image: {err}"),
+                    }
+                }
+            }
+            // A form without resources uses those of the content painting it.
+            ContentEvent::EnterForm(resources, _) => {
+                let mut xobjects = BTreeMap::new();
+                match resources {
+                    Some(resources) => self.doc.collect_xobjects_from_resources(resources, &mut xobjects),
+                    None => xobjects = self.xobject_scopes.last().cloned().unwrap_or_default(),
+                }
+                self.xobject_scopes.push(xobjects);
+            }
+            ContentEvent::LeaveForm => {
+                self.xobject_scopes.pop();
+            }
+        }
+    }
+}
+
+/// Counts the strings shown in a clipping rendering mode while walking the content of a page.
+#[derive(Default)]
+struct ClippingTextCounter {
+    text_state: TextState,
+    /// The text states saved by `q` and by painting Form XObjects.
+    saved_states: Vec<TextState>,
+    strings: usize,
+}
+
+impl ClippingTextCounter {
+    fn visit(&mut self, event: ContentEvent<'_, '_>) {
+        match event {
+            ContentEvent::Operation(operation, _) => {
+                self.text_state.apply(operation);
+                match operation.operator.as_ref() {
+                    "q" => self.saved_states.push(self.text_state),
+                    "Q" => self.text_state = self.saved_states.pop().unwrap_or(self.text_state),
+                    "Tj" | "TJ" | "'" | "\"" if self.text_state.is_clipping() => self.strings += 1,
+                    _ => {}
+                }
+            }
+            ContentEvent::EnterForm(..) => self.saved_states.push(self.text_state),
+            ContentEvent::LeaveForm => self.text_state = self.saved_states.pop().unwrap_or(self.text_state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+    use crate::object::tests::DECOMPRESSIONS;
+    use crate::Stream;
+    use std::cell::Cell;
+
+    #[test]
+    fn extract_page_matches_individual_apis() {
+        let mut doc = create_document_with_texts(&["Hello World!", "second"]);
+        let page_id = doc.page_iter().next().unwrap();
+        let image = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 1,
+                "Height" => 1,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+            },
+            vec![0x80],
+        );
+        doc.insert_image(page_id, image, (100.0, 200.0), (50.0, 20.0)).unwrap();
+        doc.add_page_contents(
+            page_id,
+            b"q 10 0 0 10 5 5 cm BI /W 1 /H 1 /CS /G /BPC 8 ID \x80 EI Q".to_vec(),
+        )
+        .unwrap();
+
+        let destination = vec![page_id.into(), "XYZ".into(), 0.into(), 800.into(), Object::Null];
+        let tree = doc.add_object(dictionary! {
+            "Names" => vec![Object::string_literal("chapter"), destination.into()],
+        });
+        doc.catalog_mut().unwrap().set("Names", dictionary! { "Dests" => tree });
+        let annotations = vec![
+            doc.add_object(dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Link",
+                "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+                "Dest" => Object::string_literal("chapter"),
+            })
+            .into(),
+            doc.add_object(dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Link",
+                "A" => dictionary! { "S" => "URI", "URI" => Object::string_literal("https://example.com/") },
+            })
+            .into(),
+            doc.add_object(dictionary! { "Type" => "Annot", "Subtype" => "Text" })
+                .into(),
+        ];
+        doc.get_dictionary_mut(page_id).unwrap().set("Annots", annotations);
+        doc.compress();
+
+        let options = PageExtractionOptions {
+            text_runs: true,
+            ..PageExtractionOptions::default()
+        };
+        let content_streams = doc.get_page_contents(page_id).len();
+        DECOMPRESSIONS.with(|count| count.set(0));
+        let page = doc.extract_page(1, &options).unwrap();
+        // The content streams of the page are decoded once, and are the only streams decoded.
+        assert_eq!(DECOMPRESSIONS.with(Cell::get), content_streams);
+
+        assert_eq!(page.page_id, page_id);
+        assert_eq!(page.text, doc.extract_text(&[1]).unwrap());
+        assert!(page.text.contains("Hello World!"));
+        assert_eq!(page.text_runs, doc.get_page_text_runs(page_id).unwrap());
+        assert_eq!(page.annotations, doc.get_page_annotations(page_id).unwrap());
+        assert_eq!(
+            page.geometry,
+            PageGeometry {
+                media_box: [0.0, 0.0, 595.0, 842.0],
+                crop_box: [0.0, 0.0, 595.0, 842.0],
+                rotation: Rotation::R0,
+                user_unit: 1.0,
+            }
+        );
+
+        let images = doc.get_page_images(page_id).unwrap();
+        let inline_images = doc.get_page_inline_images(page_id).unwrap();
+        assert_eq!(page.images.len(), 1);
+        assert_eq!(page.images[0].id, images[0].id);
+        assert_eq!(page.images[0].content, images[0].content);
+        assert_eq!(page.images[0].rect, Some([100.0, 200.0, 150.0, 220.0]));
+        assert_eq!(page.inline_images.len(), 1);
+        assert_eq!(page.inline_images[0].rect, inline_images[0].rect);
+        assert_eq!(page.inline_images[0].content, inline_images[0].content);
+
+        assert_eq!(
+            page.links,
+            [
+                PageLink {
+                    rect: Some([0.0, 0.0, 10.0, 10.0]),
+                    target: Some(BookmarkTarget::Page(
+                        page_id,
+                        DestinationFit::XYZ {
+                            left: Some(0.0),
+                            top: Some(800.0),
+                            zoom: None
+                        }
+                    )),
+                },
+                PageLink {
+                    rect: None,
+                    target: Some(BookmarkTarget::Uri("https://example.com/".to_string())),
+                },
+            ]
+        );
+
+        let page = doc.extract_page(2, &PageExtractionOptions::default()).unwrap();
+        assert_eq!(page.text, "second\n");
+        assert!(
+            page.text_runs.is_empty()
+                && page.images.is_empty()
+                && page.inline_images.is_empty()
+                && page.links.is_empty()
+        );
+        assert!(doc.extract_page(3, &options).is_err());
+    }
+}
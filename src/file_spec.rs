@@ -0,0 +1,176 @@
+use crate::{decode_text_string, Dictionary, Document, Object, ObjectId, Result, Stream};
+
+/// A file specification, which names a file outside the PDF file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileSpec {
+    /// A file name string, in the PDF file name syntax, e.g. `/docs/data.bin`.
+    Name(Vec<u8>),
+    /// A file specification dictionary, with the file name in `/UF` or `/F`.
+    Dictionary(Dictionary),
+    /// A file specification dictionary in another object, see [`Document::resolve_external_streams`].
+    Reference(ObjectId),
+}
+
+impl FileSpec {
+    /// Read a file specification, `None` if the object is neither a string, a dictionary nor a reference.
+    pub fn from_object(object: &Object) -> Option<FileSpec> {
+        match object {
+            Object::String(name, _) => Some(FileSpec::Name(name.clone())),
+            Object::Dictionary(dict) => Some(FileSpec::Dictionary(dict.clone())),
+            Object::Reference(id) => Some(FileSpec::Reference(*id)),
+            _ => None,
+        }
+    }
+
+    /// The name of the file, preferring the Unicode `/UF` of a dictionary, then `/F` and the
+    /// platform specific `/Unix`, `/DOS` and `/Mac` names.
+    pub fn file_name(&self) -> Option<String> {
+        match self {
+            FileSpec::Name(name) => Some(String::from_utf8_lossy(name).into_owned()),
+            FileSpec::Dictionary(dict) => [b"UF".as_slice(), b"F", b"Unix", b"DOS", b"Mac"]
+                .into_iter()
+                .find_map(|key| decode_text_string(dict.get(key).ok()?).ok()),
+            FileSpec::Reference(_) => None,
+        }
+    }
+}
+
+/// Where the data of a stream is, see [`Stream::data`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamData<'a> {
+    /// The data follows the stream dictionary in the PDF file.
+    Inline(&'a [u8]),
+    /// The data is in the external file of `/F`, encoded with the filters of `/FFilter`. The bytes of
+    /// the stream in the PDF file, usually none, are ignored.
+    External(FileSpec),
+}
+
+impl Stream {
+    /// Get the data of the stream, or the file holding it for a stream with an `/F` entry.
+    ///
+    /// The data of an external file isn't read: decoding such a stream fails with
+    /// [`Error::ExternalStream`](crate::Error::ExternalStream) until [`Document::resolve_external_streams`] supplies it.
+    pub fn data(&self) -> StreamData<'_> {
+        match self.dict.get(b"F").ok().and_then(FileSpec::from_object) {
+            Some(file_spec) => StreamData::External(file_spec),
+            None => StreamData::Inline(&self.content),
+        }
+    }
+
+    /// Check whether the data of the stream is in an external file, see [`Stream::data`].
+    pub fn is_external(&self) -> bool {
+        self.dict.has(b"F")
+    }
+}
+
+impl Document {
+    /// Supply the data of the streams whose data is in external files, see [`Stream::data`], and
+    /// return the number of streams resolved.
+    ///
+    /// lopdf never reads these files itself. `resolver` is given the file specification of each
+    /// stream, with file specification dictionaries in other objects dereferenced, and returns the
+    /// data of the file, encoded with the filters of `/FFilter`. The stream then holds the data:
+    /// `/FFilter` and `/FDecodeParms` become `/Filter` and `/DecodeParms`, and `/F` and `/DL` are
+    /// removed. An error of `resolver` is returned at once, the streams resolved before are kept.
+    pub fn resolve_external_streams<R>(&mut self, resolver: R) -> Result<usize>
+    where
+        R: Fn(&FileSpec) -> Result<Vec<u8>>,
+    {
+        let external: Vec<(ObjectId, FileSpec)> = self
+            .objects
+            .iter()
+            .filter_map(|(&id, object)| {
+                let file_spec = object.as_stream().ok()?.dict.get(b"F").ok()?;
+                let file_spec = match self.dereference(file_spec) {
+                    Ok((_, object)) => FileSpec::from_object(object),
+                    Err(_) => FileSpec::from_object(file_spec),
+                };
+                Some((id, file_spec?))
+            })
+            .collect();
+        let mut resolved = 0;
+        for (id, file_spec) in external {
+            let data = resolver(&file_spec)?;
+            let stream = self.get_object_mut(id).and_then(Object::as_stream_mut)?;
+            for key in [b"F".as_slice(), b"DL", b"Filter", b"DecodeParms"] {
+                stream.dict.remove(key);
+            }
+            if let Some(filters) = stream.dict.remove(b"FFilter") {
+                stream.dict.set("Filter", filters);
+            }
+            if let Some(params) = stream.dict.remove(b"FDecodeParms") {
+                stream.dict.set("DecodeParms", params);
+            }
+            stream.set_content(data);
+            resolved += 1;
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+    use crate::Error;
+
+    #[test]
+    fn external_streams() {
+        let mut doc = create_document();
+        let hex_id = doc.add_object(Stream::new(
+            dictionary! {
+                "F" => Object::string_literal("data.hex"),
+                "FFilter" => "ASCIIHexDecode",
+                "DL" => 5,
+            },
+            vec![],
+        ));
+        let file_spec_id = doc.add_object(dictionary! {
+            "Type" => "Filespec",
+            "F" => Object::string_literal("plain.txt"),
+            "UF" => Object::string_literal("plain.txt"),
+        });
+        let plain_id = doc.add_object(Stream::new(dictionary! { "F" => file_spec_id }, vec![]));
+        let inline_id = doc.add_object(Stream::new(dictionary! {}, b"inline".to_vec()));
+        for id in [hex_id, plain_id, inline_id] {
+            doc.catalog_mut().unwrap().set(format!("Stream{}", id.0), id);
+        }
+
+        // Saving and loading keeps the /F form untouched.
+        let mut buffer = vec![];
+        doc.clone().save_to(&mut buffer).unwrap();
+        let mut doc = Document::load_mem(&buffer).unwrap();
+        let stream = |doc: &Document, id| doc.get_object(id).and_then(Object::as_stream).unwrap().clone();
+        let hex = stream(&doc, hex_id);
+        assert_eq!(hex.data(), StreamData::External(FileSpec::Name(b"data.hex".to_vec())));
+        assert_eq!(hex.dict.get(b"FFilter").unwrap(), &Object::from("ASCIIHexDecode"));
+        assert_eq!(
+            stream(&doc, plain_id).data(),
+            StreamData::External(FileSpec::Reference(file_spec_id))
+        );
+        assert_eq!(stream(&doc, inline_id).data(), StreamData::Inline(b"inline"));
+
+        assert!(matches!(hex.decompressed_content(), Err(Error::ExternalStream)));
+        assert!(matches!(hex.get_plain_content(), Err(Error::ExternalStream)));
+        assert!(matches!(hex.clone().decompress(), Err(Error::ExternalStream)));
+        doc.compress();
+        assert_eq!(stream(&doc, hex_id), hex);
+
+        // An error of the resolver is returned.
+        let result = doc.resolve_external_streams(|_| Err(Error::ExternalStream));
+        assert!(matches!(result, Err(Error::ExternalStream)));
+
+        let resolved = doc.resolve_external_streams(|file_spec| match file_spec.file_name().as_deref() {
+            Some("data.hex") => Ok(b"48656C6C6F>".to_vec()),
+            Some("plain.txt") => Ok(b"plain text".to_vec()),
+            _ => Err(Error::ExternalStream),
+        });
+        assert_eq!(resolved.unwrap(), 2);
+        let hex = stream(&doc, hex_id);
+        assert!(!hex.is_external() && !hex.dict.has(b"DL") && !hex.dict.has(b"FFilter"));
+        assert_eq!(hex.filters().unwrap(), [b"ASCIIHexDecode"]);
+        assert_eq!(hex.decompressed_content().unwrap(), b"Hello");
+        assert_eq!(stream(&doc, plain_id).decompressed_content().unwrap(), b"plain text");
+        assert_eq!(doc.resolve_external_streams(|_| Err(Error::ExternalStream)).unwrap(), 0);
+    }
+}
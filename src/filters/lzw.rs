@@ -0,0 +1,136 @@
+//! The LZW compression of the `LZWDecode` filter.
+//!
+//! Codes are written most significant bit first and start 9 bits wide, after the clear code 256.
+//! They grow up to 12 bits as the table fills, and the end of data code 257 ends the data. With
+//! `EarlyChange` 1, the default, the width grows one code earlier than the table requires.
+use log::warn;
+use weezl::{decode::Decoder, encode::Encoder, BitOrder, LzwStatus};
+
+/// Bits of the literal codes, which are bytes.
+const LITERAL_BITS: u8 = 8;
+
+/// Decode LZW data, stopping once `limit` bytes are produced.
+///
+/// Truncated or corrupted data gives the bytes decoded before the end or the first invalid code.
+pub fn decode(mut input: &[u8], early_change: bool, limit: usize) -> Vec<u8> {
+    let mut decoder = if early_change {
+        Decoder::with_tiff_size_switch(BitOrder::Msb, LITERAL_BITS)
+    } else {
+        Decoder::new(BitOrder::Msb, LITERAL_BITS)
+    };
+    let mut output = vec![];
+    let mut buffer = [0; 4096];
+    while output.len() < limit {
+        let result = decoder.decode_bytes(input, &mut buffer);
+        input = &input[result.consumed_in..];
+        output.extend_from_slice(&buffer[..result.consumed_out]);
+        match result.status {
+            Ok(LzwStatus::Ok) => {}
+            Ok(LzwStatus::Done | LzwStatus::NoProgress) => break,
+            Err(err) => {
+                warn!("Invalid LZW data: {err}");
+                break;
+            }
+        }
+    }
+    output.truncate(limit);
+    output
+}
+
+/// Encode data with LZW, ending it with the end of data code.
+pub fn encode(input: &[u8], early_change: bool) -> Vec<u8> {
+    let mut encoder = if early_change {
+        Encoder::with_tiff_size_switch(BitOrder::Msb, LITERAL_BITS)
+    } else {
+        Encoder::new(BitOrder::Msb, LITERAL_BITS)
+    };
+    // Encoding into a vector can't fail.
+    encoder.encode(input).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack codes starting with the clear code, growing the width as a decoder expects.
+    fn pack_codes(codes: &[u16], early_change: bool) -> Vec<u8> {
+        let (mut bits, mut width, mut next_code) = (0u64, 9, 258u32);
+        let (mut pending, mut output) = (0, vec![]);
+        for (index, &code) in codes.iter().enumerate() {
+            bits = bits << width | u64::from(code);
+            pending += width;
+            while pending >= 8 {
+                pending -= 8;
+                output.push((bits >> pending) as u8);
+            }
+            // Each code but the clear code and the first code after it adds an entry to the table.
+            if index > 1 && code < 256 {
+                next_code += 1;
+            }
+            if next_code + u32::from(early_change) >= 1 << width && width < 12 {
+                width += 1;
+            }
+        }
+        if pending > 0 {
+            output.push((bits << (8 - pending)) as u8);
+        }
+        output
+    }
+
+    /// Bytes whose successive pairs are all different, so LZW only writes literal codes for them.
+    fn unrepeated_pairs(len: usize) -> Vec<u8> {
+        let mut used = std::collections::HashSet::new();
+        let mut data = vec![0u8];
+        while data.len() < len {
+            let last = *data.last().unwrap();
+            let next = (1..=255)
+                .map(|step| last.wrapping_add(step))
+                .find(|&next| used.insert((last, next)));
+            data.push(next.unwrap());
+        }
+        data
+    }
+
+    #[test]
+    fn decode_example_of_the_specification() {
+        let encoded = [0x80, 0x0B, 0x60, 0x50, 0x22, 0x0C, 0x0C, 0x85, 0x01];
+        assert_eq!(decode(&encoded, true, usize::MAX), b"-----A---B");
+    }
+
+    #[test]
+    fn early_change_moves_the_width_increase() {
+        // Enough literal codes to fill the table past 511 entries, so 10 bit codes are written.
+        let data = unrepeated_pairs(700);
+        for early_change in [false, true] {
+            let codes: Vec<u16> = [256]
+                .into_iter()
+                .chain(data.iter().map(|&byte| u16::from(byte)))
+                .chain([257])
+                .collect();
+            let fixture = pack_codes(&codes, early_change);
+            assert_eq!(encode(&data, early_change), fixture, "early change {early_change}");
+            assert_eq!(decode(&fixture, early_change, usize::MAX), data);
+            // Reading the codes with the other width increase garbles the data after it.
+            assert_ne!(decode(&fixture, !early_change, usize::MAX), data);
+        }
+    }
+
+    #[test]
+    fn round_trip_and_recovery() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * i % 251) as u8).collect();
+        for early_change in [false, true] {
+            let encoded = encode(&data, early_change);
+            assert!(encoded.len() < data.len());
+            assert_eq!(decode(&encoded, early_change, usize::MAX), data);
+            assert_eq!(decode(&encoded, early_change, 100), data[..100]);
+
+            // Truncated data gives a prefix of the data.
+            let truncated = decode(&encoded[..encoded.len() / 2], early_change, usize::MAX);
+            assert!(!truncated.is_empty() && data.starts_with(&truncated));
+        }
+
+        // A code beyond the table stops decoding, keeping what was decoded before it.
+        let corrupted = pack_codes(&[256, u16::from(b'a'), u16::from(b'b'), 300, 257], true);
+        assert_eq!(decode(&corrupted, true, usize::MAX), b"ab");
+    }
+}
@@ -1 +1,2 @@
+pub mod lzw;
 pub mod png;
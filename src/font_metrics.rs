@@ -0,0 +1,404 @@
+use crate::{Dictionary, Document, Object};
+
+/// Widths of the glyphs of a simple font by character code, in thousandths of an em,
+/// see [`FontMetrics::widths`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontWidths {
+    /// Code of the first width of `widths`.
+    pub first_char: u8,
+    /// Widths of the consecutive codes from `first_char`.
+    pub widths: Vec<f32>,
+    /// Width of the other codes, the `/MissingWidth` of the font descriptor.
+    pub missing_width: f32,
+}
+
+impl FontWidths {
+    /// Width of the glyph of a character code.
+    pub fn width(&self, code: u8) -> f32 {
+        (code as usize)
+            .checked_sub(self.first_char as usize)
+            .and_then(|index| self.widths.get(index))
+            .copied()
+            .unwrap_or(self.missing_width)
+    }
+
+    /// Total width of the glyphs of a string shown with the font, one byte per character code.
+    pub fn text_width(&self, text: &[u8]) -> f32 {
+        text.iter().map(|&code| self.width(code)).sum()
+    }
+}
+
+/// An inconsistency of the widths of a font, see [`FontMetrics::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WidthsIssue {
+    /// No `/Widths` array, though the font isn't one of the standard 14 fonts.
+    MissingWidths,
+    /// `/FirstChar` or `/LastChar` is missing or isn't a character code from 0 to 255.
+    InvalidCharRange,
+    /// `/FirstChar` is greater than `/LastChar`, so no width applies.
+    ReversedCharRange { first_char: i64, last_char: i64 },
+    /// `/Widths` has another number of entries than the codes from `/FirstChar` to `/LastChar`.
+    /// Only the widths of the codes within both are used.
+    LengthMismatch { expected: usize, found: usize },
+    /// The entry of `/Widths` at this index isn't a number, the missing width is used instead.
+    InvalidWidth(usize),
+}
+
+/// Glyph metrics of a simple font dictionary, read leniently.
+///
+/// Indirect objects are resolved in the document; inconsistent entries never make measuring fail
+/// and are reported by [`FontMetrics::validate`] instead.
+#[derive(Debug, Clone, Copy)]
+pub struct FontMetrics<'a> {
+    document: &'a Document,
+    font: &'a Dictionary,
+}
+
+impl<'a> FontMetrics<'a> {
+    pub fn new(document: &'a Document, font: &'a Dictionary) -> Self {
+        FontMetrics { document, font }
+    }
+
+    /// Widths of the glyphs, from `/Widths` between `/FirstChar` and `/LastChar`.
+    ///
+    /// Only the codes within both the range and the array have a width, the other codes get the
+    /// `/MissingWidth` of the font descriptor, 0 by default. Standard 14 fonts without `/Widths`
+    /// get the widths of their Adobe font metrics for the printable ASCII codes of their built-in
+    /// `StandardEncoding`, except `Symbol` and `ZapfDingbats`.
+    pub fn widths(&self) -> FontWidths {
+        simple_font_widths(self.font, |object| self.resolve(object), 0.0).0
+    }
+
+    /// Inconsistencies of the entries [`FontMetrics::widths`] reads.
+    pub fn validate(&self) -> Vec<WidthsIssue> {
+        simple_font_widths(self.font, |object| self.resolve(object), 0.0).1
+    }
+
+    fn resolve(&self, object: &'a Object) -> &'a Object {
+        self.document.dereference(object).map_or(object, |(_, object)| object)
+    }
+}
+
+/// Read the widths of a simple font with [`FontMetrics::widths`], dereferencing indirect objects
+/// with `resolve`, and the inconsistencies found. Without `/MissingWidth`, `missing_width` is used.
+pub(crate) fn simple_font_widths<'a>(
+    font: &'a Dictionary, resolve: impl Fn(&'a Object) -> &'a Object, missing_width: f32,
+) -> (FontWidths, Vec<WidthsIssue>) {
+    let get = |dict: &'a Dictionary, key: &[u8]| dict.get(key).ok().map(&resolve);
+    let missing_width = get(font, b"FontDescriptor")
+        .and_then(|descriptor| get(descriptor.as_dict().ok()?, b"MissingWidth")?.as_float().ok())
+        .unwrap_or(missing_width);
+    let mut font_widths = FontWidths {
+        first_char: 0,
+        widths: vec![],
+        missing_width,
+    };
+    let mut issues = vec![];
+
+    let Some(Object::Array(widths)) = get(font, b"Widths") else {
+        let base_font = get(font, b"BaseFont").and_then(|name| name.as_name().ok());
+        match base_font {
+            Some(name) if STANDARD_14_FONTS.contains(&name) => {
+                if let Some(table) = standard_font_widths(name) {
+                    font_widths.first_char = FIRST_PRINTABLE;
+                    font_widths.widths = table.iter().map(|&width| f32::from(width)).collect();
+                }
+            }
+            // Composite fonts have their widths in their descendant font.
+            _ if get(font, b"Subtype").and_then(|subtype| subtype.as_name().ok()) == Some(b"Type0") => {}
+            _ => issues.push(WidthsIssue::MissingWidths),
+        }
+        return (font_widths, issues);
+    };
+
+    let code = |key: &[u8]| get(font, key).and_then(|code| code.as_i64().ok());
+    let (first_char, last_char) = match (code(b"FirstChar"), code(b"LastChar")) {
+        (Some(first_char @ 0..=255), Some(last_char @ 0..=255)) => (first_char, last_char),
+        (first_char, last_char) => {
+            issues.push(WidthsIssue::InvalidCharRange);
+            let first_char = first_char.unwrap_or(0).clamp(0, 255);
+            let last_char = last_char.map_or(first_char + widths.len() as i64 - 1, |last| last.min(255));
+            (first_char, last_char)
+        }
+    };
+    if first_char > last_char {
+        issues.push(WidthsIssue::ReversedCharRange { first_char, last_char });
+        return (font_widths, issues);
+    }
+
+    let expected = (last_char - first_char + 1) as usize;
+    if widths.len() != expected {
+        issues.push(WidthsIssue::LengthMismatch {
+            expected,
+            found: widths.len(),
+        });
+    }
+    font_widths.first_char = first_char as u8;
+    font_widths.widths = widths
+        .iter()
+        .take(expected)
+        .enumerate()
+        .map(|(index, width)| {
+            resolve(width).as_float().unwrap_or_else(|_| {
+                issues.push(WidthsIssue::InvalidWidth(index));
+                missing_width
+            })
+        })
+        .collect();
+    (font_widths, issues)
+}
+
+const STANDARD_14_FONTS: [&[u8]; 14] = [
+    b"Times-Roman",
+    b"Times-Bold",
+    b"Times-Italic",
+    b"Times-BoldItalic",
+    b"Helvetica",
+    b"Helvetica-Bold",
+    b"Helvetica-Oblique",
+    b"Helvetica-BoldOblique",
+    b"Courier",
+    b"Courier-Bold",
+    b"Courier-Oblique",
+    b"Courier-BoldOblique",
+    b"Symbol",
+    b"ZapfDingbats",
+];
+
+/// Code of the first width of the built-in tables, a space.
+const FIRST_PRINTABLE: u8 = 32;
+
+/// Widths of the printable ASCII codes, from 32 to 126, of a standard 14 font in `StandardEncoding`.
+fn standard_font_widths(base_font: &[u8]) -> Option<&'static [u16; 95]> {
+    match base_font {
+        b"Courier" | b"Courier-Bold" | b"Courier-Oblique" | b"Courier-BoldOblique" => Some(&COURIER),
+        b"Helvetica" | b"Helvetica-Oblique" => Some(&HELVETICA),
+        b"Helvetica-Bold" | b"Helvetica-BoldOblique" => Some(&HELVETICA_BOLD),
+        b"Times-Roman" => Some(&TIMES_ROMAN),
+        b"Times-Bold" => Some(&TIMES_BOLD),
+        b"Times-Italic" => Some(&TIMES_ITALIC),
+        b"Times-BoldItalic" => Some(&TIMES_BOLD_ITALIC),
+        _ => None,
+    }
+}
+
+const COURIER: [u16; 95] = [600; 95];
+
+const HELVETICA: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 222, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833,
+    722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 222, 556, 556, 500, 556,
+    556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334,
+    260, 334, 584,
+];
+
+const HELVETICA_BOLD: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 278, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833,
+    722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556, 278, 556, 611, 556, 611,
+    556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389,
+    280, 389, 584,
+];
+
+const TIMES_ROMAN: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 333, 333, 333, 500, 564, 250, 333, 250, 278, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 278, 278, 564, 564, 564, 444, 921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889,
+    722, 722, 556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500, 333, 444, 500, 444, 500,
+    444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500, 500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480,
+    200, 480, 541,
+];
+
+const TIMES_BOLD: [u16; 95] = [
+    250, 333, 555, 500, 500, 1000, 833, 333, 333, 333, 500, 570, 250, 333, 250, 278, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 333, 333, 570, 570, 570, 500, 930, 722, 667, 722, 722, 667, 611, 778, 778, 389, 500, 778, 667, 944,
+    722, 778, 611, 778, 722, 556, 667, 722, 722, 1000, 722, 722, 667, 333, 278, 333, 581, 500, 333, 500, 556, 444, 556,
+    444, 333, 500, 556, 278, 333, 556, 278, 833, 556, 500, 556, 556, 444, 389, 333, 556, 500, 722, 500, 500, 444, 394,
+    220, 394, 520,
+];
+
+const TIMES_ITALIC: [u16; 95] = [
+    250, 333, 420, 500, 500, 833, 778, 333, 333, 333, 500, 675, 250, 333, 250, 278, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 333, 333, 675, 675, 675, 500, 920, 611, 611, 667, 722, 611, 611, 722, 722, 333, 444, 667, 556, 833,
+    667, 722, 611, 722, 611, 500, 556, 722, 611, 833, 611, 556, 556, 389, 278, 389, 422, 500, 333, 500, 500, 444, 500,
+    444, 278, 500, 500, 278, 278, 444, 278, 722, 500, 500, 500, 500, 389, 389, 278, 500, 444, 667, 444, 444, 389, 400,
+    275, 400, 541,
+];
+
+const TIMES_BOLD_ITALIC: [u16; 95] = [
+    250, 389, 555, 500, 500, 833, 778, 333, 333, 333, 500, 570, 250, 333, 250, 278, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 333, 333, 570, 570, 570, 500, 832, 667, 667, 667, 722, 667, 667, 722, 778, 389, 500, 667, 611, 889,
+    722, 722, 611, 722, 667, 556, 611, 722, 667, 889, 667, 611, 611, 333, 278, 333, 570, 500, 333, 500, 500, 444, 500,
+    444, 333, 500, 556, 278, 278, 500, 278, 778, 556, 500, 500, 500, 389, 389, 278, 556, 444, 667, 500, 444, 389, 348,
+    220, 348, 570,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+    use crate::ObjectId;
+
+    fn metrics(font: Dictionary) -> (FontWidths, Vec<WidthsIssue>) {
+        let doc = Document::new();
+        let metrics = FontMetrics::new(&doc, &font);
+        (metrics.widths(), metrics.validate())
+    }
+
+    #[test]
+    fn consistent_widths() {
+        let (widths, issues) = metrics(dictionary! {
+            "Subtype" => "TrueType",
+            "FirstChar" => 65,
+            "LastChar" => 67,
+            "Widths" => vec![600.into(), 650.5.into(), 700.into()],
+        });
+        assert!(issues.is_empty());
+        assert_eq!((widths.width(b'B'), widths.width(b'C')), (650.5, 700.0));
+        assert_eq!((widths.width(b'@'), widths.width(b'D')), (0.0, 0.0));
+    }
+
+    #[test]
+    fn malformed_ranges() {
+        // Widths beyond /LastChar are ignored.
+        let (widths, issues) = metrics(dictionary! {
+            "FirstChar" => 65,
+            "LastChar" => 66,
+            "Widths" => vec![600.into(), 650.into(), 700.into()],
+        });
+        assert_eq!(issues, [WidthsIssue::LengthMismatch { expected: 2, found: 3 }]);
+        assert_eq!(widths.widths, [600.0, 650.0]);
+
+        let (widths, issues) = metrics(dictionary! {
+            "FirstChar" => 66,
+            "LastChar" => 65,
+            "Widths" => vec![600.into(), 650.into()],
+        });
+        assert_eq!(
+            issues,
+            [WidthsIssue::ReversedCharRange {
+                first_char: 66,
+                last_char: 65
+            }]
+        );
+        assert!(widths.widths.is_empty());
+
+        // Without /LastChar, the range ends with the widths.
+        let (widths, issues) = metrics(dictionary! {
+            "FirstChar" => 65,
+            "Widths" => vec![600.into(), "Oops".into()],
+        });
+        assert_eq!(issues, [WidthsIssue::InvalidCharRange, WidthsIssue::InvalidWidth(1)]);
+        assert_eq!((widths.first_char, widths.widths), (65, vec![600.0, 0.0]));
+
+        let (widths, issues) = metrics(dictionary! {
+            "FirstChar" => -3,
+            "LastChar" => 300,
+            "Widths" => vec![600.into()],
+        });
+        assert_eq!(
+            issues,
+            [
+                WidthsIssue::InvalidCharRange,
+                WidthsIssue::LengthMismatch {
+                    expected: 256,
+                    found: 1
+                }
+            ]
+        );
+        assert_eq!((widths.first_char, widths.widths), (0, vec![600.0]));
+    }
+
+    #[test]
+    fn missing_widths() {
+        let (widths, issues) = metrics(dictionary! { "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+        assert!(issues.is_empty());
+        assert_eq!(widths.text_width(b"Hello"), 2278.0);
+        let (widths, _) = metrics(dictionary! { "BaseFont" => "Times-Bold" });
+        assert_eq!(widths.text_width(b"World"), 2778.0);
+        let (widths, _) = metrics(dictionary! { "BaseFont" => "Courier-Oblique" });
+        assert_eq!(widths.text_width(b"Hello"), 3000.0);
+
+        // Widths outside the printable ASCII codes and of the symbolic fonts aren't known.
+        let (widths, issues) = metrics(dictionary! { "BaseFont" => "ZapfDingbats" });
+        assert!(issues.is_empty());
+        assert_eq!(widths.text_width(b"\xA1Hello"), 0.0);
+
+        let (_, issues) = metrics(dictionary! { "Subtype" => "Type0", "BaseFont" => "Arial" });
+        assert!(issues.is_empty());
+        let (widths, issues) = metrics(dictionary! { "Subtype" => "TrueType", "BaseFont" => "Arial" });
+        assert_eq!(issues, [WidthsIssue::MissingWidths]);
+        assert!(widths.widths.is_empty());
+    }
+
+    #[test]
+    fn widths_in_object_stream() {
+        let body = [
+            "1 0 obj<</Type/Catalog/Pages 2 0 R>>endobj\n",
+            "2 0 obj<</Type/Pages/Kids[]/Count 0>>endobj\n",
+            "3 0 obj<</Type/Font/Subtype/Type1/BaseFont/Custom/FirstChar 65/LastChar 66/Widths 4 0 R\
+             /FontDescriptor<</MissingWidth 5 0 R>>>>endobj\n",
+            "6 0 obj<</Type/ObjStm/N 2/First 9/Length 22>>stream\n4 0 5 10 [500 550] 250\nendstream endobj\n",
+        ]
+        .concat();
+        let mut buffer = format!("%PDF-1.5\n{body}").into_bytes();
+        let offset = |buffer: &[u8], id: u32| {
+            let header = format!("{id} 0 obj");
+            let offset = buffer
+                .windows(header.len())
+                .position(|window| window == header.as_bytes());
+            (offset.unwrap() as u32).to_be_bytes()
+        };
+        let mut entries = vec![[0, 0, 0, 0, 0, 0xFF, 0xFF]];
+        for id in [1, 2, 3] {
+            let [a, b, c, d] = offset(&buffer, id);
+            entries.push([1, a, b, c, d, 0, 0]);
+        }
+        entries.extend([[2, 0, 0, 0, 6, 0, 0], [2, 0, 0, 0, 6, 0, 1]]);
+        let [a, b, c, d] = offset(&buffer, 6);
+        entries.push([1, a, b, c, d, 0, 0]);
+        let xref_start = buffer.len();
+        let [a, b, c, d] = (xref_start as u32).to_be_bytes();
+        entries.push([1, a, b, c, d, 0, 0]);
+        buffer.extend(b"7 0 obj<</Type/XRef/Size 8/W[1 4 2]/Root 1 0 R/Length 56>>stream\n");
+        buffer.extend(entries.concat());
+        buffer.extend(format!("\nendstream endobj\nstartxref\n{xref_start}\n%%EOF").as_bytes());
+
+        let doc = Document::load_mem(&buffer).unwrap();
+        let font = doc.get_dictionary(ObjectId(3, 0)).unwrap();
+        let metrics = FontMetrics::new(&doc, font);
+        assert!(metrics.validate().is_empty());
+        assert_eq!(metrics.widths().text_width(b"ABC"), 500.0 + 550.0 + 250.0);
+    }
+
+    #[test]
+    fn measure_text_with_short_widths() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let descriptor_id = doc.add_object(dictionary! { "Type" => "FontDescriptor", "MissingWidth" => 400 });
+        let widths_id = doc.add_object(vec![500.into(), 600.into()]);
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "TrueType",
+            "BaseFont" => "Custom",
+            "FirstChar" => 65,
+            "LastChar" => 68,
+            "Widths" => widths_id,
+            "FontDescriptor" => descriptor_id,
+        });
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Resources", dictionary! { "Font" => dictionary! { "F2" => font_id } });
+        doc.change_page_content(page_id, b"BT /F2 10 Tf 100 600 Td (ABCD) Tj ET".to_vec())
+            .unwrap();
+
+        let font = doc.get_dictionary(font_id).unwrap();
+        let metrics = FontMetrics::new(&doc, font);
+        assert_eq!(
+            metrics.validate(),
+            [WidthsIssue::LengthMismatch { expected: 4, found: 2 }]
+        );
+        assert_eq!(metrics.widths().text_width(b"ABCD"), 500.0 + 600.0 + 400.0 + 400.0);
+        let runs = doc.get_page_text_runs(page_id).unwrap();
+        assert_eq!(runs[0].width(), 19.0);
+    }
+}
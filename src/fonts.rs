@@ -0,0 +1,491 @@
+use crate::{Dictionary, Document, Object, ObjectId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Form XObjects nested deeper than this aren't searched for fonts.
+const MAX_FORM_DEPTH: usize = 16;
+
+/// How the character codes of a font select its glyphs, see [`FontInfo::encoding`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FontEncodingInfo {
+    /// No `/Encoding`, the built-in encoding of the font program is used.
+    Builtin,
+    /// A predefined encoding or CMap, e.g. `WinAnsiEncoding` or `Identity-H`.
+    Named(String),
+    /// An encoding dictionary with `/Differences` from its base encoding, if any.
+    Differences { base: Option<String> },
+    /// A CMap stream embedded in the document.
+    EmbeddedCMap,
+}
+
+impl fmt::Display for FontEncodingInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontEncodingInfo::Builtin => f.write_str("Builtin"),
+            FontEncodingInfo::Named(name) => f.write_str(name),
+            FontEncodingInfo::Differences { base: Some(base) } => write!(f, "{base}+Differences"),
+            FontEncodingInfo::Differences { base: None } => f.write_str("Custom+Differences"),
+            FontEncodingInfo::EmbeddedCMap => f.write_str("Embedded CMap"),
+        }
+    }
+}
+
+/// Summary of a font used by the pages of a document, see [`Document::font_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontInfo {
+    /// The font dictionary, `None` for a font dictionary written directly in resources.
+    pub id: Option<ObjectId>,
+    /// Names of the font in the resources using it, sorted.
+    pub names: Vec<Vec<u8>>,
+    pub base_font: Option<String>,
+    pub subtype: Option<String>,
+    /// Subtype of the descendant font of a `Type0` font, e.g. `CIDFontType2`.
+    pub descendant_subtype: Option<String>,
+    pub encoding: FontEncodingInfo,
+    /// Key of the embedded font program in the font descriptor, e.g. `FontFile2`, followed by
+    /// the subtype of a `FontFile3` stream, e.g. `FontFile3/OpenType`. `Type3` fonts, whose
+    /// glyphs are content streams, are reported as `Type3`.
+    pub embedded: Option<String>,
+    /// The font has a `/ToUnicode` CMap.
+    pub to_unicode: bool,
+    /// Numbers of the pages using the font, sorted.
+    pub pages: Vec<u32>,
+}
+
+/// Entries of font dictionaries which must be equal for [`Document::merge_duplicate_fonts`].
+const MERGED_FONT_KEYS: [&[u8]; 12] = [
+    b"Subtype",
+    b"Encoding",
+    b"ToUnicode",
+    b"FirstChar",
+    b"LastChar",
+    b"Widths",
+    b"W",
+    b"W2",
+    b"DW",
+    b"DW2",
+    b"CIDToGIDMap",
+    b"CIDSystemInfo",
+];
+
+const FONT_FILE_KEYS: [&[u8]; 3] = [b"FontFile", b"FontFile2", b"FontFile3"];
+
+/// Strip the tag of a font subset, e.g. `ABCDEF+` of `ABCDEF+DejaVuSans`.
+fn strip_subset_tag(name: &[u8]) -> &[u8] {
+    match name.get(..7) {
+        Some(tag) if tag[6] == b'+' && tag[..6].iter().all(u8::is_ascii_uppercase) => &name[7..],
+        _ => name,
+    }
+}
+
+/// A name entry of a font dictionary or descriptor without its subset tag.
+fn untagged_name<'a>(dict: &'a Dictionary, key: &[u8]) -> Option<&'a [u8]> {
+    dict.get(key).and_then(Object::as_name).ok().map(strip_subset_tag)
+}
+
+fn replace_references(object: &mut Object, replaced: &BTreeMap<ObjectId, ObjectId>) -> usize {
+    match object {
+        Object::Reference(id) => match replaced.get(id) {
+            Some(new_id) => {
+                *id = *new_id;
+                1
+            }
+            None => 0,
+        },
+        Object::Array(array) => array.iter_mut().map(|item| replace_references(item, replaced)).sum(),
+        Object::Dictionary(dict) => dict
+            .iter_mut()
+            .map(|(_, value)| replace_references(value, replaced))
+            .sum(),
+        Object::Stream(stream) => stream
+            .dict
+            .iter_mut()
+            .map(|(_, value)| replace_references(value, replaced))
+            .sum(),
+        _ => 0,
+    }
+}
+
+fn name_string(object: Option<&Object>) -> Option<String> {
+    object
+        .and_then(|it| it.as_name().ok())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+}
+
+impl Document {
+    /// Summarize the fonts used by the pages, each font dictionary once.
+    ///
+    /// The fonts of the page resources are included, along with those of the Form XObjects the
+    /// pages can paint. Fonts are listed in the order of the page first using them.
+    pub fn font_report(&self) -> Vec<FontInfo> {
+        let mut fonts: Vec<FontInfo> = vec![];
+        let mut indices = HashMap::new();
+        for (page_number, page_id) in self.get_pages() {
+            let Ok((resource_dict, resource_ids)) = self.get_page_resources(page_id) else {
+                continue;
+            };
+            let resources = resource_dict
+                .into_iter()
+                .chain(resource_ids.iter().filter_map(|id| self.get_dictionary(*id).ok()));
+            let mut used = vec![];
+            let mut forms = HashSet::new();
+            for resources in resources {
+                self.collect_report_fonts(resources, &mut used, &mut forms, 0);
+            }
+
+            for (name, id, font) in used {
+                let index = match id.and_then(|id| indices.get(&id)) {
+                    Some(&index) => index,
+                    None => {
+                        if let Some(id) = id {
+                            indices.insert(id, fonts.len());
+                        }
+                        fonts.push(self.summarize_font(id, font));
+                        fonts.len() - 1
+                    }
+                };
+                let info = &mut fonts[index];
+                if !info.names.contains(&name) {
+                    info.names.push(name);
+                    info.names.sort();
+                }
+                if info.pages.last() != Some(&page_number) {
+                    info.pages.push(page_number);
+                }
+            }
+        }
+        fonts
+    }
+
+    /// Merge embedded fonts which are the same, typically subsets of a font embedded again by
+    /// each of the merged documents, and return the number of font dictionaries merged into another.
+    ///
+    /// Fonts are only merged when their font programs are equal after decompression and all the
+    /// entries affecting how they are shown, like `/Widths`, `/FirstChar`, `/LastChar`,
+    /// `/Encoding` and `/ToUnicode`, are equal as well; their names may only differ by the subset
+    /// tag. References to a merged font are rewritten to the font it was merged into, then the font
+    /// and the objects only it used are removed.
+    pub fn merge_duplicate_fonts(&mut self) -> usize {
+        let mut kept: HashMap<Vec<u8>, Vec<ObjectId>> = HashMap::new();
+        let mut replaced = BTreeMap::new();
+        for (&id, object) in &self.objects {
+            let Ok(font) = object.as_dict() else {
+                continue;
+            };
+            if font.get_type().ok() != Some(b"Font") {
+                continue;
+            }
+            let Some(program) = self.embedded_font_program(font) else {
+                continue;
+            };
+            let same_program = kept.entry(program).or_default();
+            let merged_into = same_program.iter().copied().find(|&kept_id| {
+                self.get_dictionary(kept_id)
+                    .is_ok_and(|kept_font| self.equivalent_fonts(kept_font, font, true))
+            });
+            match merged_into {
+                Some(kept_id) => {
+                    replaced.insert(id, kept_id);
+                }
+                None => same_program.push(id),
+            }
+        }
+        if replaced.is_empty() {
+            return 0;
+        }
+
+        for (id, object) in self.objects.iter_mut() {
+            if replace_references(object, &replaced) > 0 {
+                self.dirty.insert(*id);
+            }
+        }
+        for (_, value) in self.trailer.iter_mut() {
+            replace_references(value, &replaced);
+        }
+
+        // Remove the merged fonts, then the objects no longer referenced once they are gone.
+        let mut counts: HashMap<ObjectId, usize> = HashMap::new();
+        let mut references = vec![];
+        for (_, value) in self.trailer.iter() {
+            value.push_references(&mut references);
+        }
+        for object in self.objects.values() {
+            object.push_references(&mut references);
+        }
+        for id in references.drain(..) {
+            *counts.entry(id).or_default() += 1;
+        }
+        let mut pending: Vec<ObjectId> = replaced.keys().copied().collect();
+        while let Some(id) = pending.pop() {
+            if counts.get(&id).is_some_and(|&count| count > 0) {
+                continue;
+            }
+            let Some(object) = self.objects.remove(&id) else {
+                continue;
+            };
+            self.dirty.insert(id);
+            object.push_references(&mut references);
+            for id in references.drain(..) {
+                if let Some(count) = counts.get_mut(&id) {
+                    *count -= 1;
+                    if *count == 0 {
+                        pending.push(id);
+                    }
+                }
+            }
+        }
+        replaced.len()
+    }
+
+    /// The decompressed font program embedded for a font, or its descendant font for a `Type0` font.
+    fn embedded_font_program(&self, font: &Dictionary) -> Option<Vec<u8>> {
+        let font = self.descendant_font(font).unwrap_or(font);
+        let descriptor = font.get_deref(b"FontDescriptor", self).and_then(Object::as_dict).ok()?;
+        let key = FONT_FILE_KEYS.into_iter().find(|key| descriptor.has(key))?;
+        let program = descriptor.get_deref(key, self).and_then(Object::as_stream).ok()?;
+        program.get_plain_content().ok()
+    }
+
+    fn descendant_font<'a>(&'a self, font: &'a Dictionary) -> Option<&'a Dictionary> {
+        let descendants = font
+            .get_deref(b"DescendantFonts", self)
+            .and_then(Object::as_array)
+            .ok()?;
+        self.dereference(descendants.first()?).ok()?.1.as_dict().ok()
+    }
+
+    /// Whether two fonts with the same font program can be merged, see [`Document::merge_duplicate_fonts`].
+    ///
+    /// The descendant fonts of `Type0` fonts are compared when `descend` is set.
+    fn equivalent_fonts(&self, a: &Dictionary, b: &Dictionary, descend: bool) -> bool {
+        if untagged_name(a, b"BaseFont") != untagged_name(b, b"BaseFont")
+            || !MERGED_FONT_KEYS.iter().all(|key| self.equal_entries(a, b, key))
+        {
+            return false;
+        }
+
+        let descriptors = (
+            a.get_deref(b"FontDescriptor", self).and_then(Object::as_dict),
+            b.get_deref(b"FontDescriptor", self).and_then(Object::as_dict),
+        );
+        let same_descriptors = match descriptors {
+            (Ok(a), Ok(b)) => {
+                // The font programs are already known to be equal.
+                untagged_name(a, b"FontName") == untagged_name(b, b"FontName")
+                    && a.iter()
+                        .chain(b.iter())
+                        .map(|(key, _)| key.as_slice())
+                        .filter(|key| *key != b"FontName" && !FONT_FILE_KEYS.contains(key))
+                        .all(|key| self.equal_entries(a, b, key))
+            }
+            (Err(_), Err(_)) => true,
+            _ => false,
+        };
+        if !same_descriptors {
+            return false;
+        }
+
+        match (self.descendant_font(a), self.descendant_font(b)) {
+            (Some(a), Some(b)) => descend && self.equivalent_fonts(a, b, false),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether an entry is missing from both dictionaries or has the same value in both, streams
+    /// being compared by their decompressed data.
+    fn equal_entries(&self, a: &Dictionary, b: &Dictionary, key: &[u8]) -> bool {
+        if !a.has(key) && !b.has(key) {
+            return true;
+        }
+        match (a.get_deref(key, self), b.get_deref(key, self)) {
+            (Ok(Object::Stream(a)), Ok(Object::Stream(b))) => {
+                matches!((a.get_plain_content(), b.get_plain_content()), (Ok(a), Ok(b)) if a == b)
+            }
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Collect the fonts of `resources` and of the Form XObjects they name.
+    fn collect_report_fonts<'a>(
+        &'a self, resources: &'a Dictionary, used: &mut Vec<(Vec<u8>, Option<ObjectId>, &'a Dictionary)>,
+        forms: &mut HashSet<ObjectId>, depth: usize,
+    ) {
+        if let Ok(font_dict) = self.get_dict_in_dict(resources, b"Font") {
+            for (name, value) in font_dict {
+                let id = value.as_reference().ok();
+                if let Ok((_, Object::Dictionary(font))) = self.dereference(value) {
+                    used.push((name.clone(), id, font));
+                }
+            }
+        }
+        if depth >= MAX_FORM_DEPTH {
+            return;
+        }
+        let Ok(xobjects) = self.get_dict_in_dict(resources, b"XObject") else {
+            return;
+        };
+        for (_, value) in xobjects {
+            let Ok(id) = value.as_reference() else {
+                continue;
+            };
+            let Ok(form) = self.get_object(id).and_then(Object::as_stream) else {
+                continue;
+            };
+            if form.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Form") || !forms.insert(id) {
+                continue;
+            }
+            if let Ok(resources) = self.get_dict_in_dict(&form.dict, b"Resources") {
+                self.collect_report_fonts(resources, used, forms, depth + 1);
+            }
+        }
+    }
+
+    fn summarize_font(&self, id: Option<ObjectId>, font: &Dictionary) -> FontInfo {
+        fn get<'a>(doc: &'a Document, dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+            dict.get(key).and_then(|it| doc.dereference(it)).ok().map(|it| it.1)
+        }
+        let subtype = name_string(font.get(b"Subtype").ok());
+        let descendant = get(self, font, b"DescendantFonts")
+            .and_then(|it| it.as_array().ok()?.first())
+            .and_then(|it| self.dereference(it).ok()?.1.as_dict().ok());
+
+        let encoding = match get(self, font, b"Encoding") {
+            None => FontEncodingInfo::Builtin,
+            Some(Object::Name(name)) => FontEncodingInfo::Named(String::from_utf8_lossy(name).into_owned()),
+            Some(Object::Stream(_)) => FontEncodingInfo::EmbeddedCMap,
+            Some(Object::Dictionary(encoding)) => {
+                let base = name_string(encoding.get(b"BaseEncoding").ok());
+                match (encoding.has(b"Differences"), base) {
+                    (true, base) => FontEncodingInfo::Differences { base },
+                    (false, Some(base)) => FontEncodingInfo::Named(base),
+                    (false, None) => FontEncodingInfo::Builtin,
+                }
+            }
+            Some(_) => FontEncodingInfo::Builtin,
+        };
+
+        let descriptor = get(self, descendant.unwrap_or(font), b"FontDescriptor").and_then(|it| it.as_dict().ok());
+        let embedded = if subtype.as_deref() == Some("Type3") {
+            Some("Type3".to_string())
+        } else {
+            descriptor.and_then(|descriptor| {
+                let key = [b"FontFile".as_slice(), b"FontFile2", b"FontFile3"]
+                    .into_iter()
+                    .find(|key| descriptor.has(key))?;
+                let key = String::from_utf8_lossy(key).into_owned();
+                let program = get(self, descriptor, key.as_bytes()).and_then(|it| it.as_stream().ok());
+                Some(match program.and_then(|it| name_string(it.dict.get(b"Subtype").ok())) {
+                    Some(program_subtype) => format!("{key}/{program_subtype}"),
+                    None => key,
+                })
+            })
+        };
+
+        FontInfo {
+            id,
+            names: vec![],
+            base_font: name_string(font.get(b"BaseFont").ok()),
+            subtype,
+            descendant_subtype: descendant.and_then(|it| name_string(it.get(b"Subtype").ok())),
+            encoding,
+            embedded,
+            to_unicode: font.has(b"ToUnicode"),
+            pages: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+
+    #[test]
+    fn report_of_created_document() {
+        let doc = create_document_with_texts(&["Hello", "World"]);
+        let report = doc.font_report();
+        assert_eq!(report.len(), 1);
+        let font = &report[0];
+        assert!(font.id.is_some());
+        assert_eq!(font.names, [b"F1".to_vec()]);
+        assert_eq!(font.base_font.as_deref(), Some("Courier"));
+        assert_eq!(font.subtype.as_deref(), Some("Type1"));
+        assert_eq!(font.encoding, FontEncodingInfo::Builtin);
+        assert_eq!(font.embedded, None);
+        assert!(!font.to_unicode);
+        assert_eq!(font.pages, [1, 2]);
+    }
+
+    #[test]
+    fn report_of_embedded_fonts() {
+        let doc = Document::load("assets/unicode.pdf").unwrap();
+        let report = doc.font_report();
+        assert_eq!(report.len(), 1);
+        let font = &report[0];
+        assert_eq!(font.base_font.as_deref(), Some("AAAAAA+NotoEmoji-Regular"));
+        assert_eq!(font.subtype.as_deref(), Some("Type0"));
+        assert_eq!(font.descendant_subtype.as_deref(), Some("CIDFontType2"));
+        assert_eq!(font.encoding.to_string(), "Identity-H");
+        assert_eq!(font.embedded.as_deref(), Some("FontFile2"));
+        assert!(font.to_unicode);
+        assert_eq!(font.pages, [1]);
+    }
+
+    /// Append the pages of a second copy of the document at `path` to the first one.
+    fn load_twice(path: &str) -> Document {
+        let mut doc = Document::load(path).unwrap();
+        let mut other = Document::load(path).unwrap();
+        other.renumber_objects_with(doc.max_id + 1);
+        let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let other_pages: Vec<ObjectId> = other.page_iter().collect();
+        for &page_id in &other_pages {
+            other.get_dictionary_mut(page_id).unwrap().set("Parent", pages_id);
+        }
+        doc.max_id = other.max_id;
+        doc.objects.extend(other.objects);
+        let pages = doc.get_dictionary_mut(pages_id).unwrap();
+        let kids = pages.get_mut(b"Kids").and_then(Object::as_array_mut).unwrap();
+        kids.extend(other_pages.into_iter().map(Object::from));
+        let count = kids.len() as i64;
+        pages.set("Count", count);
+        doc
+    }
+
+    #[test]
+    fn merge_fonts_of_merged_documents() {
+        let mut doc = load_twice("assets/unicode.pdf");
+        let text = doc.extract_text(&[1]).unwrap();
+        assert_eq!(doc.extract_text(&[2]).unwrap(), text);
+        assert_eq!(doc.font_report().len(), 2);
+        let object_count = doc.objects.len();
+
+        // The Type0 font and its descendant font.
+        assert_eq!(doc.merge_duplicate_fonts(), 2);
+        let report = doc.font_report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].pages, [1, 2]);
+        // The font descriptor, program and ToUnicode CMap of the merged font are gone as well.
+        assert!(doc.objects.len() <= object_count - 5);
+        assert_eq!(doc.extract_text(&[1]).unwrap(), text);
+        assert_eq!(doc.extract_text(&[2]).unwrap(), text);
+        assert_eq!(doc.merge_duplicate_fonts(), 0);
+    }
+
+    #[test]
+    fn fonts_with_other_widths_are_kept() {
+        let mut doc = load_twice("assets/unicode.pdf");
+        let cid_fonts: Vec<ObjectId> = doc
+            .objects
+            .iter()
+            .filter(|(_, object)| object.as_dict().is_ok_and(|font| font.has(b"CIDSystemInfo")))
+            .map(|(id, _)| *id)
+            .collect();
+        assert_eq!(cid_fonts.len(), 2);
+        doc.get_dictionary_mut(cid_fonts[1]).unwrap().set("DW", 500);
+
+        assert_eq!(doc.merge_duplicate_fonts(), 0);
+        assert_eq!(doc.font_report().len(), 2);
+    }
+}
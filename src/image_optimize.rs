@@ -0,0 +1,586 @@
+#![cfg(feature = "nom_parser")]
+use log::warn;
+use std::collections::BTreeMap;
+
+use crate::content::{matrix_from_operands, multiply_matrix, IDENTITY_MATRIX};
+use crate::{Dictionary, Document, Object, ObjectId, Result, Stream};
+
+/// Options for [`Document::optimize_images`].
+#[derive(Debug, Clone, Default)]
+pub struct ImageOptimizeOptions {
+    /// Maximum effective resolution in dots per inch, measured against the largest size
+    /// at which an image is painted on a page. Images whose placement can't be determined
+    /// (e.g. ones only used inside form XObjects) are not affected by this limit.
+    pub max_dpi: Option<f32>,
+    /// Maximum width and height in pixels, applied to every image.
+    pub max_pixels: Option<u32>,
+    /// Quality (1-100) used to re-encode `DCTDecode` images. JPEG images are left untouched when `None`.
+    #[cfg(feature = "embed_image")]
+    pub jpeg_quality: Option<u8>,
+}
+
+/// Size change of a single image rewritten by [`Document::optimize_images`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizedImage {
+    pub id: ObjectId,
+    /// Width and height in pixels before and after optimization.
+    pub original_dimensions: (u32, u32),
+    pub optimized_dimensions: (u32, u32),
+    /// Length of the encoded stream data before and after optimization.
+    pub original_size: usize,
+    pub optimized_size: usize,
+}
+
+/// Result of [`Document::optimize_images`], listing the images that were replaced.
+#[derive(Debug, Clone, Default)]
+pub struct OptimizeReport {
+    pub images: Vec<OptimizedImage>,
+}
+
+impl OptimizeReport {
+    /// Total number of stream bytes saved.
+    pub fn saved_bytes(&self) -> usize {
+        self.images
+            .iter()
+            .map(|image| image.original_size.saturating_sub(image.optimized_size))
+            .sum()
+    }
+}
+
+impl Document {
+    /// Downsample and recompress image XObjects.
+    ///
+    /// Images with 8 bits per component are resampled down to the limits given in `options`,
+    /// a soft mask (`/SMask`) is always resampled together with its parent image.
+    /// 1-bit images and masks are encoded with both `CCITTFaxDecode` (Group 4) and `FlateDecode`,
+    /// keeping the smaller one. Other images are only recompressed with `FlateDecode`.
+    /// An image is only replaced if the new stream is smaller; object ids and content streams
+    /// are left unchanged.
+    pub fn optimize_images(&mut self, options: ImageOptimizeOptions) -> Result<OptimizeReport> {
+        let placements = self.image_placements();
+
+        let mut soft_masks = BTreeMap::new();
+        let mut image_ids = vec![];
+        for (&id, object) in &self.objects {
+            if let Ok(stream) = object.as_stream() {
+                if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Image") {
+                    image_ids.push(id);
+                    if let Ok(mask_id) = stream.dict.get(b"SMask").and_then(Object::as_reference) {
+                        soft_masks.insert(mask_id, id);
+                    }
+                }
+            }
+        }
+
+        let mut report = OptimizeReport::default();
+        for id in image_ids {
+            if soft_masks.contains_key(&id) {
+                // Handled together with the parent image.
+                continue;
+            }
+            let stream = match self.get_object(id).and_then(Object::as_stream) {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let (width, height) = match image_dimensions(&stream.dict) {
+                Some(dimensions) => dimensions,
+                None => continue,
+            };
+            let target = target_dimensions((width, height), placements.get(&id).copied(), &options);
+
+            let mut replacements = vec![];
+            match optimize_image(self, stream, (width, height), target, &options) {
+                Ok(Some(optimized)) => {
+                    let optimized_dimensions = image_dimensions(&optimized.dict).unwrap_or((width, height));
+                    let mask_id = stream.dict.get(b"SMask").and_then(Object::as_reference).ok();
+                    if let Some(mask_id) = mask_id.filter(|_| optimized_dimensions != (width, height)) {
+                        // A resized image needs a soft mask resized by the same ratio.
+                        match self.resize_soft_mask(mask_id, (width, height), optimized_dimensions) {
+                            Some(mask) => replacements.push((mask_id, mask)),
+                            None => {
+                                warn!("Soft mask {:?} of image {:?} can't be resampled", mask_id, id);
+                                continue;
+                            }
+                        }
+                    }
+                    replacements.push((id, optimized));
+                }
+                Ok(None) => {}
+                Err(err) => warn!("Skipping image {:?}: {}", id, err),
+            }
+
+            for (id, optimized) in replacements {
+                if let Ok(Object::Stream(stream)) = self.get_object_mut(id) {
+                    report.images.push(OptimizedImage {
+                        id,
+                        original_dimensions: image_dimensions(&stream.dict).unwrap_or_default(),
+                        optimized_dimensions: image_dimensions(&optimized.dict).unwrap_or_default(),
+                        original_size: stream.content.len(),
+                        optimized_size: optimized.content.len(),
+                    });
+                    *stream = optimized;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Largest size in points at which each image XObject is painted directly by a page.
+    fn image_placements(&self) -> BTreeMap<ObjectId, (f32, f32)> {
+        let mut placements = BTreeMap::new();
+        for page_id in self.page_iter() {
+            let content = match self.get_and_decode_page_content(page_id) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            let xobjects = self.page_xobjects(page_id);
+            let mut ctm = IDENTITY_MATRIX;
+            let mut graphics_stack = vec![];
+            for operation in &content.operations {
+                match operation.operator.as_ref() {
+                    "q" => graphics_stack.push(ctm),
+                    "Q" => ctm = graphics_stack.pop().unwrap_or(IDENTITY_MATRIX),
+                    "cm" => {
+                        if let Some(matrix) = matrix_from_operands(&operation.operands) {
+                            ctm = multiply_matrix(&matrix, &ctm);
+                        }
+                    }
+                    "Do" => {
+                        let id = operation
+                            .operands
+                            .first()
+                            .and_then(|name| name.as_name().ok())
+                            .and_then(|name| xobjects.get(name));
+                        if let Some(&id) = id {
+                            let size = (ctm[0].hypot(ctm[1]), ctm[2].hypot(ctm[3]));
+                            let placement = placements.entry(id).or_insert((0.0_f32, 0.0_f32));
+                            *placement = (placement.0.max(size.0), placement.1.max(size.1));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        placements
+    }
+
+    fn page_xobjects(&self, page_id: ObjectId) -> BTreeMap<Vec<u8>, ObjectId> {
+        let mut xobjects = BTreeMap::new();
+        if let Ok((resource_dict, resource_ids)) = self.get_page_resources(page_id) {
+            let resources = resource_dict
+                .into_iter()
+                .chain(resource_ids.into_iter().filter_map(|id| self.get_dictionary(id).ok()));
+            for resources in resources {
+                if let Ok(dict) = self.get_dict_in_dict(resources, b"XObject") {
+                    for (name, value) in dict {
+                        if let Ok(id) = value.as_reference() {
+                            xobjects.entry(name.clone()).or_insert(id);
+                        }
+                    }
+                }
+            }
+        }
+        xobjects
+    }
+
+    fn resize_soft_mask(&self, mask_id: ObjectId, original: (u32, u32), resized: (u32, u32)) -> Option<Stream> {
+        let mask = self.get_object(mask_id).and_then(Object::as_stream).ok()?;
+        let (width, height) = image_dimensions(&mask.dict)?;
+        let target = (
+            scale_dimension(width, resized.0 as f32 / original.0 as f32),
+            scale_dimension(height, resized.1 as f32 / original.1 as f32),
+        );
+        let pixels = decode_pixels(mask, 1)?;
+        Some(encode_flate(
+            mask,
+            resample(&pixels, (width, height), target, 1),
+            target,
+        ))
+    }
+}
+
+fn image_dimensions(dict: &Dictionary) -> Option<(u32, u32)> {
+    let width = dict.get(b"Width").and_then(Object::as_i64).ok()?;
+    let height = dict.get(b"Height").and_then(Object::as_i64).ok()?;
+    Some((u32::try_from(width).ok()?, u32::try_from(height).ok()?)).filter(|&(w, h)| w > 0 && h > 0)
+}
+
+fn scale_dimension(size: u32, factor: f32) -> u32 {
+    ((size as f32 * factor).round() as u32).clamp(1, size)
+}
+
+/// Dimensions an image should be resampled to, keeping its aspect ratio.
+fn target_dimensions(
+    (width, height): (u32, u32), placement: Option<(f32, f32)>, options: &ImageOptimizeOptions,
+) -> (u32, u32) {
+    let mut factor = 1.0_f32;
+    if let (Some(dpi), Some((placed_width, placed_height))) = (options.max_dpi, placement) {
+        let needed_width = placed_width / 72.0 * dpi;
+        let needed_height = placed_height / 72.0 * dpi;
+        factor = factor.min((needed_width / width as f32).max(needed_height / height as f32));
+    }
+    if let Some(max_pixels) = options.max_pixels {
+        factor = factor.min(max_pixels as f32 / width.max(height) as f32);
+    }
+    (scale_dimension(width, factor), scale_dimension(height, factor))
+}
+
+fn color_components(doc: &Document, stream: &Stream) -> Option<usize> {
+    let color_space = stream.dict.get(b"ColorSpace").ok()?;
+    let name = match color_space {
+        Object::Name(name) => name.as_slice(),
+        Object::Array(array) => array.first()?.as_name().ok()?,
+        _ => return None,
+    };
+    match name {
+        b"DeviceGray" | b"CalGray" => Some(1),
+        b"DeviceRGB" | b"CalRGB" | b"Lab" => Some(3),
+        b"DeviceCMYK" => Some(4),
+        // The number of components is stored in the ICC profile stream, which may be indirect.
+        b"ICCBased" => {
+            let profile = doc.dereference(color_space.as_array().ok()?.get(1)?).ok()?.1;
+            let n = profile.as_stream().ok()?.dict.get(b"N").and_then(Object::as_i64).ok()?;
+            usize::try_from(n).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Decode an image with 8 bits per component into its raw samples.
+fn decode_pixels(stream: &Stream, components: usize) -> Option<Vec<u8>> {
+    if stream.dict.get(b"BitsPerComponent").and_then(Object::as_i64).ok() != Some(8) {
+        return None;
+    }
+    let (width, height) = image_dimensions(&stream.dict)?;
+    let pixels = if stream.is_compressed() {
+        stream.decompressed_content().ok()?
+    } else {
+        stream.content.clone()
+    };
+    let expected = width as usize * height as usize * components;
+    (pixels.len() >= expected).then(|| pixels[..expected].to_vec())
+}
+
+fn encode_flate(original: &Stream, pixels: Vec<u8>, (width, height): (u32, u32)) -> Stream {
+    let mut dict = original.dict.clone();
+    dict.remove(b"Filter");
+    dict.remove(b"DecodeParms");
+    dict.set("Width", width);
+    dict.set("Height", height);
+    let mut stream = Stream::new(dict, pixels).with_compression(original.allows_compression);
+    // Ignore any compression error, the stream is then just larger.
+    let _ = stream.compress();
+    stream
+}
+
+/// Produce a smaller replacement for an image, or `None` if it can't be improved.
+fn optimize_image(
+    doc: &Document, stream: &Stream, dimensions: (u32, u32), target: (u32, u32), options: &ImageOptimizeOptions,
+) -> Result<Option<Stream>> {
+    let filters = stream.filters().unwrap_or_default();
+    let optimized = if filters.contains(&b"DCTDecode".as_slice()) {
+        reencode_jpeg(stream, target, options)?
+    } else if is_bilevel(doc, stream) {
+        recompress_bilevel(stream)
+    } else if let Some(components) = color_components(doc, stream) {
+        match decode_pixels(stream, components) {
+            Some(pixels) => Some(encode_flate(
+                stream,
+                resample(&pixels, dimensions, target, components),
+                target,
+            )),
+            None => recompress(stream),
+        }
+    } else {
+        recompress(stream)
+    };
+    Ok(optimized.filter(|optimized| optimized.content.len() < stream.content.len()))
+}
+
+/// Whether an image has a single component of 1 bit, like image masks and black and white scans.
+fn is_bilevel(doc: &Document, stream: &Stream) -> bool {
+    if stream.dict.get(b"ImageMask").and_then(Object::as_bool).ok() == Some(true) {
+        return true;
+    }
+    if stream.dict.get(b"BitsPerComponent").and_then(Object::as_i64).ok() != Some(1) {
+        return false;
+    }
+    let indexed = stream
+        .dict
+        .get(b"ColorSpace")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|array| array.first()?.as_name().ok())
+        == Some(b"Indexed".as_slice());
+    indexed || color_components(doc, stream) == Some(1)
+}
+
+/// Recompress a 1-bit image with `CCITTFaxDecode` or `FlateDecode`, whichever is smaller.
+fn recompress_bilevel(stream: &Stream) -> Option<Stream> {
+    let flate = recompress(stream)?;
+    let bits = flate.decompressed_content().ok()?;
+    let ccitt = image_dimensions(&stream.dict).and_then(|dimensions| encode_ccitt(stream, &bits, dimensions));
+    match ccitt {
+        Some(ccitt) if ccitt.content.len() < flate.content.len() => Some(ccitt),
+        _ => Some(flate),
+    }
+}
+
+/// Encode rows of 1-bit samples with `CCITTFaxDecode` Group 4, where a 0 bit is black.
+fn encode_ccitt(original: &Stream, bits: &[u8], (width, height): (u32, u32)) -> Option<Stream> {
+    use fax::{encoder::Encoder, Color, VecWriter};
+
+    let columns = u16::try_from(width).ok()?;
+    let row_length = (width as usize).div_ceil(8);
+    if bits.len() < row_length * height as usize {
+        return None;
+    }
+    let mut encoder = Encoder::new(VecWriter::new());
+    for row in bits.chunks_exact(row_length).take(height as usize) {
+        let pels = (0..width as usize).map(|x| match row[x / 8] & (0x80 >> (x % 8)) {
+            0 => Color::Black,
+            _ => Color::White,
+        });
+        encoder.encode_line(pels, columns).ok()?;
+    }
+    let content = encoder.finish().ok()?.finish();
+
+    let mut dict = original.dict.clone();
+    dict.set("Filter", Object::Name(b"CCITTFaxDecode".to_vec()));
+    dict.set(
+        "DecodeParms",
+        dictionary! {
+            "K" => -1,
+            "Columns" => width,
+            "Rows" => height,
+        },
+    );
+    Some(Stream::new(dict, content).with_compression(false))
+}
+
+/// Recompress an image that can't be resampled (e.g. 1-bit masks or indexed images) with `FlateDecode`.
+fn recompress(stream: &Stream) -> Option<Stream> {
+    let pixels = if stream.is_compressed() {
+        stream.decompressed_content().ok()?
+    } else {
+        stream.content.clone()
+    };
+    let dimensions = image_dimensions(&stream.dict)?;
+    Some(encode_flate(stream, pixels, dimensions))
+}
+
+#[cfg(not(feature = "embed_image"))]
+fn reencode_jpeg(_stream: &Stream, _target: (u32, u32), _options: &ImageOptimizeOptions) -> Result<Option<Stream>> {
+    Ok(None)
+}
+
+#[cfg(feature = "embed_image")]
+fn reencode_jpeg(
+    stream: &Stream, (width, height): (u32, u32), options: &ImageOptimizeOptions,
+) -> Result<Option<Stream>> {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::imageops::FilterType;
+
+    let quality = match options.jpeg_quality {
+        Some(quality) if stream.filters()?.len() == 1 => quality,
+        _ => return Ok(None),
+    };
+
+    let image = image::load_from_memory_with_format(&stream.content, image::ImageFormat::Jpeg)?;
+    let image = if (width, height) != (image.width(), image.height()) {
+        image.resize_exact(width, height, FilterType::Triangle)
+    } else {
+        image
+    };
+    let mut content = vec![];
+    JpegEncoder::new_with_quality(&mut content, quality.clamp(1, 100)).encode_image(&image)?;
+
+    let mut dict = stream.dict.clone();
+    dict.remove(b"DecodeParms");
+    dict.set("Width", width);
+    dict.set("Height", height);
+    Ok(Some(Stream::new(dict, content).with_compression(false)))
+}
+
+/// Resample interleaved 8-bit samples with a box filter.
+fn resample(
+    pixels: &[u8], (width, height): (u32, u32), (new_width, new_height): (u32, u32), components: usize,
+) -> Vec<u8> {
+    if (width, height) == (new_width, new_height) {
+        return pixels.to_vec();
+    }
+    let (width, height) = (width as usize, height as usize);
+    let (new_width, new_height) = (new_width as usize, new_height as usize);
+    let span = |index: usize, size: usize, new_size: usize| {
+        let start = index * size / new_size;
+        let end = ((index + 1) * size / new_size).max(start + 1);
+        start..end
+    };
+
+    let mut output = Vec::with_capacity(new_width * new_height * components);
+    for y in 0..new_height {
+        let rows = span(y, height, new_height);
+        for x in 0..new_width {
+            let columns = span(x, width, new_width);
+            let count = (rows.len() * columns.len()) as u32;
+            for component in 0..components {
+                let mut sum = 0u32;
+                for row in rows.clone() {
+                    for column in columns.clone() {
+                        sum += u32::from(pixels[(row * width + column) * components + component]);
+                    }
+                }
+                output.push(((sum + count / 2) / count) as u8);
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+
+    fn image_stream(width: u32, height: u32, color_space: &str, components: usize) -> Stream {
+        let mut dict = Dictionary::new();
+        dict.set("Type", Object::Name(b"XObject".to_vec()));
+        dict.set("Subtype", Object::Name(b"Image".to_vec()));
+        dict.set("Width", width);
+        dict.set("Height", height);
+        dict.set("ColorSpace", Object::Name(color_space.as_bytes().to_vec()));
+        dict.set("BitsPerComponent", 8);
+        let pixels = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                (0..components).map(move |c| ((x * 7 + y * 3 + c as u32 * 50) % 256) as u8)
+            })
+            .collect();
+        let mut stream = Stream::new(dict, pixels);
+        stream.compress().unwrap();
+        stream
+    }
+
+    #[test]
+    fn optimize_oversized_image() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let mask_id = doc.add_object(image_stream(400, 400, "DeviceGray", 1));
+        let mut image = image_stream(400, 400, "DeviceRGB", 3);
+        image.dict.set("SMask", mask_id);
+        // Painted at one inch square.
+        doc.insert_image(page_id, image, (100.0, 100.0), (72.0, 72.0)).unwrap();
+        let content_before = doc.get_page_content(page_id).unwrap();
+        let image_id = *doc.page_xobjects(page_id).values().next().unwrap();
+        let size_before = doc.get_object(image_id).unwrap().as_stream().unwrap().content.len();
+
+        let options = ImageOptimizeOptions {
+            max_dpi: Some(100.0),
+            ..Default::default()
+        };
+        let report = doc.optimize_images(options).unwrap();
+        assert_eq!(report.images.len(), 2);
+        assert!(report.saved_bytes() > 0);
+
+        let image = doc.get_object(image_id).unwrap().as_stream().unwrap();
+        assert!(image.content.len() < size_before);
+        assert_eq!(image_dimensions(&image.dict), Some((100, 100)));
+        assert_eq!(image.decompressed_content().unwrap().len(), 100 * 100 * 3);
+        assert_eq!(image.dict.get(b"SMask").unwrap().as_reference().unwrap(), mask_id);
+        let mask = doc.get_object(mask_id).unwrap().as_stream().unwrap();
+        assert_eq!(image_dimensions(&mask.dict), Some((100, 100)));
+
+        // Content and resources still reference the same objects.
+        assert_eq!(doc.get_page_content(page_id).unwrap(), content_before);
+        assert!(doc.page_xobjects(page_id).values().any(|&id| id == image_id));
+    }
+
+    #[test]
+    fn unplaced_image_only_capped_by_pixels() {
+        let mut doc = create_document();
+        let image_id = doc.add_object(image_stream(300, 150, "DeviceRGB", 3));
+
+        let options = ImageOptimizeOptions {
+            max_dpi: Some(10.0),
+            ..Default::default()
+        };
+        let report = doc.optimize_images(options.clone()).unwrap();
+        assert!(report.images.is_empty());
+
+        doc.optimize_images(ImageOptimizeOptions {
+            max_pixels: Some(100),
+            ..options
+        })
+        .unwrap();
+        let image = doc.get_object(image_id).unwrap().as_stream().unwrap();
+        assert_eq!(image_dimensions(&image.dict), Some((100, 50)));
+    }
+
+    fn bilevel_stream(width: u32, height: u32, black: impl Fn(u32, u32) -> bool) -> Stream {
+        let row_length = (width as usize).div_ceil(8);
+        let mut bits = vec![0xFF; row_length * height as usize];
+        for (y, row) in bits.chunks_exact_mut(row_length).enumerate() {
+            for x in 0..width {
+                if black(x, y as u32) {
+                    row[x as usize / 8] &= !(0x80 >> (x % 8));
+                }
+            }
+        }
+        let dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => width,
+            "Height" => height,
+            "ImageMask" => true,
+        };
+        Stream::new(dict, bits)
+    }
+
+    #[test]
+    fn bilevel_image_encoded_with_ccitt() {
+        let mut doc = create_document();
+        let disc = |x: u32, y: u32| (x as i32 - 100).pow(2) + (y as i32 - 100).pow(2) < 80 * 80;
+        let image = bilevel_stream(203, 200, disc);
+        let bits = image.content.clone();
+        let image_id = doc.add_object(image);
+
+        let report = doc.optimize_images(ImageOptimizeOptions::default()).unwrap();
+        assert_eq!(report.images.len(), 1);
+        let image = doc.get_object(image_id).unwrap().as_stream().unwrap();
+        assert_eq!(image.filters().unwrap(), vec![b"CCITTFaxDecode".as_slice()]);
+        assert!(image.content.len() < bits.len());
+
+        // Each decoded line lists the positions where the color changes, starting with white.
+        let mut decoded = vec![];
+        fax::decoder::decode_g4(image.content.iter().copied(), 203, Some(200), |transitions| {
+            let mut row = vec![0xFFu8; 26];
+            for pair in transitions.chunks(2) {
+                let end = pair.get(1).copied().unwrap_or(203);
+                for x in pair[0]..end {
+                    row[x as usize / 8] &= !(0x80 >> (x % 8));
+                }
+            }
+            decoded.extend(row);
+        })
+        .unwrap();
+        assert_eq!(decoded, bits);
+    }
+
+    #[test]
+    fn bilevel_image_encoded_with_flate_when_smaller() {
+        let mut doc = create_document();
+        // Alternating columns are a transition at every pixel for CCITT, but repeat for Flate.
+        let image_id = doc.add_object(bilevel_stream(200, 200, |x, _| x % 2 == 0));
+
+        doc.optimize_images(ImageOptimizeOptions::default()).unwrap();
+        let image = doc.get_object(image_id).unwrap().as_stream().unwrap();
+        assert_eq!(image.filters().unwrap(), vec![b"FlateDecode".as_slice()]);
+        assert_eq!(image.decompressed_content().unwrap(), vec![0x55; 25 * 200]);
+    }
+
+    #[test]
+    fn resample_averages_blocks() {
+        let pixels = [0, 10, 20, 30, 40, 50, 60, 70];
+        assert_eq!(resample(&pixels, (4, 2), (2, 1), 1), vec![25, 45]);
+    }
+}
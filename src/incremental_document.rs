@@ -49,7 +49,8 @@ pub fn get_prev_documents_bytes(&self) -> &[u8] {
     /// If the object already exists nothing is done.
     ///
     /// This function can be used to clone an object so it can be changed in the incremental updates.
-    pub fn opt_clone_object_to_new_document(&mut self, object_id: ObjectId) -> Result<()> {
+    pub fn opt_clone_object_to_new_document(&mut self, object_id: impl Into<ObjectId>) -> Result<()> {
+        let object_id = object_id.into();
         if !self.new_document.has_object(object_id) {
             let old_object = self.prev_documents.get_object(object_id)?;
             self.new_document.set_object(object_id, old_object.clone());
@@ -60,7 +61,8 @@ pub fn opt_clone_object_to_new_document(&mut self, object_id: ObjectId) -> Resul
     /// Get the page's resource dictionary (only in new document).
     ///
     /// Get Object that has the key `Resources`.
-    pub fn get_or_create_resources(&mut self, page_id: ObjectId) -> Result<&mut Object> {
+    pub fn get_or_create_resources(&mut self, page_id: impl Into<ObjectId>) -> Result<&mut Object> {
+        let page_id = page_id.into();
         self.opt_clone_object_to_new_document(page_id)?;
         let resources_id = {
             let page = self.new_document.get_object(page_id).and_then(Object::as_dict)?;
@@ -88,8 +90,10 @@ pub fn get_or_create_resources(&mut self, page_id: ObjectId) -> Result<&mut Obje
     ///
     /// Get Object that has the key `Resources -> XObject`.
     pub fn add_xobject<N: Into<Vec<u8>>>(
-        &mut self, page_id: ObjectId, xobject_name: N, xobject_id: ObjectId,
+        &mut self, page_id: impl Into<ObjectId>, xobject_name: N, xobject_id: impl Into<ObjectId>,
     ) -> Result<()> {
+        let page_id = page_id.into();
+        let xobject_id = xobject_id.into();
         if let Ok(resources) = self.get_or_create_resources(page_id).and_then(Object::as_dict_mut) {
             if !resources.has(b"XObject") {
                 resources.set("XObject", Dictionary::new());
@@ -112,8 +116,10 @@ pub fn add_xobject<N: Into<Vec<u8>>>(
     ///
     /// Get Object that has the key `Resources -> ExtGState`.
     pub fn add_graphics_state<N: Into<Vec<u8>>>(
-        &mut self, page_id: ObjectId, gs_name: N, gs_id: ObjectId,
+        &mut self, page_id: impl Into<ObjectId>, gs_name: N, gs_id: impl Into<ObjectId>,
     ) -> Result<()> {
+        let page_id = page_id.into();
+        let gs_id = gs_id.into();
         if let Ok(resources) = self.get_or_create_resources(page_id).and_then(Object::as_dict_mut) {
             if !resources.has(b"ExtGState") {
                 resources.set("ExtGState", Dictionary::new());
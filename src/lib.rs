@@ -2,7 +2,12 @@
 #![forbid(unsafe_code)]
 #![deny(clippy::all)]
 
+#[cfg(all(feature = "pom_parser", not(feature = "nom_parser")))]
+compile_error!("the `pom_parser` feature was removed, lopdf only has a parser built on nom: enable the `nom_parser` feature instead");
+
 pub mod content;
+pub mod diff;
+#[cfg(feature = "encryption")]
 pub mod encryption;
 pub mod filters;
 pub mod xobject;
@@ -13,18 +18,48 @@
 mod document;
 mod incremental_document;
 
+mod annotations;
 mod bookmarks;
+mod cancel;
+mod certification;
 mod cmap_section;
 mod common_data_structures;
+mod content_cache;
 mod creator;
 mod datetime;
 mod destinations;
 mod encodings;
 mod error;
+mod extensions;
+mod extracted_page;
+mod file_spec;
+mod font_metrics;
+mod fonts;
+mod image_optimize;
+mod linearization;
+mod logging;
+mod maybe_ref;
+mod memory;
+mod name_tree;
+mod optional_content;
 mod outlines;
+mod output_intents;
+mod overlay;
+mod page;
+mod piece_info;
 mod processor;
+#[cfg(feature = "encryption")]
 mod rc4;
+mod repair;
+mod resource_names;
+mod rich_media;
+mod statistics;
+mod text_encoding;
+mod text_strings;
+mod threads;
+mod thumbnail;
 mod toc;
+mod viewer_preferences;
 mod writer;
 
 mod object_stream;
@@ -32,17 +67,50 @@
 mod parser_aux;
 mod reader;
 
-pub use document::Document;
-pub use object::{Dictionary, Object, ObjectId, Stream, StringFormat};
+pub use document::{Document, FileSpans, LoadReport};
+pub use object::{Dictionary, DuplicateKeyPolicy, Object, ObjectId, Stream, StreamEol, StringFormat, TryFromObject};
 
-pub use bookmarks::Bookmark;
-pub use common_data_structures::{decode_text_string, text_string};
+pub use bookmarks::{Bookmark, BookmarkIssue, BookmarkTarget, DestinationFit, OutlineFlags};
+pub use cancel::CancelToken;
+pub use certification::{CertificationInfo, CertifiedChange, DocMdpPermission};
+pub use common_data_structures::{decode_text_string, text_string, text_string_v2, TextStringEncoding};
 pub use destinations::Destination;
-pub use encodings::{encode_utf16_be, encode_utf8, Encoding};
-pub use error::{Error, Result};
+pub use encodings::{
+    encode_utf16_be, encode_utf8, CodedCharacterSet, Encoding, EXPERT_ENCODING, MAC_EXPERT_ENCODING,
+    MAC_ROMAN_ENCODING, PDF_DOC_ENCODING, STANDARD_ENCODING, SYMBOL_ENCODING, WIN_ANSI_ENCODING,
+};
+pub use error::{Error, ErrorCategory, Result};
+pub use extensions::DeveloperExtension;
+#[cfg(feature = "nom_parser")]
+pub use extracted_page::{ExtractedPage, PageExtractionOptions, PageGeometry, PageLink};
+pub use file_spec::{FileSpec, StreamData};
+pub use font_metrics::{FontMetrics, FontWidths, WidthsIssue};
+pub use fonts::{FontEncodingInfo, FontInfo};
+#[cfg(feature = "nom_parser")]
+pub use image_optimize::{ImageOptimizeOptions, OptimizeReport, OptimizedImage};
 pub use incremental_document::IncrementalDocument;
-pub use object_stream::ObjectStream;
+pub use maybe_ref::{FromObject, MaybeRef};
+pub use memory::MemoryUsage;
+pub use name_tree::{NameTree, NameTreeIter, TreeKey};
+pub use object_stream::{ObjectStream, ObjectStreamIndex};
+pub use optional_content::LayerVisibility;
 pub use outlines::Outline;
+#[cfg(feature = "srgb_profile")]
+pub use output_intents::SRGB_ICC_PROFILE;
+pub use output_intents::{OutputIntent, OutputIntentSubtype};
+pub use overlay::OverlayMode;
+pub use page::{PageView, Rotation};
+#[cfg(feature = "nom_parser")]
+pub use parser_aux::{ExtractionOptions, TextChunk, TextReplacement, TextRun};
 #[cfg(feature = "nom_parser")]
-pub use reader::Reader;
+pub use reader::{ParseOptions, Reader};
+pub use repair::RepairSummary;
+pub use resource_names::{ConflictPolicy, RenameMap, ResourceNamer};
+pub use rich_media::RichMediaRef;
+pub use statistics::DocumentStats;
+pub use text_encoding::{EncodingTarget, FontNormalization, NormalizationReport};
+pub use text_strings::{StringKind, TextStringContext};
+pub use threads::{ArticleBead, ArticleThread};
 pub use toc::Toc;
+pub use viewer_preferences::{Duplex, PageLayout, PageMode, ViewerPreferences};
+pub use writer::{ObjectOrder, SaveOptions, StreamingWriter, UpdatePolicy, XrefMode};
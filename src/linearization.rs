@@ -0,0 +1,463 @@
+use crate::writer::{CountingWrite, Writer};
+use crate::xref::{Xref, XrefEntry, XrefType};
+use crate::{Dictionary, Document, Error, Object, ObjectId, Result, SaveOptions};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+/// Width of the numbers which are only known once the layout of the file is complete.
+/// They are written padded with spaces, and replaced in place afterwards.
+const NUMBER_WIDTH: usize = 10;
+
+/// Objects of a linearized file, in the order they are written.
+struct Layout {
+    /// Page object of the first page, followed by the objects it uses.
+    first_page: Vec<ObjectId>,
+    /// Page object of each other page, followed by the objects used by that page only.
+    pages: Vec<Vec<ObjectId>>,
+    /// Objects used by several pages other than the first one.
+    shared: Vec<ObjectId>,
+    /// Everything else, e.g. the page tree nodes, outlines and document information.
+    other: Vec<ObjectId>,
+}
+
+/// Position of a page in the output file, for the page offset hint table.
+struct PageHint {
+    objects: u32,
+    offset: u32,
+    length: u32,
+}
+
+impl Document {
+    /// Save the document linearized ("fast web view"), so that viewers can display the first page
+    /// before the whole file is downloaded.
+    ///
+    /// The file starts with the linearization parameter dictionary, followed by the cross-reference
+    /// section of the first page, the document catalog, the primary hint stream and the objects of the
+    /// first page. The other pages follow with the objects they use on their own, then the objects
+    /// shared between pages, then everything else. Objects are renumbered in that order and
+    /// cross-reference tables are used. The hint stream holds a complete page offset hint table,
+    /// but an empty shared object hint table.
+    pub fn save_linearized<W: Write>(&self, target: &mut W) -> Result<()> {
+        let catalog_id = self.trailer.get(b"Root")?.as_reference()?;
+        let layout = self.linearization_layout(catalog_id)?;
+
+        // Objects outside the first page section are numbered from 1, those of the first page section
+        // follow: the linearization dictionary, the catalog, the first page and the hint stream.
+        let main_ids: Vec<ObjectId> = layout
+            .pages
+            .iter()
+            .flatten()
+            .chain(&layout.shared)
+            .chain(&layout.other)
+            .copied()
+            .collect();
+        let main_count = main_ids.len() as u32 + 1;
+        let linearization_id = main_count;
+        let first_page_ids: Vec<ObjectId> = std::iter::once(catalog_id)
+            .chain(layout.first_page.iter().copied())
+            .collect();
+        let hint_id = linearization_id + 1 + first_page_ids.len() as u32;
+        let mut renumbering = HashMap::new();
+        for (id, new_id) in main_ids.iter().zip(1..) {
+            renumbering.insert(*id, ObjectId(new_id, 0));
+        }
+        for (id, new_id) in first_page_ids.iter().zip(linearization_id + 1..) {
+            renumbering.insert(*id, ObjectId(new_id, 0));
+        }
+        let renumbered = |id: &ObjectId| {
+            let mut object = self.objects[id].clone();
+            renumber_references(&mut object, &renumbering);
+            object
+        };
+
+        let mut buffer = Vec::new();
+        let mut file = CountingWrite {
+            inner: &mut buffer,
+            bytes_written: 0,
+        };
+        let mut xref = Xref::new(hint_id + 1, XrefType::CrossReferenceTable);
+        let options = SaveOptions::default();
+        writeln!(file, "%PDF-{}", self.version)?;
+        file.write_all(b"%\xE2\xE3\xCF\xD3\n")?;
+
+        let linearization_offset = file.bytes_written;
+        xref.insert(
+            linearization_id,
+            XrefEntry::Normal {
+                offset: linearization_offset as u64,
+                generation: 0,
+            },
+        );
+        let pages = layout.pages.len() + 1;
+        let first_page_number = linearization_id + 2;
+        let placeholder = linearization_dict(linearization_id, first_page_number, pages, [0; 5]);
+        file.write_all(placeholder.as_bytes())?;
+
+        // Cross-reference section of the first page, with its entries and the offset of the main
+        // cross-reference section filled in at the end.
+        let first_xref_offset = file.bytes_written;
+        writeln!(file, "xref\n{} {}", linearization_id, hint_id + 1 - linearization_id)?;
+        let first_entries_offset = file.bytes_written;
+        for _ in linearization_id..=hint_id {
+            XrefEntry::Free { generation: 0 }.write_xref_entry(&mut file)?;
+        }
+        let mut trailer = Dictionary::new();
+        for (key, value) in self.trailer.iter() {
+            if !matches!(
+                key.as_slice(),
+                b"Prev" | b"XRefStm" | b"Size" | b"Type" | b"W" | b"Index" | b"Length" | b"Filter" | b"DecodeParms"
+            ) {
+                let mut value = value.clone();
+                renumber_references(&mut value, &renumbering);
+                trailer.set(key.clone(), value);
+            }
+        }
+        trailer.set("Size", i64::from(hint_id + 1));
+        let mut trailer_bytes = Vec::new();
+        Writer::write_dictionary(&mut trailer_bytes, &trailer, &options)?;
+        file.write_all(b"trailer\n<</Prev ")?;
+        let prev_offset = file.bytes_written;
+        write!(file, "{:<NUMBER_WIDTH$}", 0)?;
+        file.write_all(&trailer_bytes[2..])?;
+        file.write_all(b"\nstartxref\n0\n%%EOF\n")?;
+
+        let catalog_number = renumbering[&catalog_id].0;
+        Writer::write_indirect_object(
+            &mut file,
+            catalog_number,
+            0,
+            &renumbered(&catalog_id),
+            &mut xref,
+            &options,
+        )?;
+
+        // The hint stream has a fixed size, so it can be written before the offsets it holds are known.
+        let page_hints: Vec<PageHint> = (0..pages)
+            .map(|_| PageHint {
+                objects: 0,
+                offset: 0,
+                length: 0,
+            })
+            .collect();
+        let (hint_data, shared_hints_offset) = hint_stream_data(&page_hints);
+        let hint_offset = file.bytes_written;
+        xref.insert(
+            hint_id,
+            XrefEntry::Normal {
+                offset: hint_offset as u64,
+                generation: 0,
+            },
+        );
+        write!(
+            file,
+            "{hint_id} 0 obj\n<</Length {}/S {shared_hints_offset}>>stream\n",
+            hint_data.len()
+        )?;
+        let hint_data_offset = file.bytes_written;
+        file.write_all(&hint_data)?;
+        file.write_all(b"\nendstream\nendobj\n")?;
+        let hint_length = file.bytes_written - hint_offset;
+
+        let mut page_hints = Vec::with_capacity(pages);
+        let first_page_offset = file.bytes_written;
+        for id in &layout.first_page {
+            Writer::write_indirect_object(&mut file, renumbering[id].0, 0, &renumbered(id), &mut xref, &options)?;
+        }
+        let first_page_end = file.bytes_written;
+        page_hints.push(PageHint {
+            objects: layout.first_page.len() as u32,
+            offset: first_page_offset as u32,
+            length: (first_page_end - first_page_offset) as u32,
+        });
+
+        for page in &layout.pages {
+            let offset = file.bytes_written;
+            for id in page {
+                Writer::write_indirect_object(&mut file, renumbering[id].0, 0, &renumbered(id), &mut xref, &options)?;
+            }
+            page_hints.push(PageHint {
+                objects: page.len() as u32,
+                offset: offset as u32,
+                length: (file.bytes_written - offset) as u32,
+            });
+        }
+        for id in layout.shared.iter().chain(&layout.other) {
+            Writer::write_indirect_object(&mut file, renumbering[id].0, 0, &renumbered(id), &mut xref, &options)?;
+        }
+
+        // Main cross-reference section.
+        let main_xref_offset = file.bytes_written;
+        writeln!(file, "xref\n0 {main_count}")?;
+        let main_entries_offset = file.bytes_written;
+        XrefEntry::UnusableFree.write_xref_entry(&mut file)?;
+        for id in 1..main_count {
+            xref.get(id)
+                .ok_or(Error::MissingXrefEntry)?
+                .write_xref_entry(&mut file)?;
+        }
+        write!(
+            file,
+            "trailer\n<</Size {main_count}>>\nstartxref\n{first_xref_offset}\n%%EOF"
+        )?;
+        let file_length = file.bytes_written;
+
+        // Fill in what is known now that the layout is complete.
+        let values = [
+            file_length,
+            hint_offset,
+            hint_length,
+            first_page_end,
+            main_entries_offset - 1,
+        ];
+        let dict = linearization_dict(linearization_id, first_page_number, pages, values);
+        buffer[linearization_offset..linearization_offset + dict.len()].copy_from_slice(dict.as_bytes());
+        let prev = format!("{main_xref_offset:<NUMBER_WIDTH$}");
+        buffer[prev_offset..prev_offset + NUMBER_WIDTH].copy_from_slice(prev.as_bytes());
+        let mut entries = Vec::new();
+        for id in linearization_id..=hint_id {
+            xref.get(id)
+                .ok_or(Error::MissingXrefEntry)?
+                .write_xref_entry(&mut entries)?;
+        }
+        buffer[first_entries_offset..first_entries_offset + entries.len()].copy_from_slice(&entries);
+        // Offsets in hint tables are given as if the hint stream wasn't there.
+        for hint in &mut page_hints {
+            if hint.offset as usize > hint_offset {
+                hint.offset -= hint_length as u32;
+            }
+        }
+        let (hint_data, _) = hint_stream_data(&page_hints);
+        buffer[hint_data_offset..hint_data_offset + hint_data.len()].copy_from_slice(&hint_data);
+
+        target.write_all(&buffer)?;
+        Ok(())
+    }
+
+    /// Assign the objects to the sections of a linearized file.
+    fn linearization_layout(&self, catalog_id: ObjectId) -> Result<Layout> {
+        let page_ids: Vec<ObjectId> = self.page_iter().collect();
+        let (&first_page_id, other_page_ids) = page_ids.split_first().ok_or(Error::PageNumberNotFound(1))?;
+        let skipped: HashSet<ObjectId> = self
+            .objects
+            .iter()
+            .filter(|(_, object)| matches!(object.type_name(), Ok(b"ObjStm" | b"XRef" | b"Linearized")))
+            .map(|(id, _)| *id)
+            .chain([catalog_id])
+            .collect();
+
+        let first_page = self.objects_of_page(first_page_id, &skipped);
+        let mut assigned: HashSet<ObjectId> = skipped.iter().chain(&first_page).copied().collect();
+        let page_objects: Vec<Vec<ObjectId>> = other_page_ids
+            .iter()
+            .map(|&page_id| {
+                let mut objects = self.objects_of_page(page_id, &assigned);
+                // A page listed twice in the page tree is only written once.
+                objects.retain(|id| !assigned.contains(id));
+                assigned.insert(page_id);
+                objects
+            })
+            .collect();
+        let mut uses: HashMap<ObjectId, usize> = HashMap::new();
+        for id in page_objects.iter().flat_map(|objects| objects.iter().skip(1)) {
+            *uses.entry(*id).or_default() += 1;
+        }
+
+        let mut shared = vec![];
+        let mut pages = vec![];
+        for objects in page_objects {
+            let mut page = vec![];
+            for (index, id) in objects.into_iter().enumerate() {
+                if !assigned.insert(id) && index > 0 {
+                    continue;
+                }
+                if index > 0 && uses[&id] > 1 {
+                    shared.push(id);
+                } else {
+                    page.push(id);
+                }
+            }
+            pages.push(page);
+        }
+        let other = self
+            .objects
+            .keys()
+            .filter(|id| !assigned.contains(id))
+            .copied()
+            .collect();
+
+        Ok(Layout {
+            first_page,
+            pages,
+            shared,
+            other,
+        })
+    }
+
+    /// Collect a page object and the objects it uses, in depth-first order.
+    ///
+    /// References to the parent, to other pages and to the excluded objects are not followed.
+    fn objects_of_page(&self, page_id: ObjectId, excluded: &HashSet<ObjectId>) -> Vec<ObjectId> {
+        // Popped from the end, so the references are visited in the order they appear.
+        let push_references = |object: &Object, pending: &mut Vec<ObjectId>| {
+            let start = pending.len();
+            object.push_references_except(b"Parent", pending);
+            pending[start..].reverse();
+        };
+
+        let mut objects = vec![page_id];
+        let mut seen = HashSet::from([page_id]);
+        let mut pending = vec![];
+        if let Some(page) = self.objects.get(&page_id) {
+            push_references(page, &mut pending);
+        }
+        while let Some(id) = pending.pop() {
+            if excluded.contains(&id) || !seen.insert(id) {
+                continue;
+            }
+            let Some(object) = self.objects.get(&id) else {
+                continue;
+            };
+            if matches!(object.type_name(), Ok(b"Page" | b"Pages" | b"Catalog")) {
+                continue;
+            }
+            objects.push(id);
+            push_references(object, &mut pending);
+        }
+        objects
+    }
+}
+
+/// Text of the linearization parameter dictionary object.
+///
+/// `values` are the file length `/L`, the offset and length of the hint stream `/H`, the end of the
+/// first page `/E` and the offset of the first entry of the main cross-reference table `/T`.
+fn linearization_dict(id: u32, first_page_id: u32, pages: usize, values: [usize; 5]) -> String {
+    let [file_length, hint_offset, hint_length, first_page_end, main_xref] = values;
+    format!(
+        "{id} 0 obj\n<</Linearized 1/L {file_length:<NUMBER_WIDTH$}/H [{hint_offset:<NUMBER_WIDTH$} \
+         {hint_length:<NUMBER_WIDTH$}]/O {first_page_id}/E {first_page_end:<NUMBER_WIDTH$}/N {pages}\
+         /T {main_xref:<NUMBER_WIDTH$}>>\nendobj\n"
+    )
+}
+
+/// Data of the primary hint stream, and the offset of the shared object hint table in it.
+///
+/// All varying values are written with 32 bits, so the size of the data only depends on the number of pages.
+/// Every page is given a single content stream spanning the whole page and no shared objects.
+fn hint_stream_data(pages: &[PageHint]) -> (Vec<u8>, usize) {
+    let least_objects = pages.iter().map(|page| page.objects).min().unwrap_or(0);
+    let least_length = pages.iter().map(|page| page.length).min().unwrap_or(0);
+    let first_page_offset = pages.first().map_or(0, |page| page.offset);
+
+    // Page offset hint table header.
+    let mut data = Vec::new();
+    data.extend(least_objects.to_be_bytes());
+    data.extend(first_page_offset.to_be_bytes());
+    data.extend(32u16.to_be_bytes());
+    data.extend(least_length.to_be_bytes());
+    data.extend(32u16.to_be_bytes());
+    // Offset of the content stream in the page, 0 for all pages.
+    data.extend(0u32.to_be_bytes());
+    data.extend(0u16.to_be_bytes());
+    // Length of the content stream.
+    data.extend(least_length.to_be_bytes());
+    data.extend(32u16.to_be_bytes());
+    // Shared object references, identifiers, and the numerator and denominator of their positions.
+    for _ in 0..4 {
+        data.extend(0u16.to_be_bytes());
+    }
+
+    // Per-page entries, each item given for all pages in turn.
+    data.extend(
+        pages
+            .iter()
+            .flat_map(|page| (page.objects - least_objects).to_be_bytes()),
+    );
+    data.extend(pages.iter().flat_map(|page| (page.length - least_length).to_be_bytes()));
+    data.extend(pages.iter().flat_map(|page| (page.length - least_length).to_be_bytes()));
+
+    // Shared object hint table without any shared object group.
+    let shared_hints_offset = data.len();
+    data.extend([0u32; 4].iter().flat_map(|value| value.to_be_bytes()));
+    data.extend(0u16.to_be_bytes());
+    data.extend(0u32.to_be_bytes());
+    data.extend(0u16.to_be_bytes());
+    (data, shared_hints_offset)
+}
+
+/// Replace the references in `object` using `renumbering`, references to missing objects become null.
+fn renumber_references(object: &mut Object, renumbering: &HashMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => match renumbering.get(id) {
+            Some(new_id) => *id = *new_id,
+            None => *object = Object::Null,
+        },
+        Object::Array(array) => array.iter_mut().for_each(|item| renumber_references(item, renumbering)),
+        Object::Dictionary(dict) => dict
+            .iter_mut()
+            .for_each(|(_, value)| renumber_references(value, renumbering)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter_mut()
+            .for_each(|(_, value)| renumber_references(value, renumbering)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+
+    #[test]
+    fn save_linearized_document() {
+        let texts = ["first", "second", "third"];
+        let doc = create_document_with_texts(&texts);
+        let mut buffer = Vec::new();
+        doc.save_linearized(&mut buffer).unwrap();
+
+        let linearized = Document::load_mem(&buffer).unwrap();
+        let pages: Vec<ObjectId> = linearized.page_iter().collect();
+        assert_eq!(pages.len(), 3);
+        for (number, text) in (1..).zip(texts) {
+            assert_eq!(linearized.extract_text(&[number]).unwrap(), format!("{text}\n"));
+        }
+
+        // The linearization dictionary is the first object of the file.
+        let offset = |id: ObjectId| match linearized.reference_table.get(id.0) {
+            Some(XrefEntry::Normal { offset, .. }) => *offset as usize,
+            entry => panic!("unexpected entry {entry:?} of {id:?}"),
+        };
+        let header_end = buffer.iter().position(|&byte| byte == b'\n').unwrap() + 1;
+        let header_end = header_end + buffer[header_end..].iter().position(|&byte| byte == b'\n').unwrap() + 1;
+        let (&linearization_id, dict) = linearized
+            .objects
+            .iter()
+            .find(|(_, object)| object.type_name().ok() == Some(b"Linearized"))
+            .unwrap();
+        assert_eq!(offset(linearization_id), header_end);
+        let dict = dict.as_dict().unwrap();
+        let value = |key: &[u8]| dict.get(key).and_then(Object::as_i64).unwrap() as usize;
+
+        assert_eq!(value(b"L"), buffer.len());
+        assert_eq!(value(b"N"), 3);
+        assert_eq!(value(b"O") as u32, pages[0].0);
+        assert!(buffer[value(b"T") + 1..].starts_with(b"0000000000 65535 f"));
+        let hint = dict.get(b"H").and_then(Object::as_array).unwrap();
+        let hint_offset = hint[0].as_i64().unwrap() as usize;
+        let hint_length = hint[1].as_i64().unwrap() as usize;
+        let hint_object = &buffer[hint_offset..hint_offset + hint_length];
+        assert!(hint_object.ends_with(b"endobj\n"));
+        assert!(std::str::from_utf8(&hint_object[..20]).unwrap().contains(" 0 obj"));
+
+        // The first page comes before the end of the first page section, the others follow in order.
+        let catalog_id = linearized.trailer.get(b"Root").and_then(Object::as_reference).unwrap();
+        assert!(offset(catalog_id) < hint_offset);
+        assert!(hint_offset < offset(pages[0]));
+        assert!(offset(pages[0]) < value(b"E"));
+        assert!(value(b"E") <= offset(pages[1]));
+        assert!(offset(pages[1]) < offset(pages[2]));
+        // The first page section is numbered after the other objects.
+        assert!(pages[0].0 > linearization_id.0 && pages[1].0 < linearization_id.0);
+    }
+}
@@ -0,0 +1,260 @@
+//! Internal logging helpers, so that call sites don't depend on how phases of work are reported.
+//!
+//! With the `tracing` feature, phases are `tracing` spans and worked around problems are `tracing`
+//! events carrying their fields. Otherwise spans cost nothing and problems are logged with `log`.
+
+/// A phase of work, such as reading the cross-reference table, entered until it is dropped.
+///
+/// Create one with the [`span!`] macro and keep it alive until the phase is done.
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    _entered: tracing::span::EnteredSpan,
+}
+
+impl Span {
+    #[cfg(feature = "tracing")]
+    pub(crate) fn enter(span: tracing::Span) -> Span {
+        Span {
+            _entered: span.entered(),
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn enter() -> Span {
+        Span {}
+    }
+}
+
+/// The phase of work running on the current thread, to continue it in work done on other threads,
+/// e.g. by parallel iterators.
+#[derive(Clone)]
+pub(crate) struct Scope {
+    #[cfg(feature = "tracing")]
+    dispatch: tracing::Dispatch,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl Scope {
+    pub(crate) fn current() -> Scope {
+        Scope {
+            #[cfg(feature = "tracing")]
+            dispatch: tracing::dispatcher::get_default(Clone::clone),
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::current(),
+        }
+    }
+
+    /// Run `f` inside the phase, reporting to the subscriber of the thread the scope was taken on.
+    pub(crate) fn in_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "tracing")]
+        return tracing::dispatcher::with_default(&self.dispatch, || self.span.in_scope(f));
+        #[cfg(not(feature = "tracing"))]
+        f()
+    }
+}
+
+/// Enter a [`Span`], e.g. `span!(DEBUG, "xref", offset = xref_start)`. Fields are recorded with their
+/// `Debug` formatting.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($level:ident, $name:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        $crate::logging::Span::enter(::tracing::span!(::tracing::Level::$level, $name $(, $key = ?$value)*))
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($level:ident, $name:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        $crate::logging::Span::enter()
+    };
+}
+pub(crate) use span;
+
+/// Report a problem that was worked around, e.g.
+/// `recovered!(id = object_id, bytes = skipped.len(); "Skipped {} bytes", skipped.len())`.
+///
+/// The fields locate the problem in `tracing` events, `log` only gets the message.
+#[cfg(feature = "tracing")]
+macro_rules! recovered {
+    ($($key:ident = $value:expr),* ; $($message:tt)+) => {
+        ::tracing::warn!($($key = ?$value,)* $($message)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! recovered {
+    ($($key:ident = $value:expr),* ; $($message:tt)+) => {
+        ::log::warn!($($message)+)
+    };
+}
+pub(crate) use recovered;
+
+#[cfg(all(test, feature = "tracing"))]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, ThreadId};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+    use tracing_core::span::Current;
+
+    /// A span or event reported to a [`Collector`].
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) struct Collected {
+        pub(crate) name: &'static str,
+        /// The name of the span it happened in.
+        pub(crate) parent: Option<&'static str>,
+        /// The names of the fields with a value.
+        pub(crate) fields: Vec<&'static str>,
+    }
+
+    #[derive(Default)]
+    struct State {
+        spans_by_id: HashMap<u64, &'static Metadata<'static>>,
+        /// The spans entered on each thread.
+        entered: HashMap<ThreadId, Vec<u64>>,
+        spans: Vec<Collected>,
+        events: Vec<Collected>,
+    }
+
+    /// A subscriber collecting spans and events, in the order they were created.
+    #[derive(Clone, Default)]
+    pub(crate) struct Collector {
+        next_id: Arc<AtomicU64>,
+        state: Arc<Mutex<State>>,
+    }
+
+    struct FieldNames(Vec<&'static str>);
+
+    impl Visit for FieldNames {
+        fn record_debug(&mut self, field: &Field, _value: &dyn std::fmt::Debug) {
+            self.0.push(field.name());
+        }
+    }
+
+    impl Collector {
+        pub(crate) fn spans(&self) -> Vec<Collected> {
+            self.state.lock().unwrap().spans.clone()
+        }
+
+        pub(crate) fn events(&self) -> Vec<Collected> {
+            self.state.lock().unwrap().events.clone()
+        }
+
+        fn collect(
+            state: &State, name: &'static str, parent: Option<&Id>, contextual: bool,
+            record: impl FnOnce(&mut FieldNames),
+        ) -> Collected {
+            let current = || state.entered.get(&thread::current().id())?.last().copied();
+            let parent = match parent {
+                Some(parent) => Some(parent.into_u64()),
+                None if contextual => current(),
+                None => None,
+            };
+            let mut fields = FieldNames(vec![]);
+            record(&mut fields);
+            Collected {
+                name,
+                parent: parent.and_then(|parent| Some(state.spans_by_id.get(&parent)?.name())),
+                fields: fields.0,
+            }
+        }
+    }
+
+    impl Subscriber for Collector {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+            let mut state = self.state.lock().unwrap();
+            let metadata = span.metadata();
+            let collected = Self::collect(&state, metadata.name(), span.parent(), span.is_contextual(), |fields| {
+                span.record(fields)
+            });
+            state.spans_by_id.insert(id, metadata);
+            state.spans.push(collected);
+            Id::from_u64(id)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut state = self.state.lock().unwrap();
+            let collected = Self::collect(
+                &state,
+                event.metadata().name(),
+                event.parent(),
+                event.is_contextual(),
+                |fields| event.record(fields),
+            );
+            state.events.push(collected);
+        }
+
+        fn enter(&self, span: &Id) {
+            let mut state = self.state.lock().unwrap();
+            state
+                .entered
+                .entry(thread::current().id())
+                .or_default()
+                .push(span.into_u64());
+        }
+
+        fn exit(&self, _span: &Id) {
+            let mut state = self.state.lock().unwrap();
+            state.entered.entry(thread::current().id()).or_default().pop();
+        }
+
+        fn current_span(&self) -> Current {
+            let state = self.state.lock().unwrap();
+            let current = state
+                .entered
+                .get(&thread::current().id())
+                .and_then(|entered| entered.last());
+            match current {
+                Some(&id) => Current::new(Id::from_u64(id), state.spans_by_id[&id]),
+                None => Current::none(),
+            }
+        }
+    }
+
+    #[test]
+    fn spans_nest() {
+        let collector = Collector::default();
+        tracing::subscriber::with_default(collector.clone(), || {
+            let outer = span!(DEBUG, "outer", bytes = 10);
+            let scope = super::Scope::current();
+            let inner = move || scope.in_scope(|| drop(span!(TRACE, "inner", id = (1, 0))));
+            thread::spawn(inner).join().unwrap();
+            drop(outer);
+            let _next = span!(DEBUG, "next");
+            recovered!(offset = 5; "worked around");
+        });
+
+        let collected = |name, parent, fields: &[_]| Collected {
+            name,
+            parent,
+            fields: fields.to_vec(),
+        };
+        assert_eq!(
+            collector.spans(),
+            [
+                collected("outer", None, &["bytes"]),
+                collected("inner", Some("outer"), &["id"]),
+                collected("next", None, &[]),
+            ]
+        );
+        let events = collector.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            (events[0].parent, &events[0].fields[..]),
+            (Some("next"), &["message", "offset"][..])
+        );
+    }
+}
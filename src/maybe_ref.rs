@@ -0,0 +1,168 @@
+use crate::{Dictionary, Document, Object, ObjectId, Result, Stream};
+
+/// A value which may be given directly, or as a reference to an indirect object.
+///
+/// Many entries, like `/Resources`, `/Annots` or `/DecodeParms`, may take either form. Getting them
+/// with [`Dictionary::get_maybe_dict`], [`Dictionary::get_maybe_array`] or [`Dictionary::get_maybe_stream`]
+/// and then calling [`MaybeRef::resolve`] handles both.
+#[derive(Debug)]
+pub enum MaybeRef<'a, T: ?Sized> {
+    Direct(&'a T),
+    Indirect(ObjectId),
+}
+
+impl<T: ?Sized> Clone for MaybeRef<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for MaybeRef<'_, T> {}
+
+/// Types of objects which can be held by a [`MaybeRef`].
+pub trait FromObject {
+    fn from_object(object: &Object) -> Result<&Self>;
+}
+
+impl FromObject for Dictionary {
+    fn from_object(object: &Object) -> Result<&Self> {
+        object.as_dict()
+    }
+}
+
+impl FromObject for [Object] {
+    fn from_object(object: &Object) -> Result<&Self> {
+        object.as_array().map(Vec::as_slice)
+    }
+}
+
+impl FromObject for Stream {
+    fn from_object(object: &Object) -> Result<&Self> {
+        object.as_stream()
+    }
+}
+
+impl<'a, T: FromObject + ?Sized> MaybeRef<'a, T> {
+    /// Wrap an object, which must be a reference or a value of type `T`.
+    pub fn new(object: &'a Object) -> Result<Self> {
+        match object {
+            Object::Reference(id) => Ok(MaybeRef::Indirect(*id)),
+            object => T::from_object(object).map(MaybeRef::Direct),
+        }
+    }
+
+    /// Get the value, looking up the referenced object in `doc` if it is indirect.
+    pub fn resolve<'b>(&self, doc: &'b Document) -> Result<&'b T>
+    where
+        'a: 'b,
+    {
+        match *self {
+            MaybeRef::Direct(value) => Ok(value),
+            MaybeRef::Indirect(id) => T::from_object(doc.get_object(id)?),
+        }
+    }
+
+    /// The ID of the referenced object, `None` if the value is direct.
+    pub fn id(&self) -> Option<ObjectId> {
+        match *self {
+            MaybeRef::Direct(_) => None,
+            MaybeRef::Indirect(id) => Some(id),
+        }
+    }
+}
+
+impl Dictionary {
+    /// Get a dictionary value which may be direct or indirect, see [`MaybeRef`].
+    pub fn get_maybe_dict(&self, key: &[u8]) -> Result<MaybeRef<'_, Dictionary>> {
+        self.get(key).and_then(MaybeRef::new)
+    }
+
+    /// Get an array value which may be direct or indirect, see [`MaybeRef`].
+    pub fn get_maybe_array(&self, key: &[u8]) -> Result<MaybeRef<'_, [Object]>> {
+        self.get(key).and_then(MaybeRef::new)
+    }
+
+    /// Get a stream value, which is normally indirect, see [`MaybeRef`].
+    pub fn get_maybe_stream(&self, key: &[u8]) -> Result<MaybeRef<'_, Stream>> {
+        self.get(key).and_then(MaybeRef::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+
+    /// Give the first page of a document its resources and annotations, indirectly or not.
+    fn document_with_page_entries(indirect: bool) -> (Document, ObjectId) {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+        });
+        let text = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Rect" => vec![20.into(), 20.into(), 30.into(), 30.into()],
+        };
+        let fonts = dictionary! { "F2" => font_id };
+        let resources = dictionary! { "Font" => fonts };
+        let annots: Vec<Object> = vec![link_id.into(), text.into()];
+        let (resources, annots): (Object, Object) = if indirect {
+            (doc.add_object(resources).into(), doc.add_object(annots).into())
+        } else {
+            (resources.into(), annots.into())
+        };
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("Resources", resources);
+        page.set("Annots", annots);
+        (doc, page_id)
+    }
+
+    #[test]
+    fn direct_and_indirect_page_entries() {
+        for indirect in [false, true] {
+            let (doc, page_id) = document_with_page_entries(indirect);
+            let page = doc.get_dictionary(page_id).unwrap();
+            assert_eq!(page.get_maybe_dict(b"Resources").unwrap().id().is_some(), indirect);
+
+            let fonts = doc.get_page_fonts(page_id).unwrap();
+            let base_font = fonts[b"F2".as_slice()].get(b"BaseFont").unwrap();
+            assert_eq!(base_font.as_name().unwrap(), b"Helvetica");
+
+            let annotations = doc.get_page_annotations(page_id).unwrap();
+            let subtypes: Vec<_> = annotations
+                .iter()
+                .map(|annotation| annotation.get(b"Subtype").unwrap().as_name().unwrap())
+                .collect();
+            assert_eq!(subtypes, [b"Link".as_slice(), b"Text"]);
+
+            let mut doc = doc;
+            let annots = doc.get_dictionary(page_id).unwrap().get_maybe_array(b"Annots").unwrap();
+            let link_id = annots.resolve(&doc).unwrap()[0].as_reference().unwrap();
+            doc.remove_object(link_id).unwrap();
+            assert_eq!(doc.get_page_annotations(page_id).unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn resolve_checks_the_type() {
+        let (doc, page_id) = document_with_page_entries(true);
+        let page = doc.get_dictionary(page_id).unwrap();
+        let annots = page.get_maybe_array(b"Annots").unwrap();
+        assert_eq!(annots.resolve(&doc).unwrap().len(), 2);
+        let as_dict = MaybeRef::<Dictionary>::Indirect(annots.id().unwrap());
+        assert!(as_dict.resolve(&doc).is_err());
+        assert!(page
+            .get_maybe_stream(b"Resources")
+            .is_ok_and(|it| it.resolve(&doc).is_err()));
+        assert!(page.get_maybe_stream(b"Type").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{Dictionary, Document, Object, ObjectId};
+use std::mem::size_of;
+use std::ops::Range;
+
+/// Approximate heap memory held by a document, by component, see [`Document::approximate_memory_usage`].
+///
+/// All figures are in bytes and count the capacity of the collections, so they include memory
+/// reserved but not used yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The entries of the map of objects, `Document::objects`.
+    pub object_map: usize,
+    /// Names, strings, arrays and dictionaries of the objects, stream dictionaries included.
+    pub object_data: usize,
+    /// The data of the streams.
+    pub stream_bytes: usize,
+    /// The entries of the cross-reference table.
+    pub reference_table: usize,
+    /// The decompressed page content kept by [`Document::enable_content_cache`].
+    pub caches: usize,
+    /// The bookmarks added with [`Document::add_bookmark`].
+    pub bookmarks: usize,
+    /// The trailer, the load report, the registered encodings and the tracking of modified objects.
+    pub other: usize,
+}
+
+impl MemoryUsage {
+    /// The sum of all components.
+    pub fn total(&self) -> usize {
+        self.object_map
+            + self.object_data
+            + self.stream_bytes
+            + self.reference_table
+            + self.caches
+            + self.bookmarks
+            + self.other
+    }
+}
+
+impl Document {
+    /// Estimate the heap memory held by the document, e.g. to decide which documents a service
+    /// keeps open.
+    ///
+    /// The objects are walked once without decompressing anything, so the cost is linear in their
+    /// size. Map entries are counted at the size of their key and value, without the overhead of
+    /// the allocator and of the nodes of the maps, which makes the figures lower bounds.
+    pub fn approximate_memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage {
+            object_map: self.objects.len() * size_of::<(ObjectId, Object)>(),
+            reference_table: self.reference_table.entries.len() * size_of::<(u32, crate::xref::XrefEntry)>(),
+            caches: self.content_cache.memory_usage(),
+            ..MemoryUsage::default()
+        };
+
+        (usage.object_data, usage.stream_bytes) = objects_size(self.objects.values());
+
+        usage.bookmarks = self.bookmarks.capacity() * size_of::<u32>()
+            + self.bookmark_table.capacity() * (size_of::<(u32, crate::Bookmark)>() + 1)
+            + self
+                .bookmark_table
+                .values()
+                .map(|bookmark| bookmark.title.capacity() + bookmark.children.capacity() * size_of::<u32>())
+                .sum::<usize>();
+
+        let load_report = &self.load_report;
+        usage.other = self.version.capacity()
+            + dictionary_size(&self.trailer)
+            + objects_size(self.trailer.iter().map(|(_, value)| value)).0
+            + (load_report.duplicate_pages.capacity()
+                + load_report.unreadable_objects.capacity()
+                + load_report.invalid_stream_extents.capacity()
+                + load_report.coerced_lengths.capacity()
+                + load_report.invalid_object_stream_chains.capacity())
+                * size_of::<ObjectId>()
+            + load_report
+                .unreadable_catalog
+                .as_ref()
+                .map_or(0, |(_, cause)| cause.capacity())
+            + self.custom_encodings.capacity() * (size_of::<(Vec<u8>, crate::encodings::CodedCharacterSet)>() + 1)
+            + self.file_spans.objects.len() * size_of::<(ObjectId, Range<usize>)>()
+            + self.file_spans.compressed.len() * size_of::<(ObjectId, (ObjectId, u16))>()
+            + (self.file_spans.xref_sections.capacity() + self.file_spans.trailers.capacity())
+                * size_of::<Range<usize>>()
+            + self.dirty.memory_usage();
+        usage
+    }
+}
+
+/// Heap memory held by objects and the objects within them, and separately the data of the streams.
+/// The objects are walked without recursion, so deeply nested arrays can't overflow the stack.
+fn objects_size<'a>(objects: impl IntoIterator<Item = &'a Object>) -> (usize, usize) {
+    let (mut data, mut stream_bytes) = (0, 0);
+    let mut pending: Vec<&Object> = objects.into_iter().collect();
+    while let Some(object) = pending.pop() {
+        match object {
+            Object::Name(bytes) | Object::String(bytes, _) | Object::RawToken(bytes) => data += bytes.capacity(),
+            Object::Array(array) => {
+                data += array.capacity() * size_of::<Object>();
+                pending.extend(array);
+            }
+            Object::Dictionary(dict) => {
+                data += dictionary_size(dict);
+                pending.extend(dict.iter().map(|(_, value)| value));
+            }
+            Object::Stream(stream) => {
+                data += dictionary_size(&stream.dict);
+                stream_bytes += stream.content.capacity();
+                pending.extend(stream.dict.iter().map(|(_, value)| value));
+            }
+            Object::Null | Object::Boolean(_) | Object::Integer(_) | Object::Real(_) | Object::Reference(_) => {}
+        }
+    }
+    (data, stream_bytes)
+}
+
+/// Heap memory of the entries of a dictionary, without that of their values: a key, a value and a
+/// hash for each entry, and the index of the entry in the hash table.
+fn dictionary_size(dict: &Dictionary) -> usize {
+    dict.capacity() * (size_of::<(u64, Vec<u8>, Object)>() + size_of::<usize>() + 1)
+        + dict.iter().map(|(key, _)| key.capacity()).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::creator::tests::create_document_with_texts;
+    use crate::{Object, Stream};
+
+    #[test]
+    fn memory_usage_of_streams() {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        for i in 0..20 {
+            doc.add_object(Stream::new(
+                dictionary! { "Index" => i as i64 },
+                vec![b'x'; 1_000 * (i + 1)],
+            ));
+        }
+        let stream_lengths: usize = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_stream().ok())
+            .map(|stream| stream.content.len())
+            .sum();
+
+        let usage = doc.approximate_memory_usage();
+        assert!(usage.stream_bytes >= stream_lengths);
+        assert!(usage.stream_bytes <= stream_lengths + stream_lengths / 20);
+        assert!(usage.object_map > 0 && usage.object_data > 0);
+        assert_eq!(
+            usage.total(),
+            usage.object_map
+                + usage.object_data
+                + usage.stream_bytes
+                + usage.reference_table
+                + usage.caches
+                + usage.bookmarks
+                + usage.other
+        );
+
+        assert_eq!(usage.caches, 0);
+        doc.enable_content_cache(1 << 20);
+        let page_id = doc.page_iter().next().unwrap();
+        doc.get_page_content(page_id).unwrap();
+        let cached = doc.approximate_memory_usage();
+        assert!(cached.caches > 0);
+        assert_eq!(cached.stream_bytes, usage.stream_bytes);
+
+        doc.add_object(Object::string_literal("x".repeat(10_000)));
+        assert!(doc.approximate_memory_usage().object_data >= cached.object_data + 10_000);
+    }
+}
@@ -0,0 +1,420 @@
+use crate::{Dictionary, Document, Error, Object, ObjectId, Result};
+use log::warn;
+use std::collections::{BTreeMap, HashSet};
+
+/// Maximum depth of the nodes of a tree which are followed.
+const MAX_TREE_DEPTH: usize = 64;
+
+/// A key of a name tree, a string, or of a number tree, an integer.
+///
+/// Keys of name trees sort by their bytes, which is the order the PDF specification requires.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TreeKey {
+    Number(i64),
+    Name(Vec<u8>),
+}
+
+impl TreeKey {
+    fn from_object(object: &Object) -> Option<TreeKey> {
+        match object {
+            Object::String(name, _) => Some(TreeKey::Name(name.clone())),
+            Object::Integer(number) => Some(TreeKey::Number(*number)),
+            _ => None,
+        }
+    }
+
+    fn to_object(&self) -> Object {
+        match self {
+            TreeKey::Number(number) => Object::Integer(*number),
+            TreeKey::Name(name) => Object::string_literal(name.clone()),
+        }
+    }
+}
+
+impl From<i64> for TreeKey {
+    fn from(number: i64) -> Self {
+        TreeKey::Number(number)
+    }
+}
+
+impl From<&[u8]> for TreeKey {
+    fn from(name: &[u8]) -> Self {
+        TreeKey::Name(name.to_vec())
+    }
+}
+
+impl From<&str> for TreeKey {
+    fn from(name: &str) -> Self {
+        TreeKey::Name(name.as_bytes().to_vec())
+    }
+}
+
+/// A name tree, e.g. `/Dests` or `/EmbeddedFiles` of the `/Names` dictionary, or a number tree,
+/// e.g. `/PageLabels`, read from a document.
+///
+/// Nodes which are referenced twice, nested deeper than 64 levels, or aren't dictionaries are skipped,
+/// as well as keys of the wrong type.
+#[derive(Debug, Clone, Copy)]
+pub struct NameTree<'a> {
+    doc: &'a Document,
+    root: &'a Dictionary,
+    /// Whether the leaves use `/Nums` rather than `/Names`.
+    numbers: bool,
+}
+
+impl<'a> NameTree<'a> {
+    /// Read the tree whose root node is `root`, a dictionary or a reference to one.
+    pub fn load(doc: &'a Document, root: &'a Object) -> Result<NameTree<'a>> {
+        let (_, root) = doc.dereference(root)?;
+        Ok(NameTree::from_dict(doc, root.as_dict()?))
+    }
+
+    /// Read the tree whose root node is `root`.
+    pub fn from_dict(doc: &'a Document, root: &'a Dictionary) -> NameTree<'a> {
+        let mut tree = NameTree {
+            doc,
+            root,
+            numbers: false,
+        };
+        // The first leaf tells the kind of the tree.
+        tree.numbers = tree
+            .nodes()
+            .find(|node| node.has(b"Names") || node.has(b"Nums"))
+            .is_some_and(|leaf| leaf.has(b"Nums"));
+        tree
+    }
+
+    /// Whether this is a number tree, with integer keys.
+    pub fn is_number_tree(&self) -> bool {
+        self.numbers
+    }
+
+    /// Iterate over the entries in key order, reading the nodes as they are reached.
+    ///
+    /// Values are returned as stored in the tree, they may be references.
+    pub fn iter(&self) -> NameTreeIter<'a> {
+        NameTreeIter {
+            tree: *self,
+            nodes: vec![(self.root, 0)],
+            entries: [].iter(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Look up the value of `key`.
+    ///
+    /// Only the nodes whose `/Limits` include the key are searched. Nodes with missing or malformed
+    /// `/Limits` are always searched.
+    pub fn get(&self, key: impl Into<TreeKey>) -> Option<&'a Object> {
+        let key = key.into();
+        let mut seen = HashSet::new();
+        self.find(self.root, &key, 0, &mut seen)
+    }
+
+    fn find(
+        &self, node: &'a Dictionary, key: &TreeKey, depth: usize, seen: &mut HashSet<ObjectId>,
+    ) -> Option<&'a Object> {
+        if let Some(entries) = self.leaf_entries(node) {
+            let found = entries
+                .chunks_exact(2)
+                .find(|pair| TreeKey::from_object(&pair[0]).as_ref() == Some(key));
+            if let Some(pair) = found {
+                return Some(&pair[1]);
+            }
+        }
+        if depth >= MAX_TREE_DEPTH {
+            warn!("Skipping the nodes of a name tree nested too deep");
+            return None;
+        }
+        self.kids(node, seen)
+            .into_iter()
+            .filter(|kid| match limits(kid) {
+                Some((low, high)) => low <= *key && *key <= high,
+                None => true,
+            })
+            .find_map(|kid| self.find(kid, key, depth + 1, seen))
+    }
+
+    /// The nodes of the tree, depth first.
+    fn nodes(&self) -> impl Iterator<Item = &'a Dictionary> {
+        let tree = *self;
+        let mut seen = HashSet::new();
+        let mut stack = vec![(self.root, 0)];
+        std::iter::from_fn(move || {
+            let (node, depth) = stack.pop()?;
+            if depth < MAX_TREE_DEPTH {
+                let kids = tree.kids(node, &mut seen);
+                stack.extend(kids.into_iter().rev().map(|kid| (kid, depth + 1)));
+            }
+            Some(node)
+        })
+    }
+
+    /// The key and value pairs of a leaf node, `None` for an intermediate node.
+    fn leaf_entries(&self, node: &'a Dictionary) -> Option<&'a [Object]> {
+        let key: &[u8] = if self.numbers { b"Nums" } else { b"Names" };
+        let entries = node.get_deref(key, self.doc).and_then(Object::as_array).ok()?;
+        if entries.len() % 2 != 0 {
+            warn!("Ignoring the unpaired last key of a name tree node");
+        }
+        Some(entries)
+    }
+
+    /// The kids of an intermediate node which are dictionaries and haven't been seen yet.
+    fn kids(&self, node: &'a Dictionary, seen: &mut HashSet<ObjectId>) -> Vec<&'a Dictionary> {
+        let Ok(kids) = node.get_deref(b"Kids", self.doc).and_then(Object::as_array) else {
+            return vec![];
+        };
+        kids.iter()
+            .filter_map(|kid| {
+                if let Ok(id) = kid.as_reference() {
+                    if !seen.insert(id) {
+                        warn!("Skipping name tree node {id:?}, which is referenced twice");
+                        return None;
+                    }
+                }
+                self.doc.dereference(kid).and_then(|(_, kid)| kid.as_dict()).ok()
+            })
+            .collect()
+    }
+}
+
+/// The `/Limits` of a node, if they are two keys in order.
+fn limits(node: &Dictionary) -> Option<(TreeKey, TreeKey)> {
+    match node.get(b"Limits").and_then(Object::as_array).ok()?.as_slice() {
+        [low, high] => {
+            let (low, high) = (TreeKey::from_object(low)?, TreeKey::from_object(high)?);
+            (low <= high).then_some((low, high))
+        }
+        _ => None,
+    }
+}
+
+/// Iterator over the entries of a [`NameTree`], see [`NameTree::iter`].
+pub struct NameTreeIter<'a> {
+    tree: NameTree<'a>,
+    /// The nodes left to read, with their depth, the next one last.
+    nodes: Vec<(&'a Dictionary, usize)>,
+    entries: std::slice::Iter<'a, Object>,
+    seen: HashSet<ObjectId>,
+}
+
+impl<'a> Iterator for NameTreeIter<'a> {
+    type Item = (TreeKey, &'a Object);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let (Some(key), Some(value)) = (self.entries.next(), self.entries.next()) {
+                match TreeKey::from_object(key) {
+                    Some(key) => return Some((key, value)),
+                    None => {
+                        warn!("Skipping a name tree entry with a key of type {}", key.enum_variant());
+                        continue;
+                    }
+                }
+            }
+            let (node, depth) = self.nodes.pop()?;
+            self.entries = self.tree.leaf_entries(node).unwrap_or_default().iter();
+            if depth < MAX_TREE_DEPTH {
+                let kids = self.tree.kids(node, &mut self.seen);
+                self.nodes.extend(kids.into_iter().rev().map(|kid| (kid, depth + 1)));
+            } else {
+                warn!("Skipping the nodes of a name tree nested too deep");
+            }
+        }
+    }
+}
+
+impl Document {
+    /// Write `entries` as a balanced name tree, or number tree if the keys are numbers, and return
+    /// the ID of its root node.
+    ///
+    /// Every node but the root has at most `node_size` kids or entries. The nodes of a level differ
+    /// in size by at most one, so with a `node_size` of 3 or more they all have at least 2.
+    /// Keys must all be names or all be numbers.
+    pub fn add_name_tree(&mut self, entries: &BTreeMap<TreeKey, Object>, node_size: usize) -> Result<ObjectId> {
+        let node_size = node_size.max(2);
+        let numbers = matches!(entries.keys().next(), Some(TreeKey::Number(_)));
+        if let Some(key) = entries.keys().find(|key| matches!(key, TreeKey::Number(_)) != numbers) {
+            return Err(Error::ObjectType {
+                expected: if numbers { "Integer" } else { "String" },
+                found: key.to_object().enum_variant(),
+            });
+        }
+        let leaf_key = if numbers { "Nums" } else { "Names" };
+
+        let entries: Vec<(&TreeKey, &Object)> = entries.iter().collect();
+        if entries.len() <= node_size {
+            let pairs = entries
+                .iter()
+                .flat_map(|(key, value)| [key.to_object(), (*value).clone()]);
+            return Ok(self.add_object(dictionary! { leaf_key => pairs.collect::<Vec<_>>() }));
+        }
+
+        // Each level of nodes with the first and last keys below them.
+        let mut level: Vec<(ObjectId, &TreeKey, &TreeKey)> = even_chunks(&entries, node_size)
+            .map(|chunk| {
+                let pairs = chunk
+                    .iter()
+                    .flat_map(|(key, value)| [key.to_object(), (*value).clone()]);
+                let (first, last) = (chunk[0].0, chunk[chunk.len() - 1].0);
+                let id = self.add_object(dictionary! {
+                    leaf_key => pairs.collect::<Vec<_>>(),
+                    "Limits" => vec![first.to_object(), last.to_object()],
+                });
+                (id, first, last)
+            })
+            .collect();
+        while level.len() > node_size {
+            level = even_chunks(&level, node_size)
+                .map(|chunk| {
+                    let (first, last) = (chunk[0].1, chunk[chunk.len() - 1].2);
+                    let id = self.add_object(dictionary! {
+                        "Kids" => chunk.iter().map(|(id, _, _)| Object::Reference(*id)).collect::<Vec<_>>(),
+                        "Limits" => vec![first.to_object(), last.to_object()],
+                    });
+                    (id, first, last)
+                })
+                .collect();
+        }
+        let kids: Vec<Object> = level.iter().map(|(id, _, _)| Object::Reference(*id)).collect();
+        Ok(self.add_object(dictionary! { "Kids" => kids }))
+    }
+}
+
+/// Split `items` into the fewest consecutive chunks of at most `size` items, whose lengths differ by
+/// at most one.
+fn even_chunks<T>(items: &[T], size: usize) -> impl Iterator<Item = &[T]> {
+    let count = items.len().div_ceil(size);
+    let (length, longer) = (items.len() / count.max(1), items.len() % count.max(1));
+    let mut rest = items;
+    (0..count).map(move |index| {
+        let (chunk, tail) = rest.split_at(length + usize::from(index < longer));
+        rest = tail;
+        chunk
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_tree_round_trip() {
+        let mut doc = Document::with_version("1.7");
+        let entries: BTreeMap<TreeKey, Object> = (0..1000)
+            .map(|i| (TreeKey::from(format!("name{i:04}").as_str()), Object::Integer(i)))
+            .collect();
+        let root = doc.add_name_tree(&entries, 10).unwrap();
+        let catalog = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Names" => dictionary! { "Dests" => root },
+        });
+        doc.trailer.set("Root", catalog);
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+
+        let doc = Document::load_mem(&buffer).unwrap();
+        let names = doc.get_dict_in_dict(doc.catalog().unwrap(), b"Names").unwrap();
+        let tree = NameTree::load(&doc, names.get(b"Dests").unwrap()).unwrap();
+        assert!(!tree.is_number_tree());
+        let read: Vec<(TreeKey, Object)> = tree.iter().map(|(key, value)| (key, value.clone())).collect();
+        assert_eq!(read, entries.into_iter().collect::<Vec<_>>());
+        assert_eq!(tree.get("name0000").unwrap().as_i64().unwrap(), 0);
+        assert_eq!(tree.get("name0457").unwrap().as_i64().unwrap(), 457);
+        assert_eq!(tree.get("name0999").unwrap().as_i64().unwrap(), 999);
+        assert!(tree.get("name1000").is_none());
+        assert!(tree.get(457).is_none());
+
+        // 1000 entries in leaves of 10, under nodes of 10 kids: the root has 10 kids.
+        let root = doc.get_dictionary(root).unwrap();
+        assert_eq!(root.get(b"Kids").unwrap().as_array().unwrap().len(), 10);
+        assert!(!root.has(b"Limits"));
+    }
+
+    #[test]
+    fn nodes_of_a_level_have_even_sizes() {
+        // The number of kids or entries of each node below the root, level by level.
+        fn node_sizes(doc: &Document, root: ObjectId) -> Vec<Vec<usize>> {
+            let mut levels = vec![];
+            let mut level = vec![root];
+            while !level.is_empty() {
+                let mut sizes = vec![];
+                let mut next = vec![];
+                for id in level {
+                    let node = doc.get_dictionary(id).unwrap();
+                    match node.get(b"Kids") {
+                        Ok(kids) => {
+                            let kids = kids.as_array().unwrap();
+                            next.extend(kids.iter().map(|kid| kid.as_reference().unwrap()));
+                            sizes.push(kids.len());
+                        }
+                        Err(_) => sizes.push(node.get(b"Names").unwrap().as_array().unwrap().len() / 2),
+                    }
+                }
+                levels.push(sizes);
+                level = next;
+            }
+            levels.remove(0);
+            levels
+        }
+
+        let mut doc = Document::with_version("1.7");
+        let tree = |count: i64| -> BTreeMap<TreeKey, Object> {
+            (0..count)
+                .map(|i| (TreeKey::from(format!("name{i:04}").as_str()), Object::Integer(i)))
+                .collect()
+        };
+        let root = doc.add_name_tree(&tree(6), 5).unwrap();
+        assert_eq!(node_sizes(&doc, root), [vec![3, 3]]);
+        // 17 entries need 5 leaves of at most 4, which need 2 intermediate nodes.
+        let root = doc.add_name_tree(&tree(17), 4).unwrap();
+        assert_eq!(node_sizes(&doc, root), [vec![3, 2], vec![4, 4, 3, 3, 3]]);
+    }
+
+    #[test]
+    fn number_tree_with_malformed_limits() {
+        let mut doc = Document::with_version("1.7");
+        let entries: BTreeMap<TreeKey, Object> = [0, 3, 10, 20, 21]
+            .into_iter()
+            .map(|page| (TreeKey::Number(page), dictionary! { "St" => page + 1 }.into()))
+            .collect();
+        let root = doc.add_name_tree(&entries, 2).unwrap();
+        let kids = doc
+            .get_dictionary(root)
+            .unwrap()
+            .get(b"Kids")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .clone();
+        // Limits of the wrong type, too short and reversed don't hide the keys below them.
+        let malformed = [
+            vec![Object::Name(b"a".to_vec()), Object::Name(b"z".to_vec())],
+            vec![Object::Integer(0)],
+            vec![Object::Integer(30), Object::Integer(10)],
+        ];
+        for (kid, limits) in kids.iter().zip(malformed) {
+            doc.get_dictionary_mut(kid.as_reference().unwrap())
+                .unwrap()
+                .set("Limits", limits);
+        }
+        // A kid referenced twice is read once.
+        let mut kids = kids;
+        kids.push(kids[0].clone());
+        doc.get_dictionary_mut(root).unwrap().set("Kids", kids);
+
+        let root = Object::Reference(root);
+        let tree = NameTree::load(&doc, &root).unwrap();
+        assert!(tree.is_number_tree());
+        for page in [0, 3, 10, 20, 21] {
+            let label = tree.get(page).unwrap().as_dict().unwrap();
+            assert_eq!(label.get(b"St").unwrap().as_i64().unwrap(), page + 1);
+        }
+        let keys: Vec<TreeKey> = tree.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, entries.keys().cloned().collect::<Vec<_>>());
+
+        let mixed: BTreeMap<TreeKey, Object> = [(TreeKey::Number(1), Object::Null), ("a".into(), Object::Null)].into();
+        assert!(doc.add_name_tree(&mixed, 10).is_err());
+    }
+}
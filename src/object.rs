@@ -10,9 +10,97 @@
 use std::str;
 
 /// Object identifier consists of two parts: object number and generation number.
-pub type ObjectId = (u32, u16);
+///
+/// It converts from and into a `(u32, u16)` tuple, and the numbers are also available as the fields `.0` and `.1`.
+/// Methods taking an object ID accept anything converting into one, so tuples can still be passed to them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId(pub u32, pub u16);
+
+impl ObjectId {
+    pub const fn new(num: u32, gen: u16) -> ObjectId {
+        ObjectId(num, gen)
+    }
+
+    /// The object number.
+    pub const fn num(&self) -> u32 {
+        self.0
+    }
+
+    /// The generation number.
+    pub const fn gen(&self) -> u16 {
+        self.1
+    }
+}
+
+impl From<(u32, u16)> for ObjectId {
+    fn from((num, gen): (u32, u16)) -> Self {
+        ObjectId(num, gen)
+    }
+}
+
+impl From<&(u32, u16)> for ObjectId {
+    fn from(&(num, gen): &(u32, u16)) -> Self {
+        ObjectId(num, gen)
+    }
+}
+
+impl From<&ObjectId> for ObjectId {
+    fn from(id: &ObjectId) -> Self {
+        *id
+    }
+}
+
+impl From<ObjectId> for (u32, u16) {
+    fn from(id: ObjectId) -> Self {
+        (id.0, id.1)
+    }
+}
+
+impl PartialEq<(u32, u16)> for ObjectId {
+    fn eq(&self, other: &(u32, u16)) -> bool {
+        (self.0, self.1) == *other
+    }
+}
+
+/// Formatted as a reference, e.g. `152 0 R`.
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} R", self.0, self.1)
+    }
+}
+
+/// Parse an object ID from its numbers, e.g. `152 0`, optionally followed by `R`.
+impl str::FromStr for ObjectId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidObjectId(s.to_string());
+        let mut parts = s.split_ascii_whitespace();
+        let num = parts.next().and_then(|num| num.parse().ok()).ok_or_else(invalid)?;
+        let gen = parts.next().and_then(|gen| gen.parse().ok()).ok_or_else(invalid)?;
+        match (parts.next(), parts.next()) {
+            (None | Some("R"), None) => Ok(ObjectId(num, gen)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Which value a dictionary read from a file keeps for a key given more than once, see
+/// [`Dictionary::set_with_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeyPolicy {
+    /// The last value wins, as most readers do.
+    #[default]
+    KeepLast,
+    KeepFirst,
+    /// Loading fails with [`Error::DuplicateKey`]. The dictionary itself keeps the last value.
+    Error,
+}
 
 /// Dictionary object.
+///
+/// Keys keep the order in which they were inserted, which for a parsed dictionary is the order
+/// in the file, and are written in that order unless [`SaveOptions::sort_dictionary_keys`](crate::SaveOptions) is set.
 #[derive(Clone, Default, PartialEq)]
 pub struct Dictionary(IndexMap<Vec<u8>, Object>);
 
@@ -28,8 +116,22 @@ pub struct Stream {
     /// Can the stream be compressed by the `Document::compress()` function?
     /// Font streams may not be compressed, for example
     pub allows_compression: bool,
-    /// Stream data's position in PDF file.
+    /// Offset of the stream data in the file it was read from, `None` for a stream created in memory.
     pub start_position: Option<usize>,
+    /// Line endings surrounding the data of a stream read from a file.
+    /// Reset whenever the content is replaced through the methods of `Stream`, and ignored on save
+    /// when the content was replaced directly, leaving `/Length` stale.
+    pub original_eol: Option<StreamEol>,
+}
+
+/// The exact bytes a loaded stream had between the `stream` keyword and its data,
+/// and between its data and the `endstream` keyword.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamEol {
+    /// Usually `\n` or `\r\n`, possibly preceded by non-conforming spaces.
+    pub after_keyword: Vec<u8>,
+    /// End-of-line marker before `endstream`, empty if there was none.
+    pub before_endstream: Vec<u8>,
 }
 
 /// Basic PDF object types defined in an enum.
@@ -45,6 +147,9 @@ pub enum Object {
     Dictionary(Dictionary),
     Stream(Stream),
     Reference(ObjectId),
+    /// A token of a content stream which isn't a PDF object, e.g. an operand of a private operator,
+    /// kept as is so it is written back unchanged. It only appears in decoded content.
+    RawToken(Vec<u8>),
 }
 
 /// String objects can be written in two formats.
@@ -132,6 +237,12 @@ fn from(id: ObjectId) -> Self {
     }
 }
 
+impl From<(u32, u16)> for Object {
+    fn from(id: (u32, u16)) -> Self {
+        Object::Reference(id.into())
+    }
+}
+
 impl Object {
     pub fn string_literal<S: Into<Vec<u8>>>(s: S) -> Self {
         Object::String(s.into(), StringFormat::Literal)
@@ -161,6 +272,23 @@ pub fn as_i64(&self) -> Result<i64> {
         }
     }
 
+    /// Get the object value as an integer, also accepting a Real, truncated, and a string holding a
+    /// number, as some writers give numbers that must be integers, e.g. `/Length 1024.0` or `/Length (1024)`.
+    pub(crate) fn as_i64_lenient(&self) -> Result<i64> {
+        let real = match self {
+            Object::Integer(value) => return Ok(*value),
+            Object::Real(value) => Some(f64::from(*value)),
+            Object::String(bytes, _) => str::from_utf8(bytes).ok().and_then(|text| text.trim().parse().ok()),
+            _ => None,
+        };
+        real.filter(|value| value.is_finite() && value.abs() < i64::MAX as f64)
+            .map(|value| value.trunc() as i64)
+            .ok_or(Error::ObjectType {
+                expected: "Integer",
+                found: self.enum_variant(),
+            })
+    }
+
     pub fn as_f32(&self) -> Result<f32> {
         match self {
             Object::Real(value) => Ok(*value),
@@ -234,6 +362,11 @@ pub fn as_array(&self) -> Result<&Vec<Object>> {
         }
     }
 
+    /// Convert the elements of an array, e.g. `as_vec_of::<f32>()` for an array of numbers.
+    pub fn as_vec_of<T: TryFromObject>(&self) -> Result<Vec<T>> {
+        self.as_array()?.iter().map(T::try_from_object).collect()
+    }
+
     pub fn as_array_mut(&mut self) -> Result<&mut Vec<Object>> {
         match self {
             Object::Array(arr) => Ok(arr),
@@ -308,10 +441,107 @@ pub fn enum_variant(&self) -> &'static str {
             Object::Dictionary(_) => "Dictionary",
             Object::Stream(_) => "Stream",
             Object::Reference(_) => "Reference",
+            Object::RawToken(_) => "RawToken",
+        }
+    }
+
+    /// Push the IDs of the objects this object refers to, in the order they appear, including those in
+    /// nested arrays and dictionaries and in the dictionary of a stream.
+    pub(crate) fn push_references(&self, references: &mut Vec<ObjectId>) {
+        self.push_references_skipping(None, references);
+    }
+
+    /// Like [`Object::push_references`], without following the dictionary entries `key`, e.g. `/Parent`
+    /// when walking down the page tree.
+    pub(crate) fn push_references_except(&self, key: &[u8], references: &mut Vec<ObjectId>) {
+        self.push_references_skipping(Some(key), references);
+    }
+
+    fn push_references_skipping(&self, skipped: Option<&[u8]>, references: &mut Vec<ObjectId>) {
+        match self {
+            Object::Reference(id) => references.push(*id),
+            Object::Array(array) => array
+                .iter()
+                .for_each(|item| item.push_references_skipping(skipped, references)),
+            Object::Dictionary(dict) | Object::Stream(Stream { dict, .. }) => dict
+                .iter()
+                .filter(|(key, _)| Some(key.as_slice()) != skipped)
+                .for_each(|(_, value)| value.push_references_skipping(skipped, references)),
+            _ => {}
+        }
+    }
+}
+
+/// A value read from an object, see [`Object::as_vec_of`] and [`Dictionary::get_as`].
+///
+/// Fixed-size arrays such as `[f32; 4]` for a rectangle or `[f32; 6]` for a matrix are read from
+/// arrays with exactly that many elements, otherwise [`Error::ArrayLength`] is returned.
+pub trait TryFromObject: Sized {
+    /// What the value is called in error messages.
+    const DESCRIPTION: &'static str;
+
+    fn try_from_object(object: &Object) -> Result<Self>;
+}
+
+impl TryFromObject for i64 {
+    const DESCRIPTION: &'static str = "integer";
+
+    fn try_from_object(object: &Object) -> Result<Self> {
+        object.as_i64()
+    }
+}
+
+/// Both integers and reals are accepted.
+impl TryFromObject for f32 {
+    const DESCRIPTION: &'static str = "number";
+
+    fn try_from_object(object: &Object) -> Result<Self> {
+        object.as_float()
+    }
+}
+
+impl TryFromObject for ObjectId {
+    const DESCRIPTION: &'static str = "reference";
+
+    fn try_from_object(object: &Object) -> Result<Self> {
+        object.as_reference()
+    }
+}
+
+/// The bytes of a name or a string.
+impl TryFromObject for Vec<u8> {
+    const DESCRIPTION: &'static str = "name or string";
+
+    fn try_from_object(object: &Object) -> Result<Self> {
+        match object {
+            Object::Name(bytes) | Object::String(bytes, _) => Ok(bytes.clone()),
+            _ => Err(Error::ObjectType {
+                expected: "Name or String",
+                found: object.enum_variant(),
+            }),
         }
     }
 }
 
+impl<T: TryFromObject, const N: usize> TryFromObject for [T; N] {
+    const DESCRIPTION: &'static str = "array";
+
+    fn try_from_object(object: &Object) -> Result<Self> {
+        array_from_objects(object.as_array()?)
+    }
+}
+
+/// Convert exactly `N` objects, e.g. the operands of an operator.
+pub(crate) fn array_from_objects<T: TryFromObject, const N: usize>(objects: &[Object]) -> Result<[T; N]> {
+    let values = objects.iter().map(T::try_from_object).collect::<Result<Vec<_>>>()?;
+    values.try_into().map_err(|values: Vec<T>| Error::ArrayLength {
+        expected: N,
+        found: values.len(),
+        element: T::DESCRIPTION,
+        key: None,
+    })
+}
+
 impl fmt::Debug for Object {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -334,7 +564,8 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             }
             Object::Dictionary(dict) => write!(f, "{:?}", dict),
             Object::Stream(stream) => write!(f, "{:?}stream...endstream", stream.dict),
-            Object::Reference(id) => write!(f, "{} {} R", id.0, id.1),
+            Object::Reference(id) => write!(f, "{id}"),
+            Object::RawToken(token) => write!(f, "{}", String::from_utf8_lossy(token)),
         }
     }
 }
@@ -345,13 +576,46 @@ pub fn new() -> Dictionary {
     }
 
     pub fn has(&self, key: &[u8]) -> bool {
-        self.0.contains_key(key)
+        self.index_of(key).is_some()
     }
 
     pub fn get(&self, key: &[u8]) -> Result<&Object> {
-        self.0
-            .get(key)
-            .ok_or(Error::DictKey(String::from_utf8_lossy(key).to_string()))
+        self.index_of(key)
+            .and_then(|index| self.0.get_index(index))
+            .map(|(_, value)| value)
+            .ok_or_else(|| Error::DictKey(String::from_utf8_lossy(key).to_string()))
+    }
+
+    /// The position of `key`. Most dictionaries have a handful of entries, for which comparing the
+    /// keys, which compares their lengths first, is faster than hashing the key.
+    fn index_of(&self, key: &[u8]) -> Option<usize> {
+        if self.0.len() <= Self::SMALL_LEN {
+            self.0.keys().position(|existing| existing.as_slice() == key)
+        } else {
+            self.0.get_index_of(key)
+        }
+    }
+
+    /// The number of entries up to which [`Dictionary::index_of`] compares the keys one by one.
+    const SMALL_LEN: usize = 8;
+
+    /// Get a value converted with [`TryFromObject`], e.g. `get_as::<[f32; 4]>(b"MediaBox")`.
+    /// A reference is not followed.
+    pub fn get_as<T: TryFromObject>(&self, key: &[u8]) -> Result<T> {
+        T::try_from_object(self.get(key)?).map_err(|err| match err {
+            Error::ArrayLength {
+                expected,
+                found,
+                element,
+                key: None,
+            } => Error::ArrayLength {
+                expected,
+                found,
+                element,
+                key: Some(String::from_utf8_lossy(key).into_owned()),
+            },
+            err => err,
+        })
     }
 
     /// Extract object from dictionary, dereferencing
@@ -361,9 +625,10 @@ pub fn get_deref<'a>(&'a self, key: &[u8], doc: &'a Document) -> Result<&'a Obje
     }
 
     pub fn get_mut(&mut self, key: &[u8]) -> Result<&mut Object> {
-        self.0
-            .get_mut(key)
-            .ok_or(Error::DictKey(String::from_utf8_lossy(key).to_string()))
+        match self.index_of(key) {
+            Some(index) => Ok(&mut self.0[index]),
+            None => Err(Error::DictKey(String::from_utf8_lossy(key).to_string())),
+        }
     }
 
     pub fn set<K, V>(&mut self, key: K, value: V)
@@ -374,6 +639,32 @@ pub fn set<K, V>(&mut self, key: K, value: V)
         self.0.insert(key.into(), value.into());
     }
 
+    /// Set an entry like [`Dictionary::set`], with `policy` deciding which value a key already
+    /// present keeps. The key keeps its position either way. Returns whether the key was present.
+    pub fn set_with_policy<K, V>(&mut self, key: K, value: V, policy: DuplicateKeyPolicy) -> bool
+    where
+        K: Into<Vec<u8>>,
+        V: Into<Object>,
+    {
+        let key = key.into();
+        match self.0.get_mut(&key) {
+            Some(_) if policy == DuplicateKeyPolicy::KeepFirst => true,
+            Some(existing) => {
+                *existing = value.into();
+                true
+            }
+            None => {
+                self.0.insert(key, value.into());
+                false
+            }
+        }
+    }
+
+    /// Number of entries the dictionary can hold without reallocating.
+    pub(crate) fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
     pub fn len(&self) -> usize {
         self.0.len()
     }
@@ -382,8 +673,14 @@ pub fn is_empty(&self) -> bool {
         self.0.len() == 0
     }
 
+    /// Remove a key, keeping the order of the remaining keys.
     pub fn remove(&mut self, key: &[u8]) -> Option<Object> {
-        self.0.swap_remove(key)
+        self.0.shift_remove(key)
+    }
+
+    /// Sort the keys in byte order, e.g. to produce canonical output.
+    pub fn sort_keys(&mut self) {
+        self.0.sort_keys();
     }
 
     pub fn has_type(&self, type_name: &[u8]) -> bool {
@@ -396,15 +693,15 @@ pub fn get_type(&self) -> Result<&[u8]> {
             .or_else(|_| self.get(b"Linearized").and(Ok(b"Linearized")))
     }
 
-    pub fn iter(&self) -> indexmap::map::Iter<Vec<u8>, Object> {
+    pub fn iter(&self) -> indexmap::map::Iter<'_, Vec<u8>, Object> {
         self.0.iter()
     }
 
-    pub fn iter_mut(&mut self) -> indexmap::map::IterMut<Vec<u8>, Object> {
+    pub fn iter_mut(&mut self) -> indexmap::map::IterMut<'_, Vec<u8>, Object> {
         self.0.iter_mut()
     }
 
-    pub fn get_font_encoding(&self, doc: &Document) -> Result<Encoding> {
+    pub fn get_font_encoding<'a>(&'a self, doc: &'a Document) -> Result<Encoding<'a>> {
         if !self.has_type(b"Font") {
             return Err(Error::DictType {
                 expected: "Font",
@@ -413,12 +710,14 @@ pub fn get_font_encoding(&self, doc: &Document) -> Result<Encoding> {
         }
 
         // Note: currently not all encodings are handled, not implemented:
-        // - dictionary differences encoding
-        // - default base encoding in dictionary differences encoding
+        // - built-in base encoding of symbolic fonts in dictionary differences encoding
         // - TrueType cmap tables
         // - DescendantFonts in CID-Keyed fonts
         // - predefined CJK CMAP other than indicated in SimpleEncoding
         match self.get(b"Encoding").and_then(Object::as_name) {
+            Ok(name) if doc.custom_encodings.contains_key(name) => {
+                Ok(Encoding::OneByteEncoding(&doc.custom_encodings[name]))
+            }
             Ok(b"StandardEncoding") => Ok(Encoding::OneByteEncoding(&encodings::STANDARD_ENCODING)),
             Ok(b"MacRomanEncoding") => Ok(Encoding::OneByteEncoding(&encodings::MAC_ROMAN_ENCODING)),
             Ok(b"MacExpertEncoding") => Ok(Encoding::OneByteEncoding(&encodings::MAC_EXPERT_ENCODING)),
@@ -433,12 +732,18 @@ pub fn get_font_encoding(&self, doc: &Document) -> Result<Encoding> {
             }
             Ok(name) => Ok(Encoding::SimpleEncoding(name)),
             Err(err) => {
+                let to_unicode = self.get_deref(b"ToUnicode", doc).and_then(Object::as_stream);
+                let encoding = self.get_deref(b"Encoding", doc).and_then(Object::as_dict);
+                if let (Err(_), Ok(encoding)) = (&to_unicode, encoding) {
+                    return Ok(Encoding::DifferencesEncoding(Box::new(Self::differences_encoding(
+                        encoding, doc,
+                    ))));
+                }
                 warn!(
                     "Could not parse the encoding, error: {:#?}\nFont: {:#?}\nTrying to retrieve ToUnicode.",
                     err, self
                 );
-                let stream = self.get_deref(b"ToUnicode", doc).and_then(Object::as_stream);
-                if let Ok(stream) = stream {
+                if let Ok(stream) = to_unicode {
                     return self.get_encoding_from_to_unicode_cmap(stream);
                 }
 
@@ -448,7 +753,33 @@ pub fn get_font_encoding(&self, doc: &Document) -> Result<Encoding> {
         }
     }
 
-    fn get_encoding_from_to_unicode_cmap(&self, stream: &Stream) -> Result<Encoding> {
+    /// Character set of an encoding dictionary: its `/BaseEncoding`, standard encoding by default,
+    /// with the codes of `/Differences` mapped to the Unicode values of their glyph names.
+    fn differences_encoding(encoding: &Dictionary, doc: &Document) -> encodings::CodedCharacterSet {
+        let mut code_set = match encoding.get(b"BaseEncoding").and_then(Object::as_name) {
+            Ok(b"WinAnsiEncoding") => encodings::WIN_ANSI_ENCODING,
+            Ok(b"MacRomanEncoding") => encodings::MAC_ROMAN_ENCODING,
+            Ok(b"MacExpertEncoding") => encodings::MAC_EXPERT_ENCODING,
+            _ => encodings::STANDARD_ENCODING,
+        };
+        let differences = encoding.get_deref(b"Differences", doc).and_then(Object::as_array);
+        let mut code = 0;
+        for item in differences.map_or(&[][..], Vec::as_slice) {
+            match doc.dereference(item).map(|(_, item)| item) {
+                Ok(Object::Integer(first)) => code = *first,
+                Ok(Object::Name(name)) => {
+                    if let Some(slot) = usize::try_from(code).ok().and_then(|code| code_set.get_mut(code)) {
+                        *slot = encodings::glyph_unicode(name);
+                    }
+                    code += 1;
+                }
+                _ => {}
+            }
+        }
+        code_set
+    }
+
+    fn get_encoding_from_to_unicode_cmap(&self, stream: &Stream) -> Result<Encoding<'_>> {
         let content = stream.get_plain_content()?;
         let cmap = ToUnicodeCMap::parse(content)?;
         Ok(Encoding::UnicodeMapEncoding(cmap))
@@ -606,6 +937,7 @@ pub fn new(mut dict: Dictionary, content: Vec<u8>) -> Stream {
             content,
             allows_compression: true,
             start_position: None,
+            original_eol: None,
         }
     }
 
@@ -615,6 +947,7 @@ pub fn with_position(dict: Dictionary, position: usize) -> Stream {
             content: vec![],
             allows_compression: true,
             start_position: Some(position),
+            original_eol: None,
         }
     }
 
@@ -627,7 +960,12 @@ pub fn with_compression(mut self, allows_compression: bool) -> Stream {
     }
 
     pub fn filters(&self) -> Result<Vec<&[u8]>> {
-        let filter = self.dict.get(b"Filter")?;
+        Self::filters_of(&self.dict)
+    }
+
+    /// The `/Filter` names of a stream or inline image dictionary.
+    pub(crate) fn filters_of(dict: &Dictionary) -> Result<Vec<&[u8]>> {
+        let filter = dict.get(b"Filter")?;
 
         if let Ok(name) = filter.as_name() {
             Ok(vec![name])
@@ -644,6 +982,110 @@ pub fn filters(&self) -> Result<Vec<&[u8]>> {
     pub fn set_content(&mut self, content: Vec<u8>) {
         self.content = content;
         self.dict.set("Length", self.content.len() as i64);
+        self.original_eol = None;
+    }
+
+    /// Edit the stream dictionary with `f`, except for the entries which describe the data.
+    ///
+    /// The managed entries `/Length`, `/Filter` and `/DecodeParms` follow [`Stream::set_content`] and
+    /// [`Stream::set_filters`], which also re-encodes the data. If `f` changes them, they are restored
+    /// and [`Error::ManagedStreamKey`] is returned; the other changes of `f` are kept.
+    ///
+    /// Fails with [`Error::InvalidStream`] without calling `f` if `/Length` doesn't match the data,
+    /// e.g. after replacing [`Stream::content`] directly.
+    pub fn with_dict_mut<R>(&mut self, f: impl FnOnce(&mut Dictionary) -> R) -> Result<R> {
+        const MANAGED_KEYS: [&[u8]; 3] = [b"Length", b"Filter", b"DecodeParms"];
+
+        self.check_length()?;
+        let managed = MANAGED_KEYS.map(|key| self.dict.get(key).ok().cloned());
+        let result = f(&mut self.dict);
+        let mut changed = None;
+        for (key, value) in MANAGED_KEYS.into_iter().zip(managed) {
+            if self.dict.get(key).ok() == value.as_ref() {
+                continue;
+            }
+            changed.get_or_insert(key);
+            match value {
+                Some(value) => self.dict.set(key, value),
+                None => {
+                    self.dict.remove(key);
+                }
+            }
+        }
+        match changed {
+            Some(key) => Err(Error::ManagedStreamKey(String::from_utf8_lossy(key).into_owned())),
+            None => Ok(result),
+        }
+    }
+
+    /// Check that `/Length` matches the data, unless the data is yet to be read from the file.
+    fn check_length(&self) -> Result<()> {
+        if self.start_position.is_some() && self.content.is_empty() {
+            return Ok(());
+        }
+        match self.dict.get(b"Length").and_then(Object::as_i64) {
+            Ok(length) if length != self.content.len() as i64 => Err(Error::InvalidStream(format!(
+                "/Length {length} doesn't match the {} bytes of data",
+                self.content.len()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Re-encode the data with `filters`, given in decoding order like `/Filter`, which replace the current ones.
+    ///
+    /// An empty list stores the data unencoded. The filters which can encode data are listed in
+    /// [`Stream::ENCODING_FILTERS`].
+    pub fn set_filters(&mut self, filters: &[&[u8]]) -> Result<()> {
+        let mut data = self.decompressed_content()?;
+        for filter in filters.iter().rev() {
+            data = match *filter {
+                b"FlateDecode" => Self::compress_zlib(&data)?,
+                b"LZWDecode" => crate::filters::lzw::encode(&data, true),
+                b"ASCIIHexDecode" => Self::encode_ascii_hex(&data),
+                b"ASCII85Decode" => Self::encode_ascii85(&data),
+                _ => return Err(Error::Unimplemented("compression algorithms")),
+            };
+        }
+        self.dict.remove(b"DecodeParms");
+        match filters {
+            [] => {
+                self.dict.remove(b"Filter");
+            }
+            [filter] => self.dict.set("Filter", Object::Name(filter.to_vec())),
+            _ => self.dict.set(
+                "Filter",
+                filters
+                    .iter()
+                    .map(|filter| Object::Name(filter.to_vec()))
+                    .collect::<Vec<_>>(),
+            ),
+        }
+        self.set_content(data);
+        Ok(())
+    }
+
+    /// Replace the decoded data, encoding it again with the filters of the stream.
+    ///
+    /// If one of the filters can't encode data, the data is stored unencoded and `/Filter` is removed.
+    pub fn set_decoded_content(&mut self, content: Vec<u8>) -> Result<()> {
+        let filters: Vec<Vec<u8>> = self
+            .filters()
+            .map(|filters| filters.into_iter().map(<[u8]>::to_vec).collect())
+            .unwrap_or_default();
+        self.set_plain_content(content);
+        if let Some(filter) = filters
+            .iter()
+            .find(|filter| !Self::ENCODING_FILTERS.contains(&filter.as_slice()))
+        {
+            warn!(
+                "Storing stream data unencoded, it can't be encoded with /{}",
+                String::from_utf8_lossy(filter)
+            );
+            return Ok(());
+        }
+        let filters: Vec<&[u8]> = filters.iter().map(Vec::as_slice).collect();
+        self.set_filters(&filters)
     }
 
     pub fn set_plain_content(&mut self, content: Vec<u8>) {
@@ -651,24 +1093,20 @@ pub fn set_plain_content(&mut self, content: Vec<u8>) {
         self.dict.remove(b"Filter");
         self.dict.set("Length", content.len() as i64);
         self.content = content;
+        self.original_eol = None;
     }
 
     pub fn get_plain_content(&self) -> Result<Vec<u8>> {
         match self.filters() {
             Ok(vec) if !vec.is_empty() => self.decompressed_content(),
+            _ if self.is_external() => Err(Error::ExternalStream),
             _ => Ok(self.content.clone()),
         }
     }
 
     pub fn compress(&mut self) -> Result<()> {
-        use flate2::write::ZlibEncoder;
-        use flate2::Compression;
-        use std::io::prelude::*;
-
         if self.dict.get(b"Filter").is_err() {
-            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
-            encoder.write_all(self.content.as_slice())?;
-            let compressed = encoder.finish()?;
+            let compressed = Self::compress_zlib(&self.content)?;
             if compressed.len() + 19 < self.content.len() {
                 self.dict.set("Filter", "FlateDecode");
                 self.set_content(compressed);
@@ -677,63 +1115,216 @@ pub fn compress(&mut self) -> Result<()> {
         Ok(())
     }
 
+    /// Re-encode data compressed with `LZWDecode` with `FlateDecode`, which compresses better.
+    ///
+    /// The other filters are kept. Returns whether the stream was converted, it is left as it is on error.
+    pub fn lzw_to_flate(&mut self) -> Result<bool> {
+        let Ok(filters) = self.filters() else {
+            return Ok(false);
+        };
+        if !filters.contains(&b"LZWDecode".as_slice()) {
+            return Ok(false);
+        }
+        let filters: Vec<Vec<u8>> = filters
+            .into_iter()
+            .map(|filter| match filter {
+                b"LZWDecode" => b"FlateDecode".to_vec(),
+                filter => filter.to_vec(),
+            })
+            .collect();
+        let filters: Vec<&[u8]> = filters.iter().map(Vec::as_slice).collect();
+        self.set_filters(&filters)?;
+        Ok(true)
+    }
+
+    /// The `/Type` of the streams which [`Document::compress`](crate::Document::compress) leaves as they are.
+    pub const UNCOMPRESSED_TYPES: [&'static [u8]; 3] = [b"XRef", b"ObjStm", b"Metadata"];
+
+    /// The filters which [`Stream::set_filters`] can encode data with.
+    pub const ENCODING_FILTERS: [&'static [u8]; 4] =
+        [b"FlateDecode", b"LZWDecode", b"ASCIIHexDecode", b"ASCII85Decode"];
+
+    fn encode_ascii_hex(input: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = input.iter().flat_map(|c| format!("{c:02X}").into_bytes()).collect();
+        output.push(b'>');
+        output
+    }
+
+    fn encode_ascii85(input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(input.len() / 4 * 5 + 7);
+        for chunk in input.chunks(4) {
+            let mut group = [0; 4];
+            group[..chunk.len()].copy_from_slice(chunk);
+            let mut value = u32::from_be_bytes(group);
+            if value == 0 && chunk.len() == 4 {
+                output.push(b'z');
+                continue;
+            }
+            let mut digits = [0; 5];
+            for digit in digits.iter_mut().rev() {
+                *digit = (value % 85) as u8 + b'!';
+                value /= 85;
+            }
+            // A final group of n bytes is written as n + 1 digits.
+            output.extend_from_slice(&digits[..chunk.len() + 1]);
+        }
+        output.extend_from_slice(b"~>");
+        output
+    }
+
+    fn compress_zlib(input: &[u8]) -> Result<Vec<u8>> {
+        use flate2::write::ZlibEncoder;
+        use flate2::Compression;
+        use std::io::prelude::*;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(input)?;
+        Ok(encoder.finish()?)
+    }
+
+    /// Decode the data with the filters of the stream, it is returned as is if there is no `/Filter`.
     pub fn decompressed_content(&self) -> Result<Vec<u8>> {
-        let params = self.dict.get(b"DecodeParms").and_then(Object::as_dict).ok();
+        self.decompress_with_limit(usize::MAX)
+    }
+
+    /// Decode at most the first `max_bytes` of the data, e.g. to sniff its format.
+    ///
+    /// The last filter stops once it has produced `max_bytes`, so the whole data isn't inflated.
+    /// The filters before it, usually ASCII ones which don't expand the data, decode all of it.
+    pub fn decompress_prefix(&self, max_bytes: usize) -> Result<Vec<u8>> {
+        self.decompress_with_limit(max_bytes)
+    }
+
+    fn decompress_with_limit(&self, max_bytes: usize) -> Result<Vec<u8>> {
+        #[cfg(test)]
+        tests::DECOMPRESSIONS.with(|count| count.set(count.get() + 1));
+        if self.is_external() {
+            return Err(Error::ExternalStream);
+        }
+        if !self.dict.has(b"Filter") {
+            return Ok(self.content[..self.content.len().min(max_bytes)].to_vec());
+        }
         let filters = self.filters()?;
+        let params = self.decode_parms(filters.len());
+        Self::decode_filters(&self.content, &filters, &params, max_bytes)
+    }
 
-        let mut input = self.content.as_slice();
-        let mut output = vec![];
+    /// Decode `content` with `filters`, in decoding order, each with its parameters in `params`.
+    ///
+    /// The last filter stops once it has produced `max_bytes`.
+    pub(crate) fn decode_filters(
+        content: &[u8], filters: &[&[u8]], params: &[Option<&Dictionary>], max_bytes: usize,
+    ) -> Result<Vec<u8>> {
+        let mut output: Option<Vec<u8>> = None;
 
         // Filters are in decoding order.
-        for filter in filters {
-            output = match filter {
-                b"FlateDecode" => Self::decompress_zlib(input, params)?,
-                b"LZWDecode" => Self::decompress_lzw(input, params)?,
+        for (index, filter) in filters.iter().enumerate() {
+            let params = params.get(index).copied().flatten();
+            let input = output.as_deref().unwrap_or(content);
+            let limit = if index + 1 == filters.len() {
+                max_bytes
+            } else {
+                usize::MAX
+            };
+            output = Some(match *filter {
+                b"FlateDecode" => Self::decompress_zlib(input, params, limit)?,
+                b"LZWDecode" => Self::decompress_lzw(input, params, limit)?,
                 b"ASCII85Decode" => Self::decode_ascii85(input)?,
+                b"ASCIIHexDecode" => Self::decode_ascii_hex(input)?,
+                // The data was decrypted with the document, or it isn't encrypted by the Identity crypt filter.
+                b"Crypt" => continue,
                 _ => return Err(Error::Unimplemented("decompression algorithms")),
-            };
-            input = &output;
+            });
         }
+        let mut output = output.unwrap_or_else(|| content.to_vec());
+        output.truncate(max_bytes);
         Ok(output)
     }
 
-    fn decompress_lzw(input: &[u8], params: Option<&Dictionary>) -> Result<Vec<u8>> {
-        use weezl::{decode::Decoder, BitOrder};
-        const MIN_BITS: u8 = 9;
+    /// Get the parameters of each of the `count` filters of the stream.
+    ///
+    /// `/DecodeParms` is either an array with an entry for each filter, or a dictionary which is used for every
+    /// filter.
+    fn decode_parms(&self, count: usize) -> Vec<Option<&Dictionary>> {
+        Self::decode_parms_of(&self.dict, count)
+    }
+
+    /// The parameters of each of the `count` filters of a stream or inline image dictionary, see
+    /// [`Stream::decode_parms`].
+    pub(crate) fn decode_parms_of(dict: &Dictionary, count: usize) -> Vec<Option<&Dictionary>> {
+        match dict.get(b"DecodeParms") {
+            Ok(Object::Array(params)) => (0..count)
+                .map(|index| params.get(index).and_then(|params| params.as_dict().ok()))
+                .collect(),
+            Ok(Object::Dictionary(params)) => vec![Some(params); count],
+            _ => vec![None; count],
+        }
+    }
+
+    /// Remove the `/Crypt` filter which starts the filters of the stream, with its parameters.
+    ///
+    /// Returns the name of the crypt filter, `None` if the stream has none.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn take_crypt_filter(&mut self) -> Option<Vec<u8>> {
+        let filters = self.filters().ok()?;
+        if filters.first() != Some(&b"Crypt".as_slice()) {
+            return None;
+        }
+        let name = self.decode_parms(filters.len())[0]
+            .and_then(|params| params.get(b"Name").and_then(Object::as_name).ok())
+            .unwrap_or(b"Identity")
+            .to_vec();
+
+        let mut filters: Vec<Object> = filters[1..]
+            .iter()
+            .map(|&filter| Object::Name(filter.to_vec()))
+            .collect();
+        let params = match self.dict.remove(b"DecodeParms") {
+            Some(Object::Array(mut params)) if !params.is_empty() => {
+                params.remove(0);
+                params
+            }
+            // A dictionary is for the crypt filter when it is alone.
+            Some(params @ Object::Dictionary(_)) if !filters.is_empty() => vec![params],
+            _ => vec![],
+        };
+        match filters.len() {
+            0 => {
+                self.dict.remove(b"Filter");
+            }
+            1 => self.dict.set("Filter", filters.pop().unwrap()),
+            _ => self.dict.set("Filter", filters),
+        }
+        if params.iter().any(|params| !matches!(params, Object::Null)) {
+            let params = if params.len() == 1 {
+                params.into_iter().next().unwrap()
+            } else {
+                Object::Array(params)
+            };
+            self.dict.set("DecodeParms", params);
+        }
+        Some(name)
+    }
+
+    fn decompress_lzw(input: &[u8], params: Option<&Dictionary>, limit: usize) -> Result<Vec<u8>> {
+        use crate::filters::lzw;
 
         let early_change = params
             .and_then(|p| p.get(b"EarlyChange").ok())
             .and_then(|p| Object::as_i64(p).ok())
             .map(|v| v != 0)
             .unwrap_or(true);
-
-        let mut decoder = if early_change {
-            Decoder::with_tiff_size_switch(BitOrder::Msb, MIN_BITS - 1)
-        } else {
-            Decoder::new(BitOrder::Msb, MIN_BITS - 1)
-        };
-
-        let output = Self::decompress_lzw_loop(input, &mut decoder);
+        let output = lzw::decode(input, early_change, Self::predicted_limit(limit, params));
         Self::decompress_predictor(output, params)
     }
 
-    fn decompress_lzw_loop(input: &[u8], decoder: &mut weezl::decode::Decoder) -> Vec<u8> {
-        let mut output = vec![];
-
-        let result = decoder.into_stream(&mut output).decode_all(input);
-        if let Err(err) = result.status {
-            warn!("{}", err);
-        }
-
-        output
-    }
-
-    fn decompress_zlib(input: &[u8], params: Option<&Dictionary>) -> Result<Vec<u8>> {
+    fn decompress_zlib(input: &[u8], params: Option<&Dictionary>, limit: usize) -> Result<Vec<u8>> {
         use flate2::read::ZlibDecoder;
         use std::io::prelude::*;
 
-        let mut output = Vec::with_capacity(input.len() * 2);
-        let mut decoder = ZlibDecoder::new(input);
+        let limit = Self::predicted_limit(limit, params);
+        let mut output = Vec::with_capacity(input.len().saturating_mul(2).min(limit));
+        let mut decoder = ZlibDecoder::new(input).take(limit as u64);
 
         if !input.is_empty() {
             decoder.read_to_end(&mut output).unwrap_or_else(|err| {
@@ -744,6 +1335,29 @@ fn decompress_zlib(input: &[u8], params: Option<&Dictionary>) -> Result<Vec<u8>>
         Self::decompress_predictor(output, params)
     }
 
+    fn decode_ascii_hex(input: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(input.len() / 2);
+        let mut high = None;
+        for &ch in input {
+            let digit = match ch {
+                b'>' => break,
+                _ if ch.is_ascii_whitespace() => continue,
+                _ => (ch as char)
+                    .to_digit(16)
+                    .ok_or(DecompressError::AsciiHex("invalid hexadecimal digit"))? as u8,
+            };
+            match high.take() {
+                Some(high) => output.push(high << 4 | digit),
+                None => high = Some(digit),
+            }
+        }
+        // A missing final digit is taken as 0.
+        if let Some(high) = high {
+            output.push(high << 4);
+        }
+        Ok(output)
+    }
+
     fn decode_ascii85(input: &[u8]) -> Result<Vec<u8>> {
         let mut output = vec![];
         let mut buffer: u32 = 0;
@@ -799,22 +1413,36 @@ fn decode_ascii85(input: &[u8]) -> Result<Vec<u8>> {
         Ok(output)
     }
 
+    /// Number of bytes to decompress before the PNG predictor to get `limit` bytes after it, in whole rows.
+    fn predicted_limit(limit: usize, params: Option<&Dictionary>) -> usize {
+        match params.and_then(Self::png_predictor_row) {
+            Some((bytes_per_pixel, pixels_per_row)) => {
+                let bytes_per_row = bytes_per_pixel * pixels_per_row;
+                limit.div_ceil(bytes_per_row).saturating_mul(bytes_per_row + 1)
+            }
+            None => limit,
+        }
+    }
+
+    /// Bytes per pixel and pixels per row of the PNG predictor, if one is used.
+    fn png_predictor_row(params: &Dictionary) -> Option<(usize, usize)> {
+        let predictor = params.get(b"Predictor").and_then(Object::as_i64).unwrap_or(1);
+        if !(10..=15).contains(&predictor) {
+            return None;
+        }
+        let pixels_per_row = max(1, params.get(b"Columns").and_then(Object::as_i64).unwrap_or(1)) as usize;
+        let colors = max(1, params.get(b"Colors").and_then(Object::as_i64).unwrap_or(1)) as usize;
+        let bits = max(8, params.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(8)) as usize;
+        Some((colors * bits / 8, pixels_per_row))
+    }
+
     fn decompress_predictor(mut data: Vec<u8>, params: Option<&Dictionary>) -> Result<Vec<u8>> {
         use crate::filters::png;
 
-        if let Some(params) = params {
-            let predictor = params.get(b"Predictor").and_then(Object::as_i64).unwrap_or(1);
-            if (10..=15).contains(&predictor) {
-                let pixels_per_row = max(1, params.get(b"Columns").and_then(Object::as_i64).unwrap_or(1)) as usize;
-                let colors = max(1, params.get(b"Colors").and_then(Object::as_i64).unwrap_or(1)) as usize;
-                let bits = max(8, params.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(8)) as usize;
-                let bytes_per_pixel = colors * bits / 8;
-                data = png::decode_frame(data.as_slice(), bytes_per_pixel, pixels_per_row)?;
-            }
-            Ok(data)
-        } else {
-            Ok(data)
+        if let Some((bytes_per_pixel, pixels_per_row)) = params.and_then(Self::png_predictor_row) {
+            data = png::decode_frame(data.as_slice(), bytes_per_pixel, pixels_per_row)?;
         }
+        Ok(data)
     }
 
     pub fn decompress(&mut self) -> Result<()> {
@@ -831,10 +1459,16 @@ pub fn is_compressed(&self) -> bool {
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod tests {
     use crate::{error::DecompressError, Error};
+    use std::cell::Cell;
 
-    use super::Stream;
+    thread_local! {
+        /// Number of streams decoded on this thread.
+        pub(crate) static DECOMPRESSIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    use super::{Object, ObjectId, Stream, TryFromObject};
 
     #[test]
     fn test_decode_ascii85() {
@@ -856,4 +1490,300 @@ fn test_decode_ascii85_overflow() {
         // let expected: Result<Vec<u8>, Error> = Err(Error::ContentDecode);
         assert!(matches!(output, Err(Error::Decompress(DecompressError::Ascii85(_)))));
     }
+
+    #[test]
+    fn length_follows_content() {
+        let mut stream = Stream::new(dictionary! {}, b"abc".to_vec());
+        stream.set_content(b"abcdef".to_vec());
+        assert_eq!(stream.dict.get(b"Length").unwrap().as_i64().unwrap(), 6);
+
+        // The writer corrects a /Length left stale by replacing the data directly.
+        stream.content = b"abcdefghi".to_vec();
+        let mut buffer = vec![];
+        crate::writer::Writer::write_object(&mut buffer, &stream.clone().into(), &Default::default()).unwrap();
+        assert!(buffer.starts_with(b"<</Length 9>>stream"));
+    }
+
+    #[test]
+    fn with_dict_mut_keeps_managed_keys() {
+        let data = b"Some text which compresses well. ".repeat(10);
+        let mut stream = Stream::new(dictionary! { "Type" => "Test" }, data.clone());
+        stream.compress().unwrap();
+        let result = stream.with_dict_mut(|dict| {
+            dict.remove(b"Filter");
+            dict.set("Length", 1);
+            dict.set("Subtype", "Changed");
+        });
+        assert!(matches!(result, Err(Error::ManagedStreamKey(key)) if key == "Length"));
+        assert_eq!(stream.filters().unwrap(), [b"FlateDecode"]);
+        assert_eq!(
+            stream.dict.get(b"Length").unwrap().as_i64().unwrap(),
+            stream.content.len() as i64
+        );
+        assert_eq!(stream.dict.get(b"Subtype").unwrap().as_name().unwrap(), b"Changed");
+        assert_eq!(stream.decompressed_content().unwrap(), data);
+
+        let kind = stream.with_dict_mut(|dict| {
+            dict.set("Type", "XObject");
+            dict.get(b"Type").unwrap().as_name().unwrap().to_vec()
+        });
+        assert_eq!(kind.unwrap(), b"XObject");
+
+        // Data replaced directly leaves /Length stale, which is reported before `f` runs.
+        stream.content = data.clone();
+        let result = stream.with_dict_mut(|dict| dict.set("Type", "Changed"));
+        assert!(matches!(result, Err(Error::InvalidStream(_))));
+        assert_eq!(stream.dict.get(b"Type").unwrap().as_name().unwrap(), b"XObject");
+        stream.set_content(data);
+        assert!(stream.with_dict_mut(|_| ()).is_ok());
+    }
+
+    #[test]
+    fn set_filters_reencodes_data() {
+        let data = b"Some text which compresses well. ".repeat(10);
+        let mut stream = Stream::new(dictionary! {}, data.clone());
+        stream.set_filters(&[b"FlateDecode", b"FlateDecode"]).unwrap();
+        assert_eq!(stream.filters().unwrap(), [b"FlateDecode", b"FlateDecode"]);
+        assert_eq!(stream.decompressed_content().unwrap(), data);
+        assert!(stream.set_filters(&[b"DCTDecode"]).is_err());
+        stream.set_filters(&[b"ASCII85Decode", b"ASCIIHexDecode"]).unwrap();
+        assert_eq!(stream.decompressed_content().unwrap(), data);
+        for data in [&b""[..], b"\0\0\0\0a", b"ab", b"abc\xff"] {
+            let mut stream = Stream::new(dictionary! {}, data.to_vec());
+            stream.set_filters(&[b"ASCIIHexDecode", b"ASCII85Decode"]).unwrap();
+            assert_eq!(stream.decompressed_content().unwrap(), data);
+        }
+        stream.set_filters(&[]).unwrap();
+        assert!(!stream.is_compressed());
+        assert_eq!(stream.content, data);
+        assert_eq!(stream.dict.get(b"Length").unwrap().as_i64().unwrap(), data.len() as i64);
+    }
+
+    #[test]
+    fn lzw_streams_are_converted_to_flate() {
+        use crate::filters::lzw;
+
+        let data = b"Some text which compresses well. ".repeat(100);
+        let mut stream = Stream::new(dictionary! {}, data.clone());
+        stream.set_filters(&[b"LZWDecode"]).unwrap();
+        assert_eq!(stream.content, lzw::encode(&data, true));
+        assert_eq!(stream.decompressed_content().unwrap(), data);
+
+        let mut stream = Stream::new(
+            dictionary! {
+                "Filter" => vec![Object::from("ASCIIHexDecode"), "LZWDecode".into()],
+                "DecodeParms" => vec![Object::Null, dictionary! { "EarlyChange" => 0 }.into()],
+            },
+            Stream::encode_ascii_hex(&lzw::encode(&data, false)),
+        );
+        let lzw_length = stream.content.len();
+        assert!(stream.lzw_to_flate().unwrap());
+        assert_eq!(
+            stream.filters().unwrap(),
+            [b"ASCIIHexDecode".as_slice(), b"FlateDecode"]
+        );
+        assert!(!stream.dict.has(b"DecodeParms"));
+        assert!(stream.content.len() < lzw_length);
+        assert_eq!(stream.decompressed_content().unwrap(), data);
+        assert!(!stream.lzw_to_flate().unwrap());
+
+        let mut doc = crate::Document::with_version("1.5");
+        let id = doc.add_object(Stream::new(
+            dictionary! { "Filter" => "LZWDecode" },
+            lzw::encode(&data, true),
+        ));
+        doc.compress();
+        let stream = doc.get_object(id).and_then(Object::as_stream).unwrap();
+        assert_eq!(stream.filters().unwrap(), [b"FlateDecode"]);
+        assert_eq!(stream.decompressed_content().unwrap(), data);
+    }
+
+    #[test]
+    fn decompressed_content_without_filter() {
+        let data = b"Some text which compresses well. ".repeat(10);
+        let mut stream = Stream::new(dictionary! {}, data.clone());
+        stream.compress().unwrap();
+        let compressed = stream.content.clone();
+        // Removing /Filter by hand leaves the encoded data, which is then taken as is.
+        stream.dict.remove(b"Filter");
+        assert_eq!(stream.decompressed_content().unwrap(), compressed);
+        assert_eq!(stream.get_plain_content().unwrap(), compressed);
+        stream.decompress().unwrap();
+        assert_eq!(stream.content, compressed);
+    }
+
+    #[test]
+    fn decompress_prefix_of_large_stream() {
+        let data: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let mut stream = Stream::new(dictionary! {}, data.clone());
+        stream.compress().unwrap();
+        assert!(stream.content.len() < data.len() / 100);
+
+        let prefix = stream.decompress_prefix(64).unwrap();
+        assert_eq!(prefix, data[..64]);
+        // Only the prefix was inflated.
+        assert!(prefix.capacity() < 64 * 1024);
+        assert_eq!(stream.decompress_prefix(usize::MAX).unwrap(), data);
+
+        let lzw = weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+            .encode(&data)
+            .unwrap();
+        let stream = Stream::new(dictionary! { "Filter" => "LZWDecode" }, lzw);
+        assert_eq!(stream.decompress_prefix(5000).unwrap(), data[..5000]);
+        assert_eq!(stream.decompressed_content().unwrap(), data);
+    }
+
+    #[test]
+    fn decompress_prefix_with_predictor() {
+        // Rows of 4 pixels, each with the Up filter type.
+        let rows: Vec<u8> = (0..8u8).flat_map(|row| [2, row, 1, 1, 1]).collect();
+        let compressed = Stream::compress_zlib(&rows).unwrap();
+        let params = dictionary! { "Predictor" => 12, "Columns" => 4 };
+        let stream = Stream::new(
+            dictionary! { "Filter" => "FlateDecode", "DecodeParms" => params },
+            compressed,
+        );
+        let content = stream.decompressed_content().unwrap();
+        assert_eq!(content.len(), 32);
+        assert_eq!(stream.decompress_prefix(10).unwrap(), content[..10]);
+    }
+
+    #[test]
+    fn crypt_filter_is_skipped() {
+        let rows: Vec<u8> = (0..8u8).flat_map(|row| [2, row, 1, 1, 1]).collect();
+        let params = dictionary! { "Predictor" => 12, "Columns" => 4 };
+        let stream = Stream::new(
+            dictionary! {
+                "Filter" => vec!["Crypt".into(), "FlateDecode".into()],
+                "DecodeParms" => vec![Object::Null, params.clone().into()],
+            },
+            Stream::compress_zlib(&rows).unwrap(),
+        );
+        let content = stream.decompressed_content().unwrap();
+        assert_eq!(content.len(), 32);
+
+        #[cfg(feature = "encryption")]
+        {
+            let mut stream = stream;
+            assert_eq!(stream.take_crypt_filter().unwrap(), b"Identity");
+            assert_eq!(stream.dict.get(b"Filter").unwrap().as_name().unwrap(), b"FlateDecode");
+            assert_eq!(stream.dict.get(b"DecodeParms").unwrap().as_dict().unwrap(), &params);
+            assert_eq!(stream.decompressed_content().unwrap(), content);
+            assert!(stream.take_crypt_filter().is_none());
+        }
+    }
+
+    #[test]
+    // `remove_object` took a borrowed ID before `ObjectId` was a struct.
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn object_id_display_and_parse() {
+        let id = ObjectId::new(152, 0);
+        assert_eq!((id.num(), id.gen()), (152, 0));
+        assert_eq!(id.to_string(), "152 0 R");
+        assert_eq!(Error::ObjectNotFound(id).to_string(), "object 152 0 R not found");
+
+        assert_eq!("152 0".parse::<ObjectId>().unwrap(), id);
+        assert_eq!(" 152  0 R ".parse::<ObjectId>().unwrap(), id);
+        assert_eq!(id.to_string().parse::<ObjectId>().unwrap(), id);
+        for invalid in ["", "152", "152 0 X", "a 0", "152 0 R R", "152 70000"] {
+            assert!(matches!(invalid.parse::<ObjectId>(), Err(Error::InvalidObjectId(_))));
+        }
+
+        assert_eq!(ObjectId::from((152, 0)), id);
+        assert_eq!(<(u32, u16)>::from(id), (152, 0));
+        assert_eq!(id, (152, 0));
+        assert!(ObjectId(2, 0) < ObjectId(10, 0));
+        assert!(ObjectId(2, 0) < ObjectId(2, 1));
+
+        // Methods taking an object ID still accept tuples.
+        let mut doc = crate::Document::with_version("1.5");
+        let id = doc.add_object(Object::Null);
+        assert!(doc.has_object((id.0, id.1)));
+        assert_eq!(doc.get_object((id.0, 0)).unwrap(), &Object::Null);
+        doc.remove_object(&id).unwrap();
+        assert!(doc.delete_object((id.0, 0)).is_some());
+    }
+
+    #[test]
+    fn checked_conversions() {
+        let numbers = Object::Array(vec![1.into(), 2.5.into(), (-3).into()]);
+        assert_eq!(numbers.as_vec_of::<f32>().unwrap(), [1.0, 2.5, -3.0]);
+        assert!(matches!(numbers.as_vec_of::<i64>(), Err(Error::ObjectType { .. })));
+        let integers = Object::Array(vec![1.into(), 2.into()]);
+        assert_eq!(integers.as_vec_of::<i64>().unwrap(), [1, 2]);
+
+        let references = Object::Array(vec![ObjectId(3, 0).into(), ObjectId(5, 1).into()]);
+        assert_eq!(
+            references.as_vec_of::<ObjectId>().unwrap(),
+            [ObjectId(3, 0), ObjectId(5, 1)]
+        );
+        let names = Object::Array(vec!["Fit".into(), Object::string_literal("text")]);
+        assert_eq!(
+            names.as_vec_of::<Vec<u8>>().unwrap(),
+            [b"Fit".to_vec(), b"text".to_vec()]
+        );
+        assert!(Object::Array(vec![1.into()]).as_vec_of::<Vec<u8>>().is_err());
+
+        let matrix = Object::Array(vec![1.into(), 0.into(), 0.into(), 1.into(), 10.into(), 20.5.into()]);
+        assert_eq!(
+            <[f32; 6]>::try_from_object(&matrix).unwrap(),
+            [1.0, 0.0, 0.0, 1.0, 10.0, 20.5]
+        );
+        let err = <[f32; 4]>::try_from_object(&matrix).unwrap_err();
+        assert_eq!(err.to_string(), "expected array of 4 numbers, found 6 elements");
+        let pairs = Object::Array(vec![numbers.clone(), numbers]);
+        assert_eq!(<[[f32; 3]; 2]>::try_from_object(&pairs).unwrap()[1], [1.0, 2.5, -3.0]);
+    }
+
+    #[test]
+    fn media_box_length_is_checked() {
+        let page = dictionary! {
+            "Type" => "Page",
+            "MediaBox" => vec![0.into(), 0.into(), 612.into()],
+            "CropBox" => vec![0.into(), 0.into(), 612.into(), 792.5.into()],
+        };
+        assert_eq!(page.get_as::<[f32; 4]>(b"CropBox").unwrap(), [0.0, 0.0, 612.0, 792.5]);
+        let err = page.get_as::<[f32; 4]>(b"MediaBox").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ArrayLength {
+                expected: 4,
+                found: 3,
+                ..
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "expected array of 4 numbers for /MediaBox, found 3 elements"
+        );
+        assert!(matches!(page.get_as::<[f32; 4]>(b"BleedBox"), Err(Error::DictKey(_))));
+    }
+
+    #[test]
+    fn dictionary_lookup_of_exotic_keys() {
+        let long = vec![b'k'; 200];
+        let keys: Vec<Vec<u8>> = vec![vec![], long.clone(), vec![0xFF, 0x00, 0xFE], b"Type".to_vec()];
+        // Small dictionaries compare the keys, larger ones hash them.
+        for padding in [0, 20] {
+            let mut dict = super::Dictionary::new();
+            for i in 0..padding {
+                dict.set(format!("Key{i}"), i);
+            }
+            for (i, key) in keys.iter().enumerate() {
+                dict.set(key.clone(), i as i64);
+            }
+            for (i, key) in keys.iter().enumerate() {
+                assert!(dict.has(key));
+                assert_eq!(dict.get(key).unwrap().as_i64().unwrap(), i as i64);
+            }
+            *dict.get_mut(&long).unwrap() = Object::Null;
+            assert_eq!(dict.get(&long).unwrap(), &Object::Null);
+            // Keys which only differ in length or in their last byte.
+            for key in [&long[..199], b"Typ", b"Typf", &[0xFF, 0x00, 0xFF]] {
+                assert!(!dict.has(key));
+                assert!(matches!(dict.get(key), Err(Error::DictKey(_))));
+            }
+            assert!(dict.get_mut(b"Missing").is_err());
+        }
+    }
 }
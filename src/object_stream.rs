@@ -1,69 +1,508 @@
+use crate::logging::{recovered, span};
 #[cfg(feature = "nom_parser")]
 use crate::parser::{self, ParserInput};
-use crate::{Error, Object, ObjectId, Result, Stream};
-use std::collections::BTreeMap;
-use std::num::TryFromIntError;
+use crate::xref::XrefEntry;
+use crate::{Document, Error, Object, ObjectId, Result, Stream};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 
-use log::warn;
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
 
+/// Object streams whose `/Extends` chain is longer than this are taken as broken.
+pub(crate) const MAX_EXTENDS_DEPTH: usize = 32;
+
 #[derive(Debug)]
 pub struct ObjectStream {
     pub objects: BTreeMap<ObjectId, Object>,
+    /// The object stream this one extends, whose objects come after its own, see [`ObjectStream::extends`].
+    pub extends: Option<ObjectId>,
+}
+
+/// The header of an object stream, which locates its objects without parsing them.
+#[derive(Debug)]
+pub struct ObjectStreamIndex<'a> {
+    content: Cow<'a, [u8]>,
+    /// Object number and absolute start of each object, in stream order with ascending starts.
+    entries: Vec<(u32, usize)>,
+}
+
+/// Objects of object streams that were resolved on demand by [`Document::get_object`], for objects
+/// only known from a compressed cross-reference entry.
+///
+/// Resolved objects are stored in chunks that are never moved or dropped while the document is
+/// borrowed, so lookups through `&Document` can hand out references to them.
+#[derive(Debug, Default)]
+pub(crate) struct CompressedObjects {
+    /// The headers of the object streams read so far.
+    indexes: Mutex<HashMap<ObjectId, Arc<ObjectStreamIndex<'static>>>>,
+    /// The slot of each resolved object.
+    slots: Mutex<HashMap<ObjectId, usize>>,
+    /// The next free slot. Slots of objects taken out aren't reused, as they may still be in use by then.
+    next_slot: AtomicUsize,
+    /// Chunk `n` holds the `2^n` slots from `2^n - 1` on.
+    chunks: [OnceLock<Box<[OnceLock<Object>]>>; 32],
+}
+
+impl Clone for CompressedObjects {
+    /// The resolved objects aren't cloned, the clone resolves them again when needed.
+    fn clone(&self) -> Self {
+        CompressedObjects::default()
+    }
+}
+
+impl CompressedObjects {
+    /// The object `id` of the compressed cross-reference entry of `doc`, parsed out of its object
+    /// stream on the first lookup.
+    pub(crate) fn get<'a>(&'a self, doc: &Document, id: ObjectId) -> Result<&'a Object> {
+        if let Some(&slot) = self.slots.lock().unwrap().get(&id) {
+            if let Some(object) = self.slot(slot).get() {
+                return Ok(object);
+            }
+        }
+        let object = self.resolve(doc, id)?;
+        let mut slots = self.slots.lock().unwrap();
+        let slot = *slots
+            .entry(id)
+            .or_insert_with(|| self.next_slot.fetch_add(1, Ordering::Relaxed));
+        let slot = self.slot(slot);
+        // Another thread may have resolved the object in the meantime.
+        let _ = slot.set(object);
+        Ok(slot.get().unwrap())
+    }
+
+    /// Forget the object `id`, returning it if it was resolved.
+    pub(crate) fn take(&mut self, id: ObjectId) -> Option<Object> {
+        let slot = self.slots.get_mut().unwrap().remove(&id)?;
+        let (chunk, index) = Self::position(slot);
+        self.chunks[chunk].get_mut()?[index].take()
+    }
+
+    fn position(slot: usize) -> (usize, usize) {
+        let chunk = (usize::BITS - 1 - (slot + 1).leading_zeros()) as usize;
+        (chunk, slot + 1 - (1 << chunk))
+    }
+
+    fn slot(&self, slot: usize) -> &OnceLock<Object> {
+        let (chunk, index) = Self::position(slot);
+        let chunk = self.chunks[chunk].get_or_init(|| (0..1_usize << chunk).map(|_| OnceLock::new()).collect());
+        &chunk[index]
+    }
+
+    fn index(&self, doc: &Document, container: ObjectId) -> Result<Arc<ObjectStreamIndex<'static>>> {
+        if let Some(index) = self.indexes.lock().unwrap().get(&container) {
+            return Ok(index.clone());
+        }
+        let stream = doc.objects.get(&container).ok_or(Error::ObjectNotFound(container))?;
+        let index = Arc::new(ObjectStreamIndex::with_id(stream.as_stream()?, container)?.into_owned());
+        self.indexes.lock().unwrap().insert(container, index.clone());
+        Ok(index)
+    }
+
+    fn resolve(&self, doc: &Document, id: ObjectId) -> Result<Object> {
+        let Some(&XrefEntry::Compressed { container, index }) = doc.reference_table.get(id.0) else {
+            return Err(Error::ObjectNotFound(id));
+        };
+        if id.1 != 0 {
+            return Err(Error::ObjectNotFound(id));
+        }
+        // An object missing from its container is looked for in the streams it extends.
+        let mut containers = vec![ObjectId(container, 0)];
+        loop {
+            let container = containers[containers.len() - 1];
+            let object_stream = self.index(doc, container)?;
+            let index = Some(index as usize)
+                .filter(|&index| containers.len() == 1 && object_stream.id(index) == Some(id.0))
+                .or_else(|| object_stream.position(id.0));
+            if let Some(index) = index {
+                return Ok(object_stream.object(index)?.1);
+            }
+            let stream = doc.objects.get(&container).ok_or(Error::ObjectNotFound(container))?;
+            match ObjectStream::extends(stream.as_stream()?) {
+                Some(next) if !containers.contains(&next) && containers.len() < MAX_EXTENDS_DEPTH => {
+                    containers.push(next)
+                }
+                _ => return Err(Error::ObjectNotFound(id)),
+            }
+        }
+    }
 }
 
 impl ObjectStream {
     pub fn new(stream: &mut Stream) -> Result<ObjectStream> {
+        Self::read(stream, None)
+    }
+
+    /// Unpack the object stream `id`, which is named in errors and warnings about its header.
+    pub fn with_id(stream: &mut Stream, id: impl Into<ObjectId>) -> Result<ObjectStream> {
+        let id = id.into();
+        Self::read(stream, Some(id))
+    }
+
+    fn read(stream: &mut Stream, id: Option<ObjectId>) -> Result<ObjectStream> {
+        let _span = span!(DEBUG, "object_stream", id = id, bytes = stream.content.len());
+        let extends = Self::extends(stream);
+        let encoded = !stream.content.is_empty() && stream.is_compressed();
         let _ = stream.decompress();
 
         if stream.content.is_empty() {
+            // Corrupt data decodes to nothing rather than failing, which would lose the objects silently.
+            if encoded {
+                let name = id.map_or_else(|| "object stream".to_string(), |id| format!("object stream {id}"));
+                return Err(Error::InvalidObjectStream(format!(
+                    "{name}: the data couldn't be decoded"
+                )));
+            }
             return Ok(ObjectStream {
                 objects: BTreeMap::new(),
+                extends,
             });
         }
 
-        let first_offset = stream
+        let index = ObjectStreamIndex::read(stream, id)?;
+        let chunks_filter_map = |i| index.object(i).ok();
+        #[cfg(feature = "rayon")]
+        let objects = (0..index.len()).into_par_iter().filter_map(chunks_filter_map).collect();
+        #[cfg(not(feature = "rayon"))]
+        let objects = (0..index.len()).filter_map(chunks_filter_map).collect();
+
+        Ok(ObjectStream { objects, extends })
+    }
+
+    /// The object stream that `stream` extends, from its `/Extends`. An object given in both
+    /// streams of a chain is taken from the nearest one, the one extending the other.
+    pub fn extends(stream: &Stream) -> Option<ObjectId> {
+        stream.dict.get(b"Extends").and_then(Object::as_reference).ok()
+    }
+}
+
+impl<'a> ObjectStreamIndex<'a> {
+    /// Read the header of an object stream. Only the header is parsed, the objects are parsed on demand.
+    ///
+    /// A header that doesn't match `/N` or `/First` is read as far as it is consistent: entries with an
+    /// offset outside the data or not after the previous entry's offset are skipped with a warning.
+    pub fn new(stream: &'a Stream) -> Result<ObjectStreamIndex<'a>> {
+        Self::read(stream, None)
+    }
+
+    /// Read the header of the object stream `id` like [`ObjectStreamIndex::new`], naming it in errors and warnings.
+    pub fn with_id(stream: &'a Stream, id: impl Into<ObjectId>) -> Result<ObjectStreamIndex<'a>> {
+        let id = id.into();
+        Self::read(stream, Some(id))
+    }
+
+    fn read(stream: &'a Stream, id: Option<ObjectId>) -> Result<ObjectStreamIndex<'a>> {
+        let name = id.map_or_else(|| "object stream".to_string(), |id| format!("object stream {id}"));
+        let content = if stream.is_compressed() {
+            Cow::Owned(stream.decompressed_content()?)
+        } else {
+            Cow::Borrowed(stream.content.as_slice())
+        };
+
+        let first = stream
             .dict
             .get(b"First")
-            .and_then(Object::as_i64)?
-            .try_into()
-            .map_err(|e: TryFromIntError| Error::NumericCast(e.to_string()))?;
-        let index_block = stream
-            .content
-            .get(..first_offset)
-            .ok_or(Error::InvalidOffset(first_offset))?;
-
-        let numbers_str = std::str::from_utf8(index_block).map_err(|e| Error::InvalidObjectStream(e.to_string()))?;
-        let numbers: Vec<_> = numbers_str
-            .split_whitespace()
-            .map(|number| u32::from_str(number).ok())
+            .and_then(Object::as_i64)
+            .map_err(|err| Error::InvalidObjectStream(format!("{name}: invalid /First: {err}")))?;
+        let first_offset = usize::try_from(first)
+            .ok()
+            .filter(|&first| first <= content.len())
+            .ok_or_else(|| {
+                Error::InvalidObjectStream(format!(
+                    "{name}: /First {first} is outside the {} bytes of data",
+                    content.len()
+                ))
+            })?;
+
+        // The header ends at the first token which isn't a number, e.g. where a truncated header
+        // runs into the first object.
+        let numbers: Vec<usize> = content[..first_offset]
+            .split(|c| c.is_ascii_whitespace())
+            .filter(|token| !token.is_empty())
+            .map_while(|token| {
+                std::str::from_utf8(token)
+                    .ok()
+                    .and_then(|token| usize::from_str(token).ok())
+            })
             .collect();
-        let len = numbers.len() / 2 * 2; // Ensure only pairs.
+        let mut count = numbers.len() / 2;
+        match stream.dict.get(b"N").and_then(Object::as_i64) {
+            Ok(n) if usize::try_from(n).ok() == Some(count) => {}
+            Ok(n) => {
+                recovered!(id = id; "{name}: /N is {n} but the header has {count} objects");
+                count = count.min(usize::try_from(n).unwrap_or(0));
+            }
+            Err(_) => recovered!(id = id; "{name}: invalid /N, reading the {count} objects of the header"),
+        }
 
-        let n = stream.dict.get(b"N").and_then(Object::as_i64)?;
-        if numbers.len().try_into().ok() != n.checked_mul(2) {
-            warn!("object stream: the object stream dictionary specifies a wrong number of objects")
+        let mut entries: Vec<(u32, usize)> = Vec::with_capacity(count);
+        for pair in numbers.chunks_exact(2).take(count) {
+            let Ok(object_number) = u32::try_from(pair[0]) else {
+                recovered!(id = id; "{name}: skipping invalid object number {}", pair[0]);
+                continue;
+            };
+            let offset = first_offset
+                .checked_add(pair[1])
+                .filter(|&offset| offset < content.len());
+            let Some(offset) = offset else {
+                recovered!(id = id, offset = pair[1], bytes = content.len();
+                    "{name}: skipping object {object_number}, its offset {} is outside the data",
+                    pair[1]
+                );
+                continue;
+            };
+            if entries.last().is_some_and(|&(_, previous)| offset <= previous) {
+                recovered!(id = id, offset = pair[1];
+                    "{name}: skipping object {object_number}, its offset {} isn't increasing",
+                    pair[1]
+                );
+                continue;
+            }
+            entries.push((object_number, offset));
         }
 
-        let chunks_filter_map = |chunk: &[_]| {
-            let id = chunk[0]?;
-            let offset = first_offset + chunk[1]? as usize;
+        Ok(ObjectStreamIndex { content, entries })
+    }
 
-            if offset >= stream.content.len() {
-                warn!("out-of-bounds offset in object stream");
-                return None;
-            }
-            let object = parser::direct_object(ParserInput::new_extra(&stream.content[offset..], "direct object"))?;
+    /// The index with its own copy of the data of the stream.
+    pub fn into_owned(self) -> ObjectStreamIndex<'static> {
+        ObjectStreamIndex {
+            content: Cow::Owned(self.content.into_owned()),
+            entries: self.entries,
+        }
+    }
+
+    /// Number of objects located in the stream.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Object number of the object at `index`.
+    pub fn id(&self, index: usize) -> Option<u32> {
+        self.entries.get(index).map(|&(id, _)| id)
+    }
+
+    /// Position of the object with the given object number.
+    pub fn position(&self, id: u32) -> Option<usize> {
+        self.entries.iter().position(|&(entry_id, _)| entry_id == id)
+    }
+
+    /// The bytes of the object at `index`, up to the start of the next object.
+    pub fn object_bytes(&self, index: usize) -> Option<&[u8]> {
+        let (_, start) = *self.entries.get(index)?;
+        let end = self
+            .entries
+            .get(index + 1)
+            .map_or(self.content.len(), |&(_, next)| next);
+        self.content.get(start..end)
+    }
+
+    /// Parse the object at `index`, leaving all other objects of the stream untouched.
+    pub fn object(&self, index: usize) -> Result<(ObjectId, Object)> {
+        let (id, start) = *self
+            .entries
+            .get(index)
+            .ok_or_else(|| Error::InvalidObjectStream(format!("no object at index {index}")))?;
+        // The parser stops after one object, so a header with wrong offsets doesn't cut it short.
+        let bytes = &self.content[start..];
+        let object = parser::direct_object(ParserInput::new_extra(bytes, "direct object"))
+            .ok_or_else(|| Error::InvalidObjectStream(format!("object {id} could not be parsed")))?;
+        Ok((ObjectId(id, 0), object))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// The number of objects parsed out of object streams by lookups of `doc`.
+    fn parsed_objects(doc: &Document) -> usize {
+        doc.compressed_objects.slots.lock().unwrap().len()
+    }
+
+    fn object_stream(count: u32) -> Stream {
+        let bodies: Vec<_> = (1..=count).map(|id| format!("<</Value {id}>>")).collect();
+        let mut header = String::new();
+        let mut offset = 0;
+        for (id, body) in (1..=count).zip(&bodies) {
+            header += &format!("{id} {offset} ");
+            offset += body.len() + 1;
+        }
+        let content = format!("{header}{}", bodies.join("\n"));
+        Stream::new(
+            dictionary! { "Type" => "ObjStm", "N" => count, "First" => header.len() as i64 },
+            content.into_bytes(),
+        )
+    }
+
+    #[test]
+    fn index_locates_objects() {
+        let stream = object_stream(3);
+        let index = ObjectStreamIndex::new(&stream).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.position(2), Some(1));
+        assert_eq!(index.object_bytes(1).unwrap(), b"<</Value 2>>\n");
+        assert_eq!(index.object_bytes(2).unwrap(), b"<</Value 3>>");
+        assert!(index.object(3).is_err());
+
+        let mut stream = object_stream(3);
+        assert_eq!(ObjectStream::new(&mut stream).unwrap().objects.len(), 3);
+    }
+
+    fn values(stream: &Stream) -> Vec<(u32, i64)> {
+        let index = ObjectStreamIndex::with_id(stream, ObjectId(10, 0)).unwrap();
+        (0..index.len())
+            .filter_map(|i| index.object(i).ok())
+            .map(|(id, object)| (id.0, object.as_dict().unwrap().get(b"Value").unwrap().as_i64().unwrap()))
+            .collect()
+    }
 
-            Some(((id, 0), object))
+    #[test]
+    fn inconsistent_headers_keep_valid_objects() {
+        let stream = object_stream(3);
+        let first = stream.dict.get(b"First").unwrap().as_i64().unwrap() as usize;
+        let with_header = |header: &str| {
+            let mut stream = stream.clone();
+            stream.dict.set("First", header.len() as i64);
+            stream.set_plain_content([header.as_bytes(), &stream.content[first..]].concat());
+            stream
         };
-        #[cfg(feature = "rayon")]
-        let objects = numbers[..len].par_chunks(2).filter_map(chunks_filter_map).collect();
-        #[cfg(not(feature = "rayon"))]
-        let objects = numbers[..len].chunks(2).filter_map(chunks_filter_map).collect();
 
-        Ok(ObjectStream { objects })
+        // /N larger or smaller than the header.
+        let mut larger = stream.clone();
+        larger.dict.set("N", 10);
+        assert_eq!(values(&larger), [(1, 1), (2, 2), (3, 3)]);
+        let mut smaller = stream.clone();
+        smaller.dict.set("N", 2);
+        assert_eq!(values(&smaller), [(1, 1), (2, 2)]);
+        // Truncated headers.
+        assert_eq!(values(&with_header("1 0 2 13 3")), [(1, 1), (2, 2)]);
+        assert_eq!(values(&with_header("1 0 2 13 3 2x ")), [(1, 1), (2, 2)]);
+        // Offsets beyond the data or not increasing.
+        assert_eq!(values(&with_header("1 0 2 9999 3 26 ")), [(1, 1), (3, 3)]);
+        assert_eq!(values(&with_header("1 0 3 26 2 13 ")), [(1, 1), (3, 3)]);
+        // /First of 0 leaves no room for a header.
+        assert!(values(&with_header("")).is_empty());
+
+        for first in [-1, stream.content.len() as i64 + 1] {
+            let mut stream = stream.clone();
+            stream.dict.set("First", first);
+            let err = ObjectStreamIndex::with_id(&stream, ObjectId(10, 0)).unwrap_err();
+            assert!(err.to_string().contains("object stream 10 0 R"), "{err}");
+        }
+        let mut empty = Stream::new(dictionary! { "N" => 2, "First" => 0 }, vec![]);
+        assert!(ObjectStream::with_id(&mut empty, ObjectId(10, 0))
+            .unwrap()
+            .objects
+            .is_empty());
+    }
+
+    /// A document with the objects 1 to 50 only known from compressed entries of the object stream 51.
+    fn compressed_document() -> Document {
+        let mut doc = Document::with_version("1.5");
+        let container = ObjectId(51, 0);
+        let mut stream = object_stream(50);
+        stream.compress().unwrap();
+        doc.objects.insert(container, stream.into());
+        for (index, id) in (1..=50).enumerate() {
+            let entry = XrefEntry::Compressed {
+                container: container.0,
+                index: index as u16,
+            };
+            doc.reference_table.insert(id, entry);
+        }
+        doc
+    }
+
+    fn value(object: &Object) -> i64 {
+        object.as_dict().unwrap().get(b"Value").unwrap().as_i64().unwrap()
+    }
+
+    #[test]
+    fn load_single_compressed_object() {
+        let mut doc = compressed_document();
+
+        let object = doc.get_object(ObjectId(37, 0)).unwrap();
+        assert_eq!(object.as_dict().unwrap().get(b"Value").unwrap().as_i64().unwrap(), 37);
+        assert_eq!(parsed_objects(&doc), 1);
+        assert_eq!(doc.compressed_objects.indexes.lock().unwrap().len(), 1);
+        assert_eq!(doc.objects.len(), 1);
+
+        // The object is cached, so it isn't parsed again, and references to it are followed.
+        let reference = doc.add_object(ObjectId(37, 0));
+        assert!(doc.get_object(reference).unwrap().as_dict().is_ok());
+        assert_eq!(parsed_objects(&doc), 1);
+        assert!(doc.get_object(ObjectId(52, 0)).is_err());
+
+        // Getting it mutably keeps it in the objects.
+        doc.get_dictionary_mut(ObjectId(37, 0)).unwrap().set("Value", 0);
+        assert_eq!(
+            doc.get_dictionary(ObjectId(37, 0))
+                .unwrap()
+                .get(b"Value")
+                .unwrap()
+                .as_i64()
+                .unwrap(),
+            0
+        );
+        assert!(doc.objects.contains_key(&ObjectId(37, 0)));
+        assert_eq!(
+            doc.load_compressed_object(ObjectId(38, 0))
+                .unwrap()
+                .as_dict()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(doc.objects.contains_key(&ObjectId(38, 0)));
+    }
+
+    #[test]
+    fn objects_resolved_after_loading_one_get_their_own_slot() {
+        let mut doc = compressed_document();
+        assert_eq!(value(doc.get_object(ObjectId(1, 0)).unwrap()), 1);
+        assert_eq!(value(doc.get_object(ObjectId(2, 0)).unwrap()), 2);
+        doc.load_compressed_object(ObjectId(1, 0)).unwrap();
+
+        assert_eq!(value(doc.get_object(ObjectId(3, 0)).unwrap()), 3);
+        assert_eq!(value(doc.get_object(ObjectId(2, 0)).unwrap()), 2);
+        assert_eq!(value(doc.get_object(ObjectId(1, 0)).unwrap()), 1);
+    }
+
+    #[test]
+    fn load_compressed_object_of_extended_stream() {
+        let mut doc = Document::with_version("1.5");
+        let extends = |mut stream: Stream, id: u32| {
+            stream.dict.set("Extends", ObjectId(id, 0));
+            Object::Stream(stream)
+        };
+        // Object 3 is named in the first stream of the chain 30, 10, 20, but only the last holds it.
+        doc.objects.insert(ObjectId(30, 0), extends(object_stream(1), 10));
+        doc.objects.insert(ObjectId(10, 0), extends(object_stream(2), 20));
+        doc.objects.insert(ObjectId(20, 0), object_stream(3).into());
+        for id in [3, 4] {
+            doc.reference_table.insert(
+                id,
+                XrefEntry::Compressed {
+                    container: 30,
+                    index: 0,
+                },
+            );
+        }
+
+        let object = doc.get_object(ObjectId(3, 0)).unwrap();
+        assert_eq!(object.as_dict().unwrap().get(b"Value").unwrap().as_i64().unwrap(), 3);
+        assert!(doc.get_object(ObjectId(4, 0)).is_err());
+
+        // A cyclic chain ends the search.
+        doc.objects.insert(ObjectId(20, 0), extends(object_stream(3), 30));
+        assert!(doc.load_compressed_object(ObjectId(4, 0)).is_err());
     }
 }
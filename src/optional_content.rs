@@ -0,0 +1,191 @@
+use crate::{Dictionary, Document, Object, ObjectId};
+use std::collections::BTreeMap;
+
+/// Which optional content groups (layers) are shown when extracting content.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LayerVisibility {
+    /// Show the layers which are on in the default configuration, the `/D` entry of `/OCProperties`.
+    UseDefaultConfig,
+    /// Show all content, ignoring optional content.
+    #[default]
+    AllVisible,
+    /// Show the optional content groups mapped to `true`. Groups not in the map follow the default configuration.
+    Custom(BTreeMap<ObjectId, bool>),
+}
+
+/// Visibility expressions nested deeper than this are ignored.
+const MAX_EXPRESSION_DEPTH: usize = 32;
+
+/// The visibility of each optional content group, resolved from a [`LayerVisibility`].
+pub(crate) struct LayerState {
+    /// `None` if everything is visible.
+    groups: Option<BTreeMap<ObjectId, bool>>,
+}
+
+impl LayerState {
+    pub(crate) fn new(doc: &Document, visibility: &LayerVisibility) -> LayerState {
+        let custom = match visibility {
+            LayerVisibility::AllVisible => return LayerState { groups: None },
+            LayerVisibility::UseDefaultConfig => None,
+            LayerVisibility::Custom(groups) => Some(groups),
+        };
+        let mut groups = BTreeMap::new();
+        let properties = doc
+            .catalog()
+            .and_then(|catalog| doc.get_dict_in_dict(catalog, b"OCProperties"));
+        if let Ok(properties) = properties {
+            let config = doc.get_dict_in_dict(properties, b"D").ok();
+            let base_state = config.and_then(|config| config.get(b"BaseState").and_then(Object::as_name).ok());
+            let base_visible = base_state != Some(b"OFF");
+            for id in group_ids(doc, properties.get(b"OCGs").ok()) {
+                groups.insert(id, base_visible);
+            }
+            if let Some(config) = config {
+                for id in group_ids(doc, config.get(b"ON").ok()) {
+                    groups.insert(id, true);
+                }
+                for id in group_ids(doc, config.get(b"OFF").ok()) {
+                    groups.insert(id, false);
+                }
+            }
+        }
+        if let Some(custom) = custom {
+            groups.extend(custom);
+        }
+        LayerState { groups: Some(groups) }
+    }
+
+    /// Whether content belonging to an optional content group or membership dictionary is visible.
+    ///
+    /// Anything that isn't a group or membership dictionary doesn't hide content.
+    pub(crate) fn is_visible(&self, doc: &Document, membership: &Object) -> bool {
+        let Some(groups) = &self.groups else {
+            return true;
+        };
+        let (id, object) = match membership {
+            Object::Reference(id) => match doc.get_object(*id) {
+                Ok(object) => (Some(*id), object),
+                Err(_) => return true,
+            },
+            object => (None, object),
+        };
+        let Ok(dict) = object.as_dict() else {
+            return true;
+        };
+        match dict.get(b"Type").and_then(Object::as_name) {
+            Ok(b"OCG") => id.map_or(true, |id| group_visible(groups, id)),
+            Ok(b"OCMD") => membership_visible(doc, groups, dict),
+            _ => true,
+        }
+    }
+}
+
+fn group_visible(groups: &BTreeMap<ObjectId, bool>, id: ObjectId) -> bool {
+    groups.get(&id).copied().unwrap_or(true)
+}
+
+/// The references of an array of groups.
+fn group_ids(doc: &Document, groups: Option<&Object>) -> Vec<ObjectId> {
+    match groups.and_then(|groups| doc.dereference(groups).ok()) {
+        Some((_, Object::Array(items))) => items.iter().filter_map(|item| item.as_reference().ok()).collect(),
+        _ => vec![],
+    }
+}
+
+/// Visibility of an optional content membership dictionary, from its `/VE` expression if it has
+/// one, otherwise from its `/OCGs` and `/P` policy.
+fn membership_visible(doc: &Document, groups: &BTreeMap<ObjectId, bool>, membership: &Dictionary) -> bool {
+    if let Ok(expression) = membership.get(b"VE") {
+        if let Some(visible) = expression_visible(doc, groups, expression, 0) {
+            return visible;
+        }
+    }
+    let members: Vec<bool> = match membership.get(b"OCGs") {
+        Ok(Object::Reference(id)) => vec![group_visible(groups, *id)],
+        Ok(members) => group_ids(doc, Some(members))
+            .into_iter()
+            .map(|id| group_visible(groups, id))
+            .collect(),
+        Err(_) => vec![],
+    };
+    if members.is_empty() {
+        return true;
+    }
+    match membership.get(b"P").and_then(Object::as_name) {
+        Ok(b"AllOn") => members.iter().all(|&visible| visible),
+        Ok(b"AnyOff") => members.iter().any(|&visible| !visible),
+        Ok(b"AllOff") => members.iter().all(|&visible| !visible),
+        _ => members.iter().any(|&visible| visible),
+    }
+}
+
+/// Evaluate a visibility expression such as `[/And 1 0 R [/Not 2 0 R]]`, `None` if it is invalid.
+fn expression_visible(
+    doc: &Document, groups: &BTreeMap<ObjectId, bool>, expression: &Object, depth: usize,
+) -> Option<bool> {
+    if depth > MAX_EXPRESSION_DEPTH {
+        return None;
+    }
+    let expression = match expression {
+        Object::Reference(id) => match doc.get_object(*id).ok()? {
+            Object::Array(items) => items,
+            _ => return Some(group_visible(groups, *id)),
+        },
+        Object::Array(items) => items,
+        _ => return None,
+    };
+    let (operator, operands) = expression.split_first()?;
+    let mut operands = operands
+        .iter()
+        .map(|operand| expression_visible(doc, groups, operand, depth + 1));
+    match operator.as_name().ok()? {
+        b"And" => operands.try_fold(true, |all, visible| Some(all && visible?)),
+        b"Or" => operands.try_fold(false, |any, visible| Some(any || visible?)),
+        b"Not" => match (operands.next(), operands.next()) {
+            (Some(visible), None) => visible.map(|visible| !visible),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visibility_expressions() {
+        let mut doc = Document::with_version("1.5");
+        let on_id = doc.add_object(dictionary! { "Type" => "OCG" });
+        let off_id = doc.add_object(dictionary! { "Type" => "OCG" });
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "OCProperties" => dictionary! {
+                "OCGs" => vec![on_id.into(), off_id.into()],
+                "D" => dictionary! { "BaseState" => "OFF", "ON" => vec![on_id.into()] },
+            },
+        });
+        doc.trailer.set("Root", catalog_id);
+        let layers = LayerState::new(&doc, &LayerVisibility::UseDefaultConfig);
+        let membership = |entries: Dictionary| {
+            let mut dict = dictionary! { "Type" => "OCMD" };
+            dict.extend(&entries);
+            Object::Dictionary(dict)
+        };
+
+        assert!(layers.is_visible(&doc, &on_id.into()));
+        assert!(!layers.is_visible(&doc, &off_id.into()));
+        let not_off = vec!["Not".into(), off_id.into()];
+        let ve = membership(dictionary! { "VE" => vec!["And".into(), on_id.into(), not_off.into()] });
+        assert!(layers.is_visible(&doc, &ve));
+        let ve = membership(dictionary! { "VE" => vec!["And".into(), on_id.into(), off_id.into()] });
+        assert!(!layers.is_visible(&doc, &ve));
+        let any_off = membership(dictionary! { "OCGs" => vec![on_id.into(), off_id.into()], "P" => "AnyOff" });
+        assert!(layers.is_visible(&doc, &any_off));
+        let any_on = membership(dictionary! { "OCGs" => off_id });
+        assert!(!layers.is_visible(&doc, &any_on));
+
+        let layers = LayerState::new(&doc, &LayerVisibility::AllVisible);
+        assert!(layers.is_visible(&doc, &off_id.into()));
+    }
+}
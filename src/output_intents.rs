@@ -0,0 +1,227 @@
+use crate::{decode_text_string, text_string, Document, Error, Object, ObjectId, Result, Stream};
+
+/// A compact version 2 ICC profile of the sRGB IEC61966-2.1 color space, see
+/// [`Document::add_srgb_output_intent`].
+#[cfg(feature = "srgb_profile")]
+pub const SRGB_ICC_PROFILE: &[u8] = include_bytes!("../assets/sRGB-v2.icc");
+
+/// The subtype `/S` of an output intent, which names the standard the intent is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputIntentSubtype {
+    /// `/GTS_PDFA1`, the output intent of PDF/A files.
+    PdfA,
+    /// `/ISO_PDF`, an output intent which isn't bound to a standard, added in PDF 2.0.
+    IsoPdf,
+}
+
+impl OutputIntentSubtype {
+    /// The name of the subtype for the `/S` entry of an output intent.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputIntentSubtype::PdfA => "GTS_PDFA1",
+            OutputIntentSubtype::IsoPdf => "ISO_PDF",
+        }
+    }
+}
+
+/// An output intent of the catalog `/OutputIntents`, see [`Document::get_output_intents`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputIntent {
+    /// The subtype `/S`, e.g. `GTS_PDFA1`.
+    pub subtype: Vec<u8>,
+    /// The `/OutputConditionIdentifier`, naming the output condition in a registry or the profile.
+    pub condition_identifier: Option<String>,
+    /// The human readable description `/Info`.
+    pub info: Option<String>,
+    /// The ICC profile stream `/DestOutputProfile`.
+    pub profile: Option<ObjectId>,
+    /// The number of color components `/N` of the profile.
+    pub components: Option<i64>,
+}
+
+impl Document {
+    /// Get the output intents of the catalog `/OutputIntents`, skipping entries which aren't dictionaries.
+    pub fn get_output_intents(&self) -> Result<Vec<OutputIntent>> {
+        let Ok(intents) = self.catalog()?.get_deref(b"OutputIntents", self) else {
+            return Ok(vec![]);
+        };
+        let intents = intents
+            .as_array()?
+            .iter()
+            .filter_map(|intent| self.dereference(intent).ok()?.1.as_dict().ok())
+            .map(|intent| {
+                let text = |key: &[u8]| intent.get(key).ok().and_then(|value| decode_text_string(value).ok());
+                let profile = intent.get(b"DestOutputProfile").and_then(Object::as_reference).ok();
+                OutputIntent {
+                    subtype: intent.get(b"S").and_then(Object::as_name).unwrap_or_default().to_vec(),
+                    condition_identifier: text(b"OutputConditionIdentifier"),
+                    info: text(b"Info"),
+                    profile,
+                    components: profile
+                        .and_then(|id| self.get_object(id).and_then(Object::as_stream).ok())
+                        .and_then(|stream| stream.dict.get(b"N").and_then(Object::as_i64).ok()),
+                }
+            })
+            .collect();
+        Ok(intents)
+    }
+
+    /// Tag the document with the sRGB color space, adding an output intent of `subtype` with an
+    /// embedded sRGB ICC profile, see [`Document::add_output_intent`].
+    #[cfg(feature = "srgb_profile")]
+    pub fn add_srgb_output_intent(&mut self, subtype: OutputIntentSubtype) -> Result<ObjectId> {
+        self.add_output_intent(subtype, SRGB_ICC_PROFILE, 3, "sRGB IEC61966-2.1")
+    }
+
+    /// Add an output intent of `subtype` to the catalog `/OutputIntents`, with the ICC `profile`
+    /// of `components` color components, described by `condition`. Returns the ID of the profile stream.
+    ///
+    /// A stream holding the same profile is reused rather than embedding it again, and an output
+    /// intent of the same subtype with that profile isn't added twice. `/ISO_PDF` output intents
+    /// fail with [`Error::UnsupportedVersion`] for documents whose version is below 2.0.
+    pub fn add_output_intent(
+        &mut self, subtype: OutputIntentSubtype, profile: &[u8], components: i64, condition: &str,
+    ) -> Result<ObjectId> {
+        if subtype == OutputIntentSubtype::IsoPdf && !self.is_version_at_least((2, 0)) {
+            return Err(Error::UnsupportedVersion {
+                feature: "ISO_PDF output intents",
+                required: "2.0",
+                version: self.version.clone(),
+            });
+        }
+        let profile_id = self.icc_profile_stream(profile, components);
+        let existing = self.get_output_intents()?;
+        if existing
+            .iter()
+            .any(|intent| intent.subtype == subtype.name().as_bytes() && intent.profile == Some(profile_id))
+        {
+            return Ok(profile_id);
+        }
+
+        let intent = dictionary! {
+            "Type" => "OutputIntent",
+            "S" => subtype.name(),
+            "OutputConditionIdentifier" => text_string(condition),
+            "Info" => text_string(condition),
+            "DestOutputProfile" => profile_id,
+        };
+        let catalog = self.catalog_mut()?;
+        match catalog.get(b"OutputIntents") {
+            Ok(Object::Reference(id)) => {
+                let id = *id;
+                self.get_object_mut(id)?.as_array_mut()?.push(intent.into());
+            }
+            Ok(Object::Array(_)) => catalog.get_mut(b"OutputIntents")?.as_array_mut()?.push(intent.into()),
+            _ => catalog.set("OutputIntents", vec![intent.into()]),
+        }
+        Ok(profile_id)
+    }
+
+    /// Get the ID of a stream holding the ICC `profile`, adding one if there is none.
+    fn icc_profile_stream(&mut self, profile: &[u8], components: i64) -> ObjectId {
+        let existing = self.objects.iter().find_map(|(&id, object)| {
+            let stream = object.as_stream().ok()?;
+            if stream.dict.get(b"N").and_then(Object::as_i64).ok() != Some(components) {
+                return None;
+            }
+            let same = if stream.is_compressed() {
+                stream.decompressed_content().is_ok_and(|content| content == profile)
+            } else {
+                stream.content == profile
+            };
+            same.then_some(id)
+        });
+        existing.unwrap_or_else(|| self.add_object(Stream::new(dictionary! { "N" => components }, profile.to_vec())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+
+    #[test]
+    fn output_intents_share_profiles() {
+        let mut doc = create_document();
+        assert!(doc.get_output_intents().unwrap().is_empty());
+        let profile = b"not really an ICC profile".to_vec();
+        let mut existing = Stream::new(dictionary! { "N" => 3 }, profile.clone());
+        existing.compress().unwrap();
+        let existing_id = doc.add_object(existing);
+
+        let profile_id = doc
+            .add_output_intent(OutputIntentSubtype::PdfA, &profile, 3, "Custom RGB")
+            .unwrap();
+        assert_eq!(profile_id, existing_id);
+        let objects = doc.objects.len();
+        assert_eq!(
+            doc.add_output_intent(OutputIntentSubtype::PdfA, &profile, 3, "Custom RGB")
+                .unwrap(),
+            profile_id
+        );
+        assert_eq!(doc.objects.len(), objects);
+
+        let intents = doc.get_output_intents().unwrap();
+        assert_eq!(
+            intents,
+            [OutputIntent {
+                subtype: b"GTS_PDFA1".to_vec(),
+                condition_identifier: Some("Custom RGB".to_string()),
+                info: Some("Custom RGB".to_string()),
+                profile: Some(profile_id),
+                components: Some(3),
+            }]
+        );
+        let intent = doc
+            .catalog()
+            .unwrap()
+            .get(b"OutputIntents")
+            .unwrap()
+            .as_array()
+            .unwrap()[0]
+            .as_dict()
+            .unwrap();
+        assert!(intent.has_type(b"OutputIntent"));
+
+        // Output intents not bound to a standard need PDF 2.0.
+        assert!(matches!(
+            doc.add_output_intent(OutputIntentSubtype::IsoPdf, &profile, 3, "Custom RGB"),
+            Err(Error::UnsupportedVersion { .. })
+        ));
+        doc.version = "2.0".to_string();
+        doc.add_output_intent(OutputIntentSubtype::IsoPdf, &profile, 3, "Custom RGB")
+            .unwrap();
+        let intents = doc.get_output_intents().unwrap();
+        assert_eq!(intents.len(), 2);
+        assert_eq!(intents[1].subtype, b"ISO_PDF");
+        assert_eq!(intents[1].profile, Some(profile_id));
+    }
+
+    #[cfg(feature = "srgb_profile")]
+    #[test]
+    fn srgb_output_intent() {
+        let mut doc = create_document();
+        let profile_id = doc.add_srgb_output_intent(OutputIntentSubtype::PdfA).unwrap();
+        assert_eq!(
+            doc.add_srgb_output_intent(OutputIntentSubtype::PdfA).unwrap(),
+            profile_id
+        );
+
+        let profile = doc.get_object(profile_id).and_then(Object::as_stream).unwrap();
+        assert_eq!(profile.content, SRGB_ICC_PROFILE);
+        // An ICC version 2 display profile of RGB data, whose size is given in its header.
+        assert_eq!(&SRGB_ICC_PROFILE[36..40], b"acsp");
+        assert_eq!(SRGB_ICC_PROFILE[8], 2);
+        assert_eq!(&SRGB_ICC_PROFILE[12..20], b"mntrRGB ");
+        assert_eq!(
+            u32::from_be_bytes(SRGB_ICC_PROFILE[..4].try_into().unwrap()) as usize,
+            SRGB_ICC_PROFILE.len()
+        );
+
+        let intents = doc.get_output_intents().unwrap();
+        assert_eq!(intents.len(), 1);
+        assert_eq!(intents[0].subtype, b"GTS_PDFA1");
+        assert_eq!(intents[0].condition_identifier.as_deref(), Some("sRGB IEC61966-2.1"));
+        assert_eq!(intents[0].components, Some(3));
+    }
+}
@@ -0,0 +1,304 @@
+use crate::content::{Content, Operation};
+use crate::{Dictionary, Document, Error, Object, ObjectId, Result, Stream};
+use std::collections::HashMap;
+
+/// Where [`Document::apply_page_overlay`] paints the template page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayMode {
+    /// Under the existing content of the page, e.g. a letterhead.
+    Background,
+    /// Over the existing content of the page, e.g. a stamp.
+    Overlay,
+}
+
+impl Document {
+    /// Paint a page of `template` under or over pages of this document.
+    ///
+    /// The template page is imported once as a Form XObject shared by the pages, which are given
+    /// by number and default to all of them. On each page it is scaled to fit the crop box,
+    /// preserving its aspect ratio, and centered. Both the template and the existing content are
+    /// wrapped in `q`/`Q`, so neither leaks graphics state into the other.
+    ///
+    /// Fails with [`Error::EmptyMediaBox`] if the media box of the template page has no area.
+    pub fn apply_page_overlay(
+        &mut self, template: &Document, template_page: impl Into<ObjectId>, mode: OverlayMode, pages: Option<&[u32]>,
+    ) -> Result<()> {
+        let template_page = template_page.into();
+        let [tx0, ty0, tx1, ty1] = template.page(template_page)?.media_box()?;
+        let (template_width, template_height) = (tx1 - tx0, ty1 - ty0);
+        if !(template_width > 0.0 && template_height > 0.0) {
+            return Err(Error::EmptyMediaBox(template_page));
+        }
+        let mut form = Object::Stream(template.page_to_xobject(template_page)?);
+        self.import_references(template, &mut form);
+        let form_id = self.add_object(form);
+
+        let targets: Vec<ObjectId> = match pages {
+            Some(numbers) => {
+                let all = self.get_pages();
+                numbers.iter().filter_map(|number| all.get(number).copied()).collect()
+            }
+            None => self.page_iter().collect(),
+        };
+        // Saves the graphics state before the existing content for an overlay, restores it after for a background.
+        let wrap_id = self.add_object(Stream::new(
+            Dictionary::new(),
+            match mode {
+                OverlayMode::Background => b"\nQ".to_vec(),
+                OverlayMode::Overlay => b"q\n".to_vec(),
+            },
+        ));
+        for page_id in targets {
+            let [x0, y0, x1, y1] = self.page(page_id)?.crop_box()?;
+            let (width, height) = (x1 - x0, y1 - y0);
+            let scale = (width / template_width).min(height / template_height);
+            let dx = x0 + (width - template_width * scale) / 2.0 - tx0 * scale;
+            let dy = y0 + (height - template_height * scale) / 2.0 - ty0 * scale;
+
+            let name = self.ensure_resource(page_id, b"XObject", form_id.into())?;
+
+            let mut operations = vec![];
+            if mode == OverlayMode::Overlay {
+                operations.push(Operation::new("Q", vec![]));
+            }
+            operations.extend([
+                Operation::new("q", vec![]),
+                Operation::new(
+                    "cm",
+                    vec![scale.into(), 0.into(), 0.into(), scale.into(), dx.into(), dy.into()],
+                ),
+                Operation::new("Do", vec![Object::Name(name)]),
+                Operation::new("Q", vec![]),
+            ]);
+            if mode == OverlayMode::Background {
+                operations.push(Operation::new("q", vec![]));
+            }
+            let stamp = Content { operations }.encode()?;
+            let stamp_id = self.add_object(Stream::new(Dictionary::new(), stamp));
+
+            let mut contents: Vec<Object> = self.get_page_contents(page_id).into_iter().map(Object::from).collect();
+            match mode {
+                OverlayMode::Background => {
+                    contents.insert(0, stamp_id.into());
+                    contents.push(wrap_id.into());
+                }
+                OverlayMode::Overlay => {
+                    contents.insert(0, wrap_id.into());
+                    contents.push(stamp_id.into());
+                }
+            }
+            self.get_dictionary_mut(page_id)?.set("Contents", contents);
+            self.touch_piece_info(page_id);
+        }
+        Ok(())
+    }
+
+    /// Turn a page into a Form XObject painting its content, with the media box as bounding box.
+    ///
+    /// The resources of the form are those of the page, still referring to objects of this document.
+    fn page_to_xobject(&self, page_id: ObjectId) -> Result<Stream> {
+        let page = self.page(page_id)?;
+        let media_box = page.media_box()?;
+        let mut dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => media_box.iter().map(|&value| Object::Real(value)).collect::<Vec<_>>(),
+            "Resources" => page.resources().cloned().unwrap_or_default(),
+        };
+        if let Some(group) = page.group() {
+            dict.set("Group", group.clone());
+        }
+        let mut stream = Stream::new(dict, self.get_page_content(page_id)?);
+        let _ = stream.compress();
+        Ok(stream)
+    }
+
+    /// Copy the objects `object` refers to from `source` into this document, and those they refer
+    /// to in turn, rewriting the references to the new IDs. Each object is copied once.
+    ///
+    /// References to pages and page tree nodes become null, so the page tree of `source` isn't copied along.
+    fn import_references(&mut self, source: &Document, object: &mut Object) {
+        let mut imported = HashMap::new();
+        let mut pending = vec![];
+        self.rewrite_imported_references(source, object, &mut imported, &mut pending);
+        while let Some((id, new_id)) = pending.pop() {
+            let mut copy = source.objects[&id].clone();
+            self.rewrite_imported_references(source, &mut copy, &mut imported, &mut pending);
+            self.set_object(new_id, copy);
+        }
+    }
+
+    /// Rewrite the references in `object` to the IDs of the objects copied from `source`, giving
+    /// a new ID to those not met before and adding them to `pending`.
+    fn rewrite_imported_references(
+        &mut self, source: &Document, object: &mut Object, imported: &mut HashMap<ObjectId, ObjectId>,
+        pending: &mut Vec<(ObjectId, ObjectId)>,
+    ) {
+        let mut objects = vec![object];
+        while let Some(object) = objects.pop() {
+            match object {
+                Object::Reference(id) => {
+                    if let Some(new_id) = imported.get(id) {
+                        *object = Object::Reference(*new_id);
+                        continue;
+                    }
+                    let is_page = |referenced: &Object| {
+                        referenced
+                            .as_dict()
+                            .and_then(|dict| dict.get(b"Type"))
+                            .and_then(Object::as_name)
+                            .is_ok_and(|kind| kind == b"Page" || kind == b"Pages")
+                    };
+                    if !source.objects.get(id).is_some_and(|referenced| !is_page(referenced)) {
+                        *object = Object::Null;
+                        continue;
+                    }
+                    let new_id = self.new_object_id();
+                    imported.insert(*id, new_id);
+                    pending.push((*id, new_id));
+                    *object = Object::Reference(new_id);
+                }
+                Object::Array(items) => objects.extend(items.iter_mut()),
+                Object::Dictionary(dict) | Object::Stream(Stream { dict, .. }) => {
+                    objects.extend(dict.iter_mut().map(|(_, value)| value))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::{create_document, create_document_with_texts};
+
+    #[test]
+    fn overlay_template_on_every_page() {
+        let mut template = create_document();
+        let template_page = template.page_iter().next().unwrap();
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let pages: Vec<ObjectId> = doc.page_iter().collect();
+        // The first page already has an XObject named like the one the overlay would get.
+        let image_id = doc.add_object(Stream::new(dictionary! {}, vec![]));
+        doc.get_dictionary_mut(pages[0]).unwrap().set(
+            "Resources",
+            dictionary! { "XObject" => dictionary! { "X1" => image_id } },
+        );
+        template.compress();
+
+        doc.apply_page_overlay(&template, template_page, OverlayMode::Overlay, None)
+            .unwrap();
+
+        let mut forms = vec![];
+        for &page_id in &pages {
+            let content = doc.get_and_decode_page_content(page_id).unwrap();
+            let names: Vec<&[u8]> = content
+                .operations
+                .iter()
+                .filter(|operation| operation.operator == "Do")
+                .map(|operation| operation.operands[0].as_name().unwrap())
+                .collect();
+            assert_eq!(names.len(), 1);
+            assert_eq!(content.operations.first().unwrap().operator, "q");
+            assert_eq!(content.operations.last().unwrap().operator, "Q");
+            let resources = doc.page(page_id).unwrap().resources().unwrap();
+            let xobjects = doc.get_dict_in_dict(resources, b"XObject").unwrap();
+            forms.push(xobjects.get(names[0]).unwrap().as_reference().unwrap());
+        }
+        assert_eq!(forms[0], forms[1]);
+        assert!(!doc.get_page_fonts(pages[1]).unwrap().is_empty());
+
+        let form = doc.get_object(forms[0]).and_then(Object::as_stream).unwrap();
+        assert_eq!(form.dict.get(b"Subtype").unwrap().as_name().unwrap(), b"Form");
+        let font_ref = doc
+            .get_dict_in_dict(&form.dict, b"Resources")
+            .and_then(|resources| doc.get_dict_in_dict(resources, b"Font"))
+            .and_then(|fonts| fonts.get(b"F1"))
+            .and_then(Object::as_reference)
+            .unwrap();
+        assert_eq!(
+            doc.get_dictionary(font_ref)
+                .unwrap()
+                .get(b"BaseFont")
+                .unwrap()
+                .as_name()
+                .unwrap(),
+            b"Courier"
+        );
+        let text = form.decompressed_content().unwrap();
+        assert!(String::from_utf8_lossy(&text).contains("Hello World!"));
+    }
+
+    #[test]
+    fn background_is_scaled_and_centered() {
+        let template = create_document();
+        let template_page = template.page_iter().next().unwrap();
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let pages: Vec<ObjectId> = doc.page_iter().collect();
+        // A landscape page twice the width of the template.
+        doc.get_dictionary_mut(pages[1])
+            .unwrap()
+            .set("MediaBox", vec![0.into(), 0.into(), 1190.into(), 421.into()]);
+
+        doc.apply_page_overlay(&template, template_page, OverlayMode::Background, Some(&[2]))
+            .unwrap();
+
+        let untouched = doc.get_and_decode_page_content(pages[0]).unwrap();
+        assert!(untouched.operations.iter().all(|operation| operation.operator != "Do"));
+        let content = doc.get_and_decode_page_content(pages[1]).unwrap();
+        let operators: Vec<&str> = content.operations.iter().map(|op| op.operator.as_str()).collect();
+        assert_eq!(&operators[..5], ["q", "cm", "Do", "Q", "q"]);
+        assert_eq!(operators.last(), Some(&"Q"));
+        let matrix: Vec<f32> = content.operations[1]
+            .operands
+            .iter()
+            .map(|operand| operand.as_float().unwrap())
+            .collect();
+        assert_eq!(matrix, [0.5, 0.0, 0.0, 0.5, 446.25, 0.0]);
+    }
+
+    #[test]
+    fn template_without_area_is_rejected() {
+        let mut template = create_document();
+        let template_page = template.page_iter().next().unwrap();
+        template
+            .get_dictionary_mut(template_page)
+            .unwrap()
+            .set("MediaBox", vec![0.into(), 0.into(), 0.into(), 842.into()]);
+        let mut doc = create_document();
+        let object_count = doc.objects.len();
+
+        let result = doc.apply_page_overlay(&template, template_page, OverlayMode::Overlay, None);
+        assert!(matches!(result, Err(Error::EmptyMediaBox(id)) if id == template_page));
+        assert_eq!(doc.objects.len(), object_count);
+    }
+
+    #[test]
+    fn import_long_reference_chain() {
+        let mut template = create_document();
+        let template_page = template.page_iter().next().unwrap();
+        // A chain of objects, each referring to the next one and back to the first.
+        let first_id = template.new_object_id();
+        let mut next: Object = Object::Null;
+        for _ in 0..100_000 {
+            next = template
+                .add_object(dictionary! { "Next" => next, "First" => first_id })
+                .into();
+        }
+        template.set_object(first_id, dictionary! { "Next" => next });
+        let resources = template.page(template_page).unwrap().resources_id().unwrap();
+        template
+            .get_dictionary_mut(resources)
+            .unwrap()
+            .set("Properties", dictionary! { "P1" => first_id });
+        let mut doc = create_document();
+        let object_count = doc.objects.len();
+
+        doc.apply_page_overlay(&template, template_page, OverlayMode::Overlay, None)
+            .unwrap();
+        // The chain and the resources, font, form and content streams of the overlay.
+        assert!(doc.objects.len() > object_count + 100_001);
+        assert!(doc.objects.len() < object_count + 100_010);
+    }
+}
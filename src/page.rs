@@ -0,0 +1,487 @@
+use crate::{Dictionary, Document, Error, Object, ObjectId, Result, Stream, TryFromObject};
+use log::warn;
+use std::collections::HashSet;
+
+/// Attributes a page inherits from the nodes of the page tree above it, unless it sets them itself.
+pub(crate) const INHERITABLE: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// The clockwise rotation of a page when displayed, see [`PageView::rotate`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    #[default]
+    R0,
+    R90,
+    R180,
+    R270,
+}
+
+impl Rotation {
+    /// Normalize an angle in degrees: it is reduced modulo 360, then rounded to the nearest multiple
+    /// of 90, halfway values rounding up. E.g. -90 and 450 give `R270` and `R90`, 45 gives `R90`.
+    pub fn from_degrees(degrees: f32) -> Rotation {
+        let quarter_turns = (degrees.rem_euclid(360.0) / 90.0).round();
+        match quarter_turns as i64 {
+            1 => Rotation::R90,
+            2 => Rotation::R180,
+            3 => Rotation::R270,
+            _ => Rotation::R0,
+        }
+    }
+
+    /// The angle in degrees, the canonical value of `/Rotate`.
+    pub fn degrees(self) -> i64 {
+        match self {
+            Rotation::R0 => 0,
+            Rotation::R90 => 90,
+            Rotation::R180 => 180,
+            Rotation::R270 => 270,
+        }
+    }
+
+    /// Whether width and height are swapped when the page is displayed.
+    pub fn is_quarter_turn(self) -> bool {
+        matches!(self, Rotation::R90 | Rotation::R270)
+    }
+}
+
+/// A typed, read-only view of a page dictionary, see [`Document::page`].
+///
+/// Inheritable attributes are looked up in the page tree nodes above the page when the page doesn't
+/// set them itself.
+#[derive(Debug, Clone, Copy)]
+pub struct PageView<'a> {
+    doc: &'a Document,
+    id: ObjectId,
+    dict: &'a Dictionary,
+}
+
+impl Document {
+    /// Get a typed view of the page `page_id`.
+    pub fn page(&self, page_id: impl Into<ObjectId>) -> Result<PageView<'_>> {
+        let page_id = page_id.into();
+        Ok(PageView {
+            doc: self,
+            id: page_id,
+            dict: self.get_dictionary(page_id)?,
+        })
+    }
+
+    /// Set the size of default user space units of a page, in multiples of 1/72 inch, see
+    /// [`PageView::user_unit`]. The unit must be at least 1, and a unit of 1 removes `/UserUnit`.
+    ///
+    /// User units were added in PDF 1.6, the version of the document isn't changed.
+    pub fn set_user_unit(&mut self, page_id: impl Into<ObjectId>, user_unit: f32) -> Result<()> {
+        let page_id = page_id.into();
+        if !(user_unit >= 1.0 && user_unit.is_finite()) {
+            return Err(Error::InvalidUserUnit(user_unit));
+        }
+        let page = self.get_dictionary_mut(page_id)?;
+        if user_unit == 1.0 {
+            page.remove(b"UserUnit");
+        } else {
+            page.set("UserUnit", user_unit);
+        }
+        Ok(())
+    }
+
+    /// Rewrite the `/Rotate` entries of the page tree as their canonical integer, see
+    /// [`Rotation::from_degrees`], returning the nodes whose value wasn't a multiple of 90 with the
+    /// rotation it was rounded to.
+    ///
+    /// The objects aren't marked as changed, this is meant for normalizing a document being loaded.
+    pub(crate) fn normalize_rotations(&mut self) -> Vec<(ObjectId, Rotation)> {
+        let nodes: Vec<_> = self
+            .objects
+            .iter()
+            .filter_map(|(id, object)| {
+                let dict = object.as_dict().ok()?;
+                let is_node = dict.has_type(b"Page") || dict.has_type(b"Pages");
+                let degrees = self.dereference(dict.get(b"Rotate").ok()?).ok()?.1;
+                match degrees {
+                    Object::Integer(degrees) if is_node && matches!(degrees, 0 | 90 | 180 | 270) => None,
+                    _ if is_node => Some((*id, degrees.as_float())),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        let mut invalid = vec![];
+        for (id, degrees) in nodes {
+            let Ok(degrees) = degrees else {
+                warn!("Ignoring /Rotate of page tree node {id:?}, which isn't a number");
+                continue;
+            };
+            let rotation = Rotation::from_degrees(degrees);
+            if degrees % 90.0 != 0.0 {
+                warn!(
+                    "/Rotate {degrees} of page tree node {id:?} isn't a multiple of 90, using {}",
+                    rotation.degrees()
+                );
+                invalid.push((id, rotation));
+            }
+            if let Some(Object::Dictionary(dict)) = self.objects.get_mut(&id) {
+                dict.set("Rotate", rotation.degrees());
+            }
+        }
+        invalid
+    }
+}
+
+impl<'a> PageView<'a> {
+    pub fn id(&self) -> ObjectId {
+        self.id
+    }
+
+    /// The page dictionary itself, for entries without a typed accessor.
+    pub fn raw(&self) -> &'a Dictionary {
+        self.dict
+    }
+
+    /// Get an attribute of the page, or the one it inherits from the page tree, with references followed.
+    ///
+    /// Only the inheritable attributes `Resources`, `MediaBox`, `CropBox` and `Rotate` are inherited, `None`
+    /// is returned for other keys.
+    pub fn inherited(&self, key: &[u8]) -> Option<&'a Object> {
+        let value = self.inherited_entry(key)?;
+        self.doc.dereference(value).ok().map(|(_, value)| value)
+    }
+
+    /// Find the entry of the page or the nearest page tree node above it which sets `key`.
+    pub(crate) fn inherited_entry(&self, key: &[u8]) -> Option<&'a Object> {
+        self.inherited_from(key).map(|(_, value)| value)
+    }
+
+    /// Like [`PageView::inherited_entry`], with the ID of the page or node setting `key`.
+    pub(crate) fn inherited_from(&self, key: &[u8]) -> Option<(ObjectId, &'a Object)> {
+        if !INHERITABLE.contains(&key) {
+            return None;
+        }
+        self.ancestry()
+            .find_map(|(id, node)| node.get(key).ok().map(|value| (id, value)))
+    }
+
+    /// The page, then the page tree nodes above it, nearest first.
+    ///
+    /// The walk stops at a reference cycle and after [`Document::DEREF_LIMIT`] levels.
+    pub(crate) fn ancestry(&self) -> impl Iterator<Item = (ObjectId, &'a Dictionary)> + 'a {
+        let doc = self.doc;
+        let mut visited = HashSet::new();
+        std::iter::successors(Some((self.id, self.dict)), move |(_, node)| {
+            let parent_id = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+            Some((parent_id, doc.get_dictionary(parent_id).ok()?))
+        })
+        .take_while(move |(id, _)| visited.insert(*id))
+        .take(Document::DEREF_LIMIT)
+    }
+
+    /// Get an inheritable attribute converted with [`TryFromObject`].
+    fn inherited_as<T: TryFromObject>(&self, key: &[u8]) -> Result<T> {
+        let value = self
+            .inherited(key)
+            .ok_or_else(|| Error::DictKey(String::from_utf8_lossy(key).into_owned()))?;
+        let mut dict = Dictionary::new();
+        dict.set(key, value.clone());
+        dict.get_as(key)
+    }
+
+    /// The boundaries of the physical medium, `/MediaBox`, in default user space units.
+    pub fn media_box(&self) -> Result<[f32; 4]> {
+        self.inherited_as(b"MediaBox")
+    }
+
+    /// The visible region of the page, `/CropBox`, which defaults to the media box.
+    pub fn crop_box(&self) -> Result<[f32; 4]> {
+        match self.inherited(b"CropBox") {
+            Some(_) => self.inherited_as(b"CropBox"),
+            None => self.media_box(),
+        }
+    }
+
+    /// The clockwise rotation of the page when displayed, `/Rotate`. It defaults to `R0`.
+    ///
+    /// Values which aren't a multiple of 90 degrees are normalized by [`Rotation::from_degrees`].
+    pub fn rotate(&self) -> Result<Rotation> {
+        match self.inherited(b"Rotate") {
+            Some(rotate) => rotate.as_float().map(Rotation::from_degrees),
+            None => Ok(Rotation::R0),
+        }
+    }
+
+    /// The resource dictionary of the page.
+    pub fn resources(&self) -> Option<&'a Dictionary> {
+        self.inherited(b"Resources")?.as_dict().ok()
+    }
+
+    /// The ID of the resource dictionary of the page, if it is an indirect object.
+    pub fn resources_id(&self) -> Option<ObjectId> {
+        self.inherited_entry(b"Resources")?.as_reference().ok()
+    }
+
+    /// The IDs of the content streams of the page, see [`Document::get_page_contents`].
+    pub fn contents(&self) -> Vec<ObjectId> {
+        self.doc.get_page_contents(self.id)
+    }
+
+    /// The IDs of the annotations of the page. Annotations stored directly in `/Annots` have no ID and are skipped.
+    pub fn annots(&self) -> Vec<ObjectId> {
+        match self.get(b"Annots") {
+            Some(Object::Array(annots)) => annots.iter().filter_map(|annot| annot.as_reference().ok()).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The transparency group attributes of the page, `/Group`.
+    pub fn group(&self) -> Option<&'a Dictionary> {
+        self.get(b"Group")?.as_dict().ok()
+    }
+
+    /// The colors and guidelines for displaying the page boundaries, `/BoxColorInfo`.
+    pub fn box_color_info(&self) -> Option<&'a Dictionary> {
+        self.get(b"BoxColorInfo")?.as_dict().ok()
+    }
+
+    /// The XMP metadata stream of the page, `/Metadata`.
+    pub fn metadata(&self) -> Option<&'a Stream> {
+        self.get(b"Metadata")?.as_stream().ok()
+    }
+
+    /// The key of the page in the parent tree of the structure tree, `/StructParents`.
+    pub fn struct_parents(&self) -> Option<i64> {
+        self.get(b"StructParents")?.as_i64().ok()
+    }
+
+    /// The size of default user space units in multiples of 1/72 inch, `/UserUnit`. It defaults to 1,
+    /// which is also used for values below 1.
+    ///
+    /// Coordinates lopdf reads or writes, such as the boxes of the page and positions of extracted
+    /// text, stay in default user space units. The unit only matters for physical sizes, see
+    /// [`PageView::physical_size_mm`].
+    pub fn user_unit(&self) -> f32 {
+        self.get(b"UserUnit")
+            .and_then(|unit| unit.as_float().ok())
+            .filter(|&unit| unit >= 1.0)
+            .unwrap_or(1.0)
+    }
+
+    /// The width and height of the page as displayed in millimeters: the crop box scaled by the user unit,
+    /// with width and height swapped if the page is rotated by a quarter turn.
+    pub fn physical_size_mm(&self) -> Result<(f32, f32)> {
+        const MM_PER_POINT: f32 = 25.4 / 72.0;
+        let [left, bottom, right, top] = self.crop_box()?;
+        let scale = self.user_unit() * MM_PER_POINT;
+        let (width, height) = ((right - left).abs() * scale, (top - bottom).abs() * scale);
+        if self.rotate()?.is_quarter_turn() {
+            Ok((height, width))
+        } else {
+            Ok((width, height))
+        }
+    }
+
+    /// Get an entry of the page dictionary itself, with references followed.
+    fn get(&self, key: &[u8]) -> Option<&'a Object> {
+        let value = self.dict.get(key).ok()?;
+        self.doc.dereference(value).ok().map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inherited_and_overridden_attributes() {
+        let mut doc = Document::with_version("1.5");
+        let root_id = doc.new_object_id();
+        let node_id = doc.new_object_id();
+        let resources_id = doc.add_object(dictionary! { "Font" => dictionary! {} });
+        let inheriting_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => node_id,
+            "Group" => dictionary! { "S" => "Transparency" },
+            "StructParents" => 4,
+        });
+        let overriding_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => node_id,
+            "MediaBox" => vec![0.into(), 0.into(), 300.into(), 400.5.into()],
+            "Rotate" => 180,
+            "UserUnit" => 2,
+        });
+        doc.objects.insert(
+            node_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Parent" => root_id,
+                "Kids" => vec![inheriting_id.into(), overriding_id.into()],
+                "Count" => 2,
+            }),
+        );
+        doc.objects.insert(
+            root_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![node_id.into()],
+                "Count" => 2,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+                "Rotate" => 90,
+                "Resources" => resources_id,
+            }),
+        );
+
+        let page = doc.page(inheriting_id).unwrap();
+        assert_eq!(page.media_box().unwrap(), [0.0, 0.0, 595.0, 842.0]);
+        assert_eq!(page.crop_box().unwrap(), [0.0, 0.0, 595.0, 842.0]);
+        assert_eq!(page.rotate().unwrap(), Rotation::R90);
+        assert_eq!(page.resources_id(), Some(resources_id));
+        assert!(page.resources().unwrap().has(b"Font"));
+        assert_eq!(
+            page.group().unwrap().get(b"S").unwrap().as_name().unwrap(),
+            b"Transparency"
+        );
+        assert_eq!(page.struct_parents(), Some(4));
+        assert_eq!(page.user_unit(), 1.0);
+        assert!(page.metadata().is_none());
+        // Other entries of the page and its parents aren't inherited.
+        assert!(page.inherited(b"Group").is_none());
+        assert!(page.inherited(b"Count").is_none());
+        assert_eq!(
+            doc.get_page_resources(inheriting_id).unwrap(),
+            (None, vec![resources_id])
+        );
+
+        let page = doc.page(overriding_id).unwrap();
+        assert_eq!(page.media_box().unwrap(), [0.0, 0.0, 300.0, 400.5]);
+        assert_eq!(page.rotate().unwrap(), Rotation::R180);
+        assert_eq!(page.user_unit(), 2.0);
+        assert!(page.group().is_none());
+        assert_eq!(page.raw().get(b"Type").unwrap().as_name().unwrap(), b"Page");
+
+        // A user unit below 1 is invalid.
+        doc.get_dictionary_mut(overriding_id).unwrap().set("UserUnit", 0.5);
+        assert_eq!(doc.page(overriding_id).unwrap().user_unit(), 1.0);
+
+        // A wrong box names the key.
+        doc.get_dictionary_mut(overriding_id)
+            .unwrap()
+            .set("MediaBox", vec![0.into(), 0.into()]);
+        let err = doc.page(overriding_id).unwrap().media_box().unwrap_err();
+        assert!(err.to_string().contains("/MediaBox"), "{err}");
+    }
+
+    #[test]
+    fn direct_resources_inherited_from_an_ancestor() {
+        let mut doc = Document::with_version("1.5");
+        let root_id = doc.new_object_id();
+        let node_id = doc.new_object_id();
+        let font = |name: &str| dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => name };
+        let resources_id = doc.add_object(dictionary! { "Font" => dictionary! { "F2" => font("Courier") } });
+        let page_id = doc.add_object(dictionary! { "Type" => "Page", "Parent" => node_id });
+        doc.objects.insert(
+            node_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Parent" => root_id,
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => font("Helvetica") } },
+            }),
+        );
+        doc.objects.insert(
+            root_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![node_id.into()],
+                "Count" => 1,
+                "Resources" => resources_id,
+            }),
+        );
+
+        let (resource_dict, resource_ids) = doc.get_page_resources(page_id).unwrap();
+        assert!(resource_dict
+            .unwrap()
+            .get(b"Font")
+            .unwrap()
+            .as_dict()
+            .unwrap()
+            .has(b"F1"));
+        assert_eq!(resource_ids, [resources_id]);
+        let fonts = doc.get_page_fonts(page_id).unwrap();
+        assert_eq!(fonts.keys().collect::<Vec<_>>(), [b"F1", b"F2"]);
+        assert_eq!(
+            doc.page(page_id)
+                .unwrap()
+                .resources()
+                .unwrap()
+                .get(b"Font")
+                .unwrap()
+                .as_dict()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn user_unit_and_physical_size() {
+        let mut doc = crate::creator::tests::create_document();
+        let page_id = doc.get_pages()[&1];
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("MediaBox", vec![0.into(), 0.into(), 14400.into(), 7200.into()]);
+        let (width, height) = doc.page(page_id).unwrap().physical_size_mm().unwrap();
+        assert!((width - 5080.0).abs() < 0.01 && (height - 2540.0).abs() < 0.01);
+
+        doc.set_user_unit(page_id, 10.0).unwrap();
+        let page = doc.page(page_id).unwrap();
+        assert_eq!(page.user_unit(), 10.0);
+        // The boxes stay in default user space units.
+        assert_eq!(page.media_box().unwrap(), [0.0, 0.0, 14400.0, 7200.0]);
+        let (width, height) = page.physical_size_mm().unwrap();
+        assert!((width - 50800.0).abs() < 0.1 && (height - 25400.0).abs() < 0.1);
+
+        doc.get_dictionary_mut(page_id).unwrap().set("Rotate", 270);
+        let (width, height) = doc.page(page_id).unwrap().physical_size_mm().unwrap();
+        assert!((width - 25400.0).abs() < 0.1 && (height - 50800.0).abs() < 0.1);
+
+        assert!(doc.set_user_unit(page_id, 0.5).is_err());
+        assert!(doc.set_user_unit(page_id, f32::NAN).is_err());
+        doc.set_user_unit(page_id, 1.0).unwrap();
+        assert!(!doc.get_dictionary(page_id).unwrap().has(b"UserUnit"));
+    }
+
+    #[test]
+    fn rotations_are_normalized_on_load() {
+        let mut doc = crate::creator::tests::create_document_with_texts(&["a", "b", "c", "d", "e"]);
+        let pages: Vec<_> = doc.get_pages().into_values().collect();
+        let values: [Object; 5] = [(-90).into(), 270.0.into(), 450.into(), 45.into(), 180.into()];
+        for (&page_id, value) in pages.iter().zip(values) {
+            doc.get_dictionary_mut(page_id).unwrap().set("Rotate", value);
+        }
+        assert_eq!(doc.page(pages[0]).unwrap().rotate().unwrap(), Rotation::R270);
+
+        let mut buffer = Vec::new();
+        doc.save_to(&mut buffer).unwrap();
+        let doc = Document::load_mem(&buffer).unwrap();
+        assert_eq!(doc.load_report.invalid_rotations, [(pages[3], Rotation::R90)]);
+        let rotations: Vec<_> = pages
+            .iter()
+            .map(|&id| doc.page(id).unwrap().rotate().unwrap())
+            .collect();
+        assert_eq!(
+            rotations,
+            [
+                Rotation::R270,
+                Rotation::R270,
+                Rotation::R90,
+                Rotation::R90,
+                Rotation::R180
+            ]
+        );
+        for page_id in pages {
+            let rotate = doc.get_dictionary(page_id).unwrap().get(b"Rotate").unwrap();
+            assert!(matches!(rotate, Object::Integer(0 | 90 | 180 | 270)), "{rotate:?}");
+        }
+        assert_eq!(Rotation::from_degrees(-450.0), Rotation::R270);
+        assert_eq!(Rotation::from_degrees(359.0), Rotation::R0);
+    }
+}
@@ -1,11 +1,14 @@
 use crate::cmap_section::{ArrayOfTargetStrings, CMapParseError, CMapSection, CodeLen, SourceCode, SourceRangeMapping};
-use crate::parser::{comment, dict_dup, dictionary, eol, hex_char, name, NomError, NomResult, ParserInput};
+use crate::parser::{comment, dict_dup, dictionary, eol, name, NomError, NomResult, ParserInput};
+use log::warn;
 use nom::branch::alt;
 pub use nom::bytes::complete::tag;
-use nom::combinator::map;
+use nom::bytes::complete::{take_till1, take_while};
+use nom::character::is_hex_digit;
+use nom::combinator::{map, not};
 use nom::error::ParseError;
-use nom::multi::{fold_many0, fold_many1, fold_many_m_n, many1, many_m_n, separated_list1};
-use nom::sequence::{pair, preceded, separated_pair, terminated};
+use nom::multi::{fold_many0, fold_many1, fold_many_m_n, separated_list1};
+use nom::sequence::{preceded, separated_pair};
 use nom::Parser;
 use nom::{
     character::complete::digit1,
@@ -23,13 +26,14 @@ fn from(err: nom::Err<E>) -> Self {
     }
 }
 
-pub(crate) fn parse(stream_content: ParserInput) -> Result<Vec<CMapSection>, CMapParseError> {
+/// Parse the sections of a CMap, returning them with the number of malformed entries which were skipped.
+pub(crate) fn parse(stream_content: ParserInput) -> Result<(Vec<CMapSection>, usize), CMapParseError> {
     let result = cmap_stream(stream_content);
     let result = result.map_err(CMapParseError::from)?;
     Ok(result.1)
 }
 
-fn cmap_stream(input: ParserInput) -> NomResult<Vec<CMapSection>> {
+fn cmap_stream(input: ParserInput) -> NomResult<(Vec<CMapSection>, usize)> {
     delimited(
         cidinit_procset,
         cmap_resource_dictionary,
@@ -99,13 +103,13 @@ fn cidinit_procset(input: ParserInput) -> NomResult<()> {
     )
 }
 
-fn cmap_resource_dictionary(input: ParserInput) -> NomResult<Vec<CMapSection>> {
+fn cmap_resource_dictionary(input: ParserInput) -> NomResult<(Vec<CMapSection>, usize)> {
     let begin_parser = tuple((digit1, space1, tag(b"dict"), space1, tag(b"begin"), multispace1));
     let end_parser = tuple((tag(b"end"), multispace1));
     delimited(begin_parser, cmap_data, end_parser)(input)
 }
 
-fn cmap_data(input: ParserInput) -> NomResult<Vec<CMapSection>> {
+fn cmap_data(input: ParserInput) -> NomResult<(Vec<CMapSection>, usize)> {
     let cmap_end = tuple((
         tag(b"endcmap"),
         multispace1,
@@ -162,15 +166,51 @@ fn cmap_type(input: ParserInput) -> NomResult<()> {
     )
 }
 
-fn cmap_codespace_and_mappings(input: ParserInput) -> NomResult<Vec<CMapSection>> {
-    many1(alt((codespace_range_section, bf_char_section, bf_range_section)))(input)
+fn cmap_codespace_and_mappings(input: ParserInput) -> NomResult<(Vec<CMapSection>, usize)> {
+    fold_many1(
+        alt((
+            map(codespace_range_section, |section| (section, 0)),
+            bf_char_section,
+            bf_range_section,
+        )),
+        || (Vec::new(), 0),
+        |(mut sections, skipped), (section, section_skipped)| {
+            sections.push(section);
+            (sections, skipped + section_skipped)
+        },
+    )(input)
+}
+
+/// Parse the entries of a section, skipping the rest of the line when an entry is malformed.
+///
+/// Returns the valid entries with the number of lines skipped.
+fn entries_skipping_malformed<'a, O>(
+    entry: impl FnMut(ParserInput<'a>) -> NomResult<'a, O>,
+) -> impl FnMut(ParserInput<'a>) -> NomResult<'a, (Vec<O>, usize)> {
+    let malformed_entry = tuple((
+        space0,
+        not(tag(b"end")),
+        take_till1(|c| c == b'\r' || c == b'\n'),
+        multispace1,
+    ));
+    fold_many1(
+        alt((map(entry, Some), map(malformed_entry, |_| None))),
+        || (Vec::new(), 0),
+        |(mut entries, mut skipped), entry| {
+            match entry {
+                Some(entry) => entries.push(entry),
+                None => skipped += 1,
+            }
+            (entries, skipped)
+        },
+    )
 }
 
 fn codespace_range_section(input: ParserInput) -> NomResult<CMapSection> {
     let begin_section = tuple((digit1, space1, tag(b"begincodespacerange"), multispace1));
     let end_section = tuple((tag(b"endcodespacerange"), multispace1));
     let parse_range = delimited(space0, code_range_pair, multispace1);
-    let (rest_of_input, ranges_result) = delimited(begin_section, many1(parse_range), end_section)(input)?;
+    let (rest_of_input, ranges_result) = delimited(begin_section, nom::multi::many1(parse_range), end_section)(input)?;
     Ok((rest_of_input, CMapSection::CsRange(ranges_result)))
 }
 
@@ -185,14 +225,39 @@ fn code_range_pair(input: ParserInput) -> NomResult<(SourceCode, SourceCode, Cod
 }
 
 fn create_code_len_err<'a, T, E: ParseError<ParserInput<'a>>>(input: ParserInput<'a>) -> Result<T, nom::Err<E>> {
-    Err(nom::Err::Failure(nom::error::make_error(
+    // Not a failure, so that the entry can be skipped.
+    Err(nom::Err::Error(nom::error::make_error(
         input,
         nom::error::ErrorKind::LengthValue,
     )))
 }
 
+/// Parse a hexadecimal string, which may contain white-space.
+///
+/// As in other hexadecimal strings, a missing final digit is assumed to be 0.
+fn hex_bytes(input: ParserInput) -> NomResult<Vec<u8>> {
+    let is_space = |c: u8| b" \t\r\n\x0c\0".contains(&c);
+    let (rest_of_input, digits) = delimited(
+        tag(b"<"),
+        take_while(move |c| is_hex_digit(c) || is_space(c)),
+        tag(b">"),
+    )(input)?;
+    let mut digits: Vec<u8> = digits.iter().copied().filter(|&c| !is_space(c)).collect();
+    if digits.len() % 2 == 1 {
+        digits.push(b'0');
+    }
+    let bytes = digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect();
+    Ok((rest_of_input, bytes))
+}
+
 fn source_code(input: ParserInput) -> NomResult<(SourceCode, CodeLen)> {
-    let (rest_of_input, bytes) = delimited(tag(b"<"), many_m_n(1, 4, hex_char), tag(b">"))(input)?;
+    let (rest_of_input, bytes) = hex_bytes(input)?;
+    if bytes.is_empty() || bytes.len() > 4 {
+        return create_code_len_err(input);
+    }
     let code_len = bytes.len();
     let source_code = bytes
         .into_iter()
@@ -203,29 +268,41 @@ fn source_code(input: ParserInput) -> NomResult<(SourceCode, CodeLen)> {
     Ok((rest_of_input, (source_code, code_len as u8)))
 }
 
-fn hex_u16(input: ParserInput) -> NomResult<u16> {
-    map(pair(hex_char, hex_char), |(h1, h2)| h1 as u16 * 256 + h2 as u16)(input)
-}
-
-fn bf_char_section(input: ParserInput) -> NomResult<CMapSection> {
+fn bf_char_section(input: ParserInput) -> NomResult<(CMapSection, usize)> {
     let begin_section = tuple((digit1, space1, tag(b"beginbfchar"), multispace1));
     let end_section = tuple((tag(b"endbfchar"), multispace1));
     let bf_char_line = delimited(space0, separated_pair(source_code, space0, target_string), multispace1);
-    let (rest_of_input, bf_char_mappings) = delimited(begin_section, many1(bf_char_line), end_section)(input)?;
-    Ok((rest_of_input, CMapSection::BfChar(bf_char_mappings)))
+    let (rest_of_input, (bf_char_mappings, skipped)) =
+        delimited(begin_section, entries_skipping_malformed(bf_char_line), end_section)(input)?;
+    if skipped > 0 {
+        warn!("Skipped {skipped} malformed entries of a bfchar section");
+    }
+    Ok((rest_of_input, (CMapSection::BfChar(bf_char_mappings), skipped)))
 }
 
 fn target_string(input: ParserInput) -> NomResult<Vec<u16>> {
     // according to specification dstString can be up to 512 bytes
     // in ToUnicode cmap these should be 2-byte big endian Unicode values
-    delimited(tag(b"<"), many_m_n(1, 256, terminated(hex_u16, multispace0)), tag(b">"))(input)
+    let (rest_of_input, bytes) = hex_bytes(input)?;
+    if bytes.is_empty() || bytes.len() > 512 || bytes.len() % 2 == 1 {
+        return create_code_len_err(input);
+    }
+    let string = bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    Ok((rest_of_input, string))
 }
 
-fn bf_range_section(input: ParserInput) -> NomResult<CMapSection> {
+fn bf_range_section(input: ParserInput) -> NomResult<(CMapSection, usize)> {
     let begin_section = tuple((digit1, space1, tag(b"beginbfrange"), multispace1));
     let end_section = tuple((tag(b"endbfrange"), multispace1));
-    let (rest_of_input, bf_range_mappings) = delimited(begin_section, many1(bf_range_line), end_section)(input)?;
-    Ok((rest_of_input, CMapSection::BfRange(bf_range_mappings)))
+    let (rest_of_input, (bf_range_mappings, skipped)) =
+        delimited(begin_section, entries_skipping_malformed(bf_range_line), end_section)(input)?;
+    if skipped > 0 {
+        warn!("Skipped {skipped} malformed entries of a bfrange section");
+    }
+    Ok((rest_of_input, (CMapSection::BfRange(bf_range_mappings), skipped)))
 }
 
 fn bf_range_line(input: ParserInput) -> NomResult<SourceRangeMapping> {
@@ -249,7 +326,7 @@ fn range_target_array(input: ParserInput) -> NomResult<ArrayOfTargetStrings> {
 mod tests {
     use super::*;
 
-    fn test_span(s: &[u8]) -> ParserInput {
+    fn test_span(s: &[u8]) -> ParserInput<'_> {
         ParserInput::new_extra(s, "")
     }
     #[test]
@@ -349,8 +426,9 @@ fn parse_bf_range_section() {
             <0020>  <002f> [<0000> <00000010> ]\n\
         endbfrange\n";
 
-        let (rem, res) = bf_range_section(test_span(data)).unwrap();
+        let (rem, (res, skipped)) = bf_range_section(test_span(data)).unwrap();
         assert_eq!(*rem, b"");
+        assert_eq!(skipped, 0);
         assert_eq!(
             res,
             CMapSection::BfRange(vec![
@@ -369,8 +447,9 @@ fn parse_bf_char_section() {
             <1f> <0066 0066>
             <20> <0020>\n\
         endbfchar\n";
-        let (rem, res) = bf_char_section(test_span(data)).unwrap();
+        let (rem, (res, skipped)) = bf_char_section(test_span(data)).unwrap();
         assert_eq!(*rem, b"");
+        assert_eq!(skipped, 0);
         assert_eq!(
             res,
             CMapSection::BfChar(vec![
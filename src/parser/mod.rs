@@ -1,10 +1,12 @@
 #[cfg(feature = "nom_parser")]
-use super::{Dictionary, Object, ObjectId, Reader, Stream, StringFormat};
+use super::{Dictionary, Object, ObjectId, Reader, Stream, StreamEol, StringFormat};
 use crate::content::*;
 use crate::error;
 use crate::xref::*;
-use crate::Error;
+use crate::{DuplicateKeyPolicy, Error};
+use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
+use std::ops::Range;
 use std::str::{self, FromStr};
 
 use nom::branch::alt;
@@ -13,8 +15,8 @@
 use nom::character::complete::{digit0, digit1, one_of};
 use nom::character::complete::{space0, space1};
 use nom::character::{is_hex_digit, is_oct_digit};
-use nom::combinator::cut;
-use nom::combinator::{map, map_opt, map_res, opt, verify};
+use nom::combinator::{cut, eof};
+use nom::combinator::{map, map_opt, map_res, opt, recognize, verify};
 use nom::error::{ErrorKind, ParseError};
 use nom::multi::{fold_many0, fold_many1, many0, many0_count};
 use nom::sequence::{delimited, pair, preceded, separated_pair, terminated, tuple};
@@ -46,20 +48,14 @@ fn convert_result<O, E>(result: Result<O, E>, input: ParserInput, error_kind: Er
     })
 }
 
-#[inline]
-fn offset_stream(object: &mut Object, offset: usize) {
-    if let Object::Stream(stream) = object {
-        stream.start_position = stream.start_position.and_then(|sp| sp.checked_add(offset));
-    }
-}
-
 pub(crate) fn eol(input: ParserInput) -> NomResult<ParserInput> {
     alt((tag(b"\r\n"), tag(b"\n"), tag(b"\r")))(input)
 }
 
+/// A comment, running up to the end of the line or of the input.
 pub(crate) fn comment(input: ParserInput) -> NomResult<()> {
     map(
-        tuple((tag(b"%"), take_while(|c: u8| !b"\r\n".contains(&c)), eol)),
+        tuple((tag(b"%"), take_while(|c: u8| !b"\r\n".contains(&c)), alt((eol, eof)))),
         |_| (),
     )(input)
 }
@@ -279,12 +275,73 @@ fn inner_dictionary(input: ParserInput) -> NomResult<Dictionary> {
         pair(terminated(name, space), _direct_object),
         Dictionary::new,
         |mut dict, (key, value)| {
-            dict.set(key, value);
+            set_parsed_entry(&mut dict, key, value);
             dict
         },
     )(input)
 }
 
+thread_local! {
+    /// The policy for keys repeated in a dictionary and the keys found repeated, while
+    /// [`with_duplicate_keys`] runs on this thread.
+    static DUPLICATE_KEYS: RefCell<Option<(DuplicateKeyPolicy, Vec<Vec<u8>>)>> = const { RefCell::new(None) };
+}
+
+/// Run `parse`, applying `policy` to the dictionaries it parses on this thread, and return the keys
+/// found more than once in one of them, sorted and listed once as the parser may backtrack over a
+/// dictionary. Elsewhere the last value of a repeated key is kept.
+pub(crate) fn with_duplicate_keys<T>(policy: DuplicateKeyPolicy, parse: impl FnOnce() -> T) -> (T, Vec<Vec<u8>>) {
+    let outer = DUPLICATE_KEYS.with(|state| state.replace(Some((policy, vec![]))));
+    let result = parse();
+    let keys = DUPLICATE_KEYS
+        .with(|state| state.replace(outer))
+        .map_or(vec![], |(_, mut keys)| {
+            keys.sort_unstable();
+            keys.dedup();
+            keys
+        });
+    (result, keys)
+}
+
+thread_local! {
+    /// Whether a `/Length` was coerced to an integer, while [`with_lenient_lengths`] runs on this
+    /// thread with lengths to be coerced.
+    static LENIENT_LENGTHS: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+/// Run `parse`, accepting the Real and numeric string `/Length` of the streams it parses on this
+/// thread if `lenient`, see [`Object::as_i64_lenient`], and return whether such a length was found.
+pub(crate) fn with_lenient_lengths<T>(lenient: bool, parse: impl FnOnce() -> T) -> (T, bool) {
+    let outer = LENIENT_LENGTHS.with(|state| state.replace(lenient.then_some(false)));
+    let result = parse();
+    let coerced = LENIENT_LENGTHS.with(|state| state.replace(outer));
+    (result, coerced == Some(true))
+}
+
+/// The value of the `/Length` of a stream, coerced to an integer while [`with_lenient_lengths`] allows it.
+pub(crate) fn stream_length(value: &Object) -> crate::Result<i64> {
+    value.as_i64().or_else(|err| match LENIENT_LENGTHS.with(Cell::get) {
+        Some(_) => {
+            let length = value.as_i64_lenient()?;
+            LENIENT_LENGTHS.with(|state| state.set(Some(true)));
+            Ok(length)
+        }
+        None => Err(err),
+    })
+}
+
+fn set_parsed_entry(dict: &mut Dictionary, key: Vec<u8>, value: Object) {
+    DUPLICATE_KEYS.with(|state| match &mut *state.borrow_mut() {
+        Some((policy, keys)) => {
+            if dict.has(&key) {
+                keys.push(key.clone());
+            }
+            dict.set_with_policy(key, value, *policy);
+        }
+        None => dict.set(key, value),
+    })
+}
+
 pub(crate) fn dict_dup(input: ParserInput) -> NomResult<Dictionary> {
     delimited(
         tuple((
@@ -313,24 +370,40 @@ pub(crate) fn dict_dup(input: ParserInput) -> NomResult<Dictionary> {
 }
 
 fn stream<'a>(input: ParserInput<'a>, reader: &Reader, already_seen: &mut HashSet<ObjectId>) -> NomResult<'a, Object> {
-    let (i, dict) = terminated(dictionary, tuple((space, tag(b"stream"), space0, eol)))(input)?;
+    let (i, (dict, after_keyword)) = pair(
+        dictionary,
+        preceded(pair(space, tag(b"stream")), recognize(pair(space0, eol))),
+    )(input)?;
 
     if let Ok(length) = dict.get(b"Length").and_then(|value| {
         if let Ok(id) = value.as_reference() {
-            reader.get_object(id, already_seen).and_then(|value| value.as_i64())
+            reader
+                .get_object(id, already_seen)
+                .and_then(|value| stream_length(&value))
         } else {
-            value.as_i64()
+            stream_length(value)
         }
     }) {
         if length < 0 {
             // artificial error kind is created to allow descriptive nom errors
             return Err(nom::Err::Failure(NomError::from_error_kind(i, ErrorKind::LengthValue)));
         }
-        let (i, data) = terminated(take(length as usize), pair(opt(eol), tag(b"endstream")))(i)?;
-        Ok((i, Object::Stream(Stream::new(dict, data.to_vec()))))
+        let (i, (data, before_endstream)) = pair(take(length as usize), terminated(opt(eol), tag(b"endstream")))(i)?;
+        let mut stream = Stream::new(dict, data.to_vec());
+        stream.start_position = Some(data.location_offset());
+        stream.original_eol = Some(StreamEol {
+            after_keyword: after_keyword.to_vec(),
+            before_endstream: before_endstream.map(|eol| eol.to_vec()).unwrap_or_default(),
+        });
+        Ok((i, Object::Stream(stream)))
     } else {
-        // Return position relative to the start of the stream dictionary.
-        Ok((i, Object::Stream(Stream::with_position(dict, input.len() - i.len()))))
+        let mut stream = Stream::with_position(dict, i.location_offset());
+        // The end of the data is only known once the content is read.
+        stream.original_eol = Some(StreamEol {
+            after_keyword: after_keyword.to_vec(),
+            before_endstream: Vec::new(),
+        });
+        Ok((i, Object::Stream(stream)))
     }
 }
 
@@ -341,7 +414,10 @@ fn unsigned_int<I: FromStr>(input: ParserInput) -> NomResult<I> {
 }
 
 fn object_id(input: ParserInput) -> NomResult<ObjectId> {
-    pair(terminated(unsigned_int, space), terminated(unsigned_int, space))(input)
+    map(
+        pair(terminated(unsigned_int, space), terminated(unsigned_int, space)),
+        |(num, gen)| ObjectId(num, gen),
+    )(input)
 }
 
 fn reference(input: ParserInput) -> NomResult<Object> {
@@ -378,39 +454,92 @@ fn object<'a>(input: ParserInput<'a>, reader: &Reader, already_seen: &mut HashSe
     )(input)
 }
 
+/// An indirect object with the range of the garbage skipped before its `endobj`, if any, and its range
+/// from its header through its `endobj`.
+pub type IndirectObject = (ObjectId, Object, Option<Range<usize>>, Range<usize>);
+
+/// Parse the indirect object at `offset`, also returning the range of the garbage skipped between
+/// the end of the object and its `endobj`, if any, and the range of the object from its header through
+/// its `endobj`.
 pub fn indirect_object(
     input: ParserInput, offset: usize, expected_id: Option<ObjectId>, reader: &Reader,
     already_seen: &mut HashSet<ObjectId>,
-) -> crate::Result<(ObjectId, Object)> {
-    let (id, mut object) = _indirect_object(input.slice(offset..), offset, expected_id, reader, already_seen)?;
-
-    offset_stream(&mut object, offset);
+) -> crate::Result<IndirectObject> {
+    _indirect_object(input.slice(offset..), offset, expected_id, reader, already_seen)
+}
 
-    Ok((id, object))
+/// Whether an indirect object without `endobj` ends here: at the header of the next object, a
+/// cross-reference section, the trailer or the end of the input.
+fn at_object_boundary(input: ParserInput) -> bool {
+    input.is_empty()
+        || [b"xref".as_slice(), b"trailer", b"startxref"]
+            .iter()
+            .any(|keyword| input.starts_with(keyword))
+        || pair(object_id, tag(b"obj"))(input).is_ok()
 }
 
 fn _indirect_object<'a>(
     input: ParserInput<'a>, offset: usize, expected_id: Option<ObjectId>, reader: &Reader,
     already_seen: &mut HashSet<ObjectId>,
-) -> crate::Result<(ObjectId, Object)> {
-    let (i, (_, object_id)) = terminated(tuple((space, object_id)), pair(tag(b"obj"), space))(input)
-        .map_err(|_| Error::IndirectObject { offset })?;
+) -> crate::Result<IndirectObject> {
+    let (header, _) = space(input).map_err(|_| Error::IndirectObject { offset })?;
+    let (i, object_id) =
+        terminated(object_id, pair(tag(b"obj"), space))(header).map_err(|_| Error::IndirectObject { offset })?;
     if let Some(expected_id) = expected_id {
         if object_id != expected_id {
             return Err(crate::error::Error::ObjectIdMismatch);
         }
     }
 
-    let object_offset = input.len() - i.len();
-    let (_, mut object) = terminated(
-        |i: ParserInput<'a>| object(i, reader, already_seen),
-        tuple((space, opt(tag(b"endobj")), space)),
-    )(i)
-    .map_err(|_| Error::IndirectObject { offset })?;
+    let (i, object) = object(i, reader, already_seen).map_err(|_| Error::IndirectObject { offset })?;
+
+    // `endobj` is optional, but anything else before the next object is skipped up to the first
+    // `endobj` or object boundary, so the header of the next object is never taken as garbage.
+    // The data of a stream whose `/Length` is yet to be resolved follows, and its end is found later.
+    let pending_stream =
+        matches!(&object, Object::Stream(stream) if stream.start_position == Some(i.location_offset()));
+    let mut skipped = None;
+    if !pending_stream && !i.starts_with(b"endobj") && !at_object_boundary(i) {
+        let length = (1..i.len())
+            .find(|&n| {
+                let rest = i.slice(n..);
+                rest.starts_with(b"endobj") || (is_whitespace(i[n - 1]) && at_object_boundary(rest))
+            })
+            .unwrap_or(i.len());
+        let start = i.location_offset();
+        skipped = Some(start..start + length);
+    }
+    let rest = match &skipped {
+        Some(skipped) => i.slice(skipped.len()..),
+        None => i,
+    };
+    let end = rest.location_offset() + if rest.starts_with(b"endobj") { 6 } else { 0 };
+
+    Ok((object_id, object, skipped, header.location_offset()..end))
+}
 
-    offset_stream(&mut object, object_offset);
+/// The end of an indirect stream object whose data ends where `input` starts: past `endstream` and the
+/// `endobj` following it, if any.
+pub(crate) fn stream_object_end(input: ParserInput) -> Option<usize> {
+    let (i, _) = tuple((opt(eol), tag(b"endstream"), space))(input).ok()?;
+    Some(i.location_offset() + if i.starts_with(b"endobj") { 6 } else { 0 })
+}
 
-    Ok((object_id, object))
+/// The ranges of the cross-reference section at the start of `input`, a table or a stream, and of
+/// the `trailer` dictionary following a table.
+pub(crate) fn xref_section_spans(input: ParserInput, reader: &Reader) -> Option<(Range<usize>, Option<Range<usize>>)> {
+    let span = |consumed: ParserInput| consumed.location_offset()..consumed.location_offset() + consumed.len();
+    if let Ok((i, table)) = recognize(xref)(input) {
+        let (_, dict) = recognize(pair(pair(tag(b"trailer"), space), dictionary))(i).ok()?;
+        // Without the white-space after the last entry.
+        let length = table
+            .iter()
+            .rposition(|&c| !is_whitespace(c))
+            .map_or(0, |last| last + 1);
+        return Some((span(table.slice(..length)), Some(span(dict))));
+    }
+    let (_, _, _, stream) = _indirect_object(input, 0, None, reader, &mut HashSet::new()).ok()?;
+    Some((stream, None))
 }
 
 pub fn header(input: ParserInput) -> Option<String> {
@@ -444,10 +573,14 @@ fn xref(input: ParserInput) -> NomResult<Xref> {
             || -> Xref { Xref::new(0, XrefType::CrossReferenceTable) },
             |mut xref, ((start, _count), entries)| {
                 for (index, ((offset, generation), is_normal)) in entries.into_iter().enumerate() {
+                    let (id, Ok(generation)) = ((start + index) as u32, generation.try_into()) else {
+                        continue;
+                    };
                     if is_normal {
-                        if let Ok(generation) = generation.try_into() {
-                            xref.insert((start + index) as u32, XrefEntry::Normal { offset, generation });
-                        }
+                        xref.insert(id, XrefEntry::Normal { offset, generation });
+                    } else if id != 0 {
+                        // An object freed by this revision hides its definition in earlier ones.
+                        xref.insert(id, XrefEntry::Free { generation });
                     }
                 }
                 xref
@@ -461,6 +594,11 @@ fn trailer(input: ParserInput) -> NomResult<Dictionary> {
     delimited(pair(tag(b"trailer"), space), dictionary, space)(input)
 }
 
+/// Parse a `trailer` keyword and the dictionary following it.
+pub(crate) fn trailer_dictionary(input: ParserInput) -> Option<Dictionary> {
+    strip_nom(trailer(input))
+}
+
 pub fn xref_and_trailer(input: ParserInput, reader: &Reader) -> crate::Result<(Xref, Dictionary)> {
     let xref_trailer = map(pair(xref, trailer), |(mut xref, trailer)| {
         xref.size = trailer
@@ -473,7 +611,7 @@ pub fn xref_and_trailer(input: ParserInput, reader: &Reader) -> crate::Result<(X
         xref_trailer,
         (|input| {
             _indirect_object(input, 0, None, reader, &mut HashSet::new())
-                .map(|(_, obj)| {
+                .map(|(_, obj, _, _)| {
                     let res = match obj {
                         Object::Stream(stream) => decode_xref_stream(stream),
                         _ => Err(crate::error::ParseError::InvalidXref.into()),
@@ -500,15 +638,50 @@ pub fn xref_start(input: ParserInput) -> Option<i64> {
 
 // The following code create parser to parse content stream.
 
+#[inline]
+fn is_content_whitespace(c: u8) -> bool {
+    b" \t\r\n".contains(&c)
+}
+
+/// White-space and comments between the tokens of a content stream.
 fn content_space(input: ParserInput) -> NomResult<()> {
-    map(take_while(|c| b" \t\r\n".contains(&c)), |_| ())(input)
+    fold_many0(
+        alt((map(take_while1(is_content_whitespace), |_| ()), comment)),
+        || {},
+        |_, _| (),
+    )(input)
+}
+
+#[inline]
+fn is_operator_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || b"*'\"".contains(&c)
+}
+
+/// An operator: a letter, `*`, `'` or `"` followed by any regular characters, e.g. `d0` or `T*`.
+fn operator_token(input: ParserInput) -> NomResult<ParserInput> {
+    recognize(pair(take_while_m_n(1, 1, is_operator_start), take_while(is_regular)))(input)
 }
 
 fn operator(input: ParserInput) -> NomResult<String> {
-    map_res(
-        take_while1(|c: u8| c.is_ascii_alphabetic() || b"*'\"".contains(&c)),
-        |op: ParserInput| str::from_utf8(&op).map(Into::into),
-    )(input)
+    map_res(operator_token, |op: ParserInput| str::from_utf8(&op).map(Into::into))(input)
+}
+
+/// A token which is neither an operand nor an operator, e.g. of a private or newer construct.
+fn raw_token(input: ParserInput) -> NomResult<ParserInput> {
+    alt((
+        verify(take_while1(is_regular), |token: &ParserInput| {
+            !is_operator_start(token[0])
+        }),
+        take_while_m_n(1, 1, |c| b"()<>[]{}".contains(&c)),
+    ))(input)
+}
+
+/// Succeeds where a token ends: before white-space, a delimiter or the end of the input.
+fn token_end(input: ParserInput) -> NomResult<()> {
+    match input.as_bytes().first() {
+        Some(&c) if is_regular(c) => Err(nom::Err::Error(NomError::from_error_kind(input, ErrorKind::Verify))),
+        _ => Ok((input, ())),
+    }
 }
 
 fn operand(input: ParserInput) -> NomResult<Object> {
@@ -523,6 +696,7 @@ fn operand(input: ParserInput) -> NomResult<Object> {
             hexadecimal_string,
             map(array, Object::Array),
             map(dictionary, Object::Dictionary),
+            map(raw_token, |token| Object::RawToken(token.to_vec())),
         )),
         content_space,
     )(input)
@@ -530,21 +704,19 @@ fn operand(input: ParserInput) -> NomResult<Object> {
 
 fn operation(input: ParserInput) -> NomResult<Operation> {
     map(
-        preceded(
-            many0(comment),
-            alt((inline_image, terminated(pair(many0(operand), operator), content_space))),
-        ),
+        alt((inline_image, terminated(pair(many0(operand), operator), content_space))),
         |(operands, operator)| Operation { operator, operands },
     )(input)
 }
 
 fn inline_image(input: ParserInput) -> NomResult<(Vec<Object>, String)> {
-    preceded(pair(tag(b"BI"), content_space), cut(inline_image_impl))(input)
+    preceded(tuple((tag(b"BI"), token_end, content_space)), cut(inline_image_impl))(input)
 }
 
 fn inline_image_impl(input: ParserInput) -> NomResult<(Vec<Object>, String)> {
     let (input, stream_dict) = inner_dictionary(input)?;
-    let (input, _) = pair(tag(b"ID"), content_space)(input)?;
+    // The image data follows right after the white-space, and may start with a `%`.
+    let (input, _) = pair(tag(b"ID"), take_while(is_content_whitespace))(input)?;
     let (_, (input, stream)) = convert_result(image_data_stream(input, stream_dict), input, ErrorKind::Fail)?;
     let (input, _) = tuple((content_space, tag(b"EI"), content_space))(input)?;
     Ok((input, (vec![Object::Stream(stream)], String::from("BI"))))
@@ -552,42 +724,49 @@ fn inline_image_impl(input: ParserInput) -> NomResult<(Vec<Object>, String)> {
 
 fn image_data_stream(input: ParserInput, stream_dict: Dictionary) -> crate::Result<(ParserInput, Stream)> {
     let get_abbr = |key_abbr: &[u8], key: &[u8]| stream_dict.get(key_abbr).or_else(|_| stream_dict.get(key));
-    let width = get_abbr(b"W", b"Width")?.as_i64()? as usize;
-    let height = get_abbr(b"H", b"Height")?.as_i64()? as usize;
-    let bpc = get_abbr(b"BPC", b"BitsPerComponent")?.as_i64()? as usize;
-    let colorspace = get_abbr(b"CS", b"ColorSpace")?.as_name()?;
-    let num_colors = match colorspace {
-        b"DeviceGray" | b"Gray" => 1,
-        b"DeviceRGB" | b"RGB" => 3,
-        b"DeviceRGBA" | b"RGBA" => 4,
-        b"DeviceCMYK" | b"CMYK" => 4,
-        b"Pattern" => {
-            log::warn!("Pattern colorspace is not allowed in inline images");
-            return Err(Error::InvalidInlineImage(String::from(
-                "Pattern colorspace is not allowed in inline images",
-            )));
-        }
-        _ => {
-            log::warn!("Colorspace of inline image not recognized / not yet implemented");
-            return Err(Error::Unimplemented("inline image colorspaces"));
-        }
-    };
-
-    let stride = (width * (num_colors * bpc) + 7) / 8;
-    let length = height * stride;
 
     let (input, content) = match get_abbr(b"F", b"Filter") {
         Err(_) => {
             // no decompression needed as no filter was applied
+            let width = get_abbr(b"W", b"Width")?.as_i64()? as usize;
+            let height = get_abbr(b"H", b"Height")?.as_i64()? as usize;
+            let bpc = get_abbr(b"BPC", b"BitsPerComponent")?.as_i64()? as usize;
+            let colorspace = get_abbr(b"CS", b"ColorSpace")?.as_name()?;
+            let num_colors = match colorspace {
+                b"DeviceGray" | b"Gray" | b"G" => 1,
+                b"DeviceRGB" | b"RGB" => 3,
+                b"DeviceRGBA" | b"RGBA" => 4,
+                b"DeviceCMYK" | b"CMYK" => 4,
+                b"Pattern" => {
+                    log::warn!("Pattern colorspace is not allowed in inline images");
+                    return Err(Error::InvalidInlineImage(String::from(
+                        "Pattern colorspace is not allowed in inline images",
+                    )));
+                }
+                _ => {
+                    log::warn!("Colorspace of inline image not recognized / not yet implemented");
+                    return Err(Error::Unimplemented("inline image colorspaces"));
+                }
+            };
+
+            let stride = (width * (num_colors * bpc)).div_ceil(8);
+            let length = height * stride;
+
             take(length)(input).map_err(|_: nom::Err<()>| crate::error::ParseError::EndOfInput)?
         }
-        Ok(Object::Name(_filter)) => {
-            log::warn!("Filters for inline images are not yet implemented");
-            return Err(Error::Unimplemented("filters for inline images"));
-        }
-        Ok(Object::Array(_filters)) => {
-            log::warn!("Filters for inline images are not yet implemented");
-            return Err(Error::Unimplemented("filters for inline images"));
+        Ok(filter @ (Object::Name(_) | Object::Array(_))) => {
+            // The length of encoded data can't be derived from the image dictionary. The first filter finds
+            // the end of its data when it marks it, otherwise the data runs up to an `EI` operator.
+            let first_filter = match filter {
+                Object::Array(filters) => filters.first().and_then(|filter| filter.as_name().ok()),
+                filter => filter.as_name().ok(),
+            };
+            let end = first_filter
+                .and_then(|filter| encoded_data_length(input.as_bytes(), filter))
+                .filter(|&end| ends_inline_image(input.slice(end..)))
+                .or_else(|| find_end_operator(input))
+                .ok_or(crate::error::ParseError::EndOfInput)?;
+            (input.slice(end..), input.slice(..end))
         }
         Ok(obj) => {
             log::warn!("Filter must be either a Name or and Array.");
@@ -600,6 +779,66 @@ fn image_data_stream(input: ParserInput, stream_dict: Dictionary) -> crate::Resu
     Ok((input, Stream::new(stream_dict, content.to_vec())))
 }
 
+/// The length of the data encoded by `filter` at the start of `data`, for the filters which mark its end.
+fn encoded_data_length(data: &[u8], filter: &[u8]) -> Option<usize> {
+    match filter {
+        b"AHx" | b"ASCIIHexDecode" => data.iter().position(|&c| c == b'>').map(|end| end + 1),
+        b"A85" | b"ASCII85Decode" => data.windows(2).position(|eod| eod == b"~>").map(|end| end + 2),
+        b"Fl" | b"FlateDecode" => {
+            let mut inflater = flate2::Decompress::new(true);
+            let mut output = [0; 4096];
+            loop {
+                let (consumed, produced) = (inflater.total_in(), inflater.total_out());
+                let input = data.get(consumed as usize..)?;
+                match inflater
+                    .decompress(input, &mut output, flate2::FlushDecompress::None)
+                    .ok()?
+                {
+                    flate2::Status::StreamEnd => return Some(inflater.total_in() as usize),
+                    _ if inflater.total_in() == consumed && inflater.total_out() == produced => return None,
+                    _ => {}
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Whether `input`, following the data of an inline image, is its `EI` operator, followed by the end of the
+/// content or by another operation.
+fn ends_inline_image(input: ParserInput) -> bool {
+    let Ok((input, _)) = tuple((content_space, tag(b"EI"), token_end, content_space))(input) else {
+        return false;
+    };
+    // Operands are read without nested inline images, which would search for their own end in turn.
+    // Operators are short words, which binary image data is unlikely to end with.
+    input.is_empty()
+        || preceded(many0(operand), operator_token)(input).is_ok_and(|(_, operator)| {
+            operator.len() <= 3
+                && operator
+                    .iter()
+                    .all(|&c| c.is_ascii_alphanumeric() || b"*'\"".contains(&c))
+        })
+}
+
+/// The length of the data of an inline image, up to the white-space before an `EI` operator followed by
+/// other operations, or before the first `EI` surrounded by white-space if none is.
+fn find_end_operator(input: ParserInput) -> Option<usize> {
+    let data = input.as_bytes();
+    let mut candidates = (1..data.len().saturating_sub(1))
+        .filter(|&i| {
+            is_content_whitespace(data[i - 1])
+                && &data[i..i + 2] == b"EI"
+                && data.get(i + 2).map_or(true, |&c| is_whitespace(c) || is_delimiter(c))
+        })
+        .map(|i| i - 1);
+    let first = candidates.next()?;
+    std::iter::once(first)
+        .chain(candidates)
+        .find(|&end| ends_inline_image(input.slice(end..)))
+        .or(Some(first))
+}
+
 fn _content(input: ParserInput) -> NomResult<Content<Vec<Operation>>> {
     preceded(
         content_space,
@@ -611,11 +850,145 @@ pub fn content(input: ParserInput) -> Option<Content<Vec<Operation>>> {
     strip_nom(_content(input))
 }
 
+/// Parse all of the input as content operations, with the offset of each operation.
+///
+/// Fails with the offset of the first bytes which aren't an operation.
+pub fn content_operations(input: ParserInput) -> Result<Vec<(usize, Operation)>, usize> {
+    let (mut input, _) = content_space(input).map_err(|_| 0usize)?;
+    let mut operations = vec![];
+    while !input.is_empty() {
+        let offset = input.location_offset();
+        let (rest, operation) = operation(input).map_err(|_| offset)?;
+        operations.push((offset, operation));
+        input = rest;
+    }
+    Ok(operations)
+}
+
+/// The digits of a number in a content stream, which are only converted when needed.
+fn raw_number(input: ParserInput) -> NomResult<ParserInput> {
+    recognize(pair(
+        opt(one_of("+-")),
+        alt((
+            recognize(pair(digit1, opt(pair(tag(b"."), digit0)))),
+            recognize(pair(tag(b"."), digit1)),
+        )),
+    ))(input)
+}
+
+/// The bytes of a literal string between its parentheses, without decoding its escapes.
+fn raw_literal_string(input: ParserInput) -> NomResult<ParserInput> {
+    let bytes = input.as_bytes();
+    let error = || nom::Err::Error(NomError::from_error_kind(input, ErrorKind::Char));
+    if bytes.first() != Some(&b'(') {
+        return Err(error());
+    }
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'(' if depth == crate::reader::MAX_BRACKET => return Err(error()),
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((input.slice(i + 1..), input.slice(1..i)));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Err(error())
+}
+
+fn raw_operand(input: ParserInput) -> NomResult<RawOperand> {
+    terminated(
+        alt((
+            map(tag(b"null"), |_| RawOperand::Null),
+            map(tag(b"true"), |_| RawOperand::Boolean(true)),
+            map(tag(b"false"), |_| RawOperand::Boolean(false)),
+            map(raw_number, |number| RawOperand::Number(number.into_fragment())),
+            map(
+                recognize(pair(tag(b"/"), take_while(is_regular))),
+                |name: ParserInput| RawOperand::Name(name.into_fragment()),
+            ),
+            map(recognize(raw_literal_string), |text| {
+                RawOperand::String(text.into_fragment(), StringFormat::Literal)
+            }),
+            map(
+                recognize(delimited(
+                    tag(b"<"),
+                    take_while(|c| is_hex_digit(c) || is_whitespace(c)),
+                    tag(b">"),
+                )),
+                |text: ParserInput| RawOperand::String(text.into_fragment(), StringFormat::Hexadecimal),
+            ),
+            map(recognize(array), |array| RawOperand::Array(array.into_fragment())),
+            map(recognize(dictionary), |dict| {
+                RawOperand::Dictionary(dict.into_fragment())
+            }),
+            map(raw_token, |token| RawOperand::Token(token.into_fragment())),
+        )),
+        content_space,
+    )(input)
+}
+
+fn raw_operator<'a>(input: ParserInput<'a>) -> NomResult<'a, &'a str> {
+    map_res(operator_token, |op: ParserInput<'a>| str::from_utf8(op.into_fragment()))(input)
+}
+
+/// Lex the content operation at the start of `input`, borrowing its operands.
+pub(crate) fn raw_operation(input: ParserInput) -> Option<(ParserInput, RawOperation)> {
+    alt((
+        map(
+            preceded(
+                tuple((tag(b"BI"), token_end, content_space)),
+                cut(recognize(inline_image_impl)),
+            ),
+            |image| RawOperation {
+                operator: "BI",
+                operands: vec![RawOperand::InlineImage(image.into_fragment())],
+            },
+        ),
+        map(
+            terminated(pair(many0(raw_operand), raw_operator), content_space),
+            |(operands, operator)| RawOperation { operator, operands },
+        ),
+    ))(input)
+    .ok()
+}
+
+/// Skip the white-space and comments before the first operation of a content stream.
+pub(crate) fn skip_content_space(input: ParserInput) -> ParserInput {
+    content_space(input).map_or(input, |(input, _)| input)
+}
+
+/// Convert an operand lexed by [`raw_operation`] to an object, parsing the bytes it borrows as
+/// [`content`] does.
+pub(crate) fn raw_operand_object(operand: &RawOperand) -> Option<Object> {
+    let span = |bytes| ParserInput::new_extra(bytes, "content operand");
+    match *operand {
+        RawOperand::Null => Some(Object::Null),
+        RawOperand::Boolean(value) => Some(Object::Boolean(value)),
+        RawOperand::Number(token)
+        | RawOperand::Name(token)
+        | RawOperand::String(token, _)
+        | RawOperand::Array(token)
+        | RawOperand::Dictionary(token) => strip_nom(self::operand(span(token))),
+        RawOperand::Token(token) => Some(Object::RawToken(token.to_vec())),
+        RawOperand::InlineImage(image) => {
+            strip_nom(inline_image_impl(span(image))).and_then(|(mut operands, _)| operands.pop())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn test_span(s: &[u8]) -> ParserInput {
+    fn test_span(s: &[u8]) -> ParserInput<'_> {
         LocatedSpan::new_extra(s, "test")
     }
 
@@ -759,6 +1132,34 @@ fn content_with_comments() {
         assert_eq!(out.operations.len(), 3);
     }
 
+    #[test]
+    fn content_with_comments_between_operands() {
+        let input = b"[1 % first\n 2 %second\n] % after the array\n0 % between operands\nd
+/Span << /ActualText % before the value\n(Hi) >> BDC EMC % at the end";
+        let out = content(test_span(input)).unwrap();
+        let operators: Vec<_> = out.operations.iter().map(|it| it.operator.as_str()).collect();
+        assert_eq!(operators, ["d", "BDC", "EMC"]);
+        assert_eq!(out.operations[0].operands[0].as_array().unwrap().len(), 2);
+        let properties = out.operations[1].operands[1].as_dict().unwrap();
+        assert_eq!(properties.get(b"ActualText").unwrap().as_str().unwrap(), b"Hi");
+    }
+
+    #[test]
+    fn inline_image_with_comments() {
+        let input = b"BI % image dictionary
+/W 2 /H % height
+1 /CS /G % color space
+/BPC 8
+ID %0EI Q";
+        let out = content(test_span(input)).unwrap();
+        let operators: Vec<_> = out.operations.iter().map(|it| it.operator.as_str()).collect();
+        assert_eq!(operators, ["BI", "Q"]);
+        let image = out.operations[0].operands[0].as_stream().unwrap();
+        assert_eq!(image.dict.get(b"H").unwrap().as_i64().unwrap(), 1);
+        assert_eq!(image.dict.get(b"CS").unwrap().as_name().unwrap(), b"G");
+        assert_eq!(image.content, b"%0");
+    }
+
     #[test]
     fn inline_image() {
         env_logger::init();
@@ -773,4 +1174,41 @@ fn inline_image() {
             b"00000z0z00zzz00z0zzz0zzzEI aazazaazzzaazazzzazzz"
         )
     }
+
+    #[test]
+    fn inline_image_with_filter() {
+        let input = b"BI /W 4 /H 4 /CS /G /BPC 8 /F /AHx
+ID
+00112233445566778899AABBCCDDEEFF>
+EI";
+        let out = super::inline_image(test_span(input)).unwrap().1;
+        assert_eq!(
+            &out.0[0].as_stream().unwrap().content,
+            b"00112233445566778899AABBCCDDEEFF>"
+        )
+    }
+
+    #[test]
+    fn inline_image_data_containing_end_operator() {
+        // The filters marking the end of their data give its length.
+        let input = b"BI /W 4 /H 1 /CS /G /BPC 8 /F /A85 ID\n8o EI 9~>\nEI Q";
+        let (rest, out) = super::inline_image(test_span(input)).unwrap();
+        assert_eq!(out.0[0].as_stream().unwrap().content, b"8o EI 9~>");
+        assert_eq!(rest.as_bytes(), b"Q");
+
+        let data = b"data EI with the end operator";
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, data).unwrap();
+        let encoded = encoder.finish().unwrap();
+        let input = [&b"BI /W 29 /H 1 /CS /G /BPC 8 /F /Fl ID "[..], &encoded, b" EI Q"].concat();
+        let (rest, out) = super::inline_image(test_span(&input)).unwrap();
+        assert_eq!(out.0[0].as_stream().unwrap().content, encoded);
+        assert_eq!(rest.as_bytes(), b"Q");
+
+        // Otherwise the data runs up to an `EI` followed by operations.
+        let input = b"BI /W 4 /H 1 /CS /G /BPC 8 /F /DCT ID\n\xff EI ab\xd8\xff EI 1 0 0 1 0 0 cm";
+        let (rest, out) = super::inline_image(test_span(input)).unwrap();
+        assert_eq!(out.0[0].as_stream().unwrap().content, b"\xff EI ab\xd8\xff");
+        assert_eq!(rest.as_bytes(), b"1 0 0 1 0 0 cm");
+    }
 }
@@ -2,27 +2,75 @@
 use log::warn;
 
 use crate::{
-    content::{Content, Operation},
+    cancel::check_cancel,
+    content::{
+        invalid_content, matrix_from_operands, multiply_matrix, operator_operands, Content, GlyphWidths, Operation,
+        TextState, IDENTITY_MATRIX,
+    },
     document::Document,
     encodings::Encoding,
-    error::ParseError,
+    error::{ParseError, XrefError},
+    logging::span,
     object::Object::Name,
+    optional_content::{LayerState, LayerVisibility},
     parser::ParserInput,
+    xobject::InlineImage,
     xref::{Xref, XrefEntry, XrefType},
-    Error, Result,
+    CancelToken, CertifiedChange, Error, Result,
 };
 use crate::{parser, Dictionary, Object, ObjectId, Stream};
-use std::{
-    collections::BTreeMap,
-    io::{Cursor, Read},
-};
+use std::{borrow::Cow, collections::BTreeMap, ops::Range, rc::Rc};
 
 impl Content<Vec<Operation>> {
     /// Decode content operations.
+    ///
+    /// Operands which aren't PDF objects, e.g. of private operators, are kept as
+    /// [`Object::RawToken`], which [`Content::encode`] writes back unchanged. Empty data, or data
+    /// with only whitespace and comments, decodes to no operations.
     pub fn decode(data: &[u8]) -> Result<Self> {
         parser::content(ParserInput::new_extra(data, "content operations"))
             .ok_or(ParseError::InvalidContentStream.into())
     }
+
+    /// Decode a fragment of content, e.g. written by hand, rejecting anything that isn't valid.
+    ///
+    /// Unlike [`Content::decode`], which stops at the first invalid bytes, the whole fragment must
+    /// consist of operations, and the operands of the operators of the PDF specification are
+    /// checked against [`operator_operands`]. Other operators
+    /// are only allowed in a `BX`/`EX` compatibility section, and tokens which aren't PDF objects,
+    /// which [`Content::decode`] keeps as [`Object::RawToken`], are rejected.
+    pub fn parse_fragment(bytes: &[u8]) -> Result<Self> {
+        let invalid = |offset: usize, reason: String| invalid_content(bytes, offset, reason);
+
+        let operations = parser::content_operations(ParserInput::new_extra(bytes, "content fragment"))
+            .map_err(|offset| invalid(offset, "not a content operation".to_string()))?;
+        let mut compatibility_depth = 0usize;
+        for (offset, operation) in &operations {
+            match operation.operator.as_str() {
+                "BX" => compatibility_depth += 1,
+                "EX" => compatibility_depth = compatibility_depth.saturating_sub(1),
+                _ => {}
+            }
+            if let Some(Object::RawToken(token)) =
+                operation.operands.iter().find(|it| matches!(it, Object::RawToken(_)))
+            {
+                let reason = format!("unrecognized token `{}`", String::from_utf8_lossy(token));
+                return Err(invalid(*offset, reason));
+            }
+            if let Some(reason) = operation.operand_mismatch() {
+                return Err(invalid(*offset, reason));
+            }
+            if operator_operands(&operation.operator).is_none()
+                && operation.operator != "BI"
+                && compatibility_depth == 0
+            {
+                return Err(invalid(*offset, format!("unknown operator `{}`", operation.operator)));
+            }
+        }
+        Ok(Content {
+            operations: operations.into_iter().map(|(_, operation)| operation).collect(),
+        })
+    }
 }
 
 impl Stream {
@@ -34,20 +82,53 @@ pub fn decode_content(&self) -> Result<Content<Vec<Operation>>> {
 
 impl Document {
     /// Get decoded page content;
-    pub fn get_and_decode_page_content(&self, page_id: ObjectId) -> Result<Content<Vec<Operation>>> {
+    pub fn get_and_decode_page_content(&self, page_id: impl Into<ObjectId>) -> Result<Content<Vec<Operation>>> {
+        let page_id = page_id.into();
         let content_data = self.get_page_content(page_id)?;
         Content::decode(&content_data)
     }
 
     /// Add content to a page. All existing content will be unchanged.
-    pub fn add_to_page_content(&mut self, page_id: ObjectId, content: Content<Vec<Operation>>) -> Result<()> {
+    pub fn add_to_page_content(
+        &mut self, page_id: impl Into<ObjectId>, content: Content<Vec<Operation>>,
+    ) -> Result<()> {
+        let page_id = page_id.into();
         let content_data = Content::encode(&content)?;
         self.add_page_contents(page_id, content_data)?;
         Ok(())
     }
 
+    /// Add a fragment of content to a page after checking it with [`Content::parse_fragment`].
+    ///
+    /// The fragment is appended as is, nothing is added if it is invalid.
+    pub fn add_page_contents_checked(&mut self, page_id: impl Into<ObjectId>, fragment: &[u8]) -> Result<()> {
+        let page_id = page_id.into();
+        Content::parse_fragment(fragment)?;
+        self.add_page_contents(page_id, fragment.to_vec())
+    }
+
     pub fn extract_text(&self, page_numbers: &[u32]) -> Result<String> {
-        let text_fragments = self.extract_text_chunks(page_numbers);
+        self.extract_text_with_options(page_numbers, &ExtractionOptions::default())
+    }
+
+    /// Extract the text of pages like [`Document::extract_text`], using the given options.
+    pub fn extract_text_with_options(&self, page_numbers: &[u32], options: &ExtractionOptions) -> Result<String> {
+        Self::join_text_chunks(self.extract_text_chunks_with_options(page_numbers, options))
+    }
+
+    /// Extract the text of the pages `page_ids`, like [`Document::extract_text`] does for page numbers.
+    ///
+    /// Fails with [`Error::NotAPage`] if an object isn't a page dictionary.
+    pub fn extract_text_for(&self, page_ids: &[ObjectId]) -> Result<String> {
+        self.extract_text_for_with_options(page_ids, &ExtractionOptions::default())
+    }
+
+    /// Extract the text of the pages `page_ids` like [`Document::extract_text_for`], using the given options.
+    pub fn extract_text_for_with_options(&self, page_ids: &[ObjectId], options: &ExtractionOptions) -> Result<String> {
+        Self::join_text_chunks(self.extract_text_chunks_for_with_options(page_ids, options))
+    }
+
+    fn join_text_chunks(text_fragments: Vec<Result<String>>) -> Result<String> {
         let mut text = String::new();
         for maybe_text_fragment in text_fragments.into_iter() {
             let text_fragment = maybe_text_fragment?;
@@ -58,22 +139,136 @@ pub fn extract_text(&self, page_numbers: &[u32]) -> Result<String> {
     }
 
     pub fn extract_text_chunks(&self, page_numbers: &[u32]) -> Vec<Result<String>> {
-        let pages: BTreeMap<u32, (u32, u16)> = self.get_pages();
-        page_numbers
-            .iter()
-            .flat_map(|page_number| {
-                let result = self.extract_text_chunks_from_page(&pages, *page_number);
-                match result {
-                    Ok(text_chunks) => text_chunks,
-                    Err(err) => vec![Err(err)],
-                }
-            })
-            .collect()
+        self.extract_text_chunks_with_options(page_numbers, &ExtractionOptions::default())
+    }
+
+    /// Extract text chunks like [`Document::extract_text_chunks`], using the given options.
+    pub fn extract_text_chunks_with_options(
+        &self, page_numbers: &[u32], options: &ExtractionOptions,
+    ) -> Vec<Result<String>> {
+        let _span = span!(DEBUG, "extract_text", pages = page_numbers.len());
+        let pages: BTreeMap<u32, ObjectId> = self.get_pages();
+        let page_ids = page_numbers.iter().map(|page_number| {
+            pages
+                .get(page_number)
+                .copied()
+                .ok_or(Error::PageNumberNotFound(*page_number))
+        });
+        self.extract_text_chunks_of(page_ids, options)
+    }
+
+    /// Extract the text chunks of the pages `page_ids`, like [`Document::extract_text_chunks`] does
+    /// for page numbers.
+    ///
+    /// An object which isn't a page dictionary gives an [`Error::NotAPage`] chunk.
+    pub fn extract_text_chunks_for(&self, page_ids: &[ObjectId]) -> Vec<Result<String>> {
+        self.extract_text_chunks_for_with_options(page_ids, &ExtractionOptions::default())
+    }
+
+    /// Extract text chunks like [`Document::extract_text_chunks_for`], using the given options.
+    pub fn extract_text_chunks_for_with_options(
+        &self, page_ids: &[ObjectId], options: &ExtractionOptions,
+    ) -> Vec<Result<String>> {
+        let _span = span!(DEBUG, "extract_text", pages = page_ids.len());
+        let page_ids = page_ids.iter().map(|&page_id| {
+            let is_page = self
+                .get_object(page_id)?
+                .as_dict()
+                .is_ok_and(|page| page.has_type(b"Page"));
+            if is_page {
+                Ok(page_id)
+            } else {
+                Err(Error::NotAPage(page_id))
+            }
+        });
+        self.extract_text_chunks_of(page_ids, options)
     }
 
-    fn extract_text_chunks_from_page(
-        &self, pages: &BTreeMap<u32, (u32, u16)>, page_number: u32,
+    fn extract_text_chunks_of(
+        &self, page_ids: impl Iterator<Item = Result<ObjectId>>, options: &ExtractionOptions,
+    ) -> Vec<Result<String>> {
+        let layers = LayerState::new(self, &options.layer_visibility);
+        let mut chunks = vec![];
+        for page_id in page_ids {
+            if let Err(err) = check_cancel(options.cancel.as_ref()) {
+                chunks.push(Err(err));
+                break;
+            }
+            let page_chunks = page_id.and_then(|page_id| {
+                self.extract_page_text_chunks(page_id, &layers, options.skip_clipping_text, &mut |_| {})
+            });
+            match page_chunks {
+                Ok(text_chunks) => chunks.extend(text_chunks),
+                Err(err) => chunks.push(Err(err)),
+            }
+        }
+        chunks
+    }
+
+    /// Extract the text chunks of a page, also reporting the events of the content walk to `also`,
+    /// so other collectors can share it.
+    pub(crate) fn extract_page_text_chunks<'a>(
+        &'a self, page_id: ObjectId, layers: &LayerState, skip_clipping: bool,
+        also: &mut dyn FnMut(ContentEvent<'_, 'a>),
     ) -> Result<Vec<Result<String>>> {
+        let fonts = self.get_page_fonts(page_id)?;
+        let (resource_dict, resource_ids) = self.get_page_resources(page_id)?;
+        let properties = resource_dict
+            .into_iter()
+            .chain(resource_ids.into_iter().filter_map(|id| self.get_dictionary(id).ok()))
+            .find_map(|resources| self.get_dict_in_dict(resources, b"Properties").ok());
+        self.extract_text_chunks_with(fonts, properties, layers, skip_clipping, |visit| {
+            self.walk_page_content(page_id, &mut |event| {
+                also(event);
+                visit(event);
+            })
+        })
+    }
+
+    /// Extract the text of the normal appearance stream of an annotation, such as the value shown
+    /// by a form field.
+    ///
+    /// Fonts are looked up in the resources of the appearance stream first, then in the default
+    /// resources of the interactive form, see [`Document::get_appearance_fonts`].
+    pub fn extract_annotation_text(&self, annotation: &Dictionary) -> Result<String> {
+        let appearance = self.get_annotation_appearance(annotation)?;
+        let content = appearance
+            .decompressed_content()
+            .map_or(Cow::Borrowed(&appearance.content), Cow::Owned);
+        let mut xobjects = BTreeMap::new();
+        if let Ok(resources) = self.get_dict_in_dict(&appearance.dict, b"Resources") {
+            self.collect_xobjects_from_resources(resources, &mut xobjects);
+        }
+        let fonts = self.get_appearance_fonts(appearance);
+        let properties = self
+            .get_dict_in_dict(&appearance.dict, b"Resources")
+            .and_then(|resources| self.get_dict_in_dict(resources, b"Properties"))
+            .ok();
+        let layers = LayerState::new(self, &LayerVisibility::AllVisible);
+        let chunks = self.extract_text_chunks_with(fonts, properties, &layers, false, |visit| {
+            let operations = Content::iter_operations(&content).cloned_operations();
+            self.walk_content(
+                operations,
+                &xobjects,
+                IDENTITY_MATRIX,
+                &mut FormVisits::default(),
+                visit,
+            );
+            Ok(())
+        })?;
+        chunks.into_iter().collect()
+    }
+
+    /// Extract text chunks from the content reported by `walk`, which is shown with `fonts` and
+    /// names optional content from `properties`. Text in layers hidden by `layers` is skipped, as is
+    /// text shown in a clipping rendering mode if `skip_clipping` is set.
+    fn extract_text_chunks_with<'a, W>(
+        &'a self, fonts: BTreeMap<Vec<u8>, &'a Dictionary>, properties: Option<&'a Dictionary>, layers: &LayerState,
+        skip_clipping: bool, walk: W,
+    ) -> Result<Vec<Result<String>>>
+    where
+        W: FnOnce(&mut dyn FnMut(ContentEvent<'_, 'a>)) -> Result<()>,
+    {
         fn collect_text(text: &mut String, encoding: &Encoding, operands: &[Object]) -> Result<()> {
             for operand in operands.iter() {
                 match operand {
@@ -84,74 +279,152 @@ fn collect_text(text: &mut String, encoding: &Encoding, operands: &[Object]) ->
                         collect_text(text, encoding, arr)?;
                         text.push(' ');
                     }
-                    Object::Integer(i) => {
-                        if *i < -100 {
-                            text.push(' ');
-                        }
+                    Object::Integer(i) if *i < -100 => {
+                        text.push(' ');
                     }
                     _ => {}
                 }
             }
             Ok(())
         }
+        fn font_encodings<'a>(
+            doc: &'a Document, fonts: BTreeMap<Vec<u8>, &'a Dictionary>, errors: &mut Vec<Result<String>>,
+        ) -> BTreeMap<Vec<u8>, Rc<Encoding<'a>>> {
+            fonts
+                .into_iter()
+                .filter_map(|(name, font)| match font.get_font_encoding(doc) {
+                    Ok(it) => Some((name, Rc::new(it))),
+                    Err(err) => {
+                        errors.push(Err(err));
+                        None
+                    }
+                })
+                .collect()
+        }
+        let skip_clipping_text = |state: &TextState| skip_clipping && state.is_clipping();
         let mut collected_chunks_and_errs: Vec<std::result::Result<String, Error>> = Vec::new();
 
-        let page_id = *pages.get(&page_number).ok_or(Error::PageNumberNotFound(page_number))?;
-        let fonts = self.get_page_fonts(page_id)?;
-        let encodings: BTreeMap<Vec<u8>, Encoding> = fonts
-            .into_iter()
-            .filter_map(|(name, font)| match font.get_font_encoding(self) {
-                Ok(it) => Some((name, it)),
-                Err(err) => {
-                    collected_chunks_and_errs.push(Err(err));
-                    None
-                }
-            })
-            .collect();
-        let content_data = self.get_page_content(page_id)?;
-        let content = Content::decode(&content_data)?;
+        // The fonts of the page, followed by those of each Form XObject being painted.
+        let mut encoding_scopes = vec![font_encodings(self, fonts, &mut collected_chunks_and_errs)];
+        let mut saved_encodings = vec![];
 
         // each text with different encoding is extracted as separate chunk
-        let mut current_encoding = None;
+        let mut current_encoding: Option<Rc<Encoding>> = None;
         let mut current_text = String::new();
-        for operation in &content.operations {
-            match operation.operator.as_ref() {
-                "Tf" => {
-                    let current_font = operation
-                        .operands
-                        .first()
-                        .ok_or_else(|| Error::Syntax("missing font operand".to_string()))?
-                        .as_name();
-                    current_encoding = match current_font {
-                        Ok(font) => encodings.get(font),
-                        Err(err) => {
-                            collected_chunks_and_errs.push(Err(err));
-                            None
+        let mut text_state = TextState::default();
+        let mut saved_text_states = vec![];
+        // The text state, like the rendering mode, is part of the graphics state saved by q and Q.
+        let mut saved_graphics_states = vec![];
+        // Whether the content of each open marked content sequence is hidden, by it or by a sequence
+        // it is nested in. A Form XObject adds an entry for its own /OC, and the length of the stack
+        // before it is saved.
+        let mut marked_content: Vec<bool> = vec![];
+        let mut saved_marked_content = vec![];
+        let mut property_scopes = vec![properties];
+        walk(&mut |event| match event {
+            ContentEvent::Operation(operation, _) => {
+                let hidden = marked_content.last() == Some(&true);
+                // Text moved to another line within a text object starts a new line of the extracted text.
+                if text_state.apply(operation) && !hidden && !current_text.is_empty() && !current_text.ends_with('\n') {
+                    current_text.push('\n');
+                }
+                match operation.operator.as_ref() {
+                    "q" => saved_graphics_states.push(text_state),
+                    "Q" => {
+                        if let Some(state) = saved_graphics_states.pop() {
+                            text_state = TextState {
+                                text_matrix: text_state.text_matrix,
+                                line_matrix: text_state.line_matrix,
+                                ..state
+                            };
                         }
-                    };
+                    }
+                    "BMC" => marked_content.push(hidden),
+                    "BDC" => {
+                        let hides = match operation.operands.as_slice() {
+                            [Object::Name(tag), membership, ..] if tag == b"OC" => {
+                                let membership = match membership {
+                                    Object::Name(name) => property_scopes
+                                        .last()
+                                        .copied()
+                                        .flatten()
+                                        .and_then(|properties| properties.get(name).ok()),
+                                    membership => Some(membership),
+                                };
+                                membership.is_some_and(|membership| !layers.is_visible(self, membership))
+                            }
+                            _ => false,
+                        };
+                        marked_content.push(hidden || hides);
+                    }
+                    // A sequence can't end outside of the form in which it started.
+                    "EMC" if marked_content.len() > saved_marked_content.last().map_or(0, |len| len + 1) => {
+                        marked_content.pop();
+                    }
+                    "Tf" => {
+                        let current_font = operation
+                            .operands
+                            .first()
+                            .ok_or_else(|| Error::Syntax("missing font operand".to_string()))
+                            .and_then(Object::as_name);
+                        current_encoding = match current_font {
+                            Ok(font) => encoding_scopes.iter().rev().find_map(|it| it.get(font)).cloned(),
+                            Err(err) => {
+                                collected_chunks_and_errs.push(Err(err));
+                                None
+                            }
+                        };
 
-                    if !current_text.is_empty() {
-                        collected_chunks_and_errs.push(Ok(current_text));
-                        current_text = String::new();
+                        if !current_text.is_empty() {
+                            collected_chunks_and_errs.push(Ok(std::mem::take(&mut current_text)));
+                        }
                     }
-                }
-                "Tj" | "TJ" => match current_encoding {
-                    Some(encoding) => {
-                        let res = collect_text(&mut current_text, encoding, &operation.operands);
-                        if let Err(err) = res {
-                            collected_chunks_and_errs.push(Err(err));
+                    "Tj" | "TJ" | "'" | "\"" if !hidden && !skip_clipping_text(&text_state) => {
+                        match &current_encoding {
+                            Some(encoding) => {
+                                let res = collect_text(&mut current_text, encoding, shown_operands(operation));
+                                if let Err(err) = res {
+                                    collected_chunks_and_errs.push(Err(err));
+                                }
+                            }
+                            None => warn!("Could not decode extracted text"),
                         }
                     }
-                    None => warn!("Could not decode extracted text"),
-                },
-                "ET" => {
-                    if !current_text.ends_with('\n') {
-                        current_text.push('\n')
+                    "ET" if !hidden && !current_text.ends_with('\n') => {
+                        current_text.push('\n');
                     }
+                    _ => {}
                 }
-                _ => {}
             }
-        }
+            ContentEvent::EnterForm(resources, form) => {
+                // Painting a form saves and restores the graphics state, including the font.
+                saved_encodings.push(current_encoding.clone());
+                saved_text_states.push(text_state);
+                let mut fonts = BTreeMap::new();
+                if let Some(resources) = resources {
+                    self.collect_fonts_from_resources(resources, &mut fonts);
+                }
+                encoding_scopes.push(font_encodings(self, fonts, &mut collected_chunks_and_errs));
+
+                saved_marked_content.push(marked_content.len());
+                let hides = form
+                    .get(b"OC")
+                    .is_ok_and(|membership| !layers.is_visible(self, membership));
+                marked_content.push(marked_content.last() == Some(&true) || hides);
+                let properties = match resources {
+                    Some(resources) => self.get_dict_in_dict(resources, b"Properties").ok(),
+                    None => property_scopes.last().copied().flatten(),
+                };
+                property_scopes.push(properties);
+            }
+            ContentEvent::LeaveForm => {
+                marked_content.truncate(saved_marked_content.pop().unwrap_or_default());
+                property_scopes.pop();
+                encoding_scopes.pop();
+                current_encoding = saved_encodings.pop().flatten();
+                text_state = saved_text_states.pop().unwrap_or(text_state);
+            }
+        })?;
         if !current_text.is_empty() {
             collected_chunks_and_errs.push(Ok(current_text));
         }
@@ -159,50 +432,390 @@ fn collect_text(text: &mut String, encoding: &Encoding, operands: &[Object]) ->
         Ok(collected_chunks_and_errs)
     }
 
-    pub fn replace_text(&mut self, page_number: u32, text: &str, other_text: &str) -> Result<()> {
+    /// Replace the strings shown on a page whose text equals `text` as a whole with `other_text`.
+    ///
+    /// Strings whose font or encoding can't be resolved, or whose font can't encode `other_text`,
+    /// are left untouched and counted in [`TextReplacement::skipped_operands`].
+    ///
+    /// Strings shown in a clipping rendering mode are replaced too, which changes the clipping path
+    /// and with it what is visible of the content painted after them until the graphics state is
+    /// restored, see [`TextState::is_clipping`].
+    pub fn replace_text(&mut self, page_number: u32, text: &str, other_text: &str) -> Result<TextReplacement> {
+        self.rewrite_page_text(page_number, |font, bytes| {
+            let decoded: String = font.decode_codes(bytes)?.into_iter().map(|(_, text)| text).collect();
+            if decoded != text {
+                return Some(0);
+            }
+            *bytes = font.encode(other_text)?;
+            Some(1)
+        })
+    }
+
+    /// Replace every occurrence of `text` inside the strings shown on a page with `other_text`.
+    ///
+    /// Strings are split into character codes of the width used by their font, so a replacement
+    /// never cuts a multi-byte code in half. Occurrences spanning several strings are not replaced.
+    pub fn replace_partial_text(&mut self, page_number: u32, text: &str, other_text: &str) -> Result<TextReplacement> {
+        if text.is_empty() {
+            return Ok(TextReplacement::default());
+        }
+        self.rewrite_page_text(page_number, |font, bytes| {
+            let codes = font.decode_codes(bytes)?;
+            // Offsets in the decoded text at which each character code starts.
+            let mut decoded = String::new();
+            let mut boundaries = Vec::with_capacity(codes.len() + 1);
+            for (_, text) in &codes {
+                boundaries.push(decoded.len());
+                decoded.push_str(text);
+            }
+            boundaries.push(decoded.len());
+
+            let mut replacement = None;
+            let mut output = Vec::with_capacity(bytes.len());
+            let mut next_code = 0;
+            let mut count = 0;
+            for (start, matched) in decoded.match_indices(text) {
+                let first = boundaries.binary_search(&start);
+                let end = boundaries.binary_search(&(start + matched.len()));
+                let (Ok(first), Ok(end)) = (first, end) else {
+                    continue;
+                };
+                if first < next_code {
+                    continue;
+                }
+                if replacement.is_none() {
+                    replacement = Some(font.encode(other_text)?);
+                }
+                output.extend_from_slice(&bytes[next_code * font.code_width..first * font.code_width]);
+                output.extend_from_slice(replacement.as_deref().unwrap_or_default());
+                next_code = end;
+                count += 1;
+            }
+            if count > 0 {
+                output.extend_from_slice(&bytes[next_code * font.code_width..]);
+                *bytes = output;
+            }
+            Some(count)
+        })
+    }
+
+    /// Apply `rewrite` to every string shown on a page, together with the font it is shown with.
+    ///
+    /// `rewrite` returns the number of replacements made, or `None` if it refused to modify the string.
+    fn rewrite_page_text<F>(&mut self, page_number: u32, mut rewrite: F) -> Result<TextReplacement>
+    where
+        F: FnMut(&TextFont, &mut Vec<u8>) -> Option<usize>,
+    {
+        self.check_certification(CertifiedChange::Other)?;
         let page = page_number.saturating_sub(1) as usize;
         let page_id = self
             .page_iter()
             .nth(page)
             .ok_or(Error::PageNumberNotFound(page_number))?;
-        let encodings: BTreeMap<Vec<u8>, Encoding> = self
-            .get_page_fonts(page_id)?
-            .into_iter()
-            .map(|(name, font)| font.get_font_encoding(self).map(|it| (name, it)))
-            .collect::<Result<BTreeMap<Vec<u8>, Encoding>>>()?;
-        let content_data = self.get_page_content(page_id)?;
-        let mut content = Content::decode(&content_data)?;
-        let mut current_encoding = None;
+        let fonts = self.text_fonts(self.get_page_fonts(page_id)?);
+        let mut content = self.get_and_decode_page_content(page_id)?;
+
+        let mut report = TextReplacement::default();
+        // The font is part of the graphics state, which is saved and restored by q and Q.
+        let mut current_font: Option<Vec<u8>> = None;
+        let mut font_stack = vec![];
         for operation in &mut content.operations {
-            match operation.operator.as_ref() {
+            let strings: Vec<&mut Vec<u8>> = match operation.operator.as_ref() {
+                "q" => {
+                    font_stack.push(current_font.clone());
+                    continue;
+                }
+                "Q" => {
+                    if let Some(font) = font_stack.pop() {
+                        current_font = font;
+                    }
+                    continue;
+                }
                 "Tf" => {
-                    let current_font = operation
+                    let font = operation
                         .operands
                         .first()
                         .ok_or_else(|| Error::Syntax("missing font operand".to_string()))?
                         .as_name()?;
-                    current_encoding = encodings.get(current_font);
+                    current_font = Some(font.to_vec());
+                    continue;
+                }
+                "Tj" | "'" | "\"" => operation
+                    .operands
+                    .last_mut()
+                    .and_then(|it| it.as_str_mut().ok())
+                    .into_iter()
+                    .collect(),
+                "TJ" => operation
+                    .operands
+                    .first_mut()
+                    .and_then(|it| it.as_array_mut().ok())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|it| it.as_str_mut().ok())
+                    .collect(),
+                _ => continue,
+            };
+            let font = current_font.as_ref().and_then(|name| fonts.get(name));
+            for bytes in strings {
+                match font.and_then(|font| rewrite(font, bytes)) {
+                    Some(count) => report.replaced += count,
+                    None => report.skipped_operands += 1,
+                }
+            }
+        }
+        if report.skipped_operands > 0 {
+            warn!(
+                "Skipped {} text operands on page {page_number} which could not be decoded or encoded",
+                report.skipped_operands
+            );
+        }
+        if report.replaced > 0 {
+            let modified_content = content.encode()?;
+            self.change_page_content(page_id, modified_content)?;
+        }
+        Ok(report)
+    }
+
+    /// Resolve the encoding, code width and glyph widths of fonts, skipping those which can't be used.
+    fn text_fonts<'a>(&'a self, fonts: BTreeMap<Vec<u8>, &'a Dictionary>) -> BTreeMap<Vec<u8>, TextFont<'a>> {
+        fonts
+            .into_iter()
+            .filter_map(
+                |(name, font)| match (font_code_width(font), font.get_font_encoding(self)) {
+                    (Some(code_width), Ok(encoding)) => {
+                        let widths = GlyphWidths::from_font(font, |it| self.dereference(it).map_or(it, |(_, it)| it));
+                        Some((
+                            name,
+                            TextFont {
+                                encoding,
+                                code_width,
+                                widths,
+                            },
+                        ))
+                    }
+                    (None, _) => {
+                        warn!(
+                            "Font {} uses character codes of variable width",
+                            String::from_utf8_lossy(&name)
+                        );
+                        None
+                    }
+                    (_, Err(err)) => {
+                        warn!(
+                            "Could not get the encoding of font {}: {err}",
+                            String::from_utf8_lossy(&name)
+                        );
+                        None
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// Get the strings shown on a page, including those of the Form XObjects it paints,
+    /// with the font and text state they are shown with.
+    ///
+    /// Strings shown with a font whose encoding or character codes can't be resolved are skipped.
+    pub fn get_page_text_runs(&self, page_id: impl Into<ObjectId>) -> Result<Vec<TextRun>> {
+        let page_id = page_id.into();
+        let mut collector = TextRunCollector::new(self, page_id)?;
+        self.walk_page_content(page_id, &mut |event| collector.visit(event))?;
+        Ok(collector.runs)
+    }
+
+    /// Get the strings shown on page `page_number` with their origin and the position of each of
+    /// their characters, built from [`Document::get_page_text_runs`].
+    ///
+    /// Strings shown by the Form XObjects the page paints are included, marked by
+    /// [`TextChunk::in_form`] so they can be skipped.
+    pub fn get_page_text_positions(&self, page_number: u32) -> Result<Vec<TextChunk>> {
+        let page_id = self
+            .get_pages()
+            .get(&page_number)
+            .copied()
+            .ok_or(Error::PageNumberNotFound(page_number))?;
+        let runs = self.get_page_text_runs(page_id)?;
+        Ok(runs.into_iter().map(TextChunk::from).collect())
+    }
+
+    /// Find the occurrences of `needle` on a page, returning the rectangle `[llx lly urx ury]`
+    /// in user space covered by each of them.
+    ///
+    /// Occurrences are searched within each string shown, see [`Document::get_page_text_runs`].
+    pub fn search_text(&self, page_id: impl Into<ObjectId>, needle: &str) -> Result<Vec<[f32; 4]>> {
+        let page_id = page_id.into();
+        let mut rects = vec![];
+        if needle.is_empty() {
+            return Ok(rects);
+        }
+        let needle_chars = needle.chars().count();
+        for run in self.get_page_text_runs(page_id)? {
+            // Characters are counted from the previous occurrence, not from the start of the string.
+            let (mut start, mut counted) = (0, 0);
+            for (index, _) in run.text.match_indices(needle) {
+                start += run.text[counted..index].chars().count();
+                counted = index;
+                rects.push(run.bounds(start..start + needle_chars));
+            }
+        }
+        Ok(rects)
+    }
+
+    /// Get the inline images (`BI` ... `ID` ... `EI`) painted by the content stream of a page,
+    /// including those in the Form XObjects it paints.
+    ///
+    /// The abbreviated keys and names of an inline image dictionary are expanded to their long forms,
+    /// and the data is decoded by the filters lopdf decodes, so [`InlineImage::image`] can be handled like
+    /// the images of [`Document::get_page_images`]. The placement rectangle of each image is computed from
+    /// the transformation matrix in effect when the image is painted.
+    pub fn get_page_inline_images(&self, page_id: impl Into<ObjectId>) -> Result<Vec<InlineImage>> {
+        let page_id = page_id.into();
+        let mut images = vec![];
+        let mut result = Ok(());
+        self.walk_page_content(page_id, &mut |event| {
+            if let ContentEvent::Operation(operation, ctm) = event {
+                if result.is_err() {
+                    return;
                 }
-                "Tj" => match current_encoding {
-                    Some(encoding) => try_to_replace_encoded_text(operation, encoding, text, other_text)?,
-                    None => {
-                        warn!("Could not decode extracted text, some of the occurances might not be properly replaced")
+                for image in inline_images(page_id, operation, ctm) {
+                    match image {
+                        Ok(image) => images.push(image),
+                        Err(err) => result = Err(err),
                     }
+                }
+            }
+        })?;
+        result.map(|_| images)
+    }
+
+    /// Walk the content of a page, following `Do` operators into the Form XObjects they paint.
+    ///
+    /// Each operation is reported with the CTM in effect, which includes the `/Matrix` of the forms
+    /// being painted. Forms nested deeper than [`MAX_FORM_DEPTH`] or painting themselves are skipped,
+    /// and so are those which would take the operations reported from forms beyond [`MAX_FORM_OPERATIONS`].
+    pub(crate) fn walk_page_content<'a>(
+        &'a self, page_id: ObjectId, visit: &mut dyn FnMut(ContentEvent<'_, 'a>),
+    ) -> Result<()> {
+        let content = self.get_page_content(page_id)?;
+        let mut xobjects = BTreeMap::new();
+        let (resource_dict, resource_ids) = self.get_page_resources(page_id)?;
+        let resources = resource_dict
+            .into_iter()
+            .chain(resource_ids.into_iter().filter_map(|id| self.get_dictionary(id).ok()));
+        for resources in resources {
+            self.collect_xobjects_from_resources(resources, &mut xobjects);
+        }
+        let operations = Content::iter_operations(&content).cloned_operations();
+        self.walk_content(
+            operations,
+            &xobjects,
+            IDENTITY_MATRIX,
+            &mut FormVisits::default(),
+            visit,
+        );
+        Ok(())
+    }
+
+    fn walk_content<'a, O: std::borrow::Borrow<Operation>>(
+        &'a self, operations: impl Iterator<Item = O>, xobjects: &BTreeMap<Vec<u8>, ObjectId>, mut ctm: [f32; 6],
+        forms: &mut FormVisits, visit: &mut dyn FnMut(ContentEvent<'_, 'a>),
+    ) {
+        let mut graphics_stack = vec![];
+        for operation in operations {
+            let operation = operation.borrow();
+            match operation.operator.as_ref() {
+                "q" => graphics_stack.push(ctm),
+                "Q" => ctm = graphics_stack.pop().unwrap_or(ctm),
+                "cm" => match matrix_from_operands(&operation.operands) {
+                    Some(matrix) => ctm = multiply_matrix(&matrix, &ctm),
+                    None => warn!("Invalid operands of cm operator: {:?}", operation.operands),
                 },
                 _ => {}
             }
+            visit(ContentEvent::Operation(operation, &ctm));
+            if operation.operator != "Do" {
+                continue;
+            }
+
+            let name = operation.operands.first().and_then(|it| it.as_name().ok());
+            let Some(&form_id) = name.and_then(|name| xobjects.get(name)) else {
+                continue;
+            };
+            let Ok(form) = self.get_object(form_id).and_then(Object::as_stream) else {
+                continue;
+            };
+            if form.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Form") {
+                continue;
+            }
+            if forms.painting.contains(&form_id) || forms.painting.len() >= MAX_FORM_DEPTH {
+                warn!("Skipping Form XObject {form_id:?}, which is nested too deep or paints itself");
+                continue;
+            }
+            // Each form is decoded once, however many times it is painted.
+            let operations = forms
+                .decoded
+                .entry(form_id)
+                .or_insert_with(|| {
+                    let content = form
+                        .decompressed_content()
+                        .map_or(Cow::Borrowed(&form.content), Cow::Owned);
+                    Rc::new(Content::iter_operations(&content).cloned_operations().collect())
+                })
+                .clone();
+            if operations.len() > forms.budget {
+                warn!("Skipping Form XObject {form_id:?}, as too many operations of forms were painted");
+                continue;
+            }
+            forms.budget -= operations.len();
+            let form_ctm = match form.dict.get_as::<[f32; 6]>(b"Matrix") {
+                Ok(matrix) => multiply_matrix(&matrix, &ctm),
+                Err(_) => ctm,
+            };
+
+            // A form without resources uses those of the content painting it.
+            let resources = form
+                .dict
+                .get(b"Resources")
+                .and_then(|it| self.dereference(it))
+                .and_then(|(_, it)| it.as_dict())
+                .ok();
+            let mut form_xobjects = BTreeMap::new();
+            if let Some(resources) = resources {
+                self.collect_xobjects_from_resources(resources, &mut form_xobjects);
+            }
+            visit(ContentEvent::EnterForm(resources, &form.dict));
+            forms.painting.push(form_id);
+            let form_xobjects = if resources.is_some() { &form_xobjects } else { xobjects };
+            self.walk_content(operations.iter(), form_xobjects, form_ctm, forms, visit);
+            forms.painting.pop();
+            visit(ContentEvent::LeaveForm);
+        }
+    }
+
+    /// Add the XObjects of a resource dictionary which aren't in `xobjects` yet.
+    pub(crate) fn collect_xobjects_from_resources(
+        &self, resources: &Dictionary, xobjects: &mut BTreeMap<Vec<u8>, ObjectId>,
+    ) {
+        let entries = resources
+            .get(b"XObject")
+            .and_then(|it| self.dereference(it))
+            .and_then(|(_, it)| it.as_dict());
+        if let Ok(entries) = entries {
+            for (name, value) in entries {
+                if let Ok(id) = value.as_reference() {
+                    xobjects.entry(name.clone()).or_insert(id);
+                }
+            }
         }
-        let modified_content = content.encode()?;
-        self.change_page_content(page_id, modified_content)
     }
 
     pub fn insert_image(
-        &mut self, page_id: ObjectId, img_object: Stream, position: (f32, f32), size: (f32, f32),
+        &mut self, page_id: impl Into<ObjectId>, img_object: Stream, position: (f32, f32), size: (f32, f32),
     ) -> Result<()> {
+        let page_id = page_id.into();
         let img_id = self.add_object(img_object);
-        let img_name = format!("X{}", img_id.0);
-
-        self.add_xobject(page_id, img_name.as_bytes(), img_id)?;
+        let img_name = self.ensure_resource(page_id, b"XObject", img_id.into())?;
 
         let mut content = self.get_and_decode_page_content(page_id)?;
         content.operations.push(Operation::new("q", vec![]));
@@ -217,175 +830,718 @@ pub fn insert_image(
                 position.1.into(),
             ],
         ));
-        content
-            .operations
-            .push(Operation::new("Do", vec![Name(img_name.as_bytes().to_vec())]));
+        content.operations.push(Operation::new("Do", vec![Name(img_name)]));
         content.operations.push(Operation::new("Q", vec![]));
 
         self.change_page_content(page_id, content.encode()?)
     }
 
-    pub fn insert_form_object(&mut self, page_id: ObjectId, form_obj: Stream) -> Result<()> {
+    pub fn insert_form_object(&mut self, page_id: impl Into<ObjectId>, form_obj: Stream) -> Result<()> {
+        let page_id = page_id.into();
         let form_id = self.add_object(form_obj);
-        let form_name = format!("X{}", form_id.0);
+        let form_name = self.ensure_resource(page_id, b"XObject", form_id.into())?;
 
         let mut content = self.get_and_decode_page_content(page_id)?;
         content.operations.insert(0, Operation::new("q", vec![]));
         content.operations.push(Operation::new("Q", vec![]));
-        content
-            .operations
-            .push(Operation::new("Do", vec![Name(form_name.as_bytes().to_vec())]));
+        content.operations.push(Operation::new("Do", vec![Name(form_name)]));
         let modified_content = content.encode()?;
-        self.add_xobject(page_id, form_name, form_id)?;
 
         self.change_page_content(page_id, modified_content)
     }
 }
 
-fn try_to_replace_encoded_text(
-    operation: &mut Operation, encoding: &Encoding, text_to_replace: &str, replacement: &str,
-) -> Result<()> {
-    for bytes in operation.operands.iter_mut().flat_map(Object::as_str_mut) {
-        let decoded_text = Document::decode_text(encoding, bytes)?;
-        if decoded_text == text_to_replace {
-            let encoded_bytes = Document::encode_text(encoding, replacement);
-            *bytes = encoded_bytes;
+/// Options of [`Document::extract_text_with_options`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractionOptions {
+    /// Which layers contribute text. Text in a marked content sequence or Form XObject belonging
+    /// to a hidden optional content group is skipped. By default all text is extracted.
+    pub layer_visibility: LayerVisibility,
+    /// Stop with an [`Error::Cancelled`] chunk once the token is cancelled, checked before each page.
+    pub cancel: Option<CancelToken>,
+    /// Skip the text shown in a clipping rendering mode, whose glyphs may only clip what is painted
+    /// after rather than being visible text, see [`TextState::is_clipping`].
+    pub skip_clipping_text: bool,
+}
+
+/// Summary of a text replacement on a page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextReplacement {
+    /// Number of replaced occurrences.
+    pub replaced: usize,
+    /// Number of shown strings left untouched because their font could not be resolved,
+    /// or the replacement could not be encoded with it.
+    pub skipped_operands: usize,
+}
+
+/// A string shown on a page, see [`Document::get_page_text_runs`].
+///
+/// The strings of a `TJ` array make a single run, and its numbers move the following glyphs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextRun {
+    /// Decoded text of the string.
+    pub text: String,
+    /// Name of the font resource the string is shown with.
+    pub font: Vec<u8>,
+    /// Text state the string is shown with, whose text matrix places its first glyph.
+    pub state: TextState,
+    /// CTM the string is shown with.
+    pub ctm: [f32; 6],
+    /// Position of each character of `text` from the start of the string, in unscaled text space units,
+    /// followed by the position of its end. Characters decoded from the same glyph share its position.
+    pub offsets: Vec<f32>,
+    /// Whether the string is shown by a Form XObject the page paints rather than by the page itself.
+    pub in_form: bool,
+}
+
+impl TextRun {
+    /// Rectangle `[llx lly urx ury]` in user space covered by a range of the characters of `text`.
+    pub fn bounds(&self, chars: Range<usize>) -> [f32; 4] {
+        let last = self.offsets.len().saturating_sub(1);
+        let start = self.offsets.get(chars.start.min(last)).copied().unwrap_or(0.0);
+        let end = self.offsets.get(chars.end.min(last)).copied().unwrap_or(0.0);
+        self.state.bounds(start, end, &self.ctm)
+    }
+
+    /// Total horizontal displacement of the string, in unscaled text space units.
+    pub fn width(&self) -> f32 {
+        self.offsets.last().copied().unwrap_or(0.0)
+    }
+
+    /// Origin in user space of each character of `text`, on the baseline moved by the text rise.
+    pub fn positions(&self) -> Vec<(f32, f32)> {
+        let matrix = multiply_matrix(&self.state.text_matrix, &self.ctm);
+        let chars = self.offsets.len().saturating_sub(1);
+        self.offsets[..chars]
+            .iter()
+            .map(|&x| {
+                let y = self.state.rise;
+                (
+                    matrix[0] * x + matrix[2] * y + matrix[4],
+                    matrix[1] * x + matrix[3] * y + matrix[5],
+                )
+            })
+            .collect()
+    }
+}
+
+/// A string shown on a page with where it is drawn, see [`Document::get_page_text_positions`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChunk {
+    /// Decoded text of the string.
+    pub text: String,
+    /// Name of the font resource the string is shown with.
+    pub font: Vec<u8>,
+    /// Font size `Tf`, in unscaled text space units.
+    pub font_size: f32,
+    /// Origin `(x, y)` of the string set by `Tm`, `Td`, `TD` and `T*`, the translation of the text matrix.
+    pub origin: (f32, f32),
+    /// Origin in user space of each character of `text`, see [`TextRun::positions`].
+    pub positions: Vec<(f32, f32)>,
+    /// Whether the string is shown by a Form XObject the page paints.
+    pub in_form: bool,
+    /// Whether the string is shown in a clipping rendering mode, see [`TextState::is_clipping`].
+    pub is_clipping: bool,
+}
+
+impl From<TextRun> for TextChunk {
+    fn from(run: TextRun) -> Self {
+        TextChunk {
+            positions: run.positions(),
+            origin: (run.state.text_matrix[4], run.state.text_matrix[5]),
+            font_size: run.state.font_size,
+            in_form: run.in_form,
+            is_clipping: run.state.is_clipping(),
+            text: run.text,
+            font: run.font,
         }
     }
-    Ok(())
 }
 
-/// Decode CrossReferenceStream
-pub fn decode_xref_stream(mut stream: Stream) -> Result<(Xref, Dictionary)> {
-    if stream.is_compressed() {
-        stream.decompress()?;
+/// Font of the strings shown in a content stream.
+struct TextFont<'a> {
+    encoding: Encoding<'a>,
+    /// Width in bytes of every character code.
+    code_width: usize,
+    widths: GlyphWidths,
+}
+
+impl TextFont<'_> {
+    /// Split a string into its character codes and their text, `None` if it doesn't consist of whole codes.
+    fn decode_codes<'b>(&self, bytes: &'b [u8]) -> Option<Vec<(&'b [u8], String)>> {
+        if bytes.len() % self.code_width != 0 {
+            return None;
+        }
+        bytes
+            .chunks(self.code_width)
+            .map(|code| self.encoding.code_to_string(code).ok().map(|text| (code, text)))
+            .collect()
     }
-    let mut dict = stream.dict;
-    let mut reader = Cursor::new(stream.content);
-    let size = dict
-        .get(b"Size")
-        .and_then(Object::as_i64)
-        .map_err(|_| ParseError::InvalidXref)?;
-    let mut xref = Xref::new(size as u32, XrefType::CrossReferenceStream);
-    {
-        let section_indice = dict
-            .get(b"Index")
-            .and_then(parse_integer_array)
-            .unwrap_or_else(|_| vec![0, size]);
-        let field_widths = dict
-            .get(b"W")
-            .and_then(parse_integer_array)
-            .map_err(|_| ParseError::InvalidXref)?;
-
-        if field_widths.len() < 3
-            || field_widths[0].is_negative()
-            || field_widths[1].is_negative()
-            || field_widths[2].is_negative()
-        {
-            return Err(ParseError::InvalidXref.into());
-        }
-
-        let mut bytes1 = vec![0_u8; field_widths[0] as usize];
-        let mut bytes2 = vec![0_u8; field_widths[1] as usize];
-        let mut bytes3 = vec![0_u8; field_widths[2] as usize];
-
-        for i in 0..section_indice.len() / 2 {
-            let start = section_indice[2 * i];
-            let count = section_indice[2 * i + 1];
-
-            for j in 0..count {
-                let entry_type = if !bytes1.is_empty() {
-                    read_big_endian_integer(&mut reader, bytes1.as_mut_slice())?
-                } else {
-                    1
-                };
-                match entry_type {
-                    0 => {
-                        // free object
-                        read_big_endian_integer(&mut reader, bytes2.as_mut_slice())?;
-                        read_big_endian_integer(&mut reader, bytes3.as_mut_slice())?;
-                    }
-                    1 => {
-                        // normal object
-                        let offset = read_big_endian_integer(&mut reader, bytes2.as_mut_slice())?;
-                        let generation = if !bytes3.is_empty() {
-                            read_big_endian_integer(&mut reader, bytes3.as_mut_slice())?
-                        } else {
-                            0
-                        } as u16;
-                        xref.insert((start + j) as u32, XrefEntry::Normal { offset, generation });
+
+    fn encode(&self, text: &str) -> Option<Vec<u8>> {
+        self.encoding.string_to_codes(text, self.code_width)
+    }
+}
+
+/// Collects the strings shown while walking the content of a page, see [`Document::get_page_text_runs`].
+pub(crate) struct TextRunCollector<'a> {
+    doc: &'a Document,
+    /// The fonts of the page, followed by those of each Form XObject being painted.
+    font_scopes: Vec<BTreeMap<Vec<u8>, TextFont<'a>>>,
+    /// The text state is part of the graphics state, except for the text matrices.
+    text_state: TextState,
+    current_font: Option<Vec<u8>>,
+    saved_states: Vec<(TextState, Option<Vec<u8>>)>,
+    pub(crate) runs: Vec<TextRun>,
+}
+
+impl<'a> TextRunCollector<'a> {
+    pub(crate) fn new(doc: &'a Document, page_id: ObjectId) -> Result<Self> {
+        Ok(TextRunCollector {
+            doc,
+            font_scopes: vec![doc.text_fonts(doc.get_page_fonts(page_id)?)],
+            text_state: TextState::default(),
+            current_font: None,
+            saved_states: vec![],
+            runs: vec![],
+        })
+    }
+
+    pub(crate) fn visit(&mut self, event: ContentEvent<'_, 'a>) {
+        match event {
+            ContentEvent::Operation(operation, ctm) => {
+                self.text_state.apply(operation);
+                match operation.operator.as_ref() {
+                    "q" => self.saved_states.push((self.text_state, self.current_font.clone())),
+                    "Q" => {
+                        if let Some((state, font)) = self.saved_states.pop() {
+                            self.text_state = TextState {
+                                text_matrix: self.text_state.text_matrix,
+                                line_matrix: self.text_state.line_matrix,
+                                ..state
+                            };
+                            self.current_font = font;
+                        }
                     }
-                    2 => {
-                        // compressed object
-                        let container = read_big_endian_integer(&mut reader, bytes2.as_mut_slice())?;
-                        let index = read_big_endian_integer(&mut reader, bytes3.as_mut_slice())? as u16;
-                        xref.insert((start + j) as u32, XrefEntry::Compressed { container, index });
+                    "Tf" => {
+                        self.current_font = operation
+                            .operands
+                            .first()
+                            .and_then(|it| it.as_name().ok())
+                            .map(<[u8]>::to_vec)
                     }
+                    "Tj" | "TJ" | "'" | "\"" => self.show(operation, ctm),
                     _ => {}
                 }
             }
+            ContentEvent::EnterForm(resources, _) => {
+                self.saved_states.push((self.text_state, self.current_font.clone()));
+                let mut fonts = BTreeMap::new();
+                if let Some(resources) = resources {
+                    self.doc.collect_fonts_from_resources(resources, &mut fonts);
+                }
+                self.font_scopes.push(self.doc.text_fonts(fonts));
+            }
+            ContentEvent::LeaveForm => {
+                self.font_scopes.pop();
+                if let Some((state, font)) = self.saved_states.pop() {
+                    self.text_state = state;
+                    self.current_font = font;
+                }
+            }
         }
     }
-    dict.remove(b"Length");
-    dict.remove(b"W");
-    dict.remove(b"Index");
-    Ok((xref, dict))
+
+    fn show(&mut self, operation: &Operation, ctm: &[f32; 6]) {
+        let text_state = self.text_state;
+        let name = self.current_font.as_deref().unwrap_or_default();
+        let Some(font) = self.font_scopes.iter().rev().find_map(|it| it.get(name)) else {
+            warn!("Could not decode shown text");
+            return;
+        };
+        let mut run = TextRun {
+            text: String::new(),
+            font: name.to_vec(),
+            state: text_state,
+            ctm: *ctm,
+            offsets: vec![],
+            in_form: self.font_scopes.len() > 1,
+        };
+        let mut offset = 0.0;
+        for operand in shown_operands(operation) {
+            let items = match operand {
+                Object::Array(items) => items.as_slice(),
+                item => std::slice::from_ref(item),
+            };
+            for item in items {
+                if let Ok(bytes) = item.as_str() {
+                    let Some(codes) = font.decode_codes(bytes) else {
+                        warn!("Could not decode shown text");
+                        continue;
+                    };
+                    for (code, text) in codes {
+                        run.offsets.extend(text.chars().map(|_| offset));
+                        run.text.push_str(&text);
+                        let is_space = code == b" ";
+                        offset += text_state.glyph_advance(font.widths.width(code), is_space);
+                    }
+                } else if let Ok(adjustment) = item.as_float() {
+                    offset += text_state.adjustment_advance(adjustment);
+                }
+            }
+        }
+        run.offsets.push(offset);
+        self.text_state.advance(offset);
+        self.runs.push(run);
+    }
 }
 
-fn read_big_endian_integer(reader: &mut Cursor<Vec<u8>>, buffer: &mut [u8]) -> Result<u32> {
-    reader.read_exact(buffer)?;
-    let mut value = 0;
-    for &mut byte in buffer {
-        value = (value << 8) + u32::from(byte);
+/// Width in bytes of the character codes of a font, `None` for CMaps with codes of variable width.
+fn font_code_width(font: &Dictionary) -> Option<usize> {
+    match font.get(b"Encoding").and_then(Object::as_name) {
+        Ok(b"Identity-H" | b"Identity-V" | b"UniGB-UCS2-H") => Some(2),
+        _ if matches!(font.get(b"Subtype").and_then(Object::as_name), Ok(b"Type0")) => None,
+        _ => Some(1),
     }
-    Ok(value)
 }
 
-fn parse_integer_array(array: &Object) -> Result<Vec<i64>> {
-    let array = array.as_array()?;
-    let mut out = Vec::with_capacity(array.len());
+/// The operands of a text showing operator which hold the text, leaving out the spacing of `"`.
+fn shown_operands(operation: &Operation) -> &[Object] {
+    let operands = &operation.operands;
+    &operands[operands.len().saturating_sub(1)..]
+}
 
-    for n in array {
-        out.push(n.as_i64()?);
-    }
+/// Maximum nesting depth of Form XObjects followed when walking the content of a page.
+const MAX_FORM_DEPTH: usize = 16;
 
-    Ok(out)
+/// Maximum number of operations of Form XObjects reported when walking the content of a page,
+/// so forms painting each other several times can't take exponential time.
+const MAX_FORM_OPERATIONS: usize = 1 << 20;
+
+/// The Form XObjects met while walking the content of a page.
+struct FormVisits {
+    /// The forms being painted, outermost first.
+    painting: Vec<ObjectId>,
+    /// The operations of each form painted so far.
+    decoded: BTreeMap<ObjectId, Rc<Vec<Operation>>>,
+    /// How many more operations of forms can be reported.
+    budget: usize,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::creator::tests::{create_document, create_document_with_texts, save_document};
+impl Default for FormVisits {
+    fn default() -> Self {
+        FormVisits {
+            painting: vec![],
+            decoded: BTreeMap::new(),
+            budget: MAX_FORM_OPERATIONS,
+        }
+    }
+}
 
-    #[cfg(not(feature = "async"))]
-    #[test]
-    fn load_and_save() {
-        // test load_from() and save_to()
-        use std::fs::File;
-        use std::io::Cursor;
-        // Create temporary folder to store file.
-        let temp_dir = tempfile::tempdir().unwrap();
-        let file_path = temp_dir.path().join("test_1_load_and_save.pdf");
+/// What is reported while walking the content of a page.
+#[derive(Clone, Copy)]
+pub(crate) enum ContentEvent<'op, 'a> {
+    /// An operation with the CTM in effect.
+    Operation(&'op Operation, &'op [f32; 6]),
+    /// The start of a Form XObject painted by the preceding `Do`, with its own resources if it has
+    /// some, and its dictionary.
+    EnterForm(Option<&'a Dictionary>, &'a Dictionary),
+    /// The end of the Form XObject.
+    LeaveForm,
+}
 
-        let mut doc = create_document();
+/// Bounding box `[llx lly urx ury]` of the unit square transformed by `matrix`,
+/// which is the area covered by an image painted with that CTM.
+pub(crate) fn unit_square_bounds(matrix: &[f32; 6]) -> [f32; 4] {
+    let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+        (
+            matrix[0] * x + matrix[2] * y + matrix[4],
+            matrix[1] * x + matrix[3] * y + matrix[5],
+        )
+    });
+    corners.iter().fold(
+        [f32::MAX, f32::MAX, f32::MIN, f32::MIN],
+        |[llx, lly, urx, ury], &(x, y)| [llx.min(x), lly.min(y), urx.max(x), ury.max(y)],
+    )
+}
 
-        save_document(&file_path, &mut doc);
+/// The images of a `BI` operation painted with `ctm`, with their placement rectangle, nothing for other operations.
+pub(crate) fn inline_images(page_id: ObjectId, operation: &Operation, ctm: &[f32; 6]) -> Vec<Result<InlineImage>> {
+    if operation.operator != "BI" {
+        return vec![];
+    }
+    let streams = operation.operands.iter().filter_map(|operand| operand.as_stream().ok());
+    streams
+        .map(|stream| {
+            let (dict, content) = decode_inline_image(expand_inline_image_dict(stream.dict.clone()), &stream.content)?;
+            let image = InlineImage {
+                page_id,
+                dict,
+                content,
+                rect: unit_square_bounds(ctm),
+            };
+            image.image()?;
+            Ok(image)
+        })
+        .collect()
+}
 
-        let in_file = File::open(file_path).unwrap();
-        let mut in_doc = Document::load_from(in_file).unwrap();
+/// Decode the data of an inline image with its leading filters which lopdf decodes, leaving the image
+/// filters like `DCTDecode` in the dictionary with their parameters.
+fn decode_inline_image(mut dict: Dictionary, content: &[u8]) -> Result<(Dictionary, Vec<u8>)> {
+    if !dict.has(b"Filter") {
+        return Ok((dict, content.to_vec()));
+    }
+    let filters: Vec<Vec<u8>> = Stream::filters_of(&dict)?.into_iter().map(<[u8]>::to_vec).collect();
+    let params: Vec<Option<Dictionary>> = Stream::decode_parms_of(&dict, filters.len())
+        .into_iter()
+        .map(|params| params.cloned())
+        .collect();
+    let decoded = filters
+        .iter()
+        .take_while(|filter| {
+            matches!(
+                filter.as_slice(),
+                b"FlateDecode" | b"LZWDecode" | b"ASCII85Decode" | b"ASCIIHexDecode"
+            )
+        })
+        .count();
+    let content = Stream::decode_filters(
+        content,
+        &filters[..decoded].iter().map(Vec::as_slice).collect::<Vec<_>>(),
+        &params[..decoded].iter().map(Option::as_ref).collect::<Vec<_>>(),
+        usize::MAX,
+    )?;
 
-        let out_buf = Vec::new();
-        let mut memory_cursor = Cursor::new(out_buf);
-        in_doc.save_to(&mut memory_cursor).unwrap();
-        // Check if saved file is not an empty bytes vector.
-        assert!(!memory_cursor.get_ref().is_empty());
+    let (filters, params) = (&filters[decoded..], &params[decoded..]);
+    dict.remove(b"Filter");
+    dict.remove(b"DecodeParms");
+    match filters {
+        [] => {}
+        [filter] => dict.set("Filter", Object::Name(filter.clone())),
+        _ => dict.set("Filter", filters.iter().cloned().map(Object::Name).collect::<Vec<_>>()),
+    }
+    if params.iter().any(Option::is_some) {
+        let mut params: Vec<Object> = params
+            .iter()
+            .map(|params| params.clone().map_or(Object::Null, Object::Dictionary))
+            .collect();
+        let params = if params.len() == 1 {
+            params.remove(0)
+        } else {
+            Object::Array(params)
+        };
+        dict.set("DecodeParms", params);
     }
+    Ok((dict, content))
+}
 
-    #[test]
-    fn extract_text_chunks() {
-        let text1 = "Hello world!";
-        let text2 = "Ferris is the best!";
-        let doc = create_document_with_texts(&[text1, text2]);
+/// Expand the abbreviated keys and names allowed in inline image dictionaries to their long forms.
+fn expand_inline_image_dict(dict: Dictionary) -> Dictionary {
+    fn expand_name(name: &[u8]) -> &[u8] {
+        match name {
+            b"G" => b"DeviceGray",
+            b"RGB" => b"DeviceRGB",
+            b"CMYK" => b"DeviceCMYK",
+            b"I" => b"Indexed",
+            b"AHx" => b"ASCIIHexDecode",
+            b"A85" => b"ASCII85Decode",
+            b"LZW" => b"LZWDecode",
+            b"Fl" => b"FlateDecode",
+            b"RL" => b"RunLengthDecode",
+            b"CCF" => b"CCITTFaxDecode",
+            b"DCT" => b"DCTDecode",
+            _ => name,
+        }
+    }
+    fn expand_value(value: Object) -> Object {
+        match value {
+            Object::Name(name) => Object::Name(expand_name(&name).to_vec()),
+            Object::Array(array) => Object::Array(array.into_iter().map(expand_value).collect()),
+            value => value,
+        }
+    }
+
+    dict.into_iter()
+        .map(|(key, value)| {
+            let key: &[u8] = match key.as_slice() {
+                b"BPC" => b"BitsPerComponent",
+                b"CS" => b"ColorSpace",
+                b"D" => b"Decode",
+                b"DP" => b"DecodeParms",
+                b"F" => b"Filter",
+                b"H" => b"Height",
+                b"IM" => b"ImageMask",
+                b"I" => b"Interpolate",
+                b"W" => b"Width",
+                key => key,
+            };
+            let value = match key {
+                b"ColorSpace" | b"Filter" => expand_value(value),
+                _ => value,
+            };
+            (key.to_vec(), value)
+        })
+        .collect()
+}
+
+/// Decode CrossReferenceStream
+///
+/// Each row holds the fields given by the `W` widths. A zero width for the first field means every
+/// entry is of type 1, and a zero width for the others means the field is 0. Rows are numbered by
+/// the `Index` subsections, which default to `[0 Size]`.
+pub fn decode_xref_stream(mut stream: Stream) -> Result<(Xref, Dictionary)> {
+    if stream.is_compressed() {
+        stream.decompress()?;
+    }
+    let mut dict = stream.dict;
+    let size = dict
+        .get(b"Size")
+        .and_then(Object::as_i64)
+        .map_err(|_| ParseError::InvalidXref)?;
+    let size = u32::try_from(size).map_err(|_| ParseError::InvalidXref)?;
+    let mut xref = Xref::new(size, XrefType::CrossReferenceStream);
+    {
+        let field_widths = dict
+            .get(b"W")
+            .and_then(Object::as_vec_of::<i64>)
+            .map_err(|_| Error::Xref(XrefError::StreamWidths))?;
+        let field_widths = match field_widths.as_slice() {
+            [w1, w2, w3] => [*w1, *w2, *w3].map(|width| usize::try_from(width).ok().filter(|&width| width <= 8)),
+            _ => return Err(Error::Xref(XrefError::StreamWidths)),
+        };
+        let [Some(w1), Some(w2), Some(w3)] = field_widths else {
+            return Err(Error::Xref(XrefError::StreamWidths));
+        };
+        let row_width = w1 + w2 + w3;
+        if row_width == 0 {
+            return Err(Error::Xref(XrefError::StreamWidths));
+        }
+
+        let section_indice = match dict.get(b"Index") {
+            Ok(index) => index
+                .as_vec_of::<i64>()
+                .map_err(|_| Error::Xref(XrefError::StreamIndex))?,
+            Err(_) => vec![0, i64::from(size)],
+        };
+        if section_indice.len() % 2 != 0 {
+            return Err(Error::Xref(XrefError::StreamIndex));
+        }
+        let mut sections = Vec::with_capacity(section_indice.len() / 2);
+        let mut rows = 0_usize;
+        for pair in section_indice.chunks_exact(2) {
+            let (Ok(start), Ok(count)) = (u32::try_from(pair[0]), u32::try_from(pair[1])) else {
+                return Err(Error::Xref(XrefError::StreamIndex));
+            };
+            if start.checked_add(count).is_none() {
+                return Err(Error::Xref(XrefError::StreamIndex));
+            }
+            sections.push((start, count));
+            rows = rows.saturating_add(count as usize);
+        }
+
+        let expected = rows.saturating_mul(row_width);
+        let content = stream.content.as_slice();
+        if content.len() < expected {
+            return Err(Error::Xref(XrefError::StreamLength {
+                expected,
+                found: content.len(),
+            }));
+        }
+        if content.len() > expected {
+            warn!(
+                "Ignored {} bytes after the entries of a cross reference stream",
+                content.len() - expected
+            );
+        }
+
+        let mut rows = content.chunks_exact(row_width);
+        for (start, count) in sections {
+            for (id, row) in (start..start + count).zip(rows.by_ref()) {
+                let (field1, rest) = row.split_at(w1);
+                let (field2, field3) = rest.split_at(w2);
+                let entry_type = if w1 == 0 { 1 } else { read_big_endian_integer(field1) };
+                let field2 = read_big_endian_integer(field2);
+                let field3 = read_big_endian_integer(field3);
+                let entry = match entry_type {
+                    // free object
+                    0 if id == 0 => continue,
+                    0 => match u16::try_from(field3) {
+                        Ok(generation) => XrefEntry::Free { generation },
+                        Err(_) => continue,
+                    },
+                    // normal object
+                    1 => match u16::try_from(field3) {
+                        Ok(generation) => XrefEntry::Normal {
+                            offset: field2,
+                            generation,
+                        },
+                        Err(_) => {
+                            warn!("Ignored out of range cross reference stream entry for object {id}");
+                            continue;
+                        }
+                    },
+                    // compressed object
+                    2 => match (u32::try_from(field2), u16::try_from(field3)) {
+                        (Ok(container), Ok(index)) => XrefEntry::Compressed { container, index },
+                        _ => {
+                            warn!("Ignored out of range cross reference stream entry for object {id}");
+                            continue;
+                        }
+                    },
+                    // other types are references to the null object
+                    _ => continue,
+                };
+                xref.insert(id, entry);
+            }
+        }
+    }
+    dict.remove(b"Length");
+    dict.remove(b"W");
+    dict.remove(b"Index");
+    Ok((xref, dict))
+}
+
+fn read_big_endian_integer(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |value, &byte| (value << 8) | u64::from(byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::RawOperand;
+    use crate::creator::tests::{create_document, create_document_with_texts, save_document};
+    use crate::encodings::cmap::ToUnicodeCMap;
+
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn load_and_save() {
+        // test load_from() and save_to()
+        use std::fs::File;
+        use std::io::Cursor;
+        // Create temporary folder to store file.
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("test_1_load_and_save.pdf");
+
+        let mut doc = create_document();
+
+        save_document(&file_path, &mut doc);
+
+        let in_file = File::open(file_path).unwrap();
+        let mut in_doc = Document::load_from(in_file).unwrap();
+
+        let out_buf = Vec::new();
+        let mut memory_cursor = Cursor::new(out_buf);
+        in_doc.save_to(&mut memory_cursor).unwrap();
+        // Check if saved file is not an empty bytes vector.
+        assert!(!memory_cursor.get_ref().is_empty());
+    }
+
+    fn xref_stream(widths: &[i64], index: Option<&[i64]>, content: Vec<u8>) -> Stream {
+        let mut dict = dictionary! {
+            "Type" => "XRef",
+            "Size" => 10,
+            "W" => widths.iter().map(|&w| Object::Integer(w)).collect::<Vec<_>>(),
+        };
+        if let Some(index) = index {
+            dict.set("Index", index.iter().map(|&i| Object::Integer(i)).collect::<Vec<_>>());
+        }
+        Stream::new(dict, content)
+    }
+
+    #[test]
+    fn decode_xref_stream_without_type_field() {
+        // W [0 2 1]: every entry is of type 1.
+        let stream = xref_stream(&[0, 2, 1], None, [[0, 15, 0], [1, 0, 2]].repeat(5).concat());
+        let (xref, dict) = decode_xref_stream(stream).unwrap();
+        assert_eq!(xref.entries.len(), 10);
+        assert!(matches!(
+            xref.get(0),
+            Some(XrefEntry::Normal {
+                offset: 15,
+                generation: 0
+            })
+        ));
+        assert!(matches!(
+            xref.get(9),
+            Some(XrefEntry::Normal {
+                offset: 256,
+                generation: 2
+            })
+        ));
+        assert!(dict.get(b"W").is_err());
+    }
+
+    #[test]
+    fn decode_xref_stream_subsections() {
+        // W [1 2 0]: the third field defaults to 0.
+        let content = vec![0, 0, 0, 1, 0, 17, 2, 0, 5, 1, 0x12, 0x34];
+        let stream = xref_stream(&[1, 2, 0], Some(&[0, 2, 7, 2]), content);
+        let (xref, _) = decode_xref_stream(stream).unwrap();
+        assert_eq!(xref.entries.len(), 3);
+        assert!(xref.get(0).is_none());
+        assert!(matches!(
+            xref.get(1),
+            Some(XrefEntry::Normal {
+                offset: 17,
+                generation: 0
+            })
+        ));
+        assert!(xref.get(2).is_none());
+        assert!(matches!(
+            xref.get(7),
+            Some(XrefEntry::Compressed { container: 5, index: 0 })
+        ));
+        assert!(matches!(
+            xref.get(8),
+            Some(XrefEntry::Normal {
+                offset: 0x1234,
+                generation: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_xref_stream_with_offsets_beyond_4_gib() {
+        let stream = xref_stream(&[1, 5, 1], Some(&[3, 1]), vec![1, 1, 0, 0, 0, 0x10, 0]);
+        let (xref, _) = decode_xref_stream(stream).unwrap();
+        assert!(matches!(
+            xref.get(3),
+            Some(XrefEntry::Normal {
+                offset: 0x1_0000_0010,
+                generation: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_short_xref_stream() {
+        let stream = xref_stream(&[1, 2, 1], Some(&[0, 2, 5, 1]), vec![1, 0, 15, 0, 1, 0, 30, 0, 1, 0]);
+        assert!(matches!(
+            decode_xref_stream(stream),
+            Err(Error::Xref(XrefError::StreamLength {
+                expected: 12,
+                found: 10
+            }))
+        ));
+
+        let stream = xref_stream(&[1, 2, 1], Some(&[0, 2, 5]), vec![0; 12]);
+        assert!(matches!(
+            decode_xref_stream(stream),
+            Err(Error::Xref(XrefError::StreamIndex))
+        ));
+        let stream = xref_stream(&[1, 2], None, vec![0; 30]);
+        assert!(matches!(
+            decode_xref_stream(stream),
+            Err(Error::Xref(XrefError::StreamWidths))
+        ));
+    }
+
+    #[test]
+    fn extract_text_chunks() {
+        let text1 = "Hello world!";
+        let text2 = "Ferris is the best!";
+        let doc = create_document_with_texts(&[text1, text2]);
         let extracted_texts = doc.extract_text_chunks(&[1, 2]);
         assert_eq!(extracted_texts.len(), 2);
         assert_eq!(
@@ -397,6 +1553,56 @@ fn extract_text_chunks() {
         );
     }
 
+    #[test]
+    fn get_page_inline_images() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let content = b"q 100 0 0 50 10 20 cm
+BI /W 4 /H 4 /CS /RGB /BPC 8
+ID
+00000z0z00zzz00z0zzz0zzzEI aazazaazzzaazazzzazzz
+EI Q";
+        doc.change_page_content(page_id, content.to_vec()).unwrap();
+
+        let images = doc.get_page_inline_images(page_id).unwrap();
+        assert_eq!(images.len(), 1);
+        let image = images[0].image().unwrap();
+        assert_eq!(image.id, page_id);
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(image.color_space.as_deref(), Some("DeviceRGB"));
+        assert_eq!(image.bits_per_component, Some(8));
+        assert_eq!(image.rect, Some([10.0, 20.0, 110.0, 70.0]));
+        assert_eq!(image.content.len(), 48);
+        assert!(image.origin_dict.has(b"ColorSpace"));
+        assert!(!image.origin_dict.has(b"CS"));
+    }
+
+    #[test]
+    fn get_page_inline_images_decodes_data() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let content = b"BI /W 4 /H 4 /CS /G /BPC 8 /F [/AHx /DCT] /DP [null << /ColorTransform 0 >>]
+ID
+00112233445566778899AABBCCDDEEFF>
+EI";
+        doc.change_page_content(page_id, content.to_vec()).unwrap();
+
+        let images = doc.get_page_inline_images(page_id).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].content, (0..16).map(|i| i * 0x11).collect::<Vec<u8>>());
+        assert_eq!(
+            images[0].dict.get(b"Filter").unwrap(),
+            &Object::Name(b"DCTDecode".to_vec())
+        );
+        assert_eq!(
+            images[0].dict.get(b"DecodeParms").unwrap(),
+            &Object::Dictionary(dictionary! { "ColorTransform" => 0 })
+        );
+        let image = images[0].image().unwrap();
+        assert_eq!(image.filters, Some(vec!["DCTDecode".to_string()]));
+        assert_eq!(image.content, images[0].content);
+    }
+
     #[test]
     fn extract_text_concatenates_text_from_multiple_pages() {
         let text1 = "Hello world!";
@@ -405,4 +1611,828 @@ fn extract_text_concatenates_text_from_multiple_pages() {
         let extracted_text = doc.extract_text(&[1, 2]);
         assert_eq!(extracted_text.unwrap(), format!("{text1}\n{text2}\n"));
     }
+
+    #[test]
+    fn extract_text_by_page_id() {
+        let doc = create_document_with_texts(&["first", "second", "third"]);
+        let pages = doc.get_pages();
+        let page_ids = [pages[&3], pages[&1]];
+        assert_eq!(
+            doc.extract_text_for(&page_ids).unwrap(),
+            doc.extract_text(&[3, 1]).unwrap()
+        );
+        let chunks = |chunks: Vec<Result<String>>| chunks.into_iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            chunks(doc.extract_text_chunks_for(&page_ids)),
+            chunks(doc.extract_text_chunks(&[3, 1]))
+        );
+
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        let err = doc.extract_text_for(&[pages[&1], catalog_id]).unwrap_err();
+        assert!(matches!(err, Error::NotAPage(id) if id == catalog_id));
+        assert!(err.to_string().contains(&catalog_id.to_string()));
+        let missing = ObjectId(1000, 0);
+        assert!(matches!(doc.extract_text_for(&[missing]), Err(Error::ObjectNotFound(id)) if id == missing));
+        let chunks = doc.extract_text_chunks_for(&[catalog_id, pages[&2]]);
+        assert!(matches!(chunks[0], Err(Error::NotAPage(_))));
+        assert_eq!(chunks[1].as_deref().unwrap(), "second\n");
+    }
+
+    #[test]
+    fn text_split_across_content_streams() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        // The streams are divided between operands and between an operand list and its operator.
+        let parts: [&[u8]; 3] = [b"BT /F1 48 Tf 100", b"600 Td (Hello World)", b"Tj ET"];
+        let contents: Vec<Object> = parts
+            .iter()
+            .map(|part| doc.add_object(Stream::new(Dictionary::new(), part.to_vec())).into())
+            .collect();
+        doc.get_dictionary_mut(page_id).unwrap().set("Contents", contents);
+
+        let content = doc.get_and_decode_page_content(page_id).unwrap();
+        let operators: Vec<&str> = content.operations.iter().map(|it| it.operator.as_str()).collect();
+        assert_eq!(operators, ["BT", "Tf", "Td", "Tj", "ET"]);
+        assert_eq!(doc.extract_text(&[1]).unwrap(), "Hello World\n");
+
+        let report = doc.replace_partial_text(1, "World", "Ferris").unwrap();
+        assert_eq!(report.replaced, 1);
+        assert_eq!(doc.extract_text(&[1]).unwrap(), "Hello Ferris\n");
+    }
+
+    fn search_rect_width(content: &[u8], needle: &str) -> f32 {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        doc.change_page_content(page_id, content.to_vec()).unwrap();
+        let rects = doc.search_text(page_id, needle).unwrap();
+        assert_eq!(rects.len(), 1);
+        let [llx, _, urx, _] = rects[0];
+        urx - llx
+    }
+
+    #[test]
+    fn horizontal_scaling_and_char_spacing_in_search_rects() {
+        // Glyphs of Courier, a standard font without /Widths, are 0.6 em wide.
+        let width = search_rect_width(b"BT /F1 10 Tf 100 600 Td (0123456789) Tj ET", "0123456789");
+        assert_eq!(width, 60.0);
+        let width = search_rect_width(b"BT /F1 10 Tf 50 Tz 100 600 Td (0123456789) Tj ET", "0123456789");
+        assert_eq!(width, 30.0);
+        let width = search_rect_width(b"BT /F1 10 Tf 5 Tc 100 600 Td (0123456789) Tj ET", "0123456789");
+        assert_eq!(width, 110.0);
+        // Word spacing only applies to spaces, and TJ adjustments move the following glyphs.
+        let width = search_rect_width(b"BT /F1 10 Tf 3 Tw 100 600 Td [(a b) -500 (c)] TJ ET", "a bc");
+        assert_eq!(width, 32.0);
+    }
+
+    #[test]
+    fn text_runs_follow_leading_and_text_state() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let content = b"BT /F1 12 Tf 14 TL 2 Ts 200 Tz 100 600 Td (one) Tj T* (two) Tj 1 2 (three) \" ET";
+        doc.change_page_content(page_id, content.to_vec()).unwrap();
+        let runs = doc.get_page_text_runs(page_id).unwrap();
+        let texts: Vec<_> = runs.iter().map(|run| run.text.as_str()).collect();
+        assert_eq!(texts, ["one", "two", "three"]);
+        let origins: Vec<_> = runs
+            .iter()
+            .map(|run| (run.state.text_matrix[4], run.state.text_matrix[5]))
+            .collect();
+        assert_eq!(origins, [(100.0, 600.0), (100.0, 586.0), (100.0, 572.0)]);
+        assert_eq!(runs[0].font, b"F1");
+        assert_eq!((runs[0].state.font_size, runs[0].state.rise), (12.0, 2.0));
+        assert_eq!(runs[0].state.horizontal_scaling, 200.0);
+        assert!((runs[0].width() - 3.0 * 7.2 * 2.0).abs() < 1e-3);
+        assert_eq!((runs[2].state.word_spacing, runs[2].state.char_spacing), (1.0, 2.0));
+        assert!((runs[2].width() - (5.0 * 7.2 + 5.0 * 2.0) * 2.0).abs() < 1e-3);
+        assert_eq!(doc.extract_text(&[1]).unwrap(), "one\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn text_positions_follow_text_matrices() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let form_id = add_form(
+            &mut doc,
+            b"BT /F1 10 Tf 1 0 0 1 20 30 Tm (h) Tj ET",
+            Some(dictionary! { "Font" => dictionary! { "F1" => font_id } }),
+        );
+        doc.get_dictionary_mut(page_id).unwrap().set(
+            "Resources",
+            dictionary! {
+                "Font" => dictionary! { "F1" => font_id },
+                "XObject" => dictionary! { "Fm1" => form_id },
+            },
+        );
+        let content = b"BT /F1 10 Tf 14 TL 100 600 Td [(ab) -1000 (c)] TJ T* (d) Tj
+2 0 0 2 50 50 Tm (e) Tj 5 -10 TD (f) Tj T* (g) Tj ET q 1 0 0 1 10 0 cm /Fm1 Do Q";
+        doc.change_page_content(page_id, content.to_vec()).unwrap();
+
+        let chunks = doc.get_page_text_positions(1).unwrap();
+        let texts: Vec<_> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(texts, ["abc", "d", "e", "f", "g", "h"]);
+        let origins: Vec<_> = chunks.iter().map(|chunk| chunk.origin).collect();
+        // TD sets the leading to 10, T* moves down by it in text space, which Tm scales.
+        assert_eq!(
+            origins,
+            [
+                (100.0, 600.0),
+                (100.0, 586.0),
+                (50.0, 50.0),
+                (60.0, 30.0),
+                (60.0, 10.0),
+                (20.0, 30.0)
+            ]
+        );
+        // Glyphs of Courier are 0.6 em wide, and the TJ adjustment moves "c" by one em.
+        assert_eq!(chunks[0].positions, [(100.0, 600.0), (106.0, 600.0), (122.0, 600.0)]);
+        assert_eq!(
+            (chunks[0].font.as_slice(), chunks[0].font_size),
+            (b"F1".as_slice(), 10.0)
+        );
+        assert!(chunks[..5].iter().all(|chunk| !chunk.in_form));
+        // Strings of forms are placed by the CTM the form is painted with.
+        assert!(chunks[5].in_form);
+        assert_eq!(chunks[5].positions, [(30.0, 30.0)]);
+
+        assert!(matches!(
+            doc.get_page_text_positions(2),
+            Err(Error::PageNumberNotFound(2))
+        ));
+    }
+
+    #[test]
+    fn text_in_clipping_modes() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        // The rendering mode is restored by Q, so only the second string clips the rectangle.
+        let content = b"q BT /F1 12 Tf 100 600 Td (Visible) Tj 7 Tr 0 -20 Td (Clip) Tj ET 0 0 595 842 re f Q
+BT /F1 12 Tf 100 500 Td (After) Tj ET";
+        doc.change_page_content(page_id, content.to_vec()).unwrap();
+
+        assert_eq!(doc.extract_text(&[1]).unwrap(), "Visible\nClip\nAfter\n");
+        let options = ExtractionOptions {
+            skip_clipping_text: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            doc.extract_text_with_options(&[1], &options).unwrap(),
+            "Visible\nAfter\n"
+        );
+
+        let chunks = doc.get_page_text_positions(1).unwrap();
+        let clipping: Vec<_> = chunks
+            .iter()
+            .map(|chunk| (chunk.text.as_str(), chunk.is_clipping))
+            .collect();
+        assert_eq!(clipping, [("Visible", false), ("Clip", true), ("After", false)]);
+
+        let options = crate::PageExtractionOptions {
+            skip_clipping_text: true,
+            ..Default::default()
+        };
+        let page = doc.extract_page(1, &options).unwrap();
+        assert_eq!(page.text, "Visible\nAfter\n");
+        assert_eq!(page.clipping_strings, 1);
+    }
+
+    #[test]
+    fn quote_operators_move_to_the_next_line() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        // Without TL the lines overlap, but each quote operator still starts a new line.
+        let content = b"BT /F1 12 Tf 100 600 Td (one) Tj (two) ' (three) ' 3 1 (four) \" ET";
+        doc.change_page_content(page_id, content.to_vec()).unwrap();
+        assert_eq!(doc.extract_text(&[1]).unwrap(), "one\ntwo\nthree\nfour\n");
+
+        assert_eq!(doc.replace_text(1, "four", "five").unwrap().replaced, 1);
+        assert_eq!(doc.replace_partial_text(1, "hr", "HR").unwrap().replaced, 1);
+        let operations = doc.get_and_decode_page_content(page_id).unwrap().operations;
+        let shown: Vec<_> = operations
+            .iter()
+            .filter(|operation| ["'", "\""].contains(&operation.operator.as_str()))
+            .map(|operation| (operation.operator.as_str(), operation.operands.clone()))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                ("'", vec![Object::string_literal("two")]),
+                ("'", vec![Object::string_literal("tHRee")]),
+                ("\"", vec![3.into(), 1.into(), Object::string_literal("five")]),
+            ]
+        );
+        assert_eq!(doc.extract_text(&[1]).unwrap(), "one\ntwo\ntHRee\nfive\n");
+    }
+
+    #[test]
+    fn registered_encoding_is_used_for_extraction_and_replacement() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+            "Encoding" => "LegacyEncoding",
+        });
+        doc.get_dictionary_mut(page_id).unwrap().set(
+            "Resources",
+            dictionary! {
+                "Font" => dictionary! { "F3" => font_id },
+            },
+        );
+        doc.change_page_content(page_id, b"BT /F3 12 Tf (5 \\245) Tj ET".to_vec())
+            .unwrap();
+
+        let mut legacy = crate::encodings::STANDARD_ENCODING;
+        legacy[0xA5] = Some(0x20AC);
+        doc.register_encoding("LegacyEncoding", legacy);
+        assert_eq!(doc.extract_text(&[1]).unwrap(), "5 \u{20AC}\n");
+
+        let report = doc.replace_text(1, "5 \u{20AC}", "7 \u{20AC}").unwrap();
+        assert_eq!(report.replaced, 1);
+        assert_eq!(shown_strings(&doc), [b"7 \xA5".to_vec()]);
+    }
+
+    fn add_form(doc: &mut Document, content: &[u8], resources: Option<Dictionary>) -> ObjectId {
+        let mut dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        };
+        if let Some(resources) = resources {
+            dict.set("Resources", resources);
+        }
+        doc.add_object(Stream::new(dict, content.to_vec()))
+    }
+
+    fn document_painting_form(form_id: ObjectId, mut doc: Document) -> Document {
+        let page_id = doc.page_iter().next().unwrap();
+        doc.get_dictionary_mut(page_id).unwrap().set(
+            "Resources",
+            dictionary! {
+                "XObject" => dictionary! { "Fm1" => form_id },
+            },
+        );
+        doc.change_page_content(page_id, b"BT /F1 48 Tf 100 600 Td (Body) Tj ET q /Fm1 Do Q".to_vec())
+            .unwrap();
+        doc
+    }
+
+    #[test]
+    fn extract_text_from_nested_forms() {
+        let mut doc = create_document();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        // The inner form has no resources of its own and uses those of the outer form.
+        let inner_id = add_form(&mut doc, b"BT /F2 10 Tf (Footer) Tj ET", None);
+        let outer_id = add_form(
+            &mut doc,
+            b"BT /F2 10 Tf (Header) Tj ET /Fm2 Do",
+            Some(dictionary! {
+                "Font" => dictionary! { "F2" => font_id },
+                "XObject" => dictionary! { "Fm2" => inner_id },
+            }),
+        );
+        let doc = document_painting_form(outer_id, doc);
+        assert_eq!(doc.extract_text(&[1]).unwrap(), "Body\nHeader\nFooter\n");
+    }
+
+    #[test]
+    fn extract_text_from_self_painting_form() {
+        let mut doc = create_document();
+        let form_id = doc.new_object_id();
+        let form = add_form(
+            &mut doc,
+            b"BT /F1 10 Tf (Loop) Tj ET /Fm1 Do",
+            Some(dictionary! {
+                "XObject" => dictionary! { "Fm1" => form_id },
+            }),
+        );
+        let form = doc.objects.remove(&form).unwrap();
+        doc.objects.insert(form_id, form);
+        let doc = document_painting_form(form_id, doc);
+        assert_eq!(doc.extract_text(&[1]).unwrap(), "Body\nLoop\n");
+    }
+
+    #[test]
+    fn walk_forms_painting_each_other_many_times() {
+        // Each form paints the next one ten times, which would make 10^15 paintings of the innermost form.
+        let mut doc = create_document();
+        let mut form_id = add_form(&mut doc, b"BT /F1 10 Tf (Leaf) Tj ET", None);
+        for _ in 0..15 {
+            let resources = dictionary! { "XObject" => dictionary! { "Fm1" => form_id } };
+            form_id = add_form(&mut doc, &b"q /Fm1 Do Q ".repeat(10), Some(resources));
+        }
+        let doc = document_painting_form(form_id, doc);
+        let page_id = doc.page_iter().next().unwrap();
+
+        let (operations, forms) = assert_completes_within(std::time::Duration::from_secs(20), || {
+            let (mut operations, mut forms) = (0, 0);
+            doc.walk_page_content(page_id, &mut |event| match event {
+                ContentEvent::Operation(..) => operations += 1,
+                ContentEvent::EnterForm(..) => forms += 1,
+                ContentEvent::LeaveForm => {}
+            })
+            .unwrap();
+            (operations, forms)
+        });
+        assert!(operations <= 10 + MAX_FORM_OPERATIONS, "{operations} operations");
+        assert!(forms > 1000, "{forms} forms");
+    }
+
+    #[test]
+    fn extract_annotation_text_with_acroform_fonts() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let helv_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        doc.catalog_mut().unwrap().set(
+            "AcroForm",
+            dictionary! { "DR" => dictionary! { "Font" => dictionary! { "Helv" => helv_id } } },
+        );
+        let appearance = |text: &str| {
+            Stream::new(
+                dictionary! { "Resources" => dictionary! {} },
+                format!("/Tx BMC BT /Helv 10 Tf 2 4 Td ({text}) Tj ET EMC").into_bytes(),
+            )
+        };
+        let text_id = doc.add_object(appearance("Field value"));
+        let field_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "DA" => Object::string_literal("/Helv 10 Tf 0 g"),
+            "AP" => dictionary! { "N" => text_id },
+        });
+        let mut on = appearance("On");
+        let times_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Times-Roman",
+        });
+        on.dict
+            .set("Resources", dictionary! { "Font" => dictionary! { "F1" => times_id } });
+        let on_id = doc.add_object(on);
+        let off_id = doc.add_object(appearance("Off"));
+        let checkbox_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "AS" => "Yes",
+            "AP" => dictionary! { "N" => dictionary! { "Yes" => on_id, "Off" => off_id } },
+        });
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Annots", vec![field_id.into(), checkbox_id.into()]);
+
+        assert!(doc.get_acroform_resources().unwrap().has(b"Font"));
+        // The page content itself can't use the fonts of the interactive form.
+        let fonts = doc.get_page_fonts(page_id).unwrap();
+        assert!(fonts.contains_key(b"F1".as_slice()));
+        assert!(!fonts.contains_key(b"Helv".as_slice()));
+        // Each appearance stream has its own fonts, even with the same name as those of the page.
+        let base_font = |font: &Dictionary| font.get(b"BaseFont").unwrap().as_name().unwrap().to_vec();
+        let annotation_fonts = doc.get_page_annotation_fonts(page_id).unwrap();
+        assert_eq!(annotation_fonts.len(), 2);
+        assert_eq!(annotation_fonts[0].keys().collect::<Vec<_>>(), [b"Helv"]);
+        assert_eq!(base_font(annotation_fonts[1][b"F1".as_slice()]), b"Times-Roman");
+        assert_eq!(base_font(annotation_fonts[1][b"Helv".as_slice()]), b"Helvetica");
+        assert_ne!(base_font(fonts[b"F1".as_slice()]), b"Times-Roman");
+
+        let annotations = doc.get_page_annotations(page_id).unwrap();
+        assert_eq!(doc.extract_annotation_text(annotations[0]).unwrap(), "Field value\n");
+        assert_eq!(doc.extract_annotation_text(annotations[1]).unwrap(), "On\n");
+    }
+
+    #[test]
+    fn extract_text_of_visible_layers() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let shown_id = doc.add_object(dictionary! { "Type" => "OCG", "Name" => Object::string_literal("Shown") });
+        let hidden_id = doc.add_object(dictionary! { "Type" => "OCG", "Name" => Object::string_literal("Hidden") });
+        let both_on_id = doc.add_object(dictionary! {
+            "Type" => "OCMD",
+            "OCGs" => vec![shown_id.into(), hidden_id.into()],
+            "P" => "AllOn",
+        });
+        doc.catalog_mut().unwrap().set(
+            "OCProperties",
+            dictionary! {
+                "OCGs" => vec![shown_id.into(), hidden_id.into()],
+                "D" => dictionary! { "OFF" => vec![hidden_id.into()] },
+            },
+        );
+        let form_id = add_form(&mut doc, b"BT /F1 10 Tf (Form) Tj ET", None);
+        doc.get_object_mut(form_id)
+            .and_then(Object::as_stream_mut)
+            .unwrap()
+            .dict
+            .set("OC", hidden_id);
+        doc.get_dictionary_mut(page_id).unwrap().set(
+            "Resources",
+            dictionary! {
+                "Properties" => dictionary! { "oc1" => shown_id, "oc2" => hidden_id, "oc3" => both_on_id },
+                "XObject" => dictionary! { "Fm1" => form_id },
+            },
+        );
+        let content = b"/OC /oc1 BDC BT /F1 12 Tf (Visible) Tj ET EMC \
+            /OC /oc2 BDC BT /F1 12 Tf (Hidden) Tj ET /Span BMC BT (Nested) Tj ET EMC EMC \
+            /OC /oc3 BDC BT /F1 12 Tf (Both) Tj ET EMC \
+            q /Fm1 Do Q BT /F1 12 Tf (Plain) Tj ET";
+        doc.change_page_content(page_id, content.to_vec()).unwrap();
+
+        assert_eq!(
+            doc.extract_text(&[1]).unwrap(),
+            "Visible\nHidden\nNested\nBoth\nForm\nPlain\n"
+        );
+        let options = ExtractionOptions {
+            layer_visibility: LayerVisibility::UseDefaultConfig,
+            ..Default::default()
+        };
+        assert_eq!(
+            doc.extract_text_with_options(&[1], &options).unwrap(),
+            "Visible\nPlain\n"
+        );
+        let options = ExtractionOptions {
+            layer_visibility: LayerVisibility::AllVisible,
+            ..Default::default()
+        };
+        assert_eq!(
+            doc.extract_text_with_options(&[1], &options).unwrap(),
+            doc.extract_text(&[1]).unwrap()
+        );
+        let options = ExtractionOptions {
+            layer_visibility: LayerVisibility::Custom([(shown_id, false), (hidden_id, true)].into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            doc.extract_text_with_options(&[1], &options).unwrap(),
+            "Hidden\nNested\nForm\nPlain\n"
+        );
+    }
+
+    #[test]
+    fn get_inline_images_of_forms() {
+        let mut doc = create_document();
+        let form_id = add_form(
+            &mut doc,
+            b"q 10 0 0 10 0 0 cm BI /W 1 /H 1 /CS /G /BPC 8 ID\nzEI Q",
+            None,
+        );
+        let form = doc.get_object_mut(form_id).and_then(Object::as_stream_mut).unwrap();
+        form.dict.set(
+            "Matrix",
+            vec![2.into(), 0.into(), 0.into(), 2.into(), 5.into(), 5.into()],
+        );
+        let doc = document_painting_form(form_id, doc);
+        let page_id = doc.page_iter().next().unwrap();
+
+        let images = doc.get_page_inline_images(page_id).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].rect, [5.0, 5.0, 25.0, 25.0]);
+    }
+
+    fn document_with_identity_h_font(content: &[u8]) -> Document {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let cmap = b"/CIDInit /ProcSet findresource begin
+12 dict begin
+begincmap
+/CMapName /Adobe-Identity-UCS def
+/CMapType 2 def
+1 begincodespacerange
+<0000> <FFFF>
+endcodespacerange
+1 beginbfrange
+<0001> <001A> <0061>
+endbfrange
+endcmap
+CMapName currentdict /CMap defineresource pop
+end
+end";
+        let to_unicode = doc.add_object(Stream::new(Dictionary::new(), cmap.to_vec()));
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => "Test",
+            "Encoding" => "Identity-H",
+            "ToUnicode" => to_unicode,
+        });
+        doc.get_dictionary_mut(page_id).unwrap().set(
+            "Resources",
+            dictionary! {
+                "Font" => dictionary! { "F2" => font_id },
+            },
+        );
+        doc.change_page_content(page_id, content.to_vec()).unwrap();
+        doc
+    }
+
+    fn shown_strings(doc: &Document) -> Vec<Vec<u8>> {
+        let page_id = doc.page_iter().next().unwrap();
+        let content = doc.get_and_decode_page_content(page_id).unwrap();
+        let mut strings = vec![];
+        for operation in content.operations {
+            for operand in operation.operands {
+                match operand {
+                    Object::String(bytes, _) => strings.push(bytes),
+                    Object::Array(array) => {
+                        strings.extend(array.into_iter().filter_map(|it| it.as_str().ok().map(<[u8]>::to_vec)))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        strings
+    }
+
+    #[test]
+    fn parse_content_fragments() {
+        let err = Content::parse_fragment(b"BT /F1 Tf 100 600 Td (Hi) Tj ET").unwrap_err();
+        let Error::ContentFragment { offset, token, reason } = err else {
+            panic!("unexpected error {err:?}");
+        };
+        assert_eq!((offset, token.as_str()), (3, "/F1"));
+        assert_eq!(reason, "`Tf` expects 2 operands (a name, a number), found 1");
+        let err = Content::parse_fragment(b"BT 12 (F1) Tf ET").unwrap_err();
+        assert!(
+            err.to_string().contains("`Tf` expects a name but found Integer"),
+            "{err}"
+        );
+        let err = Content::parse_fragment(b"BT\n) Tj ET").unwrap_err();
+        assert!(matches!(err, Error::ContentFragment { offset: 3, ref token, .. } if token == ")"));
+        assert!(Content::parse_fragment(b"1 2 xyz").is_err());
+        assert!(Content::parse_fragment(b"BX 1 2 xyz EX 0.5 g 1 0 0 scn /P1 scn").is_ok());
+
+        let fragment = b"q 1 0 0 1 10 20 cm BT /F1 12 Tf 0 0 Td [(Hel) -20 (lo)] TJ ET Q";
+        let encoded = Content::parse_fragment(fragment).unwrap().encode().unwrap();
+        let reencoded = Content::parse_fragment(&encoded).unwrap().encode().unwrap();
+        assert_eq!(encoded, reencoded);
+
+        let mut doc = create_document();
+        let page_id = doc.get_pages()[&1];
+        assert!(doc.add_page_contents_checked(page_id, b"BT /F1 Tf ET").is_err());
+        assert_eq!(doc.get_page_contents(page_id).len(), 1);
+        doc.add_page_contents_checked(page_id, fragment).unwrap();
+        assert_eq!(doc.get_page_contents(page_id).len(), 2);
+        assert!(doc.extract_text(&[1]).unwrap().contains("Hello"));
+    }
+
+    #[test]
+    fn iterate_content_operations() {
+        let data = b"% comment\nq 1 0 0 1 10.5 -20 cm /A#20B gs BT /F1 12 Tf (a\\(b\\)) Tj <48 65 6> Tj \
+            [(Hel) -20 (lo)] TJ ET BI /W 2 /H 1 /BPC 8 /CS /G ID ab EI /P <</MCID 3>> BDC EMC Q 1 2 )";
+        let decoded = Content::decode(data).unwrap().operations;
+        let cloned: Vec<_> = Content::iter_operations(data).cloned_operations().collect();
+        assert_eq!(cloned.len(), 13);
+        assert_eq!(format!("{decoded:?}"), format!("{cloned:?}"));
+
+        let mut operations = Content::iter_operations(data);
+        let cm = operations.nth(1).unwrap().unwrap();
+        assert_eq!(cm.operator, "cm");
+        assert_eq!(cm.operands[4], RawOperand::Number(b"10.5"));
+        assert_eq!(cm.operands[5].as_f32(), Some(-20.0));
+        let gs = operations.next().unwrap().unwrap();
+        assert_eq!(gs.operands, [RawOperand::Name(b"/A#20B")]);
+        assert_eq!(gs.operands[0].to_object().unwrap(), Object::Name(b"A B".to_vec()));
+        let err = operations.find_map(Result::err).unwrap();
+        assert!(
+            matches!(err, Error::ContentFragment { ref token, .. } if token == "1"),
+            "{err:?}"
+        );
+        assert!(operations.next().is_none());
+    }
+
+    #[test]
+    fn unknown_constructs_round_trip() {
+        let data = b"q\n{ 1 2 } XYZ42\ncustomOp\n1000 0 d0\n0 0 1 rg\nQ".as_slice();
+        let content = Content::decode(data).unwrap();
+        assert_eq!(content.operations[1].operator, "XYZ42");
+        assert_eq!(content.operations[1].operands[0], Object::RawToken(b"{".to_vec()));
+        assert_eq!(content.encode().unwrap(), data);
+        let cloned: Content = Content {
+            operations: Content::iter_operations(data).cloned_operations().collect(),
+        };
+        assert_eq!(cloned.encode().unwrap(), data);
+        assert!(Content::parse_fragment(b"BX { 1 2 } XYZ42 EX").is_err());
+
+        let mut doc = create_document_with_texts(&["Hello World"]);
+        let page_id = doc.get_pages()[&1];
+        let page = b"{ 1 2 } XYZ42\nBT\n/F1 48 Tf\n100 600 Td\n(Hello World) Tj\nET";
+        doc.change_page_content(page_id, page.to_vec()).unwrap();
+        let unchanged = doc.get_and_decode_page_content(page_id).unwrap().encode().unwrap();
+        doc.change_page_content(page_id, unchanged).unwrap();
+        assert_eq!(doc.get_page_content(page_id).unwrap(), page);
+        doc.replace_text(1, "Hello World", "Bye").unwrap();
+        let replaced = doc.get_page_content(page_id).unwrap();
+        assert!(
+            replaced.starts_with(b"{ 1 2 } XYZ42\nBT"),
+            "{}",
+            String::from_utf8_lossy(&replaced)
+        );
+        assert!(replaced.ends_with(b"(Bye) Tj\nET"));
+    }
+
+    #[test]
+    fn replace_text_keeps_filters() {
+        let mut doc = create_document_with_texts(&["Hello World"]);
+        let page_id = doc.get_pages()[&1];
+        let content_id = doc.get_page_contents(page_id)[0];
+        let plain = b"BT /F1 12 Tf 100 600 Td (Hello World) Tj ET\n".repeat(20);
+        let stream = doc.get_object_mut(content_id).unwrap().as_stream_mut().unwrap();
+        stream.set_plain_content(plain.clone());
+        stream.set_filters(&[b"FlateDecode"]).unwrap();
+
+        doc.replace_text(1, "Hello World", "Goodbye").unwrap();
+        let mut buffer = Vec::new();
+        doc.save_to(&mut buffer).unwrap();
+        let mut doc = Document::load_mem(&buffer).unwrap();
+        assert!(doc.extract_text(&[1]).unwrap().contains("Goodbye"));
+        let stream = doc.get_object(content_id).unwrap().as_stream().unwrap();
+        assert_eq!(stream.filters().unwrap(), [b"FlateDecode"]);
+        assert!(stream.content.len() < plain.len());
+        assert_eq!(
+            stream.dict.get(b"Length").unwrap().as_i64().unwrap(),
+            stream.content.len() as i64
+        );
+
+        let stream = doc.get_object_mut(content_id).unwrap().as_stream_mut().unwrap();
+        stream.set_filters(&[b"ASCII85Decode", b"FlateDecode"]).unwrap();
+        doc.replace_text(1, "Goodbye", "Hello").unwrap();
+        let stream = doc.get_object(content_id).unwrap().as_stream().unwrap();
+        assert_eq!(stream.filters().unwrap(), [&b"ASCII85Decode"[..], b"FlateDecode"]);
+        assert!(doc.extract_text(&[1]).unwrap().contains("Hello"));
+    }
+
+    #[test]
+    fn replace_partial_text_keeps_two_byte_codes() {
+        let mut doc = document_with_identity_h_font(
+            b"BT /F2 12 Tf <00080005000C000C000F> Tj [<00080009> -200 <000C000C>] TJ /F9 12 Tf <0008> Tj ET",
+        );
+        let report = doc.replace_partial_text(1, "ll", "l").unwrap();
+        assert_eq!(
+            report,
+            TextReplacement {
+                replaced: 2,
+                skipped_operands: 1
+            }
+        );
+
+        let strings = shown_strings(&doc);
+        assert!(strings.iter().all(|bytes| bytes.len() % 2 == 0));
+        assert_eq!(
+            strings,
+            vec![
+                vec![0, 8, 0, 5, 0, 12, 0, 15],
+                vec![0, 8, 0, 9],
+                vec![0, 12],
+                vec![0, 8]
+            ]
+        );
+
+        let report = doc.replace_text(1, "helo", "hi").unwrap();
+        assert_eq!(report.replaced, 1);
+        assert_eq!(shown_strings(&doc)[0], vec![0, 8, 0, 9]);
+    }
+
+    #[test]
+    fn replace_text_refuses_unencodable_text() {
+        let content = b"BT /F2 12 Tf <00080009> Tj ET";
+        let mut doc = document_with_identity_h_font(content);
+        // Upper case letters have no code in the font.
+        let report = doc.replace_text(1, "hi", "HI").unwrap();
+        assert_eq!(
+            report,
+            TextReplacement {
+                replaced: 0,
+                skipped_operands: 1
+            }
+        );
+        assert_eq!(shown_strings(&doc), vec![vec![0, 8, 0, 9]]);
+
+        // Odd length strings can't be split into two byte codes.
+        let mut doc = document_with_identity_h_font(b"BT /F2 12 Tf <000800> Tj ET");
+        assert_eq!(doc.replace_partial_text(1, "h", "i").unwrap().skipped_operands, 1);
+    }
+
+    #[test]
+    fn empty_content_streams() {
+        // Pages with a zero-length stream, an empty compressed stream and only whitespace and comments.
+        let contents = [
+            Stream::new(dictionary! {}, vec![]),
+            Stream::new(dictionary! { "Filter" => "FlateDecode" }, vec![]),
+            Stream::new(dictionary! {}, b" \r\n% only a comment\n%another".to_vec()),
+        ];
+        let mut doc = create_document_with_texts(&["a", "b", "c"]);
+        let pages: Vec<ObjectId> = doc.page_iter().collect();
+        for (&page_id, stream) in pages.iter().zip(contents) {
+            let content_id = doc.get_page_contents(page_id)[0];
+            doc.objects.insert(content_id, Object::Stream(stream));
+        }
+
+        for &page_id in &pages {
+            assert!(doc.get_and_decode_page_content(page_id).unwrap().operations.is_empty());
+            assert!(doc.get_page_text_runs(page_id).unwrap().is_empty());
+        }
+        assert_eq!(doc.extract_text(&[1, 2, 3]).unwrap(), "");
+        assert!(doc.extract_text_chunks(&[1, 2, 3]).is_empty());
+        for page_number in 1..=3 {
+            assert_eq!(doc.replace_text(page_number, "a", "b").unwrap().replaced, 0);
+            assert_eq!(doc.replace_partial_text(page_number, "a", "b").unwrap().replaced, 0);
+        }
+
+        let template = create_document_with_texts(&["watermark"]);
+        let template_page = template.page_iter().next().unwrap();
+        doc.apply_page_overlay(&template, template_page, crate::OverlayMode::Overlay, None)
+            .unwrap();
+        let form = Stream::new(dictionary! { "Subtype" => "Form" }, vec![]);
+        doc.insert_form_object(pages[0], form).unwrap();
+        doc.add_page_contents(pages[1], b"q Q".to_vec()).unwrap();
+        doc.change_page_content(pages[2], vec![]).unwrap();
+
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let doc = Document::load_mem(&buffer).unwrap();
+        assert_eq!(doc.extract_text(&[3]).unwrap(), "");
+        assert!(doc.extract_text(&[1]).unwrap().contains("watermark"));
+    }
+
+    /// Fail if `run` takes longer than `limit`, a generous bound which only catches quadratic behavior.
+    fn assert_completes_within<T>(limit: std::time::Duration, run: impl FnOnce() -> T) -> T {
+        let start = std::time::Instant::now();
+        let result = run();
+        let elapsed = start.elapsed();
+        assert!(elapsed < limit, "took {elapsed:?}, more than {limit:?}");
+        result
+    }
+
+    #[test]
+    fn encode_long_text_through_to_unicode_cmap() {
+        let mut cmap = ToUnicodeCMap::new();
+        for code in 0..2_000 {
+            cmap.put_char(code + 1, 2, vec![0x4E00 + code as u16]);
+        }
+        let encoding = Encoding::UnicodeMapEncoding(cmap);
+        // 1 MiB of UTF-8, 3 bytes for each character.
+        let text: String = (0..(1 << 20) / 3)
+            .map(|i| char::from_u32(0x4E00 + i % 2_000).unwrap())
+            .collect();
+        let codes = assert_completes_within(std::time::Duration::from_secs(20), || {
+            encoding.string_to_codes(&text, 2).unwrap()
+        });
+        assert_eq!(codes.len(), text.chars().count() * 2);
+        assert_eq!(&codes[..4], [0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn replace_partial_text_in_long_content() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let mut content = b"BT /F1 12 Tf 100 600 Td ".to_vec();
+        while content.len() < 500_000 {
+            content.extend_from_slice(b"(Hello World and more words) Tj [(Hello) -120 (World)] TJ 0 -14 Td\n");
+        }
+        content.extend_from_slice(b"ET");
+        doc.change_page_content(page_id, content).unwrap();
+        let report = assert_completes_within(std::time::Duration::from_secs(20), || {
+            doc.replace_partial_text(1, "World", "Earth").unwrap()
+        });
+        assert!(report.replaced > 10_000);
+    }
+
+    #[test]
+    fn extract_text_of_many_text_operations() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let mut content = b"BT /F1 12 Tf 100 600 Td ".to_vec();
+        for i in 0..100_000 {
+            content.extend_from_slice(format!("/Span BMC ({i}) Tj\n").as_bytes());
+        }
+        content.extend_from_slice(b"ET");
+        doc.change_page_content(page_id, content).unwrap();
+        let text = assert_completes_within(std::time::Duration::from_secs(20), || doc.extract_text(&[1]).unwrap());
+        assert!(text.starts_with("0123"));
+    }
+
+    #[test]
+    fn search_text_in_long_string() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        let text = "word ".repeat(100_000);
+        let content = format!("BT /F1 12 Tf 100 600 Td ({text}) Tj ET");
+        doc.change_page_content(page_id, content.into_bytes()).unwrap();
+        let rects = assert_completes_within(std::time::Duration::from_secs(20), || {
+            doc.search_text(page_id, "word").unwrap()
+        });
+        assert_eq!(rects.len(), 100_000);
+        assert!(rects[1][0] > rects[0][0]);
+    }
 }
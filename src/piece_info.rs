@@ -0,0 +1,116 @@
+use crate::{Dictionary, Document, Object, ObjectId, Result};
+use time::OffsetDateTime;
+
+impl Document {
+    /// Get the page-piece data an application stored in the `/PieceInfo` of a page, the dictionary
+    /// with its `/LastModified` date and `/Private` data, `None` if the application stored none.
+    ///
+    /// The data is only valid if it is at least as recent as the `/LastModified` of the page,
+    /// otherwise the page was changed by another application since.
+    pub fn get_piece_info(&self, page_id: impl Into<ObjectId>, app_name: &[u8]) -> Result<Option<&Dictionary>> {
+        let page_id = page_id.into();
+        let page = self.get_dictionary(page_id)?;
+        let Ok(piece_info) = page.get_deref(b"PieceInfo", self).and_then(Object::as_dict) else {
+            return Ok(None);
+        };
+        Ok(piece_info.get_deref(app_name, self).and_then(Object::as_dict).ok())
+    }
+
+    /// Store the private data of an application in the `/PieceInfo` of a page, replacing the data
+    /// it stored before, with the current time as the `/LastModified` date of both the data and the page.
+    pub fn set_piece_info(&mut self, page_id: impl Into<ObjectId>, app_name: &[u8], private: Object) -> Result<()> {
+        let page_id = page_id.into();
+        self.set_piece_info_at(page_id, app_name, private, OffsetDateTime::now_utc())
+    }
+
+    /// Store the private data of an application in the `/PieceInfo` of a page like
+    /// [`Document::set_piece_info`], with `date` as the `/LastModified` date.
+    pub fn set_piece_info_at(
+        &mut self, page_id: impl Into<ObjectId>, app_name: &[u8], private: Object, date: OffsetDateTime,
+    ) -> Result<()> {
+        let page_id = page_id.into();
+        let date = pdf_date(date);
+        let data = dictionary! {
+            "LastModified" => date.clone(),
+            "Private" => private,
+        };
+        let page = self.get_dictionary_mut(page_id)?;
+        page.set("LastModified", date);
+        let piece_info = match page.get(b"PieceInfo") {
+            Ok(Object::Reference(id)) => {
+                let id = *id;
+                self.get_dictionary_mut(id)?
+            }
+            Ok(Object::Dictionary(_)) => page.get_mut(b"PieceInfo").and_then(Object::as_dict_mut)?,
+            _ => {
+                page.set("PieceInfo", Dictionary::new());
+                page.get_mut(b"PieceInfo").and_then(Object::as_dict_mut)?
+            }
+        };
+        piece_info.set(app_name, data);
+        Ok(())
+    }
+
+    /// Set the `/LastModified` date of a page with `/PieceInfo` to the current time after its
+    /// content was changed, so applications know their page-piece data may be stale.
+    /// Pages without `/PieceInfo` are left alone, the date is optional for them.
+    pub(crate) fn touch_piece_info(&mut self, page_id: ObjectId) {
+        if let Ok(page) = self.get_dictionary_mut(page_id) {
+            if page.has(b"PieceInfo") {
+                page.set("LastModified", pdf_date(OffsetDateTime::now_utc()));
+            }
+        }
+    }
+}
+
+/// A date as written in PDF files, which have no fractions of seconds.
+fn pdf_date(date: OffsetDateTime) -> Object {
+    date.replace_nanosecond(0).unwrap_or(date).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+
+    #[test]
+    fn piece_info_round_trip() {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let pages: Vec<ObjectId> = doc.page_iter().collect();
+        assert_eq!(doc.get_piece_info(pages[0], b"FakeApp").unwrap(), None);
+
+        let private = dictionary! { "Layers" => vec![Object::string_literal("Ink")] };
+        let stored = OffsetDateTime::from_unix_timestamp(1_577_934_245).unwrap();
+        doc.set_piece_info_at(pages[0], b"FakeApp", private.clone().into(), stored)
+            .unwrap();
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let mut doc = Document::load_mem(&buffer).unwrap();
+
+        let data = doc.get_piece_info(pages[0], b"FakeApp").unwrap().unwrap();
+        assert_eq!(data.get(b"Private").unwrap(), &Object::Dictionary(private));
+        let date = data.get(b"LastModified").unwrap().clone();
+        assert_eq!(
+            doc.get_dictionary(pages[0]).unwrap().get(b"LastModified").unwrap(),
+            &date
+        );
+        assert_eq!(doc.get_piece_info(pages[0], b"OtherApp").unwrap(), None);
+
+        // Changing the content makes the page more recent than the data, which is kept.
+        doc.add_page_contents(pages[0], b"q Q".to_vec()).unwrap();
+        let page_date = doc.get_dictionary(pages[0]).unwrap().get(b"LastModified").unwrap();
+        assert!(page_date.as_datetime().unwrap() > date.as_datetime().unwrap());
+        let data = doc.get_piece_info(pages[0], b"FakeApp").unwrap().unwrap();
+        assert_eq!(data.get(b"LastModified").unwrap(), &date);
+
+        doc.set_piece_info_at(pages[0], b"FakeApp", Object::Null, stored)
+            .unwrap();
+        doc.replace_text(1, "first", "other").unwrap();
+        let page_date = doc.get_dictionary(pages[0]).unwrap().get(b"LastModified").unwrap();
+        assert!(page_date.as_datetime().unwrap() > date.as_datetime().unwrap());
+
+        // Pages without page-piece data don't get a date.
+        doc.replace_text(2, "second", "other").unwrap();
+        assert!(!doc.get_dictionary(pages[1]).unwrap().has(b"LastModified"));
+    }
+}
@@ -1,16 +1,23 @@
-use crate::Result;
-use crate::{Document, Object, ObjectId};
-use std::collections::BTreeMap;
+use crate::cancel::check_cancel;
+use crate::page::INHERITABLE;
+use crate::xref::XrefEntry;
+use crate::{BookmarkTarget, CancelToken, CertifiedChange, Document, Error, Object, ObjectId, Result, Stream};
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
 use std::io::Write;
 
+use log::{error, warn};
+
 impl Document {
     /// Change producer of document information dictionary.
     pub fn change_producer(&mut self, producer: &str) {
         if let Ok(info) = self.trailer.get_mut(b"Info") {
             if let Some(dict) = match info {
                 Object::Dictionary(dict) => Some(dict),
-                Object::Reference(id) => self.objects.get_mut(id).and_then(|o| o.as_dict_mut().ok()),
+                Object::Reference(id) => {
+                    self.dirty.insert(*id);
+                    self.objects.get_mut(id).and_then(|o| o.as_dict_mut().ok())
+                }
                 _ => None,
             } {
                 dict.set("Producer", Object::string_literal(producer));
@@ -19,39 +26,170 @@ pub fn change_producer(&mut self, producer: &str) {
     }
 
     /// Compress PDF stream objects.
+    ///
+    /// Streams compressed with `LZWDecode` are converted to `FlateDecode`, see [`Stream::lzw_to_flate`](crate::Stream::lzw_to_flate).
+    /// The streams of [`Document::is_excluded_from_compression`] are left as they are.
     pub fn compress(&mut self) {
-        for object in self.objects.values_mut() {
+        self.compress_with(|_, _| false);
+    }
+
+    /// Compress PDF stream objects as [`Document::compress`] does, also leaving the streams for which
+    /// `skip` returns `true` as they are.
+    pub fn compress_with<F: Fn(ObjectId, &Stream) -> bool>(&mut self, skip: F) {
+        // Compressing can only fail when cancelled.
+        let _ = self.compress_internal(skip, None);
+    }
+
+    /// Compress PDF stream objects like [`Document::compress`], stopping with [`Error::Cancelled`]
+    /// once `cancel` is cancelled. The streams compressed until then are kept.
+    pub fn compress_with_cancel(&mut self, cancel: &CancelToken) -> Result<()> {
+        self.compress_internal(|_, _| false, Some(cancel))
+    }
+
+    fn compress_internal<F: Fn(ObjectId, &Stream) -> bool>(
+        &mut self, skip: F, cancel: Option<&CancelToken>,
+    ) -> Result<()> {
+        let hint_offsets = self.hint_stream_offsets();
+        let skipped: HashSet<ObjectId> = self
+            .objects
+            .iter()
+            .filter_map(|(&id, object)| Some((id, object.as_stream().ok()?)))
+            .filter(|&(id, stream)| self.excluded_from_compression(id, stream, &hint_offsets) || skip(id, stream))
+            .map(|(id, _)| id)
+            .collect();
+        for (id, object) in self.objects.iter_mut() {
             if let Object::Stream(stream) = object {
-                if stream.allows_compression {
+                if !skipped.contains(id) {
+                    check_cancel(cancel)?;
                     // Ignore any error and continue to compress other streams.
+                    let _ = stream.lzw_to_flate();
                     let _ = stream.compress();
+                    self.dirty.insert(*id);
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Check whether [`Document::compress`] leaves a stream as it is:
+    /// * a stream which doesn't allow compression, see [`Stream::allows_compression`],
+    /// * a stream whose data is in an external file, see [`Stream::data`],
+    /// * a stream whose `/Type` is in [`Stream::UNCOMPRESSED_TYPES`]: cross-reference streams, whose
+    ///   data must stay in sync with their predictor parameters, object streams, which the writer
+    ///   doesn't regenerate, and metadata streams, which PDF/A requires uncompressed,
+    /// * a hint stream of a linearized file, found at the offset given by `/H` in the linearization
+    ///   dictionary,
+    /// * a stream with a filter which can't be encoded again, i.e. not in [`Stream::ENCODING_FILTERS`].
+    pub fn is_excluded_from_compression(&self, id: impl Into<ObjectId>, stream: &Stream) -> bool {
+        let id = id.into();
+        self.excluded_from_compression(id, stream, &self.hint_stream_offsets())
+    }
+
+    /// [`Document::is_excluded_from_compression`] with the offsets of [`Document::hint_stream_offsets`].
+    fn excluded_from_compression(&self, id: ObjectId, stream: &Stream, hint_offsets: &HashSet<i64>) -> bool {
+        if !stream.allows_compression
+            || stream.is_external()
+            || stream
+                .dict
+                .get_type()
+                .is_ok_and(|name| Stream::UNCOMPRESSED_TYPES.contains(&name))
+        {
+            return true;
+        }
+        let encodable = !stream.dict.has(b"Filter")
+            || stream
+                .filters()
+                .is_ok_and(|filters| filters.iter().all(|filter| Stream::ENCODING_FILTERS.contains(filter)));
+        !encodable || self.is_hint_stream(id, hint_offsets)
+    }
+
+    /// The offsets of the hint streams given by `/H` in the linearization dictionaries.
+    fn hint_stream_offsets(&self) -> HashSet<i64> {
+        self.objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .filter(|dict| dict.has(b"Linearized"))
+            .filter_map(|dict| dict.get(b"H").and_then(Object::as_array).ok())
+            // The primary hint stream, followed by the overflow hint stream if any.
+            .flat_map(|hints| hints.iter().step_by(2))
+            .filter_map(|hint_offset| hint_offset.as_i64().ok())
+            .collect()
+    }
+
+    /// Check whether an object was read at one of the offsets of hint streams.
+    fn is_hint_stream(&self, id: ObjectId, hint_offsets: &HashSet<i64>) -> bool {
+        match self.reference_table.get(id.0) {
+            Some(XrefEntry::Normal { offset, .. }) => {
+                i64::try_from(*offset).is_ok_and(|offset| hint_offsets.contains(&offset))
+            }
+            _ => false,
+        }
     }
 
     /// Decompress PDF stream objects.
     pub fn decompress(&mut self) {
-        for object in self.objects.values_mut() {
+        for (id, object) in self.objects.iter_mut() {
             if let Object::Stream(stream) = object {
                 let _ = stream.decompress();
+                self.dirty.insert(*id);
             }
         }
     }
 
     /// Delete pages.
+    ///
+    /// Every reference to a deleted page is removed from the page tree, even when a malformed tree lists
+    /// the page in several `/Kids` arrays, and the `/Count` of the nodes which listed it and of their
+    /// ancestors is decreased accordingly. Article thread beads on deleted pages are removed from their
+    /// threads, see [`Document::get_article_threads`].
+    ///
+    /// Nothing is deleted if the certification of the document doesn't permit it, see
+    /// [`Document::try_delete_pages`].
     pub fn delete_pages(&mut self, page_numbers: &[u32]) {
+        if let Err(err) = self.try_delete_pages(page_numbers) {
+            error!("Could not delete pages: {err}");
+        }
+    }
+
+    /// Delete pages like [`Document::delete_pages`], failing with [`Error::CertificationViolation`] if changes
+    /// are checked against the certification of the document, see [`Document::set_respect_certification`].
+    pub fn try_delete_pages(&mut self, page_numbers: &[u32]) -> Result<()> {
+        self.check_certification(CertifiedChange::Other)?;
         let pages = self.get_pages();
         for page_number in page_numbers {
-            if let Some(page) = pages.get(page_number).and_then(|page_id| self.delete_object(*page_id)) {
-                let mut page_tree_ref = page
-                    .as_dict()
-                    .and_then(|dict| dict.get(b"Parent"))
-                    .and_then(Object::as_reference);
+            let Some(&page_id) = pages.get(page_number) else {
+                continue;
+            };
+            // Number of times each node of the page tree lists the page.
+            let listings: Vec<(ObjectId, i64)> = self
+                .page_tree_nodes()
+                .into_iter()
+                .filter_map(|node_id| {
+                    let kids = self.get_dictionary(node_id).ok()?.get(b"Kids").ok()?.as_array().ok()?;
+                    let listed = kids
+                        .iter()
+                        .filter(|kid| kid.as_reference().ok() == Some(page_id))
+                        .count();
+                    (listed > 0).then_some((node_id, listed as i64))
+                })
+                .collect();
+            if let Err(err) = self.unlink_page_beads(page_id) {
+                warn!("Could not remove the article beads of page {page_id:?}: {err}");
+            }
+            if self.delete_object(page_id).is_none() {
+                continue;
+            }
+            for (node_id, listed) in listings {
+                let mut visited = HashSet::new();
+                let mut page_tree_ref = Ok(node_id);
                 while let Ok(page_tree_id) = page_tree_ref {
+                    if !visited.insert(page_tree_id) {
+                        break;
+                    }
+                    self.dirty.insert(page_tree_id);
                     if let Some(page_tree) = self.objects.get_mut(&page_tree_id).and_then(|pt| pt.as_dict_mut().ok()) {
                         if let Ok(count) = page_tree.get(b"Count").and_then(Object::as_i64) {
-                            page_tree.set("Count", count - 1);
+                            page_tree.set("Count", (count - listed).max(0));
                         }
                         page_tree_ref = page_tree.get(b"Parent").and_then(Object::as_reference);
                     } else {
@@ -60,12 +198,350 @@ pub fn delete_pages(&mut self, page_numbers: &[u32]) {
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Move a page so that it becomes page number `to`, shifting the pages in between.
+    ///
+    /// The page may be moved under another node of the page tree, whose `/Count` and those of its ancestors
+    /// are updated. References to the page, e.g. from outline destinations, keep pointing to it.
+    pub fn move_page(&mut self, from: u32, to: u32) -> Result<()> {
+        let pages: Vec<ObjectId> = self.get_pages().into_values().collect();
+        let page_count = pages.len() as u32;
+        for page_number in [from, to] {
+            if page_number == 0 || page_number > page_count {
+                return Err(Error::PageNumberNotFound(page_number));
+            }
+        }
+        if from == to {
+            return Ok(());
+        }
+
+        let page_id = pages[from as usize - 1];
+        let (old_parent, old_index) = self.page_location(page_id)?;
+        let others: Vec<ObjectId> = pages.into_iter().filter(|&id| id != page_id).collect();
+        // Insert before the page taking its place, or after the last page.
+        let (new_parent, new_index) = match others.get(to as usize - 1) {
+            Some(&next_id) => self.page_location(next_id)?,
+            None => self
+                .page_location(*others.last().unwrap())
+                .map(|(parent, index)| (parent, index + 1))?,
+        };
+        if old_parent != new_parent {
+            self.copy_inherited_attributes(page_id, false);
+        }
+
+        self.get_dictionary_mut(old_parent)?
+            .get_mut(b"Kids")?
+            .as_array_mut()?
+            .remove(old_index);
+        self.add_to_page_counts(old_parent, -1);
+        let new_index = if old_parent == new_parent && old_index < new_index {
+            new_index - 1
+        } else {
+            new_index
+        };
+        self.get_dictionary_mut(new_parent)?
+            .get_mut(b"Kids")?
+            .as_array_mut()?
+            .insert(new_index, page_id.into());
+        self.add_to_page_counts(new_parent, 1);
+        self.get_dictionary_mut(page_id)?.set("Parent", new_parent);
+        Ok(())
+    }
+
+    /// Exchange the positions of two pages, which may be under different nodes of the page tree.
+    pub fn swap_pages(&mut self, a: u32, b: u32) -> Result<()> {
+        let pages = self.get_pages();
+        let page_a = *pages.get(&a).ok_or(Error::PageNumberNotFound(a))?;
+        let page_b = *pages.get(&b).ok_or(Error::PageNumberNotFound(b))?;
+        if a == b {
+            return Ok(());
+        }
+
+        let (parent_a, index_a) = self.page_location(page_a)?;
+        let (parent_b, index_b) = self.page_location(page_b)?;
+        if parent_a != parent_b {
+            self.copy_inherited_attributes(page_a, false);
+            self.copy_inherited_attributes(page_b, false);
+        }
+        self.get_dictionary_mut(parent_a)?.get_mut(b"Kids")?.as_array_mut()?[index_a] = page_b.into();
+        self.get_dictionary_mut(parent_b)?.get_mut(b"Kids")?.as_array_mut()?[index_b] = page_a.into();
+        self.get_dictionary_mut(page_a)?.set("Parent", parent_b);
+        self.get_dictionary_mut(page_b)?.set("Parent", parent_a);
+        Ok(())
+    }
+
+    /// Reverse the order of the pages, e.g. of a document scanned backwards.
+    pub fn reverse_pages(&mut self) -> Result<()> {
+        let page_count = self.get_pages().len() as u32;
+        for page_number in 1..=page_count / 2 {
+            self.swap_pages(page_number, page_count + 1 - page_number)?;
+        }
+        Ok(())
+    }
+
+    /// The default number of kids per node of [`Document::rebalance_page_tree`], also used when saving
+    /// with [`SaveOptions::rebalance_page_tree_above`](crate::SaveOptions::rebalance_page_tree_above).
+    pub const PAGE_TREE_MAX_KIDS: usize = 32;
+
+    /// Rebuild the page tree into a balanced hierarchy of nodes with at most `max_kids_per_node`
+    /// kids each, e.g. [`Document::PAGE_TREE_MAX_KIDS`], keeping the order of the pages.
+    ///
+    /// The root of the page tree stays the root, with its inheritable attributes. Those the pages
+    /// inherit from the other nodes, which are replaced, are copied onto the pages.
+    pub fn rebalance_page_tree(&mut self, max_kids_per_node: usize) -> Result<()> {
+        let max_kids = max_kids_per_node.max(2);
+        let root_id = self.catalog()?.get(b"Pages").and_then(Object::as_reference)?;
+        let pages: Vec<ObjectId> = self.page_iter().collect();
+        let old_nodes: Vec<ObjectId> = self
+            .page_tree_nodes()
+            .into_iter()
+            .filter(|&node_id| node_id != root_id)
+            .collect();
+
+        for &page_id in &pages {
+            self.copy_attributes_inherited_below(page_id, root_id);
+        }
+        for node_id in old_nodes {
+            self.dirty.insert(node_id);
+            self.objects.remove(&node_id);
+        }
+
+        // Group the kids of each level into as few nodes as possible, of sizes differing by one at most.
+        let mut level: Vec<(ObjectId, i64)> = pages.into_iter().map(|page_id| (page_id, 1)).collect();
+        while level.len() > max_kids {
+            let node_count = level.len().div_ceil(max_kids);
+            let (size, larger) = (level.len() / node_count, level.len() % node_count);
+            let mut kids = level.into_iter();
+            level = Vec::with_capacity(node_count);
+            for index in 0..node_count {
+                let group: Vec<(ObjectId, i64)> = kids.by_ref().take(size + usize::from(index < larger)).collect();
+                let count = group.iter().map(|(_, count)| count).sum();
+                let node_id = self.add_object(dictionary! {
+                    "Type" => "Pages",
+                    "Parent" => root_id,
+                    "Kids" => group.iter().map(|(kid_id, _)| Object::Reference(*kid_id)).collect::<Vec<_>>(),
+                    "Count" => count,
+                });
+                for (kid_id, _) in group {
+                    self.get_dictionary_mut(kid_id)?.set("Parent", node_id);
+                }
+                level.push((node_id, count));
+            }
+        }
+
+        let count: i64 = level.iter().map(|(_, count)| count).sum();
+        for &(kid_id, _) in &level {
+            self.get_dictionary_mut(kid_id)?.set("Parent", root_id);
+        }
+        let root = self.get_dictionary_mut(root_id)?;
+        root.set(
+            "Kids",
+            level
+                .into_iter()
+                .map(|(kid_id, _)| Object::Reference(kid_id))
+                .collect::<Vec<_>>(),
+        );
+        root.set("Count", count);
+        Ok(())
+    }
+
+    /// Copy onto a page the attributes it inherits from the page tree nodes below `root_id`.
+    fn copy_attributes_inherited_below(&mut self, page_id: ObjectId, root_id: ObjectId) {
+        let Ok(page) = self.page(page_id) else {
+            return;
+        };
+        let inherited: Vec<(&[u8], Object)> = INHERITABLE
+            .into_iter()
+            .filter(|&key| !page.raw().has(key))
+            .filter_map(|key| {
+                let mut nodes = page.ancestry().skip(1).take_while(|&(node_id, _)| node_id != root_id);
+                let value = nodes.find_map(|(_, node)| node.get(key).ok())?;
+                Some((key, value.clone()))
+            })
+            .collect();
+        if let (false, Ok(page)) = (inherited.is_empty(), self.get_dictionary_mut(page_id)) {
+            for (key, value) in inherited {
+                page.set(key, value);
+            }
+        }
+    }
+
+    /// Get the node of the page tree listing a page, and the index of the page in its `/Kids`.
+    fn page_location(&self, page_id: ObjectId) -> Result<(ObjectId, usize)> {
+        let index_in = |node_id: ObjectId| {
+            let kids = self.get_dictionary(node_id).ok()?.get(b"Kids").ok()?.as_array().ok()?;
+            kids.iter().position(|kid| kid.as_reference().ok() == Some(page_id))
+        };
+        let parent = self
+            .get_dictionary(page_id)?
+            .get(b"Parent")
+            .and_then(Object::as_reference);
+        if let Some(index) = parent.as_ref().ok().and_then(|&parent_id| index_in(parent_id)) {
+            return Ok((parent.unwrap(), index));
+        }
+        // The /Parent of the page is wrong, look for the node listing it.
+        self.page_tree_nodes()
+            .into_iter()
+            .find_map(|node_id| index_in(node_id).map(|index| (node_id, index)))
+            .ok_or(Error::ObjectNotFound(page_id))
+    }
+
+    /// Add `delta` to the `/Count` of a node of the page tree and of its ancestors.
+    fn add_to_page_counts(&mut self, node_id: ObjectId, delta: i64) {
+        let mut visited = HashSet::new();
+        let mut node_ref = Ok(node_id);
+        while let Ok(node_id) = node_ref {
+            if !visited.insert(node_id) {
+                break;
+            }
+            let Ok(node) = self.get_dictionary_mut(node_id) else {
+                break;
+            };
+            if let Ok(count) = node.get(b"Count").and_then(Object::as_i64) {
+                node.set("Count", (count + delta).max(0));
+            }
+            node_ref = node.get(b"Parent").and_then(Object::as_reference);
+        }
+    }
+
+    /// Copy onto pages the attributes they inherit from the page tree: `/Resources`, `/MediaBox`,
+    /// `/CropBox` and `/Rotate`. All pages are changed if `pages` is `None`.
+    ///
+    /// Afterwards, editing the attributes of one page doesn't change other pages. Inherited
+    /// resources are copied into a new resource dictionary for each page, together with the
+    /// dictionaries of each resource type, e.g. `/Font`; the fonts, images and other resources
+    /// themselves are still shared. The other attributes are copied directly into each page, even
+    /// when the page tree node refers to them. With `remove_from_ancestors`, attributes of the
+    /// page tree nodes which no page inherits any more are removed.
+    ///
+    /// Page tree edits such as [`Document::move_page`] copy the attributes of the pages they move,
+    /// without isolating them or removing them from the page tree.
+    pub fn materialize_inherited_attributes(&mut self, pages: Option<&[ObjectId]>, remove_from_ancestors: bool) {
+        let pages = match pages {
+            Some(pages) => pages.to_vec(),
+            None => self.page_iter().collect(),
+        };
+        for page_id in pages {
+            self.copy_inherited_attributes(page_id, true);
+        }
+        if remove_from_ancestors {
+            self.remove_unused_inherited_attributes();
+        }
+    }
+
+    /// Copy onto a page the attributes it inherits from the page tree, before it is moved under another
+    /// node or edited on its own. With `isolate`, shared resources are copied too, and other attributes
+    /// are copied instead of the references to them.
+    fn copy_inherited_attributes(&mut self, page_id: ObjectId, isolate: bool) {
+        let Ok(page) = self.page(page_id) else {
+            return;
+        };
+        let mut inherited: Vec<(&[u8], Object)> = INHERITABLE
+            .into_iter()
+            .filter(|&key| !page.raw().has(key))
+            .filter_map(|key| Some((key, page.inherited_entry(key)?.clone())))
+            .collect();
+
+        for (key, value) in inherited.iter_mut().filter(|_| isolate) {
+            *value = match *key {
+                b"Resources" => self.copy_resources(value),
+                _ => match self.dereference(value) {
+                    Ok((_, object)) => object.clone(),
+                    Err(_) => continue,
+                },
+            };
+        }
+        if let Ok(page) = self.get_dictionary_mut(page_id) {
+            for (key, value) in inherited {
+                page.set(key, value);
+            }
+        }
+    }
+
+    /// Copy a resource dictionary shared by reference, with the dictionaries of each resource type
+    /// it refers to, into a new object.
+    fn copy_resources(&mut self, resources: &Object) -> Object {
+        let Ok((Some(_), Object::Dictionary(resources))) = self.dereference(resources) else {
+            return resources.clone();
+        };
+        let mut copy = resources.clone();
+        for (_, value) in copy.iter_mut() {
+            if let Ok((Some(_), Object::Dictionary(category))) = self.dereference(value) {
+                *value = Object::Dictionary(category.clone());
+            }
+        }
+        self.add_object(copy).into()
+    }
+
+    /// Remove the inheritable attributes of page tree nodes which are set by all pages below them.
+    fn remove_unused_inherited_attributes(&mut self) {
+        let nodes: HashSet<ObjectId> = self.page_tree_nodes().into_iter().collect();
+        let mut used: HashSet<(ObjectId, &[u8])> = HashSet::new();
+        for page in self.page_iter().filter_map(|page_id| self.page(page_id).ok()) {
+            for key in INHERITABLE {
+                if let Some((node_id, _)) = page.inherited_from(key) {
+                    used.insert((node_id, key));
+                }
+            }
+        }
+        for node_id in nodes {
+            let unused: Vec<&[u8]> = INHERITABLE
+                .into_iter()
+                .filter(|&key| !used.contains(&(node_id, key)))
+                .collect();
+            let has_unused = self
+                .get_dictionary(node_id)
+                .is_ok_and(|node| unused.iter().any(|key| node.has(key)));
+            if let (true, Ok(node)) = (has_unused, self.get_dictionary_mut(node_id)) {
+                for key in unused {
+                    node.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Get the IDs of the intermediate nodes of the page tree, starting with its root.
+    fn page_tree_nodes(&self) -> Vec<ObjectId> {
+        let mut nodes: Vec<ObjectId> = self
+            .catalog()
+            .and_then(|catalog| catalog.get(b"Pages"))
+            .and_then(Object::as_reference)
+            .into_iter()
+            .collect();
+        let mut index = 0;
+        while let Some(&node_id) = nodes.get(index) {
+            index += 1;
+            let kids = self
+                .get_dictionary(node_id)
+                .and_then(|node| node.get(b"Kids"))
+                .and_then(Object::as_array);
+            for kid_id in kids.into_iter().flatten().filter_map(|kid| kid.as_reference().ok()) {
+                let is_node = matches!(self.get_dictionary(kid_id).and_then(|kid| kid.get_type()), Ok(b"Pages"));
+                if is_node && !nodes.contains(&kid_id) {
+                    nodes.push(kid_id);
+                }
+            }
+        }
+        nodes
     }
 
     /// Prune all unused objects.
     pub fn prune_objects(&mut self) -> Vec<ObjectId> {
+        // Pruning can only fail when cancelled.
+        self.prune_objects_internal(None).unwrap_or_default()
+    }
+
+    /// Prune all unused objects like [`Document::prune_objects`], stopping with [`Error::Cancelled`]
+    /// once `cancel` is cancelled. Nothing is removed then.
+    pub fn prune_objects_with_cancel(&mut self, cancel: &CancelToken) -> Result<Vec<ObjectId>> {
+        self.prune_objects_internal(Some(cancel))
+    }
+
+    fn prune_objects_internal(&mut self, cancel: Option<&CancelToken>) -> Result<Vec<ObjectId>> {
         let mut ids = vec![];
-        let refs = self.traverse_objects(|_| {});
+        let refs = self.traverse_objects_with_cancel(|_| {}, cancel)?;
         for id in self.objects.keys() {
             if !refs.contains(id) {
                 ids.push(*id);
@@ -74,21 +550,21 @@ pub fn prune_objects(&mut self) -> Vec<ObjectId> {
 
         for id in &ids {
             self.objects.remove(id);
+            self.dirty.insert(*id);
         }
 
-        ids
+        Ok(ids)
     }
 
     /// Delete object by object ID.
-    pub fn delete_object(&mut self, id: ObjectId) -> Option<Object> {
+    pub fn delete_object(&mut self, id: impl Into<ObjectId>) -> Option<Object> {
+        let id = id.into();
         let action = |object: &mut Object| match object {
             Object::Array(array) => {
-                if let Some(index) = array.iter().position(|item: &Object| match *item {
-                    Object::Reference(ref_id) => ref_id == id,
-                    _ => false,
-                }) {
-                    array.remove(index);
-                }
+                array.retain(|item: &Object| match *item {
+                    Object::Reference(ref_id) => ref_id != id,
+                    _ => true,
+                });
             }
             Object::Dictionary(dict) => {
                 let keys: Vec<Vec<u8>> = dict
@@ -106,9 +582,48 @@ pub fn delete_object(&mut self, id: ObjectId) -> Option<Object> {
             _ => {}
         };
         self.traverse_objects(action);
+        self.dirty.insert(id);
         self.objects.remove(&id)
     }
 
+    /// Replace references to `old` with references to `new` inside the object `parent`.
+    ///
+    /// Only the direct objects nested in `parent` are visited, indirect references out of it are
+    /// not followed. Returns the number of replaced references.
+    pub fn replace_reference_in(
+        &mut self, parent: impl Into<ObjectId>, old: impl Into<ObjectId>, new: impl Into<ObjectId>,
+    ) -> Result<usize> {
+        let parent = parent.into();
+        let old = old.into();
+        let new = new.into();
+        fn replace(object: &mut Object, old: ObjectId, new: ObjectId) -> usize {
+            match object {
+                Object::Reference(id) if *id == old => {
+                    *id = new;
+                    1
+                }
+                Object::Array(array) => array.iter_mut().map(|item| replace(item, old, new)).sum(),
+                Object::Dictionary(dict) => dict.iter_mut().map(|(_, value)| replace(value, old, new)).sum(),
+                Object::Stream(stream) => stream.dict.iter_mut().map(|(_, value)| replace(value, old, new)).sum(),
+                _ => 0,
+            }
+        }
+
+        let object = self.get_object_mut(parent)?;
+        Ok(replace(object, old, new))
+    }
+
+    /// Add a copy of the object `id` under a new object ID.
+    ///
+    /// References inside the copy still point to the same objects as the original, so only the
+    /// top-level object is duplicated. Combined with [`Document::replace_reference_in`] this allows
+    /// copy-on-write editing of shared objects.
+    pub fn clone_object_shallow(&mut self, id: impl Into<ObjectId>) -> Result<ObjectId> {
+        let id = id.into();
+        let object = self.get_object(id)?.clone();
+        Ok(self.add_object(object))
+    }
+
     /// Delete zero length stream objects.
     pub fn delete_zero_length_streams(&mut self) -> Vec<ObjectId> {
         let mut ids = vec![];
@@ -138,16 +653,18 @@ pub fn renumber_objects(&mut self) {
 
     fn update_bookmark_pages(&mut self, bookmarks: &[u32], old: &ObjectId, new: &ObjectId) {
         for id in bookmarks {
-            let (children, page) = match self.bookmark_table.get(id) {
-                Some(n) => (n.children.clone(), n.page),
+            let children = match self.bookmark_table.get_mut(id) {
+                Some(n) => {
+                    if let BookmarkTarget::Page(page, _) = &mut n.target {
+                        if page == old {
+                            *page = *new;
+                        }
+                    }
+                    n.children.clone()
+                }
                 None => return,
             };
 
-            if page == *old {
-                let bookmark = self.bookmark_table.get_mut(id).unwrap();
-                bookmark.page = *new;
-            }
-
             if !children.is_empty() {
                 self.update_bookmark_pages(&children[..], old, new);
             }
@@ -170,7 +687,7 @@ pub fn renumber_objects_with(&mut self, starting_id: u32) {
         // Check if we need to order the pages first, as this means the first page doesn't have a lower ID.
         // So it ends up in a random spot based on its ID. We check first to avoid double traversal, unless we have too.
 
-        let mut page_order: Vec<(i32, (u32, u16))> = self
+        let mut page_order: Vec<(i32, ObjectId)> = self
             .page_iter()
             .map(|id| {
                 i += 1;
@@ -194,17 +711,19 @@ pub fn renumber_objects_with(&mut self, starting_id: u32) {
 
             for (old, new) in pages.iter().zip(page_order) {
                 if let Some(object) = self.objects.remove(&old.1) {
-                    objects.insert((new.1 .0, old.1 .1), object);
-                    replace.insert(old.1, (new.1 .0, old.1 .1));
+                    objects.insert(ObjectId(new.1 .0, old.1 .1), object);
+                    replace.insert(old.1, ObjectId(new.1 .0, old.1 .1));
+                    self.dirty.insert(old.1);
                 }
 
                 if old.1 != new.1 {
-                    self.renumber_bookmarks(&old.1, &(new.1 .0, old.1 .1));
+                    self.renumber_bookmarks(&old.1, &ObjectId(new.1 .0, old.1 .1));
                 }
             }
 
             for (new, object) in objects {
                 self.objects.insert(new, object);
+                self.dirty.insert(new);
             }
 
             let action = |object: &mut Object| {
@@ -224,7 +743,7 @@ pub fn renumber_objects_with(&mut self, starting_id: u32) {
 
         for id in ids {
             if id.0 != new_id {
-                replace.insert(id, (new_id, id.1));
+                replace.insert(id, ObjectId(new_id, id.1));
             }
 
             new_id += 1;
@@ -257,19 +776,26 @@ pub fn renumber_objects_with(&mut self, starting_id: u32) {
         };
 
         self.traverse_objects(action);
+        // Every object may have a new ID, and the old IDs are gone.
+        for &id in replace.keys().chain(replace.values()) {
+            self.dirty.insert(id);
+        }
 
         self.max_id = new_id - 1;
     }
 
-    pub fn change_content_stream(&mut self, stream_id: ObjectId, content: Vec<u8>) {
+    pub fn change_content_stream(&mut self, stream_id: impl Into<ObjectId>, content: Vec<u8>) {
+        let stream_id = stream_id.into();
         if let Some(Object::Stream(stream)) = self.objects.get_mut(&stream_id) {
-            stream.set_plain_content(content);
-            // Ignore any compression error.
+            self.dirty.insert(stream_id);
+            // Ignore any compression error, the content is then stored unencoded.
+            let _ = stream.set_decoded_content(content);
             let _ = stream.compress();
         }
     }
 
-    pub fn change_page_content(&mut self, page_id: ObjectId, content: Vec<u8>) -> Result<()> {
+    pub fn change_page_content(&mut self, page_id: impl Into<ObjectId>, content: Vec<u8>) -> Result<()> {
+        let page_id = page_id.into();
         let contents = self.get_dictionary(page_id).and_then(|page| page.get(b"Contents"))?;
         match contents {
             Object::Reference(id) => self.change_content_stream(*id, content),
@@ -279,7 +805,17 @@ pub fn change_page_content(&mut self, page_id: ObjectId, content: Vec<u8>) -> Re
                         self.change_content_stream(id, content)
                     }
                 } else {
-                    let new_stream = self.add_object(super::Stream::new(dictionary! {}, content));
+                    // The new stream is encoded like the first of the streams it replaces.
+                    let mut stream = super::Stream::new(dictionary! {}, vec![]);
+                    let first = arr.first().and_then(|first| self.dereference(first).ok());
+                    if let Some((_, Object::Stream(first))) = first {
+                        if let Ok(filter) = first.dict.get(b"Filter") {
+                            stream.dict.set("Filter", filter.clone());
+                        }
+                    }
+                    let _ = stream.set_decoded_content(content);
+                    let _ = stream.compress();
+                    let new_stream = self.add_object(stream);
                     if let Ok(Object::Dictionary(dict)) = self.get_object_mut(page_id) {
                         dict.set("Contents", new_stream);
                     }
@@ -287,10 +823,12 @@ pub fn change_page_content(&mut self, page_id: ObjectId, content: Vec<u8>) -> Re
             }
             _ => {}
         }
+        self.touch_piece_info(page_id);
         Ok(())
     }
 
-    pub fn extract_stream(&self, stream_id: ObjectId, decompress: bool) -> Result<()> {
+    pub fn extract_stream(&self, stream_id: impl Into<ObjectId>, decompress: bool) -> Result<()> {
+        let stream_id = stream_id.into();
         let mut file = File::create(format!("{:?}.bin", stream_id))?;
         if let Ok(Object::Stream(stream)) = self.get_object(stream_id) {
             if decompress {
@@ -306,3 +844,424 @@ pub fn extract_stream(&self, stream_id: ObjectId, decompress: bool) -> Result<()
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::creator::tests::create_document_with_texts;
+    use crate::filters::lzw;
+    use crate::page::INHERITABLE;
+    use crate::{Document, Error, Object, ObjectId, Rotation, Stream};
+
+    #[test]
+    fn compress_leaves_excluded_streams() {
+        let mut doc = create_document_with_texts(&["first"]);
+        let xml = b"<rdf:Description rdf:about='' xmlns:dc='http://purl.org/dc/elements/1.1/'/>".repeat(20);
+        let metadata_id = doc.add_object(Stream::new(
+            dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+            xml.clone(),
+        ));
+        doc.catalog_mut().unwrap().set("Metadata", metadata_id);
+        // LZW compressed JPEG data, which can't be encoded again.
+        let filters = vec!["LZWDecode".into(), "DCTDecode".into()];
+        let image_id = doc.add_object(Stream::new(
+            dictionary! { "Type" => "XObject", "Subtype" => "Image", "Filter" => filters },
+            lzw::encode(&[0xFF; 500], true),
+        ));
+        let user_skipped_id = doc.add_object(Stream::new(dictionary! {}, vec![b'x'; 500]));
+        let compressed_id = doc.add_object(Stream::new(dictionary! {}, vec![b'y'; 500]));
+        for id in [metadata_id, image_id, user_skipped_id, compressed_id] {
+            doc.catalog_mut().unwrap().set(format!("Stream{}", id.0), id);
+        }
+
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let original = Document::load_mem(&buffer).unwrap();
+        let mut doc = original.clone();
+        doc.compress_with(|id, _| id == user_skipped_id);
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let saved = Document::load_mem(&buffer).unwrap();
+        for id in [metadata_id, image_id, user_skipped_id] {
+            let stream = saved.get_object(id).and_then(Object::as_stream).unwrap();
+            let before = original.get_object(id).and_then(Object::as_stream).unwrap();
+            assert_eq!((&stream.dict, &stream.content), (&before.dict, &before.content));
+        }
+        let stream = saved.get_object(compressed_id).and_then(Object::as_stream).unwrap();
+        assert_eq!(stream.filters().unwrap(), [b"FlateDecode"]);
+
+        // Cross-reference and object streams of a loaded file.
+        let mut doc = Document::load("assets/AnnotationDemo.pdf").unwrap();
+        doc.clear_dirty();
+        let structural: Vec<(ObjectId, Object)> = doc
+            .objects
+            .iter()
+            .filter(|(_, object)| matches!(object.type_name(), Ok(b"XRef" | b"ObjStm")))
+            .map(|(&id, object)| (id, object.clone()))
+            .collect();
+        assert!(!structural.is_empty());
+        doc.compress();
+        for (id, object) in structural {
+            assert_eq!(doc.get_object(id).unwrap(), &object);
+            assert!(!doc.dirty_objects().contains(&id));
+        }
+    }
+
+    #[test]
+    fn compress_leaves_hint_streams() {
+        let texts: Vec<String> = (0..100).map(|i| format!("page {i}")).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let mut buffer = vec![];
+        create_document_with_texts(&texts).save_linearized(&mut buffer).unwrap();
+        let original = Document::load_mem(&buffer).unwrap();
+        let (hint_id, hint_stream) = original
+            .objects
+            .iter()
+            .filter_map(|(&id, object)| Some((id, object.as_stream().ok()?)))
+            .find(|(id, _)| original.is_hint_stream(*id, &original.hint_stream_offsets()))
+            .unwrap();
+        let hint_stream = hint_stream.clone();
+        assert!(original.is_excluded_from_compression(hint_id, &hint_stream));
+        // Compressing it would change it.
+        let mut compressed = hint_stream.clone();
+        compressed.compress().unwrap();
+        assert_ne!(compressed.content, hint_stream.content);
+
+        let mut doc = original.clone();
+        doc.compress();
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let saved = Document::load_mem(&buffer).unwrap();
+        let stream = saved.get_object(hint_id).and_then(Object::as_stream).unwrap();
+        assert_eq!(
+            (&stream.dict, &stream.content),
+            (&hint_stream.dict, &hint_stream.content)
+        );
+    }
+
+    #[test]
+    fn copy_on_write_shared_resources() {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let pages = doc.get_pages();
+        let (page1, page2) = (pages[&1], pages[&2]);
+        let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let resources_id = doc
+            .get_dictionary(pages_id)
+            .unwrap()
+            .get(b"Resources")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        for page in [page1, page2] {
+            doc.get_dictionary_mut(page).unwrap().set("Resources", resources_id);
+        }
+
+        let copy_id = doc.clone_object_shallow(resources_id).unwrap();
+        assert_ne!(copy_id, resources_id);
+        assert_eq!(doc.replace_reference_in(page1, resources_id, copy_id).unwrap(), 1);
+        // References are not followed out of the parent object.
+        assert_eq!(
+            doc.replace_reference_in(pages_id, ObjectId(page1.0 + 1000, 0), copy_id)
+                .unwrap(),
+            0
+        );
+
+        doc.get_dictionary_mut(copy_id)
+            .unwrap()
+            .set("ProcSet", vec![Object::Name(b"PDF".to_vec())]);
+
+        let page1_resources = doc.get_dictionary(page1).unwrap().get(b"Resources").unwrap();
+        assert_eq!(page1_resources.as_reference().unwrap(), copy_id);
+        let page2_resources = doc.get_dictionary(page2).unwrap().get(b"Resources").unwrap();
+        assert_eq!(page2_resources.as_reference().unwrap(), resources_id);
+        assert!(!doc.get_dictionary(resources_id).unwrap().has(b"ProcSet"));
+        // The shallow copy still shares the font dictionary of the original.
+        assert_eq!(
+            doc.get_dictionary(copy_id).unwrap().get(b"Font").unwrap(),
+            doc.get_dictionary(resources_id).unwrap().get(b"Font").unwrap()
+        );
+        assert!(doc.clone_object_shallow(ObjectId(9999, 0)).is_err());
+    }
+
+    #[test]
+    fn materialized_attributes_are_isolated() {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let pages = doc.get_pages();
+        let (page1, page2) = (pages[&1], pages[&2]);
+        let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let resources_id = doc.page(page2).unwrap().resources_id().unwrap();
+        doc.get_dictionary_mut(pages_id).unwrap().set("Rotate", 90);
+        let crop_box_id = doc.add_object(vec![10.into(), 10.into(), 500.into(), 800.into()]);
+        doc.get_dictionary_mut(pages_id).unwrap().set("CropBox", crop_box_id);
+
+        doc.materialize_inherited_attributes(Some(&[page1]), true);
+        let page = doc.get_dictionary_mut(page1).unwrap();
+        page.set("MediaBox", vec![0.into(), 0.into(), 100.into(), 100.into()]);
+        page.set("Rotate", 180);
+        // Each attribute is copied, even those the page tree node refers to.
+        let crop_box = page.get_mut(b"CropBox").unwrap().as_array_mut().unwrap();
+        crop_box[0] = 0.into();
+        let page1_resources = doc.page(page1).unwrap().resources_id().unwrap();
+        assert_ne!(page1_resources, resources_id);
+        doc.get_dictionary_mut(page1_resources)
+            .unwrap()
+            .get_mut(b"Font")
+            .unwrap()
+            .as_dict_mut()
+            .unwrap()
+            .remove(b"F1");
+
+        let sibling = doc.page(page2).unwrap();
+        assert_eq!(sibling.media_box().unwrap(), [0.0, 0.0, 595.0, 842.0]);
+        assert_eq!(sibling.rotate().unwrap(), Rotation::R90);
+        assert_eq!(sibling.crop_box().unwrap(), [10.0, 10.0, 500.0, 800.0]);
+        assert_eq!(doc.page(page1).unwrap().crop_box().unwrap(), [0.0, 10.0, 500.0, 800.0]);
+        assert_eq!(sibling.resources_id(), Some(resources_id));
+        let has_f1 = |page: ObjectId| {
+            let resources = doc.page(page).unwrap().resources().unwrap();
+            doc.get_dict_in_dict(resources, b"Font").unwrap().has(b"F1")
+        };
+        assert!(has_f1(page2));
+        assert!(!has_f1(page1));
+        // The sibling still inherits from the page tree root.
+        assert!(doc.get_dictionary(pages_id).unwrap().has(b"MediaBox"));
+
+        // The page tree keeps the attributes unless they are to be removed.
+        doc.materialize_inherited_attributes(None, false);
+        let root = doc.get_dictionary(pages_id).unwrap();
+        assert!(INHERITABLE.iter().all(|key| root.has(key)));
+        doc.materialize_inherited_attributes(None, true);
+        let root = doc.get_dictionary(pages_id).unwrap();
+        assert!(!INHERITABLE.iter().any(|key| root.has(key)));
+        let sibling = doc.page(page2).unwrap();
+        assert_eq!(sibling.media_box().unwrap(), [0.0, 0.0, 595.0, 842.0]);
+        assert_eq!(sibling.rotate().unwrap(), Rotation::R90);
+        assert_eq!(doc.page(page1).unwrap().rotate().unwrap(), Rotation::R180);
+    }
+
+    /// Split the pages of a document into two intermediate nodes which both list the second page.
+    fn document_with_doubly_listed_page() -> crate::Document {
+        let mut doc = create_document_with_texts(&["first", "second", "third"]);
+        let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let pages: Vec<_> = doc.page_iter().collect();
+        let left = doc.add_object(dictionary! {
+            "Type" => "Pages",
+            "Parent" => pages_id,
+            "Kids" => vec![pages[0].into(), pages[1].into()],
+            "Count" => 2,
+        });
+        let right = doc.add_object(dictionary! {
+            "Type" => "Pages",
+            "Parent" => pages_id,
+            "Kids" => vec![pages[1].into(), pages[2].into()],
+            "Count" => 2,
+        });
+        let root = doc.get_dictionary_mut(pages_id).unwrap();
+        root.set("Kids", vec![left.into(), right.into()]);
+        root.set("Count", 4);
+        doc
+    }
+
+    #[test]
+    fn doubly_listed_page_is_numbered_once() {
+        let mut doc = document_with_doubly_listed_page();
+        let pages: Vec<_> = doc.page_iter().collect();
+        assert_eq!(pages.len(), 3);
+        assert_eq!(doc.get_pages().len(), 3);
+
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let loaded = crate::Document::load_mem(&buffer).unwrap();
+        assert_eq!(loaded.load_report.duplicate_pages, [pages[1]]);
+    }
+
+    #[test]
+    fn delete_doubly_listed_page() {
+        let mut doc = document_with_doubly_listed_page();
+        let pages: Vec<_> = doc.page_iter().collect();
+        doc.delete_pages(&[2]);
+        assert_eq!(doc.page_iter().collect::<Vec<_>>(), [pages[0], pages[2]]);
+        assert!(doc.duplicate_pages().is_empty());
+
+        let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let count = |id| doc.get_dictionary(id).unwrap().get(b"Count").unwrap().as_i64().unwrap();
+        let kids = doc
+            .get_dictionary(pages_id)
+            .unwrap()
+            .get(b"Kids")
+            .unwrap()
+            .as_array()
+            .unwrap();
+        let nodes: Vec<_> = kids.iter().map(|kid| kid.as_reference().unwrap()).collect();
+        assert_eq!([count(pages_id), count(nodes[0]), count(nodes[1])], [2, 1, 1]);
+        for id in doc.traverse_objects(|_| {}) {
+            assert!(
+                id != pages[1] && doc.objects.contains_key(&id),
+                "dangling reference to {id:?}"
+            );
+        }
+    }
+
+    /// Create a document with five pages, the last two under an intermediate node which rotates them.
+    fn document_with_nested_pages() -> (crate::Document, crate::ObjectId, crate::ObjectId) {
+        let mut doc = create_document_with_texts(&["1", "2", "3", "4", "5"]);
+        let pages: Vec<_> = doc.page_iter().collect();
+        let root_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let node_id = doc.add_object(dictionary! {
+            "Type" => "Pages",
+            "Parent" => root_id,
+            "Kids" => vec![pages[3].into(), pages[4].into()],
+            "Count" => 2,
+            "Rotate" => 90,
+        });
+        for page_id in &pages[3..] {
+            doc.get_dictionary_mut(*page_id).unwrap().set("Parent", node_id);
+        }
+        let root = doc.get_dictionary_mut(root_id).unwrap();
+        root.set(
+            "Kids",
+            vec![pages[0].into(), pages[1].into(), pages[2].into(), node_id.into()],
+        );
+        root.set("Count", 5);
+        (doc, root_id, node_id)
+    }
+
+    #[test]
+    fn move_page_between_nodes() {
+        let (mut doc, root_id, node_id) = document_with_nested_pages();
+        let pages: Vec<_> = doc.page_iter().collect();
+        let count =
+            |doc: &crate::Document, id| doc.get_dictionary(id).unwrap().get(b"Count").unwrap().as_i64().unwrap();
+        let parent = |doc: &crate::Document, id| {
+            doc.get_dictionary(id)
+                .unwrap()
+                .get(b"Parent")
+                .unwrap()
+                .as_reference()
+                .unwrap()
+        };
+
+        doc.move_page(5, 2).unwrap();
+        let order = [pages[0], pages[4], pages[1], pages[2], pages[3]];
+        assert_eq!(doc.page_iter().collect::<Vec<_>>(), order);
+        assert_eq!([count(&doc, root_id), count(&doc, node_id)], [5, 1]);
+        assert_eq!(parent(&doc, pages[4]), root_id);
+        // The rotation inherited from the former parent is kept.
+        let rotate = doc.get_dictionary(pages[4]).unwrap().get(b"Rotate").unwrap();
+        assert_eq!(rotate.as_i64().unwrap(), 90);
+
+        doc.move_page(1, 5).unwrap();
+        let order = [pages[4], pages[1], pages[2], pages[3], pages[0]];
+        assert_eq!(doc.page_iter().collect::<Vec<_>>(), order);
+        assert_eq!([count(&doc, root_id), count(&doc, node_id)], [5, 2]);
+        assert_eq!(parent(&doc, pages[0]), node_id);
+
+        doc.move_page(2, 3).unwrap();
+        let order = [pages[4], pages[2], pages[1], pages[3], pages[0]];
+        assert_eq!(doc.page_iter().collect::<Vec<_>>(), order);
+
+        doc.move_page(3, 3).unwrap();
+        assert_eq!(doc.page_iter().collect::<Vec<_>>(), order);
+        assert!(matches!(doc.move_page(6, 1), Err(Error::PageNumberNotFound(6))));
+        assert!(matches!(doc.move_page(1, 0), Err(Error::PageNumberNotFound(0))));
+    }
+
+    #[test]
+    fn swap_and_reverse_pages() {
+        let (mut doc, root_id, node_id) = document_with_nested_pages();
+        let pages: Vec<_> = doc.page_iter().collect();
+
+        doc.swap_pages(1, 4).unwrap();
+        let order = [pages[3], pages[1], pages[2], pages[0], pages[4]];
+        assert_eq!(doc.page_iter().collect::<Vec<_>>(), order);
+        let parent = doc.get_dictionary(pages[0]).unwrap().get(b"Parent").unwrap();
+        assert_eq!(parent.as_reference().unwrap(), node_id);
+        assert!(matches!(doc.swap_pages(1, 6), Err(Error::PageNumberNotFound(6))));
+
+        doc.swap_pages(1, 4).unwrap();
+        doc.reverse_pages().unwrap();
+        let mut reversed = pages.clone();
+        reversed.reverse();
+        assert_eq!(doc.page_iter().collect::<Vec<_>>(), reversed);
+        let count = doc.get_dictionary(root_id).unwrap().get(b"Count").unwrap();
+        assert_eq!(count.as_i64().unwrap(), 5);
+    }
+
+    #[test]
+    fn outline_follows_moved_page() {
+        let (mut doc, _, _) = document_with_nested_pages();
+        let page_id = doc.get_pages()[&4];
+        doc.add_bookmark(crate::Bookmark::new("Fourth".to_string(), [0.0; 3], 0, page_id), None);
+        let outline_id = doc.build_outline().unwrap();
+        doc.catalog_mut().unwrap().set("Outlines", outline_id);
+
+        doc.move_page(4, 1).unwrap();
+        assert_eq!(doc.get_pages()[&1], page_id);
+        let toc = doc.get_toc().unwrap();
+        assert_eq!(toc.toc[0].page, 1);
+    }
+
+    /// Check the `/Count` and `/Parent` entries below a page tree node, returning its page count and depth.
+    fn check_page_tree(doc: &crate::Document, node_id: crate::ObjectId) -> (i64, usize) {
+        let node = doc.get_dictionary(node_id).unwrap();
+        if node.get_type().unwrap() == b"Page" {
+            return (1, 0);
+        }
+        let (mut count, mut depth) = (0, 0);
+        for kid in node.get(b"Kids").unwrap().as_array().unwrap() {
+            let kid_id = kid.as_reference().unwrap();
+            let parent = doc.get_dictionary(kid_id).unwrap().get(b"Parent").unwrap();
+            assert_eq!(parent.as_reference().unwrap(), node_id);
+            let (kid_count, kid_depth) = check_page_tree(doc, kid_id);
+            count += kid_count;
+            depth = depth.max(kid_depth + 1);
+        }
+        assert_eq!(node.get(b"Count").unwrap().as_i64().unwrap(), count);
+        (count, depth)
+    }
+
+    #[test]
+    fn rebalance_large_page_tree() {
+        let texts: Vec<String> = (1..=1000).map(|number| number.to_string()).collect();
+        let mut doc = create_document_with_texts(&texts.iter().map(String::as_str).collect::<Vec<_>>());
+        let pages: Vec<_> = doc.page_iter().collect();
+        let root_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+
+        doc.rebalance_page_tree(10).unwrap();
+        assert_eq!(doc.page_iter().collect::<Vec<_>>(), pages);
+        assert_eq!(
+            doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap(),
+            root_id
+        );
+        assert_eq!(check_page_tree(&doc, root_id), (1000, 3));
+        let root = doc.get_dictionary(root_id).unwrap();
+        assert!(root.has(b"Resources") && root.has(b"MediaBox"));
+        assert_eq!(doc.extract_text(&[1000]).unwrap().trim(), "1000");
+    }
+
+    #[test]
+    fn rebalance_keeps_attributes_of_replaced_nodes() {
+        let (mut doc, root_id, node_id) = document_with_nested_pages();
+        let pages: Vec<_> = doc.page_iter().collect();
+
+        doc.rebalance_page_tree(2).unwrap();
+        assert_eq!(doc.page_iter().collect::<Vec<_>>(), pages);
+        assert_eq!(check_page_tree(&doc, root_id), (5, 3));
+        assert!(doc.get_object(node_id).is_err());
+        for (page_id, degrees) in pages.into_iter().zip([0, 0, 0, 90, 90]) {
+            let page = doc.page(page_id).unwrap();
+            assert_eq!(page.rotate().unwrap().degrees(), degrees);
+            assert!(page.resources().is_some());
+        }
+        let root = doc.get_dictionary(root_id).unwrap();
+        assert!(!root.has(b"Rotate"));
+
+        let mut buffer = vec![];
+        let options = crate::SaveOptions {
+            rebalance_page_tree_above: Some(4),
+            ..Default::default()
+        };
+        doc.save_with_options(&mut buffer, options).unwrap();
+        let root = doc.get_dictionary(root_id).unwrap();
+        assert_eq!(root.get(b"Kids").unwrap().as_array().unwrap().len(), 5);
+    }
+}
@@ -7,7 +7,9 @@
 use std::fs::File;
 #[cfg(not(feature = "async"))]
 use std::io::Read;
+use std::ops::Range;
 use std::path::Path;
+use std::str;
 use std::sync::Mutex;
 
 #[cfg(feature = "rayon")]
@@ -19,13 +21,19 @@
 #[cfg(feature = "async")]
 use tokio::pin;
 
+use crate::cancel::check_cancel;
 use crate::error::{ParseError, XrefError};
-use crate::object_stream::ObjectStream;
+use crate::logging::{recovered, span, Scope};
+use crate::object_stream::{ObjectStream, MAX_EXTENDS_DEPTH};
 use crate::parser::{self, ParserInput};
-use crate::xref::XrefEntry;
-use crate::{Document, Error, IncrementalDocument, Object, ObjectId, Result};
+use crate::xref::{Xref, XrefEntry, XrefType};
+use crate::{
+    CancelToken, Dictionary, Document, DuplicateKeyPolicy, Error, FileSpans, IncrementalDocument, LoadReport, Object,
+    ObjectId, Result, Stream, StreamEol,
+};
+use nom::Slice;
 
-type FilterFunc = fn((u32, u16), &mut Object) -> Option<((u32, u16), Object)>;
+type FilterFunc = fn(ObjectId, &mut Object) -> Option<(ObjectId, Object)>;
 
 #[cfg(not(feature = "async"))]
 impl Document {
@@ -106,16 +114,49 @@ pub fn load_mem(buffer: &[u8]) -> Result<Document> {
     }
 }
 
-impl TryInto<Document> for &[u8] {
-    type Error = Error;
-
-    fn try_into(self) -> Result<Document> {
+impl Document {
+    /// Load a PDF document from bytes in memory, such as a memory-mapped file.
+    ///
+    /// The bytes are parsed in place, the buffer isn't copied as a whole. The loaded document owns
+    /// its objects, so `buffer` can be dropped or unmapped afterwards.
+    ///
+    /// lopdf doesn't map files itself, map them with a crate like `memmap2` and pass the mapped bytes.
+    /// The file must not be modified or truncated by another process while loading. Depending on the
+    /// platform this changes the bytes under the parser or makes reading them crash the process.
+    pub fn load_from_slice(buffer: &[u8]) -> Result<Document> {
         Reader {
-            buffer: self,
+            buffer,
             document: Document::new(),
         }
         .read(None)
     }
+
+    /// Load a PDF document from bytes in memory like [`Document::load_mem`], stopping with
+    /// [`Error::Cancelled`] once `cancel` is cancelled, e.g. from another thread when a request times out.
+    pub fn load_mem_with_cancel(buffer: &[u8], cancel: &CancelToken) -> Result<Document> {
+        Reader {
+            buffer,
+            document: Document::new(),
+        }
+        .read_with_cancel(None, cancel)
+    }
+
+    /// Load a PDF document from bytes in memory like [`Document::load_mem`], with the given options.
+    pub fn load_mem_with_options(buffer: &[u8], options: &ParseOptions) -> Result<Document> {
+        Reader {
+            buffer,
+            document: Document::new(),
+        }
+        .read_with_options(None, options)
+    }
+}
+
+impl TryInto<Document> for &[u8] {
+    type Error = Error;
+
+    fn try_into(self) -> Result<Document> {
+        Document::load_from_slice(self)
+    }
 }
 
 #[cfg(not(feature = "async"))]
@@ -144,7 +185,7 @@ fn load_internal<R: Read>(mut source: R, capacity: Option<usize>) -> Result<Self
         }
         .read(None)?;
 
-        Ok(IncrementalDocument::create_from(buffer, document))
+        Ok(incremental_document_from(buffer, document))
     }
 
     /// Load a PDF document from a memory slice.
@@ -182,7 +223,7 @@ async fn load_internal<R: AsyncRead>(source: R, capacity: Option<usize>) -> Resu
         }
         .read(None)?;
 
-        Ok(IncrementalDocument::create_from(buffer, document))
+        Ok(incremental_document_from(buffer, document))
     }
 
     /// Load a PDF document from a memory slice.
@@ -191,6 +232,22 @@ pub fn load_mem(buffer: &[u8]) -> Result<Document> {
     }
 }
 
+impl IncrementalDocument {
+    /// Load a PDF document from a memory slice, with the given options.
+    ///
+    /// With [`ParseOptions::lenient`], an incomplete update at the end of the file is left out of the
+    /// previous revisions, so that a new update is appended to the complete ones.
+    pub fn load_mem_with_options(buffer: &[u8], options: &ParseOptions) -> Result<IncrementalDocument> {
+        let document = Reader {
+            buffer,
+            document: Document::new(),
+        }
+        .read_with_options(None, options)?;
+
+        Ok(incremental_document_from(buffer.to_vec(), document))
+    }
+}
+
 impl TryInto<IncrementalDocument> for &[u8] {
     type Error = Error;
 
@@ -201,21 +258,63 @@ fn try_into(self) -> Result<IncrementalDocument> {
         }
         .read(None)?;
 
-        Ok(IncrementalDocument::create_from(self.to_vec(), document))
+        Ok(incremental_document_from(self.to_vec(), document))
     }
 }
 
+/// Keep only the bytes of the revisions that were loaded, so that new updates are appended to them.
+fn incremental_document_from(mut buffer: Vec<u8>, document: Document) -> IncrementalDocument {
+    buffer.truncate(buffer.len() - document.load_report.ignored_bytes);
+    IncrementalDocument::create_from(buffer, document)
+}
+
 pub struct Reader<'a> {
     pub buffer: &'a [u8],
     pub document: Document,
 }
 
+/// Options of [`Reader::read_with_options`] and [`Document::load_mem_with_options`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Which value a dictionary keeps for a key given more than once. Whatever the policy, the
+    /// repeated keys are listed in [`LoadReport::duplicate_keys`].
+    pub duplicate_keys: DuplicateKeyPolicy,
+    /// Accept a stream `/Length` given as a Real, which is truncated, or as a string holding a
+    /// number, as other readers do. The streams are listed in [`LoadReport::coerced_lengths`].
+    /// Otherwise the data of such a stream can't be read.
+    ///
+    /// Also discard an incomplete incremental update at the end of the file and read the revisions
+    /// before it, counted in [`LoadReport::discarded_revisions`].
+    pub lenient: bool,
+    /// Record where the objects, cross-reference sections and trailers are in the file, see
+    /// [`Document::file_spans`]. Off by default, as it takes memory for every object.
+    pub record_spans: bool,
+}
+
 /// Maximum allowed embedding of literal strings.
 pub const MAX_BRACKET: usize = 100;
 
 impl Reader<'_> {
     /// Read whole document.
-    pub fn read(mut self, filter_func: Option<FilterFunc>) -> Result<Document> {
+    pub fn read(self, filter_func: Option<FilterFunc>) -> Result<Document> {
+        self.read_internal(filter_func, None, &ParseOptions::default())
+    }
+
+    /// Read the document like [`Reader::read`], stopping with [`Error::Cancelled`] once `cancel` is
+    /// cancelled. The token is checked before reading each object.
+    pub fn read_with_cancel(self, filter_func: Option<FilterFunc>, cancel: &CancelToken) -> Result<Document> {
+        self.read_internal(filter_func, Some(cancel), &ParseOptions::default())
+    }
+
+    /// Read the document like [`Reader::read`], with the given options.
+    pub fn read_with_options(self, filter_func: Option<FilterFunc>, options: &ParseOptions) -> Result<Document> {
+        self.read_internal(filter_func, None, options)
+    }
+
+    fn read_internal(
+        mut self, filter_func: Option<FilterFunc>, cancel: Option<&CancelToken>, options: &ParseOptions,
+    ) -> Result<Document> {
+        let _span = span!(DEBUG, "load", bytes = self.buffer.len());
         let offset = self.buffer.windows(5).position(|w| w == b"%PDF-").unwrap_or(0);
         self.buffer = &self.buffer[offset..];
 
@@ -224,48 +323,55 @@ pub fn read(mut self, filter_func: Option<FilterFunc>) -> Result<Document> {
         let version =
             parser::header(ParserInput::new_extra(self.buffer, "header")).ok_or(ParseError::InvalidFileHeader)?;
 
-        let xref_start = Self::get_xref_start(self.buffer)?;
-        if xref_start > self.buffer.len() {
-            return Err(Error::Xref(XrefError::Start));
-        }
-        self.document.xref_start = xref_start;
-
-        let (mut xref, mut trailer) =
-            parser::xref_and_trailer(ParserInput::new_extra(&self.buffer[xref_start..], "xref"), &self)?;
-
-        // Read previous Xrefs of linearized or incremental updated document.
-        let mut already_seen = HashSet::new();
-        let mut prev_xref_start = trailer.remove(b"Prev");
-        while let Some(prev) = prev_xref_start.and_then(|offset| offset.as_i64().ok()) {
-            if already_seen.contains(&prev) {
-                break;
-            }
-            already_seen.insert(prev);
-            if prev < 0 || prev as usize > self.buffer.len() {
-                return Err(Error::Xref(XrefError::PrevStart));
-            }
-
-            let (prev_xref, prev_trailer) =
-                parser::xref_and_trailer(ParserInput::new_extra(&self.buffer[prev as usize..], ""), &self)?;
-            xref.merge(prev_xref);
-
-            // Read xref stream in hybrid-reference file
-            let prev_xref_stream_start = trailer.remove(b"XRefStm");
-            if let Some(prev) = prev_xref_stream_start.and_then(|offset| offset.as_i64().ok()) {
-                if prev < 0 || prev as usize > self.buffer.len() {
-                    return Err(Error::Xref(XrefError::StreamStart));
+        // A file cut off in the middle of an incremental update lacks a readable final
+        // cross-reference section, but the revisions before it may still be complete. With
+        // `lenient`, the incomplete update is discarded. The updates of a complete file are kept
+        // by reconstructing the table instead.
+        let full_buffer = self.buffer;
+        let full_length = self.buffer.len();
+        // The offsets of the cross-reference sections read.
+        let mut sections = vec![];
+        let (mut xref, trailer) = loop {
+            sections.clear();
+            match self.read_xref_and_trailer(&mut sections) {
+                Ok(result) => break result,
+                Err(err) => {
+                    let previous_revision_end = Self::previous_revision_end(self.buffer)
+                        .filter(|_| options.lenient && !Self::ends_with_eof(self.buffer));
+                    match previous_revision_end {
+                        Some(end) => {
+                            recovered!(offset = end, bytes = full_length - end;
+                                "Discarding incomplete update at the end of the file ({} bytes): {}",
+                                full_length - end,
+                                err
+                            );
+                            self.buffer = &self.buffer[..end];
+                            self.document.load_report.discarded_revisions += 1;
+                            self.document.load_report.ignored_bytes = full_length - end;
+                        }
+                        // As a last resort, the objects are located by scanning the whole file.
+                        None => match Self::reconstruct_xref(full_buffer) {
+                            Some((xref, trailer, revisions)) => {
+                                recovered!(bytes = full_buffer.len();
+                                    "Reconstructing the cross-reference table of a damaged file: {}", err);
+                                self.buffer = full_buffer;
+                                self.document.load_report = LoadReport {
+                                    reconstructed_xref: true,
+                                    recovered_revisions: revisions,
+                                    ..LoadReport::default()
+                                };
+                                sections.clear();
+                                break (xref, trailer);
+                            }
+                            None => return Err(err),
+                        },
+                    }
                 }
-
-                let (prev_xref, _) =
-                    parser::xref_and_trailer(ParserInput::new_extra(&self.buffer[prev as usize..], ""), &self)?;
-                xref.merge(prev_xref);
             }
-
-            prev_xref_start = prev_trailer.get(b"Prev").cloned().ok();
-        }
+        };
         let xref_entry_count = xref.max_id().checked_add(1).ok_or(ParseError::InvalidXref)?;
         if xref.size != xref_entry_count {
-            warn!(
+            recovered!(size = xref.size;
                 "Size entry of trailer dictionary is {}, correct value is {}.",
                 xref.size, xref_entry_count
             );
@@ -279,32 +385,73 @@ pub fn read(mut self, filter_func: Option<FilterFunc>) -> Result<Document> {
 
         let zero_length_streams = Mutex::new(vec![]);
         let object_streams = Mutex::new(vec![]);
-
-        let entries_filter_map = |(_, entry): (&_, &_)| {
-            if let XrefEntry::Normal { offset, .. } = *entry {
-                let (object_id, mut object) = self
+        let unreadable_objects = Mutex::new(vec![]);
+        let skipped_bytes = Mutex::new(vec![]);
+        let stream_extents = Mutex::new(vec![]);
+        let duplicate_keys = Mutex::new(vec![]);
+        let coerced_lengths = Mutex::new(vec![]);
+        let failed_object_streams = Mutex::new(vec![]);
+        let object_spans = Mutex::new(vec![]);
+
+        let read_entry = |(&id, entry): (&u32, &XrefEntry)| {
+            check_cancel(cancel).ok()?;
+            if let XrefEntry::Normal { offset, generation } = *entry {
+                let _span = span!(TRACE, "object", id = ObjectId(id, generation), offset = offset);
+                let (object_id, mut object, skipped, span) = self
                     .read_object(offset as usize, None, &mut HashSet::new())
-                    .map_err(|e| error!("Object load error: {:?}", e))
+                    .map_err(|err| {
+                        error!("Object load error: {:?}", err);
+                        unreadable_objects.lock().unwrap().push(ObjectId(id, generation));
+                    })
                     .ok()?;
+                if let Some(skipped) = skipped {
+                    recovered!(id = object_id, offset = offset, bytes = skipped.len();
+                        "Skipped {} bytes of garbage after object {} {}",
+                        skipped.len(),
+                        object_id.0,
+                        object_id.1
+                    );
+                    skipped_bytes.lock().unwrap().push((object_id, skipped));
+                }
+                if options.record_spans {
+                    object_spans.lock().unwrap().push((object_id, span));
+                }
+                if let Object::Stream(Stream {
+                    start_position: Some(start),
+                    content,
+                    ..
+                }) = &object
+                {
+                    if !content.is_empty() {
+                        stream_extents
+                            .lock()
+                            .unwrap()
+                            .push((object_id, *start..start + content.len()));
+                    }
+                }
                 if let Some(filter_func) = filter_func {
                     filter_func(object_id, &mut object)?;
                 }
                 if let Ok(ref mut stream) = object.as_stream_mut() {
                     if stream.dict.has_type(b"ObjStm") {
-                        let obj_stream = ObjectStream::new(stream).ok()?;
-                        let mut object_streams = object_streams.lock().unwrap();
-                        // TODO: Is insert and replace intended behavior?
-                        // See https://github.com/J-F-Liu/lopdf/issues/160 for more info
-                        if let Some(filter_func) = filter_func {
-                            let objects: BTreeMap<(u32, u16), Object> = obj_stream
+                        let obj_stream = ObjectStream::with_id(stream, object_id)
+                            .map_err(|err| {
+                                recovered!(id = object_id; "Object stream load error: {}", err);
+                                failed_object_streams.lock().unwrap().push((object_id, err.to_string()));
+                            })
+                            .ok()?;
+                        let objects = match filter_func {
+                            Some(filter_func) => obj_stream
                                 .objects
                                 .into_iter()
                                 .filter_map(|(object_id, mut object)| filter_func(object_id, &mut object))
-                                .collect();
-                            object_streams.extend(objects);
-                        } else {
-                            object_streams.extend(obj_stream.objects);
-                        }
+                                .collect(),
+                            None => obj_stream.objects,
+                        };
+                        object_streams
+                            .lock()
+                            .unwrap()
+                            .push((object_id, obj_stream.extends, objects));
                     } else if stream.content.is_empty() {
                         let mut zero_length_streams = zero_length_streams.lock().unwrap();
                         zero_length_streams.push(object_id);
@@ -315,6 +462,34 @@ pub fn read(mut self, filter_func: Option<FilterFunc>) -> Result<Document> {
                 None
             }
         };
+        // Objects read in parallel are still reported as part of the load.
+        let scope = Scope::current();
+        // Keys repeated in the objects of an object stream are reported for the stream.
+        let entries_filter_map = |entry| {
+            let ((object, keys), coerced) = scope.in_scope(|| {
+                parser::with_lenient_lengths(options.lenient, || {
+                    parser::with_duplicate_keys(options.duplicate_keys, || read_entry(entry))
+                })
+            });
+            if let (Some((object_id, _)), true) = (&object, coerced) {
+                coerced_lengths.lock().unwrap().push(*object_id);
+            }
+            if let (Some((object_id, _)), false) = (&object, keys.is_empty()) {
+                for key in &keys {
+                    recovered!(id = object_id;
+                        "Object {} {} has key /{} more than once",
+                        object_id.0,
+                        object_id.1,
+                        String::from_utf8_lossy(key)
+                    );
+                }
+                duplicate_keys
+                    .lock()
+                    .unwrap()
+                    .extend(keys.into_iter().map(|key| (*object_id, key)));
+            }
+            object
+        };
         #[cfg(feature = "rayon")]
         {
             self.document.objects = self
@@ -335,20 +510,129 @@ pub fn read(mut self, filter_func: Option<FilterFunc>) -> Result<Document> {
                 .filter_map(entries_filter_map)
                 .collect();
         }
-        // Only add entries, but never replace entries
-        for (id, entry) in object_streams.into_inner().unwrap() {
-            self.document.objects.entry(id).or_insert(entry);
+        check_cancel(cancel)?;
+        self.merge_object_streams(object_streams.into_inner().unwrap());
+
+        let mut object_spans: BTreeMap<ObjectId, Range<usize>> =
+            object_spans.into_inner().unwrap().into_iter().collect();
+        let mut stream_extents = stream_extents.into_inner().unwrap();
+        let mut invalid_stream_extents = vec![];
+        let mut coerced_lengths = coerced_lengths.into_inner().unwrap();
+        for object_id in zero_length_streams.into_inner().unwrap() {
+            match self.read_stream_content(object_id, options.lenient, &mut coerced_lengths) {
+                Ok(extent) => {
+                    // The object was taken to end at the start of its data.
+                    if let (Some(span), Some(end)) = (
+                        object_spans.get_mut(&object_id),
+                        parser::stream_object_end(ParserInput::new_extra(self.buffer, "").slice(extent.end..)),
+                    ) {
+                        span.end = end;
+                    }
+                    stream_extents.push((object_id, extent));
+                }
+                Err(err @ Error::StreamExtent { .. }) => {
+                    recovered!(id = object_id; "Stream {} {}: {}", object_id.0, object_id.1, err);
+                    invalid_stream_extents.push(object_id);
+                }
+                Err(_) => {}
+            }
         }
+        invalid_stream_extents.extend(Self::nested_streams(stream_extents));
+        invalid_stream_extents.sort_unstable();
+        self.document.load_report.invalid_stream_extents = invalid_stream_extents;
+        coerced_lengths.sort_unstable();
+        coerced_lengths.dedup();
+        for object_id in &coerced_lengths {
+            recovered!(id = object_id;
+                "Stream {} {} has a /Length which isn't an integer, coerced to one",
+                object_id.0, object_id.1
+            );
+        }
+        self.document.load_report.coerced_lengths = coerced_lengths;
+
+        let mut duplicate_keys = duplicate_keys.into_inner().unwrap();
+        duplicate_keys.sort_by_key(|(id, _)| *id);
+        if let (DuplicateKeyPolicy::Error, Some((id, key))) = (options.duplicate_keys, duplicate_keys.first()) {
+            return Err(Error::DuplicateKey {
+                id: *id,
+                key: String::from_utf8_lossy(key).into_owned(),
+            });
+        }
+        self.document.load_report.duplicate_keys = duplicate_keys;
 
-        for object_id in zero_length_streams.into_inner().unwrap() {
-            let _ = self.read_stream_content(object_id);
+        let mut skipped_bytes = skipped_bytes.into_inner().unwrap();
+        skipped_bytes.sort_unstable_by_key(|(id, _)| *id);
+        self.document.load_report.skipped_bytes = skipped_bytes;
+
+        let mut unreadable_objects = unreadable_objects.into_inner().unwrap();
+        unreadable_objects.sort_unstable();
+        self.document.load_report.unreadable_objects = unreadable_objects;
+        if self.document.load_report.reconstructed_xref && !self.document.trailer.has(b"Root") {
+            self.recover_trailer();
+        }
+        if let Some((catalog, container, cause)) = self.unreadable_catalog(failed_object_streams.into_inner().unwrap())
+        {
+            if !options.lenient {
+                return Err(Error::MissingCatalog {
+                    catalog,
+                    container,
+                    cause,
+                });
+            }
+            error!(
+                "Catalog {} is in object stream {}, which couldn't be read: {}",
+                catalog, container, cause
+            );
+            self.document.load_report.unreadable_catalog = Some((container, cause));
         }
 
+        if options.record_spans {
+            self.record_file_spans(object_spans, &sections, offset);
+        }
+
+        self.document.load_report.duplicate_pages = self.document.duplicate_pages();
+        self.document.load_report.invalid_rotations = self.document.normalize_rotations();
+        self.document.clear_dirty();
         Ok(self.document)
     }
 
-    fn read_stream_content(&mut self, object_id: ObjectId) -> Result<()> {
-        let length = self.get_stream_length(object_id)?;
+    /// Set the [`FileSpans`] of the document, from the ranges of its objects and the offsets of its
+    /// cross-reference sections in the buffer, which starts `base` bytes into the file.
+    fn record_file_spans(&mut self, objects: BTreeMap<ObjectId, Range<usize>>, sections: &[usize], base: usize) {
+        let shift = |range: Range<usize>| range.start + base..range.end + base;
+        let mut spans = FileSpans {
+            objects: objects.into_iter().map(|(id, range)| (id, shift(range))).collect(),
+            ..FileSpans::default()
+        };
+        for &start in sections {
+            let input = ParserInput::new_extra(self.buffer, "xref").slice(start..);
+            if let Some((section, trailer)) = parser::xref_section_spans(input, self) {
+                spans.xref_sections.push(shift(section));
+                spans.trailers.extend(trailer.map(shift));
+            }
+        }
+        for (&id, entry) in &self.document.reference_table.entries {
+            if let XrefEntry::Compressed { container, index } = *entry {
+                if self.document.objects.contains_key(&ObjectId(id, 0)) {
+                    spans
+                        .compressed
+                        .insert(ObjectId(id, 0), (ObjectId(container, 0), index));
+                }
+            }
+        }
+        self.document.file_spans = spans;
+    }
+
+    /// Read the data of a stream whose `/Length` couldn't be resolved while parsing it, returning its extent.
+    /// With `lenient`, a length that had to be coerced to an integer adds the stream to `coerced_lengths`.
+    fn read_stream_content(
+        &mut self, object_id: ObjectId, lenient: bool, coerced_lengths: &mut Vec<ObjectId>,
+    ) -> Result<Range<usize>> {
+        let (length, coerced) = parser::with_lenient_lengths(lenient, || self.get_stream_length(object_id));
+        let length = length?;
+        if coerced {
+            coerced_lengths.push(object_id);
+        }
         let stream = self
             .document
             .get_object_mut(object_id)
@@ -362,14 +646,41 @@ fn read_stream_content(&mut self, object_id: ObjectId) -> Result<()> {
         }
 
         let length = usize::try_from(length).map_err(|e| Error::NumericCast(e.to_string()))?;
-        let end = start + length;
+        let end = start
+            .checked_add(length)
+            .filter(|&end| end <= self.buffer.len())
+            .ok_or(Error::StreamExtent { start, length })?;
+
+        let rest = &self.buffer[end..];
+        let before_endstream = [b"\r\n".as_slice(), b"\n", b"\r"]
+            .into_iter()
+            .find(|eol| rest.starts_with(eol))
+            .unwrap_or_default();
+        let original_eol = stream.original_eol.take().map(|eol| StreamEol {
+            before_endstream: before_endstream.to_vec(),
+            ..eol
+        });
+        stream.set_content(self.buffer[start..end].to_vec());
+        stream.original_eol = original_eol;
+        Ok(start..end)
+    }
 
-        if end > self.buffer.len() {
-            return Err(Error::InvalidStream("stream extends after document end.".to_string()));
+    /// Streams whose data starts within the data of another stream.
+    fn nested_streams(mut extents: Vec<(ObjectId, Range<usize>)>) -> Vec<ObjectId> {
+        extents.sort_unstable_by_key(|(_, extent)| (extent.start, cmp::Reverse(extent.end)));
+        let mut nested = vec![];
+        let mut end = 0;
+        for (object_id, extent) in extents {
+            if extent.start < end {
+                recovered!(id = object_id, offset = extent.start, bytes = extent.len();
+                    "Data of stream {} {} lies within the data of another stream",
+                    object_id.0, object_id.1
+                );
+                nested.push(object_id);
+            }
+            end = end.max(extent.end);
         }
-
-        stream.set_content(self.buffer[start..end].to_vec());
-        Ok(())
+        nested
     }
 
     fn get_stream_length(&self, object_id: ObjectId) -> Result<i64> {
@@ -379,7 +690,7 @@ fn get_stream_length(&self, object_id: ObjectId) -> Result<i64> {
             .dict
             .get(b"Length")
             .and_then(|value| self.document.dereference(value))
-            .and_then(|(_id, obj)| obj.as_i64())
+            .and_then(|(_id, obj)| parser::stream_length(obj))
             .map_err(|err| {
                 error!(
                     "stream dictionary of '{} {} R' is missing the Length entry",
@@ -390,7 +701,7 @@ fn get_stream_length(&self, object_id: ObjectId) -> Result<i64> {
     }
 
     /// Get object offset by object ID.
-    fn get_offset(&self, id: ObjectId) -> Result<u32> {
+    fn get_offset(&self, id: ObjectId) -> Result<u64> {
         let entry = self.document.reference_table.get(id.0).ok_or(Error::MissingXrefEntry)?;
         match *entry {
             XrefEntry::Normal { offset, generation } if generation == id.1 => Ok(offset),
@@ -398,21 +709,23 @@ fn get_offset(&self, id: ObjectId) -> Result<u32> {
         }
     }
 
-    pub fn get_object(&self, id: ObjectId, already_seen: &mut HashSet<ObjectId>) -> Result<Object> {
+    pub fn get_object(&self, id: impl Into<ObjectId>, already_seen: &mut HashSet<ObjectId>) -> Result<Object> {
+        let id = id.into();
         if already_seen.contains(&id) {
             warn!("reference cycle detected resolving object {} {}", id.0, id.1);
             return Err(Error::ReferenceCycle(id));
         }
         already_seen.insert(id);
         let offset = self.get_offset(id)?;
-        let (_, obj) = self.read_object(offset as usize, Some(id), already_seen)?;
+        let _span = span!(TRACE, "object", id = id, offset = offset);
+        let (_, obj, _, _) = self.read_object(offset as usize, Some(id), already_seen)?;
 
         Ok(obj)
     }
 
     fn read_object(
         &self, offset: usize, expected_id: Option<ObjectId>, already_seen: &mut HashSet<ObjectId>,
-    ) -> Result<(ObjectId, Object)> {
+    ) -> Result<parser::IndirectObject> {
         if offset > self.buffer.len() {
             return Err(Error::InvalidOffset(offset));
         }
@@ -426,9 +739,333 @@ fn read_object(
         )
     }
 
+    /// Read the cross-reference sections of the last revision in the buffer and of all revisions it updates,
+    /// adding their offsets to `sections`.
+    fn read_xref_and_trailer(&mut self, sections: &mut Vec<usize>) -> Result<(Xref, Dictionary)> {
+        let xref_start = Self::get_xref_start(self.buffer)?;
+        if xref_start > self.buffer.len() {
+            return Err(Error::Xref(XrefError::Start));
+        }
+        let _span = span!(DEBUG, "xref", offset = xref_start);
+
+        let (mut xref, mut trailer) =
+            parser::xref_and_trailer(ParserInput::new_extra(self.buffer, "xref").slice(xref_start..), self)?;
+        sections.push(xref_start);
+        self.read_hybrid_xref_stream(&mut xref, &mut trailer, sections)?;
+
+        // Read previous Xrefs of linearized or incremental updated document.
+        let mut already_seen = HashSet::new();
+        let mut prev_xref_start = trailer.remove(b"Prev");
+        while let Some(prev) = prev_xref_start.and_then(|offset| offset.as_i64().ok()) {
+            if already_seen.contains(&prev) {
+                break;
+            }
+            already_seen.insert(prev);
+            if prev < 0 || prev as usize > self.buffer.len() {
+                return Err(Error::Xref(XrefError::PrevStart));
+            }
+
+            let (mut prev_xref, mut prev_trailer) =
+                parser::xref_and_trailer(ParserInput::new_extra(self.buffer, "").slice(prev as usize..), self)?;
+            sections.push(prev as usize);
+            self.read_hybrid_xref_stream(&mut prev_xref, &mut prev_trailer, sections)?;
+            xref.merge(prev_xref);
+
+            prev_xref_start = prev_trailer.get(b"Prev").cloned().ok();
+        }
+        self.document.xref_start = xref_start;
+        self.document.load_report.xref_sections = already_seen.len() + 1;
+        Ok((xref, trailer))
+    }
+
+    /// Add the entries of the cross-reference stream `/XRefStm` of a hybrid-reference file to the table `xref`
+    /// read with `trailer`, removing the entry from the trailer.
+    ///
+    /// The stream belongs to the same revision as the table: it adds the objects the table doesn't have or
+    /// lists as free, like those of object streams, which readers without support for streams don't see.
+    fn read_hybrid_xref_stream(
+        &self, xref: &mut Xref, trailer: &mut Dictionary, sections: &mut Vec<usize>,
+    ) -> Result<()> {
+        let Some(start) = trailer.remove(b"XRefStm").and_then(|offset| offset.as_i64().ok()) else {
+            return Ok(());
+        };
+        if start < 0 || start as usize > self.buffer.len() {
+            return Err(Error::Xref(XrefError::StreamStart));
+        }
+        let (stream_xref, _) =
+            parser::xref_and_trailer(ParserInput::new_extra(self.buffer, "").slice(start as usize..), self)?;
+        sections.push(start as usize);
+        for (id, entry) in stream_xref.entries {
+            if !matches!(
+                xref.get(id),
+                Some(XrefEntry::Normal { .. } | XrefEntry::Compressed { .. })
+            ) {
+                xref.insert(id, entry);
+            }
+        }
+        xref.size = xref.size.max(stream_xref.size);
+        Ok(())
+    }
+
+    /// Rebuild the cross-reference table by scanning `buffer` for the `N G obj` headers of indirect objects,
+    /// with the last `trailer` dictionary, for files whose cross-reference sections can't be read.
+    ///
+    /// Later definitions of an object replace earlier ones, as in incremental updates.
+    /// Returns `None` if no object is found, otherwise also the revisions found, see [`Reader::revision_ranges`].
+    fn reconstruct_xref(buffer: &[u8]) -> Option<(Xref, Dictionary, Vec<Range<usize>>)> {
+        let mut xref = Xref::new(0, XrefType::CrossReferenceTable);
+        // Revisions follow each other in the file, so the headers in file order give the definitions of an
+        // object by later revisions after those of earlier ones, which they replace.
+        for (offset, ObjectId(id, generation)) in Self::scan_object_headers(buffer) {
+            xref.insert(
+                id,
+                XrefEntry::Normal {
+                    offset: offset as u64,
+                    generation,
+                },
+            );
+        }
+        if xref.entries.is_empty() {
+            return None;
+        }
+        xref.size = xref.max_id() + 1;
+
+        let mut trailer = buffer
+            .windows(7)
+            .enumerate()
+            .rev()
+            .filter(|(_, window)| window == b"trailer")
+            .find_map(|(start, _)| parser::trailer_dictionary(ParserInput::new_extra(&buffer[start..], "trailer")))
+            .unwrap_or_default();
+        for key in [b"Prev".as_slice(), b"XRefStm"] {
+            trailer.remove(key);
+        }
+        trailer.set("Size", i64::from(xref.size));
+        Some((xref, trailer, Self::revision_ranges(buffer)))
+    }
+
+    /// Split a file into its revisions, oldest first, each ending with its end of file marker, see
+    /// [`Reader::revision_ends`]. Data after the last marker makes a last, incomplete, revision.
+    fn revision_ranges(buffer: &[u8]) -> Vec<Range<usize>> {
+        let mut revisions = vec![];
+        let mut start = 0;
+        for end in Self::revision_ends(buffer) {
+            revisions.push(start..end);
+            start = end;
+        }
+        if buffer[start..].iter().any(|c| !c.is_ascii_whitespace()) {
+            revisions.push(start..buffer.len());
+        } else if let Some(last) = revisions.last_mut() {
+            last.end = buffer.len();
+        }
+        revisions
+    }
+
+    /// The ends of the revisions of a file, after each `%%EOF` marker and its end of line.
+    ///
+    /// A revision ends with `startxref`, the offset of its cross-reference section and `%%EOF`, each on a
+    /// line of its own. Other `%%EOF`, like those in the data of streams, are skipped.
+    fn revision_ends(buffer: &[u8]) -> impl Iterator<Item = usize> + '_ {
+        /// `bytes` without the white-space it ends with, which must include a line break.
+        fn strip_line_break(bytes: &[u8]) -> Option<&[u8]> {
+            let end = bytes
+                .iter()
+                .rposition(|c| !c.is_ascii_whitespace())
+                .map_or(0, |i| i + 1);
+            bytes[end..]
+                .iter()
+                .any(|&c| c == b'\n' || c == b'\r')
+                .then_some(&bytes[..end])
+        }
+        let ends_revision = |marker: usize| {
+            let Some(before) = strip_line_break(&buffer[..marker]) else {
+                return false;
+            };
+            let digits = before.iter().rev().take_while(|c| c.is_ascii_digit()).count();
+            digits > 0
+                && strip_line_break(&before[..before.len() - digits])
+                    .is_some_and(|before| before.ends_with(b"startxref"))
+        };
+        buffer
+            .windows(5)
+            .enumerate()
+            .filter(move |&(marker, window)| window == b"%%EOF" && ends_revision(marker))
+            .map(move |(marker, _)| {
+                let eof = marker + 5;
+                let eol = [b"\r\n".as_slice(), b"\n", b"\r"]
+                    .into_iter()
+                    .find(|eol| buffer[eof..].starts_with(eol))
+                    .map_or(0, <[u8]>::len);
+                eof + eol
+            })
+    }
+
+    /// Check whether the last revision of a file is complete, ending with a `%%EOF` marker.
+    fn ends_with_eof(buffer: &[u8]) -> bool {
+        let content_end = buffer
+            .iter()
+            .rposition(|c| !c.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        buffer[..content_end].ends_with(b"%%EOF")
+    }
+
+    /// Find the headers `N G obj` of indirect objects, returning their offsets and object IDs.
+    fn scan_object_headers(buffer: &[u8]) -> Vec<(usize, ObjectId)> {
+        fn digits_before(buffer: &[u8], end: usize) -> Option<usize> {
+            let start = buffer[..end]
+                .iter()
+                .rposition(|c| !c.is_ascii_digit())
+                .map_or(0, |i| i + 1);
+            (start < end).then_some(start)
+        }
+        fn spaces_before(buffer: &[u8], end: usize) -> Option<usize> {
+            let start = buffer[..end]
+                .iter()
+                .rposition(|c| !c.is_ascii_whitespace())
+                .map_or(0, |i| i + 1);
+            (start < end).then_some(start)
+        }
+
+        let mut headers = vec![];
+        for (keyword, _) in buffer.windows(3).enumerate().filter(|(_, window)| window == b"obj") {
+            let header = (|| {
+                if buffer.get(keyword + 3).is_some_and(|c| c.is_ascii_alphanumeric()) {
+                    return None;
+                }
+                let generation_end = spaces_before(buffer, keyword)?;
+                let generation_start = digits_before(buffer, generation_end)?;
+                let id_end = spaces_before(buffer, generation_start)?;
+                let id_start = digits_before(buffer, id_end)?;
+                if id_start > 0 && !buffer[id_start - 1].is_ascii_whitespace() {
+                    return None;
+                }
+                let id = str::from_utf8(&buffer[id_start..id_end]).ok()?.parse().ok()?;
+                let generation = str::from_utf8(&buffer[generation_start..generation_end])
+                    .ok()?
+                    .parse()
+                    .ok()?;
+                Some((id_start, ObjectId(id, generation)))
+            })();
+            headers.extend(header);
+        }
+        headers
+    }
+
+    /// Add the objects of the object streams which aren't given as objects of their own.
+    ///
+    /// An object given in several streams is taken from the one its cross-reference entry names,
+    /// or else from the nearest stream of an `/Extends` chain, the one extending the others.
+    fn merge_object_streams(&mut self, mut streams: Vec<(ObjectId, Option<ObjectId>, BTreeMap<ObjectId, Object>)>) {
+        let extends: BTreeMap<ObjectId, ObjectId> = streams
+            .iter()
+            .filter_map(|&(id, extends, _)| Some((id, extends?)))
+            .collect();
+        let mut chain_lengths = BTreeMap::new();
+        let mut invalid_chains = vec![];
+        for &(id, ..) in &streams {
+            let mut chain = vec![id];
+            while let Some(&next) = extends.get(&chain[chain.len() - 1]) {
+                if chain.contains(&next) || chain.len() >= MAX_EXTENDS_DEPTH {
+                    recovered!(id = id; "The /Extends chain of object stream {} is cyclic or too long", id);
+                    invalid_chains.push(id);
+                    break;
+                }
+                chain.push(next);
+            }
+            chain_lengths.insert(id, chain.len());
+        }
+        self.document.load_report.invalid_object_stream_chains = invalid_chains;
+
+        // Streams extending others come before them, which is the order their objects are taken in.
+        streams.sort_by_key(|(id, ..)| (cmp::Reverse(chain_lengths[id]), *id));
+        let document = &mut self.document;
+        for (container, _, objects) in &mut streams {
+            objects.retain(|id, object| {
+                let named = matches!(
+                    document.reference_table.get(id.0),
+                    Some(&XrefEntry::Compressed { container: named, .. }) if named == container.0
+                );
+                if named {
+                    document
+                        .objects
+                        .entry(*id)
+                        .or_insert_with(|| std::mem::replace(object, Object::Null));
+                }
+                !named
+            });
+        }
+        // Only add entries, but never replace entries
+        for (_, _, objects) in streams {
+            for (id, object) in objects {
+                document.objects.entry(id).or_insert(object);
+            }
+        }
+    }
+
+    /// Find the catalog which is missing because the object stream holding it couldn't be read,
+    /// with the stream and the error which made it unreadable.
+    fn unreadable_catalog(
+        &self, failed_object_streams: Vec<(ObjectId, String)>,
+    ) -> Option<(ObjectId, ObjectId, String)> {
+        let catalog = self.document.trailer.get(b"Root").and_then(Object::as_reference).ok()?;
+        if self.document.objects.contains_key(&catalog) {
+            return None;
+        }
+        let XrefEntry::Compressed { container, .. } = *self.document.reference_table.get(catalog.0)? else {
+            return None;
+        };
+        let (container, mut cause) = match failed_object_streams.into_iter().find(|(id, _)| id.0 == container) {
+            Some(failed) => failed,
+            None => {
+                let unreadable = self.document.load_report.unreadable_objects.iter();
+                let id = unreadable.copied().find(|id| id.0 == container)?;
+                (id, "the stream couldn't be parsed".to_string())
+            }
+        };
+        if self.document.trailer.has(b"Encrypt") {
+            cause.push_str(" (the document is encrypted, object streams can't be decrypted while loading)");
+        }
+        Some((catalog, container, cause))
+    }
+
+    /// Complete the trailer of a reconstructed file, taking its entries from a cross-reference stream
+    /// or looking for the document catalog.
+    fn recover_trailer(&mut self) {
+        let xref_stream = self.document.objects.values().rev().find_map(|object| {
+            let stream = object.as_stream().ok()?;
+            (stream.dict.has_type(b"XRef") && stream.dict.has(b"Root")).then_some(&stream.dict)
+        });
+        if let Some(dict) = xref_stream {
+            for key in [b"Root".as_slice(), b"Info", b"Encrypt", b"ID"] {
+                if let Ok(value) = dict.get(key) {
+                    self.document.trailer.set(key, value.clone());
+                }
+            }
+            return;
+        }
+        let catalog = self
+            .document
+            .objects
+            .iter()
+            .rev()
+            .find(|(_, object)| object.as_dict().is_ok_and(|dict| dict.has_type(b"Catalog")));
+        if let Some((&id, _)) = catalog {
+            self.document.trailer.set("Root", id);
+        }
+    }
+
+    /// End of the revision preceding the last one in `buffer`, see [`Reader::revision_ends`].
+    fn previous_revision_end(buffer: &[u8]) -> Option<usize> {
+        let content_end = buffer.iter().rposition(|c| !c.is_ascii_whitespace())?;
+        Self::revision_ends(buffer).take_while(|&end| end <= content_end).last()
+    }
+
     fn get_xref_start(buffer: &[u8]) -> Result<usize> {
         let seek_pos = buffer.len() - cmp::min(buffer.len(), 512);
-        Self::search_substring(buffer, b"%%EOF", seek_pos)
+        buffer[seek_pos..]
+            .windows(5)
+            .rposition(|w| w == b"%%EOF")
+            .map(|eof_pos| eof_pos + seek_pos)
             .and_then(|eof_pos| if eof_pos > 25 { Some(eof_pos) } else { None })
             .and_then(|eof_pos| Self::search_substring(buffer, b"startxref", eof_pos - 25))
             .ok_or(Error::Xref(XrefError::Start))
@@ -491,6 +1128,53 @@ async fn load_document() {
     doc.save(file_path).unwrap();
 }
 
+#[cfg(not(feature = "async"))]
+#[test]
+fn load_from_slice_matches_load() {
+    for entry in std::fs::read_dir("assets").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|it| it.to_str()) != Some("pdf") {
+            continue;
+        }
+        let loaded = Document::load(&path).unwrap();
+        // The document doesn't borrow the buffer.
+        let from_slice = Document::load_from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(from_slice.version, loaded.version, "{path:?}");
+        assert_eq!(from_slice.trailer, loaded.trailer, "{path:?}");
+        assert_eq!(from_slice.max_id, loaded.max_id, "{path:?}");
+        assert_eq!(from_slice.objects, loaded.objects, "{path:?}");
+        assert_eq!(from_slice.load_report, loaded.load_report, "{path:?}");
+    }
+}
+
+#[test]
+#[cfg(feature = "tracing")]
+fn load_phases_are_nested_spans() {
+    use crate::logging::tests::Collector;
+
+    let collector = Collector::default();
+    tracing::subscriber::with_default(collector.clone(), || {
+        Document::load_mem(include_bytes!("../assets/example.pdf")).unwrap();
+    });
+    let spans = collector.spans();
+    let load = &spans[0];
+    assert_eq!(
+        (load.name, load.parent, &load.fields[..]),
+        ("load", None, &["bytes"][..])
+    );
+    let xref = spans.iter().find(|span| span.name == "xref").unwrap();
+    assert_eq!((xref.parent, &xref.fields[..]), (Some("load"), &["offset"][..]));
+    // Objects are read in parallel with the `rayon` feature, but still within the load.
+    let objects: Vec<_> = spans.iter().filter(|span| span.name == "object").collect();
+    assert!(!objects.is_empty());
+    for object in objects {
+        assert_eq!(
+            (object.parent, &object.fields[..]),
+            (Some("load"), &["id", "offset"][..])
+        );
+    }
+}
+
 #[test]
 #[should_panic(expected = "Xref(Start)")]
 fn load_short_document() {
@@ -506,6 +1190,309 @@ fn load_document_with_preceding_bytes() {
     assert_eq!(doc.version, "1.5");
 }
 
+#[test]
+fn load_objects_without_endobj() {
+    fn pdf(objects: &[&str], separator: &str) -> Vec<u8> {
+        let mut pdf = b"%PDF-1.5\n".to_vec();
+        let mut offsets = vec![];
+        for object in objects {
+            offsets.push(pdf.len());
+            pdf.extend(object.as_bytes());
+            pdf.extend(separator.as_bytes());
+        }
+        let xref_start = pdf.len();
+        pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            pdf.extend(format!("{offset:010} 00000 n \n").as_bytes());
+        }
+        let trailer = format!(
+            "trailer\n<</Root 1 0 R/Size {}>>\nstartxref\n{xref_start}\n%%EOF",
+            objects.len() + 1
+        );
+        pdf.extend(trailer.as_bytes());
+        pdf
+    }
+
+    let objects = [
+        "1 0 obj<</Type/Catalog/Pages 2 0 R>>",
+        "2 0 obj<</Type/Pages/Kids[]/Count 0>>",
+        "3 0 obj[1 2 3]endobj",
+    ];
+    for separator in ["\n", "\r\n", ""] {
+        let doc = Document::load_mem(&pdf(&objects, separator)).unwrap();
+        assert_eq!(doc.objects.len(), 3, "{separator:?}");
+        assert_eq!(
+            doc.get_dictionary(ObjectId(2, 0))
+                .unwrap()
+                .get(b"Count")
+                .unwrap()
+                .as_i64()
+                .unwrap(),
+            0
+        );
+        assert!(doc.load_report.skipped_bytes.is_empty());
+    }
+
+    let buffer = pdf(
+        &["1 0 obj<</Type/Catalog>> junk", "2 0 obj<</A 1>> ] garbage endobj"],
+        "\n",
+    );
+    let doc = Document::load_mem(&buffer).unwrap();
+    assert_eq!(doc.objects.len(), 2);
+    let start = |text: &[u8]| buffer.windows(text.len()).position(|window| window == text).unwrap();
+    assert_eq!(
+        doc.load_report.skipped_bytes,
+        [
+            (ObjectId(1, 0), start(b"junk")..start(b"2 0 obj")),
+            (ObjectId(2, 0), start(b"] garbage")..start(b"endobj"))
+        ]
+    );
+}
+
+#[test]
+fn load_streams_with_invalid_extents() {
+    let body = [
+        "1 0 obj<</Type/Catalog/Pages 2 0 R>>endobj\n",
+        "2 0 obj<</Type/Pages/Kids[]/Count 0>>endobj\n",
+        // An embedded object, which the cross-reference entry of object 4 points to.
+        "3 0 obj<</Length 50>>stream\n4 0 obj<</Length 5>>stream\nhello\nendstream endobj\nendstream endobj\n",
+        // The lengths are in an object stream, so they are only resolved once all objects are read.
+        "5 0 obj<</Length 8 0 R>>stream\nworld\nendstream endobj\n",
+        "6 0 obj<</Length 9 0 R>>stream\ntruncated\nendstream endobj\n",
+        "7 0 obj<</Type/ObjStm/N 2/First 8/Length 16>>stream\n8 0 9 2 5 100000\nendstream endobj\n",
+    ]
+    .concat();
+    let mut buffer = format!("%PDF-1.5\n{body}").into_bytes();
+    let start = |buffer: &[u8], text: &[u8]| buffer.windows(text.len()).position(|window| window == text).unwrap();
+    let mut entries = vec![[0, 0, 0, 0, 0, 0xFF, 0xFF]];
+    for id in 1..=7 {
+        let offset = start(&buffer, format!("{id} 0 obj").as_bytes()) as u32;
+        let [a, b, c, d] = offset.to_be_bytes();
+        entries.push([1, a, b, c, d, 0, 0]);
+    }
+    entries.extend([[2, 0, 0, 0, 7, 0, 0], [2, 0, 0, 0, 7, 0, 1]]);
+    let xref_start = buffer.len() as u32;
+    let [a, b, c, d] = xref_start.to_be_bytes();
+    entries.push([1, a, b, c, d, 0, 0]);
+    buffer.extend(b"10 0 obj<</Type/XRef/Size 11/W[1 4 2]/Root 1 0 R/Length 77>>stream\n");
+    buffer.extend(entries.concat());
+    buffer.extend(format!("\nendstream endobj\nstartxref\n{xref_start}\n%%EOF").as_bytes());
+
+    let doc = Document::load_mem(&buffer).unwrap();
+    assert_eq!(doc.load_report.invalid_stream_extents, [ObjectId(4, 0), ObjectId(6, 0)]);
+    let stream = |id| doc.get_object(ObjectId(id, 0)).and_then(Object::as_stream).unwrap();
+    assert_eq!(stream(3).start_position, Some(start(&buffer, b"4 0 obj")));
+    assert_eq!(stream(4).content, b"hello");
+    assert_eq!(stream(4).start_position, Some(start(&buffer, b"hello")));
+    assert_eq!(stream(5).content, b"world");
+    assert_eq!(stream(5).start_position, Some(start(&buffer, b"world")));
+    assert!(stream(6).content.is_empty());
+    // The data of the streams with an indirect /Length isn't garbage after their dictionary.
+    assert!(doc.load_report.skipped_bytes.is_empty());
+
+    let options = ParseOptions {
+        record_spans: true,
+        ..ParseOptions::default()
+    };
+    let doc = Document::load_mem_with_options(&buffer, &options).unwrap();
+    let span = doc.object_spans()[&ObjectId(5, 0)].clone();
+    assert_eq!(
+        &buffer[span],
+        b"5 0 obj<</Length 8 0 R>>stream\nworld\nendstream endobj"
+    );
+}
+
+#[test]
+fn load_catalog_in_undecodable_object_stream() {
+    let body = [
+        "1 0 obj<</Type/Pages/Kids[]/Count 0>>endobj\n",
+        // The catalog is object 3 in this stream, whose data isn't deflated.
+        "2 0 obj<</Type/ObjStm/N 1/First 4/Filter/FlateDecode/Length 19>>stream\nnot deflated at all\nendstream endobj\n",
+    ]
+    .concat();
+    let mut buffer = format!("%PDF-1.5\n{body}").into_bytes();
+    let start = |buffer: &[u8], text: &[u8]| buffer.windows(text.len()).position(|window| window == text).unwrap();
+    let mut entries = vec![[0, 0, 0, 0, 0, 0xFF, 0xFF]];
+    for id in 1..=2 {
+        let offset = start(&buffer, format!("{id} 0 obj").as_bytes()) as u32;
+        let [a, b, c, d] = offset.to_be_bytes();
+        entries.push([1, a, b, c, d, 0, 0]);
+    }
+    entries.push([2, 0, 0, 0, 2, 0, 0]);
+    let xref_start = buffer.len() as u32;
+    let [a, b, c, d] = xref_start.to_be_bytes();
+    entries.push([1, a, b, c, d, 0, 0]);
+    buffer.extend(b"4 0 obj<</Type/XRef/Size 5/W[1 4 2]/Root 3 0 R/Length 35>>stream\n");
+    buffer.extend(entries.concat());
+    buffer.extend(format!("\nendstream endobj\nstartxref\n{xref_start}\n%%EOF").as_bytes());
+
+    match Document::load_mem(&buffer) {
+        Err(Error::MissingCatalog {
+            catalog,
+            container,
+            cause,
+        }) => {
+            assert_eq!(catalog, ObjectId(3, 0));
+            assert_eq!(container, ObjectId(2, 0));
+            assert!(cause.contains("couldn't be decoded"), "{cause}");
+        }
+        result => panic!("expected a missing catalog, got {:?}", result.map(|doc| doc.trailer)),
+    }
+
+    let options = ParseOptions {
+        lenient: true,
+        ..ParseOptions::default()
+    };
+    let doc = Document::load_mem_with_options(&buffer, &options).unwrap();
+    let (container, cause) = doc.load_report.unreadable_catalog.as_ref().unwrap();
+    assert_eq!(*container, ObjectId(2, 0));
+    assert!(cause.contains("couldn't be decoded"), "{cause}");
+    assert!(doc.catalog().is_err());
+}
+
+#[test]
+fn load_object_stream_chain() {
+    fn object_stream(id: u32, objects: &[(u32, &str)], extends: Option<u32>) -> String {
+        let mut header = String::new();
+        let mut offset = 0;
+        for (number, body) in objects {
+            header += &format!("{number} {offset} ");
+            offset += body.len() + 1;
+        }
+        let bodies: Vec<_> = objects.iter().map(|(_, body)| *body).collect();
+        let content = format!("{header}{}", bodies.join("\n"));
+        let extends = extends.map_or_else(String::new, |extends| format!("/Extends {extends} 0 R"));
+        format!(
+            "{id} 0 obj<</Type/ObjStm/N {}/First {}{extends}/Length {}>>stream\n{content}\nendstream endobj\n",
+            objects.len(),
+            header.len(),
+            content.len()
+        )
+    }
+    let file = |cyclic: bool| {
+        // Object 2 is in both streams without a cross-reference entry, object 4 only in the extended one.
+        let body = [
+            object_stream(7, &[(1, "<</Type/Catalog/Pages 3 0 R>>"), (2, "<</Value 7>>")], Some(8)),
+            object_stream(
+                8,
+                &[
+                    (3, "<</Type/Pages/Kids[]/Count 0>>"),
+                    (2, "<</Value 8>>"),
+                    (4, "<</Value 4>>"),
+                ],
+                cyclic.then_some(7),
+            ),
+        ]
+        .concat();
+        let mut buffer = format!("%PDF-1.5\n{body}").into_bytes();
+        let start = |text: &[u8]| buffer.windows(text.len()).position(|window| window == text).unwrap() as u32;
+        let free = [0, 0, 0, 0, 0, 0, 0];
+        let [a, b, c, d] = start(b"7 0 obj").to_be_bytes();
+        let [e, f, g, h] = start(b"8 0 obj").to_be_bytes();
+        let [i, j, k, l] = (buffer.len() as u32).to_be_bytes();
+        let entries = [
+            [0, 0, 0, 0, 0, 0xFF, 0xFF],
+            [2, 0, 0, 0, 7, 0, 0],
+            free,
+            [2, 0, 0, 0, 8, 0, 0],
+            free,
+            free,
+            free,
+            [1, a, b, c, d, 0, 0],
+            [1, e, f, g, h, 0, 0],
+            [1, i, j, k, l, 0, 0],
+        ];
+        let xref_start = buffer.len();
+        buffer.extend(b"9 0 obj<</Type/XRef/Size 10/W[1 4 2]/Root 1 0 R/Length 70>>stream\n");
+        buffer.extend(entries.concat());
+        buffer.extend(format!("\nendstream endobj\nstartxref\n{xref_start}\n%%EOF").as_bytes());
+        buffer
+    };
+    let value = |doc: &Document, id| {
+        doc.get_dictionary(ObjectId(id, 0))
+            .and_then(|dict| dict.get(b"Value"))
+            .and_then(Object::as_i64)
+            .unwrap()
+    };
+
+    let doc = Document::load_mem(&file(false)).unwrap();
+    assert!(doc.catalog().is_ok());
+    assert!(doc.get_dictionary(ObjectId(3, 0)).unwrap().has_type(b"Pages"));
+    assert_eq!(value(&doc, 2), 7);
+    assert_eq!(value(&doc, 4), 4);
+    assert!(doc.load_report.invalid_object_stream_chains.is_empty());
+
+    let doc = Document::load_mem(&file(true)).unwrap();
+    assert_eq!(
+        doc.load_report.invalid_object_stream_chains,
+        [ObjectId(7, 0), ObjectId(8, 0)]
+    );
+    assert!(doc.catalog().is_ok());
+    assert_eq!(value(&doc, 4), 4);
+}
+
+#[test]
+fn reconstruct_revisions_of_damaged_incremental_file() {
+    let mut doc = crate::creator::tests::create_document_with_texts(&["first"]);
+    let info_id = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+    let mut original = vec![];
+    doc.save_to(&mut original).unwrap();
+
+    let mut doc = Document::load_mem(&original).unwrap();
+    doc.get_dictionary_mut(info_id)
+        .unwrap()
+        .set("Title", Object::string_literal("Updated"));
+    let mut updated = vec![];
+    doc.save_incremental(&original, &mut updated).unwrap();
+
+    // Break the cross-reference section of the update, which is complete otherwise.
+    let start = updated.windows(9).rposition(|w| w == b"startxref").unwrap();
+    updated.truncate(start);
+    updated.extend(b"startxref\n999999\n%%EOF\n");
+
+    let doc = Document::load_mem(&updated).unwrap();
+    assert!(doc.load_report.reconstructed_xref);
+    assert_eq!(doc.load_report.discarded_revisions, 0);
+    let update_start = Reader::previous_revision_end(&updated).unwrap();
+    assert!(update_start >= original.len());
+    assert_eq!(
+        doc.load_report.recovered_revisions,
+        [0..update_start, update_start..updated.len()]
+    );
+    assert_eq!(doc.statistics().revisions, 2);
+    let info = doc.get_dictionary(info_id).unwrap();
+    assert_eq!(info.get(b"Title").unwrap().as_str().unwrap(), b"Updated");
+    assert_eq!(doc.get_pages().len(), 1);
+
+    // An update cut off before its end is still ignored.
+    let cut = &updated[..original.len() + (updated.len() - original.len()) / 2];
+    let doc = Document::load_mem(cut).unwrap();
+    assert!(!doc.load_report.reconstructed_xref);
+    let info = doc.get_dictionary(info_id).unwrap();
+    assert_ne!(info.get(b"Title").unwrap().as_str().unwrap(), b"Updated");
+}
+
+#[test]
+fn reconstruct_revisions_with_eof_marker_in_stream() {
+    let mut doc = crate::creator::tests::create_document_with_texts(&["first"]);
+    let stream_id = doc.add_object(Stream::new(dictionary! {}, b"%%EOF\n1 0 obj\n%%EOF".to_vec()));
+    let mut original = vec![];
+    doc.save_to(&mut original).unwrap();
+    let mut updated = original.clone();
+    updated.extend(b"7 0 obj\n(Updated)\nendobj\nstartxref\n999999\n%%EOF\n");
+
+    let doc = Document::load_mem(&updated).unwrap();
+    assert!(doc.load_report.reconstructed_xref);
+    // The markers in the stream don't end revisions.
+    assert_eq!(
+        doc.load_report.recovered_revisions,
+        [0..original.len(), original.len()..updated.len()]
+    );
+    let stream = doc.get_object(stream_id).and_then(Object::as_stream).unwrap();
+    assert_eq!(stream.content, b"%%EOF\n1 0 obj\n%%EOF");
+    assert_eq!(doc.get_pages().len(), 1);
+}
+
 #[test]
 fn load_many_shallow_brackets() {
     let content: String = std::iter::repeat("()")
@@ -603,5 +1590,228 @@ fn load_too_deep_brackets() {
 
     let doc = Document::load_mem(doc.as_bytes()).unwrap();
     let pages = doc.get_pages().keys().cloned().collect::<Vec<_>>();
-    assert_eq!("Hello World!\n", doc.extract_text(&pages).unwrap());
+    // The string nested too deep is kept as raw tokens, which show no text.
+    assert_eq!("Hello World!\n\n", doc.extract_text(&pages).unwrap());
+}
+
+#[test]
+fn duplicate_dictionary_keys() {
+    let mut doc = crate::creator::tests::create_document();
+    let id = doc.add_object(dictionary! { "Length" => 5, "Lengtx" => 3, "Type" => "Test" });
+    doc.catalog_mut().unwrap().set("Test", id);
+    let mut buffer = vec![];
+    doc.save_to(&mut buffer).unwrap();
+    let start = buffer.windows(7).position(|window| window == b"/Lengtx").unwrap();
+    buffer[start..start + 7].copy_from_slice(b"/Length");
+
+    let load = |duplicate_keys| {
+        Document::load_mem_with_options(
+            &buffer,
+            &ParseOptions {
+                duplicate_keys,
+                ..Default::default()
+            },
+        )
+    };
+    for (policy, length) in [(DuplicateKeyPolicy::KeepLast, 3), (DuplicateKeyPolicy::KeepFirst, 5)] {
+        let doc = load(policy).unwrap();
+        let dict = doc.get_dictionary(id).unwrap();
+        assert_eq!(dict.get(b"Length").and_then(Object::as_i64).unwrap(), length);
+        // The key keeps the position of its first occurrence.
+        assert_eq!(
+            dict.iter().map(|(key, _)| &key[..]).collect::<Vec<_>>(),
+            [b"Length".as_slice(), b"Type"]
+        );
+        assert_eq!(doc.load_report.duplicate_keys, [(id, b"Length".to_vec())]);
+    }
+    assert_eq!(Document::load_mem(&buffer).unwrap().load_report.duplicate_keys.len(), 1);
+    match load(DuplicateKeyPolicy::Error) {
+        Err(Error::DuplicateKey { id: error_id, key }) => assert_eq!((error_id, key.as_str()), (id, "Length")),
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn lenient_stream_lengths() {
+    let mut doc = crate::creator::tests::create_document();
+    let ids: Vec<ObjectId> = ["Aaaaa", "Baaaa", "Caaaa"]
+        .into_iter()
+        .map(|pad| {
+            doc.add_object(Stream::new(
+                dictionary! { "Length" => 0, "Pad" => pad },
+                b"hello".to_vec(),
+            ))
+        })
+        .collect();
+    for (i, id) in ids.iter().enumerate() {
+        doc.catalog_mut().unwrap().set(format!("Test{i}"), *id);
+    }
+    let mut buffer = vec![];
+    doc.save_to(&mut buffer).unwrap();
+    for (from, to) in [
+        (b"/Length 5/Pad/Aaaaa", b"/Length 5.9/Pad/Aaa"),
+        (b"/Length 5/Pad/Baaaa", b"/Length(5)/Pad/Baaa"),
+        (b"/Length 5/Pad/Caaaa", b"/Length -5.0/Pad/Ca"),
+    ] {
+        let start = buffer.windows(from.len()).position(|window| window == from).unwrap();
+        buffer[start..start + to.len()].copy_from_slice(to);
+    }
+
+    let lenient = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    let content = |doc: &Document, id| {
+        doc.get_object(id)
+            .and_then(Object::as_stream)
+            .map(|stream| stream.content.clone())
+    };
+    let doc = Document::load_mem_with_options(&buffer, &lenient).unwrap();
+    assert_eq!(content(&doc, ids[0]).unwrap(), b"hello");
+    assert_eq!(content(&doc, ids[1]).unwrap(), b"hello");
+    assert_ne!(content(&doc, ids[2]).ok().as_deref(), Some(b"hello".as_slice()));
+    assert_eq!(doc.load_report.coerced_lengths, ids[..2]);
+
+    // Strict parsing keeps rejecting lengths that aren't integers.
+    let doc = Document::load_mem(&buffer).unwrap();
+    for id in &ids {
+        assert_ne!(content(&doc, *id).ok().as_deref(), Some(b"hello".as_slice()));
+    }
+    assert!(doc.load_report.coerced_lengths.is_empty());
+}
+
+#[test]
+fn record_object_spans() {
+    let mut hybrid = vec![];
+    let options = crate::SaveOptions {
+        xref_mode: Some(crate::XrefMode::Hybrid),
+        ..crate::SaveOptions::default()
+    };
+    crate::creator::tests::create_document()
+        .save_with_options(&mut hybrid, options)
+        .unwrap();
+    // With the number of cross-reference sections and of trailer dictionaries.
+    let files = [
+        (std::fs::read("assets/AnnotationDemo.pdf").unwrap(), 1, 0),
+        (std::fs::read("assets/Incremental.pdf").unwrap(), 3, 0),
+        (hybrid, 2, 1),
+    ];
+    let options = ParseOptions {
+        record_spans: true,
+        ..ParseOptions::default()
+    };
+    for (file, sections, trailers) in files {
+        // Bytes before the header count in the offsets.
+        let buffer = [b"garbage\n".as_slice(), &file].concat();
+        assert!(Document::load_mem(&buffer).unwrap().object_spans().is_empty());
+        let doc = Document::load_mem_with_options(&buffer, &options).unwrap();
+        let spans = doc.file_spans();
+        // Objects are parsed like the reader does, resolving indirect stream lengths in the file.
+        let mut document = Document::new();
+        document.reference_table = doc.reference_table.clone();
+        let reader = Reader {
+            buffer: &file,
+            document,
+        };
+        let parse = |span: &Range<usize>| {
+            let input = ParserInput::new_extra(&buffer[span.clone()], "object");
+            let (id, mut object, skipped, extent) =
+                parser::indirect_object(input, 0, None, &reader, &mut HashSet::new()).unwrap();
+            assert_eq!((skipped, extent), (None, 0..span.len()));
+            if let Ok(stream) = object.as_stream_mut() {
+                stream.start_position = None;
+                // Object streams are kept decompressed.
+                if stream.dict.has_type(b"ObjStm") {
+                    stream.decompress().unwrap();
+                }
+            }
+            (id, object)
+        };
+
+        assert_eq!(spans.objects.keys().count() + spans.compressed.len(), doc.objects.len());
+        for (&id, span) in &spans.objects {
+            assert!(buffer[span.clone()].ends_with(b"endobj"));
+            let mut object = doc.objects[&id].clone();
+            if let Ok(stream) = object.as_stream_mut() {
+                stream.start_position = None;
+            }
+            assert_eq!(parse(span), (id, object));
+        }
+        for (container, _) in spans.compressed.values() {
+            assert!(spans.objects.contains_key(container));
+        }
+
+        assert_eq!((spans.xref_sections.len(), spans.trailers.len()), (sections, trailers));
+        for section in &spans.xref_sections {
+            if !buffer[section.clone()].starts_with(b"xref") {
+                let (id, object) = parse(section);
+                assert!(object.as_stream().unwrap().dict.has(b"W"));
+                assert_eq!(spans.objects.get(&id), Some(section));
+            }
+        }
+        for trailer in &spans.trailers {
+            let dict = parser::trailer_dictionary(ParserInput::new_extra(&buffer[trailer.clone()], "trailer")).unwrap();
+            assert!(dict.has(b"Root"));
+            assert!(buffer[trailer.clone()].ends_with(b">>"));
+        }
+    }
+}
+
+#[test]
+fn load_hybrid_file_with_compressed_objects_in_xref_stream() {
+    // A single revision without `/Prev`, whose table lists the page, stored in an object stream, as free.
+    let page = b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] >>";
+    let mut file = b"%PDF-1.5\n".to_vec();
+    let mut offsets = BTreeMap::new();
+    let mut add_object = |file: &mut Vec<u8>, id: u32, object: &[u8]| {
+        offsets.insert(id, file.len());
+        file.extend(format!("{id} 0 obj\n").as_bytes());
+        file.extend(object);
+        file.extend(b"\nendobj\n");
+    };
+    add_object(&mut file, 1, b"<< /Type /Catalog /Pages 2 0 R >>");
+    add_object(&mut file, 2, b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>");
+    let object_stream = [b"3 0 ".as_slice(), page].concat();
+    add_object(
+        &mut file,
+        4,
+        &[
+            format!(
+                "<< /Type /ObjStm /N 1 /First 4 /Length {} >>\nstream\n",
+                object_stream.len()
+            )
+            .as_bytes(),
+            &object_stream,
+            b"\nendstream",
+        ]
+        .concat(),
+    );
+    add_object(
+        &mut file,
+        5,
+        b"<< /Type /XRef /Size 6 /W [1 2 1] /Index [3 1] /Length 4 >>\nstream\n\x02\x00\x04\x00\nendstream",
+    );
+    let xref_stream = offsets[&5];
+    let xref_table = file.len();
+    file.extend(b"xref\n0 5\n0000000000 65535 f \n");
+    for id in 1..5 {
+        match offsets.get(&id) {
+            Some(offset) => file.extend(format!("{offset:010} 00000 n \n").as_bytes()),
+            None => file.extend(b"0000000000 00000 f \n"),
+        }
+    }
+    file.extend(format!("trailer\n<< /Size 6 /Root 1 0 R /XRefStm {xref_stream} >>\n").as_bytes());
+    file.extend(format!("startxref\n{xref_table}\n%%EOF\n").as_bytes());
+
+    let doc = Document::load_mem(&file).unwrap();
+    assert!(matches!(
+        doc.reference_table.get(3),
+        Some(XrefEntry::Compressed { container: 4, index: 0 })
+    ));
+    assert!(!doc.trailer.has(b"XRefStm"));
+    assert_eq!(doc.get_pages().into_values().collect::<Vec<_>>(), [ObjectId(3, 0)]);
+    assert_eq!(
+        doc.get_dictionary(ObjectId(3, 0)).unwrap().get(b"MediaBox").unwrap(),
+        &Object::Array(vec![0.into(), 0.into(), 100.into(), 100.into()])
+    );
 }
@@ -0,0 +1,404 @@
+use crate::{Dictionary, Document, Error, LoadReport, Object, ObjectId, Result};
+use log::warn;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// Problems found and fixed by [`Document::repair`], including the recovery performed while loading.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairSummary {
+    /// Recovery performed while loading the document.
+    pub load: LoadReport,
+    /// The trailer had no usable `/Root`, a catalog was found among the objects or created.
+    pub rebuilt_catalog: bool,
+    /// The catalog had no usable `/Pages`, a page tree was built from the page objects.
+    pub rebuilt_page_tree: bool,
+    /// Missing objects which were referenced, with the number of references removed.
+    pub dangling_references: BTreeMap<ObjectId, usize>,
+    /// Entries of `/Kids` arrays removed because they weren't pages, or listed a page or node again.
+    pub removed_kids: Vec<ObjectId>,
+    /// Nodes of the page tree whose `/Parent` was missing or wrong.
+    pub reparented_nodes: Vec<ObjectId>,
+    /// Nodes of the page tree whose `/Count` was missing or wrong.
+    pub recounted_nodes: Vec<ObjectId>,
+    /// Objects which weren't referenced anymore and were removed.
+    pub pruned_objects: Vec<ObjectId>,
+}
+
+impl RepairSummary {
+    /// Whether no problem was found.
+    pub fn is_clean(&self) -> bool {
+        self.problem_count() == 0
+    }
+
+    /// Number of problems found, each pruned object excepted as unreferenced objects are harmless.
+    pub fn problem_count(&self) -> usize {
+        let load = &self.load;
+        load.discarded_revisions
+            + usize::from(load.reconstructed_xref)
+            + load.unreadable_objects.len()
+            + load.invalid_stream_extents.len()
+            + load.duplicate_pages.len()
+            + usize::from(self.rebuilt_catalog)
+            + usize::from(self.rebuilt_page_tree)
+            + self.dangling_references.values().sum::<usize>()
+            + self.removed_kids.len()
+            + self.reparented_nodes.len()
+            + self.recounted_nodes.len()
+    }
+}
+
+impl fmt::Display for RepairSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn ids(ids: &[ObjectId]) -> String {
+            let ids: Vec<_> = ids.iter().map(ObjectId::to_string).collect();
+            ids.join(", ")
+        }
+
+        let load = &self.load;
+        if self.is_clean() {
+            writeln!(f, "No problem found.")?;
+        }
+        if load.discarded_revisions > 0 {
+            writeln!(
+                f,
+                "Incomplete updates discarded: {} ({} bytes)",
+                load.discarded_revisions, load.ignored_bytes
+            )?;
+        }
+        if load.reconstructed_xref {
+            writeln!(f, "Cross-reference table reconstructed by scanning the file")?;
+        }
+        if !load.unreadable_objects.is_empty() {
+            writeln!(
+                f,
+                "Unreadable objects dropped: {} ({})",
+                load.unreadable_objects.len(),
+                ids(&load.unreadable_objects)
+            )?;
+        }
+        if !load.invalid_stream_extents.is_empty() {
+            writeln!(
+                f,
+                "Streams outside the file or within another stream: {} ({})",
+                load.invalid_stream_extents.len(),
+                ids(&load.invalid_stream_extents)
+            )?;
+        }
+        if self.rebuilt_catalog {
+            writeln!(f, "Document catalog rebuilt")?;
+        }
+        if self.rebuilt_page_tree {
+            writeln!(f, "Page tree rebuilt from the page objects")?;
+        }
+        if !self.dangling_references.is_empty() {
+            let missing: Vec<_> = self.dangling_references.keys().copied().collect();
+            writeln!(
+                f,
+                "References to missing objects removed: {} (to {})",
+                self.dangling_references.values().sum::<usize>(),
+                ids(&missing)
+            )?;
+        }
+        if !load.duplicate_pages.is_empty() {
+            writeln!(
+                f,
+                "Pages listed more than once: {} ({})",
+                load.duplicate_pages.len(),
+                ids(&load.duplicate_pages)
+            )?;
+        }
+        if !self.removed_kids.is_empty() {
+            writeln!(
+                f,
+                "Invalid or repeated page tree kids removed: {} ({})",
+                self.removed_kids.len(),
+                ids(&self.removed_kids)
+            )?;
+        }
+        if !self.reparented_nodes.is_empty() {
+            writeln!(
+                f,
+                "Page tree /Parent fixed: {} ({})",
+                self.reparented_nodes.len(),
+                ids(&self.reparented_nodes)
+            )?;
+        }
+        if !self.recounted_nodes.is_empty() {
+            writeln!(
+                f,
+                "Page tree /Count fixed: {} ({})",
+                self.recounted_nodes.len(),
+                ids(&self.recounted_nodes)
+            )?;
+        }
+        if !self.pruned_objects.is_empty() {
+            writeln!(f, "Unreferenced objects removed: {}", self.pruned_objects.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Document {
+    /// Fix the structural problems of a damaged document, so that it can be saved as a clean file.
+    ///
+    /// References to missing objects are removed, the catalog and page tree are rebuilt if needed, the page tree
+    /// is made consistent (`/Kids`, `/Parent` and `/Count`), and unreferenced objects are pruned. The summary also
+    /// includes the recovery performed while loading, see [`LoadReport`].
+    ///
+    /// Fails with [`Error::PageNumberNotFound`] if the document has no page left, as it can't be recovered.
+    pub fn repair(&mut self) -> Result<RepairSummary> {
+        let mut summary = RepairSummary {
+            load: self.load_report.clone(),
+            ..RepairSummary::default()
+        };
+        self.remove_dangling_references(&mut summary);
+        let root_id = self.repair_catalog(&mut summary);
+        let pages_id = self.repair_page_tree_root(root_id, &mut summary);
+        let mut visited = HashSet::from([pages_id]);
+        let count = self.repair_page_tree_node(pages_id, None, &mut visited, &mut summary, 0);
+        if count == 0 {
+            return Err(Error::PageNumberNotFound(1));
+        }
+        summary.pruned_objects = self.prune_objects();
+        summary.pruned_objects.sort_unstable();
+        if !summary.is_clean() {
+            warn!("Repaired {} problems of the document", summary.problem_count());
+        }
+        Ok(summary)
+    }
+
+    /// Remove the dictionary entries referencing missing objects, and replace such array items with null.
+    fn remove_dangling_references(&mut self, summary: &mut RepairSummary) {
+        fn visit(object: &mut Object, ids: &HashSet<ObjectId>, dangling: &mut BTreeMap<ObjectId, usize>) {
+            match object {
+                Object::Array(array) => {
+                    for item in array.iter_mut() {
+                        if let Object::Reference(id) = item {
+                            if !ids.contains(id) {
+                                *dangling.entry(*id).or_default() += 1;
+                                *item = Object::Null;
+                            }
+                        } else {
+                            visit(item, ids, dangling);
+                        }
+                    }
+                }
+                Object::Dictionary(dict) => visit_dict(dict, ids, dangling),
+                Object::Stream(stream) => visit_dict(&mut stream.dict, ids, dangling),
+                _ => {}
+            }
+        }
+        fn visit_dict(dict: &mut Dictionary, ids: &HashSet<ObjectId>, dangling: &mut BTreeMap<ObjectId, usize>) {
+            let mut missing = vec![];
+            for (key, value) in dict.iter_mut() {
+                match value {
+                    Object::Reference(id) if !ids.contains(id) => {
+                        *dangling.entry(*id).or_default() += 1;
+                        missing.push(key.clone());
+                    }
+                    value => visit(value, ids, dangling),
+                }
+            }
+            for key in missing {
+                dict.remove(&key);
+            }
+        }
+
+        let ids: HashSet<ObjectId> = self.objects.keys().copied().collect();
+        let mut dangling = BTreeMap::new();
+        for (id, object) in self.objects.iter_mut() {
+            let before = dangling.values().sum::<usize>();
+            visit(object, &ids, &mut dangling);
+            if dangling.values().sum::<usize>() != before {
+                self.dirty.insert(*id);
+            }
+        }
+        visit_dict(&mut self.trailer, &ids, &mut dangling);
+        summary.dangling_references = dangling;
+    }
+
+    /// Make sure the trailer references a catalog, returning its ID.
+    fn repair_catalog(&mut self, summary: &mut RepairSummary) -> ObjectId {
+        if let Ok(id) = self.trailer.get(b"Root").and_then(Object::as_reference) {
+            if self.get_dictionary(id).is_ok() {
+                return id;
+            }
+        }
+        summary.rebuilt_catalog = true;
+        let catalog = self
+            .objects
+            .iter()
+            .rev()
+            .find(|(_, object)| object.as_dict().is_ok_and(|dict| dict.has_type(b"Catalog")))
+            .map(|(id, _)| *id);
+        let id = catalog.unwrap_or_else(|| self.add_object(dictionary! { "Type" => "Catalog" }));
+        self.trailer.set("Root", id);
+        id
+    }
+
+    /// Make sure the catalog references the root of a page tree, returning its ID.
+    ///
+    /// Without one, a page tree listing every page object is built.
+    fn repair_page_tree_root(&mut self, root_id: ObjectId, summary: &mut RepairSummary) -> ObjectId {
+        let pages = self
+            .get_dictionary(root_id)
+            .and_then(|catalog| catalog.get(b"Pages"))
+            .and_then(Object::as_reference);
+        if let Ok(id) = pages {
+            if self.get_dictionary(id).is_ok_and(|pages| pages.has_type(b"Pages")) {
+                return id;
+            }
+        }
+        summary.rebuilt_page_tree = true;
+        let kids: Vec<Object> = self
+            .objects
+            .iter()
+            .filter(|(_, object)| object.as_dict().is_ok_and(|dict| dict.has_type(b"Page")))
+            .map(|(id, _)| Object::Reference(*id))
+            .collect();
+        let count = kids.len() as i64;
+        let id = self.add_object(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+        });
+        if let Ok(catalog) = self.get_dictionary_mut(root_id) {
+            catalog.set("Pages", id);
+        }
+        id
+    }
+
+    /// Fix the `/Kids`, `/Parent` and `/Count` of a node of the page tree and of its descendants,
+    /// returning the number of pages under it.
+    fn repair_page_tree_node(
+        &mut self, node_id: ObjectId, parent_id: Option<ObjectId>, visited: &mut HashSet<ObjectId>,
+        summary: &mut RepairSummary, depth: usize,
+    ) -> i64 {
+        const MAX_DEPTH: usize = 256;
+
+        let Ok(node) = self.get_dictionary(node_id) else {
+            return 0;
+        };
+        let is_page = node.has_type(b"Page");
+        if node.get(b"Parent").and_then(Object::as_reference).ok() != parent_id {
+            summary.reparented_nodes.push(node_id);
+            let node = self.get_dictionary_mut(node_id).unwrap();
+            match parent_id {
+                Some(parent_id) => node.set("Parent", parent_id),
+                None => {
+                    node.remove(b"Parent");
+                }
+            }
+        }
+        if is_page {
+            return 1;
+        }
+
+        let kids = self
+            .get_dictionary(node_id)
+            .and_then(|node| node.get(b"Kids"))
+            .and_then(|kids| self.dereference(kids))
+            .and_then(|(_, kids)| kids.as_array())
+            .cloned()
+            .unwrap_or_default();
+        let mut valid_kids = vec![];
+        let mut count = 0;
+        for kid in kids {
+            let kid_id = kid.as_reference().ok();
+            let is_node = kid_id
+                .and_then(|id| self.get_dictionary(id).ok())
+                .is_some_and(|kid| kid.has_type(b"Page") || kid.has_type(b"Pages") && depth < MAX_DEPTH);
+            match kid_id {
+                Some(kid_id) if is_node && visited.insert(kid_id) => {
+                    count += self.repair_page_tree_node(kid_id, Some(node_id), visited, summary, depth + 1);
+                    valid_kids.push(kid);
+                }
+                Some(kid_id) => summary.removed_kids.push(kid_id),
+                None => {}
+            }
+        }
+
+        let node = self.get_dictionary_mut(node_id).unwrap();
+        if node.get(b"Kids").and_then(Object::as_array).ok() != Some(&valid_kids) {
+            node.set("Kids", valid_kids);
+        }
+        if node.get(b"Count").and_then(Object::as_i64).ok() != Some(count) {
+            summary.recounted_nodes.push(node_id);
+            node.set("Count", count);
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+
+    /// Save a document with several defects: a wrong /Count, a page listed twice, a reference to a missing
+    /// object and a broken cross-reference offset.
+    fn damaged_file() -> (Vec<u8>, Vec<ObjectId>) {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let pages: Vec<_> = doc.page_iter().collect();
+        let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let tree = doc.get_dictionary_mut(pages_id).unwrap();
+        tree.set("Count", 5);
+        tree.set("Kids", vec![pages[0].into(), pages[1].into(), pages[0].into()]);
+        let page = doc.get_dictionary_mut(pages[1]).unwrap();
+        page.set("Thumb", (999, 0));
+
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let start = buffer.windows(9).rposition(|window| window == b"startxref").unwrap();
+        buffer.truncate(start);
+        buffer.extend(b"startxref\n999999\n%%EOF\n");
+        (buffer, pages)
+    }
+
+    #[test]
+    fn repair_damaged_file() {
+        let (buffer, pages) = damaged_file();
+        let mut doc = Document::load_mem(&buffer).unwrap();
+        let summary = doc.repair().unwrap();
+        assert!(summary.load.reconstructed_xref);
+        assert_eq!(summary.load.duplicate_pages, [pages[0]]);
+        assert_eq!(summary.dangling_references, BTreeMap::from([(ObjectId(999, 0), 1)]));
+        assert_eq!(summary.removed_kids, [pages[0]]);
+        assert_eq!(summary.recounted_nodes.len(), 1);
+        assert!(!summary.is_clean());
+
+        let report = summary.to_string();
+        for defect in [
+            "reconstructed",
+            "listed more than once",
+            "missing objects",
+            "/Count fixed",
+        ] {
+            assert!(report.contains(defect), "{defect:?} not in {report}");
+        }
+
+        // The repaired document loads without any recovery, and has nothing left to repair.
+        let mut repaired = vec![];
+        doc.save_to(&mut repaired).unwrap();
+        let mut doc = Document::load_mem(&repaired).unwrap();
+        let clean = LoadReport {
+            xref_sections: 1,
+            ..LoadReport::default()
+        };
+        assert_eq!(doc.load_report, clean);
+        assert_eq!(doc.get_pages().len(), 2);
+        assert!(doc.repair().unwrap().is_clean());
+    }
+
+    #[test]
+    fn rebuild_page_tree() {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        doc.catalog_mut().unwrap().remove(b"Pages");
+        let summary = doc.repair().unwrap();
+        assert!(summary.rebuilt_page_tree && !summary.rebuilt_catalog);
+        assert_eq!(doc.get_pages().len(), 2);
+
+        let mut doc = Document::with_version("1.7");
+        assert!(doc.repair().is_err());
+    }
+}
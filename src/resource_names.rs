@@ -0,0 +1,402 @@
+use crate::content::Operation;
+use crate::{Dictionary, Document, Error, Object, ObjectId, Result};
+use std::collections::{BTreeMap, HashSet};
+
+/// Hands out resource names which no resource of a resource dictionary uses yet.
+///
+/// The names of all categories are taken into account, so a new name doesn't shadow or get
+/// confused with an existing resource of another type, e.g. a `/WM1` graphics state left by
+/// another tool when adding a `/WM1` XObject.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceNamer {
+    used: HashSet<Vec<u8>>,
+}
+
+impl ResourceNamer {
+    /// Collect the names used by `resources`, following references to its categories.
+    pub fn new(doc: &Document, resources: &Dictionary) -> ResourceNamer {
+        let mut used = HashSet::new();
+        for (_, category) in resources {
+            if let Ok((_, Object::Dictionary(category))) = doc.dereference(category) {
+                used.extend(category.iter().map(|(name, _)| name.clone()));
+            }
+        }
+        ResourceNamer { used }
+    }
+
+    pub fn is_used(&self, name: &[u8]) -> bool {
+        self.used.contains(name)
+    }
+
+    /// Mark a name as used, so it isn't handed out.
+    pub fn reserve(&mut self, name: &[u8]) {
+        self.used.insert(name.to_vec());
+    }
+
+    /// A new name made of `prefix` and the lowest number from 1 which gives an unused name,
+    /// e.g. `WM2` if `WM1` is used. The name counts as used afterwards.
+    pub fn next_name(&mut self, prefix: &[u8]) -> Vec<u8> {
+        let mut number = 1usize;
+        loop {
+            let name = [prefix, number.to_string().as_bytes()].concat();
+            if self.used.insert(name.clone()) {
+                return name;
+            }
+            number += 1;
+        }
+    }
+}
+
+/// What [`Dictionary::merge_resources`] does with a resource defined differently in both dictionaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fail with [`Error::ResourceConflict`].
+    Error,
+    /// Add the merged resource under a new name, recorded in the [`RenameMap`].
+    Rename,
+}
+
+/// Resources renamed by [`Dictionary::merge_resources`], by category and old name.
+///
+/// Content streams using the merged resources are updated with [`RenameMap::apply`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameMap {
+    renames: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl RenameMap {
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty()
+    }
+
+    pub fn insert(&mut self, category: &[u8], old_name: Vec<u8>, new_name: Vec<u8>) {
+        self.renames
+            .entry(category.to_vec())
+            .or_default()
+            .insert(old_name, new_name);
+    }
+
+    /// The new name of the resource `name` of `category`, if it was renamed.
+    pub fn get(&self, category: &[u8], name: &[u8]) -> Option<&[u8]> {
+        self.renames.get(category)?.get(name).map(Vec::as_slice)
+    }
+
+    /// Rename the resources used by content stream operations: fonts of `Tf`, XObjects of `Do`,
+    /// graphics states of `gs`, color spaces of `cs` and `CS`, patterns of `scn` and `SCN`,
+    /// shadings of `sh` and property lists of `BDC` and `DP`. Returns the number of renamed operands.
+    pub fn apply(&self, operations: &mut [Operation]) -> usize {
+        let mut renamed = 0;
+        for operation in operations {
+            let (category, index): (&[u8], usize) = match operation.operator.as_str() {
+                "Tf" => (b"Font", 0),
+                "Do" => (b"XObject", 0),
+                "gs" => (b"ExtGState", 0),
+                "cs" | "CS" => (b"ColorSpace", 0),
+                "scn" | "SCN" => (b"Pattern", operation.operands.len().saturating_sub(1)),
+                "sh" => (b"Shading", 0),
+                "BDC" | "DP" => (b"Properties", 1),
+                _ => continue,
+            };
+            if let Some(Object::Name(name)) = operation.operands.get_mut(index) {
+                if let Some(new_name) = self.get(category, name) {
+                    *name = new_name.to_vec();
+                    renamed += 1;
+                }
+            }
+        }
+        renamed
+    }
+}
+
+impl Dictionary {
+    /// Merge the resource dictionary `other` into this one.
+    ///
+    /// The resources of each category, e.g. `/Font`, are combined, and the `/ProcSet` arrays are
+    /// joined. Category dictionaries must be direct objects, see [`Document::direct_resources`].
+    /// A resource of `other` defined differently under the same name is handled by `conflict`,
+    /// renaming takes new names from `namer`, after reserving the names of both dictionaries.
+    /// Other entries of `other` are only added if this dictionary has none.
+    pub fn merge_resources(
+        &mut self, other: &Dictionary, conflict: ConflictPolicy, namer: &mut ResourceNamer,
+    ) -> Result<RenameMap> {
+        let categories = |resources: &Dictionary| -> Result<Vec<Vec<u8>>> {
+            let mut names = vec![];
+            for (key, value) in resources {
+                match value {
+                    Object::Dictionary(entries) => names.extend(entries.iter().map(|(name, _)| name.clone())),
+                    Object::Reference(_) if key.as_slice() != b"ProcSet" => {
+                        return Err(Error::ObjectType {
+                            expected: "Dictionary",
+                            found: "Reference",
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            Ok(names)
+        };
+        for name in categories(self)?.into_iter().chain(categories(other)?) {
+            namer.reserve(&name);
+        }
+        // Fail before anything is merged, leaving this dictionary as it was.
+        if conflict == ConflictPolicy::Error {
+            for (key, value) in other {
+                let (Ok(Object::Dictionary(entries)), Object::Dictionary(other_entries)) = (self.get(key), value)
+                else {
+                    continue;
+                };
+                let conflicting = other_entries
+                    .iter()
+                    .find(|&(name, resource)| entries.get(name).is_ok_and(|existing| existing != resource));
+                if let Some((name, _)) = conflicting {
+                    return Err(Error::ResourceConflict {
+                        category: String::from_utf8_lossy(key).into_owned(),
+                        name: String::from_utf8_lossy(name).into_owned(),
+                    });
+                }
+            }
+        }
+
+        let mut renames = RenameMap::default();
+        for (key, value) in other {
+            match (self.get_mut(key), value) {
+                (Ok(Object::Array(sets)), Object::Array(other_sets)) if key.as_slice() == b"ProcSet" => {
+                    for set in other_sets {
+                        if !sets.contains(set) {
+                            sets.push(set.clone());
+                        }
+                    }
+                }
+                (Ok(Object::Dictionary(entries)), Object::Dictionary(other_entries)) => {
+                    for (name, resource) in other_entries {
+                        match entries.get(name) {
+                            Err(_) => entries.set(name.clone(), resource.clone()),
+                            Ok(existing) if existing == resource => {}
+                            // Conflicts with `ConflictPolicy::Error` were ruled out above.
+                            Ok(_) => {
+                                let new_name = namer.next_name(category_prefix(key));
+                                entries.set(new_name.clone(), resource.clone());
+                                renames.insert(key, name.clone(), new_name);
+                            }
+                        }
+                    }
+                }
+                (Ok(_), _) => {}
+                (Err(_), _) => self.set(key.clone(), value.clone()),
+            }
+        }
+        Ok(renames)
+    }
+}
+
+/// The prefix of the names [`Document::ensure_resource`] gives to resources of a category.
+fn category_prefix(category: &[u8]) -> &'static [u8] {
+    match category {
+        b"Font" => b"F",
+        b"XObject" => b"X",
+        b"ExtGState" => b"GS",
+        b"ColorSpace" => b"CS",
+        b"Pattern" => b"P",
+        b"Shading" => b"Sh",
+        b"Properties" => b"MC",
+        _ => b"R",
+    }
+}
+
+impl Document {
+    /// Copy a resource dictionary with its category dictionaries written directly, following
+    /// references, as [`Dictionary::merge_resources`] expects.
+    pub fn direct_resources(&self, resources: &Dictionary) -> Dictionary {
+        let mut direct = resources.clone();
+        for (_, value) in direct.iter_mut() {
+            if let Ok((Some(_), category @ (Object::Dictionary(_) | Object::Array(_)))) = self.dereference(value) {
+                *value = category.clone();
+            }
+        }
+        direct
+    }
+
+    /// Add a resource to a page and get its name, e.g. `category` `ExtGState` for a graphics state.
+    ///
+    /// If the page already has a resource of the category equal to `object`, after following
+    /// references, its name is returned instead. New names are unique among the resources of all
+    /// categories, see [`ResourceNamer`]. A page inheriting its resources gets its own copy of the
+    /// resource dictionary first; shared resource dictionaries are changed for all their pages.
+    pub fn ensure_resource(
+        &mut self, page_id: impl Into<ObjectId>, category: &[u8], object: Object,
+    ) -> Result<Vec<u8>> {
+        let page_id = page_id.into();
+        let page = self.page(page_id)?;
+        if !page.raw().has(b"Resources") {
+            let inherited = page.resources().cloned().unwrap_or_default();
+            self.get_dictionary_mut(page_id)?.set("Resources", inherited);
+        }
+        let page = self.get_dictionary(page_id)?;
+        let resources = page.get_deref(b"Resources", self).and_then(Object::as_dict)?;
+
+        let wanted = self.dereference(&object).map(|(_, wanted)| wanted).ok();
+        if let Ok(entries) = resources.get_deref(category, self).and_then(Object::as_dict) {
+            let existing = entries.iter().find(|(_, value)| {
+                *value == &object || wanted.is_some() && self.dereference(value).ok().map(|(_, value)| value) == wanted
+            });
+            if let Some((name, _)) = existing {
+                return Ok(name.clone());
+            }
+        }
+
+        let name = ResourceNamer::new(self, resources).next_name(category_prefix(category));
+        let resources_id = page.get(b"Resources").and_then(Object::as_reference).ok();
+        let entries_id = resources.get(category).and_then(Object::as_reference).ok();
+        let resources = match (entries_id, resources_id) {
+            (Some(entries_id), _) => {
+                self.get_dictionary_mut(entries_id)?.set(name.clone(), object);
+                return Ok(name);
+            }
+            (None, Some(resources_id)) => self.get_dictionary_mut(resources_id)?,
+            (None, None) => self
+                .get_dictionary_mut(page_id)?
+                .get_mut(b"Resources")
+                .and_then(Object::as_dict_mut)?,
+        };
+        match resources.get_mut(category) {
+            Ok(Object::Dictionary(entries)) => entries.set(name.clone(), object),
+            _ => {
+                let mut entries = Dictionary::new();
+                entries.set(name.clone(), object);
+                resources.set(category, entries);
+            }
+        }
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+
+    #[test]
+    fn new_names_avoid_existing_ones() {
+        let mut doc = create_document_with_texts(&["a", "b"]);
+        let pages: Vec<ObjectId> = doc.page_iter().collect();
+        let image_id = doc.add_object(dictionary! { "Subtype" => "Image" });
+        let state_id = doc.add_object(dictionary! { "Type" => "ExtGState" });
+        let states_id = doc.add_object(dictionary! { "X1" => state_id });
+        doc.get_dictionary_mut(pages[0]).unwrap().set(
+            "Resources",
+            dictionary! {
+                "XObject" => dictionary! { "WM1" => image_id },
+                "ExtGState" => states_id,
+            },
+        );
+
+        let resources = doc.page(pages[0]).unwrap().resources().unwrap();
+        let mut namer = ResourceNamer::new(&doc, resources);
+        assert!(namer.is_used(b"WM1") && namer.is_used(b"X1"));
+        assert_eq!(namer.next_name(b"WM"), b"WM2");
+        assert_eq!(namer.next_name(b"WM"), b"WM3");
+
+        // X1 names a graphics state, so the new XObject gets another name.
+        let form_id = doc.add_object(dictionary! { "Subtype" => "Form" });
+        assert_eq!(
+            doc.ensure_resource(pages[0], b"XObject", form_id.into()).unwrap(),
+            b"X2"
+        );
+        assert_eq!(
+            doc.ensure_resource(pages[0], b"XObject", form_id.into()).unwrap(),
+            b"X2"
+        );
+        // The second page inherits the resources of the page tree, which keep their fonts.
+        assert_eq!(
+            doc.ensure_resource(pages[1], b"XObject", form_id.into()).unwrap(),
+            b"X1"
+        );
+        assert!(!doc.get_page_fonts(pages[1]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn merge_resources_renames_conflicts() {
+        let doc = create_document_with_texts(&["a"]);
+        let courier = ObjectId(100, 0);
+        let helvetica = ObjectId(101, 0);
+        let state_id = ObjectId(102, 0);
+        let mut resources = dictionary! {
+            "Font" => dictionary! { "F1" => courier },
+            "ProcSet" => vec!["PDF".into(), "Text".into()],
+        };
+        let other = dictionary! {
+            "Font" => dictionary! { "F3" => courier, "F1" => helvetica },
+            "ExtGState" => dictionary! { "F1" => state_id },
+            "ProcSet" => vec!["PDF".into(), "ImageC".into()],
+        };
+
+        // A conflict found after other resources could be merged leaves the dictionary as it was.
+        let original = resources.clone();
+        let mut namer = ResourceNamer::new(&doc, &resources);
+        let error = resources.merge_resources(&other, ConflictPolicy::Error, &mut namer);
+        assert!(matches!(error, Err(Error::ResourceConflict { .. })));
+        assert_eq!(resources, original);
+
+        let mut namer = ResourceNamer::new(&doc, &resources);
+        let renames = resources
+            .merge_resources(&other, ConflictPolicy::Rename, &mut namer)
+            .unwrap();
+        let fonts = resources.get(b"Font").and_then(Object::as_dict).unwrap();
+        assert_eq!(fonts.get(b"F1").unwrap().as_reference().unwrap(), courier);
+        assert_eq!(fonts.get(b"F2").unwrap().as_reference().unwrap(), helvetica);
+        assert_eq!(fonts.get(b"F3").unwrap().as_reference().unwrap(), courier);
+        assert_eq!(renames.get(b"Font", b"F1"), Some(b"F2".as_slice()));
+        assert_eq!(renames.get(b"ExtGState", b"F1"), None);
+        assert!(resources.get(b"ExtGState").unwrap().as_dict().unwrap().has(b"F1"));
+        let sets = resources.get(b"ProcSet").and_then(Object::as_array).unwrap();
+        assert_eq!(sets.len(), 3);
+
+        let content = b"/F1 12 Tf (F1) Tj /F1 gs /F1 Do /F3 10 Tf";
+        let mut content = crate::content::Content::decode(content).unwrap();
+        assert_eq!(renames.apply(&mut content.operations), 1);
+        let names: Vec<&[u8]> = content
+            .operations
+            .iter()
+            .filter_map(|operation| operation.operands.first()?.as_name().ok())
+            .collect();
+        assert_eq!(names, [b"F2".as_slice(), b"F1", b"F1", b"F3"]);
+    }
+
+    #[test]
+    fn direct_resources_follow_references() {
+        let mut doc = create_document_with_texts(&["a"]);
+        let page_id = doc.page_iter().next().unwrap();
+        let fonts_id = doc.add_object(dictionary! { "F9" => ObjectId(100, 0) });
+        let mut resources = doc.page(page_id).unwrap().resources().unwrap().clone();
+        resources.set("Font", fonts_id);
+
+        let mut namer = ResourceNamer::default();
+        let merged = Dictionary::new().merge_resources(&resources, ConflictPolicy::Rename, &mut namer);
+        assert!(matches!(merged, Err(Error::ObjectType { .. })));
+        let direct = doc.direct_resources(&resources);
+        assert!(direct.get(b"Font").unwrap().as_dict().unwrap().has(b"F9"));
+        let mut merged = Dictionary::new();
+        merged
+            .merge_resources(&direct, ConflictPolicy::Rename, &mut namer)
+            .unwrap();
+        assert_eq!(merged, direct);
+    }
+
+    #[test]
+    fn equal_resources_are_reused() {
+        let mut doc = create_document_with_texts(&["a"]);
+        let page_id = doc.page_iter().next().unwrap();
+        let state = || Object::Dictionary(dictionary! { "Type" => "ExtGState", "ca" => 0.5 });
+
+        let name = doc.ensure_resource(page_id, b"ExtGState", state()).unwrap();
+        assert_eq!(name, b"GS1");
+        assert_eq!(doc.ensure_resource(page_id, b"ExtGState", state()).unwrap(), name);
+        let state_id = doc.add_object(state());
+        assert_eq!(
+            doc.ensure_resource(page_id, b"ExtGState", state_id.into()).unwrap(),
+            name
+        );
+        let other = Object::Dictionary(dictionary! { "Type" => "ExtGState", "ca" => 0.25 });
+        assert_eq!(doc.ensure_resource(page_id, b"ExtGState", other).unwrap(), b"GS2");
+    }
+}
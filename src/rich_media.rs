@@ -0,0 +1,338 @@
+use crate::name_tree::NameTree;
+use crate::{Dictionary, Document, Object, ObjectId};
+use std::collections::HashSet;
+
+/// Subtypes of the annotations playing multimedia content.
+const RICH_MEDIA_SUBTYPES: [&[u8]; 3] = [b"RichMedia", b"Screen", b"3D"];
+
+/// A multimedia annotation of a page, see [`Document::find_rich_media`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RichMediaRef {
+    /// The annotation dictionary, `None` for an annotation written directly in `/Annots`.
+    pub id: Option<ObjectId>,
+    /// Subtype of the annotation: `RichMedia`, `Screen` or `3D`.
+    pub subtype: String,
+    /// The page showing the annotation.
+    pub page: ObjectId,
+    /// Streams of the content: embedded files of the `/RichMediaContent` `/Assets` name tree,
+    /// the `/3DD` stream, or the files of the media clip played by a `Screen` annotation.
+    pub streams: Vec<ObjectId>,
+}
+
+impl Document {
+    /// Find the `RichMedia`, `Screen` and `3D` annotations of all pages, which play Flash videos,
+    /// 3D models and other media, along with the streams of their content.
+    pub fn find_rich_media(&self) -> Vec<RichMediaRef> {
+        let mut found = vec![];
+        for page_id in self.page_iter() {
+            for (id, annotation) in self.get_page_annotations_with_ids(page_id).unwrap_or_default() {
+                let Some(subtype) = rich_media_subtype(annotation) else {
+                    continue;
+                };
+                let mut streams = vec![];
+                match subtype {
+                    b"RichMedia" => self.rich_media_assets(annotation, &mut streams),
+                    b"3D" => self.three_d_stream(annotation, &mut streams),
+                    _ => self.screen_media(annotation, &mut streams),
+                }
+                let mut seen = HashSet::new();
+                streams.retain(|id| seen.insert(*id));
+                found.push(RichMediaRef {
+                    id,
+                    subtype: String::from_utf8_lossy(subtype).into_owned(),
+                    page: page_id,
+                    streams,
+                });
+            }
+        }
+        found
+    }
+
+    /// Remove the annotations of [`Document::find_rich_media`] from their pages, along with the
+    /// objects only they used, such as asset streams, and return the number of bytes reclaimed,
+    /// counted as [`Document::object_sizes`] does.
+    ///
+    /// Objects still referenced from elsewhere in the document, e.g. an annotation in the
+    /// structure tree, are kept.
+    pub fn remove_rich_media(&mut self) -> usize {
+        let page_ids: Vec<ObjectId> = self.page_iter().collect();
+        let mut removed_references = vec![];
+        for page_id in page_ids {
+            let Ok(annots) = self.get_dictionary(page_id).and_then(|page| page.get(b"Annots")) else {
+                continue;
+            };
+            let Ok((annots_id, Object::Array(annots))) = self.dereference(annots) else {
+                continue;
+            };
+            let (removed, kept): (Vec<Object>, Vec<Object>) = annots.iter().cloned().partition(|annotation| {
+                self.dereference(annotation)
+                    .and_then(|(_, annotation)| annotation.as_dict())
+                    .is_ok_and(|annotation| rich_media_subtype(annotation).is_some())
+            });
+            if removed.is_empty() {
+                continue;
+            }
+            removed
+                .iter()
+                .for_each(|annotation| annotation.push_references(&mut removed_references));
+            match annots_id {
+                Some(id) => {
+                    if let Ok(annots) = self.get_object_mut(id) {
+                        *annots = Object::Array(kept);
+                    }
+                }
+                None => {
+                    if let Ok(page) = self.get_dictionary_mut(page_id) {
+                        page.set("Annots", kept);
+                    }
+                }
+            }
+        }
+
+        // Drop what the annotations used and nothing else still reaches.
+        let reachable = self.reachable_objects();
+        let mut orphans = HashSet::new();
+        while let Some(id) = removed_references.pop() {
+            if reachable.contains(&id) || !orphans.insert(id) {
+                continue;
+            }
+            if let Some(object) = self.objects.get(&id) {
+                object.push_references(&mut removed_references);
+            }
+        }
+        let reclaimed = self.object_sizes_of(orphans.iter().copied()).values().sum();
+        for id in orphans {
+            if self.objects.remove(&id).is_some() {
+                self.dirty.insert(id);
+            }
+        }
+        reclaimed
+    }
+
+    /// Push the embedded files of the `/Assets` name tree of a `RichMedia` annotation.
+    fn rich_media_assets(&self, annotation: &Dictionary, streams: &mut Vec<ObjectId>) {
+        let Ok(content) = annotation.get(b"RichMediaContent") else {
+            return;
+        };
+        let Ok((_, Object::Dictionary(content))) = self.dereference(content) else {
+            return;
+        };
+        let Ok(assets) = content.get(b"Assets") else {
+            return;
+        };
+        if let Ok(assets) = NameTree::load(self, assets) {
+            for (_, file_spec) in assets.iter() {
+                self.embedded_file_streams(file_spec, streams);
+            }
+        }
+    }
+
+    /// Push the stream of a `3D` annotation, given directly or by a 3D reference dictionary.
+    fn three_d_stream(&self, annotation: &Dictionary, streams: &mut Vec<ObjectId>) {
+        let mut object = annotation.get(b"3DD").ok();
+        // A 3D reference dictionary points to the stream of another annotation, at most a few levels deep.
+        for _ in 0..8 {
+            let Some(Ok((id, target))) = object.map(|object| self.dereference(object)) else {
+                return;
+            };
+            match target {
+                Object::Stream(_) => {
+                    streams.extend(id);
+                    return;
+                }
+                Object::Dictionary(dict) => object = dict.get(b"3DD").ok(),
+                _ => return,
+            }
+        }
+    }
+
+    /// Push the files of the media clip a `Screen` annotation plays with a rendition action.
+    fn screen_media(&self, annotation: &Dictionary, streams: &mut Vec<ObjectId>) {
+        // The action, its rendition, then the media clip.
+        let mut dict = annotation;
+        for key in [b"A".as_slice(), b"R", b"C"] {
+            match dict.get(key).and_then(|object| self.dereference(object)) {
+                Ok((_, Object::Dictionary(next))) => dict = next,
+                _ => return,
+            }
+        }
+        if let Ok(data) = dict.get(b"D") {
+            self.embedded_file_streams(data, streams);
+        }
+    }
+
+    /// Push a stream, or the embedded files of the `/EF` dictionary of a file specification.
+    fn embedded_file_streams(&self, file_spec: &Object, streams: &mut Vec<ObjectId>) {
+        match self.dereference(file_spec) {
+            Ok((Some(id), Object::Stream(_))) => streams.push(id),
+            Ok((_, Object::Dictionary(file_spec))) => {
+                let files = file_spec
+                    .get(b"EF")
+                    .and_then(|files| self.dereference(files))
+                    .and_then(|(_, files)| files.as_dict());
+                if let Ok(files) = files {
+                    for (_, file) in files.iter() {
+                        if let Ok((Some(id), Object::Stream(_))) = self.dereference(file) {
+                            streams.push(id);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// IDs of the objects the trailer refers to, directly or not.
+    fn reachable_objects(&self) -> HashSet<ObjectId> {
+        let mut pending = vec![];
+        self.trailer
+            .iter()
+            .for_each(|(_, value)| value.push_references(&mut pending));
+        let mut reachable = HashSet::new();
+        while let Some(id) = pending.pop() {
+            if !reachable.insert(id) {
+                continue;
+            }
+            if let Some(object) = self.objects.get(&id) {
+                object.push_references(&mut pending);
+            }
+        }
+        reachable
+    }
+}
+
+fn rich_media_subtype(annotation: &Dictionary) -> Option<&'static [u8]> {
+    let subtype = annotation.get(b"Subtype").and_then(Object::as_name).ok()?;
+    RICH_MEDIA_SUBTYPES.into_iter().find(|&known| known == subtype)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+    use crate::Stream;
+
+    #[test]
+    fn find_and_remove_rich_media() {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let pages = doc.get_pages();
+        let (page1, page2) = (pages[&1], pages[&2]);
+
+        let asset_id = doc.add_object(Stream::new(
+            dictionary! { "Type" => "EmbeddedFile" },
+            vec![0x5A; 1 << 20],
+        ));
+        let file_spec_id = doc.add_object(dictionary! {
+            "Type" => "Filespec",
+            "F" => Object::string_literal("movie.swf"),
+            "EF" => dictionary! { "F" => asset_id },
+        });
+        let rich_media_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "RichMedia",
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 100.into()],
+            "P" => page1,
+            "RichMediaContent" => dictionary! {
+                "Assets" => dictionary! {
+                    "Names" => vec![Object::string_literal("movie.swf"), file_spec_id.into()],
+                },
+            },
+        });
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+        });
+        let annots_id = doc.add_object(vec![rich_media_id.into(), link_id.into()]);
+        doc.get_dictionary_mut(page1).unwrap().set("Annots", annots_id);
+
+        // A 3D annotation written directly in the page, whose stream is also used elsewhere.
+        let model_id = doc.add_object(Stream::new(
+            dictionary! { "Type" => "3D", "Subtype" => "U3D" },
+            vec![1; 64],
+        ));
+        let shared_id = doc.add_object(Stream::new(
+            dictionary! { "Type" => "3D", "Subtype" => "PRC" },
+            vec![2; 64],
+        ));
+        doc.trailer.set("Shared", shared_id);
+        let three_d = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "3D",
+            "Rect" => vec![0.into(), 0.into(), 50.into(), 50.into()],
+            "3DD" => model_id,
+            "3DA" => dictionary! { "3DD" => shared_id },
+        };
+        // A Screen annotation playing an embedded movie.
+        let clip_id = doc.add_object(Stream::new(dictionary! { "Type" => "EmbeddedFile" }, vec![3; 256]));
+        let screen_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Screen",
+            "Rect" => vec![0.into(), 0.into(), 50.into(), 50.into()],
+            "A" => dictionary! {
+                "S" => "Rendition",
+                "R" => dictionary! {
+                    "S" => "MR",
+                    "C" => dictionary! {
+                        "S" => "MCD",
+                        "D" => dictionary! { "Type" => "Filespec", "EF" => dictionary! { "F" => clip_id } },
+                    },
+                },
+            },
+        });
+        doc.get_dictionary_mut(page2)
+            .unwrap()
+            .set("Annots", vec![Object::Dictionary(three_d), screen_id.into()]);
+
+        let found = doc.find_rich_media();
+        assert_eq!(
+            found,
+            [
+                RichMediaRef {
+                    id: Some(rich_media_id),
+                    subtype: "RichMedia".to_string(),
+                    page: page1,
+                    streams: vec![asset_id],
+                },
+                RichMediaRef {
+                    id: None,
+                    subtype: "3D".to_string(),
+                    page: page2,
+                    streams: vec![model_id],
+                },
+                RichMediaRef {
+                    id: Some(screen_id),
+                    subtype: "Screen".to_string(),
+                    page: page2,
+                    streams: vec![clip_id],
+                },
+            ]
+        );
+
+        let sizes = doc.object_sizes();
+        let expected = [rich_media_id, file_spec_id, asset_id, model_id, screen_id, clip_id]
+            .iter()
+            .map(|id| sizes[id])
+            .sum::<usize>();
+        let reclaimed = doc.remove_rich_media();
+        assert_eq!(reclaimed, expected);
+        assert!(reclaimed > 1 << 20);
+        assert!(doc.find_rich_media().is_empty());
+        for id in [rich_media_id, file_spec_id, asset_id, model_id, screen_id, clip_id] {
+            assert!(!doc.has_object(id), "{id:?} should be removed");
+        }
+        assert!(doc.has_object(shared_id) && doc.has_object(link_id));
+        assert_eq!(
+            doc.get_object(annots_id).unwrap().as_array().unwrap(),
+            &[link_id.into()]
+        );
+        assert!(doc.get_page_annotations(page2).unwrap().is_empty());
+
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        assert!(buffer.len() < 1 << 20);
+        let doc = Document::load_mem(&buffer).unwrap();
+        assert_eq!(doc.get_page_annotations(page1).unwrap().len(), 1);
+        assert!(doc.find_rich_media().is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+use crate::writer::{CountingWrite, Writer};
+use crate::xref::{Xref, XrefType};
+use crate::{Dictionary, Document, Object, ObjectId, SaveOptions};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+
+/// Summary of a document, see [`Document::statistics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentStats {
+    /// Version of the PDF specification given in the header.
+    pub version: String,
+    /// Number of pages in the page tree.
+    pub pages: usize,
+    /// Number of indirect objects.
+    pub objects: usize,
+    /// Number of indirect objects which are dictionaries.
+    pub dictionaries: usize,
+    /// Number of indirect objects which are streams.
+    pub streams: usize,
+    /// Number of indirect objects which are arrays.
+    pub arrays: usize,
+    /// Number of indirect objects of any other type, e.g. numbers, strings and names.
+    pub primitives: usize,
+    /// Number of font dictionaries by `/Subtype`.
+    pub fonts: BTreeMap<String, usize>,
+    /// Number of image XObjects.
+    pub images: usize,
+    /// Size in bytes of the image XObjects as stored, usually compressed.
+    pub image_bytes: usize,
+    /// Size in bytes of the content streams of the pages as stored, usually compressed.
+    pub content_bytes: usize,
+    /// The document has an `/Encrypt` dictionary.
+    pub encrypted: bool,
+    /// The document has a linearization parameter dictionary.
+    pub linearized: bool,
+    /// Kind of cross-reference section of the document.
+    pub xref_type: XrefType,
+    /// Number of revisions in the file the document was loaded from, the original one
+    /// and its incremental updates. Zero for documents which weren't loaded from a file.
+    pub revisions: usize,
+    /// The catalog has an interactive form (`/AcroForm`).
+    pub has_acroform: bool,
+    /// The catalog has a document outline (`/Outlines`).
+    pub has_outlines: bool,
+    /// Some JavaScript action or named script is present.
+    pub has_javascript: bool,
+    /// Some embedded file is present.
+    pub has_attachments: bool,
+    /// The catalog has an XMP metadata stream (`/Metadata`).
+    pub has_xmp_metadata: bool,
+}
+
+impl Document {
+    /// Summarize the document for quick profiling.
+    ///
+    /// The objects are visited once, and nothing is decompressed: stream sizes are those of the stored data.
+    pub fn statistics(&self) -> DocumentStats {
+        /// Count a dictionary by its type, returning its subtype.
+        fn count_dict<'a>(dict: &'a Dictionary, stats: &mut DocumentStats) -> Option<&'a [u8]> {
+            let subtype = dict.get(b"Subtype").and_then(Object::as_name).ok();
+            match dict.get(b"Type").and_then(Object::as_name) {
+                Ok(b"Font") => {
+                    let subtype = String::from_utf8_lossy(subtype.unwrap_or(b"Unknown")).into_owned();
+                    *stats.fonts.entry(subtype).or_default() += 1;
+                }
+                Ok(b"EmbeddedFile") => stats.has_attachments = true,
+                Ok(b"Filespec") if dict.has(b"EF") => stats.has_attachments = true,
+                _ => {}
+            }
+            stats.linearized |= dict.has(b"Linearized");
+            stats.has_javascript |= dict.has(b"JS") || dict.has(b"JavaScript");
+            stats.has_attachments |= dict.has(b"EmbeddedFiles");
+            subtype
+        }
+
+        let catalog = self.catalog().ok();
+        let has_catalog_entry = |key: &[u8]| catalog.is_some_and(|catalog| catalog.has(key));
+        let content_ids: HashSet<_> = self.page_iter().flat_map(|id| self.get_page_contents(id)).collect();
+
+        let mut stats = DocumentStats {
+            version: self.version.clone(),
+            pages: self.page_iter().count(),
+            objects: self.objects.len(),
+            dictionaries: 0,
+            streams: 0,
+            arrays: 0,
+            primitives: 0,
+            fonts: BTreeMap::new(),
+            images: 0,
+            image_bytes: 0,
+            content_bytes: 0,
+            encrypted: self.trailer.has(b"Encrypt"),
+            linearized: false,
+            xref_type: self.reference_table.cross_reference_type,
+            revisions: 0,
+            has_acroform: has_catalog_entry(b"AcroForm"),
+            has_outlines: has_catalog_entry(b"Outlines"),
+            has_javascript: false,
+            has_attachments: false,
+            has_xmp_metadata: has_catalog_entry(b"Metadata"),
+        };
+
+        for (id, object) in &self.objects {
+            match object {
+                Object::Dictionary(dict) => {
+                    stats.dictionaries += 1;
+                    count_dict(dict, &mut stats);
+                }
+                Object::Stream(stream) => {
+                    stats.streams += 1;
+                    if count_dict(&stream.dict, &mut stats) == Some(b"Image") {
+                        stats.images += 1;
+                        stats.image_bytes += stream.content.len();
+                    }
+                    if content_ids.contains(id) {
+                        stats.content_bytes += stream.content.len();
+                    }
+                }
+                Object::Array(_) => stats.arrays += 1,
+                _ => stats.primitives += 1,
+            }
+        }
+
+        // The cross-reference section of the first page of a linearized file doesn't start a revision.
+        stats.revisions = if self.load_report.reconstructed_xref {
+            self.load_report.recovered_revisions.len()
+        } else {
+            self.load_report
+                .xref_sections
+                .saturating_sub(usize::from(stats.linearized))
+        };
+        stats
+    }
+
+    /// Get the size in bytes of each indirect object as [`Document::save`] writes it, from
+    /// `obj` to `endobj`, e.g. to find what makes a file big.
+    ///
+    /// The objects are serialized one by one without being kept. Object streams, cross-reference
+    /// streams and linearization dictionaries, which are dropped when saving, aren't listed.
+    pub fn object_sizes(&self) -> BTreeMap<ObjectId, usize> {
+        self.object_sizes_of(self.objects.keys().copied())
+    }
+
+    /// Get the sizes of [`Document::object_sizes`] for the objects with the given IDs.
+    pub(crate) fn object_sizes_of(&self, ids: impl IntoIterator<Item = ObjectId>) -> BTreeMap<ObjectId, usize> {
+        let options = SaveOptions::default();
+        let mut xref = Xref::new(self.max_id + 1, self.reference_table.cross_reference_type);
+        let mut sink = io::sink();
+        let mut sizes = BTreeMap::new();
+        for id in ids {
+            let Some(object) = self.objects.get(&id) else {
+                continue;
+            };
+            let skipped = object
+                .type_name()
+                .is_ok_and(|name| [b"ObjStm".as_slice(), b"XRef", b"Linearized"].contains(&name));
+            if skipped {
+                continue;
+            }
+            let mut target = CountingWrite {
+                inner: &mut sink,
+                bytes_written: 0,
+            };
+            if Writer::write_indirect_object(&mut target, id.0, id.1, object, &mut xref, &options).is_ok() {
+                sizes.insert(id, target.bytes_written);
+            }
+        }
+        sizes
+    }
+
+    /// Get the total size of the objects of [`Document::object_sizes`] by their `/Type`, or by the
+    /// kind of object for those without a type, e.g. `Stream` for content streams.
+    pub fn object_sizes_by_type(&self) -> BTreeMap<String, usize> {
+        let mut totals = BTreeMap::new();
+        for (id, size) in self.object_sizes() {
+            let object = &self.objects[&id];
+            let kind = match object.type_name() {
+                Ok(name) => String::from_utf8_lossy(name).into_owned(),
+                Err(_) => object.enum_variant().to_string(),
+            };
+            *totals.entry(kind).or_default() += size;
+        }
+        totals
+    }
+
+    /// Get the total size of the objects of [`Document::object_sizes`] each page uses, by page number:
+    /// the page object and the objects it refers to, directly or not, such as its contents,
+    /// resources, inherited ones included, and annotations. Objects shared by pages count for each of them.
+    ///
+    /// The `/Parent` of dictionaries and other pages aren't followed.
+    pub fn object_sizes_by_page(&self) -> BTreeMap<u32, usize> {
+        let sizes = self.object_sizes();
+        let pages = self.get_pages();
+        let page_ids: HashSet<ObjectId> = pages.values().copied().collect();
+        let mut totals = BTreeMap::new();
+        for (page_number, page_id) in pages {
+            let mut seen = HashSet::from([page_id]);
+            let mut pending = vec![page_id];
+            // Resources inherited from the page tree, either shared by reference or written in a node.
+            if let Ok(page) = self.page(page_id) {
+                match (page.resources_id(), page.inherited(b"Resources")) {
+                    (Some(resources_id), _) => pending.push(resources_id),
+                    (None, Some(resources)) => resources.push_references_except(b"Parent", &mut pending),
+                    (None, None) => {}
+                }
+            }
+            seen.extend(pending.iter().copied());
+            let mut total = 0;
+            while let Some(id) = pending.pop() {
+                let Some(object) = self.objects.get(&id) else {
+                    continue;
+                };
+                total += sizes.get(&id).copied().unwrap_or(0);
+                let mut references = vec![];
+                object.push_references_except(b"Parent", &mut references);
+                pending.extend(
+                    references
+                        .into_iter()
+                        .filter(|id| !page_ids.contains(id) && seen.insert(*id)),
+                );
+            }
+            totals.insert(page_number, total);
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+
+    #[test]
+    fn statistics_of_created_document() {
+        let doc = create_document_with_texts(&["first", "second"]);
+        let stats = doc.statistics();
+        assert_eq!(stats.pages, 2);
+        assert_eq!(stats.objects, doc.objects.len());
+        assert_eq!(
+            stats.dictionaries + stats.streams + stats.arrays + stats.primitives,
+            stats.objects
+        );
+        assert_eq!(stats.streams, 2);
+        assert_eq!(stats.fonts, BTreeMap::from([("Type1".to_string(), 1)]));
+        assert_eq!(stats.images, 0);
+        let content_bytes: usize = doc
+            .page_iter()
+            .flat_map(|id| doc.get_page_contents(id))
+            .map(|id| doc.get_object(id).unwrap().as_stream().unwrap().content.len())
+            .sum();
+        assert_eq!(stats.content_bytes, content_bytes);
+        assert!(!stats.encrypted && !stats.linearized && !stats.has_outlines && !stats.has_javascript);
+        assert_eq!(stats.revisions, 0);
+    }
+
+    #[test]
+    fn object_sizes_match_serialization() {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "BaseFont" => "Courier" });
+        let sizes = doc.object_sizes();
+        assert_eq!(sizes.len(), doc.objects.len());
+        let serialized = format!("{} 0 obj\n<</Type/Font/BaseFont/Courier>>\nendobj\n", font_id.0);
+        assert_eq!(sizes[&font_id], serialized.len());
+
+        let mut buffer = vec![];
+        doc.save_to(&mut buffer).unwrap();
+        let position = buffer
+            .windows(serialized.len())
+            .position(|window| window == serialized.as_bytes());
+        assert!(position.is_some());
+        assert!(sizes.values().sum::<usize>() < buffer.len());
+
+        let by_type = doc.object_sizes_by_type();
+        assert_eq!(by_type.values().sum::<usize>(), sizes.values().sum::<usize>());
+        assert!(by_type["Font"] >= serialized.len());
+        assert!(by_type.contains_key("Stream") && by_type.contains_key("Pages"));
+
+        let by_page = doc.object_sizes_by_page();
+        assert_eq!(by_page.keys().copied().collect::<Vec<_>>(), [1, 2]);
+        let page_id = doc.get_pages()[&1];
+        let content_id = doc.get_page_contents(page_id)[0];
+        assert!(by_page[&1] > sizes[&page_id] + sizes[&content_id]);
+    }
+
+    #[test]
+    fn statistics_of_loaded_document() {
+        let doc = Document::load("assets/Incremental.pdf").unwrap();
+        let stats = doc.statistics();
+        assert_eq!(stats.version, doc.version);
+        assert_eq!(stats.pages, doc.get_pages().len());
+        assert_eq!(stats.revisions, 3);
+
+        let mut buffer = Vec::new();
+        doc.save_linearized(&mut buffer).unwrap();
+        let stats = Document::load_mem(&buffer).unwrap().statistics();
+        assert!(stats.linearized);
+        assert_eq!(stats.xref_type, XrefType::CrossReferenceTable);
+        assert_eq!(stats.revisions, 1);
+    }
+}
@@ -0,0 +1,464 @@
+#![cfg(feature = "nom_parser")]
+use crate::content::{Content, Operation};
+use crate::encodings::{self, CodedCharacterSet, Encoding};
+use crate::font_metrics::simple_font_widths;
+use crate::{Dictionary, Document, Object, ObjectId, Result};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Form XObjects nested deeper than this aren't searched for text.
+const MAX_FORM_DEPTH: usize = 16;
+
+/// A predefined single byte encoding which [`Document::normalize_text_encoding`] converts simple fonts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodingTarget {
+    WinAnsi,
+    MacRoman,
+    Standard,
+}
+
+impl EncodingTarget {
+    /// The name of the encoding for the `/Encoding` entry of a font.
+    pub fn name(self) -> &'static str {
+        match self {
+            EncodingTarget::WinAnsi => "WinAnsiEncoding",
+            EncodingTarget::MacRoman => "MacRomanEncoding",
+            EncodingTarget::Standard => "StandardEncoding",
+        }
+    }
+
+    fn code_set(self) -> &'static CodedCharacterSet {
+        match self {
+            EncodingTarget::WinAnsi => &encodings::WIN_ANSI_ENCODING,
+            EncodingTarget::MacRoman => &encodings::MAC_ROMAN_ENCODING,
+            EncodingTarget::Standard => &encodings::STANDARD_ENCODING,
+        }
+    }
+}
+
+/// The outcome of [`Document::normalize_text_encoding`] for one font.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FontNormalization {
+    /// Strings shown with the font which were re-encoded.
+    pub reencoded_strings: usize,
+    /// Characters shown with the font which the target encoding lacks. The font is left unchanged
+    /// when there are any.
+    pub unmappable_characters: usize,
+}
+
+/// The fonts converted by [`Document::normalize_text_encoding`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NormalizationReport {
+    /// The simple fonts considered, except those already using the target encoding.
+    pub fonts: BTreeMap<ObjectId, FontNormalization>,
+    /// Composite and Type 3 fonts, and fonts whose encoding can't be read, which are left unchanged.
+    pub skipped_fonts: Vec<ObjectId>,
+}
+
+/// A content stream showing text, with the fonts of its resources.
+struct TextContent {
+    target: ContentTarget,
+    fonts: BTreeMap<Vec<u8>, ObjectId>,
+    content: Content<Vec<Operation>>,
+}
+
+enum ContentTarget {
+    Page(ObjectId),
+    Form(ObjectId),
+}
+
+impl Document {
+    /// Re-encode the strings shown with simple fonts in a predefined encoding, and make the fonts use it.
+    ///
+    /// The text of the pages and of the Form XObjects of the document is converted, so text
+    /// extracted with [`Document::extract_text`] stays the same. A font whose strings have a
+    /// character missing from the target encoding is left unchanged and counted in
+    /// [`FontNormalization::unmappable_characters`]. A converted font gets the target `/Encoding`,
+    /// loses its `/ToUnicode` and has its `/Widths` moved to the new codes. Its font program must
+    /// have glyphs named as in the target encoding, as is usual for the standard Latin characters.
+    ///
+    /// Composite fonts, whose codes select glyphs by CID, and Type 3 fonts, whose glyphs are named
+    /// by their encoding, are skipped.
+    pub fn normalize_text_encoding(&mut self, target: EncodingTarget) -> Result<NormalizationReport> {
+        let mut contents = self.text_contents()?;
+        let mut report = NormalizationReport::default();
+        let font_ids: BTreeSet<ObjectId> = contents.iter().flat_map(|it| it.fonts.values().copied()).collect();
+        let mut code_maps = BTreeMap::new();
+        for id in font_ids {
+            match self.reencoding_code_map(id, target) {
+                Ok(Some(code_map)) => {
+                    code_maps.insert(id, code_map);
+                    report.fonts.insert(id, FontNormalization::default());
+                }
+                Ok(None) => {}
+                Err(_) => report.skipped_fonts.push(id),
+            }
+        }
+
+        for text_content in &mut contents {
+            for_each_shown_string(text_content, |font_id, bytes| {
+                if let (Some(code_map), Some(font)) = (code_maps.get(&font_id), report.fonts.get_mut(&font_id)) {
+                    font.reencoded_strings += 1;
+                    font.unmappable_characters +=
+                        bytes.iter().filter(|&&code| code_map[code as usize].is_none()).count();
+                }
+            });
+        }
+        code_maps.retain(|id, _| report.fonts[id].unmappable_characters == 0);
+        for font in report.fonts.values_mut().filter(|font| font.unmappable_characters > 0) {
+            font.reencoded_strings = 0;
+        }
+
+        for mut text_content in contents {
+            let mut changed = false;
+            for_each_shown_string(&mut text_content, |font_id, bytes| {
+                if let Some(code_map) = code_maps.get(&font_id) {
+                    for code in bytes.iter_mut() {
+                        *code = code_map[*code as usize].unwrap_or(*code);
+                    }
+                    changed = true;
+                }
+            });
+            if !changed {
+                continue;
+            }
+            let data = text_content.content.encode()?;
+            match text_content.target {
+                ContentTarget::Page(page_id) => self.change_page_content(page_id, data)?,
+                ContentTarget::Form(form_id) => self.change_content_stream(form_id, data),
+            }
+        }
+        for (id, code_map) in code_maps {
+            self.reencode_font(id, target, &code_map)?;
+        }
+        Ok(report)
+    }
+
+    /// Map the codes of a simple font to the codes of the same characters in `target`, `None` if
+    /// the font already uses it and an error if it can't be converted.
+    fn reencoding_code_map(&self, id: ObjectId, target: EncodingTarget) -> Result<Option<[Option<u8>; 256]>> {
+        let font = self.get_dictionary(id)?;
+        let subtype = font.get(b"Subtype").and_then(Object::as_name).unwrap_or_default();
+        if subtype == b"Type0" || subtype == b"Type3" {
+            return Err(crate::Error::Unimplemented("re-encoding of composite and Type 3 fonts"));
+        }
+        if font.get(b"Encoding").and_then(Object::as_name).ok() == Some(target.name().as_bytes()) {
+            return Ok(None);
+        }
+        // The built-in encoding of a symbolic font, used without a named encoding, isn't known.
+        let builtin_symbolic = font.get(b"Encoding").and_then(Object::as_name).is_err() && self.is_symbolic_font(font);
+        if builtin_symbolic {
+            return Err(crate::Error::Unimplemented("re-encoding of symbolic fonts"));
+        }
+        let encoding = font.get_font_encoding(self)?;
+        if let Encoding::SimpleEncoding(_) = encoding {
+            return Err(crate::Error::CharacterEncoding);
+        }
+        let mut code_map = [None; 256];
+        for (code, new_code) in code_map.iter_mut().enumerate() {
+            let Ok(text) = encoding.code_to_string(&[code as u8]) else {
+                continue;
+            };
+            let mut units = text.encode_utf16();
+            if let (Some(unit), None) = (units.next(), units.next()) {
+                *new_code = target
+                    .code_set()
+                    .iter()
+                    .position(|&it| it == Some(unit))
+                    .map(|it| it as u8);
+            }
+        }
+        Ok(Some(code_map))
+    }
+
+    fn reencode_font(&mut self, id: ObjectId, target: EncodingTarget, code_map: &[Option<u8>; 256]) -> Result<()> {
+        let font = self.get_dictionary(id)?;
+        let widths = font.has(b"Widths").then(|| {
+            let widths = simple_font_widths(font, |it| self.dereference(it).map_or(it, |(_, it)| it), 0.0).0;
+            let last_code = widths.first_char as usize + widths.widths.len();
+            let mut new_widths = BTreeMap::new();
+            for (code, new_code) in code_map.iter().enumerate() {
+                if let (Some(new_code), true) = (new_code, (widths.first_char as usize..last_code).contains(&code)) {
+                    new_widths.entry(*new_code).or_insert(widths.width(code as u8));
+                }
+            }
+            (new_widths, widths.missing_width)
+        });
+
+        let font = self.get_object_mut(id).and_then(Object::as_dict_mut)?;
+        font.set("Encoding", Object::Name(target.name().as_bytes().to_vec()));
+        font.remove(b"ToUnicode");
+        if let Some((new_widths, missing_width)) = widths {
+            if let (Some(&first), Some(&last)) = (new_widths.keys().next(), new_widths.keys().next_back()) {
+                let widths = (first..=last)
+                    .map(|code| Object::Real(new_widths.get(&code).copied().unwrap_or(missing_width)))
+                    .collect::<Vec<_>>();
+                font.set("FirstChar", first as i64);
+                font.set("LastChar", last as i64);
+                font.set("Widths", widths);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_symbolic_font(&self, font: &Dictionary) -> bool {
+        const SYMBOLIC: i64 = 1 << 2;
+        let base_font = font.get(b"BaseFont").and_then(Object::as_name).unwrap_or_default();
+        let flags = font
+            .get_deref(b"FontDescriptor", self)
+            .and_then(Object::as_dict)
+            .and_then(|descriptor| descriptor.get_deref(b"Flags", self))
+            .and_then(Object::as_i64)
+            .unwrap_or(0);
+        base_font == b"Symbol" || base_font == b"ZapfDingbats" || flags & SYMBOLIC != 0
+    }
+
+    /// Decode the content of the pages and Form XObjects of the document, with their fonts.
+    fn text_contents(&self) -> Result<Vec<TextContent>> {
+        let mut contents = vec![];
+        let mut forms = BTreeSet::new();
+        for page_id in self.page_iter() {
+            let mut fonts = BTreeMap::new();
+            let mut xobjects = BTreeMap::new();
+            let (resource_dict, resource_ids) = self.get_page_resources(page_id)?;
+            let resources = resource_dict
+                .into_iter()
+                .chain(resource_ids.into_iter().filter_map(|id| self.get_dictionary(id).ok()));
+            for resources in resources {
+                self.collect_font_ids(resources, &mut fonts);
+                self.collect_xobjects_from_resources(resources, &mut xobjects);
+            }
+            for &id in xobjects.values() {
+                self.collect_form_contents(id, &fonts, &xobjects, &mut forms, &mut contents, 0)?;
+            }
+            contents.push(TextContent {
+                target: ContentTarget::Page(page_id),
+                fonts,
+                content: self.get_and_decode_page_content(page_id)?,
+            });
+        }
+
+        // Forms which no page paints, such as annotation appearances, only use their own resources.
+        let other_forms: Vec<ObjectId> = self
+            .objects
+            .iter()
+            .filter(|(id, object)| !forms.contains(*id) && is_form(object))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in other_forms {
+            self.collect_form_contents(id, &BTreeMap::new(), &BTreeMap::new(), &mut forms, &mut contents, 0)?;
+        }
+        Ok(contents)
+    }
+
+    /// Add the content of a Form XObject and of the forms it can paint, unless already added.
+    fn collect_form_contents(
+        &self, id: ObjectId, fonts: &BTreeMap<Vec<u8>, ObjectId>, xobjects: &BTreeMap<Vec<u8>, ObjectId>,
+        forms: &mut BTreeSet<ObjectId>, contents: &mut Vec<TextContent>, depth: usize,
+    ) -> Result<()> {
+        let Ok(object) = self.get_object(id) else {
+            return Ok(());
+        };
+        if depth >= MAX_FORM_DEPTH || !is_form(object) || !forms.insert(id) {
+            return Ok(());
+        }
+        let form = object.as_stream()?;
+        // A form without resources uses those of the content painting it.
+        let resources = form
+            .dict
+            .get(b"Resources")
+            .and_then(|it| self.dereference(it))
+            .and_then(|(_, it)| it.as_dict())
+            .ok();
+        let (form_fonts, form_xobjects) = match resources {
+            Some(resources) => {
+                let mut form_fonts = BTreeMap::new();
+                let mut form_xobjects = BTreeMap::new();
+                self.collect_font_ids(resources, &mut form_fonts);
+                self.collect_xobjects_from_resources(resources, &mut form_xobjects);
+                (form_fonts, form_xobjects)
+            }
+            None => (fonts.clone(), xobjects.clone()),
+        };
+        for &xobject_id in form_xobjects.values() {
+            self.collect_form_contents(xobject_id, &form_fonts, &form_xobjects, forms, contents, depth + 1)?;
+        }
+        contents.push(TextContent {
+            target: ContentTarget::Form(id),
+            fonts: form_fonts,
+            content: Content::decode(&form.get_plain_content()?)?,
+        });
+        Ok(())
+    }
+
+    /// Add the fonts of a resource dictionary which are indirect objects and aren't in `fonts` yet.
+    fn collect_font_ids(&self, resources: &Dictionary, fonts: &mut BTreeMap<Vec<u8>, ObjectId>) {
+        if let Ok(font_dict) = self.get_dict_in_dict(resources, b"Font") {
+            for (name, value) in font_dict {
+                if let Ok(id) = value.as_reference() {
+                    fonts.entry(name.clone()).or_insert(id);
+                }
+            }
+        }
+    }
+}
+
+fn is_form(object: &Object) -> bool {
+    object
+        .as_stream()
+        .is_ok_and(|form| form.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Form"))
+}
+
+/// Call `f` with each string shown by a content stream and the font showing it.
+fn for_each_shown_string(text_content: &mut TextContent, mut f: impl FnMut(ObjectId, &mut Vec<u8>)) {
+    // The font is part of the graphics state, which is saved and restored by q and Q.
+    let mut current_font = None;
+    let mut font_stack = vec![];
+    for operation in &mut text_content.content.operations {
+        let strings: Vec<&mut Vec<u8>> = match operation.operator.as_ref() {
+            "q" => {
+                font_stack.push(current_font);
+                continue;
+            }
+            "Q" => {
+                if let Some(font) = font_stack.pop() {
+                    current_font = font;
+                }
+                continue;
+            }
+            "Tf" => {
+                let name = operation.operands.first().and_then(|it| it.as_name().ok());
+                current_font = name.and_then(|name| text_content.fonts.get(name)).copied();
+                continue;
+            }
+            "Tj" | "'" | "\"" => operation
+                .operands
+                .last_mut()
+                .and_then(|it| it.as_str_mut().ok())
+                .into_iter()
+                .collect(),
+            "TJ" => operation
+                .operands
+                .first_mut()
+                .and_then(|it| it.as_array_mut().ok())
+                .into_iter()
+                .flatten()
+                .filter_map(|it| it.as_str_mut().ok())
+                .collect(),
+            _ => continue,
+        };
+        if let Some(font_id) = current_font {
+            for bytes in strings {
+                f(font_id, bytes);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+    use crate::{FontMetrics, Stream};
+
+    #[test]
+    fn normalize_differences_font_to_win_ansi() {
+        let mut doc = create_document_with_texts(&[""]);
+        let page_id = doc.page_iter().next().unwrap();
+        let resources_id = doc.get_page_resources(page_id).unwrap().1[0];
+        let resources = doc.get_dictionary(resources_id).unwrap();
+        let font_id = doc
+            .get_dict_in_dict(resources, b"Font")
+            .unwrap()
+            .get(b"F1")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        let font = doc.get_object_mut(font_id).and_then(Object::as_dict_mut).unwrap();
+        font.set(
+            "Encoding",
+            dictionary! { "Type" => "Encoding", "Differences" => vec![1.into(), "eacute".into(), "Euro".into(), "A".into()] },
+        );
+        font.set("FirstChar", 1);
+        font.set("LastChar", 3);
+        font.set("Widths", vec![500.into(), 600.into(), 700.into()]);
+        let greek_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Times-Roman",
+            "Encoding" => dictionary! { "Differences" => vec![1.into(), "alpha".into(), "B".into()] },
+        });
+        let greek = doc.get_dictionary(greek_id).unwrap().clone();
+        let to_unicode = b"/CIDInit /ProcSet findresource begin
+12 dict begin
+begincmap
+/CMapName /Adobe-Identity-UCS def
+/CMapType 2 def
+1 begincodespacerange
+<0000> <FFFF>
+endcodespacerange
+1 beginbfrange
+<0001> <0001> <005A>
+endbfrange
+endcmap
+CMapName currentdict /CMap defineresource pop
+end
+end";
+        let to_unicode_id = doc.add_object(Stream::new(dictionary! {}, to_unicode.to_vec()));
+        let composite_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => "Arial",
+            "Encoding" => "Identity-H",
+            "ToUnicode" => to_unicode_id,
+        });
+        let form_id = doc.add_object(Stream::new(
+            dictionary! { "Type" => "XObject", "Subtype" => "Form", "BBox" => vec![0.into(), 0.into(), 100.into(), 100.into()] },
+            b"BT /F1 10 Tf 12 TL (\\003\\001) ' ET".to_vec(),
+        ));
+        let resources = doc.get_object_mut(resources_id).and_then(Object::as_dict_mut).unwrap();
+        resources.set("XObject", dictionary! { "Fm1" => form_id });
+        let fonts = resources.get_mut(b"Font").and_then(Object::as_dict_mut).unwrap();
+        fonts.set("F2", composite_id);
+        fonts.set("F3", greek_id);
+        let content_id = doc.get_page_contents(page_id)[0];
+        let content = b"BT /F1 12 Tf (\\001\\002) Tj [(\\003) -200 (\\001)] TJ /F3 12 Tf (\\001\\002) Tj /F2 12 Tf <0001> Tj ET /Fm1 Do";
+        doc.change_content_stream(content_id, content.to_vec());
+
+        let text = doc.extract_text(&[1]).unwrap();
+        assert!(text.contains("é€") && text.contains("Aé") && text.contains("αB") && text.contains('Z'));
+        let report = doc.normalize_text_encoding(EncodingTarget::WinAnsi).unwrap();
+        assert_eq!(doc.extract_text(&[1]).unwrap(), text);
+        let expected = |reencoded_strings, unmappable_characters| FontNormalization {
+            reencoded_strings,
+            unmappable_characters,
+        };
+        assert_eq!(
+            report,
+            NormalizationReport {
+                fonts: BTreeMap::from([(font_id, expected(4, 0)), (greek_id, expected(0, 1))]),
+                skipped_fonts: vec![composite_id],
+            }
+        );
+
+        let font = doc.get_dictionary(font_id).unwrap();
+        assert_eq!(font.get(b"Encoding").unwrap(), &Object::from("WinAnsiEncoding"));
+        let widths = FontMetrics::new(&doc, font).widths();
+        assert_eq!((widths.first_char, widths.widths.len()), (b'A', 0xE9 - 0x41 + 1));
+        assert_eq!(
+            [widths.width(b'A'), widths.width(0x80), widths.width(0xE9)],
+            [700.0, 600.0, 500.0]
+        );
+        assert_eq!(doc.get_dictionary(greek_id).unwrap(), &greek);
+        let form = doc.get_object(form_id).and_then(Object::as_stream).unwrap();
+        let form_content = Content::decode(&form.get_plain_content().unwrap()).unwrap();
+        assert_eq!(
+            form_content.operations[3].operands[0],
+            Object::string_literal(b"A\xE9".to_vec())
+        );
+
+        // Fonts already using the target encoding are left alone.
+        let report = doc.normalize_text_encoding(EncodingTarget::WinAnsi).unwrap();
+        assert_eq!(report.fonts.keys().collect::<Vec<_>>(), [&greek_id]);
+        assert_eq!(doc.extract_text(&[1]).unwrap(), text);
+    }
+}
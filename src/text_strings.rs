@@ -0,0 +1,325 @@
+use crate::{decode_text_string, text_string_v2, Dictionary, Document, Object, ObjectId};
+use std::collections::BTreeMap;
+
+/// Keys whose string values are text strings in whichever dictionary they are, like the titles of
+/// outline items and threads, the names and descriptions of fields and annotations, and the
+/// alternate descriptions of structure elements.
+const TEXT_KEYS: &[&[u8]] = &[
+    b"AC",
+    b"ActualText",
+    b"Alt",
+    b"Author",
+    b"CA",
+    b"ContactInfo",
+    b"Contents",
+    b"Creator",
+    b"Desc",
+    b"DS",
+    b"E",
+    b"JS",
+    b"Keywords",
+    b"Lang",
+    b"Location",
+    b"Name",
+    b"Opt",
+    b"Producer",
+    b"RC",
+    b"Reason",
+    b"Subj",
+    b"Subject",
+    b"T",
+    b"Title",
+    b"TM",
+    b"TU",
+    b"UF",
+];
+
+/// Keys whose string values are bytes or ASCII syntax rather than text, which are never mapped:
+/// file identifiers, dates, font classification bytes, default appearances and certificates.
+const BYTE_KEYS: &[&[u8]] = &[
+    b"ByteRange",
+    b"Cert",
+    b"CreationDate",
+    b"DA",
+    b"ID",
+    b"M",
+    b"ModDate",
+    b"Panose",
+];
+
+/// Whether a string object is known to be a text string, see [`TextStringContext`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringKind {
+    /// A text string, such as a document information entry, the `/Title` of an outline item or
+    /// the `/Contents` of an annotation.
+    Text,
+    /// A string whose meaning isn't known, which may be text or bytes.
+    Unknown,
+}
+
+/// Where a string was found by [`Document::map_text_strings`] or [`Document::map_string_objects`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextStringContext<'a> {
+    /// The object holding the string, which may be nested in its arrays and dictionaries.
+    pub object_id: ObjectId,
+    /// The key of the dictionary entry whose value is the string, an array holding it or a
+    /// reference to it. `None` for strings which aren't in a dictionary.
+    pub key: Option<&'a [u8]>,
+    pub kind: StringKind,
+}
+
+impl Document {
+    /// Call `f` with every text string of the document and replace the string with the text it
+    /// returns, if any. Returns the number of strings replaced.
+    ///
+    /// Strings are classified by the key they are the value of and the dictionary holding them:
+    /// the entries of the document information dictionary, outline titles, annotation and field
+    /// texts, JavaScript and the like are text strings. Byte strings, like the file identifiers and
+    /// the `/Contents` of signatures, and strings whose meaning isn't known are left alone, see
+    /// [`Document::map_string_objects`] to map the latter too.
+    ///
+    /// Text in content streams and JavaScript streams isn't mapped. Encrypted documents need to
+    /// be decrypted first, strings are replaced with ones in the encoding chosen with
+    /// [`Document::set_text_string_encoding`].
+    pub fn map_text_strings<F>(&mut self, f: F) -> usize
+    where
+        F: FnMut(TextStringContext<'_>, String) -> Option<String>,
+    {
+        self.map_strings(false, f)
+    }
+
+    /// Call `f` with every string which may be a text string, replacing it like
+    /// [`Document::map_text_strings`], including those of [`StringKind::Unknown`].
+    /// Strings known to be bytes are still left alone.
+    pub fn map_string_objects<F>(&mut self, f: F) -> usize
+    where
+        F: FnMut(TextStringContext<'_>, String) -> Option<String>,
+    {
+        self.map_strings(true, f)
+    }
+
+    fn map_strings<F>(&mut self, unknown: bool, mut f: F) -> usize
+    where
+        F: FnMut(TextStringContext<'_>, String) -> Option<String>,
+    {
+        let info = self.trailer.get(b"Info").and_then(Object::as_reference).ok();
+        let encrypt = self.trailer.get(b"Encrypt").and_then(Object::as_reference).ok();
+        let mut mapper = StringMapper {
+            unknown,
+            utf8: self.prefers_utf8_text_strings(),
+            f: &mut f,
+            replaced: 0,
+            indirect: BTreeMap::new(),
+        };
+        for (&id, object) in self.objects.iter_mut() {
+            if Some(id) == encrypt {
+                continue;
+            }
+            let replaced = mapper.replaced;
+            match object {
+                Object::Dictionary(dict) => mapper.dictionary(id, dict, Some(id) == info),
+                Object::Stream(stream) => mapper.dictionary(id, &mut stream.dict, false),
+                Object::Array(array) => {
+                    for item in array {
+                        mapper.value(id, None, Some(StringKind::Unknown), item);
+                    }
+                }
+                _ => {}
+            }
+            if mapper.replaced != replaced {
+                self.dirty.insert(id);
+            }
+        }
+
+        // Strings which are objects of their own are classified where they are referenced.
+        let indirect = std::mem::take(&mut mapper.indirect);
+        for (&id, object) in self.objects.iter_mut() {
+            if !matches!(object, Object::String(..)) {
+                continue;
+            }
+            let (key, kind) = match indirect.get(&id) {
+                Some((key, kind)) => (Some(key.as_slice()), *kind),
+                None => (None, Some(StringKind::Unknown)),
+            };
+            if let Some(kind) = kind {
+                if mapper.string(id, key, kind, object) {
+                    self.dirty.insert(id);
+                }
+            }
+        }
+        mapper.replaced
+    }
+
+    /// Whether text strings are created in UTF-8 rather than UTF-16BE.
+    pub(crate) fn prefers_utf8_text_strings(&self) -> bool {
+        self.text_string_encoding != crate::TextStringEncoding::Utf16 && self.is_version_at_least((2, 0))
+    }
+}
+
+struct StringMapper<'f, F> {
+    unknown: bool,
+    utf8: bool,
+    f: &'f mut F,
+    replaced: usize,
+    /// The key and kind of the first reference found to each object, `None` for byte strings.
+    indirect: BTreeMap<ObjectId, (Vec<u8>, Option<StringKind>)>,
+}
+
+impl<F> StringMapper<'_, F>
+where
+    F: FnMut(TextStringContext<'_>, String) -> Option<String>,
+{
+    fn dictionary(&mut self, id: ObjectId, dict: &mut Dictionary, info: bool) {
+        let signature = dict.has_type(b"Sig") || dict.has_type(b"DocTimeStamp") || dict.has(b"ByteRange");
+        let text_field = matches!(dict.get(b"FT").and_then(Object::as_name), Ok(b"Tx" | b"Ch"));
+        for (key, value) in dict.iter_mut() {
+            let kind = match key.as_slice() {
+                key if BYTE_KEYS.contains(&key) => None,
+                b"Contents" if signature => None,
+                b"V" | b"DV" if text_field => Some(StringKind::Text),
+                _ if info => Some(StringKind::Text),
+                key if TEXT_KEYS.contains(&key) => Some(StringKind::Text),
+                _ => Some(StringKind::Unknown),
+            };
+            self.value(id, Some(key), kind, value);
+        }
+    }
+
+    fn value(&mut self, id: ObjectId, key: Option<&[u8]>, kind: Option<StringKind>, object: &mut Object) {
+        match object {
+            Object::String(..) => {
+                if let Some(kind) = kind {
+                    self.string(id, key, kind, object);
+                }
+            }
+            Object::Array(array) => {
+                for item in array {
+                    self.value(id, key, kind, item);
+                }
+            }
+            Object::Dictionary(dict) => self.dictionary(id, dict, false),
+            Object::Reference(target) => {
+                if let Some(key) = key {
+                    self.indirect.entry(*target).or_insert_with(|| (key.to_vec(), kind));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Map a string, returning whether it was replaced.
+    fn string(&mut self, id: ObjectId, key: Option<&[u8]>, kind: StringKind, object: &mut Object) -> bool {
+        if kind == StringKind::Unknown && !self.unknown {
+            return false;
+        }
+        let Ok(text) = decode_text_string(object) else {
+            return false;
+        };
+        let context = TextStringContext {
+            object_id: id,
+            key,
+            kind,
+        };
+        match (self.f)(context, text) {
+            Some(text) => {
+                *object = text_string_v2(&text, self.utf8);
+                self.replaced += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+    use crate::{Bookmark, StringFormat};
+
+    #[test]
+    fn uppercase_text_strings() {
+        let mut doc = create_document_with_texts(&["Hello"]);
+        let page_id = doc.page_iter().next().unwrap();
+        let author_id = doc.add_object(Object::string_literal("someone"));
+        let info_id = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+        let info = doc.get_dictionary_mut(info_id).unwrap();
+        info.set("Title", Object::string_literal("report"));
+        info.set("Author", author_id);
+        info.set("CreationDate", Object::string_literal("D:20200102030405Z"));
+        let file_id = Object::String(b"abcdef".to_vec(), StringFormat::Hexadecimal);
+        doc.trailer.set("ID", vec![file_id.clone(), file_id.clone()]);
+
+        doc.add_bookmark(Bookmark::new("chapter one".into(), [0.0, 0.0, 1.0], 0, page_id), None);
+        let outline_id = doc.build_outline().unwrap();
+        doc.catalog_mut().unwrap().set("Outlines", outline_id);
+
+        let note_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+            "Contents" => Object::string_literal("note"),
+            "DA" => Object::string_literal("/Helv 0 Tf"),
+        });
+        let signature_bytes = Object::String(b"signature bytes".to_vec(), StringFormat::Hexadecimal);
+        let signature_id = doc.add_object(dictionary! {
+            "Type" => "Sig",
+            "Filter" => "Adobe.PPKLite",
+            "SubFilter" => "adbe.pkcs7.detached",
+            "ByteRange" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+            "Contents" => signature_bytes.clone(),
+            "Reason" => Object::string_literal("approval"),
+        });
+        let field_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Sig",
+            "T" => Object::string_literal("signature1"),
+            "V" => signature_id,
+            "Rect" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+        });
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("Annots", vec![note_id.into(), field_id.into()]);
+        page.set("Custom", Object::string_literal("unknown"));
+        doc.clear_dirty();
+
+        let replaced = doc.map_text_strings(|context, text| {
+            assert_eq!(context.kind, StringKind::Text);
+            Some(text.to_uppercase())
+        });
+        assert_eq!(replaced, 7);
+        let text = |id, key: &[u8]| decode_text_string(doc.get_dictionary(id).unwrap().get(key).unwrap()).unwrap();
+        assert_eq!(text(info_id, b"Title"), "REPORT");
+        assert_eq!(text(info_id, b"CreationDate"), "D:20200102030405Z");
+        assert_eq!(
+            decode_text_string(doc.get_object(author_id).unwrap()).unwrap(),
+            "SOMEONE"
+        );
+        assert_eq!(doc.get_toc().unwrap().toc[0].title, "CHAPTER ONE");
+        assert_eq!(text(note_id, b"Contents"), "NOTE");
+        assert_eq!(text(note_id, b"DA"), "/Helv 0 Tf");
+        assert_eq!(text(field_id, b"T"), "SIGNATURE1");
+        assert_eq!(text(signature_id, b"Reason"), "APPROVAL");
+        assert_eq!(text(page_id, b"Custom"), "unknown");
+
+        // Bytes are untouched.
+        let signature = doc.get_dictionary(signature_id).unwrap();
+        assert_eq!(signature.get(b"Contents").unwrap(), &signature_bytes);
+        assert_eq!(
+            doc.trailer.get(b"ID").unwrap(),
+            &Object::Array(vec![file_id.clone(), file_id])
+        );
+        assert!(doc.dirty_objects().contains(&signature_id));
+        assert!(!doc.dirty_objects().contains(&page_id));
+
+        // Strings of unknown meaning are only mapped on request.
+        let mut unknown = vec![];
+        doc.map_string_objects(|context, text| {
+            if context.kind == StringKind::Unknown {
+                unknown.push((context.object_id, context.key.map(<[u8]>::to_vec), text.clone()));
+            }
+            None
+        });
+        assert_eq!(unknown, [(page_id, Some(b"Custom".to_vec()), "unknown".to_string())]);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{decode_text_string, Dictionary, Document, Object, ObjectId, Result};
+use log::warn;
+use std::collections::HashSet;
+
+/// A bead of an article thread: a rectangle of a page which is read after the previous bead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArticleBead {
+    pub id: ObjectId,
+    /// The page the bead is on, `/P`.
+    pub page: Option<ObjectId>,
+    /// The area of the page, `/R`.
+    pub rect: Option<[f32; 4]>,
+}
+
+/// An article thread of the catalog `/Threads`, see [`Document::get_article_threads`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArticleThread {
+    pub id: ObjectId,
+    /// The `/Title` of the thread information dictionary `/I`.
+    pub title: Option<String>,
+    /// The `/Author` of the thread information dictionary `/I`.
+    pub author: Option<String>,
+    /// The beads in reading order, starting at the first bead `/F` and following `/N`.
+    pub beads: Vec<ArticleBead>,
+}
+
+impl Document {
+    /// Get the article threads of the document, with their beads in reading order.
+    ///
+    /// The last bead of a thread links back to the first one, the chain is followed until it does
+    /// or reaches a bead already seen. Threads which aren't indirect dictionaries are skipped.
+    pub fn get_article_threads(&self) -> Result<Vec<ArticleThread>> {
+        let mut threads = vec![];
+        for thread_id in self.article_thread_ids() {
+            let Ok(thread) = self.get_dictionary(thread_id) else {
+                warn!("Skipping article thread {thread_id:?}, which isn't a dictionary");
+                continue;
+            };
+            let info = self.get_dict_in_dict(thread, b"I").ok();
+            let info_text = |key: &[u8]| {
+                info.and_then(|info| info.get(key).ok())
+                    .and_then(|value| decode_text_string(value).ok())
+            };
+            let beads = self
+                .bead_chain(thread)
+                .into_iter()
+                .filter_map(|id| {
+                    let bead = self.get_dictionary(id).ok()?;
+                    Some(ArticleBead {
+                        id,
+                        page: bead.get(b"P").and_then(Object::as_reference).ok(),
+                        rect: bead.get_as::<[f32; 4]>(b"R").ok(),
+                    })
+                })
+                .collect();
+            threads.push(ArticleThread {
+                id: thread_id,
+                title: info_text(b"Title"),
+                author: info_text(b"Author"),
+                beads,
+            });
+        }
+        Ok(threads)
+    }
+
+    /// Remove the article threads: the catalog `/Threads`, the thread and bead objects and the
+    /// `/B` bead lists of the pages.
+    pub fn remove_article_threads(&mut self) -> Result<()> {
+        for thread_id in self.article_thread_ids() {
+            let beads = match self.get_dictionary(thread_id) {
+                Ok(thread) => self.bead_chain(thread),
+                Err(_) => vec![],
+            };
+            for id in beads.into_iter().chain([thread_id]) {
+                self.dirty.insert(id);
+                self.objects.remove(&id);
+            }
+        }
+        let pages: Vec<ObjectId> = self.page_iter().collect();
+        for page_id in pages {
+            if self.get_dictionary(page_id).is_ok_and(|page| page.has(b"B")) {
+                self.get_dictionary_mut(page_id)?.remove(b"B");
+            }
+        }
+        if let Some(Object::Reference(threads_id)) = self.catalog_mut()?.remove(b"Threads") {
+            self.dirty.insert(threads_id);
+            self.objects.remove(&threads_id);
+        }
+        Ok(())
+    }
+
+    /// Remove the beads on `page_id` from the article threads, linking their neighbors together.
+    ///
+    /// Threads left without beads are removed.
+    pub(crate) fn unlink_page_beads(&mut self, page_id: ObjectId) -> Result<()> {
+        for thread_id in self.article_thread_ids() {
+            let Ok(thread) = self.get_dictionary(thread_id) else {
+                continue;
+            };
+            let chain = self.bead_chain(thread);
+            let on_page = |id: &ObjectId| {
+                self.get_dictionary(*id)
+                    .and_then(|bead| bead.get(b"P"))
+                    .and_then(Object::as_reference)
+                    .is_ok_and(|page| page == page_id)
+            };
+            let (removed, kept): (Vec<ObjectId>, Vec<ObjectId>) = chain.into_iter().partition(on_page);
+            if removed.is_empty() {
+                continue;
+            }
+            for id in removed {
+                self.dirty.insert(id);
+                self.objects.remove(&id);
+            }
+
+            if kept.is_empty() {
+                self.dirty.insert(thread_id);
+                self.objects.remove(&thread_id);
+                let catalog = self.catalog()?;
+                let threads_id = catalog.get(b"Threads").and_then(Object::as_reference).ok();
+                let threads = match threads_id {
+                    Some(threads_id) => self.get_object_mut(threads_id)?,
+                    None => self.catalog_mut()?.get_mut(b"Threads")?,
+                };
+                if let Object::Array(threads) = threads {
+                    threads.retain(|thread| thread.as_reference().ok() != Some(thread_id));
+                }
+                continue;
+            }
+            for (index, &bead_id) in kept.iter().enumerate() {
+                let next = kept[(index + 1) % kept.len()];
+                let previous = kept[(index + kept.len() - 1) % kept.len()];
+                let bead = self.get_dictionary_mut(bead_id)?;
+                bead.set("N", next);
+                bead.set("V", previous);
+                if index == 0 {
+                    bead.set("T", thread_id);
+                }
+            }
+            self.get_dictionary_mut(thread_id)?.set("F", kept[0]);
+        }
+        Ok(())
+    }
+
+    /// The IDs of the threads listed in the catalog `/Threads`.
+    fn article_thread_ids(&self) -> Vec<ObjectId> {
+        let threads = self
+            .catalog()
+            .and_then(|catalog| catalog.get_deref(b"Threads", self))
+            .and_then(Object::as_array);
+        match threads {
+            Ok(threads) => threads.iter().filter_map(|thread| thread.as_reference().ok()).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// The IDs of the beads of a thread, from its first bead `/F` following `/N` until the chain
+    /// loops back to a bead already seen or is broken.
+    fn bead_chain(&self, thread: &Dictionary) -> Vec<ObjectId> {
+        let mut chain = vec![];
+        let mut seen = HashSet::new();
+        let mut next = thread.get(b"F").and_then(Object::as_reference).ok();
+        while let Some(id) = next.filter(|id| seen.insert(*id)) {
+            let Ok(bead) = self.get_dictionary(id) else {
+                warn!("Article thread bead {id:?} isn't a dictionary");
+                break;
+            };
+            chain.push(id);
+            next = bead.get(b"N").and_then(Object::as_reference).ok();
+        }
+        chain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+    use crate::text_string;
+
+    /// Add a thread with a bead on each of `pages`, linked in a loop.
+    fn add_thread(doc: &mut Document, title: &str, pages: &[ObjectId]) -> ObjectId {
+        let thread_id = doc.add_object(dictionary! {
+            "Type" => "Thread",
+            "I" => dictionary! { "Title" => text_string(title), "Author" => text_string("Ann") },
+        });
+        let beads: Vec<ObjectId> = pages.iter().map(|_| doc.new_object_id()).collect();
+        for (index, (&bead_id, &page_id)) in beads.iter().zip(pages).enumerate() {
+            let mut bead = dictionary! {
+                "Type" => "Bead",
+                "N" => beads[(index + 1) % beads.len()],
+                "V" => beads[(index + beads.len() - 1) % beads.len()],
+                "P" => page_id,
+                "R" => vec![0.into(), 0.into(), 100.into(), (index as i64).into()],
+            };
+            if index == 0 {
+                bead.set("T", thread_id);
+            }
+            doc.objects.insert(bead_id, Object::Dictionary(bead));
+            let page = doc.get_dictionary_mut(page_id).unwrap();
+            match page.get_mut(b"B") {
+                Ok(Object::Array(page_beads)) => page_beads.push(bead_id.into()),
+                _ => page.set("B", vec![bead_id.into()]),
+            }
+        }
+        doc.get_dictionary_mut(thread_id).unwrap().set("F", beads[0]);
+        let catalog = doc.catalog_mut().unwrap();
+        match catalog.get_mut(b"Threads") {
+            Ok(Object::Array(threads)) => threads.push(thread_id.into()),
+            _ => catalog.set("Threads", vec![thread_id.into()]),
+        }
+        thread_id
+    }
+
+    #[test]
+    fn article_threads_are_relinked_when_deleting_pages() {
+        let mut doc = create_document_with_texts(&["a", "b", "c"]);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let first = add_thread(&mut doc, "Première", &pages);
+        let second = add_thread(&mut doc, "Short", &[pages[1]]);
+
+        let threads = doc.get_article_threads().unwrap();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].id, first);
+        assert_eq!(threads[0].title.as_deref(), Some("Première"));
+        assert_eq!(threads[0].author.as_deref(), Some("Ann"));
+        let bead_pages: Vec<_> = threads[0].beads.iter().map(|bead| bead.page).collect();
+        assert_eq!(bead_pages, pages.iter().copied().map(Some).collect::<Vec<_>>());
+        assert_eq!(threads[0].beads[2].rect, Some([0.0, 0.0, 100.0, 2.0]));
+        let middle = threads[0].beads[1].id;
+
+        doc.delete_pages(&[2]);
+        assert!(doc.get_object(middle).is_err());
+        assert!(doc.get_object(second).is_err());
+        let threads = doc.get_article_threads().unwrap();
+        assert_eq!(threads.len(), 1);
+        let beads = &threads[0].beads;
+        let bead_pages: Vec<_> = beads.iter().map(|bead| bead.page).collect();
+        assert_eq!(bead_pages, [Some(pages[0]), Some(pages[2])]);
+        for (bead, other) in [(&beads[0], &beads[1]), (&beads[1], &beads[0])] {
+            let dict = doc.get_dictionary(bead.id).unwrap();
+            assert_eq!(dict.get(b"N").unwrap().as_reference().unwrap(), other.id);
+            assert_eq!(dict.get(b"V").unwrap().as_reference().unwrap(), other.id);
+        }
+
+        doc.remove_article_threads().unwrap();
+        assert!(doc.get_article_threads().unwrap().is_empty());
+        assert!(!doc.catalog().unwrap().has(b"Threads"));
+        assert!(doc.page_iter().all(|id| !doc.get_dictionary(id).unwrap().has(b"B")));
+        assert!(doc.get_object(beads[0].id).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use crate::xobject::PdfImage;
+use crate::{Dictionary, Document, Error, Object, ObjectId, Result, Stream};
+use std::collections::BTreeSet;
+
+impl Document {
+    /// Embed a thumbnail image for a page (`/Thumb` in the page dictionary).
+    ///
+    /// The image must use the `DeviceRGB` or `DeviceGray` color space, or an `Indexed` color space
+    /// based on one of them. An existing thumbnail of the page is replaced, and its stream is
+    /// removed unless it is still used by another object.
+    pub fn set_page_thumbnail(&mut self, page_id: impl Into<ObjectId>, image: Stream) -> Result<()> {
+        let page_id = page_id.into();
+        self.validate_thumbnail(&image.dict)?;
+        let old_thumbnail = match self.get_dictionary(page_id)?.get(b"Thumb") {
+            Ok(Object::Reference(id)) => Some(*id),
+            _ => None,
+        };
+
+        let thumbnail_id = self.add_object(image);
+        self.get_dictionary_mut(page_id)?.set("Thumb", thumbnail_id);
+        if let Some(id) = old_thumbnail {
+            if !self.referenced_ids().contains(&id) {
+                self.objects.remove(&id);
+                self.dirty.insert(id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the thumbnail image of a page, if it has one.
+    ///
+    /// For a thumbnail stored directly in the page dictionary, the page ID is used as image ID.
+    pub fn get_page_thumbnail(&self, page_id: impl Into<ObjectId>) -> Option<PdfImage<'_>> {
+        let page_id = page_id.into();
+        let thumbnail = self.get_dictionary(page_id).ok()?.get(b"Thumb").ok()?;
+        let (id, thumbnail) = self.dereference(thumbnail).ok()?;
+        let stream = thumbnail.as_stream().ok()?;
+        PdfImage::from_parts(id.unwrap_or(page_id), &stream.dict, &stream.content).ok()
+    }
+
+    /// Remove the thumbnail images of all pages, along with their no longer used streams.
+    pub fn remove_thumbnails(&mut self) {
+        let page_ids: Vec<ObjectId> = self.page_iter().collect();
+        let mut thumbnail_ids = vec![];
+        for page_id in page_ids {
+            if let Ok(page) = self.get_dictionary_mut(page_id) {
+                if let Some(Object::Reference(id)) = page.remove(b"Thumb") {
+                    thumbnail_ids.push(id);
+                }
+            }
+        }
+        // Streams shared by several pages, or used elsewhere, are kept.
+        let referenced = self.referenced_ids();
+        for id in thumbnail_ids {
+            if !referenced.contains(&id) {
+                self.objects.remove(&id);
+                self.dirty.insert(id);
+            }
+        }
+    }
+
+    fn validate_thumbnail(&self, dict: &Dictionary) -> Result<()> {
+        if let Ok(subtype) = dict.get(b"Subtype").and_then(Object::as_name) {
+            if subtype != b"Image" {
+                return Err(Error::InvalidThumbnail(format!(
+                    "expected an image XObject, found subtype {}",
+                    String::from_utf8_lossy(subtype)
+                )));
+            }
+        }
+        for key in ["Width", "Height"] {
+            match dict.get(key.as_bytes()).and_then(Object::as_i64) {
+                Ok(value) if value > 0 => {}
+                _ => return Err(Error::InvalidThumbnail(format!("missing or invalid {key}"))),
+            }
+        }
+
+        let is_device_color_space = |object: &Object| {
+            matches!(
+                self.dereference(object).and_then(|(_, object)| object.as_name()),
+                Ok(b"DeviceRGB" | b"DeviceGray")
+            )
+        };
+        let color_space = dict
+            .get(b"ColorSpace")
+            .and_then(|object| self.dereference(object))
+            .map_err(|_| Error::InvalidThumbnail("missing ColorSpace".to_string()))?
+            .1;
+        let valid = match color_space {
+            Object::Array(array) => {
+                matches!(array.first().map(Object::as_name), Some(Ok(b"Indexed")))
+                    && array.get(1).is_some_and(is_device_color_space)
+            }
+            _ => is_device_color_space(color_space),
+        };
+        if !valid {
+            return Err(Error::InvalidThumbnail(
+                "color space must be DeviceRGB, DeviceGray or Indexed based on them".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The IDs of the objects referred to by any object or the trailer.
+    fn referenced_ids(&self) -> BTreeSet<ObjectId> {
+        let mut references = vec![];
+        for (_, value) in self.trailer.iter() {
+            value.push_references(&mut references);
+        }
+        for object in self.objects.values() {
+            object.push_references(&mut references);
+        }
+        references.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document_with_texts;
+
+    fn thumbnail(color_space: Object) -> Stream {
+        Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 2,
+                "Height" => 2,
+                "ColorSpace" => color_space,
+                "BitsPerComponent" => 8,
+            },
+            vec![0; 12],
+        )
+    }
+
+    #[test]
+    fn set_get_and_remove_thumbnails() {
+        let mut doc = create_document_with_texts(&["first", "second"]);
+        let pages = doc.get_pages();
+        let (page1, page2) = (pages[&1], pages[&2]);
+        assert!(doc.get_page_thumbnail(page1).is_none());
+
+        doc.set_page_thumbnail(page1, thumbnail("DeviceRGB".into())).unwrap();
+        let first_id = doc.get_page_thumbnail(page1).unwrap().id;
+
+        // Replacing the thumbnail removes the old stream.
+        let gray = thumbnail("DeviceGray".into());
+        doc.set_page_thumbnail(page1, gray).unwrap();
+        let second_id = doc.get_page_thumbnail(page1).unwrap().id;
+        assert_ne!(first_id, second_id);
+        assert!(!doc.has_object(first_id));
+
+        // A thumbnail shared with another page is kept when replaced on one of them.
+        doc.get_dictionary_mut(page2).unwrap().set("Thumb", second_id);
+        let indexed = Object::Array(vec![
+            "Indexed".into(),
+            "DeviceRGB".into(),
+            1.into(),
+            Object::string_literal(vec![0; 6]),
+        ]);
+        doc.set_page_thumbnail(page1, thumbnail(indexed)).unwrap();
+        assert!(doc.has_object(second_id));
+
+        let mut buffer = Vec::new();
+        doc.save_to(&mut buffer).unwrap();
+        let mut doc = Document::load_mem(&buffer).unwrap();
+        let image = doc.get_page_thumbnail(page1).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.color_space.as_deref(), Some("Indexed"));
+        assert_eq!(doc.get_page_thumbnail(page2).unwrap().id, second_id);
+
+        let third_id = image.id;
+        doc.remove_thumbnails();
+        assert!(doc.get_page_thumbnail(page1).is_none());
+        assert!(doc.get_page_thumbnail(page2).is_none());
+        assert!(!doc.has_object(second_id));
+        assert!(!doc.has_object(third_id));
+    }
+
+    #[test]
+    fn remove_thumbnails_of_many_pages() {
+        let texts: Vec<String> = (0..200).map(|page| page.to_string()).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let mut doc = create_document_with_texts(&texts);
+        let page_ids: Vec<ObjectId> = doc.page_iter().collect();
+        for &page_id in &page_ids {
+            doc.set_page_thumbnail(page_id, thumbnail("DeviceGray".into())).unwrap();
+        }
+        // A thumbnail also used outside the pages is kept.
+        let kept_id = doc.get_page_thumbnail(page_ids[7]).unwrap().id;
+        doc.catalog_mut().unwrap().set("Logo", kept_id);
+        let object_count = doc.objects.len();
+
+        doc.remove_thumbnails();
+        assert!(page_ids
+            .iter()
+            .all(|&page_id| doc.get_page_thumbnail(page_id).is_none()));
+        assert!(doc.has_object(kept_id));
+        assert_eq!(doc.objects.len(), object_count - 199);
+    }
+
+    #[test]
+    fn reject_cmyk_thumbnail() {
+        let mut doc = create_document_with_texts(&["first"]);
+        let page_id = doc.page_iter().next().unwrap();
+        let object_count = doc.objects.len();
+
+        let result = doc.set_page_thumbnail(page_id, thumbnail("DeviceCMYK".into()));
+        assert!(matches!(result, Err(Error::InvalidThumbnail(_))));
+        let indexed_cmyk = Object::Array(vec![
+            "Indexed".into(),
+            "DeviceCMYK".into(),
+            0.into(),
+            Object::string_literal(vec![0; 4]),
+        ]);
+        assert!(doc.set_page_thumbnail(page_id, thumbnail(indexed_cmyk)).is_err());
+        assert!(doc.get_page_thumbnail(page_id).is_none());
+        assert_eq!(doc.objects.len(), object_count);
+    }
+}
@@ -3,7 +3,8 @@
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use super::{Document, Error, Object, Outline, Result};
+use super::{Document, Error, Object, ObjectId, Outline, Result};
+use crate::common_data_structures::decode_bom_less_utf16;
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
@@ -58,7 +59,7 @@ pub fn page(&self) -> Option<&Object> {
     }
 }
 
-type OutlinePageIds = IndexMap<Vec<u8>, ((u32, u16), usize, usize)>;
+type OutlinePageIds = IndexMap<Vec<u8>, (ObjectId, usize, usize)>;
 
 fn setup_outline_page_ids<'a>(
     outlines: &'a Vec<Outline>, result: &mut OutlinePageIds, level: usize,
@@ -80,7 +81,7 @@ fn setup_outline_page_ids<'a>(
 }
 
 impl Document {
-    fn setup_page_id_to_num(&self) -> IndexMap<(u32, u16), u32> {
+    fn setup_page_id_to_num(&self) -> IndexMap<ObjectId, u32> {
         let mut result = IndexMap::new();
         for (page_num, page_id) in self.get_pages() {
             result.insert(page_id, page_num);
@@ -131,6 +132,8 @@ pub fn get_toc(&self) -> Result<Toc> {
                         .map(|x| (x[1] as u16) << 8 | x[0] as u16)
                         .collect();
                     s = String::from_utf16_lossy(&t16);
+                } else if let Some(text) = decode_bom_less_utf16(&title) {
+                    s = text;
                 } else {
                     s = String::from_utf8_lossy(&title).to_string();
                 }
@@ -0,0 +1,353 @@
+use crate::{decode_text_string, text_string, Dictionary, Document, Error, Object, Result};
+
+/// Page layout to use when the document is opened (`/PageLayout` in the catalog).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageLayout {
+    SinglePage,
+    OneColumn,
+    TwoColumnLeft,
+    TwoColumnRight,
+    TwoPageLeft,
+    TwoPageRight,
+}
+
+impl PageLayout {
+    pub fn as_name(self) -> &'static [u8] {
+        match self {
+            PageLayout::SinglePage => b"SinglePage",
+            PageLayout::OneColumn => b"OneColumn",
+            PageLayout::TwoColumnLeft => b"TwoColumnLeft",
+            PageLayout::TwoColumnRight => b"TwoColumnRight",
+            PageLayout::TwoPageLeft => b"TwoPageLeft",
+            PageLayout::TwoPageRight => b"TwoPageRight",
+        }
+    }
+
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"SinglePage" => Some(PageLayout::SinglePage),
+            b"OneColumn" => Some(PageLayout::OneColumn),
+            b"TwoColumnLeft" => Some(PageLayout::TwoColumnLeft),
+            b"TwoColumnRight" => Some(PageLayout::TwoColumnRight),
+            b"TwoPageLeft" => Some(PageLayout::TwoPageLeft),
+            b"TwoPageRight" => Some(PageLayout::TwoPageRight),
+            _ => None,
+        }
+    }
+}
+
+/// How the document should be displayed when opened (`/PageMode` in the catalog).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageMode {
+    UseNone,
+    UseOutlines,
+    UseThumbs,
+    FullScreen,
+    UseOC,
+    UseAttachments,
+}
+
+impl PageMode {
+    pub fn as_name(self) -> &'static [u8] {
+        match self {
+            PageMode::UseNone => b"UseNone",
+            PageMode::UseOutlines => b"UseOutlines",
+            PageMode::UseThumbs => b"UseThumbs",
+            PageMode::FullScreen => b"FullScreen",
+            PageMode::UseOC => b"UseOC",
+            PageMode::UseAttachments => b"UseAttachments",
+        }
+    }
+
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"UseNone" => Some(PageMode::UseNone),
+            b"UseOutlines" => Some(PageMode::UseOutlines),
+            b"UseThumbs" => Some(PageMode::UseThumbs),
+            b"FullScreen" => Some(PageMode::FullScreen),
+            b"UseOC" => Some(PageMode::UseOC),
+            b"UseAttachments" => Some(PageMode::UseAttachments),
+            _ => None,
+        }
+    }
+}
+
+/// Paper handling option for printing (`/Duplex` in the viewer preferences dictionary).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplex {
+    Simplex,
+    DuplexFlipShortEdge,
+    DuplexFlipLongEdge,
+}
+
+impl Duplex {
+    pub fn as_name(self) -> &'static [u8] {
+        match self {
+            Duplex::Simplex => b"Simplex",
+            Duplex::DuplexFlipShortEdge => b"DuplexFlipShortEdge",
+            Duplex::DuplexFlipLongEdge => b"DuplexFlipLongEdge",
+        }
+    }
+
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"Simplex" => Some(Duplex::Simplex),
+            b"DuplexFlipShortEdge" => Some(Duplex::DuplexFlipShortEdge),
+            b"DuplexFlipLongEdge" => Some(Duplex::DuplexFlipLongEdge),
+            _ => None,
+        }
+    }
+}
+
+/// Commonly used viewer settings of a document.
+///
+/// `page_layout` and `page_mode` are stored directly in the catalog, the other fields
+/// in its `/ViewerPreferences` dictionary. A `None` field is left untouched when
+/// applied with [`Document::set_viewer_preferences`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ViewerPreferences {
+    pub hide_toolbar: Option<bool>,
+    pub hide_menubar: Option<bool>,
+    pub hide_window_ui: Option<bool>,
+    pub fit_window: Option<bool>,
+    pub center_window: Option<bool>,
+    pub display_doc_title: Option<bool>,
+    pub duplex: Option<Duplex>,
+    pub page_layout: Option<PageLayout>,
+    pub page_mode: Option<PageMode>,
+}
+
+const BOOLEAN_KEYS: [&[u8]; 6] = [
+    b"HideToolbar",
+    b"HideMenubar",
+    b"HideWindowUI",
+    b"FitWindow",
+    b"CenterWindow",
+    b"DisplayDocTitle",
+];
+
+impl ViewerPreferences {
+    fn booleans(&self) -> [Option<bool>; 6] {
+        [
+            self.hide_toolbar,
+            self.hide_menubar,
+            self.hide_window_ui,
+            self.fit_window,
+            self.center_window,
+            self.display_doc_title,
+        ]
+    }
+
+    fn booleans_mut(&mut self) -> [&mut Option<bool>; 6] {
+        [
+            &mut self.hide_toolbar,
+            &mut self.hide_menubar,
+            &mut self.hide_window_ui,
+            &mut self.fit_window,
+            &mut self.center_window,
+            &mut self.display_doc_title,
+        ]
+    }
+}
+
+impl Document {
+    /// Set the natural language of the document (`/Lang` in the catalog), given as a BCP 47 tag like `en-US`.
+    ///
+    /// The syntax of the tag is checked loosely, subtags aren't looked up in the registry: it must be
+    /// ASCII letters and digits, in subtags of at most 8 separated by `-`, starting with a language
+    /// of 2 to 8 letters or the `x` and `i` prefixes of private and grandfathered tags. Other tags fail
+    /// with [`Error::InvalidLanguageTag`]. An empty tag, for an unknown language, is accepted.
+    pub fn set_language(&mut self, bcp47: &str) -> Result<()> {
+        if !is_language_tag(bcp47) {
+            return Err(Error::InvalidLanguageTag(bcp47.to_string()));
+        }
+        self.catalog_mut()?.set("Lang", text_string(bcp47));
+        Ok(())
+    }
+
+    /// Get the natural language of the document from the catalog.
+    pub fn get_language(&self) -> Result<String> {
+        let lang = self.catalog()?.get(b"Lang")?;
+        decode_text_string(self.dereference(lang)?.1)
+    }
+
+    /// Read the viewer preferences, page layout and page mode of the document.
+    ///
+    /// Entries that are missing or hold unknown values are returned as `None`.
+    pub fn get_viewer_preferences(&self) -> Result<ViewerPreferences> {
+        let catalog = self.catalog()?;
+        let name_of = |dict: &Dictionary, key: &[u8]| -> Option<Vec<u8>> {
+            let value = dict.get(key).ok()?;
+            self.dereference(value).ok()?.1.as_name().ok().map(<[u8]>::to_vec)
+        };
+
+        let mut prefs = ViewerPreferences {
+            page_layout: name_of(catalog, b"PageLayout").and_then(|name| PageLayout::from_name(&name)),
+            page_mode: name_of(catalog, b"PageMode").and_then(|name| PageMode::from_name(&name)),
+            ..ViewerPreferences::default()
+        };
+        if let Ok(dict) = self.get_dict_in_dict(catalog, b"ViewerPreferences") {
+            for (key, field) in BOOLEAN_KEYS.iter().zip(prefs.booleans_mut()) {
+                *field = dict.get(key).and_then(Object::as_bool).ok();
+            }
+            prefs.duplex = name_of(dict, b"Duplex").and_then(|name| Duplex::from_name(&name));
+        }
+        Ok(prefs)
+    }
+
+    /// Apply viewer preferences to the document.
+    ///
+    /// Only fields that are `Some` are written; other entries of an existing
+    /// `/ViewerPreferences` dictionary are kept.
+    pub fn set_viewer_preferences(&mut self, prefs: &ViewerPreferences) -> Result<()> {
+        let catalog = self.catalog_mut()?;
+        if let Some(layout) = prefs.page_layout {
+            catalog.set("PageLayout", Object::Name(layout.as_name().to_vec()));
+        }
+        if let Some(mode) = prefs.page_mode {
+            catalog.set("PageMode", Object::Name(mode.as_name().to_vec()));
+        }
+
+        let mut entries = Dictionary::new();
+        for (key, value) in BOOLEAN_KEYS.iter().zip(prefs.booleans()) {
+            if let Some(value) = value {
+                entries.set(*key, value);
+            }
+        }
+        if let Some(duplex) = prefs.duplex {
+            entries.set("Duplex", Object::Name(duplex.as_name().to_vec()));
+        }
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        // The dictionary may be stored inline in the catalog or as an indirect object.
+        let target = match catalog.get(b"ViewerPreferences") {
+            Ok(Object::Reference(id)) => {
+                let id = *id;
+                self.get_dictionary_mut(id)?
+            }
+            Ok(Object::Dictionary(_)) => catalog.get_mut(b"ViewerPreferences")?.as_dict_mut()?,
+            _ => {
+                catalog.set("ViewerPreferences", Dictionary::new());
+                catalog.get_mut(b"ViewerPreferences")?.as_dict_mut()?
+            }
+        };
+        for (key, value) in entries {
+            target.set(key, value);
+        }
+        Ok(())
+    }
+}
+
+/// Loosely check the syntax of a BCP 47 language tag, see [`Document::set_language`].
+fn is_language_tag(tag: &str) -> bool {
+    if tag.is_empty() {
+        return true;
+    }
+    let subtags: Vec<&str> = tag.split('-').collect();
+    let is_subtag =
+        |subtag: &&str| (1..=8).contains(&subtag.len()) && subtag.bytes().all(|c| c.is_ascii_alphanumeric());
+    let language = subtags[0];
+    let is_prefix = language.eq_ignore_ascii_case("x") || language.eq_ignore_ascii_case("i");
+    subtags.iter().all(is_subtag)
+        && language.bytes().all(|c| c.is_ascii_alphabetic())
+        && (language.len() >= 2 || (is_prefix && subtags.len() > 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+
+    #[test]
+    fn language_round_trip() {
+        let mut doc = create_document();
+        assert!(doc.get_language().is_err());
+        doc.set_language("de-CH").unwrap();
+        assert_eq!(doc.get_language().unwrap(), "de-CH");
+
+        for valid in [
+            "",
+            "en",
+            "EN-us",
+            "zh-Hant-TW",
+            "sr-Latn-RS",
+            "es-419",
+            "x-klingon",
+            "i-navajo",
+            "de-CH-1996",
+        ] {
+            doc.set_language(valid).unwrap();
+            assert_eq!(doc.get_language().unwrap(), valid);
+        }
+        for invalid in [
+            "e",
+            "english language",
+            "en_US",
+            "en-",
+            "-en",
+            "en--US",
+            "12",
+            "x",
+            "de-CH-abcdefghi",
+            "fr-ç",
+        ] {
+            assert!(matches!(doc.set_language(invalid), Err(Error::InvalidLanguageTag(tag)) if tag == invalid));
+        }
+        assert_eq!(doc.get_language().unwrap(), "de-CH-1996");
+    }
+
+    #[test]
+    fn viewer_preferences_round_trip() {
+        let mut doc = create_document();
+        let mut existing = Dictionary::new();
+        existing.set("PrintScaling", Object::Name(b"None".to_vec()));
+        existing.set("HideToolbar", true);
+        let existing_id = doc.add_object(existing);
+        doc.catalog_mut().unwrap().set("ViewerPreferences", existing_id);
+
+        let prefs = ViewerPreferences {
+            display_doc_title: Some(true),
+            fit_window: Some(false),
+            duplex: Some(Duplex::DuplexFlipLongEdge),
+            page_layout: Some(PageLayout::TwoColumnLeft),
+            page_mode: Some(PageMode::UseOutlines),
+            ..ViewerPreferences::default()
+        };
+        doc.set_viewer_preferences(&prefs).unwrap();
+
+        let mut buffer = Vec::new();
+        doc.save_to(&mut buffer).unwrap();
+        let doc = Document::load_mem(&buffer).unwrap();
+
+        let read = doc.get_viewer_preferences().unwrap();
+        assert_eq!(
+            read,
+            ViewerPreferences {
+                hide_toolbar: Some(true),
+                ..prefs
+            }
+        );
+        // Page layout and mode belong to the catalog, not the preferences dictionary.
+        let catalog = doc.catalog().unwrap();
+        assert_eq!(catalog.get(b"PageMode").unwrap().as_name().unwrap(), b"UseOutlines");
+        let dict = doc.get_dict_in_dict(catalog, b"ViewerPreferences").unwrap();
+        assert!(!dict.has(b"PageMode"));
+        assert_eq!(dict.get(b"PrintScaling").unwrap().as_name().unwrap(), b"None");
+    }
+
+    #[test]
+    fn viewer_preferences_created_when_missing() {
+        let mut doc = create_document();
+        assert_eq!(doc.get_viewer_preferences().unwrap(), ViewerPreferences::default());
+        doc.set_viewer_preferences(&ViewerPreferences {
+            hide_menubar: Some(true),
+            ..ViewerPreferences::default()
+        })
+        .unwrap();
+        let read = doc.get_viewer_preferences().unwrap();
+        assert_eq!(read.hide_menubar, Some(true));
+        assert_eq!(read.hide_toolbar, None);
+    }
+}
@@ -1,58 +1,302 @@
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufWriter, Result, Write};
 use std::path::Path;
 use std::vec;
 
+use time::OffsetDateTime;
+
 use super::Object::*;
-use super::{Dictionary, Document, Object, Stream, StringFormat};
+use super::{Dictionary, Document, Object, ObjectId, Stream, StringFormat};
 use crate::{xref::*, IncrementalDocument};
 
+/// Options controlling how objects are serialized when saving a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveOptions {
+    /// Maximum number of digits written after the decimal point of real numbers.
+    ///
+    /// Values with more digits are rounded, then trailing zeros and a trailing decimal point are trimmed.
+    /// The default, `u8::MAX`, keeps the shortest representation that reads back to the same value.
+    pub real_precision: u8,
+    /// Write loaded streams with the same line endings around their data as in the source file,
+    /// so the data of untouched streams keeps its position relative to the `stream` keyword.
+    /// Streams without recorded line endings always use `stream\n` and `\nendstream`.
+    pub preserve_stream_eol: bool,
+    /// Write the keys of every dictionary in byte order instead of their insertion order,
+    /// without changing the dictionaries of the document.
+    pub sort_dictionary_keys: bool,
+    /// Set the `Producer` entry of the document information dictionary to this value before writing,
+    /// creating the dictionary if there is none.
+    pub update_producer: Option<std::string::String>,
+    /// How the `ModDate` entry of the document information dictionary is set before writing.
+    /// The default leaves it untouched, so saving doesn't depend on the clock.
+    pub update_mod_date: UpdatePolicy,
+    /// Rebalance the page tree before writing, with [`Document::PAGE_TREE_MAX_KIDS`] kids per node,
+    /// if the document has more pages than this, e.g. after adding pages one by one to a flat tree.
+    pub rebalance_page_tree_above: Option<usize>,
+    /// Write byte-identical output for documents with the same objects, e.g. for golden-file tests.
+    ///
+    /// The rules, on top of the other options:
+    /// - Dictionary keys are written in byte order, as with `sort_dictionary_keys`.
+    /// - Streams always use `stream\n` and `\nendstream`; `preserve_stream_eol` is ignored.
+    /// - A cross-reference table is written, whatever `xref_mode` and the cross-reference type of a loaded file.
+    /// - Objects are written in the order of their IDs, whatever `object_order`, and real numbers with the platform
+    ///   independent formatting of `real_precision`; no binary mark comment follows the header.
+    /// - Nothing depends on the clock or on randomness: saving fails with
+    ///   [`ErrorKind::InvalidInput`](std::io::ErrorKind::InvalidInput) if the trailer has no `/ID`,
+    ///   which is never generated, or if `update_mod_date` is [`UpdatePolicy::Now`].
+    ///
+    /// [`Document::minimal`] creates a document without `/Info` meeting these requirements.
+    pub canonical: bool,
+    /// Order in which the objects are written, the cross-reference entries following it.
+    pub object_order: ObjectOrder,
+    /// How the cross-reference section is written. The default, `None`, uses the cross-reference type of
+    /// the loaded file, see [`Xref::cross_reference_type`]. Incremental updates always follow the type of
+    /// the revision they update.
+    pub xref_mode: Option<XrefMode>,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        SaveOptions {
+            real_precision: u8::MAX,
+            preserve_stream_eol: false,
+            sort_dictionary_keys: false,
+            update_producer: None,
+            update_mod_date: UpdatePolicy::Never,
+            rebalance_page_tree_above: None,
+            canonical: false,
+            object_order: ObjectOrder::ById,
+            xref_mode: None,
+        }
+    }
+}
+
+/// How the cross-reference section is written, see [`SaveOptions::xref_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XrefMode {
+    /// A cross-reference table with a `trailer` dictionary, readable by any PDF reader.
+    Classic,
+    /// A cross-reference stream, which needs PDF 1.5.
+    Stream,
+    /// A hybrid-reference file: a cross-reference table covering the uncompressed objects, whose trailer
+    /// refers with `/XRefStm` to a cross-reference stream covering all of them. Readers without support for
+    /// cross-reference streams use the table and ignore the stream.
+    Hybrid,
+}
+
+/// Order in which the objects of a document are written, see [`SaveOptions::object_order`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ObjectOrder {
+    /// In the order of their IDs.
+    #[default]
+    ById,
+    /// In the order of the offsets found in the cross-reference sections when loading the document,
+    /// the objects of an object stream at the offset of the stream. Objects added since follow, in
+    /// the order of their IDs, so a document created in memory is written as with `ById`.
+    OriginalFileOrder,
+    /// Objects before the objects referring to them, except within reference cycles,
+    /// e.g. for consumers processing the file as it streams in. Ties are broken by ID.
+    DependencyFirst,
+}
+
+/// The entries of a cross-reference stream dictionary which aren't trailer entries, left in the trailer
+/// of a document loaded from a file with a cross-reference stream.
+const XREF_STREAM_KEYS: [&[u8]; 6] = [b"Type", b"W", b"Index", b"Length", b"Filter", b"DecodeParms"];
+
+/// When a date of the document information dictionary is updated on save.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpdatePolicy {
+    /// Keep the date as it is.
+    #[default]
+    Never,
+    /// Set the date to the current time.
+    Now,
+    /// Set the date to the given time, for reproducible output.
+    Fixed(OffsetDateTime),
+}
+
 impl Document {
     /// Save PDF document to specified file path.
     #[inline]
     pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<File> {
         let mut file = BufWriter::new(File::create(path)?);
-        self.save_internal(&mut file)?;
+        self.save_internal(&mut file, &SaveOptions::default())?;
         Ok(file.into_inner()?)
     }
 
     /// Save PDF to arbitrary target
     #[inline]
     pub fn save_to<W: Write>(&mut self, target: &mut W) -> Result<()> {
-        self.save_internal(target)
+        self.save_internal(target, &SaveOptions::default())
     }
 
-    fn save_internal<W: Write>(&mut self, target: &mut W) -> Result<()> {
+    /// Save PDF to arbitrary target, using the given serialization options.
+    #[inline]
+    pub fn save_with_options<W: Write>(&mut self, target: &mut W, options: SaveOptions) -> Result<()> {
+        self.save_internal(target, &options)
+    }
+
+    /// Append the objects modified since loading to `original_bytes` as an incremental update.
+    ///
+    /// `original_bytes` must be the file this document was loaded from. The update holds the
+    /// [dirty objects](Document::dirty_objects), the objects added to the document and a free entry with
+    /// the next generation number for each object of the file that was removed from the document. Edits
+    /// made directly through the `objects` field to objects of the file are not recorded and so not written.
+    /// The trailer is always written, pointing back to the cross-reference section the document was
+    /// loaded from, and the document is updated to describe the output, so it can be saved incrementally again.
+    pub fn save_incremental<W: Write>(&mut self, original_bytes: &[u8], target: &mut W) -> crate::Result<()> {
+        let prev = self.xref_start;
+        if prev == 0 || prev >= original_bytes.len() {
+            return Err(crate::Error::Xref(crate::error::XrefError::Start));
+        }
+        let options = SaveOptions::default();
         let mut target = CountingWrite {
             inner: target,
             bytes_written: 0,
         };
+        target.write_all(original_bytes)?;
+        if original_bytes.last() != Some(&b'\n') {
+            writeln!(target)?;
+        }
+
+        let present: HashSet<u32> = self.objects.keys().map(|id| id.0).collect();
+        let mut modified: Vec<ObjectId> = self
+            .objects
+            .keys()
+            .filter(|id| {
+                self.dirty_objects().contains(id)
+                    || !matches!(
+                        self.reference_table.get(id.0),
+                        Some(XrefEntry::Normal { .. } | XrefEntry::Compressed { .. })
+                    )
+            })
+            .copied()
+            .collect();
+        modified.sort_unstable();
+        self.max_id = self.max_id.max(self.reference_table.size.saturating_sub(1));
 
         let mut xref = Xref::new(self.max_id + 1, self.reference_table.cross_reference_type);
+        for (&id, entry) in &self.reference_table.entries {
+            let generation = match *entry {
+                XrefEntry::Normal { generation, .. } => generation,
+                XrefEntry::Compressed { .. } => 0,
+                _ => continue,
+            };
+            if !present.contains(&id) {
+                let generation = generation.saturating_add(1);
+                xref.insert(id, XrefEntry::Free { generation });
+            }
+        }
+        for ObjectId(id, generation) in modified {
+            let object = &self.objects[&ObjectId(id, generation)];
+            Writer::write_indirect_object(&mut target, id, generation, object, &mut xref, &options)?;
+        }
+
+        let xref_start = target.bytes_written;
+        let trailer = self.trailer.clone();
+        self.trailer.set("Prev", prev as i64);
+        self.trailer.remove(b"XRefStm");
+        for key in XREF_STREAM_KEYS {
+            self.trailer.remove(key);
+        }
+        let written = match xref.cross_reference_type {
+            XrefType::CrossReferenceTable => {
+                Writer::write_xref(&mut target, &xref).and_then(|()| self.write_trailer(&mut target, &options))
+            }
+            XrefType::CrossReferenceStream => {
+                self.write_cross_reference_stream(&mut target, &mut xref, xref_start as u32, &options)
+            }
+        };
+        self.trailer = trailer;
+        written?;
+        self.trailer.set("Size", i64::from(self.max_id + 1));
+        write!(target, "\nstartxref\n{}\n%%EOF", xref_start)?;
+
+        if xref.cross_reference_type == XrefType::CrossReferenceStream {
+            // The cross-reference stream isn't an object of the document.
+            xref.entries.remove(&self.max_id);
+        }
+        self.xref_start = xref_start;
+        self.reference_table.size = self.max_id + 1;
+        self.reference_table.entries.extend(xref.entries);
+        self.clear_dirty();
+        Ok(())
+    }
+
+    fn save_internal<W: Write>(&mut self, target: &mut W, options: &SaveOptions) -> Result<()> {
+        if options.canonical {
+            self.check_canonical(options)?;
+        }
+        self.update_info(options);
+        if let Some(threshold) = options.rebalance_page_tree_above {
+            if self.page_iter().count() > threshold {
+                self.rebalance_page_tree(Document::PAGE_TREE_MAX_KIDS)
+                    .map_err(std::io::Error::other)?;
+            }
+        }
+        let mut target = CountingWrite {
+            inner: target,
+            bytes_written: 0,
+        };
+
+        let xref_mode = match options.xref_mode {
+            _ if options.canonical => XrefMode::Classic,
+            Some(mode) => mode,
+            None => match self.reference_table.cross_reference_type {
+                XrefType::CrossReferenceTable => XrefMode::Classic,
+                XrefType::CrossReferenceStream => XrefMode::Stream,
+            },
+        };
+        let cross_reference_type = match xref_mode {
+            XrefMode::Classic | XrefMode::Hybrid => XrefType::CrossReferenceTable,
+            XrefMode::Stream => XrefType::CrossReferenceStream,
+        };
+        let mut xref = Xref::new(self.max_id + 1, cross_reference_type);
+        // The offset of the stream of a hybrid file read or saved before is stale.
+        self.trailer.remove(b"XRefStm");
+        if cross_reference_type == XrefType::CrossReferenceTable {
+            for key in XREF_STREAM_KEYS {
+                self.trailer.remove(key);
+            }
+        }
         writeln!(target, "%PDF-{}", self.version)?;
 
-        for (&(id, generation), object) in &self.objects {
+        for ObjectId(id, generation) in self.object_order(options) {
+            let object = &self.objects[&ObjectId(id, generation)];
             if object
                 .type_name()
                 .map(|name| [b"ObjStm".as_slice(), b"XRef".as_slice(), b"Linearized".as_slice()].contains(&name))
                 .ok()
                 != Some(true)
             {
-                Writer::write_indirect_object(&mut target, id, generation, object, &mut xref)?;
+                Writer::write_indirect_object(&mut target, id, generation, object, &mut xref, options)?;
             }
         }
 
-        let xref_start = target.bytes_written;
+        let mut xref_start = target.bytes_written;
 
         // Pick right cross reference stream.
-        match xref.cross_reference_type {
-            XrefType::CrossReferenceTable => {
+        match xref_mode {
+            XrefMode::Classic => {
                 Writer::write_xref(&mut target, &xref)?;
-                self.write_trailer(&mut target)?;
+                self.write_trailer(&mut target, options)?;
             }
-            XrefType::CrossReferenceStream => {
+            XrefMode::Stream => {
                 // Cross Reference Stream instead of XRef and Trailer
-                self.write_cross_reference_stream(&mut target, &mut xref, xref_start as u32)?;
+                self.write_cross_reference_stream(&mut target, &mut xref, xref_start as u32, options)?;
+            }
+            XrefMode::Hybrid => {
+                // As written by Acrobat: the stream, listed in the table like any other object, then the
+                // table, whose trailer has no `/Prev`, the stream being part of the same revision.
+                let stream_start = xref_start;
+                self.write_cross_reference_stream(&mut target, &mut xref, stream_start as u32, options)?;
+                xref_start = target.bytes_written;
+                Writer::write_xref(&mut target, &xref)?;
+                self.trailer.set("XRefStm", stream_start as i64);
+                let written = self.write_trailer(&mut target, options);
+                self.trailer.remove(b"XRefStm");
+                written?;
             }
         }
         // Write `startxref` part of trailer
@@ -66,7 +310,7 @@ fn save_internal<W: Write>(&mut self, target: &mut W) -> Result<()> {
     /// Insert an `Object` to the end of the PDF (not visible when inspecting `Document`).
     /// Note: This is different from the "Cross Reference Table".
     fn write_cross_reference_stream<W: Write>(
-        &mut self, file: &mut CountingWrite<&mut W>, xref: &mut Xref, xref_start: u32,
+        &mut self, file: &mut CountingWrite<&mut W>, xref: &mut Xref, xref_start: u32, options: &SaveOptions,
     ) -> Result<()> {
         // Increment max_id to account for CRS.
         self.max_id += 1;
@@ -74,49 +318,171 @@ fn write_cross_reference_stream<W: Write>(
         xref.insert(
             new_obj_id_for_crs,
             XrefEntry::Normal {
-                offset: xref_start,
+                offset: u64::from(xref_start),
                 generation: 0,
             },
         );
-        self.trailer.set("Type", Name(b"XRef".to_vec()));
         // Update `max_id` in trailer
         self.trailer.set("Size", i64::from(self.max_id + 1));
+        // The stream dictionary is the trailer with the entries of the stream, which the trailer of a
+        // hybrid file written after the stream doesn't get.
+        let mut dict = self.trailer.clone();
+        dict.set("Type", Name(b"XRef".to_vec()));
         // Set the size of each entry in bytes (default for PDFs is `[1 2 1]`)
         // In our case we use `[u8, u32, u16]` for each entry
         // to keep things simple and working at all times.
-        self.trailer.set("W", Array(vec![Integer(1), Integer(4), Integer(2)]));
+        dict.set("W", Array(vec![Integer(1), Integer(4), Integer(2)]));
         // Note that `ASCIIHexDecode` does not work correctly,
         // but is still useful for debugging sometimes.
         let filter = XRefStreamFilter::None;
         let (stream, stream_length, indexes) = Writer::create_xref_steam(xref, filter)?;
-        self.trailer.set("Index", indexes);
+        dict.set("Index", indexes);
 
         if filter == XRefStreamFilter::ASCIIHexDecode {
-            self.trailer.set("Filter", Name(b"ASCIIHexDecode".to_vec()));
+            dict.set("Filter", Name(b"ASCIIHexDecode".to_vec()));
         } else {
-            self.trailer.remove(b"Filter");
+            dict.remove(b"Filter");
         }
 
-        self.trailer.set("Length", stream_length as i64);
+        dict.set("Length", stream_length as i64);
 
-        let trailer = &self.trailer;
         let cross_reference_stream = Stream(Stream {
-            dict: trailer.clone(),
+            dict,
             allows_compression: true,
             content: stream,
             start_position: None,
+            original_eol: None,
         });
         // Insert Cross Reference Stream as an `Object` to the end of the PDF.
         // The `Object` is not added to `Document` because it is generated every time you save.
-        Writer::write_indirect_object(file, new_obj_id_for_crs, 0, &cross_reference_stream, xref)?;
+        Writer::write_indirect_object(file, new_obj_id_for_crs, 0, &cross_reference_stream, xref, options)?;
+
+        Ok(())
+    }
+
+    /// The IDs of the objects in the order they are written, see [`SaveOptions::object_order`].
+    fn object_order(&self, options: &SaveOptions) -> Vec<ObjectId> {
+        let mut ids: Vec<ObjectId> = self.objects.keys().copied().collect();
+        let order = if options.canonical {
+            ObjectOrder::ById
+        } else {
+            options.object_order
+        };
+        match order {
+            ObjectOrder::ById => ids,
+            ObjectOrder::OriginalFileOrder => {
+                let offset = |id: &ObjectId| match self.reference_table.get(id.0) {
+                    Some(&XrefEntry::Normal { offset, generation }) if generation == id.1 => Some((offset, 0)),
+                    Some(&XrefEntry::Compressed { container, index }) if id.1 == 0 => {
+                        match self.reference_table.get(container) {
+                            Some(&XrefEntry::Normal { offset, .. }) => Some((offset, u32::from(index) + 1)),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                // The sort is stable, so objects without offset stay in the order of their IDs.
+                ids.sort_by_cached_key(|id| {
+                    let offset = offset(id);
+                    (offset.is_none(), offset)
+                });
+                ids
+            }
+            ObjectOrder::DependencyFirst => {
+                let references = |id: ObjectId| {
+                    let mut references = vec![];
+                    self.objects[&id].push_references(&mut references);
+                    // Popped from the end, so the references are visited in the order they appear.
+                    references.reverse();
+                    references
+                };
+                let mut order = Vec::with_capacity(ids.len());
+                let mut visited = HashSet::new();
+                for root in ids {
+                    if !visited.insert(root) {
+                        continue;
+                    }
+                    // A depth-first search, writing each object once the objects it refers to are written.
+                    let mut stack = vec![(root, references(root))];
+                    while let Some((id, pending)) = stack.last_mut() {
+                        let id = *id;
+                        match pending.pop() {
+                            Some(next) => {
+                                if self.objects.contains_key(&next) && visited.insert(next) {
+                                    stack.push((next, references(next)));
+                                }
+                            }
+                            None => {
+                                order.push(id);
+                                stack.pop();
+                            }
+                        }
+                    }
+                }
+                order
+            }
+        }
+    }
 
+    /// Reject what would make a [canonical](SaveOptions::canonical) save depend on when it is made.
+    fn check_canonical(&self, options: &SaveOptions) -> Result<()> {
+        let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+        if options.update_mod_date == UpdatePolicy::Now {
+            return Err(invalid(
+                "canonical save with a modification date set to the current time",
+            ));
+        }
+        if !self.trailer.has(b"ID") {
+            return Err(invalid("canonical save of a document without a file identifier /ID"));
+        }
         Ok(())
     }
 
-    fn write_trailer(&mut self, file: &mut dyn Write) -> Result<()> {
+    /// Apply `update_producer` and `update_mod_date` to the document information dictionary.
+    /// The changes are part of what is saved, so they don't mark the dictionary as dirty.
+    fn update_info(&mut self, options: &SaveOptions) {
+        let mod_date = match options.update_mod_date {
+            UpdatePolicy::Never => None,
+            UpdatePolicy::Now => Some(OffsetDateTime::now_utc()),
+            UpdatePolicy::Fixed(date) => Some(date),
+        };
+        if options.update_producer.is_none() && mod_date.is_none() {
+            return;
+        }
+        let producer = options
+            .update_producer
+            .as_ref()
+            .map(|producer| self.encode_text_string(producer));
+
+        let info = match self.trailer.get(b"Info") {
+            Ok(Reference(id)) if self.objects.get(id).is_some_and(|info| info.as_dict().is_ok()) => {
+                let id = *id;
+                self.objects.get_mut(&id).and_then(|info| info.as_dict_mut().ok())
+            }
+            Ok(Object::Dictionary(_)) => self.trailer.get_mut(b"Info").and_then(Object::as_dict_mut).ok(),
+            _ => {
+                self.max_id += 1;
+                let id = ObjectId(self.max_id, 0);
+                self.objects.insert(id, Dictionary::new().into());
+                self.trailer.set("Info", id);
+                self.objects.get_mut(&id).and_then(|info| info.as_dict_mut().ok())
+            }
+        };
+        let Some(info) = info else {
+            return;
+        };
+        if let Some(producer) = producer {
+            info.set("Producer", producer);
+        }
+        if let Some(date) = mod_date {
+            info.set("ModDate", date.replace_nanosecond(0).unwrap_or(date));
+        }
+    }
+
+    fn write_trailer(&mut self, file: &mut dyn Write, options: &SaveOptions) -> Result<()> {
         self.trailer.set("Size", i64::from(self.max_id + 1));
         file.write_all(b"trailer\n")?;
-        Writer::write_dictionary(file, &self.trailer)?;
+        Writer::write_dictionary(file, &self.trailer, options)?;
         Ok(())
     }
 }
@@ -126,22 +492,41 @@ impl IncrementalDocument {
     #[inline]
     pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<File> {
         let mut file = BufWriter::new(File::create(path)?);
-        self.save_internal(&mut file)?;
+        self.save_internal(&mut file, &SaveOptions::default())?;
         Ok(file.into_inner()?)
     }
 
     /// Save PDF to arbitrary target
     #[inline]
     pub fn save_to<W: Write>(&mut self, target: &mut W) -> Result<()> {
-        self.save_internal(target)
+        self.save_internal(target, &SaveOptions::default())
+    }
+
+    /// Save PDF to arbitrary target, using the given serialization options.
+    #[inline]
+    pub fn save_with_options<W: Write>(&mut self, target: &mut W, options: SaveOptions) -> Result<()> {
+        self.save_internal(target, &options)
     }
 
-    fn save_internal<W: Write>(&mut self, target: &mut W) -> Result<()> {
+    fn save_internal<W: Write>(&mut self, target: &mut W, options: &SaveOptions) -> Result<()> {
         let mut target = CountingWrite {
             inner: target,
             bytes_written: 0,
         };
 
+        // An information dictionary that gets updated is written again in the new version.
+        if options.update_producer.is_some() || options.update_mod_date != UpdatePolicy::Never {
+            if let Ok(Reference(id)) = self.new_document.trailer.get(b"Info") {
+                let id = *id;
+                if !self.new_document.objects.contains_key(&id) {
+                    if let Some(info) = self.get_prev_documents().objects.get(&id).cloned() {
+                        self.new_document.objects.insert(id, info);
+                    }
+                }
+            }
+        }
+        self.new_document.update_info(options);
+
         // Write previous document versions.
         let prev_document_bytes = self.get_prev_documents_bytes();
         target.inner.write_all(prev_document_bytes)?;
@@ -161,14 +546,15 @@ fn save_internal<W: Write>(&mut self, target: &mut W) -> Result<()> {
         }
         writeln!(target, "%PDF-{}", self.new_document.version)?;
 
-        for (&(id, generation), object) in &self.new_document.objects {
+        for ObjectId(id, generation) in self.new_document.object_order(options) {
+            let object = &self.new_document.objects[&ObjectId(id, generation)];
             if object
                 .type_name()
                 .map(|name| [b"ObjStm".as_slice(), b"XRef".as_slice(), b"Linearized".as_slice()].contains(&name))
                 .ok()
                 != Some(true)
             {
-                Writer::write_indirect_object(&mut target, id, generation, object, &mut xref)?;
+                Writer::write_indirect_object(&mut target, id, generation, object, &mut xref, options)?;
             }
         }
 
@@ -178,12 +564,12 @@ fn save_internal<W: Write>(&mut self, target: &mut W) -> Result<()> {
         match xref.cross_reference_type {
             XrefType::CrossReferenceTable => {
                 Writer::write_xref(&mut target, &xref)?;
-                self.new_document.write_trailer(&mut target)?;
+                self.new_document.write_trailer(&mut target, options)?;
             }
             XrefType::CrossReferenceStream => {
                 // Cross Reference Stream instead of XRef and Trailer
                 self.new_document
-                    .write_cross_reference_stream(&mut target, &mut xref, xref_start as u32)?;
+                    .write_cross_reference_stream(&mut target, &mut xref, xref_start as u32, options)?;
             }
         }
         // Write `startxref` part of trailer
@@ -204,13 +590,16 @@ pub enum XRefStreamFilter {
 
 impl Writer {
     fn need_separator(object: &Object) -> bool {
-        matches!(*object, Null | Boolean(_) | Integer(_) | Real(_) | Reference(_))
+        matches!(
+            *object,
+            Null | Boolean(_) | Integer(_) | Real(_) | Reference(_) | RawToken(_)
+        )
     }
 
     fn need_end_separator(object: &Object) -> bool {
         matches!(
             *object,
-            Null | Boolean(_) | Integer(_) | Real(_) | Name(_) | Reference(_) | Object::Stream(_)
+            Null | Boolean(_) | Integer(_) | Real(_) | Name(_) | Reference(_) | Object::Stream(_) | RawToken(_)
         )
     }
 
@@ -238,8 +627,8 @@ fn write_xref(file: &mut dyn Write, xref: &Xref) -> Result<()> {
                     XrefEntry::Compressed { container: _, index: _ } => {
                         xref_section.add_unusable_free_entry();
                     }
-                    XrefEntry::Free => {
-                        xref_section.add_entry(XrefEntry::Free);
+                    XrefEntry::Free { generation } => {
+                        xref_section.add_entry(XrefEntry::Free { generation });
                     }
                     XrefEntry::UnusableFree => {
                         xref_section.add_unusable_free_entry();
@@ -293,14 +682,13 @@ fn create_xref_steam(xref: &Xref, filter: XRefStreamFilter) -> Result<(Vec<u8>,
             xref_index.push(Integer(section.starting_id as i64));
             xref_index.push(Integer(section.entries.len() as i64));
             // Add entries to stream
-            let mut obj_id = section.starting_id;
-            for entry in section.entries {
+            for (obj_id, entry) in (section.starting_id..).zip(section.entries) {
                 match entry {
-                    XrefEntry::Free => {
+                    XrefEntry::Free { generation } => {
                         // Type 0
                         xref_stream.push(0);
                         xref_stream.extend(obj_id.to_be_bytes());
-                        xref_stream.extend(vec![0, 0]); // TODO add generation number
+                        xref_stream.extend(generation.to_be_bytes());
                     }
                     XrefEntry::UnusableFree => {
                         // Type 0
@@ -310,6 +698,8 @@ fn create_xref_steam(xref: &Xref, filter: XRefStreamFilter) -> Result<(Vec<u8>,
                     }
                     XrefEntry::Normal { offset, generation } => {
                         // Type 1
+                        // The offset field is 4 bytes wide, see /W.
+                        let offset = u32::try_from(offset).map_err(std::io::Error::other)?;
                         xref_stream.push(1);
                         xref_stream.extend(offset.to_be_bytes());
                         xref_stream.extend(generation.to_be_bytes());
@@ -321,7 +711,6 @@ fn create_xref_steam(xref: &Xref, filter: XRefStreamFilter) -> Result<(Vec<u8>,
                         xref_stream.extend(index.to_be_bytes());
                     }
                 }
-                obj_id += 1;
             }
         }
 
@@ -338,10 +727,10 @@ fn create_xref_steam(xref: &Xref, filter: XRefStreamFilter) -> Result<(Vec<u8>,
         Ok((xref_stream, stream_length, Array(xref_index)))
     }
 
-    fn write_indirect_object<W: Write>(
-        file: &mut CountingWrite<&mut W>, id: u32, generation: u16, object: &Object, xref: &mut Xref,
+    pub(crate) fn write_indirect_object<W: Write>(
+        file: &mut CountingWrite<W>, id: u32, generation: u16, object: &Object, xref: &mut Xref, options: &SaveOptions,
     ) -> Result<()> {
-        let offset = file.bytes_written as u32;
+        let offset = file.bytes_written as u64;
         xref.insert(id, XrefEntry::Normal { offset, generation });
         write!(
             file,
@@ -350,7 +739,7 @@ fn write_indirect_object<W: Write>(
             generation,
             if Writer::need_separator(object) { " " } else { "" }
         )?;
-        Writer::write_object(file, object)?;
+        Writer::write_object(file, object, options)?;
         writeln!(
             file,
             "{}\nendobj",
@@ -359,7 +748,7 @@ fn write_indirect_object<W: Write>(
         Ok(())
     }
 
-    pub fn write_object(file: &mut dyn Write, object: &Object) -> Result<()> {
+    pub fn write_object(file: &mut dyn Write, object: &Object, options: &SaveOptions) -> Result<()> {
         match object {
             Null => file.write_all(b"null"),
             Boolean(value) => {
@@ -373,28 +762,56 @@ pub fn write_object(file: &mut dyn Write, object: &Object) -> Result<()> {
                 let mut buf = itoa::Buffer::new();
                 file.write_all(buf.format(*value).as_bytes())
             }
-            Real(value) => write!(file, "{}", value),
+            Real(value) => Writer::write_real(file, *value, options.real_precision),
             Name(name) => Writer::write_name(file, name),
             String(text, format) => Writer::write_string(file, text, format),
-            Array(array) => Writer::write_array(file, array),
-            Object::Dictionary(dict) => Writer::write_dictionary(file, dict),
-            Object::Stream(stream) => Writer::write_stream(file, stream),
+            Array(array) => Writer::write_array(file, array, options),
+            Object::Dictionary(dict) => Writer::write_dictionary(file, dict, options),
+            Object::Stream(stream) => Writer::write_stream(file, stream, options),
             Reference(id) => write!(file, "{} {} R", id.0, id.1),
+            RawToken(token) => file.write_all(token),
+        }
+    }
+
+    /// Write a real number in the fixed-point form required by the PDF syntax, never using an exponent.
+    fn write_real(file: &mut dyn Write, value: f32, precision: u8) -> Result<()> {
+        if !value.is_finite() {
+            return file.write_all(b"0");
+        }
+        // `Display` for floats never uses scientific notation.
+        let mut text = value.to_string();
+        let decimals = text.find('.').map(|point| text.len() - point - 1).unwrap_or(0);
+        if decimals > precision as usize {
+            text = format!("{:.*}", precision as usize, value);
+            if text.contains('.') {
+                let trimmed = text.trim_end_matches('0').trim_end_matches('.').len();
+                text.truncate(trimmed);
+            }
+        }
+        if text == "-0" {
+            return file.write_all(b"0");
         }
+        file.write_all(text.as_bytes())
     }
 
     fn write_name(file: &mut dyn Write, name: &[u8]) -> Result<()> {
         file.write_all(b"/")?;
-        for &byte in name {
+        // Write runs of regular characters at once.
+        let mut start = 0;
+        for (index, &byte) in name.iter().enumerate() {
             // white-space and delimiter chars are encoded to # sequences
             // also encode bytes outside of the range 33 (!) to 126 (~)
             if b" \t\n\r\x0C()<>[]{}/%#".contains(&byte) || !(33..=126).contains(&byte) {
-                write!(file, "#{:02X}", byte)?;
-            } else {
-                file.write_all(&[byte])?;
+                file.write_all(&name[start..index])?;
+                file.write_all(&[
+                    b'#',
+                    HEX_DIGITS[(byte >> 4) as usize],
+                    HEX_DIGITS[(byte & 0xF) as usize],
+                ])?;
+                start = index + 1;
             }
         }
-        Ok(())
+        file.write_all(&name[start..])
     }
 
     fn write_string(file: &mut dyn Write, text: &[u8], format: &StringFormat) -> Result<()> {
@@ -405,15 +822,14 @@ fn write_string(file: &mut dyn Write, text: &[u8], format: &StringFormat) -> Res
             // If an end-of-line marker appears within a literal string without a preceding backslash, the result is
             // equivalent to \n. So \r also need be escaped.
             StringFormat::Literal => {
+                // Positions of unbalanced parentheses, backslashes and carriage returns, in ascending order.
                 let mut escape_indice = Vec::new();
                 let mut parentheses = Vec::new();
                 for (index, &byte) in text.iter().enumerate() {
                     match byte {
                         b'(' => parentheses.push(index),
                         b')' => {
-                            if !parentheses.is_empty() {
-                                parentheses.pop();
-                            } else {
+                            if parentheses.pop().is_none() {
                                 escape_indice.push(index);
                             }
                         }
@@ -421,27 +837,31 @@ fn write_string(file: &mut dyn Write, text: &[u8], format: &StringFormat) -> Res
                         _ => continue,
                     }
                 }
-                escape_indice.append(&mut parentheses);
+                if !parentheses.is_empty() {
+                    escape_indice.append(&mut parentheses);
+                    escape_indice.sort_unstable();
+                }
 
                 file.write_all(b"(")?;
-                if !escape_indice.is_empty() {
-                    for (index, &byte) in text.iter().enumerate() {
-                        if escape_indice.contains(&index) {
-                            file.write_all(b"\\")?;
-                            file.write_all(&[if byte == b'\r' { b'r' } else { byte }])?;
-                        } else {
-                            file.write_all(&[byte])?;
-                        }
-                    }
-                } else {
-                    file.write_all(text)?;
+                let mut start = 0;
+                for index in escape_indice {
+                    file.write_all(&text[start..index])?;
+                    let byte = text[index];
+                    file.write_all(&[b'\\', if byte == b'\r' { b'r' } else { byte }])?;
+                    start = index + 1;
                 }
+                file.write_all(&text[start..])?;
                 file.write_all(b")")?;
             }
             StringFormat::Hexadecimal => {
                 file.write_all(b"<")?;
-                for &byte in text {
-                    write!(file, "{:02X}", byte)?;
+                let mut buffer = [0; 128];
+                for chunk in text.chunks(buffer.len() / 2) {
+                    for (&byte, digits) in chunk.iter().zip(buffer.chunks_mut(2)) {
+                        digits[0] = HEX_DIGITS[(byte >> 4) as usize];
+                        digits[1] = HEX_DIGITS[(byte & 0xF) as usize];
+                    }
+                    file.write_all(&buffer[..chunk.len() * 2])?;
                 }
                 file.write_all(b">")?;
             }
@@ -449,7 +869,7 @@ fn write_string(file: &mut dyn Write, text: &[u8], format: &StringFormat) -> Res
         Ok(())
     }
 
-    fn write_array(file: &mut dyn Write, array: &[Object]) -> Result<()> {
+    fn write_array(file: &mut dyn Write, array: &[Object], options: &SaveOptions) -> Result<()> {
         file.write_all(b"[")?;
         let mut first = true;
         for object in array {
@@ -458,37 +878,71 @@ fn write_array(file: &mut dyn Write, array: &[Object]) -> Result<()> {
             } else if Writer::need_separator(object) {
                 file.write_all(b" ")?;
             }
-            Writer::write_object(file, object)?;
+            Writer::write_object(file, object, options)?;
         }
         file.write_all(b"]")?;
         Ok(())
     }
 
-    fn write_dictionary(file: &mut dyn Write, dictionary: &Dictionary) -> Result<()> {
-        file.write_all(b"<<")?;
-        for (key, value) in dictionary {
+    pub(crate) fn write_dictionary(file: &mut dyn Write, dictionary: &Dictionary, options: &SaveOptions) -> Result<()> {
+        let write_entry = |file: &mut dyn Write, key: &[u8], value: &Object| {
             Writer::write_name(file, key)?;
             if Writer::need_separator(value) {
                 file.write_all(b" ")?;
             }
-            Writer::write_object(file, value)?;
+            Writer::write_object(file, value, options)
+        };
+        file.write_all(b"<<")?;
+        if options.sort_dictionary_keys || options.canonical {
+            let mut entries: Vec<_> = dictionary.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+            for (key, value) in entries {
+                write_entry(file, key, value)?;
+            }
+        } else {
+            for (key, value) in dictionary {
+                write_entry(file, key, value)?;
+            }
         }
         file.write_all(b">>")?;
         Ok(())
     }
 
-    fn write_stream(file: &mut dyn Write, stream: &Stream) -> Result<()> {
-        Writer::write_dictionary(file, &stream.dict)?;
-        file.write_all(b"stream\n")?;
-        file.write_all(&stream.content)?;
-        file.write_all(b"\nendstream")?;
+    fn write_stream(file: &mut dyn Write, stream: &Stream, options: &SaveOptions) -> Result<()> {
+        // The data may have been replaced without updating /Length.
+        let length = stream.content.len() as i64;
+        let replaced = stream.dict.get(b"Length").and_then(Object::as_i64).ok() != Some(length);
+        if !replaced {
+            Writer::write_dictionary(file, &stream.dict, options)?;
+        } else {
+            let mut dict = stream.dict.clone();
+            dict.set("Length", length);
+            Writer::write_dictionary(file, &dict, options)?;
+        }
+        // The line endings recorded for the original data don't apply to data replaced that way.
+        match &stream.original_eol {
+            Some(eol) if options.preserve_stream_eol && !options.canonical && !replaced => {
+                file.write_all(b"stream")?;
+                file.write_all(&eol.after_keyword)?;
+                file.write_all(&stream.content)?;
+                file.write_all(&eol.before_endstream)?;
+                file.write_all(b"endstream")?;
+            }
+            _ => {
+                file.write_all(b"stream\n")?;
+                file.write_all(&stream.content)?;
+                file.write_all(b"\nendstream")?;
+            }
+        }
         Ok(())
     }
 }
 
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
 pub struct CountingWrite<W: Write> {
-    inner: W,
-    bytes_written: usize,
+    pub(crate) inner: W,
+    pub(crate) bytes_written: usize,
 }
 
 impl<W: Write> Write for CountingWrite<W> {
@@ -515,30 +969,144 @@ fn flush(&mut self) -> Result<()> {
     }
 }
 
+/// Write a document object by object, for documents too large to hold in memory until they're saved.
+///
+/// Each object is written as soon as it is given, only its offset is kept, so a document builder can
+/// drop the objects it wrote, e.g. the content and the page dictionary of each page, and keep only
+/// the page tree and the catalog, which are written last with [`StreamingWriter::finish_document`].
+/// Objects are written once, in the order they are given, with a cross-reference table; an object
+/// can't be changed once written and nothing is renumbered.
+pub struct StreamingWriter<W: Write> {
+    target: CountingWrite<W>,
+    xref: Xref,
+    options: SaveOptions,
+}
+
+impl<W: Write> StreamingWriter<W> {
+    /// Start writing a PDF file of the given version to `target`.
+    pub fn new(target: W, version: &str) -> Result<Self> {
+        Self::with_options(target, version, SaveOptions::default())
+    }
+
+    /// Start writing a PDF file like [`StreamingWriter::new`], with the given serialization options.
+    ///
+    /// The options that need the whole document fail with
+    /// [`ErrorKind::InvalidInput`](std::io::ErrorKind::InvalidInput): `update_producer`,
+    /// `update_mod_date`, `rebalance_page_tree_above`, `canonical` and an `object_order` other than
+    /// by ID, the objects being written in the order they are given.
+    pub fn with_options(target: W, version: &str, options: SaveOptions) -> Result<Self> {
+        let unsupported = [
+            (options.update_producer.is_some(), "update_producer"),
+            (options.update_mod_date != UpdatePolicy::Never, "update_mod_date"),
+            (options.rebalance_page_tree_above.is_some(), "rebalance_page_tree_above"),
+            (options.canonical, "canonical"),
+            (options.object_order != ObjectOrder::ById, "object_order"),
+        ];
+        if let Some((_, option)) = unsupported.iter().find(|(set, _)| *set) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("save option {option} isn't supported when streaming objects"),
+            ));
+        }
+        let mut target = CountingWrite {
+            inner: target,
+            bytes_written: 0,
+        };
+        writeln!(target, "%PDF-{}", version)?;
+        target.write_all(b"%\xE2\xE3\xCF\xD3\n")?;
+        Ok(StreamingWriter {
+            target,
+            xref: Xref::new(1, XrefType::CrossReferenceTable),
+            options,
+        })
+    }
+
+    /// Write an object, failing with [`ErrorKind::InvalidInput`](std::io::ErrorKind::InvalidInput)
+    /// if an object with the same number was already written.
+    pub fn write_object(&mut self, id: impl Into<ObjectId>, object: &Object) -> Result<()> {
+        let id = id.into();
+        if self.xref.get(id.0).is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("object {} {} was already written", id.0, id.1),
+            ));
+        }
+        self.xref.size = self.xref.size.max(id.0 + 1);
+        Writer::write_indirect_object(&mut self.target, id.0, id.1, object, &mut self.xref, &self.options)
+    }
+
+    /// Write an object of `doc` and remove it from the document, doing nothing if there is no such object.
+    pub fn write_document_object(&mut self, doc: &mut Document, id: impl Into<ObjectId>) -> Result<()> {
+        let id = id.into();
+        match doc.objects.remove(&id) {
+            Some(object) => self.write_object(id, &object),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the remaining objects of `doc` in the order of their IDs, then finish the file with the
+    /// trailer of the document, returning the target.
+    pub fn finish_document(mut self, doc: Document) -> Result<W> {
+        for (id, object) in &doc.objects {
+            self.write_object(*id, object)?;
+        }
+        self.xref.size = self.xref.size.max(doc.max_id + 1);
+        self.finish(&doc.trailer)
+    }
+
+    /// Finish the file with the cross-reference table of the objects written and `trailer`, which
+    /// must have a `/Root`, returning the target. The `/Size` of the trailer is set.
+    pub fn finish(mut self, trailer: &Dictionary) -> Result<W> {
+        if !trailer.has(b"Root") {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "trailer without a /Root catalog",
+            ));
+        }
+        let mut trailer = trailer.clone();
+        for key in [b"Prev".as_slice(), b"XRefStm"].into_iter().chain(XREF_STREAM_KEYS) {
+            trailer.remove(key);
+        }
+        trailer.set("Size", i64::from(self.xref.size));
+
+        let xref_start = self.target.bytes_written;
+        Writer::write_xref(&mut self.target, &self.xref)?;
+        self.target.write_all(b"trailer\n")?;
+        Writer::write_dictionary(&mut self.target, &trailer, &self.options)?;
+        write!(self.target, "\nstartxref\n{}\n%%EOF", xref_start)?;
+        self.target.flush()?;
+        Ok(self.target.inner)
+    }
+}
+
 #[test]
 fn save_document() {
     let mut doc = Document::with_version("1.5");
-    doc.objects.insert((1, 0), Null);
-    doc.objects.insert((2, 0), Boolean(true));
-    doc.objects.insert((3, 0), Integer(3));
-    doc.objects.insert((4, 0), Real(0.5));
-    doc.objects
-        .insert((5, 0), String("text((\r)".as_bytes().to_vec(), StringFormat::Literal));
+    doc.objects.insert(ObjectId(1, 0), Null);
+    doc.objects.insert(ObjectId(2, 0), Boolean(true));
+    doc.objects.insert(ObjectId(3, 0), Integer(3));
+    doc.objects.insert(ObjectId(4, 0), Real(0.5));
+    doc.objects.insert(
+        ObjectId(5, 0),
+        String("text((\r)".as_bytes().to_vec(), StringFormat::Literal),
+    );
     doc.objects.insert(
-        (6, 0),
+        ObjectId(6, 0),
         String("text((\r)".as_bytes().to_vec(), StringFormat::Hexadecimal),
     );
-    doc.objects.insert((7, 0), Name(b"name \t".to_vec()));
-    doc.objects.insert((8, 0), Reference((1, 0)));
+    doc.objects.insert(ObjectId(7, 0), Name(b"name \t".to_vec()));
+    doc.objects.insert(ObjectId(8, 0), Reference(ObjectId(1, 0)));
     doc.objects
-        .insert((9, 2), Array(vec![Integer(1), Integer(2), Integer(3)]));
-    doc.objects
-        .insert((11, 0), Stream(Stream::new(Dictionary::new(), vec![0x41, 0x42, 0x43])));
+        .insert(ObjectId(9, 2), Array(vec![Integer(1), Integer(2), Integer(3)]));
+    doc.objects.insert(
+        ObjectId(11, 0),
+        Stream(Stream::new(Dictionary::new(), vec![0x41, 0x42, 0x43])),
+    );
     let mut dict = Dictionary::new();
     dict.set("A", Null);
     dict.set("B", false);
     dict.set("C", Name(b"name".to_vec()));
-    doc.objects.insert((12, 0), Object::Dictionary(dict));
+    doc.objects.insert(ObjectId(12, 0), Object::Dictionary(dict));
     doc.max_id = 12;
 
     // Create temporary folder to store file.
@@ -552,3 +1120,570 @@ fn save_document() {
     // Check if the file is above 400 bytes (should be about 610 bytes)
     assert!(file_path.metadata().unwrap().len() > 400);
 }
+
+#[cfg(test)]
+fn real_to_string(value: f32, precision: u8) -> std::string::String {
+    let mut buffer = Vec::new();
+    let options = SaveOptions {
+        real_precision: precision,
+        ..SaveOptions::default()
+    };
+    Writer::write_object(&mut buffer, &Real(value), &options).unwrap();
+    std::string::String::from_utf8(buffer).unwrap()
+}
+
+#[test]
+fn write_real_default_precision() {
+    for (value, expected) in [
+        (0.5, "0.5"),
+        (1.0, "1"),
+        (-2.25, "-2.25"),
+        (612.0, "612"),
+        (0.1, "0.1"),
+        (0.333_333_34, "0.33333334"),
+        (1e20, "100000000000000000000"),
+        (1e-7, "0.0000001"),
+    ] {
+        let mut buffer = Vec::new();
+        Writer::write_object(&mut buffer, &Real(value), &SaveOptions::default()).unwrap();
+        assert_eq!(buffer, value.to_string().as_bytes());
+        assert_eq!(real_to_string(value, u8::MAX), expected);
+    }
+}
+
+#[test]
+fn write_real_rounds_to_precision() {
+    assert_eq!(real_to_string(0.333_333_34, 2), "0.33");
+    assert_eq!(real_to_string(2.675_8, 2), "2.68");
+    assert_eq!(real_to_string(1.999_9, 3), "2");
+    assert_eq!(real_to_string(0.6, 0), "1");
+    assert_eq!(real_to_string(12.3, 0), "12");
+    assert_eq!(real_to_string(1.100_01, 2), "1.1");
+    assert_eq!(real_to_string(1e-7, 4), "0");
+    assert_eq!(real_to_string(1e20, 2), "100000000000000000000");
+}
+
+#[test]
+fn write_real_normalizes_negative_zero() {
+    assert_eq!(real_to_string(-0.0, u8::MAX), "0");
+    assert_eq!(real_to_string(-0.000_1, 2), "0");
+    assert_eq!(real_to_string(-0.004, 2), "0");
+    assert_eq!(real_to_string(-0.006, 2), "-0.01");
+    assert_eq!(real_to_string(f32::NAN, 2), "0");
+}
+
+#[test]
+fn save_with_real_precision() {
+    let mut doc = Document::with_version("1.5");
+    doc.objects
+        .insert(ObjectId(1, 0), Array(vec![Real(0.123_456), Real(-1e-9), Real(3.5)]));
+    doc.max_id = 1;
+
+    let mut default_output = Vec::new();
+    doc.clone().save_to(&mut default_output).unwrap();
+    let mut explicit_default = Vec::new();
+    doc.clone()
+        .save_with_options(&mut explicit_default, SaveOptions::default())
+        .unwrap();
+    assert_eq!(default_output, explicit_default);
+
+    let mut rounded = Vec::new();
+    doc.save_with_options(
+        &mut rounded,
+        SaveOptions {
+            real_precision: 3,
+            ..SaveOptions::default()
+        },
+    )
+    .unwrap();
+    let rounded = std::string::String::from_utf8_lossy(&rounded);
+    assert!(rounded.contains("[0.123 0 3.5]"));
+
+    let content = crate::content::Content {
+        operations: vec![crate::content::Operation::new(
+            "cm",
+            vec![Real(0.866_025_4), Real(-0.866_025_4), Integer(0)],
+        )],
+    };
+    let encoded = content
+        .encode_with_options(&SaveOptions {
+            real_precision: 2,
+            ..SaveOptions::default()
+        })
+        .unwrap();
+    assert_eq!(encoded, b"0.87 -0.87 0 cm");
+}
+
+#[test]
+fn save_preserves_stream_eol() {
+    use crate::StreamEol;
+
+    let mut doc = Document::with_version("1.5");
+    let mut stream = Stream::new(Dictionary::new(), b"ABC".to_vec());
+    stream.original_eol = Some(StreamEol {
+        after_keyword: b"\r\n".to_vec(),
+        before_endstream: Vec::new(),
+    });
+    doc.objects.insert(ObjectId(1, 0), Stream(stream));
+    doc.max_id = 1;
+    let options = SaveOptions {
+        preserve_stream_eol: true,
+        ..SaveOptions::default()
+    };
+
+    let mut first = Vec::new();
+    doc.save_with_options(&mut first, options.clone()).unwrap();
+    let data_offset = |bytes: &[u8]| bytes.windows(3).position(|w| w == b"ABC").unwrap();
+    assert!(first.windows(20).any(|w| w == b"stream\r\nABCendstream"));
+
+    // The recorded line endings survive a load/save cycle, so the stream data stays in place.
+    let mut loaded = Document::load_mem(&first).unwrap();
+    let eol = loaded
+        .get_object(ObjectId(1, 0))
+        .unwrap()
+        .as_stream()
+        .unwrap()
+        .original_eol
+        .clone();
+    assert_eq!(eol.unwrap().after_keyword, b"\r\n");
+    let mut second = Vec::new();
+    loaded.clone().save_with_options(&mut second, options.clone()).unwrap();
+    assert_eq!(data_offset(&first), data_offset(&second));
+
+    // By default streams are normalized to LF, shifting the data.
+    let mut normalized = Vec::new();
+    loaded.save_to(&mut normalized).unwrap();
+    assert_eq!(data_offset(&normalized) + 1, data_offset(&first));
+
+    // Every change of the data through the methods of Stream drops the recorded line endings.
+    fn crlf() -> StreamEol {
+        StreamEol {
+            after_keyword: b"\r\n".to_vec(),
+            before_endstream: Vec::new(),
+        }
+    }
+    let original = loaded.get_object(ObjectId(1, 0)).unwrap().as_stream().unwrap().clone();
+    let changes: [fn(&mut Stream); 5] = [
+        |stream| stream.set_content(b"DEF".to_vec()),
+        |stream| stream.set_plain_content(b"DEF".to_vec()),
+        |stream| stream.set_filters(&[b"ASCIIHexDecode"]).unwrap(),
+        |stream| {
+            stream.set_content(b"DEF".repeat(100));
+            stream.original_eol = Some(crlf());
+            stream.compress().unwrap();
+        },
+        |stream| {
+            stream.set_filters(&[b"ASCII85Decode"]).unwrap();
+            stream.original_eol = Some(crlf());
+            stream.decompress().unwrap();
+        },
+    ];
+    for change in changes {
+        let mut stream = original.clone();
+        change(&mut stream);
+        assert!(stream.original_eol.is_none());
+    }
+
+    // Data replaced directly, leaving /Length stale, is written with the default line endings.
+    let stream = loaded.get_object_mut(ObjectId(1, 0)).unwrap().as_stream_mut().unwrap();
+    stream.content = b"DEFG".to_vec();
+    let mut replaced = Vec::new();
+    loaded.save_with_options(&mut replaced, options).unwrap();
+    assert!(replaced.windows(21).any(|w| w == b"stream\nDEFG\nendstream"));
+}
+
+#[test]
+fn save_preserves_dictionary_key_order() {
+    fn keys(object: &Object) -> Option<Vec<Vec<u8>>> {
+        match object {
+            Object::Dictionary(dict) => Some(dict.iter().map(|(key, _)| key.clone()).collect()),
+            Object::Stream(stream) => Some(stream.dict.iter().map(|(key, _)| key.clone()).collect()),
+            _ => None,
+        }
+    }
+
+    let mut doc = Document::load("assets/AnnotationDemo.pdf").unwrap();
+    // Removing a key keeps the order of the others.
+    let (&id, _) = doc
+        .objects
+        .iter()
+        .find(|(_, object)| keys(object).is_some_and(|keys| keys.len() > 3))
+        .unwrap();
+    let dict = doc.get_dictionary_mut(id).unwrap();
+    let mut expected: Vec<Vec<u8>> = dict.iter().map(|(key, _)| key.clone()).collect();
+    let removed = expected.remove(1);
+    dict.remove(&removed);
+    assert_eq!(keys(doc.get_object(id).unwrap()).unwrap(), expected);
+
+    let mut buffer = Vec::new();
+    doc.save_to(&mut buffer).unwrap();
+    let reloaded = Document::load_mem(&buffer).unwrap();
+    for (id, object) in &doc.objects {
+        if let (Some(original), Ok(saved)) = (keys(object), reloaded.get_object(*id)) {
+            assert_eq!(keys(saved).unwrap(), original, "keys of {id:?}");
+        }
+    }
+
+    let mut buffer = Vec::new();
+    let options = SaveOptions {
+        sort_dictionary_keys: true,
+        ..SaveOptions::default()
+    };
+    doc.save_with_options(&mut buffer, options).unwrap();
+    let reloaded = Document::load_mem(&buffer).unwrap();
+    assert!(reloaded
+        .objects
+        .values()
+        .filter_map(keys)
+        .all(|keys| keys.windows(2).all(|pair| pair[0] <= pair[1])));
+
+    let mut dict = dictionary! { "Type" => "Page", "Contents" => 1, "MediaBox" => 2 };
+    dict.sort_keys();
+    let sorted: Vec<&[u8]> = dict.iter().map(|(key, _)| key.as_slice()).collect();
+    assert_eq!(sorted, [b"Contents".as_slice(), b"MediaBox", b"Type"]);
+}
+
+#[test]
+fn save_stamps_producer_and_mod_date() {
+    use crate::creator::tests::create_document;
+
+    let date = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let options = SaveOptions {
+        update_producer: Some("lopdf test".into()),
+        update_mod_date: UpdatePolicy::Fixed(date),
+        ..SaveOptions::default()
+    };
+    let save = |options: &SaveOptions| {
+        let mut doc = create_document();
+        let file_id = Object::string_literal(b"0123456789abcdef".to_vec());
+        doc.trailer.set("ID", vec![file_id.clone(), file_id]);
+        let dirty = doc.dirty_objects().clone();
+        let mut bytes = Vec::new();
+        doc.save_with_options(&mut bytes, options.clone()).unwrap();
+        assert_eq!(doc.dirty_objects(), &dirty);
+        bytes
+    };
+
+    let first = save(&options);
+    assert_eq!(first, save(&options));
+    let doc = Document::load_mem(&first).unwrap();
+    let info = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+    let info = doc.get_dictionary(info).unwrap();
+    assert_eq!(info.get(b"Producer").unwrap().as_str().unwrap(), b"lopdf test");
+    assert_eq!(
+        info.get(b"ModDate").unwrap().as_str().unwrap(),
+        b"D:20231114221320+00'00'"
+    );
+
+    // Without the options, saving doesn't touch the information dictionary.
+    let doc = Document::load_mem(&save(&SaveOptions::default())).unwrap();
+    let info = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+    assert!(doc.get_dictionary(info).unwrap().get(b"ModDate").is_err());
+
+    // A missing information dictionary is created.
+    let mut doc = create_document();
+    doc.trailer.remove(b"Info");
+    let options = SaveOptions {
+        update_mod_date: UpdatePolicy::Now,
+        ..SaveOptions::default()
+    };
+    let mut bytes = Vec::new();
+    doc.save_with_options(&mut bytes, options).unwrap();
+    let doc = Document::load_mem(&bytes).unwrap();
+    let info = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+    let info = doc.get_dictionary(info).unwrap();
+    assert!(info.get(b"Producer").is_err());
+    assert!(info.get(b"ModDate").unwrap().as_datetime().is_some());
+}
+
+#[test]
+fn canonical_save_is_reproducible() {
+    use crate::content::{Content, Operation};
+
+    // Two independently built documents, with their dictionary entries added in another order.
+    let build = |reversed: bool, width: i64| {
+        let mut doc = Document::minimal("1.7", b"0123456789abcdef");
+        let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let content = Content {
+            operations: vec![Operation::new(
+                "re",
+                vec![0.into(), 0.into(), width.into(), 0.25.into()],
+            )],
+        };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let mut entries = vec![
+            ("Type", Object::from("Page")),
+            ("Parent", pages_id.into()),
+            ("MediaBox", vec![0.into(), 0.into(), 595.into(), 842.into()].into()),
+            ("Contents", content_id.into()),
+        ];
+        if reversed {
+            entries.reverse();
+        }
+        let mut page = Dictionary::new();
+        for (key, value) in entries {
+            page.set(key, value);
+        }
+        let page_id = doc.add_object(page);
+        let pages = doc.get_dictionary_mut(pages_id).unwrap();
+        pages.set("Kids", vec![page_id.into()]);
+        pages.set("Count", 1);
+        (doc, content_id)
+    };
+    let options = SaveOptions {
+        canonical: true,
+        ..SaveOptions::default()
+    };
+    let save = |doc: &mut Document| {
+        let mut bytes = Vec::new();
+        doc.save_with_options(&mut bytes, options.clone()).unwrap();
+        bytes
+    };
+
+    let (mut doc, content_id) = build(false, 100);
+    let first = save(&mut doc);
+    assert_eq!(first, save(&mut build(true, 100).0));
+    assert!(!first.windows(4).any(|window| window == b"Info"));
+    let reloaded = Document::load_mem(&first).unwrap();
+    assert_eq!(reloaded.get_pages().len(), 1);
+
+    // Changing one object changes its bytes only.
+    let second = save(&mut build(false, 200).0);
+    assert_eq!(first.len(), second.len());
+    let differences: Vec<usize> = (0..first.len()).filter(|&i| first[i] != second[i]).collect();
+    let object_start = reloaded.reference_table.get(content_id.0).unwrap();
+    let XrefEntry::Normal { offset, .. } = *object_start else {
+        panic!("content stream not in the cross-reference table");
+    };
+    let object_end = offset as usize
+        + first[offset as usize..]
+            .windows(6)
+            .position(|w| w == b"endobj")
+            .unwrap();
+    assert!(!differences.is_empty());
+    assert!(differences.iter().all(|&i| (offset as usize..object_end).contains(&i)));
+
+    // Saving fails rather than writing something that depends on the time.
+    let mut bytes = Vec::new();
+    let now = SaveOptions {
+        update_mod_date: UpdatePolicy::Now,
+        ..options.clone()
+    };
+    assert!(doc.save_with_options(&mut bytes, now).is_err());
+    doc.trailer.remove(b"ID");
+    assert!(doc.save_with_options(&mut bytes, options).is_err());
+}
+
+#[test]
+fn save_with_object_order() {
+    let save = |doc: &mut Document, object_order| {
+        let mut bytes = Vec::new();
+        let options = SaveOptions {
+            object_order,
+            ..SaveOptions::default()
+        };
+        doc.save_with_options(&mut bytes, options).unwrap();
+        bytes
+    };
+    // The objects of a loaded file written when saving, in the order of their offsets.
+    let file_order = |doc: &Document| {
+        let mut ids: Vec<(u64, ObjectId)> = doc
+            .objects
+            .iter()
+            .filter(|(_, object)| !matches!(object.type_name(), Ok(b"XRef" | b"ObjStm")))
+            .filter_map(|(&id, _)| match doc.reference_table.get(id.0) {
+                Some(&XrefEntry::Normal { offset, .. }) => Some((offset, id)),
+                _ => None,
+            })
+            .collect();
+        ids.sort_unstable();
+        ids.into_iter().map(|(_, id)| id).collect::<Vec<_>>()
+    };
+
+    // A document created in memory has no original order.
+    let doc = crate::creator::tests::create_document();
+    let by_id = save(&mut doc.clone(), ObjectOrder::ById);
+    assert_eq!(save(&mut doc.clone(), ObjectOrder::OriginalFileOrder), by_id);
+    assert_eq!(
+        file_order(&Document::load_mem(&by_id).unwrap()),
+        doc.objects.keys().copied().collect::<Vec<_>>()
+    );
+
+    // Objects referred to other than as `/Parent` are written first.
+    let dependency_first = Document::load_mem(&save(&mut doc.clone(), ObjectOrder::DependencyFirst)).unwrap();
+    assert_eq!(
+        dependency_first.extract_text(&[1]).unwrap(),
+        doc.extract_text(&[1]).unwrap()
+    );
+    let order = file_order(&dependency_first);
+    assert_ne!(order, doc.objects.keys().copied().collect::<Vec<_>>());
+    let position = |id| order.iter().position(|&other| other == id).unwrap();
+    for (&id, object) in &dependency_first.objects {
+        let references: Vec<ObjectId> = match object {
+            Object::Dictionary(dict) => dict
+                .iter()
+                .filter(|(key, _)| key.as_slice() != b"Parent")
+                .flat_map(|(_, value)| match value {
+                    Reference(id) => vec![*id],
+                    Object::Dictionary(dict) => {
+                        dict.iter().filter_map(|(_, value)| value.as_reference().ok()).collect()
+                    }
+                    Array(array) => array.iter().filter_map(|value| value.as_reference().ok()).collect(),
+                    _ => vec![],
+                })
+                .collect(),
+            _ => vec![],
+        };
+        for reference in references {
+            assert!(position(reference) < position(id), "{reference:?} after {id:?}");
+        }
+    }
+
+    // Untouched objects keep their relative order, the objects of an object stream at its position.
+    let mut doc = Document::load("assets/AnnotationDemo.pdf").unwrap();
+    let compressed: Vec<ObjectId> = doc
+        .objects
+        .keys()
+        .copied()
+        .filter(|id| matches!(doc.reference_table.get(id.0), Some(XrefEntry::Compressed { .. })))
+        .collect();
+    assert!(!compressed.is_empty());
+    let original = file_order(&doc);
+    let added = doc.add_object(Integer(1));
+    let saved = Document::load_mem(&save(&mut doc, ObjectOrder::OriginalFileOrder)).unwrap();
+    assert_eq!(saved.get_pages(), doc.get_pages());
+    let order = file_order(&saved);
+    assert_eq!(order.last(), Some(&added));
+    let untouched: Vec<ObjectId> = order.iter().copied().filter(|id| original.contains(id)).collect();
+    assert_eq!(untouched, original);
+    let mut by_index = compressed.clone();
+    by_index.sort_by_key(|id| match doc.reference_table.get(id.0) {
+        Some(&XrefEntry::Compressed { index, .. }) => index,
+        _ => unreachable!(),
+    });
+    let written: Vec<ObjectId> = order.iter().copied().filter(|id| compressed.contains(id)).collect();
+    assert_eq!(written, by_index);
+}
+
+#[test]
+fn save_hybrid_reference_file() {
+    let save = |doc: &mut Document, xref_mode| {
+        let mut bytes = Vec::new();
+        let options = SaveOptions {
+            xref_mode: Some(xref_mode),
+            ..SaveOptions::default()
+        };
+        doc.save_with_options(&mut bytes, options).unwrap();
+        bytes
+    };
+    let objects = |doc: &Document| {
+        doc.objects
+            .iter()
+            .filter(|(_, object)| !matches!(object.type_name(), Ok(b"XRef" | b"ObjStm")))
+            .map(|(&id, object)| (id, object.clone()))
+            .collect::<Vec<_>>()
+    };
+    let ids = |doc: &Document| objects(doc).into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+    let find = |bytes: &[u8], text: &[u8]| bytes.windows(text.len()).position(|window| window == text);
+
+    let mut doc = Document::load("assets/AnnotationDemo.pdf").unwrap();
+    let hybrid = save(&mut doc, XrefMode::Hybrid);
+    // The objects are written at the same offsets as with a cross-reference table.
+    let baseline = Document::load_mem(&save(&mut doc, XrefMode::Classic)).unwrap();
+    assert_eq!(ids(&baseline), ids(&doc));
+    // The stream comes first, the table the file starts from after it.
+    let stream = find(&hybrid, b"/Type/XRef").unwrap();
+    let table = find(&hybrid, b"\nxref\n").unwrap();
+    assert!(stream < table);
+    assert!(find(&hybrid[table..], b"/XRefStm").is_some());
+    assert!(find(&hybrid[table..], b"/Type").is_none());
+    assert!(!doc.trailer.has(b"XRefStm"));
+
+    let loaded = Document::load_mem(&hybrid).unwrap();
+    assert_eq!(objects(&loaded), objects(&baseline));
+    assert_eq!(loaded.get_pages(), doc.get_pages());
+    assert!(!loaded.trailer.has(b"XRefStm"));
+    // A reader without support for cross-reference streams only sees the table.
+    let ignored = find(&hybrid, b"/XRefStm").unwrap();
+    let mut old_reader = hybrid.clone();
+    old_reader[ignored..ignored + 8].copy_from_slice(b"/Ignored");
+    let loaded = Document::load_mem(&old_reader).unwrap();
+    assert_eq!(objects(&loaded), objects(&baseline));
+    assert_eq!(loaded.get_pages(), doc.get_pages());
+
+    // Saving a hybrid file again writes a single cross-reference section of the type asked for.
+    let mut loaded = Document::load_mem(&hybrid).unwrap();
+    let classic = save(&mut loaded, XrefMode::Classic);
+    assert!(find(&classic, b"/XRefStm").is_none());
+    assert!(find(&classic, b"/Type/XRef").is_none());
+    let stream = save(&mut loaded, XrefMode::Stream);
+    assert!(find(&stream, b"\nxref\n").is_none());
+    for bytes in [classic, stream] {
+        let reloaded = Document::load_mem(&bytes).unwrap();
+        assert_eq!(ids(&reloaded), ids(&doc));
+    }
+}
+
+#[cfg(feature = "nom_parser")]
+#[test]
+fn streaming_writer() {
+    let mut doc = Document::with_version("1.5");
+    let mut writer = StreamingWriter::new(vec![], "1.5").unwrap();
+    let pages_id = doc.new_object_id();
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Courier",
+    });
+    let resources_id = doc.add_object(dictionary! { "Font" => dictionary! { "F1" => font_id } });
+    let mut kids = vec![];
+    let mut largest_map = 0;
+    for page in 1..=10_000 {
+        let content = format!("BT /F1 12 Tf 100 700 Td (Page {page}) Tj ET");
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+        kids.push(Object::from(page_id));
+        largest_map = largest_map.max(doc.objects.len());
+        writer.write_document_object(&mut doc, content_id).unwrap();
+        writer.write_document_object(&mut doc, page_id).unwrap();
+    }
+    assert!(largest_map <= 4);
+
+    // An object can't be written twice.
+    let spare_id = doc.new_object_id();
+    writer.write_object(spare_id, &Null).unwrap();
+    let error = writer.write_object(spare_id, &Null).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => kids.len() as i64,
+            "Kids" => kids,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    doc.trailer.set("Root", catalog_id);
+    let buffer = writer.finish_document(doc).unwrap();
+
+    let doc = Document::load_mem(&buffer).unwrap();
+    assert!(doc.load_report.unreadable_objects.is_empty() && !doc.load_report.reconstructed_xref);
+    let pages = doc.get_pages();
+    assert_eq!(pages.len(), 10_000);
+    assert_eq!(doc.extract_text(&[1]).unwrap().trim(), "Page 1");
+    assert_eq!(doc.extract_text(&[10_000]).unwrap().trim(), "Page 10000");
+
+    let options = SaveOptions {
+        canonical: true,
+        ..SaveOptions::default()
+    };
+    let error = StreamingWriter::with_options(vec![], "1.5", options).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
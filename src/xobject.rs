@@ -1,3 +1,4 @@
+use crate::content::{matrix_from_operands, multiply_matrix, Content, GlyphWidths, TextState, IDENTITY_MATRIX};
 use crate::*;
 use crate::{Dictionary, Stream};
 
@@ -7,23 +8,285 @@
 #[cfg(feature = "embed_image")]
 use std::path::Path;
 
-#[cfg(feature = "embed_image")]
-use crate::Result;
-
 #[derive(Debug, Clone)]
 pub struct PdfImage<'a> {
+    /// Object ID of the image XObject, or of the page for inline images.
     pub id: ObjectId,
     pub width: i64,
     pub height: i64,
     pub color_space: Option<String>,
     pub filters: Option<Vec<String>>,
     pub bits_per_component: Option<i64>,
+    /// Placement rectangle `[llx lly urx ury]` in default user space.
+    /// Only known for the images of [`Document::extract_page`] and inline images, whose painting is tracked.
+    pub rect: Option<[f32; 4]>,
     /// Image Data
     pub content: &'a [u8],
     /// Origin Stream Dictionary
     pub origin_dict: &'a Dictionary,
 }
 
+impl<'a> PdfImage<'a> {
+    pub(crate) fn from_parts(id: ObjectId, dict: &'a Dictionary, content: &'a [u8]) -> Result<Self> {
+        let width = dict.get(b"Width")?.as_i64()?;
+        let height = dict.get(b"Height")?.as_i64()?;
+        let color_space = match dict.get(b"ColorSpace") {
+            Ok(cs) => match cs {
+                Object::Array(array) => Some(String::from_utf8_lossy(array[0].as_name()?).to_string()),
+                Object::Name(name) => Some(String::from_utf8_lossy(name).to_string()),
+                _ => None,
+            },
+            Err(_) => None,
+        };
+        let bits_per_component = match dict.get(b"BitsPerComponent") {
+            Ok(bpc) => Some(bpc.as_i64()?),
+            Err(_) => None,
+        };
+        let mut filters = vec![];
+        if let Ok(filter) = dict.get(b"Filter") {
+            match filter {
+                Object::Array(array) => {
+                    for obj in array.iter() {
+                        let name = obj.as_name()?;
+                        filters.push(String::from_utf8_lossy(name).to_string());
+                    }
+                }
+                Object::Name(name) => {
+                    filters.push(String::from_utf8_lossy(name).to_string());
+                }
+                _ => {}
+            }
+        };
+
+        Ok(PdfImage {
+            id,
+            width,
+            height,
+            color_space,
+            bits_per_component,
+            filters: Some(filters),
+            rect: None,
+            content,
+            origin_dict: dict,
+        })
+    }
+}
+
+/// An inline image (`BI` ... `ID` ... `EI`) painted by a content stream, see [`Document::get_page_inline_images`].
+///
+/// Inline images aren't objects of the document, so they own their data, and [`InlineImage::image`] gives
+/// them as a [`PdfImage`].
+#[derive(Debug, Clone)]
+pub struct InlineImage {
+    /// Object ID of the page painting the image.
+    pub page_id: ObjectId,
+    /// The image dictionary, with the abbreviated keys and names expanded to their long forms.
+    pub dict: Dictionary,
+    /// The image data, decoded by the filters lopdf decodes. `/Filter` lists the image filters left, like
+    /// `DCTDecode`.
+    pub content: Vec<u8>,
+    /// Placement rectangle `[llx lly urx ury]` in default user space.
+    pub rect: [f32; 4],
+}
+
+impl InlineImage {
+    /// The image, with the page ID as its ID.
+    pub fn image(&self) -> Result<PdfImage<'_>> {
+        let mut image = PdfImage::from_parts(self.page_id, &self.dict, &self.content)?;
+        image.rect = Some(self.rect);
+        Ok(image)
+    }
+}
+
+/// The data of an image decoded by [`Document::decode_image`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedImage {
+    /// The raw samples of an image whose filters lopdf decodes.
+    Samples(ImageSamples),
+    /// A JPEG file, the data of a `DCTDecode` image, for an image decoder.
+    Jpeg(Vec<u8>),
+    /// A JPEG 2000 codestream, the data of a `JPXDecode` image, for an image decoder.
+    Jpeg2000(Vec<u8>),
+}
+
+/// The raw samples of an image, see [`DecodedImage::Samples`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageSamples {
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_component: u8,
+    /// Number of color components of each sample, 1 for image masks and indexed images.
+    pub components: usize,
+    /// Rows of `width * components * bits_per_component` bits, each starting at a new byte.
+    pub data: Vec<u8>,
+    /// The palette the samples index, for an `Indexed` color space.
+    pub palette: Option<ImagePalette>,
+}
+
+/// The palette of an `Indexed` color space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImagePalette {
+    /// Name of the base color space, e.g. `DeviceRGB` or `ICCBased`.
+    pub base: Vec<u8>,
+    /// Number of color components of the base color space.
+    pub components: usize,
+    /// The highest index, `hival`.
+    pub hival: u8,
+    /// The colors of the palette, `components` bytes for each index from 0 to `hival`.
+    pub lookup: Vec<u8>,
+}
+
+/// Bytes allowed in JPEG and JPEG 2000 data beyond the samples of the image, for markers, tables and
+/// embedded profiles.
+const CODEC_HEADER_ALLOWANCE: usize = 1 << 20;
+
+impl Document {
+    /// Decode the data of an image from [`Document::get_page_images`] or another of the image methods.
+    ///
+    /// Images whose filters lopdf decodes, including `FlateDecode` with predictors, give their raw samples,
+    /// with the palette of an `Indexed` color space. Images ending with `DCTDecode` or `JPXDecode` give the
+    /// JPEG or JPEG 2000 data for an image decoder, once the filters before it, as in
+    /// `[/FlateDecode /DCTDecode]`, are decoded. Other image filters, like `CCITTFaxDecode` and
+    /// `JBIG2Decode`, fail with [`Error::Unimplemented`].
+    pub fn decode_image(&self, image: &PdfImage) -> Result<DecodedImage> {
+        let dict = image.origin_dict;
+        let filters = if dict.has(b"Filter") {
+            Stream::filters_of(dict)?
+        } else {
+            vec![]
+        };
+        let params = Stream::decode_parms_of(dict, filters.len());
+        let width = u32::try_from(image.width).map_err(|_| invalid_image("Width"))?;
+        let height = u32::try_from(image.height).map_err(|_| invalid_image("Height"))?;
+        let too_large = || Error::InvalidStream(format!("image of {width}x{height} samples is too large"));
+        let codec = filters
+            .iter()
+            .position(|&filter| filter == b"DCTDecode" || filter == b"JPXDecode");
+        if let Some(codec) = codec {
+            // The encoded image is bounded by its samples at 16 bits, with room for the headers of the codec.
+            let components = match dict.get(b"ColorSpace") {
+                Ok(color_space) => self.image_color_components(color_space)?.0,
+                Err(_) => 4,
+            };
+            let limit = (width as usize)
+                .checked_mul(height as usize)
+                .and_then(|pixels| pixels.checked_mul(components.checked_mul(2)?))
+                .and_then(|bytes| bytes.checked_add(CODEC_HEADER_ALLOWANCE))
+                .ok_or_else(too_large)?;
+            let data = Stream::decode_filters(image.content, &filters[..codec], &params[..codec], limit + 1)?;
+            if data.len() > limit {
+                return Err(Error::InvalidStream(format!(
+                    "encoded image data exceeds {limit} bytes"
+                )));
+            }
+            return Ok(match filters[codec] {
+                b"DCTDecode" => DecodedImage::Jpeg(data),
+                _ => DecodedImage::Jpeg2000(data),
+            });
+        }
+
+        let image_mask = dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false);
+        let (bits_per_component, components, palette) = if image_mask {
+            (1, 1, None)
+        } else {
+            let bits_per_component = image
+                .bits_per_component
+                .and_then(|bits| u8::try_from(bits).ok())
+                .filter(|bits| [1, 2, 4, 8, 16].contains(bits))
+                .ok_or_else(|| invalid_image("BitsPerComponent"))?;
+            let color_space = dict.get(b"ColorSpace").map_err(|_| invalid_image("ColorSpace"))?;
+            let (components, palette) = self.image_color_components(color_space)?;
+            (bits_per_component, components, palette)
+        };
+
+        let expected = (width as usize)
+            .checked_mul(components)
+            .and_then(|samples| samples.checked_mul(usize::from(bits_per_component)))
+            .map(|bits| bits.div_ceil(8))
+            .and_then(|row| row.checked_mul(height as usize))
+            .ok_or_else(too_large)?;
+        // Data beyond the samples isn't decoded.
+        let data = Stream::decode_filters(image.content, &filters, &params, expected)?;
+        if data.len() < expected {
+            return Err(Error::InvalidStream(format!(
+                "image data of {} bytes where {expected} are expected",
+                data.len()
+            )));
+        }
+        Ok(DecodedImage::Samples(ImageSamples {
+            width,
+            height,
+            bits_per_component,
+            components,
+            data,
+            palette,
+        }))
+    }
+
+    /// The number of color components of an image color space, with its palette if it is indexed.
+    fn image_color_components(&self, color_space: &Object) -> Result<(usize, Option<ImagePalette>)> {
+        let color_space = self.dereference(color_space)?.1;
+        let (name, params) = match color_space {
+            Object::Name(name) => (name.as_slice(), &[][..]),
+            Object::Array(array) => match array.split_first() {
+                Some((name, params)) => (name.as_name()?, params),
+                None => return Err(invalid_image("ColorSpace")),
+            },
+            _ => return Err(invalid_image("ColorSpace")),
+        };
+        let components = match name {
+            b"DeviceGray" | b"CalGray" | b"Separation" => 1,
+            b"DeviceRGB" | b"CalRGB" | b"Lab" => 3,
+            b"DeviceCMYK" => 4,
+            b"ICCBased" => {
+                let profile = self.dereference(params.first().ok_or_else(|| invalid_image("ColorSpace"))?)?;
+                let n = profile.1.as_stream()?.dict.get(b"N")?.as_i64()?;
+                usize::try_from(n).map_err(|_| invalid_image("ColorSpace"))?
+            }
+            b"DeviceN" => self
+                .dereference(params.first().ok_or_else(|| invalid_image("ColorSpace"))?)?
+                .1
+                .as_array()?
+                .len(),
+            b"Indexed" => {
+                let [base, hival, lookup, ..] = params else {
+                    return Err(invalid_image("ColorSpace"));
+                };
+                let base_name = match self.dereference(base)?.1 {
+                    Object::Array(array) => array.first().map(Object::as_name),
+                    base => Some(base.as_name()),
+                };
+                let base_name = base_name.ok_or_else(|| invalid_image("ColorSpace"))??;
+                // The base can't be indexed itself, which also stops color spaces being their own base.
+                if base_name == b"Indexed" {
+                    return Err(invalid_image("ColorSpace"));
+                }
+                let base_name = base_name.to_vec();
+                let (base_components, _) = self.image_color_components(base)?;
+                let hival =
+                    u8::try_from(self.dereference(hival)?.1.as_i64()?).map_err(|_| invalid_image("ColorSpace"))?;
+                let lookup = match self.dereference(lookup)?.1 {
+                    Object::Stream(stream) => stream.decompressed_content()?,
+                    lookup => lookup.as_str()?.to_vec(),
+                };
+                let palette = ImagePalette {
+                    base: base_name,
+                    components: base_components,
+                    hival,
+                    lookup,
+                };
+                return Ok((1, Some(palette)));
+            }
+            _ => return Err(invalid_image("ColorSpace")),
+        };
+        Ok((components, None))
+    }
+}
+
+fn invalid_image(key: &str) -> Error {
+    Error::InvalidStream(format!("image with an invalid /{key}"))
+}
+
 pub fn form(boundingbox: Vec<f32>, matrix: Vec<f32>, content: Vec<u8>) -> Stream {
     let mut dict = Dictionary::new();
     dict.set("Type", Object::Name(b"XObject".to_vec()));
@@ -39,6 +302,185 @@ pub fn form(boundingbox: Vec<f32>, matrix: Vec<f32>, content: Vec<u8>) -> Stream
     xobject
 }
 
+/// Create a Form XObject painting `content`, with a bounding box computed from its operations.
+///
+/// The box is found by simulating path construction, text showing and image painting.
+/// Text widths are taken from the `/Widths` of fonts given directly in `resources`;
+/// otherwise every byte of a string is assumed to be one em wide, so the box errs on the large side.
+/// Glyphs are assumed to extend from a quarter em below to one em above the baseline.
+/// A Form XObject painted with `Do` covers its `/BBox`, other XObjects cover the unit square;
+/// as referenced objects can't be looked up here, see [`form_from_content_in`].
+pub fn form_from_content(content: &Content, resources: Dictionary) -> Result<Stream> {
+    let bbox = content_bounding_box(content, &resources, None).unwrap_or_default();
+    form_from_content_with_bbox(content, resources, bbox)
+}
+
+/// Create a Form XObject painting `content` like [`form_from_content`], looking up the fonts and
+/// XObjects referenced by `resources` in `doc`.
+pub fn form_from_content_in(doc: &Document, content: &Content, resources: Dictionary) -> Result<Stream> {
+    let bbox = content_bounding_box(content, &resources, Some(doc)).unwrap_or_default();
+    form_from_content_with_bbox(content, resources, bbox)
+}
+
+/// Create a Form XObject painting `content`, with the given bounding box `[llx lly urx ury]`.
+pub fn form_from_content_with_bbox(content: &Content, resources: Dictionary, bbox: [f32; 4]) -> Result<Stream> {
+    let mut xobject = form(bbox.to_vec(), IDENTITY_MATRIX.to_vec(), content.encode()?);
+    xobject.dict.set("Resources", resources);
+    Ok(xobject)
+}
+
+/// Parameters of the graphics state which affect the area painted by a content stream.
+#[derive(Clone)]
+struct PaintState {
+    ctm: [f32; 6],
+    line_width: f32,
+    widths: GlyphWidths,
+    text: TextState,
+}
+
+/// Compute the bounding box `[llx lly urx ury]` of what `content` paints, `None` if it paints nothing.
+///
+/// References in `resources` are followed in `doc`, if given.
+fn content_bounding_box(content: &Content, resources: &Dictionary, doc: Option<&Document>) -> Option<[f32; 4]> {
+    fn include(bounds: &mut Option<[f32; 4]>, matrix: &[f32; 6], (x, y): (f32, f32)) {
+        let x_t = matrix[0] * x + matrix[2] * y + matrix[4];
+        let y_t = matrix[1] * x + matrix[3] * y + matrix[5];
+        let [llx, lly, urx, ury] = bounds.get_or_insert([x_t, y_t, x_t, y_t]);
+        *llx = llx.min(x_t);
+        *lly = lly.min(y_t);
+        *urx = urx.max(x_t);
+        *ury = ury.max(y_t);
+    }
+
+    fn resolve<'a>(doc: Option<&'a Document>, object: &'a Object) -> Option<&'a Object> {
+        match object {
+            Object::Reference(id) => doc?.get_object(*id).ok(),
+            _ => Some(object),
+        }
+    }
+    let resource = |category: &[u8], name: &Object| {
+        let names = resolve(doc, resources.get(category).ok()?)?.as_dict().ok()?;
+        resolve(doc, names.get(name.as_name().ok()?).ok()?)
+    };
+    let no_widths = GlyphWidths::from_font(&Dictionary::new(), |it| it);
+    let mut state = PaintState {
+        ctm: IDENTITY_MATRIX,
+        line_width: 1.0,
+        widths: no_widths.clone(),
+        text: TextState::default(),
+    };
+    let mut state_stack = vec![];
+    let mut path = vec![];
+    let mut bounds = None;
+
+    for operation in &content.operations {
+        let operands = &operation.operands;
+        let number = |index: usize| operands.get(index).and_then(|it| it.as_float().ok()).unwrap_or(0.0);
+        let mut strings = vec![];
+        match operation.operator.as_str() {
+            "q" => state_stack.push(state.clone()),
+            // The text matrices aren't part of the graphics state.
+            "Q" => {
+                let text = state.text;
+                state = state_stack.pop().unwrap_or_else(|| state.clone());
+                state.text.text_matrix = text.text_matrix;
+                state.text.line_matrix = text.line_matrix;
+            }
+            "cm" => {
+                if let Some(matrix) = matrix_from_operands(operands) {
+                    state.ctm = multiply_matrix(&matrix, &state.ctm);
+                }
+            }
+            "w" => state.line_width = number(0),
+            "m" | "l" => path.push((number(0), number(1))),
+            "v" | "y" => path.extend([(number(0), number(1)), (number(2), number(3))]),
+            "c" => path.extend([(number(0), number(1)), (number(2), number(3)), (number(4), number(5))]),
+            "re" => {
+                let (x, y, width, height) = (number(0), number(1), number(2), number(3));
+                path.extend([(x, y), (x + width, y), (x, y + height), (x + width, y + height)]);
+            }
+            // Painting operators, the stroking ones extend the path by half the line width.
+            "f" | "F" | "f*" => {
+                for point in path.drain(..) {
+                    include(&mut bounds, &state.ctm, point);
+                }
+            }
+            "S" | "s" | "B" | "B*" | "b" | "b*" => {
+                let half_width = state.line_width / 2.0;
+                for (x, y) in path.drain(..) {
+                    for point in [
+                        (x - half_width, y - half_width),
+                        (x + half_width, y + half_width),
+                        (x - half_width, y + half_width),
+                        (x + half_width, y - half_width),
+                    ] {
+                        include(&mut bounds, &state.ctm, point);
+                    }
+                }
+            }
+            "n" => path.clear(),
+            // Forms are painted into their bounding box, mapped by their matrix, and images into the unit square.
+            "Do" | "BI" => {
+                let form = operands
+                    .first()
+                    .filter(|_| operation.operator == "Do")
+                    .and_then(|name| resource(b"XObject", name)?.as_stream().ok())
+                    .filter(|xobject| xobject.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Form"));
+                let form_box = form.and_then(|form| Some((form, form.dict.get_as::<[f32; 4]>(b"BBox").ok()?)));
+                let (matrix, [llx, lly, urx, ury]) = match form_box {
+                    Some((form, bbox)) => {
+                        let matrix = form.dict.get_as::<[f32; 6]>(b"Matrix").unwrap_or(IDENTITY_MATRIX);
+                        (multiply_matrix(&matrix, &state.ctm), bbox)
+                    }
+                    None => (state.ctm, [0.0, 0.0, 1.0, 1.0]),
+                };
+                for point in [(llx, lly), (urx, lly), (llx, ury), (urx, ury)] {
+                    include(&mut bounds, &matrix, point);
+                }
+            }
+            "Tf" => {
+                state.text.apply(operation);
+                let font = operands.first().and_then(|name| resource(b"Font", name));
+                state.widths = match font.and_then(|font| font.as_dict().ok()) {
+                    Some(font) => GlyphWidths::from_font(font, |it| it),
+                    None => no_widths.clone(),
+                };
+            }
+            "Tj" | "'" | "\"" => {
+                state.text.apply(operation);
+                strings.extend(operands.last().and_then(|it| it.as_str().ok()).map(Ok));
+            }
+            "TJ" => {
+                if let Some(Ok(array)) = operands.first().map(Object::as_array) {
+                    strings.extend(array.iter().map(|item| item.as_str().map_err(|_| item)));
+                }
+            }
+            _ => {
+                state.text.apply(operation);
+            }
+        }
+
+        // Strings to show, or adjustments of the position between them.
+        for item in strings {
+            let advance = match item {
+                Ok(bytes) => {
+                    let width: f32 = bytes
+                        .iter()
+                        .map(|&code| state.text.glyph_advance(state.widths.width(&[code]), code == b' '))
+                        .sum();
+                    let [llx, lly, urx, ury] = state.text.bounds(0.0, width, &state.ctm);
+                    include(&mut bounds, &IDENTITY_MATRIX, (llx, lly));
+                    include(&mut bounds, &IDENTITY_MATRIX, (urx, ury));
+                    width
+                }
+                Err(adjustment) => state.text.adjustment_advance(adjustment.as_float().unwrap_or(0.0)),
+            };
+            state.text.advance(advance);
+        }
+    }
+    bounds
+}
+
 #[cfg(feature = "embed_image")]
 pub fn image<P: AsRef<Path>>(path: P) -> Result<Stream> {
     use std::fs::File;
@@ -257,3 +699,241 @@ fn embed_supported_color_type() -> Result<()> {
     doc.save("supported_color_type.pdf")?;
     Ok(())
 }
+
+#[test]
+fn form_from_rectangle_content() {
+    use content::Operation;
+
+    let content = Content {
+        operations: vec![
+            Operation::new("q", vec![]),
+            Operation::new("cm", vec![1.into(), 0.into(), 0.into(), 1.into(), 10.into(), 20.into()]),
+            Operation::new("re", vec![0.into(), 0.into(), 100.into(), 50.into()]),
+            Operation::new("f", vec![]),
+            Operation::new("Q", vec![]),
+        ],
+    };
+    let form = form_from_content(&content, Dictionary::new()).unwrap();
+    let bbox: Vec<f32> = form
+        .dict
+        .get(b"BBox")
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|it| it.as_float().unwrap())
+        .collect();
+    assert_eq!(bbox, vec![10.0, 20.0, 110.0, 70.0]);
+    assert!(form.dict.get(b"Resources").unwrap().as_dict().is_ok());
+    assert_eq!(form.dict.get(b"Matrix").unwrap().as_array().unwrap().len(), 6);
+
+    let form = form_from_content_with_bbox(&content, Dictionary::new(), [0.0, 0.0, 5.0, 5.0]).unwrap();
+    let bbox: Vec<f32> = form
+        .dict
+        .get(b"BBox")
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|it| it.as_float().unwrap())
+        .collect();
+    assert_eq!(bbox, vec![0.0, 0.0, 5.0, 5.0]);
+}
+
+#[test]
+fn form_from_text_content() {
+    use content::Operation;
+
+    let content = Content {
+        operations: vec![
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec!["F1".into(), 12.into()]),
+            Operation::new("Td", vec![100.into(), 700.into()]),
+            Operation::new("Tj", vec![Object::string_literal("HH")]),
+            Operation::new("ET", vec![]),
+        ],
+    };
+    let bbox = content_bounding_box(&content, &Dictionary::new(), None).unwrap();
+    assert!(bbox[0] <= 100.0 && bbox[1] <= 700.0 && bbox[2] > 100.0 && bbox[3] > 700.0);
+
+    // With font metrics the width of the text is exact.
+    let resources = dictionary! {
+        "Font" => dictionary! {
+            "F1" => dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "FirstChar" => 72,
+                "Widths" => vec![500.into()],
+            },
+        },
+    };
+    let bbox = content_bounding_box(&content, &resources, None).unwrap();
+    assert_eq!(bbox[0], 100.0);
+    assert_eq!(bbox[2], 112.0);
+    assert_eq!(bbox[3], 712.0);
+}
+
+#[test]
+fn form_from_content_painting_xobjects() {
+    use content::Operation;
+
+    let mut doc = Document::with_version("1.5");
+    let mut inner = form(vec![0.0, 0.0, 10.0, 20.0], vec![2.0, 0.0, 0.0, 2.0, 5.0, 0.0], vec![]);
+    inner.dict.set("Resources", Dictionary::new());
+    let inner_id = doc.add_object(inner);
+    let image_id = doc.add_object(Stream::new(
+        dictionary! { "Type" => "XObject", "Subtype" => "Image", "Width" => 1, "Height" => 1 },
+        vec![0],
+    ));
+    let resources = dictionary! { "XObject" => dictionary! { "Fm1" => inner_id, "Im1" => image_id } };
+    let paint = |name: &str, e: i32, f: i32| Content {
+        operations: vec![
+            Operation::new("cm", vec![1.into(), 0.into(), 0.into(), 1.into(), e.into(), f.into()]),
+            Operation::new("Do", vec![Object::Name(name.as_bytes().to_vec())]),
+        ],
+    };
+
+    // The bounding box of the form is mapped by its matrix, then by the CTM.
+    let bbox = content_bounding_box(&paint("Fm1", 100, 100), &resources, Some(&doc)).unwrap();
+    assert_eq!(bbox, [105.0, 100.0, 125.0, 140.0]);
+    let form = form_from_content_in(&doc, &paint("Fm1", 100, 100), resources.clone()).unwrap();
+    assert_eq!(
+        form.dict.get_as::<[f32; 4]>(b"BBox").unwrap(),
+        [105.0, 100.0, 125.0, 140.0]
+    );
+    // Images, and forms which can't be looked up, cover the unit square.
+    let bbox = content_bounding_box(&paint("Im1", 100, 100), &resources, Some(&doc)).unwrap();
+    assert_eq!(bbox, [100.0, 100.0, 101.0, 101.0]);
+    let bbox = content_bounding_box(&paint("Fm1", 100, 100), &resources, None).unwrap();
+    assert_eq!(bbox, [100.0, 100.0, 101.0, 101.0]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::tests::create_document;
+    use std::io::Write;
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn image(width: i64, height: i64, entries: Dictionary, content: Vec<u8>) -> Stream {
+        let mut dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => width,
+            "Height" => height,
+            "BitsPerComponent" => 8,
+            "ColorSpace" => "DeviceRGB",
+        };
+        for (key, value) in entries {
+            dict.set(key, value);
+        }
+        Stream::new(dict, content)
+    }
+
+    #[test]
+    fn decode_page_images() {
+        let mut doc = create_document();
+        let page_id = doc.page_iter().next().unwrap();
+        // Rows of two RGB pixels, the second one predicted from the first with the PNG Up filter.
+        let rows = [0, 1, 2, 3, 4, 5, 6, 2, 1, 1, 1, 1, 1, 1];
+        let flate = image(
+            2,
+            2,
+            dictionary! {
+                "Filter" => "FlateDecode",
+                "DecodeParms" => dictionary! { "Predictor" => 12, "Colors" => 3, "Columns" => 2 },
+            },
+            zlib(&rows),
+        );
+        let jpeg = b"\xFF\xD8 not really a JPEG \xFF\xD9".to_vec();
+        let dct = image(
+            1,
+            1,
+            dictionary! { "Filter" => vec!["FlateDecode".into(), "DCTDecode".into()] },
+            zlib(&jpeg),
+        );
+        let jpx = image(1, 1, dictionary! { "Filter" => "JPXDecode" }, b"jp2 data".to_vec());
+        let lookup_id = doc.add_object(Stream::new(dictionary! {}, vec![255, 0, 0, 0, 0, 255]));
+        // Three samples of 4 bits indexing a palette of red and blue.
+        let indexed = image(
+            3,
+            1,
+            dictionary! {
+                "BitsPerComponent" => 4,
+                "ColorSpace" => vec!["Indexed".into(), "DeviceRGB".into(), 1.into(), lookup_id.into()],
+            },
+            vec![0x01, 0x00],
+        );
+        let short = image(4, 4, dictionary! {}, vec![0; 10]);
+        // The size of the samples overflows even 64 bits.
+        let huge = image(
+            u32::MAX.into(),
+            u32::MAX.into(),
+            dictionary! { "BitsPerComponent" => 16, "ColorSpace" => "DeviceCMYK" },
+            vec![0; 10],
+        );
+        // The filter before DCTDecode inflates to far more than a 1x1 JPEG takes.
+        let bomb = image(
+            1,
+            1,
+            dictionary! { "Filter" => vec!["FlateDecode".into(), "DCTDecode".into()] },
+            zlib(&vec![0; 4 << 20]),
+        );
+        let mut xobjects = Dictionary::new();
+        for (name, image) in [
+            ("Flate", flate),
+            ("Dct", dct),
+            ("Jpx", jpx),
+            ("Indexed", indexed),
+            ("Short", short),
+            ("Huge", huge),
+            ("Bomb", bomb),
+        ] {
+            xobjects.set(name, doc.add_object(image));
+        }
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Resources", dictionary! { "XObject" => xobjects });
+
+        let images = doc.get_page_images(page_id).unwrap();
+        let decoded: Vec<_> = images.iter().map(|image| doc.decode_image(image)).collect();
+        assert_eq!(
+            decoded[0].as_ref().unwrap(),
+            &DecodedImage::Samples(ImageSamples {
+                width: 2,
+                height: 2,
+                bits_per_component: 8,
+                components: 3,
+                data: vec![1, 2, 3, 4, 5, 6, 2, 3, 4, 5, 6, 7],
+                palette: None,
+            })
+        );
+        assert_eq!(decoded[1].as_ref().unwrap(), &DecodedImage::Jpeg(jpeg));
+        assert_eq!(
+            decoded[2].as_ref().unwrap(),
+            &DecodedImage::Jpeg2000(b"jp2 data".to_vec())
+        );
+        let DecodedImage::Samples(indexed) = decoded[3].as_ref().unwrap() else {
+            panic!("indexed image isn't decoded to samples");
+        };
+        assert_eq!((indexed.components, indexed.bits_per_component), (1, 4));
+        assert_eq!(indexed.data, [0x01, 0x00]);
+        assert_eq!(
+            indexed.palette,
+            Some(ImagePalette {
+                base: b"DeviceRGB".to_vec(),
+                components: 3,
+                hival: 1,
+                lookup: vec![255, 0, 0, 0, 0, 255],
+            })
+        );
+        assert!(matches!(decoded[4], Err(Error::InvalidStream(_))));
+        assert!(matches!(decoded[5], Err(Error::InvalidStream(_))));
+        assert!(matches!(decoded[6], Err(Error::InvalidStream(_))));
+    }
+}
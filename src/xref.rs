@@ -15,7 +15,7 @@ pub struct Xref {
     pub size: u32,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum XrefType {
     /// Cross-Reference Streams are supported beginning with PDF 1.5.
     CrossReferenceStream,
@@ -25,9 +25,9 @@ pub enum XrefType {
 
 #[derive(Debug, Clone)]
 pub enum XrefEntry {
-    Free, // TODO add generation number
+    Free { generation: u16 },
     UnusableFree,
-    Normal { offset: u32, generation: u16 },
+    Normal { offset: u64, generation: u16 },
     Compressed { container: u32, index: u16 },
 }
 
@@ -92,8 +92,8 @@ pub fn write_xref_entry(&self, file: &mut dyn Write) -> Result<()> {
             XrefEntry::Compressed { container: _, index: _ } => {
                 writeln!(file, "{:>010} {:>05} f ", 0, 65535)?;
             }
-            XrefEntry::Free => {
-                writeln!(file, "{:>010} {:>05} f ", 0, 0)?;
+            XrefEntry::Free { generation } => {
+                writeln!(file, "{:>010} {:>05} f ", 0, generation)?;
             }
             XrefEntry::UnusableFree => {
                 writeln!(file, "{:>010} {:>05} f ", 0, 65535)?;
@@ -0,0 +1,80 @@
+#![cfg(feature = "nom_parser")]
+
+use lopdf::content::Content;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Allocator counting the bytes in use, and the most in use since the last reset.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The most bytes allocated at once while running `f`, beyond those allocated before.
+fn peak_allocation<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let result = f();
+    (result, PEAK.load(Ordering::Relaxed) - before)
+}
+
+/// Iterate over plotter output of `2 * lines` path segments, checking it takes less memory than decoding a
+/// hundredth of them, as the memory taken by decoding grows with the operations.
+fn assert_iteration_allocates_little(lines: usize) {
+    let segment = b"10 20 m 30.5 40 l\n";
+    let data = segment.repeat(lines);
+
+    let (count, iterated) = peak_allocation(|| {
+        let mut count = 0;
+        for operation in Content::iter_operations(&data) {
+            let operation = operation.unwrap();
+            if operation.operator == "l" {
+                assert_eq!(operation.operands[0].as_f32(), Some(30.5));
+                count += 1;
+            }
+        }
+        count
+    });
+    assert_eq!(count, lines);
+
+    let sample = segment.repeat(lines / 100);
+    let (content, decoded) = peak_allocation(|| Content::decode(&sample).unwrap());
+    assert_eq!(content.operations.len(), lines / 50);
+    assert!(
+        iterated < decoded,
+        "iterating {} operations took {iterated} bytes, decoding {} took {decoded}",
+        2 * lines,
+        lines / 50
+    );
+}
+
+#[test]
+fn iterating_long_content_allocates_little() {
+    assert_iteration_allocates_little(100_000);
+}
+
+/// The same at the size of real plotter output, which takes a while in debug builds.
+#[test]
+#[ignore]
+fn iterating_2m_operations_allocates_little() {
+    assert_iteration_allocates_little(1_000_000);
+}
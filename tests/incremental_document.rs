@@ -1,7 +1,8 @@
 // Only run test when parser is enabled
 #![cfg(feature = "nom_parser")]
 
-use lopdf::Result;
+use lopdf::xref::XrefType;
+use lopdf::{dictionary, Document, IncrementalDocument, Object, ObjectId, ParseOptions, Result, Stream};
 use tempfile::tempdir;
 
 mod utils;
@@ -26,3 +27,126 @@ fn load_incremental_file() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn load_truncated_incremental_file() -> Result<()> {
+    let bytes = std::fs::read("assets/Incremental.pdf")?;
+    let last_eof = bytes.windows(5).rposition(|w| w == b"%%EOF").unwrap();
+    let previous_eof = bytes[..last_eof - 1].windows(5).rposition(|w| w == b"%%EOF").unwrap();
+    let eol_length = if bytes[previous_eof + 5..].starts_with(b"\r\n") {
+        2
+    } else {
+        1
+    };
+    let previous_revision_end = previous_eof + 5 + eol_length;
+    // Cut the file in the middle of its last incremental update.
+    let truncated = &bytes[..(previous_revision_end + last_eof) / 2];
+
+    // Only a lenient load falls back to the previous revision.
+    let strict = Document::load_mem(truncated)?;
+    assert_eq!(strict.load_report.discarded_revisions, 0);
+    let options = ParseOptions {
+        lenient: true,
+        ..ParseOptions::default()
+    };
+    let doc = Document::load_mem_with_options(truncated, &options)?;
+    assert_eq!(doc.load_report.discarded_revisions, 1);
+    assert_eq!(doc.load_report.ignored_bytes, truncated.len() - previous_revision_end);
+    let page_id = *doc.get_pages().get(&1).unwrap();
+    assert!(doc.get_dictionary(page_id)?.get(b"Annots").is_err());
+    let content = doc.get_page_content(page_id)?;
+    assert!(content.windows(12).any(|w| w == b"Hello World!"));
+
+    let doc = IncrementalDocument::load_mem_with_options(truncated, &options)?;
+    assert_eq!(doc.get_prev_documents_bytes().len(), previous_revision_end);
+
+    Ok(())
+}
+
+/// A single page file with a classic cross-reference table, with the ids of its information dictionary,
+/// page and content stream.
+fn single_page_file() -> Result<(Vec<u8>, ObjectId, ObjectId, ObjectId)> {
+    let mut doc = Document::with_version("1.5");
+    doc.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+    let info_id = doc.add_object(dictionary! { "Title" => Object::string_literal("Original") });
+    let pages_id = doc.new_object_id();
+    let content_id = doc.add_object(Stream::new(dictionary! {}, b"BT ET".to_vec()));
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "Contents" => content_id,
+    });
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    doc.trailer.set("Root", catalog_id);
+    doc.trailer.set("Info", info_id);
+    let mut original = Vec::new();
+    doc.save_to(&mut original)?;
+    Ok((original, info_id, page_id, content_id))
+}
+
+#[test]
+fn save_incremental_writes_only_modified_objects() -> Result<()> {
+    let (original, info_id, _, _) = single_page_file()?;
+    let mut doc = Document::load_mem(&original)?;
+    assert!(doc.dirty_objects().is_empty());
+    let generation = doc.generation();
+    doc.get_dictionary_mut(info_id)?
+        .set("Title", Object::string_literal("Changed"));
+    assert_eq!(doc.dirty_objects().iter().copied().collect::<Vec<_>>(), vec![info_id]);
+    assert!(doc.generation() > generation);
+
+    let mut output = Vec::new();
+    doc.save_incremental(&original, &mut output)?;
+    assert!(doc.dirty_objects().is_empty());
+    assert_eq!(&output[..original.len()], &original[..]);
+
+    let update = String::from_utf8_lossy(&output[original.len()..]);
+    assert_eq!(update.matches(" obj").count(), 1);
+    assert!(update.contains(&format!("{} {} obj", info_id.0, info_id.1)));
+    assert!(update.contains("xref") && update.contains("trailer"));
+
+    let saved = Document::load_mem(&output)?;
+    let info = saved.get_dictionary(info_id)?;
+    assert_eq!(info.get(b"Title")?.as_str()?, b"Changed");
+    assert_eq!(saved.get_pages().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn save_incremental_frees_removed_objects() -> Result<()> {
+    let (original, _, page_id, content_id) = single_page_file()?;
+    let mut doc = Document::load_mem(&original)?;
+    doc.get_dictionary_mut(page_id)?.remove(b"Contents");
+    doc.delete_object(content_id);
+    doc.trailer.set("Custom", true);
+    let prev = doc.xref_start;
+
+    let mut output = Vec::new();
+    doc.save_incremental(&original, &mut output)?;
+    let update = String::from_utf8_lossy(&output[original.len()..]);
+    assert!(update.contains("0000000000 00001 f "));
+    assert!(update.contains(&format!("/Prev {}", prev)));
+
+    let saved = Document::load_mem(&output)?;
+    assert!(saved.get_object(content_id).is_err());
+    assert!(saved.get_dictionary(page_id)?.get(b"Contents").is_err());
+    assert!(saved.trailer.get(b"Custom")?.as_bool()?);
+
+    // The document describes the output, which can be updated again.
+    doc.trailer.set("Custom", false);
+    let mut second = Vec::new();
+    doc.save_incremental(&output, &mut second)?;
+    let saved = Document::load_mem(&second)?;
+    assert!(!saved.trailer.get(b"Custom")?.as_bool()?);
+    assert!(saved.get_object(content_id).is_err());
+    assert_eq!(saved.get_pages().len(), 1);
+    Ok(())
+}